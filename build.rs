@@ -0,0 +1,21 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+fn main() {
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+/// Generate the gRPC service of `proto/kore.proto`, with the vendored `protoc` unless `PROTOC`
+/// is set.
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    if std::env::var_os("PROTOC").is_none() {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc not available");
+        std::env::set_var("PROTOC", protoc);
+    }
+    println!("cargo:rerun-if-changed=proto/kore.proto");
+    tonic_build::configure()
+        .compile_protos(&["proto/kore.proto"], &["proto"])
+        .expect("proto/kore.proto could not be compiled");
+}
@@ -0,0 +1,201 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Access logs.
+//!
+//! Structured logs of the served requests, written under the `kore_node::access` target as
+//! `key=value` pairs. Only a sample of the requests is logged; the decision depends on the trace
+//! id, so every log line of a trace is either kept or dropped. Slow and failed requests are always
+//! logged.
+//!
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use crate::{settings::AccessLogSettings, utils::replace_setting};
+
+/// Log target of the access logs.
+pub const ACCESS_LOG_TARGET: &str = "kore_node::access";
+
+/// HTTP header with the trace id of a request, echoed in the response.
+pub const TRACE_ID_HEADER: &str = "x-request-id";
+
+/// Longest trace id accepted from a caller.
+pub const MAX_TRACE_ID_LEN: usize = 128;
+
+/// Served request.
+#[derive(Debug, Clone)]
+pub struct AccessEntry<'a> {
+    /// Method or route of the request.
+    pub method: &'a str,
+    /// Time taken to serve the request.
+    pub latency: Duration,
+    /// Status of the response, e.g. `ok` or `200`.
+    pub status: &'a str,
+    /// Whether the request failed.
+    pub failed: bool,
+    /// Identity of the caller, if known.
+    pub identity: Option<&'a str>,
+    /// Trace id of the request.
+    pub trace_id: &'a str,
+}
+
+/// Access logger, shared by the services of a node. Its clones share the settings.
+#[derive(Clone, Default)]
+pub struct AccessLogger {
+    settings: Arc<RwLock<AccessLogSettings>>,
+}
+
+impl AccessLogger {
+    /// Create an access logger.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Sampling settings.
+    ///
+    pub fn new(settings: AccessLogSettings) -> Self {
+        Self {
+            settings: Arc::new(RwLock::new(settings)),
+        }
+    }
+
+    /// Replace the settings of this logger and its clones.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Sampling settings.
+    ///
+    pub fn set_settings(&self, settings: AccessLogSettings) {
+        replace_setting(&self.settings, settings);
+    }
+
+    /// Log a served request if it is sampled, slow or failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - Served request.
+    ///
+    pub fn log(&self, entry: &AccessEntry) {
+        let settings = self
+            .settings
+            .read()
+            .map(|settings| settings.clone())
+            .unwrap_or_default();
+        let slow = entry.latency >= settings.slow_threshold;
+        if !slow && !entry.failed && !is_sampled(entry.trace_id, settings.sample_rate) {
+            return;
+        }
+        let level = if slow || entry.failed {
+            log::Level::Warn
+        } else {
+            log::Level::Info
+        };
+        log::log!(
+            target: ACCESS_LOG_TARGET,
+            level,
+            "method={} status={} latency_ms={} identity={} trace_id={} slow={}",
+            entry.method,
+            entry.status,
+            entry.latency.as_millis(),
+            entry.identity.unwrap_or("-"),
+            entry.trace_id,
+            slow
+        );
+    }
+}
+
+/// New random trace id, 32 hex characters.
+pub fn new_trace_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Trace id of a request: the one sent by the caller, so that its logs and the ones of the
+/// services it called share a trace, or a new one when it sent none. Ids longer than
+/// `MAX_TRACE_ID_LEN` or with other characters than ASCII letters, digits, `-`, `_`, `.` and `:`
+/// are replaced, as they would break the `key=value` lines of the logs.
+///
+/// # Arguments
+///
+/// * `incoming` - Trace id sent by the caller, if any.
+///
+pub fn accept_trace_id(incoming: Option<&str>) -> String {
+    match incoming {
+        Some(trace_id)
+            if !trace_id.is_empty()
+                && trace_id.len() <= MAX_TRACE_ID_LEN
+                && trace_id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':')) =>
+        {
+            trace_id.to_owned()
+        }
+        _ => new_trace_id(),
+    }
+}
+
+/// Whether the trace falls in the sample.
+fn is_sampled(trace_id: &str, sample_rate: f64) -> bool {
+    if sample_rate >= 1.0 {
+        return true;
+    }
+    if sample_rate <= 0.0 {
+        return false;
+    }
+    let mut hasher = DefaultHasher::new();
+    trace_id.hash(&mut hasher);
+    (hasher.finish() % 10_000) < (sample_rate * 10_000.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_is_sampled() {
+        let traces = (0..1000).map(|_| new_trace_id()).collect::<Vec<_>>();
+        assert!(traces.iter().all(|trace| is_sampled(trace, 1.0)));
+        assert!(!traces.iter().any(|trace| is_sampled(trace, 0.0)));
+
+        let sampled = traces.iter().filter(|trace| is_sampled(trace, 0.5)).count();
+        assert!((350..650).contains(&sampled));
+        // The decision is the same for every line of a trace.
+        assert!(traces
+            .iter()
+            .all(|trace| is_sampled(trace, 0.5) == is_sampled(trace, 0.5)));
+    }
+
+    #[test]
+    fn test_new_trace_id() {
+        let trace_id = new_trace_id();
+        assert_eq!(trace_id.len(), 32);
+        assert_ne!(trace_id, new_trace_id());
+    }
+
+    #[test]
+    fn test_accept_trace_id() {
+        assert_eq!(accept_trace_id(Some("trace-1")), "trace-1");
+        assert_eq!(
+            accept_trace_id(Some("00-4bf92f35-00f067aa.01:b")),
+            "00-4bf92f35-00f067aa.01:b"
+        );
+        let long = "a".repeat(MAX_TRACE_ID_LEN + 1);
+        for invalid in [
+            None,
+            Some(""),
+            Some("trace 1"),
+            Some("t\nslow=true"),
+            Some(long.as_str()),
+        ] {
+            assert_eq!(accept_trace_id(invalid).len(), 32);
+        }
+        assert_eq!(
+            accept_trace_id(Some(&"a".repeat(MAX_TRACE_ID_LEN))).len(),
+            MAX_TRACE_ID_LEN
+        );
+    }
+}
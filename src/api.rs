@@ -7,22 +7,357 @@
 //! This module contains the Kore Node API.
 
 use crate::{
+    access_log::{new_trace_id, AccessEntry, AccessLogger},
+    archival::{timestamp_nanos, Archival},
+    backup::{write_backup, BackupSource, BACKUP_SCHEMA_VERSION},
+    config::validate::multiaddr,
+    contracts::Contracts,
+    database::{
+        index::SubjectIndex,
+        ledger::{proof_key, PROOFS_SCOPE},
+        maintenance::DbMaintenance,
+        store::{NodeStore, StoreBatch},
+    },
     error::NodeError,
+    features::{feature_description, FEATURE_FLAGS},
+    governance::{contract_source, GovernanceChange, GovernanceUpdate},
+    limits::{CallClass, CallLimiter},
+    metrics::NodeMetrics,
     model::{
-        AuthorizeSubject, EventContentResponse, EventRequestResponse, KeyAlgorithms,
-        NodeApprovalEntity, NodeEventRequest, NodeGetApprovals, NodeKeys, NodeKoreRequestState,
-        NodeProof, NodeSigned, NodeSignedEventRequest, NodeSubjectData, NodeSubjects,
-        PaginatorFromNumber, PaginatorFromString, PatchVote, PreauthorizedSubjectsResponse,
+        rfc3339_millis, AuthorizeSubject, EventContentResponse, EventRequestResponse, GraphBuilder,
+        KeyAlgorithms, NodeAllowedSubjectsFilter, NodeApprovalEntity, NodeArchivalReport,
+        NodeBackupManifest, NodeBootNode, NodeChainVerification, NodeContract, NodeContractSource,
+        NodeDbCompaction, NodeDbStats, NodeEOLRequest, NodeEventRequest, NodeEventVerification,
+        NodeFeatureFlag, NodeGetApprovals, NodeGraphRelation, NodeGraphVertex, NodeGraphVertexKind,
+        NodeHistoryEntry, NodeHistoryKind, NodeIdempotentRequest, NodeInfo, NodeKeyRotation,
+        NodeKeyVersion, NodeKeys, NodeKoreRequestState, NodeProof, NodeRequestRecord,
+        NodeRequestState, NodeRequestTransition, NodeServiceRecord, NodeSigned,
+        NodeSignedEventRequest, NodeSubjectData, NodeSubjectGraph, NodeSubjectKeys,
+        NodeSubjectSearch, NodeSubjects, NodeTransferRequest, NodeUsage, Page, PaginatorFromNumber,
+        PaginatorFromString, PatchVote, PreauthorizedSubjectsResponse,
+    },
+    peers::{BOOT_NODES_SCOPE, REMOVED_BOOT_NODES_SCOPE},
+    settings::{
+        AccessLogSettings, ApiCallSettings, ApprovalRule, DbTtlSettings, KeysSettings,
+        LimitsSettings, ServicesSettings, SignatureCheck, SigningPolicy, SubjectQuota,
+        TimestampFormat,
     },
+    subscription::{
+        EventSubscription, PendingApprovals, RequestTransitions, SubscriptionTarget, Subscriptions,
+    },
+    support::build_info,
+    usage::{usage_key, window_start, windows_prefix, UsageMeter, USAGE_RETENTION},
+    utils::{previous_key_pairs, replace_setting, rotate_key_file},
+    verification::verify_event,
 };
 use kore_base::{
-    keys::KeyPair,
+    keys::{KeyMaterial, KeyPair},
     signature::{Signature as BaseSignature, Signed as BaseSigned},
-    Api, ApprovalState, Derivable, DigestDerivator, DigestIdentifier,
-    EventRequest as BaseEventRequest, KeyDerivator, KeyIdentifier,
+    Api, ApiError as BaseApiError, ApprovalState, Derivable, DigestDerivator, DigestIdentifier,
+    Event as BaseEvent, EventRequest as BaseEventRequest, KeyDerivator, KeyIdentifier, RoutingNode,
+};
+use libp2p_identity::PeerId;
+
+use futures::{
+    stream::{self, BoxStream},
+    Future, StreamExt, TryStreamExt,
+};
+use tokio::{sync::broadcast, time::Instant};
+use tokio_util::sync::CancellationToken;
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    convert::TryFrom,
+    ops::Range,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    time::{Duration, Instant as StdInstant, SystemTime, UNIX_EPOCH},
 };
 
-use std::{collections::HashSet, convert::TryFrom, str::FromStr};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{de::DeserializeOwned, Serialize};
+
+#[cfg(feature = "export")]
+use crate::export::{verify_events, write_events, EventExportFormat, VerifyReport};
+#[cfg(feature = "export")]
+use tokio::io::{AsyncRead, AsyncWrite};
+
+#[cfg(feature = "replication")]
+use crate::model::NodeReplicationFailure;
+
+/// Classify an error of Kore Base, keeping the original one when it is not a client error.
+///
+/// # Arguments
+///
+/// * `context` - Method whose call failed.
+/// * `error` - Error returned by Kore Base.
+///
+fn base_error(context: &str, error: BaseApiError) -> NodeError {
+    match error {
+        BaseApiError::InvalidParameters(message) => NodeError::InvalidParameter(message),
+        BaseApiError::NotFound(message) => NodeError::NotFound(message),
+        BaseApiError::NotEnoughPermissions(message) => NodeError::Unauthorized(message),
+        BaseApiError::Conflict(message) => NodeError::Conflict(message),
+        error @ BaseApiError::VoteNotNeeded(_) => NodeError::Conflict(error.to_string()),
+        error => NodeError::Internal {
+            context: context.to_owned(),
+            source: Arc::new(error),
+        },
+    }
+}
+
+/// Whether an `If-None-Match` value holds an entity tag, using the weak comparison of RFC 9110.
+///
+/// # Arguments
+///
+/// * `if_none_match` - Comma separated entity tags, or `*`.
+/// * `etag` - Current entity tag.
+///
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+    let etag = opaque(etag);
+    if_none_match
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+/// Check the signer of a request against the policy of its type, if any.
+/// The signer is allowed when listed in the policy, or when the policy accepts external signers
+/// and the request came signed.
+fn check_signing_policies(
+    policies: &[SigningPolicy],
+    request_type: &str,
+    signer: &str,
+    external_signature: bool,
+) -> Result<(), NodeError> {
+    let Some(policy) = policies
+        .iter()
+        .find(|policy| policy.request_types.iter().any(|kind| kind == request_type))
+    else {
+        return Ok(());
+    };
+    if policy.signers.iter().any(|allowed| allowed == signer)
+        || (policy.external_signer && external_signature)
+    {
+        Ok(())
+    } else {
+        Err(NodeError::Unauthorized(format!(
+            "{} requests cannot be signed by {}",
+            request_type, signer
+        )))
+    }
+}
+
+/// Check the time of a signature against the window of `check`.
+///
+/// # Arguments
+///
+/// * `check` - Signature checks, with the age and clock skew allowed.
+/// * `timestamp` - Time of the signature, in nanoseconds since UNIX epoch.
+/// * `now` - Time of the node, in nanoseconds since UNIX epoch.
+///
+/// # Errors
+///
+/// * `NodeError::InvalidSignature` - The signature is stale or ahead of the node clock.
+///
+fn check_signature_time(check: &SignatureCheck, timestamp: u64, now: u64) -> Result<(), NodeError> {
+    let seconds =
+        |nanos: u64| humantime::format_duration(Duration::from_secs(nanos / 1_000_000_000));
+    if now > timestamp && u128::from(now - timestamp) > check.max_age.as_nanos() {
+        return Err(NodeError::InvalidSignature(format!(
+            "signed {} ago, more than {}",
+            seconds(now - timestamp),
+            humantime::format_duration(check.max_age)
+        )));
+    }
+    if timestamp > now && u128::from(timestamp - now) > check.max_future.as_nanos() {
+        return Err(NodeError::InvalidSignature(format!(
+            "signed {} ahead of the node clock, more than {}",
+            seconds(timestamp - now),
+            humantime::format_duration(check.max_future)
+        )));
+    }
+    Ok(())
+}
+
+/// Milliseconds since UNIX epoch.
+pub(crate) fn timestamp_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Key of a sent request in the order of the requests, sorted by timestamp and request id.
+fn request_order_key(timestamp: u64, request_id: &str) -> String {
+    format!("{:020}.{}", timestamp, request_id)
+}
+
+/// Key of the controller id of the last start, in the node store.
+const CONTROLLER_KEY: &str = "controller";
+
+/// Entries requested per page when a method reads a whole list.
+const PAGE_SIZE: i64 = 100;
+
+/// Length of the usage accounting windows, in milliseconds.
+pub const USAGE_WINDOW: u64 = 3_600_000;
+
+/// Deepest subject graph returned by `subject_graph`.
+pub const MAX_GRAPH_DEPTH: u32 = 4;
+
+/// Subjects of a subject graph, beyond which it is truncated.
+pub const MAX_GRAPH_SUBJECTS: usize = 1000;
+
+/// Longest wait of `wait_state_change`.
+pub const MAX_STATE_WAIT: Duration = Duration::from_secs(60);
+
+/// Time between two reads of a request state while waiting for it to change.
+pub(crate) const STATE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Scope of the node store holding the collections whose entries expire.
+const EXPIRING_SCOPE: &str = "expiring";
+
+/// Header of the idempotency key of an event request, see `send_idempotent_event_request`.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Longest idempotency key.
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// Collection of the idempotency keys, see `expiring_store`.
+const IDEMPOTENCY_COLLECTION: &str = "idempotency";
+
+/// Time an idempotency key is kept when `kore.db_ttl.collections` does not set it.
+const IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Time a request sent through the node is kept when `kore.db_ttl.collections` does not set it.
+pub const REQUEST_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Time an entry of the node history is kept when `kore.db_ttl.collections` does not set it.
+pub const HISTORY_RETENTION: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Time a replicated event given up is kept when `kore.db_ttl.collections` does not set it.
+#[cfg(feature = "replication")]
+pub const DEAD_LETTER_RETENTION: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+/// Move the entries directly under `from` to `to`, which rewrites them with the time to live of
+/// `to`.
+///
+/// # Returns
+///
+/// * `usize` - Entries moved.
+///
+fn move_entries<T>(from: &NodeStore, to: &NodeStore) -> Result<usize, NodeError>
+where
+    T: BorshSerialize + BorshDeserialize + Serialize + DeserializeOwned,
+{
+    let entries = from.entries::<T>()?;
+    let mut batch = StoreBatch::default();
+    for (key, value) in entries.iter() {
+        to.batch_put(&mut batch, key, value)?;
+        from.batch_del(&mut batch, key);
+    }
+    from.write(batch)?;
+    Ok(entries.len())
+}
+
+/// Sequence number and new owner, the key the subject was transferred to, of the transfer
+/// events among `events`.
+fn transfer_owners(
+    events: &[NodeSigned<EventContentResponse>],
+) -> impl Iterator<Item = (u64, String)> + '_ {
+    events
+        .iter()
+        .filter_map(|event| match &event.content.event_request.content {
+            NodeEventRequest::Transfer(transfer) => {
+                Some((event.content.sn, transfer.public_key.clone()))
+            }
+            _ => None,
+        })
+}
+
+/// Archived subjects returned by `get_subjects`.
+enum ArchiveFilter {
+    Unarchived,
+    Archived,
+    All,
+}
+
+/// Locks of the idempotency keys whose requests are being sent.
+type IdempotencyLocks = Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>;
+
+/// Lock of an idempotency key, taken from `IdempotencyLocks` and removed from them when its
+/// last user drops it.
+struct IdempotencyLock {
+    locks: IdempotencyLocks,
+    key: String,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl IdempotencyLock {
+    /// Lock of `key`, shared with the other users of the key.
+    fn new(locks: &IdempotencyLocks, key: &str) -> Self {
+        let lock = locks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(key.to_owned())
+            .or_default()
+            .clone();
+        Self {
+            locks: locks.clone(),
+            key: key.to_owned(),
+            lock,
+        }
+    }
+}
+
+impl Drop for IdempotencyLock {
+    fn drop(&mut self) {
+        let mut locks = self.locks.lock().unwrap_or_else(PoisonError::into_inner);
+        // Only held by the map and by this user.
+        if Arc::strong_count(&self.lock) == 2 {
+            locks.remove(&self.key);
+        }
+    }
+}
+
+/// Context of the calls made through a `KoreApi` handle.
+/// Carries an optional deadline and cancellation token applied to every call.
+#[derive(Clone, Default)]
+struct CallContext {
+    /// Instant after which calls fail with `NodeError::Timeout`.
+    deadline: Option<Instant>,
+    /// Token that aborts calls with `NodeError::Cancelled`.
+    cancellation: Option<CancellationToken>,
+    /// Identity of the caller, for the access logs.
+    identity: Option<String>,
+    /// Trace id of the calls; each call gets a new one when missing.
+    trace_id: Option<String>,
+}
+
+/// Outcome of a Kore Base call, for the access logs and the retries.
+trait CallOutcome {
+    /// Whether the call failed.
+    fn failed(&self) -> bool;
+
+    /// Whether the call failed in a way that another attempt may not.
+    fn transient(&self) -> bool;
+}
+
+impl<T> CallOutcome for Result<T, BaseApiError> {
+    fn failed(&self) -> bool {
+        self.is_err()
+    }
+
+    fn transient(&self) -> bool {
+        matches!(
+            self,
+            Err(BaseApiError::InternalError(_)
+                | BaseApiError::DatabaseError(_)
+                | BaseApiError::UnexpectedError)
+        )
+    }
+}
 
 /// Kore Node API.
 #[derive(Clone)]
@@ -31,6 +366,45 @@ pub struct KoreApi {
     keys: KeyPair,
     digest_derivator: DigestDerivator,
     key_derivator: KeyDerivator,
+    context: CallContext,
+    store: NodeStore,
+    subject_quota: Arc<RwLock<SubjectQuota>>,
+    /// Held while the quota usage is checked and updated, so that concurrent requests cannot
+    /// go over the quota.
+    quota_lock: Arc<Mutex<()>>,
+    /// Held per key while a request with an idempotency key is sent, so that a retry sent
+    /// before the first attempt finished waits for its request identifier.
+    idempotency_locks: IdempotencyLocks,
+    /// Timestamp of the last request recorded, held while a request is recorded, so that the
+    /// requests are written in the order of their keys in `requests_order`.
+    requests_lock: Arc<Mutex<u64>>,
+    signing_policies: Arc<RwLock<Vec<SigningPolicy>>>,
+    approval_rules: Arc<RwLock<Vec<ApprovalRule>>>,
+    signature_check: Arc<RwLock<SignatureCheck>>,
+    api_calls: Arc<RwLock<ApiCallSettings>>,
+    limiter: Arc<RwLock<CallLimiter>>,
+    access_log: AccessLogger,
+    subscriptions: Subscriptions,
+    pending_approvals: PendingApprovals,
+    transitions: RequestTransitions,
+    keys_path: Option<String>,
+    key_encryption: KeysSettings,
+    usage: UsageMeter,
+    metrics: NodeMetrics,
+    metrics_address: Arc<RwLock<Option<String>>>,
+    listen_addresses: Arc<Vec<String>>,
+    external_addresses: Arc<Vec<String>>,
+    boot_nodes: Arc<RwLock<Vec<RoutingNode>>>,
+    backup: Option<BackupSource>,
+    maintenance: Option<DbMaintenance>,
+    feature_flags: Arc<RwLock<BTreeMap<String, bool>>>,
+    services: Arc<RwLock<ServicesSettings>>,
+    peer_services: Arc<RwLock<BTreeMap<String, NodeServiceRecord>>>,
+    db_ttl: Arc<DbTtlSettings>,
+    contracts: Option<Contracts>,
+    subject_index: Option<SubjectIndex>,
+    archival: Option<Archival>,
+    timestamp_format: Arc<RwLock<TimestampFormat>>,
 }
 
 /// Kore Node API implementation.
@@ -41,665 +415,3879 @@ impl KoreApi {
         keys: KeyPair,
         digest_derivator: DigestDerivator,
         key_derivator: KeyDerivator,
+        store: NodeStore,
     ) -> Self {
         Self {
             api,
             keys,
             digest_derivator,
             key_derivator,
+            context: CallContext::default(),
+            store,
+            subject_quota: Arc::new(RwLock::new(SubjectQuota::default())),
+            quota_lock: Arc::new(Mutex::new(())),
+            idempotency_locks: IdempotencyLocks::default(),
+            requests_lock: Arc::new(Mutex::new(0)),
+            signing_policies: Arc::new(RwLock::new(vec![])),
+            approval_rules: Arc::new(RwLock::new(vec![])),
+            signature_check: Arc::new(RwLock::new(SignatureCheck::default())),
+            api_calls: Arc::new(RwLock::new(ApiCallSettings::default())),
+            limiter: Arc::new(RwLock::new(CallLimiter::default())),
+            access_log: AccessLogger::default(),
+            subscriptions: Subscriptions::default(),
+            pending_approvals: PendingApprovals::default(),
+            transitions: RequestTransitions::default(),
+            keys_path: None,
+            key_encryption: KeysSettings::default(),
+            usage: UsageMeter::default(),
+            metrics: NodeMetrics::default(),
+            metrics_address: Arc::new(RwLock::new(None)),
+            listen_addresses: Arc::new(vec![]),
+            external_addresses: Arc::new(vec![]),
+            boot_nodes: Arc::new(RwLock::new(vec![])),
+            backup: None,
+            maintenance: None,
+            feature_flags: Arc::new(RwLock::new(BTreeMap::new())),
+            services: Arc::new(RwLock::new(ServicesSettings::default())),
+            peer_services: Arc::new(RwLock::new(BTreeMap::new())),
+            db_ttl: Arc::new(DbTtlSettings::default()),
+            contracts: None,
+            subject_index: None,
+            archival: None,
+            timestamp_format: Arc::new(RwLock::new(TimestampFormat::Epoch)),
         }
     }
 
-    /// Send an event request.
-    /// If the request is a create request and the public key is not provided, a new key pair is
-    /// generated and the public key is added to the request.
-    /// If the request is not signed, a signature is generated and added to the request.
-    /// The request is then sent to the Kore API.
-    /// The request identifier is returned.
+    /// Limit the subjects that each identity may create in a governance.
+    /// Create requests over the quota fail with `NodeError::QuotaExceeded` before being sent.
     ///
     /// # Arguments
     ///
-    /// * `request` - Signed event request.
+    /// * `quota` - Subject creation quota.
     ///
-    /// # Errors
+    pub fn with_subject_quota(mut self, quota: SubjectQuota) -> Self {
+        self.subject_quota = Arc::new(RwLock::new(quota));
+        self
+    }
+
+    /// Restrict who may sign the request types listed in the policies.
+    /// Requests signed by anyone else fail with `NodeError::Unauthorized` before being sent.
     ///
-    /// * `NodeError::InternalApi` - Internal API error.
-    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// # Arguments
     ///
-    /// # Returns
+    /// * `policies` - Signing policies, at most one per request type.
     ///
-    /// * `EventRequestResponse` - Id of request.
+    pub fn with_signing_policies(mut self, policies: Vec<SigningPolicy>) -> Self {
+        self.signing_policies = Arc::new(RwLock::new(policies));
+        self
+    }
+
+    /// Approvals accepted by the `auto_approval` feature flag, see the `features` module.
     ///
-    pub async fn send_event_request(
-        &self,
-        mut request: NodeSignedEventRequest,
-    ) -> Result<EventRequestResponse, NodeError> {
-        if let NodeEventRequest::Create(create_request) = &mut request.request {
-            if create_request.public_key.is_none() {
-                let public_key = self
-                    .api
-                    .add_keys(self.key_derivator)
-                    .await
-                    .map_err(|_| NodeError::InternalApi("Failed to add keys".to_owned()))?;
-                create_request.public_key = Some(public_key.to_str());
-            }
-        }
+    /// # Arguments
+    ///
+    /// * `rules` - Approval rules, none accepts no approval.
+    ///
+    pub fn with_approval_rules(mut self, rules: Vec<ApprovalRule>) -> Self {
+        self.approval_rules = Arc::new(RwLock::new(rules));
+        self
+    }
 
-        let Ok(event_request) = BaseEventRequest::try_from(request.request) else {
-            return Err(NodeError::InvalidParameter("event request".to_owned()));
-        };
+    /// Check the signatures that come with the event requests when `check` is enabled.
+    /// Requests whose signature does not match them, or is out of the time window, fail with
+    /// `NodeError::InvalidSignature` before being sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `check` - Signature checks.
+    ///
+    pub fn with_signature_check(mut self, check: SignatureCheck) -> Self {
+        self.signature_check = Arc::new(RwLock::new(check));
+        self
+    }
 
-        let signature = match request.signature {
-            Some(signature) => {
-                let Ok(signature) = BaseSignature::try_from(signature) else {
-                    return Err(NodeError::InvalidParameter("signature".to_owned()));
-                };
-                signature
-            }
-            None => BaseSignature::new(&event_request, &self.keys, self.digest_derivator)
-                .map_err(|_| NodeError::InternalApi("Failed to create signature".to_owned()))?,
-        };
+    /// Limit the time of the calls to Kore Base and retry the reads that fail.
+    /// Calls over the timeout fail with `NodeError::Timeout` once the retries are spent.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Timeout, retries and backoff.
+    ///
+    pub fn with_api_calls(mut self, settings: ApiCallSettings) -> Self {
+        self.api_calls = Arc::new(RwLock::new(settings));
+        self
+    }
 
-        match self
-            .api
-            .external_request(BaseSigned {
-                content: event_request,
-                signature,
-            })
-            .await
-        {
-            Ok(id) => Ok(EventRequestResponse {
-                request_id: id.to_str(),
-            }),
-            Err(_) => Err(NodeError::InternalApi(
-                "Failed to process request".to_owned(),
-            )),
+    /// Bound the rate and the concurrency of the reads and the writes, see the `limits` module.
+    /// Calls over the limits fail with `NodeError::RateLimited` without reaching Kore Base.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - Limits of each class of calls.
+    ///
+    pub fn with_limits(mut self, limits: LimitsSettings) -> Self {
+        self.limiter = Arc::new(RwLock::new(CallLimiter::new(&limits)));
+        self
+    }
+
+    /// Log the calls to Kore Base through `logger`.
+    ///
+    /// # Arguments
+    ///
+    /// * `logger` - Access logger.
+    ///
+    pub fn with_access_log(mut self, logger: AccessLogger) -> Self {
+        self.access_log = logger;
+        self
+    }
+
+    /// Record the event requests and pending approvals in `metrics`.
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - Node metrics, usually registered in the metrics server registry.
+    ///
+    pub fn with_metrics(mut self, metrics: NodeMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Keep the node key files in `keys_path`, which allows rotating the node key.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys_path` - Keys directory of the node.
+    /// * `encryption` - Encryption of the key files written.
+    ///
+    pub fn with_key_files(mut self, keys_path: &str, encryption: KeysSettings) -> Self {
+        self.keys_path = Some(keys_path.to_owned());
+        self.key_encryption = encryption;
+        self
+    }
+
+    /// Allow backups of the database, see `create_backup`.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Database of the node.
+    ///
+    pub(crate) fn with_backup(mut self, source: BackupSource) -> Self {
+        self.backup = Some(source);
+        self
+    }
+
+    /// Allow the size reports and compaction of the database, see `db_stats` and `compact_db`.
+    ///
+    /// # Arguments
+    ///
+    /// * `maintenance` - Database of the node.
+    ///
+    pub(crate) fn with_maintenance(mut self, maintenance: DbMaintenance) -> Self {
+        self.maintenance = Some(maintenance);
+        self
+    }
+
+    /// Move the old events to an archive, see `archive_events`, and read them from there in
+    /// `get_event_of_subject`.
+    ///
+    /// # Arguments
+    ///
+    /// * `archival` - Archive, events and markers of the node.
+    ///
+    pub(crate) fn with_archival(mut self, archival: Archival) -> Self {
+        self.archival = Some(archival);
+        self
+    }
+
+    /// Set the time to live of the collections returned by `expiring_store`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_ttl` - Time to live of each collection.
+    ///
+    pub fn with_db_ttl(mut self, db_ttl: DbTtlSettings) -> Self {
+        self.db_ttl = Arc::new(db_ttl);
+        self
+    }
+
+    /// Serve the contracts of the contracts directory, see `list_contracts`.
+    ///
+    /// # Arguments
+    ///
+    /// * `contracts` - Contracts shared with the watcher of the directory.
+    ///
+    pub fn with_contracts(mut self, contracts: Contracts) -> Self {
+        self.contracts = Some(contracts);
+        self
+    }
+
+    /// Look the subjects up through the secondary indexes of the database, see
+    /// `search_subjects` and `reindex_subjects`.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Indexes of the subjects.
+    ///
+    pub(crate) fn with_subject_index(mut self, index: SubjectIndex) -> Self {
+        self.subject_index = Some(index);
+        self
+    }
+
+    /// Announce the network addresses of the node in `node_info`.
+    ///
+    /// # Arguments
+    ///
+    /// * `listen` - Multiaddresses the node listens on, fallback ports applied.
+    /// * `external` - Multiaddresses announced to the peers.
+    ///
+    pub fn with_network_addresses(mut self, listen: Vec<String>, external: Vec<String>) -> Self {
+        self.listen_addresses = Arc::new(listen);
+        self.external_addresses = Arc::new(external);
+        self
+    }
+
+    /// Set the peers of the ledger network known to the node, see the `peers` module.
+    ///
+    /// # Arguments
+    ///
+    /// * `boot_nodes` - Boot nodes given to Kore Base.
+    ///
+    pub fn with_peers(mut self, boot_nodes: Vec<RoutingNode>) -> Self {
+        self.boot_nodes = Arc::new(RwLock::new(boot_nodes));
+        self
+    }
+
+    /// Replace the boot nodes shared by this API and its clones, e.g. when their names resolve
+    /// to other addresses.
+    ///
+    /// # Arguments
+    ///
+    /// * `boot_nodes` - Boot nodes, with their addresses resolved.
+    ///
+    pub fn set_boot_nodes(&self, boot_nodes: Vec<RoutingNode>) {
+        replace_setting(&self.boot_nodes, boot_nodes);
+    }
+
+    /// Set the feature flags, see the `features` module.
+    ///
+    /// # Arguments
+    ///
+    /// * `flags` - State of the flags, missing ones are off.
+    ///
+    pub fn with_feature_flags(mut self, flags: BTreeMap<String, bool>) -> Self {
+        self.feature_flags = Arc::new(RwLock::new(flags));
+        self
+    }
+
+    /// Advertise service endpoints to the trusted peers, and accept theirs.
+    ///
+    /// # Arguments
+    ///
+    /// * `services` - Endpoints of this node and trusted peers.
+    ///
+    pub fn with_services(mut self, services: ServicesSettings) -> Self {
+        self.services = Arc::new(RwLock::new(services));
+        self
+    }
+
+    /// Replace the subject creation quota of this API and its clones.
+    ///
+    /// # Arguments
+    ///
+    /// * `quota` - Subject creation quota.
+    ///
+    pub fn set_subject_quota(&self, quota: SubjectQuota) {
+        replace_setting(&self.subject_quota, quota);
+    }
+
+    /// Replace the signing policies shared by this API and its clones.
+    ///
+    /// # Arguments
+    ///
+    /// * `policies` - Signing policies.
+    ///
+    pub fn set_signing_policies(&self, policies: Vec<SigningPolicy>) {
+        replace_setting(&self.signing_policies, policies);
+    }
+
+    /// Replace the approval rules shared by this API and its clones.
+    ///
+    /// # Arguments
+    ///
+    /// * `rules` - Approval rules.
+    ///
+    pub fn set_approval_rules(&self, rules: Vec<ApprovalRule>) {
+        replace_setting(&self.approval_rules, rules);
+    }
+
+    /// Approval rules of the `auto_approval` feature flag.
+    pub(crate) fn approval_rules(&self) -> Vec<ApprovalRule> {
+        self.approval_rules
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Replace the signature checks of this API and its clones.
+    ///
+    /// # Arguments
+    ///
+    /// * `check` - Signature checks.
+    ///
+    pub fn set_signature_check(&self, check: SignatureCheck) {
+        replace_setting(&self.signature_check, check);
+    }
+
+    /// Replace the timeout and retries of the calls of this API and its clones, applied from
+    /// the next call.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Timeout, retries and backoff.
+    ///
+    pub fn set_api_calls(&self, settings: ApiCallSettings) {
+        replace_setting(&self.api_calls, settings);
+    }
+
+    /// Replace the call limits of this API and its clones. The buckets start full, and the
+    /// calls in flight are not counted against the new limits.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - Limits of each class of calls.
+    ///
+    pub fn set_limits(&self, limits: LimitsSettings) {
+        replace_setting(&self.limiter, CallLimiter::new(&limits));
+    }
+
+    /// Announce the address of the prometheus server in `node_info`, for this API and its
+    /// clones.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address bound by the server, none when the metrics are not served.
+    ///
+    pub fn set_metrics_address(&self, address: Option<String>) {
+        replace_setting(&self.metrics_address, address);
+    }
+
+    /// Replace the format of the timestamps in the responses of this API and its clones.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Format of the timestamps.
+    ///
+    pub fn set_timestamp_format(&self, format: TimestampFormat) {
+        replace_setting(&self.timestamp_format, format);
+    }
+
+    /// Format of the timestamps in the responses, see `Timestamped`.
+    pub fn timestamp_format(&self) -> TimestampFormat {
+        self.timestamp_format
+            .read()
+            .map(|format| *format)
+            .unwrap_or(TimestampFormat::Epoch)
+    }
+
+    /// Replace the feature flags shared by this API and its clones, e.g. on a reload.
+    ///
+    /// # Arguments
+    ///
+    /// * `flags` - State of the flags, missing ones are off.
+    ///
+    pub fn set_feature_flags(&self, flags: BTreeMap<String, bool>) {
+        replace_setting(&self.feature_flags, flags);
+    }
+
+    /// Whether a feature flag is on.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Flag name.
+    ///
+    pub fn is_feature_enabled(&self, name: &str) -> bool {
+        self.feature_flags
+            .read()
+            .map(|flags| flags.get(name).copied().unwrap_or_default())
+            .unwrap_or_default()
+    }
+
+    /// Get the known feature flags and their state.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<NodeFeatureFlag>` - Every known flag, off unless turned on.
+    ///
+    pub fn feature_flags(&self) -> Vec<NodeFeatureFlag> {
+        FEATURE_FLAGS
+            .iter()
+            .map(|(name, description)| NodeFeatureFlag {
+                name: (*name).to_owned(),
+                description: (*description).to_owned(),
+                enabled: self.is_feature_enabled(name),
+            })
+            .collect()
+    }
+
+    /// Turn a feature flag on or off for this API and its clones, until the next start or
+    /// reload of `kore.features`. The toggle is recorded in the node history.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Flag name.
+    /// * `enabled` - New state of the flag.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::NotFound` - The flag is not known.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeFeatureFlag` - Flag with its new state.
+    ///
+    pub fn set_feature_flag(
+        &self,
+        name: &str,
+        enabled: bool,
+    ) -> Result<NodeFeatureFlag, NodeError> {
+        let description = feature_description(name)
+            .ok_or_else(|| NodeError::NotFound(format!("feature flag {}", name)))?;
+        let previous = self
+            .feature_flags
+            .write()
+            .map(|mut flags| flags.insert(name.to_owned(), enabled))
+            .map_err(|_| NodeError::InternalApi("Feature flags unavailable".to_owned()))?;
+        if previous.unwrap_or_default() != enabled {
+            let state = if enabled { "on" } else { "off" };
+            self.record_history(
+                NodeHistoryKind::FeatureToggled,
+                &format!("{} {}", name, state),
+            );
+        }
+        Ok(NodeFeatureFlag {
+            name: name.to_owned(),
+            description: description.to_owned(),
+            enabled,
+        })
+    }
+
+    /// Get the service endpoints of this node, signed with the node key pair so that peers can
+    /// authenticate them.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InternalApi` - The record cannot be signed.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeSigned<NodeServiceRecord>` - Signed endpoints of the node.
+    ///
+    pub fn service_record(&self) -> Result<NodeSigned<NodeServiceRecord>, NodeError> {
+        let (rest_url, metrics_url) = self
+            .services
+            .read()
+            .map(|services| (services.rest_url.clone(), services.metrics_url.clone()))
+            .unwrap_or_default();
+        let record = NodeServiceRecord {
+            controller_id: self.get_controller_id(),
+            rest_url: (!rest_url.is_empty()).then_some(rest_url),
+            metrics_url: (!metrics_url.is_empty()).then_some(metrics_url),
+            timestamp: timestamp_millis(),
+        };
+        let signature = BaseSignature::new(&record, &self.keys, self.digest_derivator)
+            .map_err(|_| NodeError::InternalApi("Failed to create signature".to_owned()))?;
+        Ok(NodeSigned {
+            content: record,
+            signature: signature.into(),
+        })
+    }
+
+    /// Accept the service endpoints advertised by a trusted peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `controller_id` - Controller identifier the peer is trusted as.
+    /// * `signed` - Record served by the peer.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Unauthorized` - The peer is not trusted, or the record is not its own.
+    /// * `NodeError::InvalidParameter` - Invalid signature.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeServiceRecord` - Endpoints known for the peer, which stay the previous ones when
+    ///   the record is older.
+    ///
+    pub fn record_peer_service(
+        &self,
+        controller_id: &str,
+        signed: NodeSigned<NodeServiceRecord>,
+    ) -> Result<NodeServiceRecord, NodeError> {
+        let trusted = self
+            .services
+            .read()
+            .map(|services| services.peers.contains_key(controller_id))
+            .unwrap_or_default();
+        if !trusted {
+            return Err(NodeError::Unauthorized(format!(
+                "{} is not a trusted peer",
+                controller_id
+            )));
+        }
+        if signed.content.controller_id != controller_id
+            || signed.signature.signer() != controller_id
+        {
+            return Err(NodeError::Unauthorized(format!(
+                "service record of {} not signed by it",
+                controller_id
+            )));
+        }
+        let record = signed.content.clone();
+        let signed = BaseSigned::<NodeServiceRecord>::try_from(signed)?;
+        signed
+            .signature
+            .verify(&signed.content)
+            .map_err(|_| NodeError::InvalidParameter("signature".to_owned()))?;
+        let mut peers = self
+            .peer_services
+            .write()
+            .map_err(|_| NodeError::InternalApi("Peer services unavailable".to_owned()))?;
+        let known = peers
+            .entry(controller_id.to_owned())
+            .or_insert_with(|| record.clone());
+        if known.timestamp < record.timestamp {
+            *known = record;
+        }
+        Ok(known.clone())
+    }
+
+    /// Get the service endpoints advertised by the trusted peers, as last fetched.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<NodeServiceRecord>` - Endpoints of the peers reached at least once, by
+    ///   controller identifier.
+    ///
+    pub fn peer_services(&self) -> Vec<NodeServiceRecord> {
+        self.peer_services
+            .read()
+            .map(|peers| peers.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the modules of the contracts directory, as last read.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<NodeContract>` - Modules by name, with the reason of those that failed; none when
+    ///   the contracts are not served.
+    ///
+    pub fn list_contracts(&self) -> Vec<NodeContract> {
+        self.contracts
+            .as_ref()
+            .map(Contracts::list)
+            .unwrap_or_default()
+    }
+
+    /// Read the contracts directory again, without waiting for its changes to be reported.
+    /// New and changed modules are validated, and each one that fails emits the
+    /// `contract_failed` lifecycle event. Kore Base only runs them once the node restarts.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<NodeContract>` - Modules by name, with the reason of those that failed.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - The contracts are not served by this API.
+    /// * `NodeError::InternalApi` - The directory cannot be read.
+    ///
+    pub fn reload_contracts(&self) -> Result<Vec<NodeContract>, NodeError> {
+        let contracts = self.contracts.as_ref().ok_or_else(|| {
+            NodeError::InvalidParameter("The contracts of the node are not served".to_owned())
+        })?;
+        contracts.reload()
+    }
+
+    /// Get the boot nodes given to Kore Base, with the addresses their names last resolved to,
+    /// see the `peers` module. Kore Base does not report its libp2p connections, so the node
+    /// cannot tell which peers it is connected to.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<NodeBootNode>` - Boot nodes in the order given to Kore Base.
+    ///
+    pub fn boot_nodes(&self) -> Vec<NodeBootNode> {
+        self.boot_nodes
+            .read()
+            .map(|boot_nodes| boot_nodes.iter().cloned().map(NodeBootNode::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the boot nodes kept in the node database, see the `peers` module.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The boot nodes cannot be read.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<NodeBootNode>` - Stored boot nodes, by peer identifier.
+    ///
+    pub fn stored_boot_nodes(&self) -> Result<Vec<NodeBootNode>, NodeError> {
+        Ok(self
+            .store
+            .scope(BOOT_NODES_SCOPE)
+            .entries::<NodeBootNode>()?
+            .into_iter()
+            .map(|(_, node)| node)
+            .collect())
+    }
+
+    /// Add a boot node to the node database. The change is deferred: Kore Base only dials it
+    /// from the next start of the node, after the boot nodes of the settings, see the `peers`
+    /// module. A boot node with the same peer identifier is replaced, and one removed before is
+    /// no longer left out. The addition is recorded in the node history.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - Peer identifier and multiaddresses of the boot node.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - The peer identifier is not valid, there is no address or
+    ///   an address is not a multiaddress.
+    /// * `NodeError::Database` - The boot node cannot be stored.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeBootNode` - Boot node stored, with its addresses in canonical form.
+    ///
+    pub fn add_boot_node(&self, node: RoutingNode) -> Result<NodeBootNode, NodeError> {
+        if PeerId::from_str(&node.peer_id).is_err() {
+            return Err(NodeError::InvalidParameter(format!(
+                "Invalid peer identifier {}",
+                node.peer_id
+            )));
+        }
+        if node.address.is_empty() {
+            return Err(NodeError::InvalidParameter("address".to_owned()));
+        }
+        let addresses = node
+            .address
+            .iter()
+            .map(|address| multiaddr(address).map(|address| address.to_string()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(NodeError::InvalidParameter)?;
+        let node = NodeBootNode {
+            peer_id: node.peer_id,
+            addresses,
+        };
+        let mut batch = StoreBatch::default();
+        self.store
+            .scope(BOOT_NODES_SCOPE)
+            .batch_put(&mut batch, &node.peer_id, &node)?;
+        self.store
+            .scope(REMOVED_BOOT_NODES_SCOPE)
+            .batch_del(&mut batch, &node.peer_id);
+        self.store.write(batch)?;
+        self.record_history(
+            NodeHistoryKind::BootNodeAdded,
+            &format!("{} at {}", node.peer_id, node.addresses.join(", ")),
+        );
+        Ok(node)
+    }
+
+    /// Remove a boot node from the node database. The change is deferred: Kore Base stops
+    /// dialing it from the next start of the node, see the `peers` module. The boot node is
+    /// remembered as removed, so that it is left out even if it is in the settings, until it is
+    /// added again. The removal is recorded in the node history.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - Peer identifier of the boot node.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::NotFound` - No stored boot node has this peer identifier.
+    /// * `NodeError::Database` - The boot node cannot be removed.
+    ///
+    pub fn remove_boot_node(&self, peer_id: &str) -> Result<(), NodeError> {
+        let store = self.store.scope(BOOT_NODES_SCOPE);
+        let Some(node) = store.get::<NodeBootNode>(peer_id)? else {
+            return Err(NodeError::NotFound(format!("boot node {}", peer_id)));
+        };
+        let mut batch = StoreBatch::default();
+        store.batch_del(&mut batch, peer_id);
+        self.store
+            .scope(REMOVED_BOOT_NODES_SCOPE)
+            .batch_put(&mut batch, peer_id, &node)?;
+        self.store.write(batch)?;
+        self.record_history(NodeHistoryKind::BootNodeRemoved, peer_id);
+        Ok(())
+    }
+
+    /// Current subject creation quota.
+    fn subject_quota(&self) -> SubjectQuota {
+        self.subject_quota
+            .read()
+            .map(|quota| quota.clone())
+            .unwrap_or_default()
+    }
+
+    /// Current signature checks. A poisoned lock is an error, so that signatures are never
+    /// left unchecked.
+    fn signature_check(&self) -> Result<SignatureCheck, NodeError> {
+        self.signature_check
+            .read()
+            .map(|check| check.clone())
+            .map_err(|_| NodeError::InternalApi("Signature checks unavailable".to_owned()))
+    }
+
+    /// Current timeout and retries of the calls.
+    fn api_calls(&self) -> ApiCallSettings {
+        self.api_calls
+            .read()
+            .map(|settings| settings.clone())
+            .unwrap_or_default()
+    }
+
+    /// Current call limiter.
+    fn limiter(&self) -> CallLimiter {
+        self.limiter
+            .read()
+            .map(|limiter| limiter.clone())
+            .unwrap_or_default()
+    }
+
+    /// Verify the signature of an event request signed by a client, as done before sending it
+    /// when the signature checks are enabled: the content hash must be the hash of the request,
+    /// the signature must be valid for its signer, and its time within the configured window.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Event request with its signature.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - The request has no signature, or cannot be parsed
+    /// * `NodeError::InvalidSignature` - The signature does not match the request, or is stale
+    ///   or ahead of the node clock
+    ///
+    pub fn verify_signed_request(&self, request: &NodeSignedEventRequest) -> Result<(), NodeError> {
+        let Some(signature) = request.signature.clone() else {
+            return Err(NodeError::InvalidParameter("signature".to_owned()));
+        };
+        let Ok(signature) = BaseSignature::try_from(signature) else {
+            return Err(NodeError::InvalidParameter("signature".to_owned()));
+        };
+        let Ok(event_request) = BaseEventRequest::try_from(request.request.clone()) else {
+            return Err(NodeError::InvalidParameter("event request".to_owned()));
+        };
+        self.check_signature(&event_request, &signature, &self.signature_check()?)
+    }
+
+    /// Check an external signature of an event request.
+    fn check_signature(
+        &self,
+        event_request: &BaseEventRequest,
+        signature: &BaseSignature,
+        check: &SignatureCheck,
+    ) -> Result<(), NodeError> {
+        let signer = signature.signer.to_str();
+        let content_hash =
+            DigestIdentifier::from_serializable_borsh(event_request, self.digest_derivator)
+                .map_err(|_| NodeError::InvalidParameter("event request".to_owned()))?;
+        if content_hash != signature.content_hash {
+            return Err(NodeError::InvalidSignature(format!(
+                "content hash of the signature of {} does not match the request",
+                signer
+            )));
+        }
+        signature.verify(event_request).map_err(|_| {
+            NodeError::InvalidSignature(format!("signature of {} is not valid", signer))
+        })?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default();
+        check_signature_time(check, signature.timestamp.0, now)
+    }
+
+    /// Get a handle whose calls fail with `NodeError::Timeout` once the deadline is reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - Instant after which calls are aborted.
+    ///
+    /// # Returns
+    ///
+    /// * `KoreApi` - Kore API bound to the deadline.
+    ///
+    pub fn with_deadline(&self, deadline: StdInstant) -> Self {
+        let mut api = self.clone();
+        api.context.deadline = Some(Instant::from_std(deadline));
+        api
+    }
+
+    /// Get a handle whose calls fail with `NodeError::Timeout` after `timeout`.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Time allowed from now.
+    ///
+    /// # Returns
+    ///
+    /// * `KoreApi` - Kore API bound to the deadline.
+    ///
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        self.with_deadline(StdInstant::now() + timeout)
+    }
+
+    /// Get a handle whose calls fail with `NodeError::Cancelled` when the token is cancelled.
+    ///
+    /// # Arguments
+    ///
+    /// * `cancellation` - Cancellation token.
+    ///
+    /// # Returns
+    ///
+    /// * `KoreApi` - Kore API bound to the token.
+    ///
+    pub fn with_cancellation(&self, cancellation: CancellationToken) -> Self {
+        let mut api = self.clone();
+        api.context.cancellation = Some(cancellation);
+        api
+    }
+
+    /// Get a handle whose calls are logged with the identity of the caller.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - Identity of the caller, e.g. its controller id or address.
+    ///
+    /// # Returns
+    ///
+    /// * `KoreApi` - Kore API bound to the identity.
+    ///
+    pub fn with_identity(&self, identity: &str) -> Self {
+        let mut api = self.clone();
+        api.context.identity = Some(identity.to_owned());
+        api
+    }
+
+    /// Get a handle for a background task of the node, whose calls are logged with the identity
+    /// of the task and admitted by limits of their own, so that the task and the clients of the
+    /// node never wait for each other.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - Identity of the task.
+    ///
+    pub(crate) fn background(&self, identity: &str) -> Self {
+        let mut api = self.with_identity(identity);
+        api.limiter = Arc::new(RwLock::new(CallLimiter::default()));
+        api
+    }
+
+    /// Get a handle whose calls are logged under an existing trace, e.g. the one of the request
+    /// that is being served.
+    ///
+    /// # Arguments
+    ///
+    /// * `trace_id` - Trace id.
+    ///
+    /// # Returns
+    ///
+    /// * `KoreApi` - Kore API bound to the trace.
+    ///
+    pub fn with_trace_id(&self, trace_id: &str) -> Self {
+        let mut api = self.clone();
+        api.context.trace_id = Some(trace_id.to_owned());
+        api
+    }
+
+    /// Run a call that reads under the context of the handle and log it.
+    /// The call is first admitted by the read limits, see `with_limits`. Each attempt is limited
+    /// by the timeout of the call settings, and the attempts that time out or fail in Kore Base
+    /// are retried with a growing backoff, see `ApiCallSettings`.
+    /// The future is dropped (and its work aborted) on cancellation or when the deadline expires.
+    async fn call<T, F, Fut>(&self, method: &str, call: F) -> Result<T, NodeError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = T>,
+        T: CallOutcome,
+    {
+        let settings = self.api_calls();
+        self.log_call(method, async {
+            let _permit = self.limiter().admit(CallClass::Read)?;
+            let mut backoff = settings.backoff;
+            let mut retries = 0;
+            loop {
+                let result = self.attempt(call(), settings.timeout).await;
+                let transient = match &result {
+                    Ok(output) => output.transient(),
+                    Err(error) => matches!(error, NodeError::Timeout),
+                };
+                if !transient || retries >= settings.retries {
+                    return result;
+                }
+                retries += 1;
+                log::debug!("Retrying {} ({}/{})", method, retries, settings.retries);
+                self.run(tokio::time::sleep(backoff)).await?;
+                backoff = backoff.saturating_mul(2);
+            }
+        })
+        .await
+    }
+
+    /// Run a call that writes under the context of the handle and log it.
+    /// The call is admitted by the write limits and bounded by the timeout of the call settings,
+    /// but never retried, since Kore Base may have taken it before failing.
+    async fn call_once<T, F>(&self, method: &str, future: F) -> Result<T, NodeError>
+    where
+        F: Future<Output = T>,
+        T: CallOutcome,
+    {
+        let timeout = self.api_calls().timeout;
+        self.log_call(method, async {
+            let _permit = self.limiter().admit(CallClass::Write)?;
+            self.attempt(future, timeout).await
+        })
+        .await
+    }
+
+    /// Await a call and write its access log entry.
+    async fn log_call<T, F>(&self, method: &str, call: F) -> Result<T, NodeError>
+    where
+        F: Future<Output = Result<T, NodeError>>,
+        T: CallOutcome,
+    {
+        let start = StdInstant::now();
+        let result = call.await;
+
+        let (status, failed) = match &result {
+            Ok(output) if output.failed() => ("error", true),
+            Ok(_) => ("ok", false),
+            Err(NodeError::Timeout) => ("timeout", true),
+            Err(NodeError::RateLimited(_)) => ("limited", false),
+            Err(_) => ("cancelled", false),
+        };
+        let trace_id = self.context.trace_id.clone().unwrap_or_else(new_trace_id);
+        self.access_log.log(&AccessEntry {
+            method,
+            latency: start.elapsed(),
+            status,
+            failed,
+            identity: self.context.identity.as_deref(),
+            trace_id: &trace_id,
+        });
+        result
+    }
+
+    /// Run an attempt of a call under the context of the handle, failing with
+    /// `NodeError::Timeout` after `timeout` unless it is 0.
+    async fn attempt<T, F>(&self, future: F, timeout: Duration) -> Result<T, NodeError>
+    where
+        F: Future<Output = T>,
+    {
+        if timeout.is_zero() {
+            return self.run(future).await;
+        }
+        self.run(tokio::time::timeout(timeout, future))
+            .await?
+            .map_err(|_| NodeError::Timeout)
+    }
+
+    /// Run a future under the deadline and cancellation of the handle.
+    async fn run<T, F>(&self, future: F) -> Result<T, NodeError>
+    where
+        F: Future<Output = T>,
+    {
+        let cancellation = self.context.cancellation.clone().unwrap_or_default();
+        let deadline = self.context.deadline;
+        tokio::select! {
+            biased;
+            _ = cancellation.cancelled() => Err(NodeError::Cancelled),
+            result = async {
+                match deadline {
+                    Some(deadline) => tokio::time::timeout_at(deadline, future)
+                        .await
+                        .map_err(|_| NodeError::Timeout),
+                    None => Ok(future.await),
+                }
+            } => result,
+        }
+    }
+
+    /// Send an event request.
+    /// If the request is a create request and the public key is not provided, a new key pair is
+    /// generated and the public key is added to the request.
+    /// If the request is not signed, a signature is generated and added to the request.
+    /// The request is then sent to the Kore API.
+    /// The request identifier is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Signed event request.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InternalApi` - The request could not be signed.
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::InvalidSignature` - The signature checks are enabled and the signature of
+    ///   the client does not pass them, see `verify_signed_request`.
+    /// * `NodeError::QuotaExceeded` - The signer reached its subject creation quota.
+    /// * `NodeError::Unauthorized` - A signing policy does not allow the signer.
+    ///
+    /// # Returns
+    ///
+    /// * `EventRequestResponse` - Id of request.
+    ///
+    pub async fn send_event_request(
+        &self,
+        request: NodeSignedEventRequest,
+    ) -> Result<EventRequestResponse, NodeError> {
+        let start = StdInstant::now();
+        let request_type = request.request.request_type();
+        let result = self.send_request(request).await;
+        self.metrics
+            .event_request(request_type, result.is_ok(), start.elapsed());
+        result
+    }
+
+    /// Send an event request once per idempotency key, see `send_event_request`. A request sent
+    /// again under the same key, e.g. retried after a timeout, gets the identifier of the first
+    /// one instead of being sent twice. Keys are kept for the time to live of the `idempotency`
+    /// collection of `kore.db_ttl.collections`, a day by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Signed event request.
+    /// * `key` - Idempotency key chosen by the client, at most `MAX_IDEMPOTENCY_KEY_LEN` bytes.
+    ///
+    /// # Errors
+    ///
+    /// As `send_event_request`, and:
+    ///
+    /// * `NodeError::InvalidParameter` - The key is empty or too long.
+    /// * `NodeError::Conflict` - The key was used for another request.
+    ///
+    /// # Returns
+    ///
+    /// * `EventRequestResponse` - Id of request, the same for every request under the key.
+    ///
+    pub async fn send_idempotent_event_request(
+        &self,
+        request: NodeSignedEventRequest,
+        key: &str,
+    ) -> Result<EventRequestResponse, NodeError> {
+        if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+            return Err(NodeError::InvalidParameter(format!(
+                "idempotency key must have from 1 to {} bytes",
+                MAX_IDEMPOTENCY_KEY_LEN
+            )));
+        }
+        let content = serde_json::to_string(&request.request)
+            .map_err(|error| NodeError::InvalidParameter(format!("event request: {}", error)))?;
+        let store = self.expiring_store(IDEMPOTENCY_COLLECTION, IDEMPOTENCY_TTL);
+        let lock = IdempotencyLock::new(&self.idempotency_locks, key);
+        let _idempotency = lock.lock.lock().await;
+        if let Some(sent) = store.get::<NodeIdempotentRequest>(key)? {
+            if sent.request != content {
+                return Err(NodeError::Conflict(format!(
+                    "idempotency key '{}' was used for another request",
+                    key
+                )));
+            }
+            return Ok(EventRequestResponse {
+                request_id: sent.request_id,
+            });
+        }
+        let response = self.send_event_request(request).await?;
+        store.put(
+            key,
+            &NodeIdempotentRequest {
+                request_id: response.request_id.clone(),
+                request: content,
+            },
+        )?;
+        Ok(response)
+    }
+
+    /// Sign, check and send an event request, see `send_event_request`.
+    async fn send_request(
+        &self,
+        mut request: NodeSignedEventRequest,
+    ) -> Result<EventRequestResponse, NodeError> {
+        if let NodeEventRequest::Create(create_request) = &mut request.request {
+            if create_request.public_key.is_none() {
+                let public_key = self
+                    .call_once("send_event_request", self.api.add_keys(self.key_derivator))
+                    .await?
+                    .map_err(|error| base_error("send_event_request", error))?;
+                create_request.public_key = Some(public_key.to_str());
+            }
+        }
+
+        let node_request = request.request.clone();
+        let external_signature = request.signature.is_some();
+        let subject_quota = self.subject_quota();
+        let quota_governance = match &request.request {
+            NodeEventRequest::Create(create_request) if subject_quota.max_subjects > 0 => {
+                Some(create_request.governance_id.clone())
+            }
+            _ => None,
+        };
+        let Ok(event_request) = BaseEventRequest::try_from(request.request) else {
+            return Err(NodeError::InvalidParameter("event request".to_owned()));
+        };
+
+        let signature = match request.signature {
+            Some(signature) => {
+                let Ok(signature) = BaseSignature::try_from(signature) else {
+                    return Err(NodeError::InvalidParameter("signature".to_owned()));
+                };
+                signature
+            }
+            None => BaseSignature::new(&event_request, &self.keys, self.digest_derivator)
+                .map_err(|_| NodeError::InternalApi("Failed to create signature".to_owned()))?,
+        };
+        let signature_check = self.signature_check()?;
+        if external_signature && signature_check.enabled {
+            self.check_signature(&event_request, &signature, &signature_check)?;
+        }
+        // A poisoned lock is an error, so that no request is signed unchecked.
+        let policies = self
+            .signing_policies
+            .read()
+            .map_err(|_| NodeError::InternalApi("Signing policies unavailable".to_owned()))?;
+        check_signing_policies(
+            &policies,
+            node_request.request_type(),
+            &signature.signer.to_str(),
+            external_signature,
+        )?;
+        drop(policies);
+
+        let timestamp = timestamp_millis();
+        // The creation is counted before it is sent, in the same lock as the check, and given
+        // back unless Kore Base may have taken it: only a timeout keeps it counted.
+        let quota = match quota_governance {
+            Some(governance_id) => {
+                let key = format!("{}:{}", signature.signer.to_str(), governance_id);
+                let _quota = self
+                    .quota_lock
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                let mut usage = self.subject_quota_usage(&key, &subject_quota, timestamp)?;
+                if usage.len() as u64 >= subject_quota.max_subjects {
+                    return Err(NodeError::QuotaExceeded(format!(
+                        "{} subjects created by {} in governance '{}'",
+                        usage.len(),
+                        signature.signer.to_str(),
+                        governance_id
+                    )));
+                }
+                usage.push(timestamp);
+                self.quota_store().put(&key, &usage)?;
+                Some(key)
+            }
+            None => None,
+        };
+
+        let release_quota = || {
+            if let Some(key) = &quota {
+                if let Err(release) = self.release_subject_quota(key, timestamp) {
+                    log::warn!("Subject quota of {} not given back: {}", key, release);
+                }
+            }
+        };
+        let sent = self
+            .call_once(
+                "send_event_request",
+                self.api.external_request(BaseSigned {
+                    content: event_request,
+                    signature,
+                }),
+            )
+            .await;
+        match sent {
+            Ok(Ok(id)) => {
+                // The key is taken when the request is written, later than the key of any request
+                // written before, so that a reader of `requests_after` cannot skip it.
+                let mut last = self
+                    .requests_lock
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                *last = timestamp_millis().max(*last + 1);
+                let mut batch = StoreBatch::default();
+                let record =
+                    NodeRequestRecord::new(id.to_str(), &node_request, *last, request.origin);
+                let requests = self.requests_store();
+                requests.batch_put(&mut batch, &record.request_id, &record)?;
+                self.requests_order().batch_put(
+                    &mut batch,
+                    &request_order_key(record.timestamp, &record.request_id),
+                    &record.request_id,
+                )?;
+                requests.write(batch)?;
+                Ok(EventRequestResponse {
+                    request_id: record.request_id,
+                })
+            }
+            Ok(Err(error)) => {
+                release_quota();
+                Err(base_error("send_event_request", error))
+            }
+            Err(NodeError::Timeout) => Err(NodeError::Timeout),
+            Err(error) => {
+                release_quota();
+                Err(error)
+            }
+        }
+    }
+
+    /// List event requests.
+    /// Lists the event requests sent through this node, oldest first, along with the origin
+    /// metadata provided by the caller. Requests are kept for the time to live of the `requests`
+    /// collection, 30 days by default. A page is read from the order of the requests, without
+    /// loading the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `parameters` - Pagination parameters, `from` is a request identifier.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - Database error.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<NodeRequestRecord>` - Vector of sent requests.
+    ///
+    pub fn list_requests(
+        &self,
+        parameters: PaginatorFromString,
+    ) -> Result<Vec<NodeRequestRecord>, NodeError> {
+        let after = match parameters.from {
+            Some(from) => match self.requests_store().get::<NodeRequestRecord>(&from)? {
+                Some(record) => Some(request_order_key(record.timestamp, &from)),
+                // Nothing is listed after a request that is not stored.
+                None => return Ok(vec![]),
+            },
+            None => None,
+        };
+        let quantity = parameters
+            .quantity
+            .map(|quantity| quantity.max(0) as usize)
+            .unwrap_or(usize::MAX);
+        Ok(self
+            .requests_after(after.as_deref(), quantity)?
+            .into_iter()
+            .map(|(_, record)| record)
+            .collect())
+    }
+
+    /// Requests sent through this node after the cursor `after`, oldest first, along with the
+    /// cursor of each one. A cursor is the key of the request in `requests_order`, so reading
+    /// after it works even when its request is no longer stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `after` - Cursor of the last request read, none to read from the first one.
+    /// * `quantity` - Largest number of requests read.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - Database error.
+    ///
+    pub(crate) fn requests_after(
+        &self,
+        after: Option<&str>,
+        quantity: usize,
+    ) -> Result<Vec<(String, NodeRequestRecord)>, NodeError> {
+        let order = self.requests_order();
+        let requests = self.requests_store();
+        self.index_requests(&order, &requests)?;
+        let mut records = vec![];
+        for entry in order.iter_from::<String>(after.unwrap_or_default()) {
+            if records.len() >= quantity {
+                break;
+            }
+            let (cursor, request_id) = entry?;
+            if Some(cursor.as_str()) == after {
+                continue;
+            }
+            if let Some(record) = requests.get::<NodeRequestRecord>(&request_id)? {
+                records.push((cursor, record));
+            }
+        }
+        Ok(records)
+    }
+
+    /// Cursor of the last request sent through this node, if any, see `requests_after`.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - Database error.
+    ///
+    pub(crate) fn last_request_cursor(&self) -> Result<Option<String>, NodeError> {
+        let order = self.requests_order();
+        self.index_requests(&order, &self.requests_store())?;
+        let last = order.iter::<String>("", true).next().transpose()?;
+        Ok(last.map(|(cursor, _)| cursor))
+    }
+
+    /// Add the requests recorded before `requests_order` existed to it, when it is empty.
+    fn index_requests(&self, order: &NodeStore, requests: &NodeStore) -> Result<(), NodeError> {
+        if order.iter::<String>("", false).next().is_some() {
+            return Ok(());
+        }
+        let mut batch = StoreBatch::default();
+        for entry in requests.iter::<NodeRequestRecord>("", false) {
+            let (request_id, record) = entry?;
+            order.batch_put(
+                &mut batch,
+                &request_order_key(record.timestamp, &request_id),
+                &request_id,
+            )?;
+        }
+        order.write(batch)
+    }
+
+    /// Get the node history.
+    /// Operational actions on the node, oldest first: starts and stops, settings reloads, key
+    /// rotations, listen failovers and support bundles. The history is kept in the node
+    /// database for the time to live of the `history` collection, a year by default, so it
+    /// survives restarts, and it is separate from the ledger activity.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - Database error.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<NodeHistoryEntry>` - Entries of the history.
+    ///
+    pub fn node_history(&self) -> Result<Vec<NodeHistoryEntry>, NodeError> {
+        Ok(self
+            .history_store()
+            .entries::<NodeHistoryEntry>()?
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .collect())
+    }
+
+    /// Add an entry to the node history. The history is informative, a failure is only logged.
+    pub(crate) fn record_history(&self, kind: NodeHistoryKind, detail: &str) {
+        let entry = NodeHistoryEntry {
+            timestamp: timestamp_millis(),
+            kind,
+            detail: detail.to_owned(),
+        };
+        let store = self.history_store();
+        let result = (0..)
+            // Keys sort by time, the suffix keeps apart the entries of the same millisecond.
+            .map(|sequence| format!("{:020}.{:04}", entry.timestamp, sequence))
+            .find_map(|key| match store.get::<NodeHistoryEntry>(&key) {
+                Ok(Some(_)) => None,
+                Ok(None) => Some(store.put(&key, &entry)),
+                Err(error) => Some(Err(error)),
+            })
+            .unwrap_or(Ok(()));
+        if let Err(error) = result {
+            log::warn!("Node history entry {:?} not stored: {}", kind, error);
+        }
+    }
+
+    /// Account a request served to `caller` in the current usage window.
+    /// The REST and gRPC servers account every request they serve, with the principal of its
+    /// credentials as the caller, or the client address when the endpoints require none. The
+    /// request is counted in memory, and stored by the next `flush_usage`.
+    ///
+    /// # Arguments
+    ///
+    /// * `caller` - Caller, e.g. `key:1` or the IP address of the client.
+    /// * `bytes` - Payload bytes received with the request.
+    ///
+    pub fn record_usage(&self, caller: &str, bytes: u64) {
+        self.usage.record(caller, bytes, timestamp_millis());
+    }
+
+    /// Add the usage counted since the last flush to the node store, and delete the windows
+    /// older than the time to live of the `usage` collection, `USAGE_RETENTION` by default.
+    /// The usage that could not be stored is counted again for the next flush.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - Database error.
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - Windows deleted.
+    ///
+    pub fn flush_usage(&self) -> Result<usize, NodeError> {
+        let _flushing = self.usage.flushing();
+        let store = self.usage_store();
+        let pending = self.usage.take();
+        let mut batch = StoreBatch::default();
+        let result = pending.iter().try_for_each(|counted| {
+            let key = usage_key(counted.from, &counted.caller);
+            let mut usage = store.get::<NodeUsage>(&key)?.unwrap_or_else(|| NodeUsage {
+                requests: 0,
+                bytes: 0,
+                ..counted.clone()
+            });
+            usage.requests += counted.requests;
+            usage.bytes += counted.bytes;
+            store.batch_put(&mut batch, &key, &usage)
+        });
+        if let Err(error) = result.and_then(|_| store.write(batch)) {
+            self.usage.restore(pending);
+            return Err(error);
+        }
+
+        let retention = self.db_ttl.ttl("usage", USAGE_RETENTION);
+        let cutoff = window_start(
+            timestamp_millis().saturating_sub(retention.as_millis().min(u64::MAX.into()) as u64),
+        );
+        let mut expired = StoreBatch::default();
+        let mut deleted = 0;
+        // Keys sort by window, the oldest first.
+        for entry in store.iter::<NodeUsage>("", false) {
+            let (key, usage) = entry?;
+            if usage.from >= cutoff {
+                break;
+            }
+            store.batch_del(&mut expired, &key);
+            deleted += 1;
+        }
+        if !expired.is_empty() {
+            store.write(expired)?;
+        }
+        Ok(deleted)
+    }
+
+    /// Get the usage of the node API by window, oldest first, including the usage not flushed
+    /// yet. Windows last `USAGE_WINDOW` and are returned when they start inside `range`.
+    ///
+    /// # Arguments
+    ///
+    /// * `caller` - Caller whose usage is returned, every caller when `None`.
+    /// * `range` - Milliseconds since UNIX epoch.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - Database error.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<NodeUsage>` - Usage of each caller and window.
+    ///
+    pub fn usage(
+        &self,
+        caller: Option<&str>,
+        range: Range<u64>,
+    ) -> Result<Vec<NodeUsage>, NodeError> {
+        if range.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut windows = BTreeMap::<(u64, String), NodeUsage>::new();
+        let store = self.usage_store();
+        // Only the windows sharing the digits of the range are read.
+        for entry in store.iter::<NodeUsage>(&windows_prefix(range.start, range.end), false) {
+            let (_, usage) = entry?;
+            if usage.from < range.start {
+                continue;
+            }
+            if usage.from >= range.end {
+                break;
+            }
+            windows.insert((usage.from, usage.caller.clone()), usage);
+        }
+        for counted in self.usage.pending(range.start, range.end) {
+            let usage = windows
+                .entry((counted.from, counted.caller.clone()))
+                .or_insert_with(|| NodeUsage {
+                    requests: 0,
+                    bytes: 0,
+                    ..counted.clone()
+                });
+            usage.requests += counted.requests;
+            usage.bytes += counted.bytes;
+        }
+        Ok(windows
+            .into_values()
+            .filter(|usage| caller.is_none_or(|caller| usage.caller == caller))
+            .collect())
+    }
+
+    /// Get the usage of the node API by caller, e.g. for chargeback between the members of a
+    /// shared node. The period of each summary is `range`.
+    ///
+    /// # Arguments
+    ///
+    /// * `range` - Milliseconds since UNIX epoch, see `usage`.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - Database error.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<NodeUsage>` - Totals of each caller, sorted by caller.
+    ///
+    pub fn usage_summary(&self, range: Range<u64>) -> Result<Vec<NodeUsage>, NodeError> {
+        let mut summaries = BTreeMap::<String, NodeUsage>::new();
+        for usage in self.usage(None, range.clone())? {
+            let summary = summaries
+                .entry(usage.caller.clone())
+                .or_insert_with(|| NodeUsage {
+                    caller: usage.caller,
+                    from: range.start,
+                    to: range.end,
+                    requests: 0,
+                    bytes: 0,
+                });
+            summary.requests += usage.requests;
+            summary.bytes += usage.bytes;
+        }
+        Ok(summaries.into_values().collect())
+    }
+
+    /// Record the start of the node, and a key rotation when the controller id is not the one
+    /// of the previous start.
+    pub(crate) fn record_start(&self) {
+        let controller_id = self.get_controller_id();
+        match self.store.get::<String>(CONTROLLER_KEY) {
+            Ok(Some(previous)) if previous != controller_id => self.record_history(
+                NodeHistoryKind::KeysRotated,
+                &format!("controller {} replaced by {}", previous, controller_id),
+            ),
+            Ok(_) => {}
+            Err(error) => log::warn!("Previous controller id not read: {}", error),
+        }
+        if let Err(error) = self.store.put(CONTROLLER_KEY, &controller_id) {
+            log::warn!("Controller id not stored: {}", error);
+        }
+        self.record_history(
+            NodeHistoryKind::Started,
+            &format!("controller {}", controller_id),
+        );
+    }
+
+    /// Get an event request.
+    /// The request is retrieved from the Kore API.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id` - Event request identifier.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The request does not exist.
+    /// * `NodeError::InvalidParameter` - Invalid request identifier.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeSignedEventRequest` - Signed event request.
+    ///
+    pub async fn get_event_request(
+        &self,
+        request_id: &str,
+    ) -> Result<NodeSignedEventRequest, NodeError> {
+        let request_id = DigestIdentifier::from_str(request_id)
+            .map_err(|_| NodeError::InvalidParameter("request identifier".to_owned()))?;
+        let result = self
+            .call("get_event_request", || {
+                self.api.get_request(request_id.clone())
+            })
+            .await?
+            .map_err(|error| base_error("get_event_request", error))?;
+        Ok(NodeSignedEventRequest::from(result))
+    }
+
+    /// Get an state of event request.
+    /// The state of request is retrieved from the Kore API.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id` - Event request identifier.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The request does not exist.
+    /// * `NodeError::InvalidParameter` - Invalid request identifier.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeKoreRequestState` - State of event request.
+    ///
+    pub async fn get_event_request_state(
+        &self,
+        request_id: &str,
+    ) -> Result<NodeKoreRequestState, NodeError> {
+        let request_id = DigestIdentifier::from_str(request_id)
+            .map_err(|_| NodeError::InvalidParameter("request identifier".to_owned()))?;
+        let result = self
+            .call("get_event_request_state", || {
+                self.api.get_request(request_id.clone())
+            })
+            .await?
+            .map_err(|error| base_error("get_event_request_state", error))?;
+        let state = NodeKoreRequestState::from(result);
+        self.transitions.observe(&state);
+        Ok(state)
+    }
+
+    /// Wait for the state of an event request to change, for clients that poll it.
+    /// Returns as soon as the state differs from `last_seen_state`, or is final, and otherwise
+    /// when the timeout expires, with the unchanged state. The request is read periodically
+    /// while waiting, and the transitions observed by other reads wake the wait up earlier.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id` - Event request identifier.
+    /// * `last_seen_state` - State the client already knows.
+    /// * `timeout` - Time to wait for a change, at most `MAX_STATE_WAIT`.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The request does not exist.
+    /// * `NodeError::InvalidParameter` - Invalid request identifier.
+    /// * `NodeError::Cancelled` - The handle was cancelled while waiting.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeKoreRequestState` - Current state of the request.
+    ///
+    pub async fn wait_state_change(
+        &self,
+        request_id: &str,
+        last_seen_state: NodeRequestState,
+        timeout: Duration,
+    ) -> Result<NodeKoreRequestState, NodeError> {
+        let deadline = Instant::now() + timeout.min(MAX_STATE_WAIT);
+        let mut transitions = self.transitions.subscribe();
+        loop {
+            let state = self.get_event_request_state(request_id).await?;
+            if state.state != last_seen_state || state.is_final() || Instant::now() >= deadline {
+                return Ok(state);
+            }
+            let wake_up = (Instant::now() + STATE_POLL_INTERVAL).min(deadline);
+            self.run(async {
+                while let Ok(Ok(transition)) =
+                    tokio::time::timeout_at(wake_up, transitions.recv()).await
+                {
+                    if transition.request_id == state.id {
+                        break;
+                    }
+                }
+            })
+            .await?;
+        }
+    }
+
+    /// Subscribe to the changes of state of the event requests.
+    /// Transitions are pushed when the node reads the state of a request (e.g. through
+    /// `get_event_request_state` or the webhooks) and finds it different from the last read.
+    ///
+    /// # Returns
+    ///
+    /// * `broadcast::Receiver<NodeRequestTransition>` - Transitions observed from now on.
+    ///
+    pub fn subscribe_request_transitions(&self) -> broadcast::Receiver<NodeRequestTransition> {
+        self.transitions.subscribe()
+    }
+
+    /// Get approval events.
+    /// Get the status of the approval events you want to obtain, among the 4 available:
+    /// - Pending: events pending voting.
+    /// - Obsolete: events to which the node has not taken any vote, but have become obsolete and can no longer take a vote.
+    /// - RespondedAccepted: Events to which the node has voted in favor.
+    /// - RespondedRejected: Events which the node has voted against.
+    /// The get that obtains the events is performed.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters for retrieving approval events.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    ///
+    /// # Returns
+    ///
+    /// * `Page<NodeApprovalEntity>` - Page of approval events, the cursor is an approval id.
+    ///
+    pub async fn get_approvals(
+        &self,
+        params: NodeGetApprovals,
+    ) -> Result<Page<NodeApprovalEntity>, NodeError> {
+        let status = match params.status {
+            None => None,
+            Some(value) => match value.to_lowercase().as_str() {
+                "pending" => Some(ApprovalState::Pending),
+                "obsolete" => Some(ApprovalState::Obsolete),
+                "responded_accepted" => Some(ApprovalState::RespondedAccepted),
+                "responded_rejected" => Some(ApprovalState::RespondedRejected),
+                other => {
+                    return Err(NodeError::InvalidParameter(format!(
+                        "Invalid ApprovalState: {}",
+                        other
+                    )));
+                }
+            },
+        };
+
+        let first = params.from.is_none();
+        match self
+            .call("get_approvals", || {
+                self.api.get_approvals(
+                    status.clone(),
+                    params.from.clone(),
+                    Page::<NodeApprovalEntity>::read_quantity(params.quantity),
+                )
+            })
+            .await?
+            .map(|result| {
+                result
+                    .into_iter()
+                    .map(NodeApprovalEntity::from)
+                    .collect::<Vec<NodeApprovalEntity>>()
+            }) {
+            Ok(res) => Ok(Page::cut(res, first, params.quantity, |approval| {
+                approval.id.clone()
+            })),
+            Err(error) => Err(base_error("get_approvals", error)),
+        }
+    }
+
+    /// Count the approvals pending a vote and update the `pending_approvals` metric.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - Number of pending approvals.
+    ///
+    pub async fn refresh_pending_approvals(&self) -> Result<u64, NodeError> {
+        let pending = self
+            .call("get_approvals", || {
+                self.api
+                    .get_approvals(Some(ApprovalState::Pending), None, None)
+            })
+            .await?
+            .map_err(|error| base_error("get_approvals", error))?
+            .len() as u64;
+        self.metrics.set_pending_approvals(pending);
+        Ok(pending)
+    }
+
+    /// Get approval event.
+    /// Gets an approval event from ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of approval event.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The approval does not exist.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeApprovalEntity` - Approval event.
+    ///
+    pub async fn get_approval_id(&self, id: &str) -> Result<NodeApprovalEntity, NodeError> {
+        let id = DigestIdentifier::from_str(id)
+            .map_err(|_| NodeError::InvalidParameter("approval request identifier".to_owned()))?;
+        let result = self
+            .call("get_approval_id", || self.api.get_approval(id.clone()))
+            .await?
+            .map_err(|error| base_error("get_approval_id", error))?;
+        Ok(NodeApprovalEntity::from(result))
+    }
+
+    /// Voting of an approval event.
+    /// Accepts or denies an approval event.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of approval event.
+    /// * `response` - Event approval or denial.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The approval does not exist.
+    /// * `NodeError::Conflict` - The approval is no longer pending.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeApprovalEntity` - Approval event updated with user voting.
+    ///
+    pub async fn approval_request(
+        &self,
+        id: &str,
+        response: PatchVote,
+    ) -> Result<NodeApprovalEntity, NodeError> {
+        let acceptance = match response {
+            PatchVote::RespondedAccepted => true,
+            PatchVote::RespondedRejected => false,
+        };
+
+        let id = DigestIdentifier::from_str(id)
+            .map_err(|_| NodeError::InvalidParameter("request identifier".to_owned()))?;
+        match self
+            .call_once(
+                "approval_request",
+                self.api.approval_request(id, acceptance),
+            )
+            .await?
+            .map(NodeApprovalEntity::from)
+        {
+            Ok(result) => Ok(result),
+            Err(error) => Err(base_error("approval_request", error)),
+        }
+    }
+
+    /// Get all allowed subjects and providers.
+    /// Obtain all subjects and suppliers that have been previously permitted.
+    /// The filters are applied before the page is cut, so a page holds `quantity` matches unless
+    /// the preauthorizations run out; the next page starts after the last subject returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `parameters` - Subject after which the page starts, and maximum number of entries.
+    /// * `filter` - Provider, governance and namespace of the subjects.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::InvalidParameter` - Invalid filter.
+    ///
+    /// # Returns
+    ///
+    /// * `Page<PreauthorizedSubjectsResponse>` - Page of allowed subjects and providers, the
+    ///   cursor is a subject id.
+    ///
+    pub async fn get_all_allowed_subjects_and_providers(
+        &self,
+        parameters: PaginatorFromString,
+        filter: NodeAllowedSubjectsFilter,
+    ) -> Result<Page<PreauthorizedSubjectsResponse>, NodeError> {
+        let first = parameters.from.is_none();
+        let limit = Page::<PreauthorizedSubjectsResponse>::read_quantity(parameters.quantity)
+            .map(|quantity| quantity as usize)
+            .unwrap_or(usize::MAX);
+        let matches = self
+            .scan_allowed_subjects(parameters.from, &filter, limit)
+            .await?;
+        Ok(Page::cut(matches, first, parameters.quantity, |entry| {
+            entry.subject_id.clone()
+        }))
+    }
+
+    /// Count the allowed subjects that match a filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Provider, governance and namespace of the subjects.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::InvalidParameter` - Invalid filter.
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - Number of matching subjects.
+    ///
+    pub async fn count_allowed_subjects(
+        &self,
+        filter: NodeAllowedSubjectsFilter,
+    ) -> Result<u64, NodeError> {
+        let matches = self
+            .scan_allowed_subjects(None, &filter, usize::MAX)
+            .await?;
+        Ok(matches.len() as u64)
+    }
+
+    /// Preauthorize subject.
+    /// Preauthorize subject to receive a copy of the ledger.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - ID of subject.
+    /// * `data` - Vec of providers.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    ///
+    /// # Returns
+    ///
+    /// * `String` - 'Ok' if everything went well.
+    ///
+    pub async fn add_preauthorize_subject(
+        &self,
+        subject_id: &str,
+        data: AuthorizeSubject,
+    ) -> Result<String, NodeError> {
+        let mut providers = HashSet::new();
+        for provider in data.providers.iter() {
+            let provider = match KeyIdentifier::from_str(provider) {
+                Ok(provider) => provider,
+                Err(_error) => {
+                    return Err(NodeError::InvalidParameter(format!(
+                        "Invalid key identifier {}",
+                        provider
+                    )))
+                }
+            };
+            providers.insert(provider);
+        }
+
+        let subject_id = DigestIdentifier::from_str(subject_id).map_err(|_| {
+            NodeError::InvalidParameter(format!("Invalid digest identifier {}", subject_id))
+        })?;
+        match self
+            .call_once(
+                "add_preauthorize_subject",
+                self.api.add_preauthorize_subject(&subject_id, &providers),
+            )
+            .await?
+        {
+            Ok(_) => Ok("Ok".to_owned()),
+            Err(error) => Err(base_error("add_preauthorize_subject", error)),
+        }
+    }
+
+    /// Generate keys in node and return public key.
+    /// Generates a key pair on the node and returns the public key.
+    ///
+    /// # Arguments
+    ///
+    /// * `parameters` - Algorithm for generating cryptographic material.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    ///
+    /// # Returns
+    ///
+    /// * `String` - 'Ok' if everything went well.
+    ///
+    pub async fn register_keys(&self, parameters: NodeKeys) -> Result<String, NodeError> {
+        let derivator = KeyDerivator::from(parameters.algorithm.unwrap_or(KeyAlgorithms::Ed25519));
+
+        match self
+            .call_once("register_keys", self.api.add_keys(derivator))
+            .await?
+        {
+            Ok(pub_key) => Ok(pub_key.to_str()),
+            Err(error) => Err(base_error("register_keys", error)),
+        }
+    }
+
+    /// Get subjects.
+    /// Depending on the parameters you can obtain:
+    /// - All the governances known to a node.
+    /// - All the traceability subjects of a governance.
+    /// - All traceability subjects of the node, including the governance and the subjects of the governance.
+    ///
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters for retrieving subjects.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    ///
+    /// # Returns
+    ///
+    /// * `Page<NodeSubjectData>` - Page of subjects, the cursor is a subject id.
+    ///
+    pub async fn get_subjects(
+        &self,
+        parameters: NodeSubjects,
+    ) -> Result<Page<NodeSubjectData>, NodeError> {
+        self.get_subjects_by(parameters, NodeSubjectKeys::default())
+            .await
+    }
+
+    /// Get subjects by owner or namespace.
+    /// As `get_subjects`, with the subjects of an owner or a namespace looked up through the
+    /// secondary indexes of the database instead of Kore Base, see `search_subjects`.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters for retrieving subjects.
+    /// * `keys` - Owner and namespace of the subjects, if any.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::Database` - The secondary indexes could not be read.
+    ///
+    /// # Returns
+    ///
+    /// * `Page<NodeSubjectData>` - Page of subjects, the cursor is a subject id.
+    ///
+    pub async fn get_subjects_by(
+        &self,
+        parameters: NodeSubjects,
+        keys: NodeSubjectKeys,
+    ) -> Result<Page<NodeSubjectData>, NodeError> {
+        enum SubjectType {
+            All,
+            Governances,
+        }
+        let subject_type = match &parameters.subject_type {
+            Some(data) => match data.to_lowercase().as_str() {
+                "all" => SubjectType::All,
+                "governances" => {
+                    if parameters.governanceid.is_some() {
+                        return Err(NodeError::InvalidParameter(
+                            "governanceid can not be specified with subject_type=governances"
+                                .to_string(),
+                        ));
+                    }
+                    SubjectType::Governances
+                }
+                other => {
+                    return Err(NodeError::InvalidParameter(format!(
+                        "unknown parameter {}",
+                        other
+                    )));
+                }
+            },
+            None => SubjectType::All,
+        };
+        let archive_filter = match &parameters.archive_filter {
+            Some(data) => match data.to_lowercase().as_str() {
+                "unarchived" => ArchiveFilter::Unarchived,
+                "archived" => ArchiveFilter::Archived,
+                "all" => ArchiveFilter::All,
+                other => {
+                    return Err(NodeError::InvalidParameter(format!(
+                        "unknown archive filter {}",
+                        other
+                    )));
+                }
+            },
+            None => ArchiveFilter::Unarchived,
+        };
+        let keep = |subject: &NodeSubjectData| match archive_filter {
+            ArchiveFilter::Unarchived => !subject.archived,
+            ArchiveFilter::Archived => subject.archived,
+            ArchiveFilter::All => true,
+        };
+
+        let indexed = keys.owner.is_some() || keys.namespace.is_some();
+        let governance_id = match &parameters.governanceid {
+            Some(data) if !indexed => Some(
+                DigestIdentifier::from_str(data)
+                    .map_err(|_| NodeError::InvalidParameter("governanceid".to_owned()))?,
+            ),
+            _ => None,
+        };
+
+        // Subjects are read until the page is full of the ones the archive filter keeps.
+        let first = parameters.from.is_none();
+        let quantity = Page::<NodeSubjectData>::read_quantity(parameters.quantity);
+        let archived = self.archived_store();
+        let mut kept = vec![];
+        let mut from = parameters.from.clone();
+        loop {
+            let (subjects, more) = if indexed {
+                let page = self.search_subjects(NodeSubjectSearch {
+                    namespace: keys.namespace.clone(),
+                    schema_id: matches!(subject_type, SubjectType::Governances)
+                        .then(|| "governance".to_owned()),
+                    owner: keys.owner.clone(),
+                    governance_id: parameters.governanceid.clone(),
+                    from: from.clone(),
+                    quantity: parameters.quantity,
+                    ..Default::default()
+                })?;
+                let more = page.next_cursor.is_some();
+                (page.items, more)
+            } else {
+                let data = match (&subject_type, &governance_id) {
+                    (SubjectType::All, Some(governance_id)) => {
+                        self.call("get_subjects", || {
+                            self.api.get_subjects_by_governance(
+                                governance_id.clone(),
+                                from.clone(),
+                                quantity,
+                            )
+                        })
+                        .await?
+                    }
+                    (SubjectType::All, None) => {
+                        self.call("get_subjects", || {
+                            self.api.get_subjects("".into(), from.clone(), quantity)
+                        })
+                        .await?
+                    }
+                    (SubjectType::Governances, _) => {
+                        self.call("get_subjects", || {
+                            self.api.get_governances("".into(), from.clone(), quantity)
+                        })
+                        .await?
+                    }
+                }
+                .map_err(|error| base_error("get_subjects", error))?;
+                let more = quantity.is_some_and(|quantity| data.len() as i64 >= quantity);
+                (data.into_iter().map(NodeSubjectData::from).collect(), more)
+            };
+            from = subjects.last().map(|subject| subject.subject_id.clone());
+            for mut subject in subjects {
+                subject.archived = archived.get::<u64>(&subject.subject_id)?.is_some();
+                if keep(&subject) {
+                    kept.push(subject);
+                }
+            }
+            let full = quantity.is_some_and(|quantity| kept.len() as i64 >= quantity);
+            if full || !more || from.is_none() {
+                break;
+            }
+        }
+        Ok(Page::cut(kept, first, parameters.quantity, |subject| {
+            subject.subject_id.clone()
+        }))
+    }
+
+    /// Search the subjects known to the node, through the secondary indexes of the database, see
+    /// the `search` module. The indexes are updated as Kore Base writes the subjects, right
+    /// after the write, so an event is found shortly after it is committed.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Namespace, schema, owner, governance, active flag, JSONPath expressions over
+    ///   the properties and text the subjects must match, and the page to read.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - A JSONPath expression is not valid, or the subjects are
+    ///   not indexed.
+    /// * `NodeError::Database` - The indexes could not be read.
+    ///
+    /// # Returns
+    ///
+    /// * `Page<NodeSubjectData>` - Page of subjects, the cursor is a subject id.
+    ///
+    pub fn search_subjects(
+        &self,
+        filter: NodeSubjectSearch,
+    ) -> Result<Page<NodeSubjectData>, NodeError> {
+        let mut page = self.subject_index()?.search(&filter)?;
+        let archived = self.archived_store();
+        for subject in page.items.iter_mut() {
+            subject.archived = archived.get::<u64>(&subject.subject_id)?.is_some();
+        }
+        Ok(page)
+    }
+
+    /// Rebuild the secondary indexes of the subjects from Kore Base, for databases written
+    /// before them or whose indexes were lost. The indexes are rebuilt aside and replace the
+    /// current ones once complete, which are read until then.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - The subjects are not indexed.
+    /// * `NodeError::Conflict` - The indexes are already being rebuilt.
+    /// * `NodeError::Database` - The indexes could not be written.
+    /// * Any error of `get_subjects`.
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - Subjects indexed.
+    ///
+    pub async fn reindex_subjects(&self) -> Result<usize, NodeError> {
+        let rebuild = self.subject_index()?.start_rebuild()?;
+        let indexed = self
+            .scan_subjects(|subjects| rebuild.write(subjects))
+            .await?;
+        let deleted = rebuild.finish()?;
+        log::info!("Subject indexes rebuilt, {} previous keys deleted", deleted);
+        Ok(indexed)
+    }
+
+    /// Write every subject whose sequence number or active flag changed to the indexes, e.g.
+    /// after a restart.
+    pub(crate) async fn index_subjects(&self) -> Result<usize, NodeError> {
+        let index = self.subject_index()?;
+        self.scan_subjects(|subjects| index.update(subjects)).await
+    }
+
+    /// Write the subjects Kore Base wrote to the indexes. Those Kore Base no longer holds are
+    /// deleted from the indexes.
+    pub(crate) async fn index_changed(
+        &self,
+        subject_ids: BTreeSet<String>,
+    ) -> Result<usize, NodeError> {
+        let index = self.subject_index()?;
+        let mut subjects = Vec::with_capacity(subject_ids.len());
+        for subject_id in subject_ids {
+            match self.get_subject(&subject_id).await {
+                Ok(subject) => subjects.push(subject),
+                Err(NodeError::NotFound(_)) => index.remove(&subject_id)?,
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(index.update(&subjects)?.len())
+    }
+
+    /// Page through every subject of Kore Base, writing each page with `write`.
+    async fn scan_subjects<F>(&self, write: F) -> Result<usize, NodeError>
+    where
+        F: Fn(&[NodeSubjectData]) -> Result<Vec<String>, NodeError>,
+    {
+        let mut from = None;
+        let mut updated = 0;
+        loop {
+            let page = self
+                .get_subjects(NodeSubjects {
+                    from,
+                    quantity: Some(PAGE_SIZE),
+                    subject_type: None,
+                    governanceid: None,
+                    archive_filter: Some("all".to_owned()),
+                })
+                .await?;
+            updated += write(&page.items)?.len();
+            match page.next_cursor {
+                Some(cursor) => from = Some(cursor),
+                None => return Ok(updated),
+            }
+        }
+    }
+
+    /// Get subject.
+    /// Obtains the information of a traceability subject from its id.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The subject is not known by the node.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeSubjectData` - Subject of traceability.
+    ///
+    pub async fn get_subject(&self, subject_id: &str) -> Result<NodeSubjectData, NodeError> {
+        let subject_id = DigestIdentifier::from_str(subject_id)
+            .map_err(|_| NodeError::InvalidParameter("invalid subject_id".to_owned()))?;
+        match self
+            .call("get_subject", || self.api.get_subject(subject_id.clone()))
+            .await?
+            .map(NodeSubjectData::from)
+        {
+            Ok(mut result) => {
+                result.archived = self
+                    .archived_store()
+                    .get::<u64>(&result.subject_id)?
+                    .is_some();
+                Ok(result)
+            }
+            Err(error) => Err(base_error("get_subject", error)),
+        }
+    }
+
+    /// Get subject, unless it is unchanged.
+    /// Compares the tags known by the caller with `NodeSubjectData::etag`, so that polling clients
+    /// do not download the same state again.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    /// * `etag` - Entity tags known by the caller, as in an `If-None-Match` header.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The subject is not known by the node.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<NodeSubjectData>` - Subject, `None` when one of the tags is current.
+    ///
+    pub async fn get_subject_if_changed(
+        &self,
+        subject_id: &str,
+        etag: &str,
+    ) -> Result<Option<NodeSubjectData>, NodeError> {
+        let subject = self.get_subject(subject_id).await?;
+        if etag_matches(etag, &subject.etag()) {
+            Ok(None)
+        } else {
+            Ok(Some(subject))
+        }
+    }
+
+    /// Archive subject.
+    /// Marks a subject as archived in this node. Archived subjects are hidden from the default
+    /// listing of `get_subjects`. The flag is local to the node and does not affect the ledger.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::Database` - Database error.
+    ///
+    /// # Returns
+    ///
+    /// * `String` - 'Ok' if everything went well.
+    ///
+    pub async fn archive_subject(&self, subject_id: &str) -> Result<String, NodeError> {
+        let subject = self.get_subject(subject_id).await?;
+        self.archived_store()
+            .put(&subject.subject_id, &(timestamp_millis() / 1000))?;
+        Ok("Ok".to_owned())
+    }
+
+    /// Unarchive subject.
+    /// Removes the archived mark of a subject, showing it again in the default listings.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::Database` - Database error.
+    ///
+    /// # Returns
+    ///
+    /// * `String` - 'Ok' if everything went well.
+    ///
+    pub async fn unarchive_subject(&self, subject_id: &str) -> Result<String, NodeError> {
+        let subject_id = DigestIdentifier::from_str(subject_id)
+            .map_err(|_| NodeError::InvalidParameter("invalid subject_id".to_owned()))?;
+        self.archived_store().del(&subject_id.to_str())?;
+        Ok("Ok".to_owned())
+    }
+
+    /// Transfer subject.
+    /// Sends a transfer request of a subject owned by this node, signed by the node. The subject,
+    /// its owner and the new key are checked first, so that a request Kore Base would reject is
+    /// not sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    /// * `new_public_key` - Public key of the new owner.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - Invalid subject id or public key.
+    /// * `NodeError::NotFound` - The subject is not known by the node.
+    /// * `NodeError::Unauthorized` - The subject is owned by another key.
+    /// * `NodeError::Conflict` - The subject reached its end of life, or the key already owns it.
+    /// * Any error of `send_event_request`.
+    ///
+    /// # Returns
+    ///
+    /// * `EventRequestResponse` - Id of request.
+    ///
+    pub async fn transfer_subject(
+        &self,
+        subject_id: &str,
+        new_public_key: &str,
+    ) -> Result<EventRequestResponse, NodeError> {
+        let public_key = KeyIdentifier::from_str(new_public_key)
+            .map_err(|_| NodeError::InvalidParameter("invalid public_key".to_owned()))?;
+        let subject = self.owned_subject(subject_id).await?;
+        if public_key.to_str() == subject.owner {
+            return Err(NodeError::Conflict(format!(
+                "subject {} is already owned by {}",
+                subject.subject_id, subject.owner
+            )));
+        }
+        self.send_event_request(NodeSignedEventRequest {
+            request: NodeEventRequest::Transfer(NodeTransferRequest {
+                subject_id: subject.subject_id,
+                public_key: public_key.to_str(),
+            }),
+            signature: None,
+            origin: None,
+        })
+        .await
+    }
+
+    /// End subject life.
+    /// Sends the end of life request of a subject owned by this node, signed by the node, after
+    /// checking the subject and its owner.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - Invalid subject id.
+    /// * `NodeError::NotFound` - The subject is not known by the node.
+    /// * `NodeError::Unauthorized` - The subject is owned by another key.
+    /// * `NodeError::Conflict` - The subject already reached its end of life.
+    /// * Any error of `send_event_request`.
+    ///
+    /// # Returns
+    ///
+    /// * `EventRequestResponse` - Id of request.
+    ///
+    pub async fn end_subject_life(
+        &self,
+        subject_id: &str,
+    ) -> Result<EventRequestResponse, NodeError> {
+        let subject = self.owned_subject(subject_id).await?;
+        self.send_event_request(NodeSignedEventRequest {
+            request: NodeEventRequest::EOL(NodeEOLRequest {
+                subject_id: subject.subject_id,
+            }),
+            signature: None,
+            origin: None,
+        })
+        .await
+    }
+
+    /// Update a governance.
+    /// Typed changes of its members, roles, policies and schemas, sent as one Fact event by
+    /// `GovernanceUpdate::submit`, see the `governance` module.
+    ///
+    /// # Arguments
+    ///
+    /// * `governance_id` - Governance identifier.
+    ///
+    /// # Returns
+    ///
+    /// * `GovernanceUpdate` - Update without changes.
+    ///
+    pub fn governance(&self, governance_id: &str) -> GovernanceUpdate {
+        GovernanceUpdate::new(self.clone(), governance_id)
+    }
+
+    /// Get the contract of a schema, as stored in the governance.
+    ///
+    /// # Arguments
+    ///
+    /// * `governance_id` - Governance identifier.
+    /// * `schema_id` - Schema id.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - The subject is not a governance, or the contract is not
+    ///   base64 encoded UTF-8.
+    /// * `NodeError::NotFound` - The schema is not in the governance.
+    /// * Any error of `get_subject`.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeContractSource` - Source, its digest and the governance version it was read from.
+    ///
+    pub async fn get_contract(
+        &self,
+        governance_id: &str,
+        schema_id: &str,
+    ) -> Result<NodeContractSource, NodeError> {
+        let governance = self.get_subject(governance_id).await?;
+        if governance.schema_id != "governance" {
+            return Err(NodeError::InvalidParameter(format!(
+                "{} is not a governance",
+                governance_id
+            )));
+        }
+        let source = contract_source(&governance.properties, schema_id)?;
+        let hash = DigestIdentifier::from_serializable_borsh(&source, self.digest_derivator)
+            .map_err(|_| NodeError::InternalApi(format!("Error hashing contract {}", schema_id)))?;
+        Ok(NodeContractSource {
+            governance_id: governance.subject_id,
+            schema_id: schema_id.to_owned(),
+            source,
+            hash: hash.to_string(),
+            version: governance.sn,
+        })
+    }
+
+    /// Replace the contract of a schema of a governance, with a Fact event signed by the node.
+    /// The change is applied once approved; `governance` with `set_contract` and `auto_approve`
+    /// also votes it with the node key.
+    ///
+    /// # Arguments
+    ///
+    /// * `governance_id` - Governance identifier.
+    /// * `schema_id` - Schema id.
+    /// * `source` - Source of the contract.
+    ///
+    /// # Errors
+    ///
+    /// * Any error of `GovernanceUpdate::submit`.
+    ///
+    /// # Returns
+    ///
+    /// * `GovernanceChange` - Patch and request of the Fact event.
+    ///
+    pub async fn register_contract(
+        &self,
+        governance_id: &str,
+        schema_id: &str,
+        source: &str,
+    ) -> Result<GovernanceChange, NodeError> {
+        self.governance(governance_id)
+            .set_contract(schema_id, source)
+            .submit()
+            .await
+    }
+
+    /// Active subject owned by the node key, for the requests only its owner may sign.
+    async fn owned_subject(&self, subject_id: &str) -> Result<NodeSubjectData, NodeError> {
+        let subject = self.get_subject(subject_id).await?;
+        if !subject.active {
+            return Err(NodeError::Conflict(format!(
+                "subject {} reached its end of life",
+                subject.subject_id
+            )));
+        }
+        let controller_id = self.get_controller_id();
+        if subject.owner != controller_id {
+            return Err(NodeError::Unauthorized(format!(
+                "subject {} is owned by {}, not by the node {}",
+                subject.subject_id, subject.owner, controller_id
+            )));
+        }
+        Ok(subject)
+    }
+
+    /// Store of archived subjects, subject id to archive timestamp.
+    fn archived_store(&self) -> NodeStore {
+        self.store.scope("archived")
+    }
+
+    /// Indexes of the subjects, see `with_subject_index`.
+    fn subject_index(&self) -> Result<&SubjectIndex, NodeError> {
+        self.subject_index.as_ref().ok_or_else(|| {
+            NodeError::InvalidParameter("The subjects of the node are not indexed".to_owned())
+        })
+    }
+
+    /// Store of sent requests, request id to record. Records expire after the time to live of
+    /// the `requests` collection, `REQUEST_RETENTION` by default.
+    fn requests_store(&self) -> NodeStore {
+        self.expiring_store("requests", REQUEST_RETENTION)
+    }
+
+    /// Store of the order of the sent requests, timestamp and request id to request id. Its
+    /// entries expire with the records.
+    fn requests_order(&self) -> NodeStore {
+        self.requests_store().scope("order")
+    }
+
+    /// Store of subject creations, signer and governance to creation timestamps. The
+    /// timestamps of a key expire one quota window after the last creation counted under it;
+    /// `kore.db_ttl.collections` does not apply, so that it cannot drop counted creations.
+    fn quota_store(&self) -> NodeStore {
+        self.store
+            .scope(EXPIRING_SCOPE)
+            .scope("quota")
+            .with_ttl(self.subject_quota().window)
+    }
+
+    /// Store of the validation proofs of a subject, sequence number to proof.
+    fn proofs_store(&self, subject_id: &str) -> NodeStore {
+        self.store.scope(PROOFS_SCOPE).scope(subject_id)
+    }
+
+    /// Store of the node history, timestamp and sequence to entry. Entries expire after the
+    /// time to live of the `history` collection, `HISTORY_RETENTION` by default.
+    fn history_store(&self) -> NodeStore {
+        self.expiring_store("history", HISTORY_RETENTION)
+    }
+
+    /// Store of the API usage, window and caller to usage.
+    fn usage_store(&self) -> NodeStore {
+        self.store.scope("usage")
+    }
+
+    /// Store of the replication cursors, remote node and subject id to next sequence number.
+    #[cfg(feature = "replication")]
+    pub(crate) fn replication_store(&self, remote_url: &str) -> NodeStore {
+        self.store.scope("replication").scope(remote_url)
+    }
+
+    /// Store of the replicated events given up, see `replication_dead_letters`. They expire
+    /// after the time to live of the `dead_letters` collection, `DEAD_LETTER_RETENTION` by
+    /// default.
+    #[cfg(feature = "replication")]
+    pub(crate) fn dead_letter_store(&self) -> NodeStore {
+        self.expiring_store("dead_letters", DEAD_LETTER_RETENTION)
+    }
+
+    /// Move the sent requests, node history and subject creations stored by versions that kept
+    /// them forever to their expiring stores, where they expire from now on. Failures are only
+    /// logged, the entries are moved at the next start.
+    pub(crate) fn expire_legacy_entries(&self) {
+        let order = self.store.scope("requests_order");
+        let moved = move_entries::<NodeRequestRecord>(
+            &self.store.scope("requests"),
+            &self.requests_store(),
+        )
+        .and_then(|moved| {
+            // Rebuilt from the moved records, see `index_requests`.
+            order.clear()?;
+            Ok(moved)
+        })
+        .and_then(|moved| {
+            Ok(moved
+                + move_entries::<NodeHistoryEntry>(
+                    &self.store.scope("history"),
+                    &self.history_store(),
+                )?
+                + move_entries::<Vec<u64>>(&self.store.scope("quota"), &self.quota_store())?)
+        });
+        match moved {
+            Ok(0) => {}
+            Ok(moved) => log::info!("{} entries kept forever moved to expiring stores", moved),
+            Err(error) => log::warn!("Entries kept forever not moved: {}", error),
+        }
+    }
+
+    /// Replicated events given up after failing `kore.replication.max_attempts` times, ordered
+    /// by remote node, subject and sequence number, see the `replication` module.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The store could not be read.
+    ///
+    #[cfg(feature = "replication")]
+    pub fn replication_dead_letters(&self) -> Result<Vec<NodeReplicationFailure>, NodeError> {
+        Ok(self
+            .dead_letter_store()
+            .entries::<NodeReplicationFailure>()?
+            .into_iter()
+            .map(|(_, failure)| failure)
+            .collect())
+    }
+
+    /// Store of an auxiliary collection whose entries expire, such as a cache. The time to live
+    /// is the one of `kore.db_ttl.collections` for the collection, or `default_ttl`; expired
+    /// entries are not read, and `sweep_expired` deletes them.
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - Name of the collection.
+    /// * `default_ttl` - Time to live when the settings do not set one.
+    ///
+    pub fn expiring_store(&self, collection: &str, default_ttl: Duration) -> NodeStore {
+        self.store
+            .scope(EXPIRING_SCOPE)
+            .scope(collection)
+            .with_ttl(self.db_ttl.ttl(collection, default_ttl))
+    }
+
+    /// Creation timestamps under `key` that are still inside the quota window.
+    fn subject_quota_usage(
+        &self,
+        key: &str,
+        quota: &SubjectQuota,
+        now: u64,
+    ) -> Result<Vec<u64>, NodeError> {
+        let window = quota.window.as_millis() as u64;
+        let mut usage = self.quota_store().get::<Vec<u64>>(key)?.unwrap_or_default();
+        usage.retain(|timestamp| now.saturating_sub(*timestamp) < window);
+        Ok(usage)
+    }
+
+    /// Give back a subject creation counted at `timestamp` that Kore Base refused.
+    fn release_subject_quota(&self, key: &str, timestamp: u64) -> Result<(), NodeError> {
+        let _quota = self
+            .quota_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let store = self.quota_store();
+        let mut usage = store.get::<Vec<u64>>(key)?.unwrap_or_default();
+        if let Some(position) = usage.iter().position(|counted| *counted == timestamp) {
+            usage.remove(position);
+        }
+        store.put(key, &usage)
+    }
+
+    /// Get validation proof.
+    /// Allows to obtain the validation test of the last event for a specified subject.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The subject is not known by the node.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeProof` - Validation proof.
+    ///
+    pub async fn get_validation_proof(&self, subject_id: &str) -> Result<NodeProof, NodeError> {
+        let subject_id = DigestIdentifier::from_str(subject_id)
+            .map_err(|_| NodeError::InvalidParameter("invalid subject_id".to_owned()))?;
+        match self
+            .call("get_validation_proof", || {
+                self.api.get_validation_proof(subject_id.clone())
+            })
+            .await?
+        {
+            Ok(value) => Ok(NodeProof::from(value)),
+            Err(error) => Err(base_error("get_validation_proof", error)),
+        }
+    }
+
+    /// Get the validation proof of an event of a subject, with the signatures of its validators.
+    /// Kore Base only keeps the proof of the last event, so the node records each proof as Kore
+    /// Base writes it, see `LedgerProofs`; the proofs of events validated before the node
+    /// recorded them are not available.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    /// * `sn` - Sequence number of the event.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The subject is not known, or the proof was not recorded.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::Database` - The recorded proofs could not be read.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeProof` - Validation proof of the event.
+    ///
+    pub async fn get_validation_proof_at(
+        &self,
+        subject_id: &str,
+        sn: u64,
+    ) -> Result<NodeProof, NodeError> {
+        if let Some(proof) = self
+            .proofs_store(subject_id)
+            .get::<NodeProof>(&proof_key(sn))?
+        {
+            return Ok(proof);
+        }
+        // The proof of the last event may be read before it is recorded.
+        let latest = self.get_validation_proof(subject_id).await?;
+        if latest.proof.sn == sn {
+            return Ok(latest);
+        }
+        Err(NodeError::NotFound(format!(
+            "validation proof of event {} of {}, not recorded by the node",
+            sn, subject_id
+        )))
+    }
+
+    /// Get the validation proofs recorded for the events of a subject, see
+    /// `get_validation_proof_at`. Only the proofs of the page are read from the node store.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    /// * `parameters` - First sequence number and number of proofs to read.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The recorded proofs could not be read.
+    ///
+    /// # Returns
+    ///
+    /// * `Page<NodeProof>` - Page of proofs by sequence number, with gaps for the events whose
+    ///   proof was not recorded. The cursor is the sequence number of the next event.
+    ///
+    pub async fn get_validation_proofs(
+        &self,
+        subject_id: &str,
+        parameters: PaginatorFromNumber,
+    ) -> Result<Page<NodeProof>, NodeError> {
+        let from = parameters.from.unwrap_or_default().max(0) as u64;
+        let first = from == 0;
+        let quantity = Page::<NodeProof>::read_quantity(parameters.quantity);
+        let store = self.proofs_store(subject_id);
+        let from = proof_key(from);
+        let proofs = store
+            .iter_from::<NodeProof>(&from)
+            .map(|entry| entry.map(|(_, proof)| proof))
+            .take(quantity.map_or(usize::MAX, |quantity| quantity as usize))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Page::cut(proofs, first, parameters.quantity, |proof| {
+            (proof.proof.sn + 1).to_string()
+        }))
+    }
+
+    /// Get events of subject.
+    /// Get events of traceability subject.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    /// * `parameters` - Parameters for retrieving events of subject
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    ///
+    /// # Returns
+    ///
+    /// * `Page<NodeSigned<EventContentResponse>>` - Page of events of a traceability subject,
+    ///   the cursor is the sequence number of the next event.
+    ///
+    pub async fn get_events_of_subject(
+        &self,
+        subject_id: &str,
+        parameters: PaginatorFromNumber,
+    ) -> Result<Page<NodeSigned<EventContentResponse>>, NodeError> {
+        let subject_id = DigestIdentifier::from_str(subject_id)
+            .map_err(|_| NodeError::InvalidParameter("invalid subject_id".to_owned()))?;
+        let first = matches!(parameters.from, None | Some(0));
+        let value = self
+            .call("get_events_of_subject", || {
+                self.api.get_events(
+                    subject_id.clone(),
+                    parameters.from,
+                    Page::<NodeSigned<EventContentResponse>>::read_quantity(parameters.quantity),
+                )
+            })
+            .await?
+            .map(|vec| {
+                vec.into_iter()
+                    .map(NodeSigned::<EventContentResponse>::from)
+                    .collect::<Vec<NodeSigned<EventContentResponse>>>()
+            });
+        match value {
+            Ok(v) => Ok(Page::cut(v, first, parameters.quantity, |event| {
+                (event.content.sn + 1).to_string()
+            })),
+            Err(error) => Err(base_error("get_events_of_subject", error)),
+        }
+    }
+
+    /// Get event of subject.
+    /// Get a specific event of traceability subject. Events moved to the archive, see
+    /// `archive_events`, are read from there by the collections of Kore Base.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    /// * `sn` - Versión of subject.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The event does not exist.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeSigned<EventContentResponse>` - Event of a traceability subject
+    ///
+    pub async fn get_event_of_subject(
+        &self,
+        subject_id: &str,
+        sn: u64,
+    ) -> Result<NodeSigned<EventContentResponse>, NodeError> {
+        let subject_id = DigestIdentifier::from_str(subject_id)
+            .map_err(|_| NodeError::InvalidParameter("invalid subject_id".to_owned()))?;
+        let value = self
+            .call("get_event_of_subject", || {
+                self.api.get_event(subject_id.clone(), sn)
+            })
+            .await?
+            .map(NodeSigned::<EventContentResponse>::from);
+        match value {
+            Ok(v) => Ok(v),
+            Err(error) => Err(base_error("get_event_of_subject", error)),
+        }
+    }
+
+    /// Signed event of a subject as stored by Kore Base, for the checks that need its
+    /// signatures in their original encoding.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The subject or the event does not exist.
+    /// * `NodeError::InvalidParameter` - Invalid subject id.
+    ///
+    pub(crate) async fn base_event(
+        &self,
+        subject_id: &str,
+        sn: u64,
+    ) -> Result<BaseSigned<BaseEvent>, NodeError> {
+        let subject_id = DigestIdentifier::from_str(subject_id)
+            .map_err(|_| NodeError::InvalidParameter("invalid subject_id".to_owned()))?;
+        self.call("get_event", || self.api.get_event(subject_id.clone(), sn))
+            .await?
+            .map_err(|error| base_error("get_event", error))
+    }
+
+    /// Verify an event of a subject on the ledger of the node, without the validators: its
+    /// sequence, its link to the previous event, and the signatures of the subject, the issuer
+    /// and the approvers, see the `verification` module.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    /// * `sn` - Sequence number of the event.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The subject or the event does not exist.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeEventVerification` - Checks of the event. A failed check is reported, not returned
+    ///   as an error.
+    ///
+    pub async fn verify_event(
+        &self,
+        subject_id: &str,
+        sn: u64,
+    ) -> Result<NodeEventVerification, NodeError> {
+        let (id, governance_id) = self.verified_subject(subject_id).await?;
+        let event = self.base_event(subject_id, sn).await?;
+        let previous = match sn.checked_sub(1) {
+            Some(previous) => Some(self.base_event(subject_id, previous).await?),
+            None => None,
+        };
+        Ok(verify_event(&event, previous.as_ref(), &id, &governance_id))
+    }
+
+    /// Verify every event of a subject on the ledger of the node, from its creation, see
+    /// `verify_event`.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The subject does not exist.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeChainVerification` - Outcome of the checks, and the first event with a failed
+    ///   check. The checks of each event are not kept, see `verify_subject_chain_reports`.
+    ///
+    pub async fn verify_subject_chain(
+        &self,
+        subject_id: &str,
+    ) -> Result<NodeChainVerification, NodeError> {
+        let (id, _) = self.verified_subject(subject_id).await?;
+        let mut reports = self.verify_subject_chain_reports(subject_id).await?;
+        let mut chain = NodeChainVerification::new(&id.to_str());
+        while let Some(report) = reports.try_next().await? {
+            chain.add(&report);
         }
+        Ok(chain)
     }
 
-    /// Get an event request.
-    /// The request is retrieved from the Kore API.
+    /// Verify every event of a subject on the ledger of the node, from its creation, as
+    /// `verify_subject_chain` does, and stream the checks of each event. Events are read page
+    /// by page, and only the last one read is kept.
     ///
     /// # Arguments
     ///
-    /// * `request_id` - Event request identifier.
+    /// * `subject_id` - Subject id.
     ///
     /// # Errors
     ///
-    /// * `NodeError::InternalApi` - Internal API error.
-    /// * `NodeError::InvalidParameter` - Invalid request identifier.
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The subject does not exist.
+    /// * `NodeError::InvalidParameter` - Invalid request parameter.
     ///
     /// # Returns
     ///
-    /// * `NodeSignedEventRequest` - Signed event request.
+    /// * `BoxStream<Result<NodeEventVerification, NodeError>>` - Checks of each event, by
+    ///   sequence number; the stream ends at the first error reading the ledger.
     ///
-    pub async fn get_event_request(
+    pub async fn verify_subject_chain_reports(
         &self,
-        request_id: &str,
-    ) -> Result<NodeSignedEventRequest, NodeError> {
-        let result = self
-            .api
-            .get_request(
-                DigestIdentifier::from_str(request_id)
-                    .map_err(|_| NodeError::InvalidParameter("request identifier".to_owned()))?,
-            )
-            .await
-            .map_err(|_| NodeError::InternalApi("Failed to get request".to_owned()))?;
-        Ok(NodeSignedEventRequest::from(result))
+        subject_id: &str,
+    ) -> Result<BoxStream<'static, Result<NodeEventVerification, NodeError>>, NodeError> {
+        let (id, governance_id) = self.verified_subject(subject_id).await?;
+        let pages = stream::try_unfold(
+            (self.clone(), Some(0), None),
+            move |(api, from, mut previous)| {
+                let (id, governance_id) = (id.clone(), governance_id.clone());
+                async move {
+                    let Some(from) = from else {
+                        return Ok(None);
+                    };
+                    let events = api
+                        .call("verify_subject_chain", || {
+                            api.api.get_events(id.clone(), Some(from), Some(PAGE_SIZE))
+                        })
+                        .await?
+                        .map_err(|error| base_error("verify_subject_chain", error))?;
+                    let next = (events.len() as i64 == PAGE_SIZE).then_some(from + PAGE_SIZE);
+                    let mut reports = Vec::with_capacity(events.len());
+                    for event in events {
+                        reports.push(verify_event(&event, previous.as_ref(), &id, &governance_id));
+                        previous = Some(event);
+                    }
+                    Ok::<_, NodeError>(Some((reports, (api, next, previous))))
+                }
+            },
+        );
+        Ok(pages
+            .map_ok(|reports| stream::iter(reports.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed())
     }
 
-    /// Get an state of event request.
-    /// The state of request is retrieved from the Kore API.
+    /// Identifier and governance of a subject to verify.
+    async fn verified_subject(
+        &self,
+        subject_id: &str,
+    ) -> Result<(DigestIdentifier, DigestIdentifier), NodeError> {
+        let subject = self.get_subject(subject_id).await?;
+        let id = DigestIdentifier::from_str(&subject.subject_id)
+            .map_err(|_| NodeError::InvalidParameter("invalid subject_id".to_owned()))?;
+        // The approvals of a governance are requested under its own identifier.
+        let governance_id = match subject.governance_id.as_str() {
+            "" => id.clone(),
+            governance_id => DigestIdentifier::from_str(governance_id)
+                .map_err(|_| NodeError::InternalApi("invalid governance_id".to_owned()))?,
+        };
+        Ok((id, governance_id))
+    }
+
+    /// Get all the subjects of a governance, archived ones included, reading them page by page.
     ///
     /// # Arguments
     ///
-    /// * `request_id` - Event request identifier.
+    /// * `governance_id` - Governance identifier.
     ///
     /// # Errors
     ///
-    /// * `NodeError::InternalApi` - Internal API error
-    /// * `NodeError::InvalidParameter` - Invalid request identifier.
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::InvalidParameter` - Invalid governance identifier.
+    ///
+    pub(crate) async fn get_all_subjects_of_governance(
+        &self,
+        governance_id: &str,
+    ) -> Result<Vec<NodeSubjectData>, NodeError> {
+        let (subjects, _) = self
+            .subjects_of_governance(governance_id, usize::MAX)
+            .await?;
+        Ok(subjects)
+    }
+
+    /// Subjects of a governance, archived ones included, read page by page until `limit`.
     ///
     /// # Returns
     ///
-    /// * `NodeKoreRequestState` - State of event request.
+    /// * `(Vec<NodeSubjectData>, bool)` - Subjects, and whether they are all of them.
     ///
-    pub async fn get_event_request_state(
+    async fn subjects_of_governance(
         &self,
-        request_id: &str,
-    ) -> Result<NodeKoreRequestState, NodeError> {
-        let result = self
-            .api
-            .get_request(
-                DigestIdentifier::from_str(request_id)
-                    .map_err(|_| NodeError::InvalidParameter("request identifier".to_owned()))?,
-            )
-            .await
-            .map_err(|_| NodeError::InternalApi("Failed to get request".to_owned()))?;
-        Ok(NodeKoreRequestState::from(result))
+        governance_id: &str,
+        limit: usize,
+    ) -> Result<(Vec<NodeSubjectData>, bool), NodeError> {
+        let mut subjects: Vec<NodeSubjectData> = vec![];
+        let mut from = None;
+        loop {
+            let quantity = (limit - subjects.len()).min(PAGE_SIZE as usize) as i64;
+            let page = self
+                .get_subjects(NodeSubjects {
+                    from,
+                    quantity: Some(quantity),
+                    subject_type: None,
+                    governanceid: Some(governance_id.to_owned()),
+                    archive_filter: Some("all".to_owned()),
+                })
+                .await?;
+            subjects.extend(page.items);
+            match page.next_cursor {
+                None => return Ok((subjects, true)),
+                Some(_) if subjects.len() >= limit => return Ok((subjects, false)),
+                Some(cursor) => from = Some(cursor),
+            }
+        }
     }
 
-    /// Get approval events.
-    /// Get the status of the approval events you want to obtain, among the 4 available:
-    /// - Pending: events pending voting.
-    /// - Obsolete: events to which the node has not taken any vote, but have become obsolete and can no longer take a vote.
-    /// - RespondedAccepted: Events to which the node has voted in favor.
-    /// - RespondedRejected: Events which the node has voted against.
-    /// The get that obtains the events is performed.
+    /// Export events.
+    /// Writes every signed event of a subject to `writer`, followed by the validation proof of
+    /// its last event, as `EventRecord`s. When the subject is a governance, the events of all
+    /// its subjects follow, archived ones included. Records are written as they are read.
     ///
     /// # Arguments
     ///
-    /// * `params` - Parameters for retrieving approval events.
+    /// * `subject_id` - Subject, or governance, to export.
+    /// * `writer` - Output of the records, e.g. a file or a socket; flushed at the end.
+    /// * `format` - JSON Lines, or CBOR with the `cbor` feature.
     ///
     /// # Errors
     ///
-    /// * `NodeError::InternalApi` - Internal API error.
-    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::InvalidParameter` - Invalid subject identifier.
+    /// * `NodeError::NotFound` - The subject is not known by the node.
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::Export` - A record could not be encoded or written.
     ///
     /// # Returns
     ///
-    /// * `Vec<NodeApprovalEntity>` - Vector of approval event.
+    /// * `usize` - Number of events written.
     ///
-    pub async fn get_approvals(
+    #[cfg(feature = "export")]
+    pub async fn export_events<W>(
         &self,
-        params: NodeGetApprovals,
-    ) -> Result<Vec<NodeApprovalEntity>, NodeError> {
-        let status = match params.status {
-            None => None,
-            Some(value) => match value.to_lowercase().as_str() {
-                "pending" => Some(ApprovalState::Pending),
-                "obsolete" => Some(ApprovalState::Obsolete),
-                "responded_accepted" => Some(ApprovalState::RespondedAccepted),
-                "responded_rejected" => Some(ApprovalState::RespondedRejected),
-                other => {
-                    return Err(NodeError::InvalidParameter(format!(
-                        "Invalid ApprovalState: {}",
-                        other
-                    )));
-                }
-            },
-        };
-
-        match self
-            .api
-            .get_approvals(status, params.from, params.quantity)
-            .await
-            .map(|result| {
-                result
-                    .into_iter()
-                    .map(NodeApprovalEntity::from)
-                    .collect::<Vec<NodeApprovalEntity>>()
-            }) {
-            Ok(res) => Ok(res),
-            Err(_) => Err(NodeError::InternalApi(
-                "Failed to process request".to_owned(),
-            )),
-        }
+        subject_id: &str,
+        writer: &mut W,
+        format: EventExportFormat,
+    ) -> Result<usize, NodeError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        write_events(self, subject_id, writer, format).await
     }
 
-    /// Get approval event.
-    /// Gets an approval event from ID.
+    /// Verify events.
+    /// Checks an archive written by `export_events`, subject by subject: the sequence numbers,
+    /// the hash chaining of the events and their validation proof, and the signatures of the
+    /// events and of their requests. Nothing is written to the node, so an archive cannot seed
+    /// a fresh node: Kore Base only takes event requests, which it turns into new events signed
+    /// and validated again, and has no way to store events it did not process. A node is
+    /// restored from a backup of its database, see `backup::restore_backup`, and takes the
+    /// events after the backup from the network.
     ///
     /// # Arguments
     ///
-    /// * `id` - ID of approval event.
+    /// * `reader` - Archive, e.g. a file.
+    /// * `format` - Encoding of the archive.
     ///
     /// # Errors
     ///
-    /// * `NodeError::InternalApi` - Internal API error.
-    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::Export` - The archive could not be read.
+    /// * `NodeError::InvalidParameter` - A record could not be decoded, or the events of a
+    ///   subject do not follow each other.
+    /// * `NodeError::InvalidSignature` - A signature is not valid.
     ///
     /// # Returns
     ///
-    /// * `NodeApprovalEntity` - Approval event.
+    /// * `VerifyReport` - Subjects and events checked.
     ///
-    pub async fn get_approval_id(&self, id: &str) -> Result<NodeApprovalEntity, NodeError> {
-        let result = self
-            .api
-            .get_approval(DigestIdentifier::from_str(id).map_err(|_| {
-                NodeError::InvalidParameter("approval request identifier".to_owned())
-            })?)
-            .await
-            .map_err(|_| NodeError::InternalApi("Failed to get request".to_owned()))?;
-        Ok(NodeApprovalEntity::from(result))
+    #[cfg(feature = "export")]
+    pub async fn verify_events<R>(
+        &self,
+        reader: &mut R,
+        format: EventExportFormat,
+    ) -> Result<VerifyReport, NodeError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        verify_events(reader, format, self.digest_derivator).await
     }
 
-    /// Voting of an approval event.
-    /// Accepts or denies an approval event.
+    /// Get the graph of a subject.
+    /// Follows the links between a subject, its governance and the other subjects of the
+    /// governance up to `depth` links away, and adds the creator, the owner and the transfers of
+    /// every subject reached. Archived subjects are included.
     ///
     /// # Arguments
     ///
-    /// * `id` - ID of approval event.
-    /// * `response` - Event approval or denial.
+    /// * `root` - Subject id the graph starts from.
+    /// * `depth` - Governance links followed from the root, up to `MAX_GRAPH_DEPTH`.
     ///
     /// # Errors
     ///
-    /// * `NodeError::InternalApi` - Internal API error.
-    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::NotFound` - The root subject is not known by the node.
+    /// * `NodeError::InvalidParameter` - Invalid subject id or depth.
     ///
     /// # Returns
     ///
-    /// * `NodeApprovalEntity` - Approval event updated with user voting.
+    /// * `NodeSubjectGraph` - Subjects and identities with their relationships. It is truncated
+    ///   after `MAX_GRAPH_SUBJECTS` subjects.
     ///
-    pub async fn approval_request(
+    pub async fn subject_graph(
         &self,
-        id: &str,
-        response: PatchVote,
-    ) -> Result<NodeApprovalEntity, NodeError> {
-        let acceptance = match response {
-            PatchVote::RespondedAccepted => true,
-            PatchVote::RespondedRejected => false,
+        root: &str,
+        depth: u32,
+    ) -> Result<NodeSubjectGraph, NodeError> {
+        if depth > MAX_GRAPH_DEPTH {
+            return Err(NodeError::InvalidParameter(format!(
+                "depth {} is over {}",
+                depth, MAX_GRAPH_DEPTH
+            )));
+        }
+        let root = self.get_subject(root).await?;
+        let mut builder = GraphBuilder::new(&root.subject_id, depth);
+        let mut subjects = vec![];
+        let mut pending = VecDeque::from([(root, 0)]);
+        while let Some((subject, level)) = pending.pop_front() {
+            let governance = subject.governance_id.is_empty();
+            builder.vertex(NodeGraphVertex {
+                id: subject.subject_id.clone(),
+                kind: if governance {
+                    NodeGraphVertexKind::Governance
+                } else {
+                    NodeGraphVertexKind::Subject
+                },
+                name: Some(subject.name.clone()),
+                schema_id: Some(subject.schema_id.clone()),
+            });
+            if level < depth {
+                let related = if governance {
+                    // More subjects than the graph holds are never read.
+                    let (related, complete) = self
+                        .subjects_of_governance(&subject.subject_id, MAX_GRAPH_SUBJECTS + 1)
+                        .await?;
+                    if !complete {
+                        builder.truncate();
+                    }
+                    related
+                } else {
+                    vec![self.get_subject(&subject.governance_id).await?]
+                };
+                for other in related {
+                    let queued = pending
+                        .iter()
+                        .any(|(queued, _)| queued.subject_id == other.subject_id);
+                    if builder.contains(&other.subject_id) || queued {
+                        continue;
+                    }
+                    if builder.len() + pending.len() >= MAX_GRAPH_SUBJECTS {
+                        builder.truncate();
+                        break;
+                    }
+                    pending.push_back((other, level + 1));
+                }
+            }
+            subjects.push(subject);
+        }
+
+        for subject in subjects.iter() {
+            if builder.contains(&subject.governance_id) {
+                builder.edge(
+                    &subject.governance_id,
+                    &subject.subject_id,
+                    NodeGraphRelation::Governs,
+                    None,
+                );
+            }
+        }
+        for subject in subjects.iter() {
+            let id = &subject.subject_id;
+            builder.identity(id, &subject.creator, NodeGraphRelation::CreatedBy, None);
+            builder.identity(id, &subject.owner, NodeGraphRelation::OwnedBy, None);
+            for (sn, owner) in self.subject_transfers(id).await? {
+                builder.identity(id, &owner, NodeGraphRelation::TransferredTo, Some(sn));
+            }
+        }
+        Ok(builder.build())
+    }
+
+    /// Sequence number and new owner of the transfer events of a subject, read page by page.
+    async fn subject_transfers(&self, subject_id: &str) -> Result<Vec<(u64, String)>, NodeError> {
+        let mut transfers = vec![];
+        let mut from = 0;
+        loop {
+            let page = self
+                .get_events_of_subject(
+                    subject_id,
+                    PaginatorFromNumber {
+                        from: Some(from),
+                        quantity: Some(PAGE_SIZE),
+                    },
+                )
+                .await?;
+            transfers.extend(transfer_owners(&page.items));
+            match page.items.last() {
+                Some(last) if page.next_cursor.is_some() => from = last.content.sn as i64 + 1,
+                _ => return Ok(transfers),
+            }
+        }
+    }
+
+    /// Read the preauthorizations page by page from `from` on, keeping up to `limit` matches.
+    async fn scan_allowed_subjects(
+        &self,
+        mut from: Option<String>,
+        filter: &NodeAllowedSubjectsFilter,
+        limit: usize,
+    ) -> Result<Vec<PreauthorizedSubjectsResponse>, NodeError> {
+        if let Some(provider) = &filter.provider {
+            KeyIdentifier::from_str(provider)
+                .map_err(|_| NodeError::InvalidParameter(format!("provider {}", provider)))?;
+        }
+        if let Some(governance_id) = &filter.governance_id {
+            DigestIdentifier::from_str(governance_id).map_err(|_| {
+                NodeError::InvalidParameter(format!("governance_id {}", governance_id))
+            })?;
+        }
+        let mut matches = vec![];
+        while matches.len() < limit {
+            let page = self
+                .call("get_all_allowed_subjects_and_providers", || {
+                    self.api
+                        .get_all_allowed_subjects_and_providers(from.clone(), Some(PAGE_SIZE))
+                })
+                .await?
+                .map_err(|error| base_error("get_all_allowed_subjects_and_providers", error))?;
+            let last_page = (page.len() as i64) < PAGE_SIZE;
+            from = page.last().map(|(subject_id, _)| subject_id.to_str());
+            for entry in page.into_iter().map(PreauthorizedSubjectsResponse::from) {
+                if matches.len() < limit && self.allowed_subject_matches(&entry, filter).await? {
+                    matches.push(entry);
+                }
+            }
+            if last_page {
+                break;
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Whether a preauthorized subject matches the filter.
+    async fn allowed_subject_matches(
+        &self,
+        entry: &PreauthorizedSubjectsResponse,
+        filter: &NodeAllowedSubjectsFilter,
+    ) -> Result<bool, NodeError> {
+        if let Some(provider) = &filter.provider {
+            if !entry.providers.contains(provider) {
+                return Ok(false);
+            }
+        }
+        if !filter.needs_subject() {
+            return Ok(true);
+        }
+        let subject = match self.get_subject(&entry.subject_id).await {
+            Ok(subject) => subject,
+            // The copy of the ledger may not have arrived yet.
+            Err(NodeError::NotFound(_)) => return Ok(false),
+            Err(error) => return Err(error),
         };
+        if let Some(governance_id) = &filter.governance_id {
+            if subject.governance_id != *governance_id && subject.subject_id != *governance_id {
+                return Ok(false);
+            }
+        }
+        if let Some(namespace) = &filter.namespace {
+            if subject.namespace != *namespace {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
 
-        match self
-            .api
-            .approval_request(
-                DigestIdentifier::from_str(id)
-                    .map_err(|_| NodeError::InvalidParameter("request identifier".to_owned()))?,
-                acceptance,
-            )
-            .await
-            .map(NodeApprovalEntity::from)
-        {
-            Ok(result) => Ok(result),
-            Err(_) => Err(NodeError::InternalApi(
-                "Failed to process request".to_owned(),
-            )),
+    /// Get every approval waiting for a vote, reading them page by page.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    ///
+    pub(crate) async fn get_all_pending_approvals(
+        &self,
+    ) -> Result<Vec<NodeApprovalEntity>, NodeError> {
+        let mut approvals: Vec<NodeApprovalEntity> = vec![];
+        let mut from = None;
+        loop {
+            let page = self
+                .get_approvals(NodeGetApprovals {
+                    status: Some("pending".to_owned()),
+                    from,
+                    quantity: Some(PAGE_SIZE),
+                })
+                .await?;
+            approvals.extend(page.items);
+            match page.next_cursor {
+                Some(cursor) => from = Some(cursor),
+                None => return Ok(approvals),
+            }
         }
     }
 
-    /// Get all allowed subjects and providers.
-    /// Obtain all subjects and suppliers that have been previously permitted.
+    /// Get the events of a subject from the sequence number `from` on, reading them page by page.
     ///
     /// # Arguments
     ///
-    /// * `parameters` - Parameters for retrieving allowed subjects and providers.
+    /// * `subject_id` - Subject identifier.
+    /// * `from` - Sequence number of the first event.
     ///
     /// # Errors
     ///
-    /// * `NodeError::InternalApi` - Internal API error.
-    ///
-    /// # Returns
-    ///
-    /// * `Vec<PreauthorizedSubjectsResponse>` - Vector of allowed subjects and providers.
+    /// * `NodeError::Internal` - Kore Base failed, the error keeps its cause.
+    /// * `NodeError::InvalidParameter` - Invalid subject identifier.
     ///
-    pub async fn get_all_allowed_subjects_and_providers(
+    pub(crate) async fn get_all_events_of_subject(
         &self,
-        parameters: PaginatorFromString,
-    ) -> Result<Vec<PreauthorizedSubjectsResponse>, NodeError> {
-        match self
-            .api
-            .get_all_allowed_subjects_and_providers(parameters.from, parameters.quantity)
-            .await
-            .map(|x| Vec::from_iter(x.into_iter().map(PreauthorizedSubjectsResponse::from)))
-        {
-            Ok(result) => Ok(result),
-            Err(_) => Err(NodeError::InternalApi(
-                "Failed to process request".to_owned(),
-            )),
+        subject_id: &str,
+        from: u64,
+    ) -> Result<Vec<NodeSigned<EventContentResponse>>, NodeError> {
+        let mut events: Vec<NodeSigned<EventContentResponse>> = vec![];
+        let mut next = from;
+        loop {
+            let page = self
+                .get_events_of_subject(
+                    subject_id,
+                    PaginatorFromNumber {
+                        from: Some(next as i64),
+                        quantity: Some(PAGE_SIZE),
+                    },
+                )
+                .await?;
+            events.extend(page.items);
+            match page.next_cursor {
+                Some(_) => next = events.last().map_or(next, |event| event.content.sn + 1),
+                None => return Ok(events),
+            }
         }
     }
 
-    /// Preauthorize subject.
-    /// Preauthorize subject to receive a copy of the ledger.
+    /// Subscribe to the events committed to a subject, or to the subjects of a governance, from
+    /// now on. Subscriptions to the same target share the reads of the node, so clients do not
+    /// need to poll `get_events_of_subject`.
     ///
     /// # Arguments
     ///
-    /// * `subject_id` - ID of subject.
-    /// * `data` - Vec of providers.
+    /// * `target` - Subject or governance.
     ///
     /// # Errors
     ///
-    /// * `NodeError::InternalApi` - Internal API error.
-    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::InvalidParameter` - Invalid identifier.
+    /// * `NodeError::NotFound` - Unknown subject or governance.
+    /// * `NodeError::Internal` - The subject or governance could not be read.
     ///
     /// # Returns
     ///
-    /// * `String` - 'Ok' if everything went well.
+    /// * `EventSubscription` - Subscription, which ends when dropped.
     ///
-    pub async fn add_preauthorize_subject(
+    pub async fn subscribe(
         &self,
-        subject_id: &str,
-        data: AuthorizeSubject,
-    ) -> Result<String, NodeError> {
-        let mut providers = HashSet::new();
-        for provider in data.providers.iter() {
-            let provider = match KeyIdentifier::from_str(provider) {
-                Ok(provider) => provider,
-                Err(_error) => {
-                    return Err(NodeError::InvalidParameter(format!(
-                        "Invalid key identifier {}",
-                        provider
-                    )))
-                }
-            };
-            providers.insert(provider);
-        }
-
-        match self
-            .api
-            .add_preauthorize_subject(
-                &DigestIdentifier::from_str(subject_id).map_err(|_| {
-                    NodeError::InvalidParameter(format!("Invalid digest identifier {}", subject_id))
-                })?,
-                &providers,
-            )
-            .await
-        {
-            Ok(_) => Ok("Ok".to_owned()),
-            Err(_) => Err(NodeError::InternalApi(
-                "Failed to process request".to_owned(),
-            )),
-        }
+        target: SubscriptionTarget,
+    ) -> Result<EventSubscription, NodeError> {
+        self.subscriptions.subscribe(self.follower(), target).await
     }
 
-    /// Generate keys in node and return public key.
-    /// Generates a key pair on the node and returns the public key.
+    /// Stream of the approvals that become pending from now on, so that clients react to them
+    /// instead of polling `get_approvals`. The approvals are read by a single task shared by every
+    /// stream, which stops once the last one is dropped; a stream that falls behind skips the
+    /// older approvals with `RecvError::Lagged`.
     ///
-    /// # Arguments
+    /// # Returns
     ///
-    /// * `parameters` - Algorithm for generating cryptographic material.
+    /// * `broadcast::Receiver<NodeApprovalEntity>` - Pending approvals, as they appear.
     ///
-    /// # Errors
+    pub fn approvals_stream(&self) -> broadcast::Receiver<NodeApprovalEntity> {
+        self.pending_approvals.subscribe(self.follower())
+    }
+
+    /// Handle for the reads of a background follower, which outlive the caller: they get a plain
+    /// context, and are only logged when slow or failed.
+    fn follower(&self) -> KoreApi {
+        let mut follower = self.clone();
+        follower.context = CallContext::default();
+        follower.access_log = AccessLogger::new(AccessLogSettings {
+            sample_rate: 0.0,
+            ..AccessLogSettings::default()
+        });
+        follower
+    }
+
+    /// Get Controller ID.
+    ///
+    /// # Returns
+    ///
+    /// * `String - Controller ID
     ///
-    /// * `NodeError::InternalApi` - Internal API error.
+    pub fn get_controller_id(&self) -> String {
+        self.api.controller_id().clone()
+    }
+
+    /// Get Peer ID.
     ///
     /// # Returns
     ///
-    /// * `String` - 'Ok' if everything went well.
+    /// * `String - Peer ID
     ///
-    pub async fn register_keys(&self, parameters: NodeKeys) -> Result<String, NodeError> {
-        let derivator = KeyDerivator::from(parameters.algorithm.unwrap_or(KeyAlgorithms::Ed25519));
+    pub fn get_peer_id(&self) -> String {
+        self.api.peer_id().to_string()
+    }
 
-        match self.api.add_keys(derivator).await {
-            Ok(pub_key) => Ok(pub_key.to_str()),
-            Err(_) => Err(NodeError::InternalApi(
-                "Failed to process request".to_owned(),
-            )),
+    /// Get node info.
+    /// Both identities of the node: the controller identifier, used to sign, and the peer
+    /// identifier, used in the network. They are derived from the same node key pair, so a key
+    /// rotation changes both. The address of the prometheus server is included, with the port
+    /// actually bound when the settings ask for any free port, along with the algorithms of the
+    /// keys and digests, the network addresses, and the versions the node was built with.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeInfo` - Identities, addresses and build of the node.
+    ///
+    pub fn node_info(&self) -> NodeInfo {
+        NodeInfo {
+            controller_id: self.get_controller_id(),
+            peer_id: self.get_peer_id(),
+            prometheus: self
+                .metrics_address
+                .read()
+                .ok()
+                .and_then(|address| address.clone()),
+            key_derivator: format!("{:?}", self.key_derivator),
+            digest_derivator: format!("{:?}", self.digest_derivator),
+            listen_addresses: self.listen_addresses.to_vec(),
+            external_addresses: self.external_addresses.to_vec(),
+            build: build_info(),
         }
     }
 
-    /// Get subjects.
-    /// Depending on the parameters you can obtain:
-    /// - All the governances known to a node.
-    /// - All the traceability subjects of a governance.
-    /// - All traceability subjects of the node, including the governance and the subjects of the governance.
+    /// Write a backup of the database, see the `backup` module.
+    /// The database is copied from a consistent snapshot while the node keeps running. The
+    /// height of the manifest counts the events of every subject known by the node when the
+    /// backup starts.
     ///
     /// # Arguments
     ///
-    /// * `params` - Parameters for retrieving subjects.
+    /// * `path` - Archive to write (`tar.zst`), replaced if it exists.
     ///
     /// # Errors
     ///
-    /// * `NodeError::InternalApi` - Internal API error.
-    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::InvalidParameter` - The database of the node is not backed up (PostgreSQL).
+    /// * `NodeError::Internal` - Kore Base failed while reading the subjects.
+    /// * `NodeError::Database` - The database could not be copied.
+    /// * `NodeError::InternalApi` - The archive could not be written.
     ///
     /// # Returns
     ///
-    /// * `Vec<NodeSubjectData>` - Vector of subjects.
+    /// * `NodeBackupManifest` - Manifest of the backup.
     ///
-    pub async fn get_subjects(
-        &self,
-        parameters: NodeSubjects,
-    ) -> Result<Vec<NodeSubjectData>, NodeError> {
-        enum SubjectType {
-            All,
-            Governances,
-        }
-        let subject_type = match &parameters.subject_type {
-            Some(data) => match data.to_lowercase().as_str() {
-                "all" => SubjectType::All,
-                "governances" => {
-                    if parameters.governanceid.is_some() {
-                        return Err(NodeError::InvalidParameter(
-                            "governanceid can not be specified with subject_type=governances"
-                                .to_string(),
-                        ));
-                    }
-                    SubjectType::Governances
-                }
-                other => {
-                    return Err(NodeError::InvalidParameter(format!(
-                        "unknow parameter {}",
-                        other
-                    )));
-                }
-            },
-            None => SubjectType::All,
+    pub async fn create_backup(&self, path: &Path) -> Result<NodeBackupManifest, NodeError> {
+        let source = self.backup.clone().ok_or_else(|| {
+            NodeError::InvalidParameter("The database of the node is not backed up".to_owned())
+        })?;
+        let manifest = NodeBackupManifest {
+            schema_version: BACKUP_SCHEMA_VERSION,
+            backend: source.backend().to_owned(),
+            node_version: env!("CARGO_PKG_VERSION").to_owned(),
+            node_id: self.get_controller_id(),
+            peer_id: self.get_peer_id(),
+            height: self.ledger_height().await?,
+            created_at: timestamp_millis(),
         };
-
-        let data = match subject_type {
-            SubjectType::All => {
-                if let Some(data) = &parameters.governanceid {
-                    self.api
-                        .get_subjects_by_governance(
-                            DigestIdentifier::from_str(data).map_err(|_| {
-                                NodeError::InvalidParameter("governanceid".to_owned())
-                            })?,
-                            parameters.from,
-                            parameters.quantity,
-                        )
-                        .await
-                } else {
-                    self.api
-                        .get_subjects("".into(), parameters.from, parameters.quantity)
-                        .await
-                }
-            }
-            SubjectType::Governances => {
-                self.api
-                    .get_governances("".into(), parameters.from, parameters.quantity)
-                    .await
-            }
-        }
-        .map(|s| {
-            s.into_iter()
-                .map(NodeSubjectData::from)
-                .collect::<Vec<NodeSubjectData>>()
-        });
-
-        match data {
-            Ok(data) => Ok(data),
-            Err(_) => Err(NodeError::InternalApi(
-                "Failed to process request".to_owned(),
-            )),
-        }
+        let (archive, written) = (path.to_owned(), manifest.clone());
+        tokio::task::spawn_blocking(move || write_backup(&source, &written, &archive))
+            .await
+            .map_err(|error| NodeError::InternalApi(error.to_string()))??;
+        self.record_history(NodeHistoryKind::Backup, &path.display().to_string());
+        Ok(manifest)
     }
 
-    /// Get subject.
-    /// Obtains the information of a traceability subject from its id.
-    ///
-    /// # Arguments
-    ///
-    /// * `subject_id` - Subject id.
+    /// Keys and approximate size of each collection of the database, and the bytes it uses on
+    /// disk, see the `maintenance` database module. Every key is read, so it takes as long as a
+    /// scan of the database.
     ///
     /// # Errors
     ///
-    /// * `NodeError::InternalApi` - Internal API error.
-    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::InvalidParameter` - The node was built without access to its database.
+    /// * `NodeError::Database` - The database could not be read.
     ///
     /// # Returns
     ///
-    /// * `NodeSubjectData` - Subject of traceability.
+    /// * `NodeDbStats` - Size report of the database.
     ///
-    pub async fn get_subject(&self, subject_id: &str) -> Result<NodeSubjectData, NodeError> {
-        match self
-            .api
-            .get_subject(
-                DigestIdentifier::from_str(subject_id)
-                    .map_err(|_| NodeError::InvalidParameter("invalid subject_id".to_owned()))?,
-            )
-            .await
-            .map(NodeSubjectData::from)
-        {
-            Ok(result) => Ok(result),
-            Err(_) => Err(NodeError::InternalApi(
-                "Failed to process request".to_owned(),
-            )),
-        }
+    pub async fn db_stats(&self) -> Result<NodeDbStats, NodeError> {
+        let maintenance = self.maintenance()?;
+        tokio::task::spawn_blocking(move || {
+            let (collections, disk_size) = maintenance.stats()?;
+            Ok(NodeDbStats {
+                backend: maintenance.backend().to_owned(),
+                collections,
+                disk_size,
+            })
+        })
+        .await
+        .map_err(|error| NodeError::InternalApi(error.to_string()))?
     }
 
-    /// Get validation proof.
-    /// Allows to obtain the validation test of the last event for a specified subject.
+    /// Compact the database: LevelDB compaction, SQLite `VACUUM` or PostgreSQL `VACUUM`, see the
+    /// `maintenance` database module. The node keeps serving requests meanwhile, although its
+    /// writes may wait for the compaction.
     ///
-    /// # Arguments
+    /// # Errors
     ///
-    /// * `subject_id` - Subject id.
+    /// * `NodeError::InvalidParameter` - The node was built without access to its database.
+    /// * `NodeError::Database` - The compaction failed, retryable when the database was busy.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeDbCompaction` - Bytes used on disk before and after the compaction.
+    ///
+    pub async fn compact_db(&self) -> Result<NodeDbCompaction, NodeError> {
+        let maintenance = self.maintenance()?;
+        let compaction = tokio::task::spawn_blocking(move || {
+            let start = Instant::now();
+            let disk_size_before = maintenance.disk_size()?;
+            maintenance.compact()?;
+            Ok::<_, NodeError>(NodeDbCompaction {
+                backend: maintenance.backend().to_owned(),
+                disk_size_before,
+                disk_size_after: maintenance.disk_size()?,
+                elapsed_ms: start.elapsed().as_millis() as u64,
+            })
+        })
+        .await
+        .map_err(|error| NodeError::InternalApi(error.to_string()))??;
+        self.record_history(
+            NodeHistoryKind::Compacted,
+            &format!(
+                "{} from {} to {} bytes",
+                compaction.backend, compaction.disk_size_before, compaction.disk_size_after
+            ),
+        );
+        Ok(compaction)
+    }
+
+    /// Move the old events of the subjects to the archive, see the `archival` module. Kore Base
+    /// reads the archived events through, so they are still sent to other nodes and verified.
     ///
     /// # Errors
     ///
-    /// * `NodeError::InternalApi` - Internal API error.
-    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::InvalidParameter` - The node has no archive.
+    /// * `NodeError::Archive` - An event could not be uploaded; those before it are archived.
+    /// * `NodeError::Database` - The events or the markers could not be read or written.
     ///
     /// # Returns
     ///
-    /// * `NodeProof` - Validation proof.
+    /// * `NodeArchivalReport` - Events and bytes archived, and bytes left in the database.
     ///
-    pub async fn get_validation_proof(&self, subject_id: &str) -> Result<NodeProof, NodeError> {
-        match self
-            .api
-            .get_validation_proof(
-                DigestIdentifier::from_str(subject_id)
-                    .map_err(|_| NodeError::InvalidParameter("invalid subject_id".to_owned()))?,
-            )
-            .await
-        {
-            Ok(value) => Ok(NodeProof::from(value)),
-            Err(_) => Err(NodeError::InternalApi(
-                "Failed to process request".to_owned(),
-            )),
+    pub async fn archive_events(&self) -> Result<NodeArchivalReport, NodeError> {
+        let archival = self
+            .archival
+            .as_ref()
+            .ok_or_else(|| NodeError::InvalidParameter("The node has no archive".to_owned()))?;
+        let report = archival.run(timestamp_nanos()).await?;
+        if report.events > 0 {
+            self.record_history(
+                NodeHistoryKind::EventsArchived,
+                &format!("{} events, {} bytes", report.events, report.bytes),
+            );
         }
+        Ok(report)
     }
 
-    /// Get events of subject.
-    /// Get events of traceability subject.
-    ///
-    /// # Arguments
-    ///
-    /// * `subject_id` - Subject id.
-    /// * `parameters` - Parameters for retrieving events of subject
+    /// Delete the expired entries of every collection returned by `expiring_store`.
     ///
     /// # Errors
     ///
-    /// * `NodeError::InternalApi` - Internal API error.
-    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::Database` - The entries could not be read or deleted.
     ///
     /// # Returns
     ///
-    /// * `Vec<NodeSigned<EventContentResponse>>` - Event vector of a traceability subject
+    /// * `usize` - Entries deleted.
     ///
-    pub async fn get_events_of_subject(
-        &self,
-        subject_id: &str,
-        parameters: PaginatorFromNumber,
-    ) -> Result<Vec<NodeSigned<EventContentResponse>>, NodeError> {
-        let value = self
-            .api
-            .get_events(
-                DigestIdentifier::from_str(subject_id)
-                    .map_err(|_| NodeError::InvalidParameter("invalid subject_id".to_owned()))?,
-                parameters.from,
-                parameters.quantity,
-            )
+    pub async fn sweep_expired(&self) -> Result<usize, NodeError> {
+        let store = self.store.scope(EXPIRING_SCOPE);
+        tokio::task::spawn_blocking(move || store.sweep())
             .await
-            .map(|vec| {
-                vec.into_iter()
-                    .map(NodeSigned::<EventContentResponse>::from)
-                    .collect::<Vec<NodeSigned<EventContentResponse>>>()
-            });
-        match value {
-            Ok(v) => Ok(v),
-            Err(_) => Err(NodeError::InternalApi(
-                "Failed to process request".to_owned(),
-            )),
+            .map_err(|error| NodeError::InternalApi(error.to_string()))?
+    }
+
+    /// Database of the node, for the maintenance operations.
+    fn maintenance(&self) -> Result<DbMaintenance, NodeError> {
+        self.maintenance.clone().ok_or_else(|| {
+            NodeError::InvalidParameter("The node has no access to its database".to_owned())
+        })
+    }
+
+    /// Events of every subject known by the node, archived ones included.
+    async fn ledger_height(&self) -> Result<u64, NodeError> {
+        let mut height = 0;
+        let mut from = None;
+        loop {
+            let page = self
+                .get_subjects(NodeSubjects {
+                    from,
+                    quantity: Some(PAGE_SIZE),
+                    subject_type: Some("all".to_owned()),
+                    governanceid: None,
+                    archive_filter: Some("all".to_owned()),
+                })
+                .await?;
+            height += page.items.iter().map(|subject| subject.sn + 1).sum::<u64>();
+            match page.next_cursor {
+                Some(cursor) => from = Some(cursor),
+                None => return Ok(height),
+            }
         }
     }
 
-    /// Get event of subject.
-    /// Get a specific event of traceability subject.
+    /// Rotate the node key.
+    /// A new key pair is written to `node_private.der`, encrypted with the password of the node,
+    /// and the key in use is kept as the next key version. The node keeps signing with the key
+    /// in use until it restarts; from then on its controller and peer identifiers are those of
+    /// the new key.
     ///
     /// # Arguments
     ///
-    /// * `subject_id` - Subject id.
-    /// * `sn` - Versión of subject.
+    /// * `password` - Password of the node key.
     ///
     /// # Errors
     ///
-    /// * `NodeError::InternalApi` - Internal API error.
-    /// * `NodeError::InvalidParameter` - Invalid request parameter.
+    /// * `NodeError::InvalidParameter` - The API does not know the keys directory.
+    /// * `NodeError::Keys` - Wrong password, or the key files cannot be written.
+    /// * `NodeError::Conflict` - A rotated key is waiting for the restart of the node.
     ///
     /// # Returns
     ///
-    /// * `NodeSigned<EventContentResponse>` - Event of a traceability subject
+    /// * `NodeKeyRotation` - Version of the replaced key and the new controller identifier.
     ///
-    pub async fn get_event_of_subject(
-        &self,
-        subject_id: &str,
-        sn: u64,
-    ) -> Result<NodeSigned<EventContentResponse>, NodeError> {
-        let value = self
-            .api
-            .get_event(
-                DigestIdentifier::from_str(subject_id)
-                    .map_err(|_| NodeError::InvalidParameter("invalid subject_id".to_owned()))?,
-                sn,
-            )
-            .await
-            .map(NodeSigned::<EventContentResponse>::from);
-        match value {
-            Ok(v) => Ok(v),
-            Err(_) => Err(NodeError::InternalApi(
-                "Failed to process request".to_owned(),
-            )),
-        }
+    pub fn rotate_node_key(&self, password: &str) -> Result<NodeKeyRotation, NodeError> {
+        let (version, key_pair) = rotate_key_file(
+            self.keys_path()?,
+            self.key_derivator,
+            &self.key_encryption,
+            &self.keys,
+            password,
+        )?;
+        let rotation = NodeKeyRotation {
+            version,
+            previous_controller_id: self.get_controller_id(),
+            controller_id: self.key_controller_id(&key_pair),
+        };
+        self.record_history(
+            NodeHistoryKind::KeysRotated,
+            &format!(
+                "controller {} kept as key version {}, {} used on restart",
+                rotation.previous_controller_id, version, rotation.controller_id
+            ),
+        );
+        Ok(rotation)
     }
 
-    /// Get Controller ID.
+    /// Replaced keys of the node, oldest first, whose controller identifiers still verify the
+    /// signatures made with them.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// * `String - Controller ID
+    /// * `password` - Password of the node key.
     ///
-    pub fn get_controller_id(&self) -> String {
-        self.api.controller_id().clone()
-    }
-
-    /// Get Peer ID.
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - The API does not know the keys directory.
+    /// * `NodeError::Keys` - The keys directory cannot be read.
     ///
     /// # Returns
     ///
-    /// * `String - Peer ID
+    /// * `Vec<NodeKeyVersion>` - Key versions that could be decrypted with `password`.
     ///
-    pub fn get_peer_id(&self) -> String {
-        self.api.peer_id().to_string()
+    pub fn node_key_versions(&self, password: &str) -> Result<Vec<NodeKeyVersion>, NodeError> {
+        let key_pairs = previous_key_pairs(
+            self.keys_path()?,
+            self.key_derivator,
+            &self.key_encryption,
+            password,
+        )?;
+        Ok(key_pairs
+            .into_iter()
+            .map(|(version, key_pair)| NodeKeyVersion {
+                version,
+                controller_id: self.key_controller_id(&key_pair),
+            })
+            .collect())
+    }
+
+    /// Keys directory of the node.
+    fn keys_path(&self) -> Result<&str, NodeError> {
+        self.keys_path.as_deref().ok_or_else(|| {
+            NodeError::InvalidParameter("the keys directory of the node is unknown".to_owned())
+        })
+    }
+
+    /// Controller identifier of a key pair.
+    fn key_controller_id(&self, key_pair: &KeyPair) -> String {
+        KeyIdentifier::new(self.key_derivator, &key_pair.public_key_bytes()).to_str()
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     #[cfg(feature = "leveldb")]
     use crate::node::tests::export_leveldb_api;
 
     #[cfg(feature = "sqlite")]
     use crate::node::tests::export_sqlite_api;
 
-    use crate::model::{AuthorizeSubject, NodeFactRequest, NodeSubjects, PaginatorFromString};
-    use crate::model::{NodeEventRequest, NodeSignedEventRequest, NodeStartRequest};
+    use crate::model::{
+        AuthorizeSubject, NodeAllowedSubjectsFilter, NodeFactRequest, NodeSubjects,
+        PaginatorFromString,
+    };
+    use crate::model::{EventContentResponse, NodeKeys, NodeSigned, Page, PaginatorFromNumber};
+    use crate::model::{
+        NodeEOLRequest, NodeEventRequest, NodeRequestOrigin, NodeSignedEventRequest,
+        NodeStartRequest,
+    };
     use crate::model::{NodeGetApprovals, PatchVote};
-    use crate::model::{NodeKeys, PaginatorFromNumber};
-    use crate::KoreApi;
+    use crate::model::{
+        NodeGraphRelation, NodeGraphVertexKind, NodeRequestState, NodeVerificationKind,
+    };
+    use crate::subscription::SubscriptionTarget;
+    use crate::{
+        api::{timestamp_millis, MAX_GRAPH_DEPTH, MAX_IDEMPOTENCY_KEY_LEN, USAGE_WINDOW},
+        error::NodeError,
+        settings::{
+            CallLimit, LimitsSettings, ServicesSettings, SignatureCheck, SigningPolicy,
+            SubjectQuota,
+        },
+        KoreApi,
+    };
+    use kore_base::signature::Signature as BaseSignature;
     use kore_base::ApprovalState as BaseApprovalState;
     use kore_base::RoutingNode;
     use serde_json::{json, Value};
     use std::time::Duration;
     use std::vec;
+    use tokio_util::sync::CancellationToken;
 
     //////////////////////////////////////////////////////////////////////////////////////////
     /// Basic methods
     /// Methods that perform different actions that in combination achieve certain behaviors
     //////////////////////////////////////////////////////////////////////////////////////////
     /// Method that creates a governance and returns its identifier.
-    async fn create_event(api: &KoreApi, gov_id: &str, schema: &str, name: &str) -> String {
+    pub async fn create_event(api: &KoreApi, gov_id: &str, schema: &str, name: &str) -> String {
         let res = api
             .send_event_request(NodeSignedEventRequest {
                 request: NodeEventRequest::Create(NodeStartRequest {
@@ -710,6 +4298,7 @@ mod tests {
                     public_key: None,
                 }),
                 signature: None,
+                origin: None,
             })
             .await
             .unwrap();
@@ -744,6 +4333,7 @@ mod tests {
                     payload,
                 }),
                 signature: None,
+                origin: None,
             })
             .await
             .unwrap();
@@ -757,7 +4347,8 @@ mod tests {
                     quantity: None,
                 })
                 .await
-                .unwrap();
+                .unwrap()
+                .items;
             if res_vec.is_empty() {
                 tokio::time::sleep(Duration::from_millis(300)).await;
             } else {
@@ -789,7 +4380,8 @@ mod tests {
                 quantity: None,
             })
             .await
-            .unwrap();
+            .unwrap()
+            .items;
         assert!(res_vec.is_empty());
     }
 
@@ -802,12 +4394,16 @@ mod tests {
         assert_eq!(res, "Ok".to_owned());
 
         let res = api_node2
-            .get_all_allowed_subjects_and_providers(PaginatorFromString {
-                from: None,
-                quantity: None,
-            })
+            .get_all_allowed_subjects_and_providers(
+                PaginatorFromString {
+                    from: None,
+                    quantity: None,
+                },
+                NodeAllowedSubjectsFilter::default(),
+            )
             .await
-            .unwrap();
+            .unwrap()
+            .items;
         assert_eq!(res[0].subject_id, subject);
 
         let mut res_vec;
@@ -818,9 +4414,11 @@ mod tests {
                     governanceid: None,
                     subject_type: None,
                     quantity: None,
+                    archive_filter: None,
                 })
                 .await
-                .unwrap();
+                .unwrap()
+                .items;
             if res_vec.is_empty() {
                 tokio::time::sleep(Duration::from_millis(300)).await;
             } else {
@@ -831,6 +4429,20 @@ mod tests {
 
         let res = api_node2.get_subject(subject).await.unwrap();
         assert_eq!(res.subject_id, res_vec[0].subject_id);
+
+        let filter =
+            |provider: Option<&str>, governance_id: Option<&str>| NodeAllowedSubjectsFilter {
+                provider: provider.map(str::to_owned),
+                governance_id: governance_id.map(str::to_owned),
+                namespace: Some("".to_owned()),
+            };
+        let count = api_node2.count_allowed_subjects(filter(None, Some(subject)));
+        assert_eq!(count.await.unwrap(), 1);
+        let provider = api_node2.get_controller_id();
+        let count = api_node2.count_allowed_subjects(filter(Some(&provider), None));
+        assert_eq!(count.await.unwrap(), 0);
+        let count = api_node2.count_allowed_subjects(filter(Some("invalid"), None));
+        assert!(matches!(count.await, Err(NodeError::InvalidParameter(_))));
     }
 
     /// method that for a given subject checks 'number' events
@@ -846,13 +4458,28 @@ mod tests {
                     },
                 )
                 .await
-                .unwrap();
+                .unwrap()
+                .items;
             if res_vec.len() < number {
                 tokio::time::sleep(Duration::from_millis(300)).await;
             } else {
                 break;
             }
         }
+        let first = api
+            .get_events_of_subject(
+                gov_subject,
+                PaginatorFromNumber {
+                    from: None,
+                    quantity: Some(1),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(first.items.len(), 1);
+        if res_vec.len() > 1 {
+            assert_eq!(first.next_cursor, Some("1".to_owned()));
+        }
 
         for n in 0..number {
             let res = api
@@ -913,8 +4540,8 @@ mod tests {
     }
 
     async fn api_preauthorize_subject(api_node1: &KoreApi, api_node2: &KoreApi) {
-        let controller_id_node1 = api_node1.api.controller_id();
-        let controller_id_node2 = api_node2.api.controller_id();
+        let controller_id_node1 = api_node1.node_info().controller_id;
+        let controller_id_node2 = api_node2.node_info().controller_id;
 
         let gov_subject = create_event(&api_node1, "", "governance", "wine").await;
         let payload: Value = json!({
@@ -971,11 +4598,330 @@ mod tests {
             .await
             .unwrap();
 
-        assert!(!pub_key.is_empty());
+        assert!(!pub_key.is_empty());
+    }
+
+    async fn api_check_event_events_of_subject(api: &KoreApi, number: usize) {
+        let gov_subject = create_event(&api, "", "governance", "wine").await;
+        let payload = json!({
+            "Patch": {
+                "data": [
+                {
+                    "op": "add",
+                    "path": "/members/0",
+                    "value": {
+                    "id": "EnyisBz0lX9sRvvV0H-BXTrVtARjUa0YDHzaxFHWH-N4",
+                    "name": "Test1"
+                    }
+                }
+            ]
+            }
+        });
+
+        create_approval_event_and_vote(&api, payload, &gov_subject, PatchVote::RespondedAccepted)
+            .await;
+
+        check_event_events_of_subject(&api, &gov_subject, number).await;
+
+        let chain = api.verify_subject_chain(&gov_subject).await.unwrap();
+        assert_eq!(chain.events, number as u64);
+        assert_eq!(chain.first_invalid, None, "{:?}", chain);
+        let reports = api
+            .verify_subject_chain_reports(&gov_subject)
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(reports.len() as u64, chain.events);
+        // Evaluator signatures cannot be checked on the ledger.
+        assert_eq!(chain.partial, reports.iter().any(|report| report.partial));
+        assert_eq!(chain.valid, !chain.partial);
+        let event = api.verify_event(&gov_subject, 1).await.unwrap();
+        assert_eq!(event, reports[1]);
+        assert!(event
+            .checks
+            .iter()
+            .any(|check| check.kind == NodeVerificationKind::ApproverSignature));
+        assert!(api.verify_event(&gov_subject, 99).await.is_err());
+    }
+
+    async fn api_cancelled_call(api: &KoreApi) {
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        let res = api
+            .with_cancellation(cancellation)
+            .get_subjects(NodeSubjects {
+                from: None,
+                governanceid: None,
+                subject_type: None,
+                quantity: None,
+                archive_filter: None,
+            })
+            .await;
+        assert!(matches!(res, Err(NodeError::Cancelled)));
+
+        let res = api
+            .with_timeout(Duration::from_secs(30))
+            .get_subjects(NodeSubjects {
+                from: None,
+                governanceid: None,
+                subject_type: None,
+                quantity: None,
+                archive_filter: None,
+            })
+            .await;
+        assert!(res.is_ok());
+    }
+
+    async fn api_archive_subject(api: &KoreApi) {
+        let gov_subject = create_event(api, "", "governance", "wine").await;
+        let list = |archive_filter: Option<&str>| NodeSubjects {
+            from: None,
+            governanceid: None,
+            subject_type: None,
+            quantity: None,
+            archive_filter: archive_filter.map(|filter| filter.to_owned()),
+        };
+
+        let etag = api.get_subject(&gov_subject).await.unwrap().etag();
+        let res = api.get_subject_if_changed(&gov_subject, &etag).await;
+        assert!(res.unwrap().is_none());
+
+        assert_eq!(api.archive_subject(&gov_subject).await.unwrap(), "Ok");
+        assert!(api.get_subject(&gov_subject).await.unwrap().archived);
+        let res = api.get_subject_if_changed(&gov_subject, &etag).await;
+        assert!(res.unwrap().unwrap().archived);
+        assert!(api.get_subjects(list(None)).await.unwrap().items.is_empty());
+        let res = api
+            .get_subjects(list(Some("archived")))
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(res[0].subject_id, gov_subject);
+
+        assert_eq!(api.unarchive_subject(&gov_subject).await.unwrap(), "Ok");
+        let res = api.get_subjects(list(None)).await.unwrap().items;
+        assert_eq!(res[0].subject_id, gov_subject);
+        assert!(!res[0].archived);
+
+        // Pages are full of the subjects the archive filter keeps.
+        create_event(api, "", "governance", "beer").await;
+        let all = api.get_subjects(list(None)).await.unwrap().items;
+        assert_eq!(all.len(), 2);
+        api.archive_subject(&all[0].subject_id).await.unwrap();
+        let page = api
+            .get_subjects(NodeSubjects {
+                quantity: Some(1),
+                ..list(None)
+            })
+            .await
+            .unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].subject_id, all[1].subject_id);
+    }
+
+    async fn api_transfer_subject(api: &KoreApi) {
+        let gov_subject = create_event(api, "", "governance", "wine").await;
+        let res = api.transfer_subject(&gov_subject, "owner").await;
+        assert!(matches!(res, Err(NodeError::InvalidParameter(_))));
+        let res = api
+            .transfer_subject(&gov_subject, &api.get_controller_id())
+            .await;
+        assert!(matches!(res, Err(NodeError::Conflict(_))));
+        let res = api.end_subject_life("subject").await;
+        assert!(matches!(res, Err(NodeError::InvalidParameter(_))));
+
+        let res = api.end_subject_life(&gov_subject).await.unwrap();
+        let records = api
+            .list_requests(PaginatorFromString {
+                from: None,
+                quantity: None,
+            })
+            .unwrap();
+        let record = records.last().unwrap();
+        assert_eq!(record.request_id, res.request_id);
+        assert_eq!(record.request_type, "EOL");
+    }
+
+    async fn api_approvals_stream(api: &KoreApi) {
+        let mut approvals = api.approvals_stream();
+        let gov_subject = create_event(api, "", "governance", "wine").await;
+        api.send_event_request(NodeSignedEventRequest {
+            request: NodeEventRequest::Fact(NodeFactRequest {
+                subject_id: gov_subject.clone(),
+                payload: json!({
+                    "Patch": {
+                        "data": [{
+                            "op": "add",
+                            "path": "/members/0",
+                            "value": {
+                                "id": "EnyisBz0lX9sRvvV0H-BXTrVtARjUa0YDHzaxFHWH-N4",
+                                "name": "Test1"
+                            }
+                        }]
+                    }
+                }),
+            }),
+            signature: None,
+            origin: None,
+        })
+        .await
+        .unwrap();
+
+        let approval = tokio::time::timeout(Duration::from_secs(30), approvals.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let NodeEventRequest::Fact(fact) = &approval.request.content.event_request.request else {
+            panic!("the approval should be of the fact request");
+        };
+        assert_eq!(fact.subject_id, gov_subject);
+        api.approval_request(&approval.id, PatchVote::RespondedAccepted)
+            .await
+            .unwrap();
+    }
+
+    async fn api_list_requests(api: &KoreApi) {
+        let origin = NodeRequestOrigin {
+            source: Some("erp".to_owned()),
+            device_id: Some("device-1".to_owned()),
+            geo_hint: None,
+        };
+        let res = api
+            .send_event_request(NodeSignedEventRequest {
+                request: NodeEventRequest::Create(NodeStartRequest {
+                    governance_id: "".to_owned(),
+                    schema_id: "governance".to_owned(),
+                    namespace: "".to_owned(),
+                    name: "wine".to_owned(),
+                    public_key: None,
+                }),
+                signature: None,
+                origin: Some(origin.clone()),
+            })
+            .await
+            .unwrap();
+
+        let records = api
+            .list_requests(PaginatorFromString {
+                from: None,
+                quantity: None,
+            })
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].request_id, res.request_id);
+        assert_eq!(records[0].request_type, "Create");
+        assert_eq!(records[0].origin, Some(origin));
+
+        let records = api
+            .list_requests(PaginatorFromString {
+                from: Some(res.request_id.clone()),
+                quantity: None,
+            })
+            .unwrap();
+        assert!(records.is_empty());
+
+        // Reading after a cursor goes on when its request is no longer stored.
+        let cursor = api.last_request_cursor().unwrap().unwrap();
+        create_event(api, "", "governance", "beer").await;
+        api.requests_store().del(&res.request_id).unwrap();
+        let records = api.requests_after(Some(&cursor), usize::MAX).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_ne!(records[0].1.request_id, res.request_id);
+
+        // Requests recorded before their order are added to it.
+        let order = api.requests_order();
+        for (key, _) in order.entries::<String>().unwrap() {
+            order.del(&key).unwrap();
+        }
+        let indexed = api.requests_after(None, usize::MAX).unwrap();
+        assert_eq!(indexed.len(), 1);
+        assert_eq!(indexed[0].0, records[0].0);
     }
 
-    async fn api_check_event_events_of_subject(api: &KoreApi, number: usize) {
+    async fn api_subject_quota(api: KoreApi) {
+        let api = api.with_subject_quota(SubjectQuota {
+            max_subjects: 1,
+            window: Duration::from_secs(3600),
+        });
+        let request = |name: &str| NodeSignedEventRequest {
+            request: NodeEventRequest::Create(NodeStartRequest {
+                governance_id: "".to_owned(),
+                schema_id: "governance".to_owned(),
+                namespace: "".to_owned(),
+                name: name.to_owned(),
+                public_key: None,
+            }),
+            signature: None,
+            origin: None,
+        };
+        // A creation that does not reach Kore Base is not counted: the key of the subject takes
+        // the only write allowed.
+        let limited = api.clone().with_limits(LimitsSettings {
+            writes: CallLimit {
+                rate: 0.001,
+                burst: 1,
+                max_in_flight: 0,
+            },
+            ..Default::default()
+        });
+        assert!(matches!(
+            limited.send_event_request(request("cider")).await,
+            Err(NodeError::RateLimited(_))
+        ));
+
+        // Concurrent creations cannot go over the quota.
+        let create = |name: &str| api.send_event_request(request(name));
+        let (wine, beer) = tokio::join!(create("wine"), create("beer"));
+        assert_eq!(wine.is_ok() as u8 + beer.is_ok() as u8, 1);
+        assert!(matches!(
+            wine.err().or(beer.err()),
+            Some(NodeError::QuotaExceeded(_))
+        ));
+    }
+
+    async fn api_signing_policy(api: KoreApi) {
         let gov_subject = create_event(&api, "", "governance", "wine").await;
+        let api = api.with_signing_policies(vec![SigningPolicy {
+            request_types: vec!["EOL".to_owned()],
+            signers: vec!["EnyisBz0lX9sRvvV0H-BXTrVtARjUa0YDHzaxFHWH-N4".to_owned()],
+            external_signer: false,
+        }]);
+        let eol = || NodeSignedEventRequest {
+            request: NodeEventRequest::EOL(NodeEOLRequest {
+                subject_id: gov_subject.clone(),
+            }),
+            signature: None,
+            origin: None,
+        };
+        let res = api.send_event_request(eol()).await;
+        assert!(matches!(res, Err(NodeError::Unauthorized(_))));
+
+        // A poisoned lock does not let the request through.
+        let policies = api.signing_policies.clone();
+        let _ = std::thread::spawn(move || {
+            let _policies = policies.write().unwrap();
+            panic!("policies poisoned");
+        })
+        .join();
+        let res = api.send_event_request(eol()).await;
+        assert!(matches!(res, Err(NodeError::InternalApi(_))));
+
+        api.set_signing_policies(vec![SigningPolicy {
+            request_types: vec!["EOL".to_owned()],
+            signers: vec![api.get_controller_id()],
+            external_signer: false,
+        }]);
+        assert!(api.send_event_request(eol()).await.is_ok());
+    }
+
+    async fn api_subscribe(api: &KoreApi) {
+        let gov_subject = create_event(api, "", "governance", "wine").await;
+        let mut subscription = api
+            .subscribe(SubscriptionTarget::Subject(gov_subject.clone()))
+            .await
+            .unwrap();
         let payload = json!({
             "Patch": {
                 "data": [
@@ -990,11 +4936,209 @@ mod tests {
             ]
             }
         });
+        create_approval_event_and_vote(api, payload, &gov_subject, PatchVote::RespondedAccepted)
+            .await;
 
-        create_approval_event_and_vote(&api, payload, &gov_subject, PatchVote::RespondedAccepted)
+        // Events committed before subscribing are not pushed.
+        let event = tokio::time::timeout(Duration::from_secs(10), subscription.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.content.subject_id, gov_subject);
+        assert_eq!(event.content.sn, 1);
+
+        let res = api
+            .subscribe(SubscriptionTarget::Subject("invalid".to_owned()))
             .await;
+        assert!(matches!(res, Err(NodeError::InvalidParameter(_))));
+    }
 
-        check_event_events_of_subject(&api, &gov_subject, number).await;
+    async fn api_wait_state_change(api: &KoreApi) {
+        let res = api
+            .send_event_request(NodeSignedEventRequest {
+                request: NodeEventRequest::Create(NodeStartRequest {
+                    governance_id: "".to_owned(),
+                    schema_id: "governance".to_owned(),
+                    namespace: "".to_owned(),
+                    name: "wait".to_owned(),
+                    public_key: None,
+                }),
+                signature: None,
+                origin: None,
+            })
+            .await
+            .unwrap();
+        let mut state = NodeRequestState::Processing;
+        while !state.is_final() {
+            state = api
+                .wait_state_change(&res.request_id, state, Duration::from_secs(10))
+                .await
+                .unwrap()
+                .state;
+        }
+        assert_eq!(state, NodeRequestState::Finished);
+
+        // A final state never changes, so there is nothing to wait for.
+        let start = std::time::Instant::now();
+        let request = api
+            .wait_state_change(&res.request_id, state, Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert_eq!(request.state, NodeRequestState::Finished);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let res = api
+            .wait_state_change("invalid", state, Duration::from_secs(1))
+            .await;
+        assert!(matches!(res, Err(NodeError::InvalidParameter(_))));
+    }
+
+    fn api_peer_services(api: &KoreApi) {
+        let controller_id = api.get_controller_id();
+        let api = api.clone().with_services(ServicesSettings {
+            rest_url: "https://node.example.com/api".to_owned(),
+            peers: [(
+                controller_id.clone(),
+                "https://node.example.com/api".to_owned(),
+            )]
+            .into(),
+            ..Default::default()
+        });
+        let signed = api.service_record().unwrap();
+        assert_eq!(signed.content.controller_id, controller_id);
+        assert_eq!(
+            signed.content.rest_url.as_deref(),
+            Some("https://node.example.com/api")
+        );
+        assert_eq!(signed.content.metrics_url, None);
+        assert!(api.peer_services().is_empty());
+
+        let res = api.record_peer_service("untrusted", signed.clone());
+        assert!(matches!(res, Err(NodeError::Unauthorized(_))));
+        let mut forged = signed.clone();
+        forged.content.rest_url = None;
+        forged.content.controller_id = "other".to_owned();
+        let res = api.record_peer_service(&controller_id, forged);
+        assert!(matches!(res, Err(NodeError::Unauthorized(_))));
+
+        let record = api
+            .record_peer_service(&controller_id, signed.clone())
+            .unwrap();
+        assert_eq!(record, signed.content);
+        let mut older = signed.clone();
+        older.content.timestamp -= 1;
+        older.content.rest_url = None;
+        older.signature = BaseSignature::new(&older.content, &api.keys, api.digest_derivator)
+            .unwrap()
+            .into();
+        let record = api.record_peer_service(&controller_id, older).unwrap();
+        assert_eq!(record, signed.content);
+        assert_eq!(api.peer_services(), vec![signed.content]);
+    }
+
+    fn api_peers(api: &KoreApi) {
+        let boot_node = RoutingNode {
+            peer_id: "12D3KooWBoot".to_owned(),
+            address: vec!["/memory/1".to_owned()],
+        };
+        let api = api.clone().with_peers(vec![boot_node.clone()]);
+        assert_eq!(api.boot_nodes(), vec![NodeBootNode::from(boot_node)]);
+
+        let boot_node = RoutingNode {
+            peer_id: "12D3KooWBoot".to_owned(),
+            address: vec!["/ip4/10.0.0.1/tcp/50000".to_owned()],
+        };
+        let res = api.add_boot_node(boot_node.clone());
+        assert!(matches!(res, Err(NodeError::InvalidParameter(_))));
+        let peer_id = "12D3KooWLXexpg81PjdjnrhmHUxN7U5EtfXJgr9cahei1SJ9Ub3B".to_owned();
+        let res = api.add_boot_node(RoutingNode {
+            peer_id: peer_id.clone(),
+            address: vec!["10.0.0.1:50000".to_owned()],
+        });
+        assert!(matches!(res, Err(NodeError::InvalidParameter(_))));
+        let stored = api
+            .add_boot_node(RoutingNode {
+                peer_id: peer_id.clone(),
+                ..boot_node
+            })
+            .unwrap();
+        assert_eq!(api.stored_boot_nodes().unwrap(), vec![stored.clone()]);
+        api.remove_boot_node(&peer_id).unwrap();
+        assert!(api.stored_boot_nodes().unwrap().is_empty());
+        let removed = api.store.scope(REMOVED_BOOT_NODES_SCOPE);
+        assert_eq!(
+            removed.get::<NodeBootNode>(&peer_id).unwrap(),
+            Some(stored.clone())
+        );
+        let res = api.remove_boot_node(&peer_id);
+        assert!(matches!(res, Err(NodeError::NotFound(_))));
+        api.add_boot_node(stored.into()).unwrap();
+        assert_eq!(removed.get::<NodeBootNode>(&peer_id).unwrap(), None);
+    }
+
+    fn api_usage(api: &KoreApi) {
+        api.record_usage("10.0.0.2", 120);
+        api.flush_usage().unwrap();
+        api.record_usage("10.0.0.2", 80);
+        api.record_usage("10.0.0.3", 0);
+
+        let now = timestamp_millis();
+        let usage = api.usage(Some("10.0.0.2"), 0..now + 1).unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!((usage[0].requests, usage[0].bytes), (2, 200));
+        assert_eq!(usage[0].to - usage[0].from, USAGE_WINDOW);
+        assert_eq!(api.usage(None, 0..now + 1).unwrap().len(), 2);
+        assert!(api
+            .usage(None, now + USAGE_WINDOW..u64::MAX)
+            .unwrap()
+            .is_empty());
+
+        let summary = api.usage_summary(0..now + 1).unwrap();
+        let totals = summary
+            .iter()
+            .map(|usage| (usage.caller.as_str(), usage.requests, usage.bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(totals, vec![("10.0.0.2", 2, 200), ("10.0.0.3", 1, 0)]);
+
+        // Flushed windows add up, and are kept within the retention.
+        assert_eq!(api.flush_usage().unwrap(), 0);
+        let usage = api.usage(Some("10.0.0.2"), 0..now + 1).unwrap();
+        assert_eq!((usage[0].requests, usage[0].bytes), (2, 200));
+    }
+
+    async fn api_subject_graph(api: &KoreApi) {
+        let governance_id = create_event(api, "", "governance", "graph").await;
+        let graph = api.subject_graph(&governance_id, 1).await.unwrap();
+        let controller_id = api.get_controller_id();
+
+        assert_eq!(graph.root, governance_id);
+        assert_eq!(graph.vertices[0].id, governance_id);
+        assert_eq!(graph.vertices[0].kind, NodeGraphVertexKind::Governance);
+        assert_eq!(graph.vertices[1].id, controller_id);
+        let relations = graph
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str(), edge.relation))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            relations,
+            vec![
+                (
+                    governance_id.as_str(),
+                    controller_id.as_str(),
+                    NodeGraphRelation::CreatedBy
+                ),
+                (
+                    governance_id.as_str(),
+                    controller_id.as_str(),
+                    NodeGraphRelation::OwnedBy
+                ),
+            ]
+        );
+        assert!(!graph.truncated);
+
+        let res = api.subject_graph(&governance_id, MAX_GRAPH_DEPTH + 1).await;
+        assert!(matches!(res, Err(NodeError::InvalidParameter(_))));
     }
 
     async fn api_get_validation_proof(api: &KoreApi) {
@@ -1003,13 +5147,30 @@ mod tests {
 
         assert_eq!(gov_subject, res.proof.subject_id);
         assert_eq!(0, res.proof.sn);
+
+        let proof = api.get_validation_proof_at(&gov_subject, 0).await.unwrap();
+        assert_eq!(proof, res);
+        let res = api.get_validation_proof_at(&gov_subject, 1).await;
+        assert!(matches!(res, Err(NodeError::NotFound(_))));
+        let page = api
+            .get_validation_proofs(
+                &gov_subject,
+                PaginatorFromNumber {
+                    from: None,
+                    quantity: Some(10),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.items, vec![proof]);
+        assert_eq!(page.next_cursor, None);
     }
 
     /// LevelDB Tests
     #[tokio::test]
     #[cfg(feature = "leveldb")]
     async fn test_leveldb_api_send_get_event_request() {
-        let api = export_leveldb_api(101, vec![]);
+        let api = export_leveldb_api(101, vec![]).await;
 
         create_event(&api, "", "governance", "wine").await;
     }
@@ -1017,7 +5178,7 @@ mod tests {
     #[tokio::test]
     #[cfg(feature = "leveldb")]
     async fn test_leveldb_api_approval_accept() {
-        let api = export_leveldb_api(102, vec![]);
+        let api = export_leveldb_api(102, vec![]).await;
 
         api_approval_accept(&api).await;
     }
@@ -1025,15 +5186,15 @@ mod tests {
     #[tokio::test]
     #[cfg(feature = "leveldb")]
     async fn test_leveldb_api_approval_rejected() {
-        let api = export_leveldb_api(103, vec![]);
+        let api = export_leveldb_api(103, vec![]).await;
         api_approval_rejected(&api).await;
     }
 
     #[tokio::test]
     #[cfg(feature = "leveldb")]
     async fn test_leveldb_api_preauthorize_subject() {
-        let api_node1 = export_leveldb_api(104, vec![]);
-        let peer_id_node1 = api_node1.api.peer_id().to_string();
+        let api_node1 = export_leveldb_api(104, vec![]).await;
+        let peer_id_node1 = api_node1.node_info().peer_id;
 
         let api_node2 = export_leveldb_api(
             105,
@@ -1041,36 +5202,128 @@ mod tests {
                 address: vec!["/ip4/127.0.0.1/tcp/50104".to_owned()],
                 peer_id: peer_id_node1,
             }],
-        );
+        )
+        .await;
         api_preauthorize_subject(&api_node1, &api_node2).await;
     }
 
     #[tokio::test]
     #[cfg(feature = "leveldb")]
     async fn test_leveldb_api_public_key() {
-        let api = export_leveldb_api(106, vec![]);
+        let api = export_leveldb_api(106, vec![]).await;
         api_public_key(&api).await;
     }
 
     #[tokio::test]
     #[cfg(feature = "leveldb")]
     async fn test_leveldb_api_events_subject() {
-        let api = export_leveldb_api(107, vec![]);
+        let api = export_leveldb_api(107, vec![]).await;
         api_check_event_events_of_subject(&api, 2).await;
     }
 
     #[tokio::test]
     #[cfg(feature = "leveldb")]
     async fn test_leveldb_api_validation_proof() {
-        let api = export_leveldb_api(108, vec![]);
+        let api = export_leveldb_api(108, vec![]).await;
         api_get_validation_proof(&api).await;
     }
 
+    #[tokio::test]
+    #[cfg(feature = "leveldb")]
+    async fn test_leveldb_api_cancelled_call() {
+        let api = export_leveldb_api(109, vec![]).await;
+        api_cancelled_call(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "leveldb")]
+    async fn test_leveldb_api_archive_subject() {
+        let api = export_leveldb_api(110, vec![]).await;
+        api_archive_subject(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "leveldb")]
+    async fn test_leveldb_api_transfer_subject() {
+        let api = export_leveldb_api(119, vec![]).await;
+        api_transfer_subject(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "leveldb")]
+    async fn test_leveldb_api_approvals_stream() {
+        let api = export_leveldb_api(120, vec![]).await;
+        api_approvals_stream(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "leveldb")]
+    async fn test_leveldb_api_list_requests() {
+        let api = export_leveldb_api(111, vec![]).await;
+        api_list_requests(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "leveldb")]
+    async fn test_leveldb_api_subject_quota() {
+        let api = export_leveldb_api(112, vec![]).await;
+        api_subject_quota(api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "leveldb")]
+    async fn test_leveldb_api_subscribe() {
+        let api = export_leveldb_api(113, vec![]).await;
+        api_subscribe(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "leveldb")]
+    async fn test_leveldb_api_signing_policy() {
+        let api = export_leveldb_api(114, vec![]).await;
+        api_signing_policy(api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "leveldb")]
+    async fn test_leveldb_api_usage() {
+        let api = export_leveldb_api(115, vec![]).await;
+        api_usage(&api);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "leveldb")]
+    async fn test_leveldb_api_subject_graph() {
+        let api = export_leveldb_api(116, vec![]).await;
+        api_subject_graph(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "leveldb")]
+    async fn test_leveldb_api_wait_state_change() {
+        let api = export_leveldb_api(117, vec![]).await;
+        api_wait_state_change(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "leveldb")]
+    async fn test_leveldb_api_peer_services() {
+        let api = export_leveldb_api(118, vec![]).await;
+        api_peer_services(&api);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "leveldb")]
+    async fn test_leveldb_api_peers() {
+        let api = export_leveldb_api(121, vec![]).await;
+        api_peers(&api);
+    }
+
     /// Sqlite Tests
     #[tokio::test]
     #[cfg(feature = "sqlite")]
     async fn test_sqlite_api_send_get_event_request() {
-        let api = export_sqlite_api(201, vec![]);
+        let api = export_sqlite_api(201, vec![]).await;
 
         create_event(&api, "", "governance", "wine").await;
     }
@@ -1078,7 +5331,7 @@ mod tests {
     #[tokio::test]
     #[cfg(feature = "sqlite")]
     async fn test_sqlite_api_approval_accept() {
-        let api = export_sqlite_api(202, vec![]);
+        let api = export_sqlite_api(202, vec![]).await;
 
         api_approval_accept(&api).await;
     }
@@ -1086,15 +5339,15 @@ mod tests {
     #[tokio::test]
     #[cfg(feature = "sqlite")]
     async fn test_sqlite_api_approval_rejected() {
-        let api = export_sqlite_api(203, vec![]);
+        let api = export_sqlite_api(203, vec![]).await;
         api_approval_rejected(&api).await;
     }
 
     #[tokio::test]
     #[cfg(feature = "sqlite")]
     async fn test_sqlite_api_preauthorize_subject() {
-        let api_node1 = export_sqlite_api(204, vec![]);
-        let peer_id_node1 = api_node1.api.peer_id().to_string();
+        let api_node1 = export_sqlite_api(204, vec![]).await;
+        let peer_id_node1 = api_node1.node_info().peer_id;
 
         let api_node2 = export_sqlite_api(
             205,
@@ -1102,28 +5355,376 @@ mod tests {
                 address: vec!["/ip4/127.0.0.1/tcp/50204".to_owned()],
                 peer_id: peer_id_node1,
             }],
-        );
+        )
+        .await;
         api_preauthorize_subject(&api_node1, &api_node2).await;
     }
 
     #[tokio::test]
     #[cfg(feature = "sqlite")]
     async fn test_sqlite_api_public_key() {
-        let api = export_sqlite_api(206, vec![]);
+        let api = export_sqlite_api(206, vec![]).await;
         api_public_key(&api).await;
     }
 
     #[tokio::test]
     #[cfg(feature = "sqlite")]
     async fn test_sqlite_api_events_subject() {
-        let api = export_sqlite_api(207, vec![]);
+        let api = export_sqlite_api(207, vec![]).await;
         api_check_event_events_of_subject(&api, 2).await;
     }
 
     #[tokio::test]
     #[cfg(feature = "sqlite")]
     async fn test_sqlite_api_validation_proof() {
-        let api = export_sqlite_api(208, vec![]);
+        let api = export_sqlite_api(208, vec![]).await;
         api_get_validation_proof(&api).await;
     }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_cancelled_call() {
+        let api = export_sqlite_api(209, vec![]).await;
+        api_cancelled_call(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_archive_subject() {
+        let api = export_sqlite_api(210, vec![]).await;
+        api_archive_subject(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_transfer_subject() {
+        let api = export_sqlite_api(232, vec![]).await;
+        api_transfer_subject(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_approvals_stream() {
+        let api = export_sqlite_api(234, vec![]).await;
+        api_approvals_stream(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_list_requests() {
+        let api = export_sqlite_api(211, vec![]).await;
+        api_list_requests(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_subject_quota() {
+        let api = export_sqlite_api(212, vec![]).await;
+        api_subject_quota(api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_subscribe() {
+        let api = export_sqlite_api(215, vec![]).await;
+        api_subscribe(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_signing_policy() {
+        let api = export_sqlite_api(218, vec![]).await;
+        api_signing_policy(api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_usage() {
+        let api = export_sqlite_api(221, vec![]).await;
+        api_usage(&api);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_subject_graph() {
+        let api = export_sqlite_api(222, vec![]).await;
+        api_subject_graph(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_wait_state_change() {
+        let api = export_sqlite_api(226, vec![]).await;
+        api_wait_state_change(&api).await;
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_peer_services() {
+        let api = export_sqlite_api(227, vec![]).await;
+        api_peer_services(&api);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_peers() {
+        let api = export_sqlite_api(237, vec![]).await;
+        api_peers(&api);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_api_idempotent_event_request() {
+        let api = export_sqlite_api(240, vec![]).await;
+        let governance_id = create_event(&api, "", "governance", "idempotent").await;
+        let request = |payload: Value| NodeSignedEventRequest {
+            request: NodeEventRequest::Fact(NodeFactRequest {
+                subject_id: governance_id.clone(),
+                payload,
+            }),
+            signature: None,
+            origin: None,
+        };
+
+        let first = api
+            .send_idempotent_event_request(request(json!({"attempt": 1})), "fact-1")
+            .await
+            .unwrap();
+        // A retry gets the first request instead of sending another one.
+        let retry = api
+            .send_idempotent_event_request(request(json!({"attempt": 1})), "fact-1")
+            .await
+            .unwrap();
+        assert_eq!(retry.request_id, first.request_id);
+
+        // Requests under different keys are sent concurrently, and the lock of each key is
+        // dropped once its request is sent.
+        let (second, third) = tokio::join!(
+            api.send_idempotent_event_request(request(json!({"attempt": 1})), "fact-2"),
+            api.send_idempotent_event_request(request(json!({"attempt": 1})), "fact-3"),
+        );
+        assert_ne!(second.unwrap().request_id, third.unwrap().request_id);
+        assert!(api.idempotency_locks.lock().unwrap().is_empty());
+
+        assert!(matches!(
+            api.send_idempotent_event_request(request(json!({"attempt": 2})), "fact-1")
+                .await,
+            Err(NodeError::Conflict(_))
+        ));
+        let long = "k".repeat(MAX_IDEMPOTENCY_KEY_LEN + 1);
+        assert!(matches!(
+            api.send_idempotent_event_request(request(json!({"attempt": 2})), &long)
+                .await,
+            Err(NodeError::InvalidParameter(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_api_expire_legacy_entries() {
+        let api = export_sqlite_api(241, vec![]).await;
+        let legacy = api.store.scope("quota");
+        legacy.put("signer:governance", &vec![1_u64, 2]).unwrap();
+        api.store
+            .scope("requests_order")
+            .put("order", &"request".to_owned())
+            .unwrap();
+
+        api.expire_legacy_entries();
+        assert!(legacy
+            .get::<Vec<u64>>("signer:governance")
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            api.quota_store()
+                .get::<Vec<u64>>("signer:governance")
+                .unwrap(),
+            Some(vec![1, 2])
+        );
+        assert!(api
+            .store
+            .scope("requests_order")
+            .entries::<String>()
+            .unwrap()
+            .is_empty());
+        // Nothing left to move the next time.
+        api.expire_legacy_entries();
+        assert_eq!(
+            api.quota_store()
+                .get::<Vec<u64>>("signer:governance")
+                .unwrap(),
+            Some(vec![1, 2])
+        );
+    }
+
+    #[test]
+    fn test_base_error() {
+        use kore_base::ApiError as BaseApiError;
+
+        let classify = |error| super::base_error("get_subject", error);
+        assert!(matches!(
+            classify(BaseApiError::NotFound("subject".to_owned())),
+            NodeError::NotFound(_)
+        ));
+        assert!(matches!(
+            classify(BaseApiError::InvalidParameters("sn".to_owned())),
+            NodeError::InvalidParameter(_)
+        ));
+        assert!(matches!(
+            classify(BaseApiError::NotEnoughPermissions("approver".to_owned())),
+            NodeError::Unauthorized(_)
+        ));
+        assert!(matches!(
+            classify(BaseApiError::VoteNotNeeded("JRequest".to_owned())),
+            NodeError::Conflict(_)
+        ));
+        let error = classify(BaseApiError::DatabaseError("closed".to_owned()));
+        let NodeError::Internal { context, .. } = &error else {
+            panic!("not an internal error");
+        };
+        assert_eq!(context, "get_subject");
+        // The cause is kept for the callers.
+        assert!(std::error::Error::source(&error)
+            .unwrap()
+            .to_string()
+            .contains("closed"));
+    }
+
+    #[test]
+    fn test_page() {
+        let cursor = |n: &u64| n.to_string();
+        assert_eq!(Page::<u64>::read_quantity(Some(2)), Some(3));
+        assert_eq!(Page::<u64>::read_quantity(None), None);
+
+        let page = Page::cut(vec![1, 2, 3], true, Some(2), cursor);
+        assert_eq!(page.items, vec![1, 2]);
+        assert_eq!(page.next_cursor, Some("2".to_owned()));
+        assert_eq!(page.total, None);
+
+        let mut page = Page::cut(vec![1, 2], true, Some(2), cursor);
+        assert_eq!(page.next_cursor, None);
+        assert_eq!(page.total, Some(2));
+        page.retain(|n| *n > 1);
+        assert_eq!(page.total, Some(1));
+
+        // Only the first page knows the total.
+        let page = Page::cut(vec![3], false, Some(2), cursor);
+        assert_eq!(page.next_cursor, None);
+        assert_eq!(page.total, None);
+        let page = Page::cut(vec![1, 2, 3], true, None, cursor);
+        assert_eq!(page.total, Some(3));
+    }
+
+    #[test]
+    fn test_transfer_owners() {
+        let subject_id = "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE";
+        let event = |sn: u64, mut request: serde_json::Value| {
+            let signature = serde_json::json!({
+                "signer": "EnyisBz0lX9sRvvV0H-BXTrVtARjUa0YDHzaxFHWH-N4",
+                "timestamp": 1,
+                "value": "SEYml_XhryHvxRylu023oyR0nIjlwVCyw2ZC_Tgvf9gH5ChnCqG9cSDB3Fo3a6jBIhO7Cxg9DDeIZn1Ej-VNXRCg",
+                "content_hash": subject_id,
+            });
+            request["signature"] = signature.clone();
+            serde_json::from_value::<NodeSigned<EventContentResponse>>(serde_json::json!({
+                "subject_id": subject_id,
+                "event_request": request,
+                "gov_version": 0,
+                "sn": sn,
+                "patch": [],
+                "state_hash": subject_id,
+                "eval_success": true,
+                "appr_required": false,
+                "approved": true,
+                "hash_prev_event": "",
+                "evaluators": [],
+                "approvers": [],
+                "signature": signature,
+            }))
+            .unwrap()
+        };
+        let new_owner = "E2ZY7GjU14U3m-iAqvhQM6kiG62uqLdBMBwv4J-4tzwI";
+        let events = vec![
+            event(
+                1,
+                serde_json::json!({ "Fact": { "subject_id": subject_id, "payload": {} } }),
+            ),
+            event(
+                2,
+                serde_json::json!({
+                    "Transfer": { "subject_id": subject_id, "public_key": new_owner },
+                }),
+            ),
+        ];
+        // The new owner, not the previous one that signed the request.
+        assert_eq!(
+            super::transfer_owners(&events).collect::<Vec<_>>(),
+            vec![(2, new_owner.to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_check_signing_policies() {
+        let policies = vec![
+            SigningPolicy {
+                request_types: vec!["EOL".to_owned()],
+                signers: vec!["EAdmin".to_owned()],
+                external_signer: false,
+            },
+            SigningPolicy {
+                request_types: vec!["Transfer".to_owned()],
+                signers: vec![],
+                external_signer: true,
+            },
+        ];
+        let check = |request_type, signer, external| {
+            super::check_signing_policies(&policies, request_type, signer, external)
+        };
+        assert!(check("Fact", "ENode", false).is_ok());
+        assert!(check("EOL", "EAdmin", true).is_ok());
+        assert!(matches!(
+            check("EOL", "ENode", false),
+            Err(NodeError::Unauthorized(_))
+        ));
+        assert!(check("Transfer", "EWallet", true).is_ok());
+        assert!(check("Transfer", "ENode", false).is_err());
+    }
+
+    #[test]
+    fn test_transient_outcomes() {
+        use super::CallOutcome;
+        use kore_base::ApiError as BaseApiError;
+
+        let outcome = |error: BaseApiError| Err::<(), _>(error).transient();
+        assert!(outcome(BaseApiError::DatabaseError("busy".to_owned())));
+        assert!(outcome(BaseApiError::InternalError(
+            "channel closed".to_owned()
+        )));
+        assert!(!outcome(BaseApiError::NotFound("subject".to_owned())));
+        assert!(!outcome(BaseApiError::InvalidParameters("sn".to_owned())));
+        assert!(!Ok::<(), BaseApiError>(()).transient());
+    }
+
+    #[test]
+    fn test_check_signature_time() {
+        let check = SignatureCheck {
+            enabled: true,
+            max_age: Duration::from_secs(60),
+            max_future: Duration::from_secs(5),
+        };
+        let second = 1_000_000_000;
+        let now = 1_714_644_000 * second;
+        assert!(super::check_signature_time(&check, now, now).is_ok());
+        assert!(super::check_signature_time(&check, now - 60 * second, now).is_ok());
+        assert!(super::check_signature_time(&check, now + 5 * second, now).is_ok());
+        let Err(NodeError::InvalidSignature(stale)) =
+            super::check_signature_time(&check, now - 61 * second, now)
+        else {
+            panic!("the signature should be stale");
+        };
+        assert_eq!(stale, "signed 1m 1s ago, more than 1m");
+        assert!(matches!(
+            super::check_signature_time(&check, now + 6 * second, now),
+            Err(NodeError::InvalidSignature(_))
+        ));
+    }
 }
@@ -0,0 +1,748 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Archival of old events.
+//!
+//! Events are immutable and kept forever by Kore Base, so the database of an edge node grows
+//! with the history of its subjects. The archival moves the old events to an `ArchiveStore`: a
+//! directory, or a bucket of S3, MinIO, Google Cloud Storage or Azure with the `object-store`
+//! feature, and keeps a marker of each of them in the node database, freeing their space.
+//!
+//! An event is archived when it was signed more than `kore.archival.max_age` ago, or when the
+//! events in the database are over `kore.archival.max_size` bytes, the oldest first. The last
+//! event of a subject is never archived, as Kore Base reads it to extend the subject.
+//!
+//! Events are read from the collection of Kore Base, whose keys are
+//! `event<SEP><subject id><SEP><sn>`, and archived as they are stored there: the signed event
+//! encoded with Borsh, decrypted when the database is encrypted. Each event is uploaded to
+//! `<subject id>/<sn>` of the archive before its marker is written, and deleted from the
+//! database after, so an interrupted run leaves the event in the database, archived again by
+//! the next run.
+//!
+//! Kore Base reads the archived events through: the `LedgerManager` of the
+//! [database](../database/ledger/index.html) returns the event of a marker from the archive on
+//! `get`, and merges the markers into the entries of `iter`. Archived events are still sent to
+//! the nodes that sync the subjects, returned by `KoreApi::get_event_of_subject` and verified by
+//! `KoreApi::verify_event`, at the cost of fetching them from the archive.
+//!
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use borsh::{BorshDeserialize, BorshSerialize};
+use kore_base::{signature::Signed, Event};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    runtime::Handle,
+    sync::oneshot,
+    time::{interval_at, Instant, MissedTickBehavior},
+};
+
+use crate::{
+    database::{batch::BatchCollection, store::NodeStore},
+    error::NodeError,
+    model::NodeArchivalReport,
+    settings::ArchivalSettings,
+    tasks::NodeTasks,
+    KoreApi,
+};
+
+/// Collection of the events in Kore Base, also the first part of their keys.
+pub(crate) const EVENT_COLLECTION: &str = "event";
+
+/// Scope of the node store with the markers of the archived events.
+pub(crate) const ARCHIVAL_SCOPE: &str = "archival";
+
+/// Separator of the parts of the keys of Kore Base.
+pub(crate) const SEPARATOR: char = char::MAX;
+
+/// Identity of the calls of the scheduled archival, in the access logs.
+const ARCHIVAL_SOURCE: &str = "archival";
+
+/// Storage of the archived events.
+#[async_trait]
+pub trait ArchiveStore: Send + Sync {
+    /// Write an object, replacing it if it exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the object, `/` separated.
+    /// * `data` - Content of the object.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Archive` - The object could not be written.
+    ///
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), NodeError>;
+
+    /// Read an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the object, `/` separated.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::NotFound` - The object does not exist.
+    /// * `NodeError::Archive` - The object could not be read.
+    ///
+    async fn get(&self, key: &str) -> Result<Vec<u8>, NodeError>;
+}
+
+/// Archive in a local directory, e.g. a network share, with a file per object.
+pub struct DirectoryArchive {
+    root: PathBuf,
+}
+
+impl DirectoryArchive {
+    /// Archive in `root`, created on the first write.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+#[async_trait]
+impl ArchiveStore for DirectoryArchive {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), NodeError> {
+        let path = self.root.join(key);
+        tokio::task::spawn_blocking(move || {
+            let error = |error: std::io::Error| {
+                NodeError::Archive(format!("{}: {}", path.display(), error))
+            };
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(error)?;
+            }
+            // Written aside and renamed, so that an object is never read half written.
+            let partial = path.with_extension("partial");
+            fs::write(&partial, data).map_err(error)?;
+            fs::rename(&partial, &path).map_err(error)
+        })
+        .await
+        .map_err(|error| NodeError::InternalApi(error.to_string()))?
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, NodeError> {
+        let path = self.root.join(key);
+        tokio::task::spawn_blocking(move || {
+            fs::read(&path).map_err(|error| match error.kind() {
+                std::io::ErrorKind::NotFound => {
+                    NodeError::NotFound(format!("archived object {}", path.display()))
+                }
+                _ => NodeError::Archive(format!("{}: {}", path.display(), error)),
+            })
+        })
+        .await
+        .map_err(|error| NodeError::InternalApi(error.to_string()))?
+    }
+}
+
+/// Archive in a bucket of object storage, under the path of its URL.
+#[cfg(feature = "object-store")]
+pub struct ObjectStoreArchive {
+    store: Box<dyn object_store::ObjectStore>,
+    path: object_store::path::Path,
+    destination: String,
+}
+
+#[cfg(feature = "object-store")]
+impl ObjectStoreArchive {
+    /// Archive at a URL such as `s3://bucket/path`, with the credentials and the options of the
+    /// store read from the environment, e.g. `AWS_ACCESS_KEY_ID` or `AWS_ENDPOINT` for MinIO.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Archive` - The URL is not valid or its scheme is not supported.
+    ///
+    pub fn new(destination: &str) -> Result<Self, NodeError> {
+        let error = |error: &dyn std::fmt::Display| {
+            NodeError::Archive(format!("{}: {}", destination, error))
+        };
+        let url = url::Url::parse(destination).map_err(|e| error(&e))?;
+        let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+        let (store, path) = object_store::parse_url_opts(&url, options).map_err(|e| error(&e))?;
+        Ok(Self {
+            store,
+            path,
+            destination: destination.trim_end_matches('/').to_owned(),
+        })
+    }
+
+    /// Path of an object in the bucket.
+    fn object(&self, key: &str) -> object_store::path::Path {
+        key.split('/')
+            .fold(self.path.clone(), |path, part| path.child(part))
+    }
+}
+
+#[cfg(feature = "object-store")]
+#[async_trait]
+impl ArchiveStore for ObjectStoreArchive {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), NodeError> {
+        self.store
+            .put(&self.object(key), data.into())
+            .await
+            .map(|_| ())
+            .map_err(|error| NodeError::Archive(format!("{}/{}: {}", self.destination, key, error)))
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, NodeError> {
+        let error = |error: object_store::Error| match error {
+            object_store::Error::NotFound { .. } => {
+                NodeError::NotFound(format!("archived object {}/{}", self.destination, key))
+            }
+            error => NodeError::Archive(format!("{}/{}: {}", self.destination, key, error)),
+        };
+        let object = self.store.get(&self.object(key)).await.map_err(error)?;
+        Ok(object.bytes().await.map_err(error)?.to_vec())
+    }
+}
+
+/// Open the archive of `kore.archival.destination`: object storage for a URL, a directory
+/// otherwise.
+///
+/// # Arguments
+///
+/// * `destination` - Directory or URL of the archive.
+///
+/// # Errors
+///
+/// * `NodeError::Archive` - The URL is not valid, or the node was built without the
+///   `object-store` feature.
+///
+pub fn open_archive(destination: &str) -> Result<Arc<dyn ArchiveStore>, NodeError> {
+    if !destination.contains("://") {
+        return Ok(Arc::new(DirectoryArchive::new(destination)));
+    }
+    #[cfg(feature = "object-store")]
+    {
+        Ok(Arc::new(ObjectStoreArchive::new(destination)?))
+    }
+    #[cfg(not(feature = "object-store"))]
+    Err(NodeError::Archive(format!(
+        "{}: object storage requires the object-store feature",
+        destination
+    )))
+}
+
+/// Marker of an archived event, kept in the node store in place of the event.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ArchiveMarker {
+    /// Key of the event in the archive.
+    object: String,
+    /// Bytes of the key and the value the event had in the database.
+    size: u64,
+}
+
+/// Key of the marker of the event stored at `key` by Kore Base: the key in hexadecimal, which
+/// keeps the order of the keys and their prefixes without the separators of the node store.
+fn marker_key(key: &str) -> String {
+    key.bytes().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Key of the event of a marker key, `None` if it is not one.
+fn event_key(marker_key: &str) -> Option<String> {
+    let bytes = (0..marker_key.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(marker_key.get(index..index + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// Reads the archived events for the collections of Kore Base.
+/// The reads of a collection are synchronous, so the archive is driven by a runtime of its own,
+/// on a thread that lives as long as the reader: a read blocks the caller until the object is
+/// fetched, without waiting on the runtime of the node the caller may be running on.
+#[derive(Clone)]
+pub(crate) struct ArchiveReader {
+    archive: Arc<dyn ArchiveStore>,
+    markers: NodeStore,
+    runtime: Handle,
+    /// Stops the thread of the runtime once every clone of the reader is dropped.
+    _shutdown: Arc<oneshot::Sender<()>>,
+}
+
+impl ArchiveReader {
+    /// Reader of `archive`, with the markers of the archived events in `markers`.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Archive` - The runtime of the archive could not be started.
+    ///
+    pub fn new(archive: Arc<dyn ArchiveStore>, markers: NodeStore) -> Result<Self, NodeError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|error| NodeError::Archive(format!("archive runtime: {}", error)))?;
+        let handle = runtime.handle().clone();
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        thread::Builder::new()
+            .name("kore-archive".to_owned())
+            .spawn(move || {
+                let _ = runtime.block_on(stopped);
+            })
+            .map_err(|error| NodeError::Archive(format!("archive runtime: {}", error)))?;
+        Ok(Self {
+            archive,
+            markers,
+            runtime: handle,
+            _shutdown: Arc::new(shutdown),
+        })
+    }
+
+    /// Upload an object on the runtime of the archive.
+    async fn put(&self, key: String, data: Vec<u8>) -> Result<(), NodeError> {
+        let archive = self.archive.clone();
+        self.runtime
+            .spawn(async move { archive.put(&key, data).await })
+            .await
+            .map_err(|error| NodeError::InternalApi(error.to_string()))?
+    }
+
+    /// Marker of the event stored at `key`, `None` when it is not archived.
+    pub fn marker(&self, key: &str) -> Result<Option<ArchiveMarker>, NodeError> {
+        self.markers.get(&marker_key(key))
+    }
+
+    /// Write the marker of the event stored at `key`.
+    fn mark(&self, key: &str, marker: &ArchiveMarker) -> Result<(), NodeError> {
+        self.markers.put(&marker_key(key), marker)
+    }
+
+    /// Delete the marker of the event stored at `key`, if any.
+    pub fn unmark(&self, key: &str) -> Result<(), NodeError> {
+        self.markers.del(&marker_key(key))
+    }
+
+    /// Markers of the events whose key starts with `prefix`, with the key of each event,
+    /// ordered like the keys of the collections.
+    pub fn markers<'a>(
+        &'a self,
+        prefix: &str,
+        reverse: bool,
+    ) -> impl Iterator<Item = (String, ArchiveMarker)> + 'a {
+        self.markers
+            .iter::<ArchiveMarker>(&marker_key(prefix), reverse)
+            .filter_map(|entry| match entry {
+                Ok((key, marker)) => Some((event_key(&key)?, marker)),
+                Err(error) => {
+                    log::warn!("Archive marker skipped: {}", error);
+                    None
+                }
+            })
+    }
+
+    /// Read an archived event as Kore Base stored it, blocking until it is fetched.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::NotFound` - The event is missing from the archive.
+    /// * `NodeError::Archive` - The event could not be read.
+    ///
+    pub fn read(&self, marker: &ArchiveMarker) -> Result<Vec<u8>, NodeError> {
+        let (archive, object) = (self.archive.clone(), marker.object.clone());
+        futures::executor::block_on(
+            self.runtime
+                .spawn(async move { archive.get(&object).await }),
+        )
+        .map_err(|error| NodeError::InternalApi(error.to_string()))?
+    }
+}
+
+/// Event found in the collection of Kore Base.
+#[derive(Debug, Clone, PartialEq)]
+struct LocalEvent {
+    /// Key in the collection.
+    key: String,
+    subject_id: String,
+    sn: u64,
+    /// Nanoseconds since UNIX epoch at which it was signed.
+    timestamp: u64,
+    /// Bytes of the key and the value.
+    size: u64,
+}
+
+/// Events to archive: those signed before `now - max_age`, then the oldest until the events
+/// left are within `max_size`, never the last event of a subject.
+///
+/// # Arguments
+///
+/// * `events` - Events in the database.
+/// * `settings` - Age and size limits.
+/// * `now` - Nanoseconds since UNIX epoch.
+///
+/// # Returns
+///
+/// * `Vec<LocalEvent>` - Events to archive, the oldest first.
+///
+fn select(mut events: Vec<LocalEvent>, settings: &ArchivalSettings, now: u64) -> Vec<LocalEvent> {
+    let mut last = BTreeMap::<String, u64>::new();
+    for event in &events {
+        let sn = last.entry(event.subject_id.clone()).or_default();
+        *sn = (*sn).max(event.sn);
+    }
+    let mut local_bytes: u64 = events.iter().map(|event| event.size).sum();
+    events.retain(|event| last.get(&event.subject_id) != Some(&event.sn));
+    events.sort_by(|a, b| {
+        (a.timestamp, &a.subject_id, a.sn).cmp(&(b.timestamp, &b.subject_id, b.sn))
+    });
+    let max_age = settings.max_age.as_nanos().min(u64::MAX.into()) as u64;
+    events
+        .into_iter()
+        .take_while(|event| {
+            let old = !settings.max_age.is_zero() && now.saturating_sub(event.timestamp) > max_age;
+            let over_budget = settings.max_size > 0 && local_bytes > settings.max_size;
+            if old || over_budget {
+                local_bytes -= event.size;
+            }
+            old || over_budget
+        })
+        .collect()
+}
+
+/// Key of an event in the archive.
+fn object_key(subject_id: &str, sn: u64) -> String {
+    format!("{}/{:020}", subject_id, sn)
+}
+
+/// Archive of the events of the node: the events in Kore Base, the archive and the markers.
+#[derive(Clone)]
+pub(crate) struct Archival {
+    reader: ArchiveReader,
+    events: Arc<dyn BatchCollection>,
+    settings: ArchivalSettings,
+}
+
+impl Archival {
+    /// Archival of the events of `events`, the collection of Kore Base, to `archive`.
+    ///
+    /// # Arguments
+    ///
+    /// * `archive` - Storage of the archived events.
+    /// * `events` - Collection `EVENT_COLLECTION` of the database, below the `LedgerManager`
+    ///   that reads the archived events through.
+    /// * `markers` - Store of the markers, `ARCHIVAL_SCOPE` of the node store.
+    /// * `settings` - Age and size limits.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Archive` - The runtime of the archive could not be started.
+    ///
+    pub fn new(
+        archive: Arc<dyn ArchiveStore>,
+        events: Arc<dyn BatchCollection>,
+        markers: NodeStore,
+        settings: ArchivalSettings,
+    ) -> Result<Self, NodeError> {
+        Ok(Self {
+            reader: ArchiveReader::new(archive, markers)?,
+            events,
+            settings,
+        })
+    }
+
+    /// Reader of the archived events, for the collections of Kore Base.
+    pub fn reader(&self) -> ArchiveReader {
+        self.reader.clone()
+    }
+
+    /// Every event in the database. Values that are not a signed event are skipped, and a read
+    /// error fails the whole listing instead of archiving from part of it.
+    fn local_events(&self) -> Result<Vec<LocalEvent>, NodeError> {
+        let prefix = format!("{}{}", EVENT_COLLECTION, SEPARATOR);
+        let mut events = Vec::new();
+        for entry in self.events.try_iter(false, &prefix) {
+            let (key, value) = entry?;
+            let Some(subject_id) = key.split(SEPARATOR).next().map(str::to_owned) else {
+                continue;
+            };
+            let Ok(event) = Signed::<Event>::try_from_slice(&value) else {
+                continue;
+            };
+            events.push(LocalEvent {
+                size: (prefix.len() + key.len() + value.len()) as u64,
+                key: format!("{}{}", prefix, key),
+                subject_id,
+                sn: event.content.sn,
+                timestamp: event.signature.timestamp.0,
+            });
+        }
+        Ok(events)
+    }
+
+    /// Move the events over the limits to the archive, see the module documentation.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Nanoseconds since UNIX epoch.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Archive` - An event could not be uploaded. The events archived before it
+    ///   remain archived.
+    /// * `NodeError::Database` - The events or the markers could not be read or written.
+    ///
+    pub async fn run(&self, now: u64) -> Result<NodeArchivalReport, NodeError> {
+        let archival = self.clone();
+        let local = tokio::task::spawn_blocking(move || archival.local_events())
+            .await
+            .map_err(|error| NodeError::InternalApi(error.to_string()))??;
+        let total: u64 = local.iter().map(|event| event.size).sum();
+        let mut report = NodeArchivalReport {
+            events: 0,
+            bytes: 0,
+            local_bytes: total,
+        };
+        for event in select(local, &self.settings, now) {
+            let value = match self.events.get(&event.key) {
+                Ok(value) => value,
+                // Deleted meanwhile, e.g. by another run.
+                Err(kore_base::DbError::EntryNotFound) => continue,
+                Err(error) => return Err(error.into()),
+            };
+            let object = object_key(&event.subject_id, event.sn);
+            self.reader.put(object.clone(), value).await?;
+            // Marked before the delete, so that the event is always read from one of them.
+            self.reader.mark(
+                &event.key,
+                &ArchiveMarker {
+                    object,
+                    size: event.size,
+                },
+            )?;
+            self.events.del(&event.key)?;
+            report.events += 1;
+            report.bytes += event.size;
+            report.local_bytes -= event.size;
+        }
+        Ok(report)
+    }
+}
+
+/// Nanoseconds since UNIX epoch, the unit of the timestamps of the signatures.
+pub(crate) fn timestamp_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// Archive the old events every `kore.archival.interval` until the node is cancelled.
+///
+/// # Arguments
+///
+/// * `api` - Kore API of the node, built with an archival.
+/// * `settings` - Interval of the runs.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_archival(api: KoreApi, settings: ArchivalSettings, tasks: &NodeTasks) {
+    let cancellation = tasks.token();
+    let api = api.background(ARCHIVAL_SOURCE);
+    tasks.spawn(async move {
+        let period = settings.interval.max(Duration::from_secs(1));
+        let mut interval = interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = cancellation.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            match api.archive_events().await {
+                Ok(report) if report.events > 0 => log::info!(
+                    "{} events archived, {} bytes, {} bytes not archived",
+                    report.events,
+                    report.bytes,
+                    report.local_bytes
+                ),
+                Ok(_) => {}
+                Err(error) => log::warn!("Archival failed: {}", error),
+            }
+        }
+    });
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+
+    use kore_base::{DatabaseCollection, DatabaseManager};
+
+    use super::*;
+    use crate::{
+        database::{ledger::LedgerManager, sqlite::SqliteManager},
+        model::{EventContentResponse, NodeSigned},
+    };
+
+    const SUBJECT: &str = "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE";
+
+    fn local(subject_id: &str, sn: u64, timestamp: u64, size: u64) -> LocalEvent {
+        LocalEvent {
+            key: format!("{}/{}", subject_id, sn),
+            subject_id: subject_id.to_owned(),
+            sn,
+            timestamp,
+            size,
+        }
+    }
+
+    fn event(sn: u64, timestamp: u64) -> Signed<Event> {
+        let signature = serde_json::json!({
+            "signer": "EnyisBz0lX9sRvvV0H-BXTrVtARjUa0YDHzaxFHWH-N4",
+            "timestamp": timestamp,
+            "value": "SEYml_XhryHvxRylu023oyR0nIjlwVCyw2ZC_Tgvf9gH5ChnCqG9cSDB3Fo3a6jBIhO7Cxg9DDeIZn1Ej-VNXRCg",
+            "content_hash": SUBJECT,
+        });
+        let event: NodeSigned<EventContentResponse> = serde_json::from_value(serde_json::json!({
+            "subject_id": SUBJECT,
+            "event_request": {
+                "Fact": {
+                    "subject_id": SUBJECT,
+                    "payload": { "temperature": sn },
+                },
+                "signature": signature,
+            },
+            "gov_version": 0,
+            "sn": sn,
+            "patch": [],
+            "state_hash": SUBJECT,
+            "eval_success": true,
+            "appr_required": false,
+            "approved": true,
+            "hash_prev_event": "",
+            "evaluators": [],
+            "approvers": [],
+            "signature": signature,
+        }))
+        .unwrap();
+        Signed {
+            content: Event::try_from(event.content).unwrap(),
+            signature: event.signature.try_into().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let events = vec![
+            local("J1", 0, 10, 100),
+            local("J1", 1, 20, 100),
+            local("J1", 2, 30, 100),
+            local("J2", 0, 15, 100),
+        ];
+        let selected = |settings: ArchivalSettings| {
+            select(events.clone(), &settings, 100)
+                .into_iter()
+                .map(|event| (event.subject_id, event.sn))
+                .collect::<Vec<_>>()
+        };
+        assert!(selected(ArchivalSettings::default()).is_empty());
+        // The last event of each subject stays, however old.
+        let old = ArchivalSettings {
+            max_age: Duration::from_nanos(50),
+            ..Default::default()
+        };
+        assert_eq!(
+            selected(old),
+            vec![("J1".to_owned(), 0), ("J1".to_owned(), 1)]
+        );
+        let budget = ArchivalSettings {
+            max_size: 250,
+            ..Default::default()
+        };
+        assert_eq!(
+            selected(budget),
+            vec![("J1".to_owned(), 0), ("J1".to_owned(), 1)]
+        );
+        let budget = ArchivalSettings {
+            max_size: 300,
+            ..Default::default()
+        };
+        assert_eq!(selected(budget), vec![("J1".to_owned(), 0)]);
+    }
+
+    #[test]
+    fn test_marker_key() {
+        let key = format!("event{}{}{}1", SEPARATOR, SUBJECT, SEPARATOR);
+        assert_eq!(event_key(&marker_key(&key)), Some(key.clone()));
+        assert!(marker_key(&key).starts_with(&marker_key("event")));
+        assert!(marker_key("event0") < marker_key(&key));
+        assert_eq!(event_key("e"), None);
+    }
+
+    #[tokio::test]
+    async fn test_archival() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let destination = tempdir.path().join("archive");
+        let database = tempdir.path().join("database.db");
+        let manager = SqliteManager::new(database.to_str().unwrap());
+        let events = Arc::new(manager.create_collection(EVENT_COLLECTION));
+        let key = |sn: u64| format!("event{}{}{}{}", SEPARATOR, SUBJECT, SEPARATOR, sn);
+        for sn in 0..3 {
+            let value = borsh::to_vec(&event(sn, sn * 1_000)).unwrap();
+            events.put(&key(sn), &value).unwrap();
+        }
+        let markers = NodeStore::new(Arc::new(manager.create_collection("node")), "node")
+            .scope(ARCHIVAL_SCOPE);
+        let archival = Archival::new(
+            open_archive(destination.to_str().unwrap()).unwrap(),
+            events.clone(),
+            markers,
+            ArchivalSettings {
+                destination: destination.to_str().unwrap().to_owned(),
+                max_age: Duration::from_nanos(1_500),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let report = archival.run(3_000).await.unwrap();
+        assert_eq!(report.events, 2);
+        assert!(report.bytes > 0 && report.local_bytes > 0);
+        assert!(events.get(&key(0)).is_err());
+        assert!(events.get(&key(1)).is_err());
+        assert!(events.get(&key(2)).is_ok());
+        assert!(destination
+            .join(SUBJECT)
+            .join(format!("{:020}", 0))
+            .is_file());
+        assert_eq!(archival.run(3_000).await.unwrap().events, 0);
+
+        // Kore Base reads the archived events through its collection.
+        let ledger = LedgerManager::new(manager, Some(archival.reader()));
+        let ledger_events = ledger.create_collection(EVENT_COLLECTION);
+        let archived =
+            Signed::<Event>::try_from_slice(&ledger_events.get(&key(1)).unwrap()).unwrap();
+        assert_eq!(archived.content.sn, 1);
+        let prefix = format!("event{}{}{}", SEPARATOR, SUBJECT, SEPARATOR);
+        let sns = |reverse: bool| {
+            ledger_events
+                .iter(reverse, &prefix)
+                .map(|(key, value)| {
+                    let event = Signed::<Event>::try_from_slice(&value).unwrap();
+                    (key, event.content.sn)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sns(false),
+            vec![
+                ("0".to_owned(), 0),
+                ("1".to_owned(), 1),
+                ("2".to_owned(), 2)
+            ]
+        );
+        assert_eq!(
+            sns(true).into_iter().map(|(_, sn)| sn).collect::<Vec<_>>(),
+            vec![2, 1, 0]
+        );
+        // Other collections do not read the archive.
+        assert!(ledger.create_collection("subject").get(&key(1)).is_err());
+
+        ledger_events.del(&key(1)).unwrap();
+        assert!(ledger_events.get(&key(1)).is_err());
+    }
+}
@@ -0,0 +1,474 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! JWT validation.
+//!
+//! A JWT is accepted when its signature matches a key of the JWK set at `kore.auth.jwks_url`, it
+//! is not expired nor used before its `nbf`, and its `iss` and `aud` claims match
+//! `kore.auth.issuer` and `kore.auth.audience` when they are set. RS256, RS384, RS512, ES256,
+//! ES384 and EdDSA (Ed25519) signatures are supported.
+//!
+//! The JWK set is fetched on the first JWT and kept for `JWKS_TTL`, then fetched again in the
+//! background while the JWTs are still checked against it. A JWT signed by a key it does not hold
+//! fetches it again, so that the issuer can rotate its keys. A set is fetched at most once per
+//! `JWKS_REFRESH`, even when the fetch fails, and a stale set is kept until a fetch succeeds.
+//!
+
+use std::{
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use reqwest::Client;
+use ring::signature::{
+    self, EcdsaVerificationAlgorithm, RsaParameters, RsaPublicKeyComponents, UnparsedPublicKey,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::sync::OwnedMutexGuard;
+
+use crate::{error::NodeError, settings::AuthSettings};
+
+/// Time a JWK set is kept.
+const JWKS_TTL: Duration = Duration::from_secs(300);
+
+/// Shortest time between two fetches of a JWK set.
+const JWKS_REFRESH: Duration = Duration::from_secs(30);
+
+/// Longest time to fetch a JWK set.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Clock skew allowed in the `exp` and `nbf` claims, in seconds.
+const LEEWAY_SECS: u64 = 60;
+
+/// Public key of a JWK set, RFC 7517.
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    alg: Option<String>,
+    #[serde(default)]
+    crv: String,
+    #[serde(default)]
+    n: String,
+    #[serde(default)]
+    e: String,
+    #[serde(default)]
+    x: String,
+    #[serde(default)]
+    y: String,
+}
+
+/// JWK set.
+#[derive(Debug, Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+/// Header of a JWT.
+#[derive(Debug, Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+/// Audience of a JWT, one or many.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Claims of a JWT checked by the node.
+#[derive(Debug, Deserialize)]
+struct Claims {
+    #[serde(default)]
+    sub: Option<String>,
+    #[serde(default)]
+    iss: Option<String>,
+    #[serde(default)]
+    aud: Option<Audience>,
+    #[serde(default)]
+    exp: Option<u64>,
+    #[serde(default)]
+    nbf: Option<u64>,
+}
+
+/// Parts of a JWT.
+struct Token<'a> {
+    header: Header,
+    claims: Claims,
+    /// Header and claims as signed.
+    message: &'a str,
+    signature: Vec<u8>,
+}
+
+/// Error of an invalid JWT.
+fn invalid(reason: &str) -> NodeError {
+    NodeError::Unauthenticated(format!("invalid JWT: {}", reason))
+}
+
+/// Decode a base64url part of a JWT.
+fn decode(part: &str) -> Result<Vec<u8>, NodeError> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| invalid("malformed encoding"))
+}
+
+/// Decode a JSON part of a JWT.
+fn decode_json<T: DeserializeOwned>(part: &str, name: &str) -> Result<T, NodeError> {
+    serde_json::from_slice(&decode(part)?).map_err(|_| invalid(&format!("malformed {}", name)))
+}
+
+impl<'a> Token<'a> {
+    /// Split and decode a compact JWT, without checking it.
+    fn parse(token: &'a str) -> Result<Self, NodeError> {
+        let (message, signature) = token.rsplit_once('.').ok_or_else(|| invalid("not a JWT"))?;
+        let (header, claims) = message
+            .split_once('.')
+            .ok_or_else(|| invalid("not a JWT"))?;
+        Ok(Self {
+            header: decode_json(header, "header")?,
+            claims: decode_json(claims, "claims")?,
+            message,
+            signature: decode(signature)?,
+        })
+    }
+}
+
+/// Key type of the JWKs that verify an algorithm, none when it is not supported.
+fn key_type(alg: &str) -> Option<&'static str> {
+    match alg {
+        "RS256" | "RS384" | "RS512" => Some("RSA"),
+        "ES256" | "ES384" => Some("EC"),
+        "EdDSA" => Some("OKP"),
+        _ => None,
+    }
+}
+
+/// Whether a key may have signed a JWT with `header`.
+fn candidate(jwk: &Jwk, header: &Header) -> bool {
+    key_type(&header.alg) == Some(jwk.kty.as_str())
+        && jwk.alg.as_ref().is_none_or(|alg| *alg == header.alg)
+        && header
+            .kid
+            .as_ref()
+            .is_none_or(|kid| jwk.kid.as_ref() == Some(kid))
+}
+
+/// Whether `signature` of `message` was made with the key of `jwk`.
+fn verify_signature(alg: &str, jwk: &Jwk, message: &[u8], signature: &[u8]) -> bool {
+    let decode = |value: &str| URL_SAFE_NO_PAD.decode(value).ok();
+    let rsa = |params: &'static RsaParameters| match (decode(&jwk.n), decode(&jwk.e)) {
+        (Some(n), Some(e)) => RsaPublicKeyComponents { n, e }
+            .verify(params, message, signature)
+            .is_ok(),
+        _ => false,
+    };
+    let ecdsa =
+        |algorithm: &'static EcdsaVerificationAlgorithm| match (decode(&jwk.x), decode(&jwk.y)) {
+            (Some(x), Some(y)) => {
+                let point = [&[0x04][..], &x, &y].concat();
+                UnparsedPublicKey::new(algorithm, point)
+                    .verify(message, signature)
+                    .is_ok()
+            }
+            _ => false,
+        };
+    match (alg, jwk.crv.as_str()) {
+        ("RS256", _) => rsa(&signature::RSA_PKCS1_2048_8192_SHA256),
+        ("RS384", _) => rsa(&signature::RSA_PKCS1_2048_8192_SHA384),
+        ("RS512", _) => rsa(&signature::RSA_PKCS1_2048_8192_SHA512),
+        ("ES256", "P-256") => ecdsa(&signature::ECDSA_P256_SHA256_FIXED),
+        ("ES384", "P-384") => ecdsa(&signature::ECDSA_P384_SHA384_FIXED),
+        ("EdDSA", "Ed25519") => decode(&jwk.x).is_some_and(|x| {
+            UnparsedPublicKey::new(&signature::ED25519, x)
+                .verify(message, signature)
+                .is_ok()
+        }),
+        _ => false,
+    }
+}
+
+/// Check the time, issuer and audience of a JWT.
+fn check_claims(claims: &Claims, settings: &AuthSettings, now: u64) -> Result<(), NodeError> {
+    let exp = claims.exp.ok_or_else(|| invalid("no expiry"))?;
+    if exp.saturating_add(LEEWAY_SECS) <= now {
+        return Err(invalid("expired"));
+    }
+    if claims
+        .nbf
+        .is_some_and(|nbf| nbf > now.saturating_add(LEEWAY_SECS))
+    {
+        return Err(invalid("not valid yet"));
+    }
+    if !settings.issuer.is_empty() && claims.iss.as_deref() != Some(settings.issuer.as_str()) {
+        return Err(invalid("unexpected issuer"));
+    }
+    let audience = &settings.audience;
+    let audience_matches = match &claims.aud {
+        _ if audience.is_empty() => true,
+        Some(Audience::One(aud)) => aud == audience,
+        Some(Audience::Many(auds)) => auds.contains(audience),
+        None => false,
+    };
+    if !audience_matches {
+        return Err(invalid("unexpected audience"));
+    }
+    Ok(())
+}
+
+/// Check a JWT against the keys of a JWK set.
+fn verify_token(
+    token: &Token,
+    keys: &[Jwk],
+    settings: &AuthSettings,
+    now: u64,
+) -> Result<(), NodeError> {
+    let signed = keys
+        .iter()
+        .filter(|jwk| candidate(jwk, &token.header))
+        .any(|jwk| {
+            verify_signature(
+                &token.header.alg,
+                jwk,
+                token.message.as_bytes(),
+                &token.signature,
+            )
+        });
+    if !signed {
+        return Err(invalid("bad signature"));
+    }
+    check_claims(&token.claims, settings, now)
+}
+
+/// Fetch the JWK set at `url`.
+async fn fetch(url: &str) -> Result<Vec<Jwk>, NodeError> {
+    let unavailable =
+        |error: String| NodeError::Unauthenticated(format!("JWK set unavailable: {}", error));
+    let client = Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(|error| unavailable(error.to_string()))?;
+    let body = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|error| unavailable(error.to_string()))?
+        .bytes()
+        .await
+        .map_err(|error| unavailable(error.to_string()))?;
+    let jwks: Jwks =
+        serde_json::from_slice(&body).map_err(|error| unavailable(error.to_string()))?;
+    Ok(jwks.keys)
+}
+
+/// Keys of the JWK set and the fetches made.
+#[derive(Default)]
+struct JwksState {
+    /// URL the keys were fetched from.
+    url: String,
+    keys: Arc<Vec<Jwk>>,
+    /// Last successful fetch of `url`, none before the first one.
+    fetched: Option<Instant>,
+    /// URL and end of the last fetch, successful or not.
+    attempted: Option<(String, Instant)>,
+}
+
+/// JWK set of the node, fetched when first needed. Fetches are made without holding the keys,
+/// so that JWTs are checked against the keys held while the set is fetched.
+#[derive(Default)]
+pub(super) struct JwksCache {
+    state: RwLock<JwksState>,
+    /// Held by the fetch in progress, so that the JWTs that wait for it do not fetch again.
+    fetching: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl JwksCache {
+    /// Read the state. It is replaced as a whole, so a poisoned lock still holds a valid one.
+    fn state(&self) -> RwLockReadGuard<'_, JwksState> {
+        self.state.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Fetch the JWK set at `url`, unless a fetch of it ended after `requested`, holding
+    /// `fetching` until the keys are stored.
+    async fn refresh(
+        &self,
+        url: &str,
+        requested: Instant,
+        _fetching: OwnedMutexGuard<()>,
+    ) -> Result<(), NodeError> {
+        let fetched_meanwhile = self
+            .state()
+            .attempted
+            .as_ref()
+            .is_some_and(|(attempted, at)| attempted == url && *at >= requested);
+        if fetched_meanwhile {
+            return Ok(());
+        }
+        let result = fetch(url).await;
+        let mut state = self.state.write().unwrap_or_else(PoisonError::into_inner);
+        // A failed fetch of the URL is not retried before `JWKS_REFRESH` either.
+        state.attempted = Some((url.to_owned(), Instant::now()));
+        let keys = result?;
+        url.clone_into(&mut state.url);
+        state.keys = Arc::new(keys);
+        state.fetched = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Check a JWT, fetching the JWK set when it is stale or misses the key of the JWT.
+    /// A stale set that holds the key is used while it is fetched again in the background, and
+    /// kept when the fetch fails.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Unauthenticated` - Invalid JWT, or JWK set unavailable.
+    ///
+    /// # Returns
+    ///
+    /// * `String` - Subject of the JWT, empty when it has none.
+    ///
+    pub(super) async fn verify(
+        self: &Arc<Self>,
+        token: &str,
+        settings: &AuthSettings,
+    ) -> Result<String, NodeError> {
+        let token = Token::parse(token)?;
+        let url = &settings.jwks_url;
+        let requested = Instant::now();
+        let (mut keys, stale, retry) = {
+            let state = self.state();
+            let current = state.url == *url;
+            let keys = if current {
+                state.keys.clone()
+            } else {
+                Arc::default()
+            };
+            let stale = !current
+                || state
+                    .fetched
+                    .is_none_or(|fetched| fetched.elapsed() >= JWKS_TTL);
+            let retry = state
+                .attempted
+                .as_ref()
+                .is_none_or(|(attempted, at)| attempted != url || at.elapsed() >= JWKS_REFRESH);
+            (keys, stale, retry)
+        };
+        let known = keys.iter().any(|jwk| candidate(jwk, &token.header));
+        if known && stale && retry {
+            // None when a fetch is already in progress.
+            if let Ok(fetching) = self.fetching.clone().try_lock_owned() {
+                let (cache, url) = (self.clone(), url.clone());
+                tokio::spawn(async move {
+                    if let Err(error) = cache.refresh(&url, requested, fetching).await {
+                        log::warn!("JWK set not refreshed: {}", error);
+                    }
+                });
+            }
+        } else if !known && retry {
+            let fetching = self.fetching.clone().lock_owned().await;
+            self.refresh(url, requested, fetching).await?;
+            let state = self.state();
+            if state.url == *url {
+                keys = state.keys.clone();
+            }
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        verify_token(&token, &keys, settings, now)?;
+        Ok(token.claims.sub.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn sign(
+        key_pair: &Ed25519KeyPair,
+        header: serde_json::Value,
+        claims: serde_json::Value,
+    ) -> String {
+        let encode = |value: serde_json::Value| URL_SAFE_NO_PAD.encode(value.to_string());
+        let message = format!("{}.{}", encode(header), encode(claims));
+        let signature = key_pair.sign(message.as_bytes());
+        format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature.as_ref()))
+    }
+
+    #[test]
+    fn test_verify_token() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let keys: Vec<Jwk> = serde_json::from_value(json!([{
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "kid": "node",
+            "x": URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref()),
+        }]))
+        .unwrap();
+        let settings = AuthSettings {
+            issuer: "https://issuer.example.com/".to_owned(),
+            audience: "kore-node".to_owned(),
+            jwks_url: "https://issuer.example.com/jwks.json".to_owned(),
+            ..Default::default()
+        };
+        let header = json!({ "alg": "EdDSA", "kid": "node" });
+        let claims = json!({
+            "iss": "https://issuer.example.com/",
+            "aud": ["kore-node", "other"],
+            "exp": NOW + 600,
+        });
+        let check = |token: &str| verify_token(&Token::parse(token)?, &keys, &settings, NOW);
+
+        assert!(check(&sign(&key_pair, header.clone(), claims.clone())).is_ok());
+
+        let mut tampered = sign(&key_pair, header.clone(), claims.clone());
+        tampered.replace_range(..1, "x");
+        assert!(check(&tampered).is_err());
+
+        let rejected = [
+            (json!({ "alg": "EdDSA", "kid": "other" }), claims.clone()),
+            (json!({ "alg": "none" }), claims.clone()),
+            (
+                header.clone(),
+                json!({ "iss": "https://issuer.example.com/", "aud": "kore-node" }),
+            ),
+            (
+                header.clone(),
+                json!({ "aud": "kore-node", "exp": NOW + 600 }),
+            ),
+            (
+                header.clone(),
+                json!({ "iss": "https://issuer.example.com/", "aud": "other", "exp": NOW + 600 }),
+            ),
+            (
+                header.clone(),
+                json!({ "iss": "https://issuer.example.com/", "aud": "kore-node", "exp": NOW - 3600 }),
+            ),
+        ];
+        for (header, claims) in rejected {
+            assert!(matches!(
+                check(&sign(&key_pair, header, claims)),
+                Err(NodeError::Unauthenticated(_))
+            ));
+        }
+        assert!(check("not-a-jwt").is_err());
+    }
+}
@@ -0,0 +1,338 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Authentication of the HTTP endpoints.
+//!
+//! The metrics server, the REST API and the gRPC API admit a request only when it carries one of
+//! the keys of `kore.auth.api_keys` in the `x-api-key` header or metadata, or, with the `jwt`
+//! feature, a JWT in `Authorization: Bearer <token>` signed by a key of the JWK set at
+//! `kore.auth.jwks_url`, see `jwt`. Other requests get `401 Unauthorized`. With no key and no
+//! JWK set every request is admitted, and `/health` of the metrics server is always open for load
+//! balancers.
+//!
+//! Calls of the gRPC API without valid credentials fail with `UNAUTHENTICATED`, see `GrpcAuth`.
+//!
+//! These credentials are checked before the tokens of the surfaces (`kore.api_auth`), which still
+//! apply to the REST and gRPC APIs. The `Principal` of the credentials is added to the extensions
+//! of the request, so that its usage is accounted to it: `key:<n>` for the n-th key of
+//! `kore.auth.api_keys`, and `jwt:<sub>` for the subject of a JWT.
+//!
+
+#[cfg(feature = "jwt")]
+mod jwt;
+
+use std::sync::{Arc, RwLock};
+
+#[cfg(any(feature = "prometheus", feature = "http-api"))]
+use axum::{
+    extract::{Request, State},
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+#[cfg(any(feature = "prometheus", feature = "http-api"))]
+use serde_json::json;
+
+#[cfg(feature = "grpc")]
+use tonic::{
+    body::BoxBody,
+    codegen::{http, BoxFuture, Context, Poll, Service},
+    server::NamedService,
+    Status,
+};
+
+use crate::{
+    error::NodeError, settings::AuthSettings, surface::same_token, utils::replace_setting,
+};
+
+/// HTTP header with the API key of a request.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Who the credentials of a request belong to, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal(pub String);
+
+/// Checks the credentials of the HTTP requests. Its clones share the settings and the JWK set.
+#[derive(Clone, Default)]
+pub struct Authenticator {
+    settings: Arc<RwLock<AuthSettings>>,
+    #[cfg(feature = "jwt")]
+    jwks: Arc<jwt::JwksCache>,
+}
+
+impl Authenticator {
+    /// Create an authenticator.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Accepted API keys and JWTs.
+    ///
+    pub fn new(settings: AuthSettings) -> Self {
+        Self {
+            settings: Arc::new(RwLock::new(settings)),
+            #[cfg(feature = "jwt")]
+            jwks: Arc::default(),
+        }
+    }
+
+    /// Replace the settings of this authenticator and its clones. A new JWK set URL is fetched
+    /// on the next JWT.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Accepted API keys and JWTs.
+    ///
+    pub fn set_settings(&self, settings: AuthSettings) {
+        replace_setting(&self.settings, settings);
+    }
+
+    /// Check the credentials of a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - Value of the `x-api-key` header, if any.
+    /// * `bearer` - Bearer token of the `Authorization` header, if any.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Unauthenticated` - Credentials missing, unknown, invalid or expired.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Principal>` - Principal of the credentials, none when no credentials are
+    ///   required.
+    ///
+    pub async fn authenticate(
+        &self,
+        api_key: Option<&str>,
+        bearer: Option<&str>,
+    ) -> Result<Option<Principal>, NodeError> {
+        // A poisoned lock must not leave the endpoints open.
+        let settings = self
+            .settings
+            .read()
+            .map(|settings| settings.clone())
+            .map_err(|_| NodeError::Unauthenticated("credentials cannot be checked".to_owned()))?;
+        if !settings.is_enabled() {
+            return Ok(None);
+        }
+        if let Some(api_key) = api_key {
+            return settings
+                .api_keys
+                .iter()
+                .position(|expected| same_token(api_key, expected))
+                .map(|index| Some(Principal(format!("key:{}", index + 1))))
+                .ok_or_else(|| NodeError::Unauthenticated("unknown API key".to_owned()));
+        }
+        match bearer {
+            Some(token) if !settings.jwks_url.is_empty() => {
+                let subject = self.verify_jwt(token, &settings).await?;
+                Ok(Some(Principal(format!("jwt:{}", subject))))
+            }
+            _ => Err(NodeError::Unauthenticated(
+                "an API key or a JWT is required".to_owned(),
+            )),
+        }
+    }
+
+    /// Subject of a valid JWT.
+    #[cfg(feature = "jwt")]
+    async fn verify_jwt(&self, token: &str, settings: &AuthSettings) -> Result<String, NodeError> {
+        self.jwks.verify(token, settings).await
+    }
+
+    #[cfg(not(feature = "jwt"))]
+    async fn verify_jwt(
+        &self,
+        _token: &str,
+        _settings: &AuthSettings,
+    ) -> Result<String, NodeError> {
+        Err(NodeError::Unauthenticated(
+            "JWTs are not supported in this build".to_owned(),
+        ))
+    }
+}
+
+/// Answer the requests without valid credentials with `401 Unauthorized`.
+///
+/// # Arguments
+///
+/// * `authenticator` - Checks the credentials, see `Authenticator::authenticate`.
+/// * `request` - Request served when its credentials are valid.
+/// * `next` - Rest of the routes.
+///
+#[cfg(any(feature = "prometheus", feature = "http-api"))]
+pub async fn require_credentials(
+    State(authenticator): State<Authenticator>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let headers = request.headers();
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+    };
+    let api_key = header(API_KEY_HEADER);
+    let bearer = header(AUTHORIZATION.as_str())
+        .and_then(|value| value.strip_prefix("Bearer ").map(str::to_owned));
+    match authenticator
+        .authenticate(api_key.as_deref(), bearer.as_deref())
+        .await
+    {
+        Ok(principal) => {
+            if let Some(principal) = principal {
+                request.extensions_mut().insert(principal);
+            }
+            next.run(request).await
+        }
+        Err(error) => (
+            StatusCode::UNAUTHORIZED,
+            [(WWW_AUTHENTICATE, "Bearer")],
+            Json(json!({ "error": error.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+/// Interceptor of the gRPC services, failing the calls without valid credentials with
+/// `UNAUTHENTICATED`, see `Authenticator::authenticate`. The credentials are checked before the
+/// call is decoded, and their principal is added to the extensions of the call.
+#[cfg(feature = "grpc")]
+#[derive(Clone)]
+pub struct GrpcAuth<S> {
+    inner: S,
+    authenticator: Authenticator,
+}
+
+#[cfg(feature = "grpc")]
+impl<S> GrpcAuth<S> {
+    /// Check the credentials of the calls of `inner` with `authenticator`.
+    pub fn new(inner: S, authenticator: Authenticator) -> Self {
+        Self {
+            inner,
+            authenticator,
+        }
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl<S, B> Service<http::Request<B>> for GrpcAuth<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<S::Response, S::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        // The service polled ready serves this call, its clone the next one.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let authenticator = self.authenticator.clone();
+        Box::pin(async move {
+            let header = |name: &str| {
+                request
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_owned)
+            };
+            let api_key = header(API_KEY_HEADER);
+            let bearer = header("authorization")
+                .and_then(|value| value.strip_prefix("Bearer ").map(str::to_owned));
+            match authenticator
+                .authenticate(api_key.as_deref(), bearer.as_deref())
+                .await
+            {
+                Ok(principal) => {
+                    if let Some(principal) = principal {
+                        request.extensions_mut().insert(principal);
+                    }
+                    inner.call(request).await
+                }
+                Err(error) => Ok(Status::unauthenticated(error.to_string()).into_http()),
+            }
+        })
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl<S: NamedService> NamedService for GrpcAuth<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_authenticate_api_keys() {
+        let authenticator = Authenticator::default();
+        assert_eq!(authenticator.authenticate(None, None).await.unwrap(), None);
+
+        authenticator.set_settings(AuthSettings {
+            api_keys: vec!["first-key".to_owned(), "second-key".to_owned()],
+            ..Default::default()
+        });
+        assert_eq!(
+            authenticator
+                .authenticate(Some("second-key"), None)
+                .await
+                .unwrap(),
+            Some(Principal("key:2".to_owned()))
+        );
+        for (api_key, bearer) in [
+            (None, None),
+            (Some("third-key"), None),
+            (None, Some("first-key")),
+        ] {
+            assert!(matches!(
+                authenticator.authenticate(api_key, bearer).await,
+                Err(NodeError::Unauthenticated(_))
+            ));
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    #[tokio::test]
+    async fn test_grpc_auth() {
+        use tower::{service_fn, ServiceExt};
+
+        let authenticator = Authenticator::new(AuthSettings {
+            api_keys: vec!["first-key".to_owned()],
+            ..Default::default()
+        });
+        let inner = service_fn(|request: http::Request<()>| async move {
+            assert_eq!(
+                request.extensions().get::<Principal>(),
+                Some(&Principal("key:1".to_owned()))
+            );
+            Ok::<_, std::convert::Infallible>(http::Response::new(tonic::body::empty_body()))
+        });
+        let service = GrpcAuth::new(inner, authenticator);
+
+        let request = http::Request::builder()
+            .header(API_KEY_HEADER, "first-key")
+            .body(())
+            .unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        assert!(response.headers().get("grpc-status").is_none());
+
+        // Unauthenticated.
+        let response = service.oneshot(http::Request::new(())).await.unwrap();
+        assert_eq!(response.headers().get("grpc-status").unwrap(), "16");
+    }
+}
@@ -0,0 +1,655 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Backups.
+//!
+//! A backup is a `tar.zst` archive of the local database (LevelDB, sled, redb or SQLite):
+//!
+//! | Entry | Content |
+//! |-------|---------|
+//! | `manifest.json` | `NodeBackupManifest`: schema version, backend, node identifiers and height |
+//! | `database/` | Copy of the database, read from a consistent snapshot while the node runs |
+//!
+//! Backups are written by `KoreApi::create_backup`, and every `kore.backup.interval` to
+//! `kore.backup.directory` when it is set. When the database is empty at startup, the node
+//! builders restore the newest backup of that directory with `restore_backup`.
+//!
+//! PostgreSQL databases are left to the tools of the server, such as `pg_dump`.
+//!
+
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[cfg(feature = "leveldb")]
+use std::sync::Arc;
+
+#[cfg(feature = "leveldb")]
+use leveldb::database::Database;
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
+#[cfg(feature = "leveldb")]
+use crate::database::leveldb::StringKey;
+use crate::{
+    api::timestamp_millis,
+    archival::EVENT_COLLECTION,
+    database::maintenance::DbMaintenance,
+    error::NodeError,
+    model::NodeBackupManifest,
+    settings::{BackupSettings, DbSettings},
+    tasks::NodeTasks,
+    KoreApi,
+};
+
+/// Layout version of the archives written by this node.
+pub const BACKUP_SCHEMA_VERSION: u32 = 1;
+
+/// Entry of the manifest.
+const MANIFEST: &str = "manifest.json";
+
+/// Directory of the database copy in the archive.
+const DATABASE_DIR: &str = "database";
+
+/// Prefix and extension of the scheduled backups.
+const BACKUP_PREFIX: &str = "backup-";
+const BACKUP_EXTENSION: &str = ".tar.zst";
+
+/// Identity of the calls of the scheduled backups, in the access logs.
+const BACKUP_SOURCE: &str = "backup";
+
+/// Database that the node can copy while it runs.
+#[derive(Clone)]
+pub(crate) enum BackupSource {
+    /// Open LevelDB database, copied from a snapshot.
+    #[cfg(feature = "leveldb")]
+    LevelDB(Arc<Database<StringKey>>),
+    /// Open sled database, copied entry by entry.
+    #[cfg(feature = "sled")]
+    Sled(sled::Db),
+    /// Open redb database, copied from a read transaction.
+    #[cfg(feature = "redb")]
+    Redb {
+        db: crate::database::redb::RedbDatabase,
+        path: String,
+    },
+    /// Path of the SQLite database file.
+    #[cfg(feature = "sqlite")]
+    Sqlite(String),
+}
+
+impl BackupSource {
+    /// Backend name of the manifest.
+    pub(crate) fn backend(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "leveldb")]
+            BackupSource::LevelDB(_) => "leveldb",
+            #[cfg(feature = "sled")]
+            BackupSource::Sled(_) => "sled",
+            #[cfg(feature = "redb")]
+            BackupSource::Redb { .. } => "redb",
+            #[cfg(feature = "sqlite")]
+            BackupSource::Sqlite(_) => "sqlite",
+        }
+    }
+
+    /// Copy the database into `dir`, which must not exist.
+    #[allow(unused_variables)]
+    fn snapshot(&self, dir: &Path) -> Result<(), NodeError> {
+        match *self {
+            #[cfg(feature = "leveldb")]
+            BackupSource::LevelDB(ref db) => crate::database::leveldb::snapshot(db, dir),
+            #[cfg(feature = "sled")]
+            BackupSource::Sled(ref db) => crate::database::sled::snapshot(db, dir),
+            #[cfg(feature = "redb")]
+            BackupSource::Redb { ref db, ref path } => {
+                fs::create_dir_all(dir).map_err(io_error)?;
+                crate::database::redb::snapshot(db, &dir.join(file_name(path)))
+            }
+            #[cfg(feature = "sqlite")]
+            BackupSource::Sqlite(ref path) => {
+                fs::create_dir_all(dir).map_err(io_error)?;
+                crate::database::sqlite::snapshot(path, &dir.join(file_name(path)))
+            }
+        }
+    }
+}
+
+/// Write a backup archive.
+/// The database is copied to a staging directory next to `path`, archived, and the archive
+/// replaces `path` once complete.
+///
+/// # Arguments
+///
+/// * `source` - Database to copy.
+/// * `manifest` - Manifest of the archive.
+/// * `path` - Archive to write.
+///
+/// # Errors
+///
+/// * `NodeError::Database` - The database could not be copied.
+/// * `NodeError::InternalApi` - The archive could not be written.
+///
+pub(crate) fn write_backup(
+    source: &BackupSource,
+    manifest: &NodeBackupManifest,
+    path: &Path,
+) -> Result<(), NodeError> {
+    let staging = sibling(path, "staging");
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging).map_err(io_error)?;
+    let result = source
+        .snapshot(&staging.join(DATABASE_DIR))
+        .and_then(|_| write_archive(&staging, manifest, path));
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+/// Write a backup of a local database without starting a node on it, e.g. from the command
+/// line. No node may be running on the database, whose lock is taken by the LevelDB, sled and
+/// redb backends.
+/// The height of the manifest is the number of events in the database; those moved out by the
+/// archival stay in the archive and are not part of the backup.
+///
+/// # Arguments
+///
+/// * `db` - Database to copy.
+/// * `node_id` - Controller identifier of the node key.
+/// * `peer_id` - Peer identifier of the node key.
+/// * `path` - Archive to write.
+///
+/// # Errors
+///
+/// * `NodeError::InvalidParameter` - The database is not local, see `restore_backup`.
+/// * `NodeError::NotFound` - The database has no data.
+/// * `NodeError::Database` - The database could not be opened, e.g. a node is using it, or
+///   copied.
+/// * `NodeError::InternalApi` - The archive could not be written.
+///
+/// # Returns
+///
+/// * `NodeBackupManifest` - Manifest of the backup.
+///
+pub fn backup_database(
+    db: &DbSettings,
+    node_id: &str,
+    peer_id: &str,
+    path: &Path,
+) -> Result<NodeBackupManifest, NodeError> {
+    let (backend, target) = local_database(db).ok_or_else(|| {
+        NodeError::InvalidParameter(
+            "Only LevelDB, sled, redb and SQLite databases are backed up".to_owned(),
+        )
+    })?;
+    if is_empty(db) {
+        return Err(NodeError::NotFound(format!(
+            "data in database {}",
+            target.display()
+        )));
+    }
+    let (source, maintenance) = open_database(db)?;
+    let (collections, _) = maintenance.stats()?;
+    let manifest = NodeBackupManifest {
+        schema_version: BACKUP_SCHEMA_VERSION,
+        backend: backend.to_owned(),
+        node_version: env!("CARGO_PKG_VERSION").to_owned(),
+        node_id: node_id.to_owned(),
+        peer_id: peer_id.to_owned(),
+        height: collections
+            .iter()
+            .find(|collection| collection.name == EVENT_COLLECTION)
+            .map_or(0, |collection| collection.keys),
+        created_at: timestamp_millis(),
+    };
+    write_backup(&source, &manifest, path)?;
+    Ok(manifest)
+}
+
+/// Open a local database to copy it, as the node builders do but without Kore Base.
+fn open_database(db: &DbSettings) -> Result<(BackupSource, DbMaintenance), NodeError> {
+    match db {
+        #[cfg(feature = "leveldb")]
+        DbSettings::LevelDB(path) => {
+            let db = crate::database::leveldb::try_open_db(Path::new(path))?;
+            let maintenance = DbMaintenance::LevelDB {
+                db: db.clone(),
+                path: path.clone(),
+            };
+            Ok((BackupSource::LevelDB(db), maintenance))
+        }
+        #[cfg(feature = "sled")]
+        DbSettings::Sled(path) => {
+            let db = crate::database::sled::open_db(Path::new(path))?;
+            let maintenance = DbMaintenance::Sled {
+                db: db.clone(),
+                path: path.clone(),
+            };
+            Ok((BackupSource::Sled(db), maintenance))
+        }
+        #[cfg(feature = "redb")]
+        DbSettings::Redb(path) => {
+            let db = crate::database::redb::open_db(Path::new(path))?;
+            let maintenance = DbMaintenance::Redb {
+                db: db.clone(),
+                path: path.clone(),
+            };
+            Ok((
+                BackupSource::Redb {
+                    db,
+                    path: path.clone(),
+                },
+                maintenance,
+            ))
+        }
+        #[cfg(feature = "sqlite")]
+        DbSettings::Sqlite(path) => Ok((
+            BackupSource::Sqlite(path.clone()),
+            DbMaintenance::Sqlite(path.clone()),
+        )),
+        #[cfg(feature = "postgres")]
+        DbSettings::Postgres { .. } => Err(NodeError::InvalidParameter(
+            "PostgreSQL databases are backed up with the tools of the server".to_owned(),
+        )),
+    }
+}
+
+/// Archive the staged database with its manifest.
+fn write_archive(
+    staging: &Path,
+    manifest: &NodeBackupManifest,
+    path: &Path,
+) -> Result<(), NodeError> {
+    let manifest = serde_json::to_vec_pretty(manifest)
+        .map_err(|error| NodeError::InternalApi(error.to_string()))?;
+    let partial = sibling(path, "partial");
+    let write = || -> std::io::Result<()> {
+        let encoder = zstd::Encoder::new(File::create(&partial)?, 0)?;
+        let mut archive = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(timestamp_millis() / 1000);
+        header.set_cksum();
+        archive.append_data(&mut header, MANIFEST, manifest.as_slice())?;
+        archive.append_dir_all(DATABASE_DIR, staging.join(DATABASE_DIR))?;
+        archive.into_inner()?.finish()?.sync_all()?;
+        fs::rename(&partial, path)
+    };
+    write().map_err(|error| {
+        let _ = fs::remove_file(&partial);
+        NodeError::InternalApi(format!("Error writing backup: {}", error))
+    })
+}
+
+/// Restore a backup into the database of the settings, which must be empty.
+///
+/// # Arguments
+///
+/// * `path` - Backup archive.
+/// * `db` - Database to restore.
+///
+/// # Errors
+///
+/// * `NodeError::InvalidParameter` - The archive is not a backup of this backend, or it was
+///   written by a newer node.
+/// * `NodeError::Conflict` - The database is not empty.
+/// * `NodeError::InternalApi` - The archive could not be read or extracted.
+///
+/// # Returns
+///
+/// * `NodeBackupManifest` - Manifest of the restored backup.
+///
+pub fn restore_backup(path: &Path, db: &DbSettings) -> Result<NodeBackupManifest, NodeError> {
+    let (backend, target) = local_database(db).ok_or_else(|| {
+        NodeError::InvalidParameter(
+            "Only LevelDB, sled, redb and SQLite databases are restored".to_owned(),
+        )
+    })?;
+    if !is_empty(db) {
+        return Err(NodeError::Conflict(format!(
+            "Database {} is not empty",
+            target.display()
+        )));
+    }
+    let staging = sibling(&target, "restore");
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging).map_err(io_error)?;
+    let result = extract(path, backend, &staging).and_then(|manifest| {
+        let copy = match db {
+            #[cfg(feature = "sqlite")]
+            DbSettings::Sqlite(path) => staging.join(DATABASE_DIR).join(file_name(path)),
+            #[cfg(feature = "redb")]
+            DbSettings::Redb(path) => staging.join(DATABASE_DIR).join(file_name(path)),
+            #[allow(unreachable_patterns)]
+            _ => staging.join(DATABASE_DIR),
+        };
+        if let Some(dir) = target.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        // An empty LevelDB or sled directory may be left by a previous start.
+        let _ = fs::remove_dir(&target);
+        fs::rename(copy, &target).map_err(io_error)?;
+        Ok(manifest)
+    });
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+/// Extract a backup of `backend` into `staging`, checking its manifest first.
+fn extract(path: &Path, backend: &str, staging: &Path) -> Result<NodeBackupManifest, NodeError> {
+    let invalid = |message: String| {
+        NodeError::InvalidParameter(format!(
+            "{} is not a valid backup: {}",
+            path.display(),
+            message
+        ))
+    };
+    let decoder = zstd::Decoder::new(File::open(path).map_err(io_error)?).map_err(io_error)?;
+    let mut archive = tar::Archive::new(decoder);
+    let mut manifest = None;
+    for entry in archive
+        .entries()
+        .map_err(|error| invalid(error.to_string()))?
+    {
+        let mut entry = entry.map_err(|error| invalid(error.to_string()))?;
+        let name = entry
+            .path()
+            .map_err(|error| invalid(error.to_string()))?
+            .into_owned();
+        if name == Path::new(MANIFEST) {
+            let mut content = String::new();
+            entry
+                .read_to_string(&mut content)
+                .map_err(|error| invalid(error.to_string()))?;
+            let read: NodeBackupManifest =
+                serde_json::from_str(&content).map_err(|error| invalid(error.to_string()))?;
+            if read.schema_version > BACKUP_SCHEMA_VERSION {
+                return Err(invalid(format!(
+                    "schema version {} is newer than {}",
+                    read.schema_version, BACKUP_SCHEMA_VERSION
+                )));
+            }
+            if read.backend != backend {
+                return Err(invalid(format!(
+                    "it holds a {} database, not {}",
+                    read.backend, backend
+                )));
+            }
+            manifest = Some(read);
+        } else if manifest.is_none() {
+            return Err(invalid(format!("{} before the manifest", name.display())));
+        } else if name.starts_with(DATABASE_DIR) {
+            entry.unpack_in(staging).map_err(io_error)?;
+        }
+    }
+    manifest.ok_or_else(|| invalid("no manifest".to_owned()))
+}
+
+/// Restore the newest backup of `kore.backup.directory` when the database is empty.
+///
+/// # Errors
+///
+/// * `NodeError` - The backup could not be restored, see `restore_backup`.
+///
+/// # Returns
+///
+/// * `Option<(PathBuf, NodeBackupManifest)>` - Restored backup, if any.
+///
+pub(crate) fn restore_newest(
+    settings: &BackupSettings,
+    db: &DbSettings,
+) -> Result<Option<(PathBuf, NodeBackupManifest)>, NodeError> {
+    if settings.directory.is_empty() || !is_empty(db) {
+        return Ok(None);
+    }
+    let Some(path) = backups(Path::new(&settings.directory)).pop() else {
+        return Ok(None);
+    };
+    let manifest = restore_backup(&path, db)?;
+    log::info!(
+        "Database restored from {}, taken by {} at height {}",
+        path.display(),
+        manifest.node_id,
+        manifest.height
+    );
+    Ok(Some((path, manifest)))
+}
+
+/// Write a backup to `kore.backup.directory` every `kore.backup.interval`, keeping the newest
+/// `kore.backup.keep`, until the node is cancelled.
+///
+/// # Arguments
+///
+/// * `api` - Kore API of the node.
+/// * `settings` - Directory, interval and retention of the backups.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_backups(api: KoreApi, settings: BackupSettings, tasks: &NodeTasks) {
+    let cancellation = tasks.token();
+    let api = api.background(BACKUP_SOURCE);
+    tasks.spawn(async move {
+        let period = settings.interval.max(Duration::from_secs(1));
+        let mut interval = interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let dir = PathBuf::from(&settings.directory);
+        loop {
+            tokio::select! {
+                _ = cancellation.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            if let Err(error) = fs::create_dir_all(&dir) {
+                log::warn!("Backup skipped: {}", error);
+                continue;
+            }
+            let path = dir.join(format!(
+                "{}{:013}{}",
+                BACKUP_PREFIX,
+                timestamp_millis(),
+                BACKUP_EXTENSION
+            ));
+            match api.create_backup(&path).await {
+                Ok(manifest) => {
+                    log::info!(
+                        "Backup {} written at height {}",
+                        path.display(),
+                        manifest.height
+                    );
+                    prune(&dir, settings.keep);
+                }
+                Err(error) => log::warn!("Backup failed: {}", error),
+            }
+        }
+    });
+}
+
+/// Scheduled backups of `dir`, the oldest first.
+fn backups(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut backups = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let millis = name
+                .strip_prefix(BACKUP_PREFIX)?
+                .strip_suffix(BACKUP_EXTENSION)?
+                .parse::<u64>()
+                .ok()?;
+            Some((millis, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    backups.sort();
+    backups.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Remove the oldest scheduled backups of `dir`, keeping `keep`.
+fn prune(dir: &Path, keep: usize) {
+    let backups = backups(dir);
+    let excess = backups.len().saturating_sub(keep);
+    for path in backups.into_iter().take(excess) {
+        if let Err(error) = fs::remove_file(&path) {
+            log::warn!("Backup {} not removed: {}", path.display(), error);
+        }
+    }
+}
+
+/// Backend name and path of a local database.
+fn local_database(db: &DbSettings) -> Option<(&'static str, PathBuf)> {
+    match db {
+        #[cfg(feature = "leveldb")]
+        DbSettings::LevelDB(path) => Some(("leveldb", PathBuf::from(path))),
+        #[cfg(feature = "sled")]
+        DbSettings::Sled(path) => Some(("sled", PathBuf::from(path))),
+        #[cfg(feature = "redb")]
+        DbSettings::Redb(path) => Some(("redb", PathBuf::from(path))),
+        #[cfg(feature = "sqlite")]
+        DbSettings::Sqlite(path) => Some(("sqlite", PathBuf::from(path))),
+        #[cfg(feature = "postgres")]
+        DbSettings::Postgres { .. } => None,
+    }
+}
+
+/// Whether a local database has no data yet: a missing or empty directory (LevelDB and sled),
+/// a missing or empty file (SQLite), or a file without keys (redb).
+pub(crate) fn is_empty(db: &DbSettings) -> bool {
+    match db {
+        #[cfg(feature = "leveldb")]
+        DbSettings::LevelDB(path) => fs::read_dir(path)
+            .map(|mut entries| entries.next().is_none())
+            .unwrap_or(true),
+        #[cfg(feature = "sled")]
+        DbSettings::Sled(path) => fs::read_dir(path)
+            .map(|mut entries| entries.next().is_none())
+            .unwrap_or(true),
+        #[cfg(feature = "redb")]
+        DbSettings::Redb(path) => crate::database::redb::is_empty(Path::new(path)),
+        #[cfg(feature = "sqlite")]
+        DbSettings::Sqlite(path) => fs::metadata(path)
+            .map(|metadata| metadata.len() == 0)
+            .unwrap_or(true),
+        #[cfg(feature = "postgres")]
+        DbSettings::Postgres { .. } => false,
+    }
+}
+
+/// Path next to `path`, with a suffix.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path.display(), suffix))
+}
+
+/// File name of a database path.
+#[cfg(any(feature = "redb", feature = "sqlite"))]
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "database".to_owned())
+}
+
+fn io_error(error: std::io::Error) -> NodeError {
+    NodeError::InternalApi(format!("Backup I/O error: {}", error))
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+
+    use super::*;
+    use rusqlite::Connection;
+
+    fn manifest(backend: &str) -> NodeBackupManifest {
+        NodeBackupManifest {
+            schema_version: BACKUP_SCHEMA_VERSION,
+            backend: backend.to_owned(),
+            node_version: env!("CARGO_PKG_VERSION").to_owned(),
+            node_id: "EController".to_owned(),
+            peer_id: "12D3KooWPeer".to_owned(),
+            height: 3,
+            created_at: 1_700_000_000_000,
+        }
+    }
+
+    #[test]
+    fn test_sqlite_backup_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = dir.path().join("node/database");
+        fs::create_dir_all(database.parent().unwrap()).unwrap();
+        let conn = Connection::open(&database).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE node (id TEXT PRIMARY KEY, value BLOB NOT NULL);
+             INSERT INTO node VALUES ('a', x'01');",
+        )
+        .unwrap();
+
+        let source = BackupSource::Sqlite(database.to_str().unwrap().to_owned());
+        let path = dir.path().join("backup.tar.zst");
+        write_backup(&source, &manifest(source.backend()), &path).unwrap();
+        assert!(!sibling(&path, "staging").exists());
+        assert!(!sibling(&path, "partial").exists());
+
+        // The database in use is not overwritten.
+        let db = DbSettings::Sqlite(database.to_str().unwrap().to_owned());
+        assert!(matches!(
+            restore_backup(&path, &db),
+            Err(NodeError::Conflict(_))
+        ));
+
+        let restored = dir.path().join("restored/database");
+        let db = DbSettings::Sqlite(restored.to_str().unwrap().to_owned());
+        assert_eq!(restore_backup(&path, &db).unwrap(), manifest("sqlite"));
+        let value: Vec<u8> = Connection::open(&restored)
+            .unwrap()
+            .query_row("SELECT value FROM node WHERE id = 'a'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(value, vec![1]);
+    }
+
+    #[test]
+    fn test_restore_rejects_other_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = dir.path().join("database");
+        Connection::open(&database)
+            .unwrap()
+            .execute_batch("CREATE TABLE node (id TEXT PRIMARY KEY, value BLOB NOT NULL);")
+            .unwrap();
+        let source = BackupSource::Sqlite(database.to_str().unwrap().to_owned());
+        let path = dir.path().join("backup.tar.zst");
+        write_backup(&source, &manifest("leveldb"), &path).unwrap();
+
+        let db = DbSettings::Sqlite(dir.path().join("empty/database").display().to_string());
+        assert!(matches!(
+            restore_backup(&path, &db),
+            Err(NodeError::InvalidParameter(message)) if message.contains("leveldb")
+        ));
+    }
+
+    #[test]
+    fn test_prune_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        for millis in [3, 1, 2] {
+            fs::write(
+                dir.path().join(format!("backup-{:013}.tar.zst", millis)),
+                b"",
+            )
+            .unwrap();
+        }
+        fs::write(dir.path().join("notes.txt"), b"").unwrap();
+        prune(dir.path(), 2);
+        let names = backups(dir.path())
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "backup-0000000000002.tar.zst",
+                "backup-0000000000003.tar.zst"
+            ]
+        );
+        assert!(dir.path().join("notes.txt").exists());
+    }
+}
@@ -0,0 +1,22 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Kore node binary, see the `cli` module for its commands.
+
+use std::process::ExitCode;
+
+use kore_node::{
+    clap::Parser,
+    cli::{report, Cli},
+};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match Cli::parse().execute().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", report(&error));
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,351 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Boot node groups.
+//!
+//! `kore.network.bootstrap.groups` gathers boot nodes under a label, such as a region or a
+//! provider, listing the groups in failover order, the nearest first. A group is healthy while
+//! any of its boot nodes accepts TCP connections within `kore.network.bootstrap.probe_timeout`.
+//!
+//! When the node starts, the groups are probed in order and the first healthy one serves the
+//! bootstrap: Kore Base gets its boot nodes first, then those of the other groups in order, and
+//! then the boot nodes of `kore.network.routing`. Skipping a group is recorded in the node
+//! history. While the node runs, the health of every group is checked periodically; both the
+//! health and the group that served the bootstrap are exported in the metrics, and a group
+//! that becomes unreachable is reported as a `degraded` lifecycle event.
+//!
+
+use std::{collections::HashSet, io, net::SocketAddr, time::Duration};
+
+use futures::future::select_ok;
+use kore_base::{NetworkConfig, RoutingConfig, RoutingNode};
+use tokio::{
+    net::{lookup_host, TcpStream},
+    time::{interval, MissedTickBehavior},
+};
+
+use crate::{
+    lifecycle::{LifecycleEvent, LifecycleEvents},
+    metrics::NodeMetrics,
+    settings::{BootGroup, BootstrapSettings},
+    tasks::NodeTasks,
+};
+
+/// Group chosen to bootstrap the node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootGroupSelection {
+    /// Label of the group, none when no group was healthy.
+    pub label: Option<String>,
+    /// Labels of the groups skipped before it, unhealthy.
+    pub skipped: Vec<String>,
+}
+
+/// Probe the groups in order and put the boot nodes of the first healthy one first.
+/// When no group is healthy, the boot nodes are kept in the configured order, so that the
+/// network layer keeps retrying all of them.
+///
+/// # Arguments
+///
+/// * `network` - Network settings, whose boot nodes are rewritten.
+/// * `settings` - Groups of boot nodes and probe timeout.
+///
+/// # Returns
+///
+/// * `BootGroupSelection` - Group that serves the bootstrap and groups skipped.
+///
+pub async fn select_boot_group(
+    network: &mut NetworkConfig,
+    settings: &BootstrapSettings,
+) -> BootGroupSelection {
+    let mut skipped = vec![];
+    let mut selected = None;
+    if settings.groups.is_empty() {
+        return BootGroupSelection {
+            label: None,
+            skipped,
+        };
+    }
+    for (index, group) in settings.groups.iter().enumerate() {
+        if probe_group(group, settings.probe_timeout).await {
+            selected = Some(index);
+            break;
+        }
+        log::warn!("Boot node group {} unreachable", group.label);
+        skipped.push(group.label.clone());
+    }
+    if selected.is_none() {
+        log::warn!("No boot node group reachable, all of them are kept in order");
+        skipped.clear();
+    }
+
+    let boot_nodes = ordered_boot_nodes(&settings.groups, selected, network.routing.boot_nodes());
+    network.routing = with_boot_nodes(&network.routing, boot_nodes);
+
+    BootGroupSelection {
+        label: selected.map(|index| settings.groups[index].label.clone()),
+        skipped,
+    }
+}
+
+/// Whether any boot node of the group accepts TCP connections. Its addresses are probed at
+/// once, names resolved included.
+///
+/// # Arguments
+///
+/// * `group` - Group of boot nodes.
+/// * `timeout` - Time allowed to the probe of the group.
+///
+pub async fn probe_group(group: &BootGroup, timeout: Duration) -> bool {
+    let probes = group
+        .boot_nodes
+        .iter()
+        .flat_map(|node| node.address.iter())
+        .map(|address| Box::pin(probe_address(address)))
+        .collect::<Vec<_>>();
+    if probes.is_empty() {
+        return false;
+    }
+    let probed = tokio::time::timeout(timeout, select_ok(probes)).await;
+    matches!(probed, Ok(Ok(_)))
+}
+
+/// Connect to the sockets of a TCP multiaddress, until one accepts the connection.
+async fn probe_address(address: &str) -> io::Result<()> {
+    let mut error = io::Error::new(io::ErrorKind::InvalidInput, "not a TCP address");
+    for socket in tcp_sockets(address).await {
+        match TcpStream::connect(socket).await {
+            Ok(_) => return Ok(()),
+            Err(failed) => error = failed,
+        }
+    }
+    Err(error)
+}
+
+/// Check the health of the groups periodically, until the node is cancelled.
+///
+/// # Arguments
+///
+/// * `metrics` - Node metrics, which get the health of each group.
+/// * `lifecycle` - Lifecycle events, which get a `degraded` event when a group becomes
+///   unhealthy.
+/// * `settings` - Groups of boot nodes, probe timeout and interval.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_boot_group_health(
+    metrics: NodeMetrics,
+    lifecycle: LifecycleEvents,
+    settings: BootstrapSettings,
+    tasks: &NodeTasks,
+) {
+    let cancellation = tasks.token();
+    tasks.spawn(async move {
+        // Groups are taken as healthy until a check fails.
+        let mut unhealthy: HashSet<String> = HashSet::new();
+        let mut interval = interval(settings.health_interval.max(Duration::from_secs(1)));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = cancellation.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            for group in settings.groups.iter() {
+                let healthy = probe_group(group, settings.probe_timeout).await;
+                metrics.set_boot_group_health(&group.label, healthy);
+                if healthy {
+                    unhealthy.remove(&group.label);
+                } else if unhealthy.insert(group.label.clone()) {
+                    lifecycle.emit(LifecycleEvent::degraded(
+                        "bootstrap",
+                        format!("boot node group {} unreachable", group.label),
+                    ));
+                }
+            }
+        }
+    });
+}
+
+/// Boot nodes of the selected group, then those of the other groups in order and then the
+/// ungrouped ones, each boot node once.
+fn ordered_boot_nodes(
+    groups: &[BootGroup],
+    selected: Option<usize>,
+    ungrouped: Vec<RoutingNode>,
+) -> Vec<RoutingNode> {
+    let groups = selected.map(|index| &groups[index]).into_iter().chain(
+        groups
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| Some(*index) != selected)
+            .map(|(_, group)| group),
+    );
+    let mut boot_nodes: Vec<RoutingNode> = vec![];
+    for node in groups
+        .flat_map(|group| group.boot_nodes.iter().cloned())
+        .chain(ungrouped)
+    {
+        if !boot_nodes.contains(&node) {
+            boot_nodes.push(node);
+        }
+    }
+    boot_nodes
+}
+
+/// Routing settings with other boot nodes.
+pub(crate) fn with_boot_nodes(
+    routing: &RoutingConfig,
+    boot_nodes: Vec<RoutingNode>,
+) -> RoutingConfig {
+    RoutingConfig::new(boot_nodes)
+        .with_dht_random_walk(routing.get_dht_random_walk())
+        .with_discovery_limit(routing.get_discovery_limit())
+        .with_allow_non_globals_in_dht(routing.get_allow_non_globals_in_dht())
+        .with_allow_private_ip(routing.get_allow_private_ip())
+        .with_mdns(routing.get_mdns())
+        .with_kademlia_disjoint_query_paths(routing.get_kademlia_disjoint_query_paths())
+        .with_kademlia_replication_factor(
+            routing
+                .get_kademlia_replication_factor()
+                .map(usize::from)
+                .unwrap_or_default(),
+        )
+        .set_all_protocols(routing.get_protocol_names())
+}
+
+/// Sockets of a `/ip4`, `/ip6`, `/dns`, `/dns4` or `/dns6` TCP multiaddress, resolving names.
+async fn tcp_sockets(address: &str) -> Vec<SocketAddr> {
+    let parts = address.split('/').collect::<Vec<&str>>();
+    let (host, port) = match parts.as_slice() {
+        ["", "ip4" | "ip6" | "dns" | "dns4" | "dns6", host, "tcp", port, ..] => (*host, *port),
+        _ => return vec![],
+    };
+    let Ok(port) = port.parse::<u16>() else {
+        return vec![];
+    };
+    lookup_host((host, port))
+        .await
+        .map(Iterator::collect)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::net::TcpListener;
+
+    fn group(label: &str, port: u16) -> BootGroup {
+        BootGroup {
+            label: label.to_owned(),
+            boot_nodes: vec![RoutingNode {
+                peer_id: format!("12D3KooW{}", label),
+                address: vec![format!("/ip4/127.0.0.1/tcp/{}", port)],
+            }],
+        }
+    }
+
+    /// Port with nothing listening on it.
+    fn closed_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    #[tokio::test]
+    async fn test_tcp_sockets() {
+        assert_eq!(
+            tcp_sockets("/ip4/127.0.0.1/tcp/50000").await,
+            vec!["127.0.0.1:50000".parse::<SocketAddr>().unwrap()]
+        );
+        assert_eq!(
+            tcp_sockets("/ip6/::1/tcp/50000/p2p/peer").await,
+            vec!["[::1]:50000".parse::<SocketAddr>().unwrap()]
+        );
+        assert!(!tcp_sockets("/dns4/localhost/tcp/50000").await.is_empty());
+        assert!(tcp_sockets("/ip4/127.0.0.1/udp/50000/quic")
+            .await
+            .is_empty());
+        assert!(tcp_sockets("/ip4/127.0.0.1/tcp/port").await.is_empty());
+    }
+
+    #[test]
+    fn test_ordered_boot_nodes() {
+        let groups = vec![
+            group("eu-west", 1),
+            group("us-east", 2),
+            group("ap-south", 3),
+        ];
+        let peers = |nodes: Vec<RoutingNode>| {
+            nodes
+                .into_iter()
+                .map(|node| node.peer_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            peers(ordered_boot_nodes(
+                &groups,
+                Some(1),
+                vec![
+                    groups[0].boot_nodes[0].clone(),
+                    group("other", 4).boot_nodes[0].clone()
+                ],
+            )),
+            vec![
+                "12D3KooWus-east",
+                "12D3KooWeu-west",
+                "12D3KooWap-south",
+                "12D3KooWother"
+            ]
+        );
+        assert_eq!(
+            peers(ordered_boot_nodes(&groups, None, vec![])),
+            vec!["12D3KooWeu-west", "12D3KooWus-east", "12D3KooWap-south"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_select_boot_group() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let settings = BootstrapSettings {
+            groups: vec![
+                group("eu-west", closed_port()),
+                group("us-east", open),
+                group("ap-south", closed_port()),
+            ],
+            probe_timeout: Duration::from_millis(500),
+            ..Default::default()
+        };
+        assert!(!probe_group(&settings.groups[0], settings.probe_timeout).await);
+        assert!(probe_group(&settings.groups[1], settings.probe_timeout).await);
+        // Nothing answers on a non-routable address.
+        let silent = BootGroup {
+            label: "silent".to_owned(),
+            boot_nodes: vec![RoutingNode {
+                peer_id: "12D3KooWsilent".to_owned(),
+                address: vec!["/ip4/10.255.255.1/tcp/50000".to_owned()],
+            }],
+        };
+        let started = std::time::Instant::now();
+        assert!(!probe_group(&silent, Duration::from_millis(200)).await);
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let mut network = NetworkConfig::new(
+            kore_base::NodeType::Bootstrap,
+            vec![],
+            vec![],
+            vec![],
+            false,
+        );
+        let selection = select_boot_group(&mut network, &settings).await;
+        assert_eq!(selection.label.as_deref(), Some("us-east"));
+        assert_eq!(selection.skipped, vec!["eu-west".to_owned()]);
+
+        let unreachable = BootstrapSettings {
+            groups: vec![group("eu-west", closed_port())],
+            probe_timeout: Duration::from_millis(500),
+            ..Default::default()
+        };
+        let selection = select_boot_group(&mut network, &unreachable).await;
+        assert_eq!(selection.label, None);
+        assert!(selection.skipped.is_empty());
+    }
+}
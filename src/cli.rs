@@ -0,0 +1,675 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Command line.
+//!
+//! Subcommands of the `kore-node` binary. Every command reads the settings as the node does,
+//! with `ConfigSources` over the `KORE_*` environment variables, the files of `--file-path` and
+//! the `--set <key>=<value>` arguments, which override both. `--config-precedence` (or
+//! `KORE_CONFIG_PRECEDENCE`) chooses whether the files or the environment win between them. An
+//! invalid configuration fails the command before anything is touched.
+//!
+//! | Command | Does |
+//! |---------|------|
+//! | `run [--watch]` | Starts the node until Ctrl+C or SIGTERM, reloading the file with `--watch` |
+//! | `config check` | Validates the settings and reports every problem found |
+//! | `config init <file>` | Writes the settings as a file with every key described, see `render` |
+//! | `keys generate` | Creates the node key, refused when one exists |
+//! | `keys rotate` | Replaces the node key, kept as the next key version |
+//! | `keys show-id` | Prints the controller identifier of the node key |
+//! | `db migrate [--dry-run]` | Moves the data found in the legacy locations, see `migration` |
+//! | `db backup <archive>` | Writes a backup of the database without starting the node, see `backup` |
+//! | `db restore <archive>` | Restores a backup into the empty database of the settings |
+//! | `db reindex` | Starts the node on its database and rebuilds the indexes of the subjects |
+//!
+//! `db backup` and `db reindex` open the database, so the node must be stopped; a running node
+//! is backed up with `KoreApi::create_backup` and reindexed with `KoreApi::reindex_subjects`
+//! instead.
+//!
+
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+use kore_base::{keys::KeyMaterial, keys::KeyPair, Derivable, KeyIdentifier};
+
+use crate::{
+    backup::{backup_database, restore_backup},
+    config::{
+        build::{
+            build_config_precedence, build_file_path, build_password, ConfigPrecedence,
+            ConfigSources,
+        },
+        password::PasswordSource,
+        render::{write_settings, ConfigFormat},
+    },
+    error::NodeError,
+    migration::{find_legacy_data, migrate_legacy_data},
+    model::NodeBackupManifest,
+    node::{DatabaseNode, KoreNode, KoreNodeBuilder, Supervisor},
+    settings::{KeysBackend, KoreSettings},
+    utils::{node_key_pair, peer_id, rotate_node_key_pair},
+};
+
+/// Arguments of the `kore-node` binary.
+#[derive(Parser, Debug, Clone, PartialEq)]
+#[command(name = "kore-node", version, about = "Kore Ledger node", long_about = None)]
+pub struct Cli {
+    /// Configuration files (json, yaml or toml) or `conf.d` directories, merged in order and
+    /// separated by `:` (`;` on Windows), `KORE_FILE_PATH` when not set
+    #[arg(short, long, global = true, default_value_t = String::default())]
+    pub file_path: String,
+
+    /// Ignore the `KORE_*` environment variables, only the file is read
+    #[arg(long, global = true)]
+    pub no_env: bool,
+
+    /// Source that wins when the file and the environment set the same key,
+    /// `KORE_CONFIG_PRECEDENCE` when not set, else the file
+    #[arg(long, global = true, value_enum)]
+    pub config_precedence: Option<ConfigPrecedence>,
+
+    /// Setting that overrides the file and the environment, as `<key>=<value>` with a dotted
+    /// key, e.g. `kore.network.port_reuse=false`. It may be repeated
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE")]
+    pub settings: Vec<String>,
+
+    /// File with the password of the node key, `KORE_PASSWORD` or a prompt when not set. The
+    /// password is never an argument, which process listings and shell histories would show
+    #[arg(long, global = true)]
+    pub password_file: Option<PathBuf>,
+
+    /// Command to run
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// Commands of the `kore-node` binary.
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Start the node until Ctrl+C or SIGTERM
+    Run {
+        /// Reload the configuration file when it changes
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Configuration of the node
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Key pair of the node
+    #[command(subcommand)]
+    Keys(KeysCommand),
+    /// Database of the node
+    #[command(subcommand)]
+    Db(DbCommand),
+}
+
+/// Commands on the configuration.
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum ConfigCommand {
+    /// Validate the settings and report every problem found
+    Check,
+    /// Write the settings to a file, with a comment describing every key
+    Init {
+        /// File to write
+        path: PathBuf,
+        /// Format of the file, from its extension when not set
+        #[arg(long, value_enum)]
+        format: Option<ConfigFormat>,
+        /// Replace the file when it exists
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Commands on the node key.
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum KeysCommand {
+    /// Create the node key
+    Generate,
+    /// Replace the node key, the current one is kept as the next key version
+    Rotate,
+    /// Print the controller identifier of the node key
+    ShowId,
+}
+
+/// Commands on the database, which must not be in use by a running node.
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum DbCommand {
+    /// Move the data found in the legacy locations to the configured ones
+    Migrate {
+        /// List the data to move without moving it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write a backup of the database
+    Backup {
+        /// Archive to write (`tar.zst`)
+        path: PathBuf,
+    },
+    /// Restore a backup into the database, which must be empty
+    Restore {
+        /// Archive to restore (`tar.zst`)
+        path: PathBuf,
+    },
+    /// Rebuild the secondary indexes of the subjects
+    Reindex,
+}
+
+impl Cli {
+    /// Run the command, printing its result.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Config` - The settings are not valid.
+    /// * Any error of the command, see the functions of each module.
+    ///
+    pub async fn execute(self) -> Result<(), NodeError> {
+        let sources = self.sources()?;
+        let settings = sources.build()?;
+        match &self.command {
+            Command::Run { watch } => {
+                let watch = watch.then_some(sources);
+                run(settings, &self.password()?, watch).await
+            }
+            Command::Config(ConfigCommand::Check) => {
+                println!("{}", describe_source(sources.env, &sources.file));
+                Ok(())
+            }
+            Command::Config(ConfigCommand::Init {
+                path,
+                format,
+                force,
+            }) => {
+                init_config(&settings, path, *format, *force)?;
+                println!("Configuration written to {}", path.display());
+                Ok(())
+            }
+            Command::Keys(KeysCommand::Generate) => {
+                let controller_id = generate_key(&settings, &self.password()?)?;
+                println!("Node key generated, controller {}", controller_id);
+                Ok(())
+            }
+            Command::Keys(KeysCommand::Rotate) => {
+                let (previous, controller_id) = rotate_key(&settings, &self.password()?)?;
+                println!(
+                    "Node key rotated, controller {} replaced by {} from the next start",
+                    previous, controller_id
+                );
+                Ok(())
+            }
+            Command::Keys(KeysCommand::ShowId) => {
+                println!("{}", show_id(&settings, &self.password()?)?);
+                Ok(())
+            }
+            Command::Db(DbCommand::Migrate { dry_run }) => {
+                for line in migrate(settings, *dry_run)? {
+                    println!("{}", line);
+                }
+                Ok(())
+            }
+            Command::Db(DbCommand::Backup { path }) => {
+                let manifest = backup(&settings, &self.password()?, path)?;
+                println!(
+                    "Backup written to {}, {} events",
+                    path.display(),
+                    manifest.height
+                );
+                Ok(())
+            }
+            Command::Db(DbCommand::Restore { path }) => {
+                let manifest = restore_backup(path, &settings.db)?;
+                println!(
+                    "Backup of node {} restored, {} events",
+                    manifest.node_id, manifest.height
+                );
+                Ok(())
+            }
+            Command::Db(DbCommand::Reindex) => {
+                let indexed = reindex(settings, &self.password()?).await?;
+                println!("Subjects reindexed, {} subjects", indexed);
+                Ok(())
+            }
+        }
+    }
+
+    /// Configuration file, from `KORE_FILE_PATH` when the argument is not set.
+    fn file(&self) -> String {
+        if self.file_path.is_empty() {
+            build_file_path()
+        } else {
+            self.file_path.clone()
+        }
+    }
+
+    /// Sources of the settings given by the arguments, with the precedence of
+    /// `KORE_CONFIG_PRECEDENCE` when the argument is not set.
+    fn sources(&self) -> Result<ConfigSources, NodeError> {
+        let precedence = match self.config_precedence {
+            Some(precedence) => precedence,
+            None => build_config_precedence()?,
+        };
+        Ok(ConfigSources::new(!self.no_env, &self.file())
+            .with_precedence(precedence)
+            .with_args(self.settings.clone()))
+    }
+
+    /// Password of the node key, from the environment or the terminal when no file is set.
+    fn password(&self) -> Result<String, NodeError> {
+        match &self.password_file {
+            Some(path) => PasswordSource::File(path.clone()).read(),
+            None => build_password(),
+        }
+    }
+}
+
+/// Message of an error of a command, with a line per configuration problem.
+///
+/// # Arguments
+///
+/// * `error` - Error returned by `Cli::execute`.
+///
+pub fn report(error: &NodeError) -> String {
+    match error {
+        NodeError::Config(errors) => errors
+            .iter()
+            .map(|error| format!("Config error: {}", error))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        error => error.to_string(),
+    }
+}
+
+/// Start the node and wait until it is shut down, restarting it after a crash when
+/// `kore.supervisor` is enabled.
+/// `watch` holds the sources of the settings, reloaded when the file changes.
+async fn run(
+    settings: KoreSettings,
+    password: &str,
+    watch: Option<ConfigSources>,
+) -> Result<(), NodeError> {
+    let supervised = settings.supervisor.enabled;
+    let builder = KoreNodeBuilder::new(settings, password);
+    let on_start = |node: &DatabaseNode| {
+        if let Some(sources) = &watch {
+            let mut events = node.watch_config(sources.clone())?;
+            // The changes are already logged by the watcher.
+            tokio::spawn(async move { while events.recv().await.is_some() {} });
+        }
+        log::info!(
+            "Node started, controller {}",
+            node.api().get_controller_id()
+        );
+        Ok(())
+    };
+    if supervised {
+        return Supervisor::new(builder)
+            .run(shutdown_signal(), on_start)
+            .await;
+    }
+    let node = builder.build().await?;
+    on_start(&node)?;
+    node.bind_with_shutdown(shutdown_signal());
+    node.token().cancelled().await;
+    Ok(())
+}
+
+/// Ctrl+C, or SIGTERM as sent by service managers and container runtimes.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {},
+                    _ = terminate.recv() => {},
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Where valid settings were read from.
+fn describe_source(env: bool, file: &str) -> String {
+    match (env, file.is_empty()) {
+        (true, true) => "Settings of the environment are valid".to_owned(),
+        (true, false) => format!("Settings of the environment and {} are valid", file),
+        (false, true) => "Default settings are valid".to_owned(),
+        (false, false) => format!("Settings of {} are valid", file),
+    }
+}
+
+/// Write the commented settings to a file, refused when it exists unless `force`.
+fn init_config(
+    settings: &KoreSettings,
+    path: &Path,
+    format: Option<ConfigFormat>,
+    force: bool,
+) -> Result<(), NodeError> {
+    let Some(format) = format.or_else(|| ConfigFormat::from_path(path)) else {
+        return Err(NodeError::InvalidParameter(format!(
+            "no format for {}, use a toml, yaml or json extension or --format",
+            path.display()
+        )));
+    };
+    if !force && path.exists() {
+        return Err(NodeError::Conflict(format!(
+            "{} already exists, --force replaces it",
+            path.display()
+        )));
+    }
+    write_settings(settings, path, format, true)
+}
+
+/// Create the node key, refused when the key file exists.
+fn generate_key(settings: &KoreSettings, password: &str) -> Result<String, NodeError> {
+    if key_file_exists(settings) {
+        return Err(NodeError::Conflict(format!(
+            "the node key already exists in {}, `keys rotate` replaces it",
+            settings.keys_path
+        )));
+    }
+    let key_pair = node_key_pair(settings, password)?;
+    Ok(controller_id(settings, &key_pair))
+}
+
+/// Rotate the node key file, returning the previous and the new controller identifiers.
+fn rotate_key(settings: &KoreSettings, password: &str) -> Result<(String, String), NodeError> {
+    if settings.keys_backend != KeysBackend::File {
+        return Err(NodeError::InvalidParameter(
+            "only key files are rotated, see kore.keys_backend".to_owned(),
+        ));
+    }
+    if !key_file_exists(settings) {
+        return Err(NodeError::NotFound(format!(
+            "node key in {}, `keys generate` creates it",
+            settings.keys_path
+        )));
+    }
+    let previous = node_key_pair(settings, password)?;
+    let key_pair = rotate_node_key_pair(settings, password)?;
+    Ok((
+        controller_id(settings, &previous),
+        controller_id(settings, &key_pair),
+    ))
+}
+
+/// Controller identifier of the node key, which is not created when missing.
+fn show_id(settings: &KoreSettings, password: &str) -> Result<String, NodeError> {
+    let key_pair = existing_key_pair(settings, password)?;
+    Ok(controller_id(settings, &key_pair))
+}
+
+/// Node key, which is not created when missing.
+fn existing_key_pair(settings: &KoreSettings, password: &str) -> Result<KeyPair, NodeError> {
+    if settings.keys_backend == KeysBackend::File && !key_file_exists(settings) {
+        return Err(NodeError::NotFound(format!(
+            "node key in {}, `keys generate` creates it",
+            settings.keys_path
+        )));
+    }
+    node_key_pair(settings, password)
+}
+
+/// Move the legacy data, or only list it with `dry_run`.
+fn migrate(mut settings: KoreSettings, dry_run: bool) -> Result<Vec<String>, NodeError> {
+    let lines: Vec<String> = if dry_run {
+        find_legacy_data(Path::new(""), &settings)
+            .into_iter()
+            .map(|data| {
+                format!(
+                    "{} would be moved from {} to {}",
+                    data.kind,
+                    data.from.display(),
+                    data.to.display()
+                )
+            })
+            .collect()
+    } else {
+        // Running the command is the consent that the setting asks for on start.
+        settings.migrate_legacy_data = true;
+        migrate_legacy_data(Path::new(""), &settings)?
+            .into_iter()
+            .map(|(_, detail)| detail)
+            .collect()
+    };
+    if lines.is_empty() {
+        return Ok(vec!["No legacy data to migrate".to_owned()]);
+    }
+    Ok(lines)
+}
+
+/// Write a backup of the database, without starting the node on it.
+fn backup(
+    settings: &KoreSettings,
+    password: &str,
+    path: &Path,
+) -> Result<NodeBackupManifest, NodeError> {
+    let key_pair = existing_key_pair(settings, password)?;
+    backup_database(
+        &settings.db,
+        &controller_id(settings, &key_pair),
+        &peer_id(&key_pair)?,
+        path,
+    )
+}
+
+/// Start the node on its database, rebuild the indexes of the subjects and stop it.
+async fn reindex(settings: KoreSettings, password: &str) -> Result<usize, NodeError> {
+    let node = KoreNodeBuilder::new(settings, password).build().await?;
+    let result = node.api().reindex_subjects().await;
+    node.token().cancel();
+    result
+}
+
+/// Whether the settings keep the node key in a file that exists.
+fn key_file_exists(settings: &KoreSettings) -> bool {
+    settings.keys_backend == KeysBackend::File
+        && Path::new(&settings.keys_path)
+            .join("node_private.der")
+            .exists()
+}
+
+/// Controller identifier of a key pair.
+fn controller_id(settings: &KoreSettings, key_pair: &KeyPair) -> String {
+    KeyIdentifier::new(
+        settings.settings.node.key_derivator,
+        &key_pair.public_key_bytes(),
+    )
+    .to_str()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{error::ConfigError, utils::MIN_PBKDF2_ITERATIONS};
+
+    #[test]
+    fn test_parse_commands() {
+        let cli = Cli::try_parse_from(["kore-node", "keys", "show-id"]).unwrap();
+        assert_eq!(cli.command, Command::Keys(KeysCommand::ShowId));
+        assert_eq!(cli.password_file, None);
+        assert!(!cli.no_env);
+
+        let cli =
+            Cli::try_parse_from(["kore-node", "--no-env", "-f", "node.toml", "run", "--watch"])
+                .unwrap();
+        assert_eq!(cli.command, Command::Run { watch: true });
+        assert_eq!(cli.file_path, "node.toml");
+        assert!(cli.no_env);
+
+        let cli = Cli::try_parse_from([
+            "kore-node",
+            "config",
+            "check",
+            "--config-precedence",
+            "env",
+            "--set",
+            "kore.network.port_reuse=false",
+            "--set",
+            "kore.node.timeout=30",
+        ])
+        .unwrap();
+        assert_eq!(cli.config_precedence, Some(ConfigPrecedence::Env));
+        assert_eq!(
+            cli.settings,
+            vec!["kore.network.port_reuse=false", "kore.node.timeout=30"]
+        );
+
+        let cli = Cli::try_parse_from(["kore-node", "db", "reindex"]).unwrap();
+        assert_eq!(cli.command, Command::Db(DbCommand::Reindex));
+
+        let cli = Cli::try_parse_from(["kore-node", "db", "restore", "backup.tar.zst"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Db(DbCommand::Restore {
+                path: PathBuf::from("backup.tar.zst")
+            })
+        );
+
+        let cli = Cli::try_parse_from([
+            "kore-node",
+            "config",
+            "init",
+            "node.conf",
+            "--format",
+            "yaml",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Config(ConfigCommand::Init {
+                path: PathBuf::from("node.conf"),
+                format: Some(ConfigFormat::Yaml),
+                force: false,
+            })
+        );
+
+        let cli = Cli::try_parse_from([
+            "kore-node",
+            "keys",
+            "generate",
+            "--password-file",
+            "/run/secrets/kore",
+        ])
+        .unwrap();
+        assert_eq!(cli.password_file, Some(PathBuf::from("/run/secrets/kore")));
+
+        assert!(Cli::try_parse_from(["kore-node", "db", "backup"]).is_err());
+        assert!(Cli::try_parse_from(["kore-node", "keys", "delete"]).is_err());
+        // Passwords are not taken as arguments.
+        assert!(Cli::try_parse_from(["kore-node", "-p", "secret", "run"]).is_err());
+        assert!(Cli::try_parse_from(["kore-node", "--password", "secret", "run"]).is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        let error = NodeError::Config(vec![
+            ConfigError::new("kore.db.path", "must not be empty"),
+            ConfigError::new("KORE_NODE_*", "invalid digit"),
+        ]);
+        assert_eq!(
+            report(&error),
+            "Config error: kore.db.path: must not be empty\nConfig error: KORE_NODE_*: invalid digit"
+        );
+        assert_eq!(
+            report(&NodeError::Keys("wrong password".to_owned())),
+            "Keys error: wrong password"
+        );
+    }
+
+    #[test]
+    fn test_key_commands() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut settings = KoreSettings::default();
+        settings.keys.iterations = MIN_PBKDF2_ITERATIONS;
+        settings.keys_path = tempdir.path().join("keys").to_str().unwrap().to_owned();
+
+        assert!(matches!(
+            show_id(&settings, "password"),
+            Err(NodeError::NotFound(_))
+        ));
+        assert!(matches!(
+            rotate_key(&settings, "password"),
+            Err(NodeError::NotFound(_))
+        ));
+
+        let controller_id = generate_key(&settings, "password").unwrap();
+        assert_eq!(show_id(&settings, "password").unwrap(), controller_id);
+        assert!(matches!(
+            generate_key(&settings, "password"),
+            Err(NodeError::Conflict(_))
+        ));
+
+        assert!(matches!(
+            rotate_key(&settings, "wrong"),
+            Err(NodeError::Keys(_))
+        ));
+        let (previous, rotated) = rotate_key(&settings, "password").unwrap();
+        assert_eq!(previous, controller_id);
+        assert_ne!(rotated, controller_id);
+        assert_eq!(show_id(&settings, "password").unwrap(), rotated);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_backup_command() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut settings = KoreSettings::default();
+        settings.keys.iterations = MIN_PBKDF2_ITERATIONS;
+        settings.keys_path = tempdir.path().join("keys").to_str().unwrap().to_owned();
+        let database = tempdir.path().join("database");
+        settings.db = crate::settings::DbSettings::Sqlite(database.to_str().unwrap().to_owned());
+        let path = tempdir.path().join("backup.tar.zst");
+
+        assert!(matches!(
+            backup(&settings, "password", &path),
+            Err(NodeError::NotFound(_))
+        ));
+        let controller_id = generate_key(&settings, "password").unwrap();
+        assert!(matches!(
+            backup(&settings, "password", &path),
+            Err(NodeError::NotFound(_))
+        ));
+
+        rusqlite::Connection::open(&database)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE event (id TEXT PRIMARY KEY, value BLOB NOT NULL);
+                 INSERT INTO event VALUES ('a', x'01'), ('b', x'02');",
+            )
+            .unwrap();
+        let manifest = backup(&settings, "password", &path).unwrap();
+        assert_eq!(manifest.node_id, controller_id);
+        assert!(manifest.peer_id.starts_with("12D3KooW"));
+        assert_eq!(manifest.height, 2);
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_init_config() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let settings = KoreSettings::default();
+        let path = tempdir.path().join("node.toml");
+        init_config(&settings, &path, None, false).unwrap();
+        let template = std::fs::read_to_string(&path).unwrap();
+        assert!(template.contains("# Backups kept.\n"));
+
+        assert!(matches!(
+            init_config(&settings, &path, None, false),
+            Err(NodeError::Conflict(_))
+        ));
+        init_config(&settings, &path, Some(ConfigFormat::Json), true).unwrap();
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with('{'));
+        assert!(matches!(
+            init_config(&settings, &tempdir.path().join("node.conf"), None, false),
+            Err(NodeError::InvalidParameter(_))
+        ));
+    }
+}
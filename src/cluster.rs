@@ -0,0 +1,541 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Cluster.
+//!
+//! Several nodes in one process, connected to each other, for tests and simulations:
+//!
+//! ```ignore
+//! let cluster = KoreCluster::builder().nodes(4).topology(Topology::Star).start().await?;
+//! let controller_ids = cluster.apis().iter().map(KoreApi::get_controller_id);
+//! cluster.shutdown();
+//! ```
+//!
+//! Every node gets the template settings with its own keys and database under the directory of
+//! the cluster, a temporary one unless given, and a listen address on `127.0.0.1`. On PostgreSQL
+//! the database of a node is a schema of the database of the template, named after the
+//! directory of the cluster and the node, and created when missing. The prometheus and REST
+//! servers of the template, when set, are bound to any free port.
+//!
+//! The boot nodes of a node are the nodes given by the topology. The peer id of a node is known
+//! once it runs, so nodes start in waves: each wave starts at once the nodes whose boot nodes are
+//! all running. The free ports of a wave are picked right before it starts, along with fallback
+//! ports used by a node whose port was taken in between, and the boot nodes of the next waves
+//! get the addresses the nodes actually listen on.
+//!
+
+use std::{
+    collections::BTreeMap,
+    net::TcpListener,
+    path::{Path, PathBuf},
+};
+
+use kore_base::RoutingNode;
+use tempfile::TempDir;
+use tokio_util::sync::CancellationToken;
+
+#[cfg(feature = "postgres")]
+use crate::database::postgres::{connection_url, PostgresManager};
+use crate::{
+    bootstrap::with_boot_nodes,
+    error::NodeError,
+    node::{DatabaseNode, KoreNode, KoreNodeBuilder},
+    settings::{BootstrapSettings, DbSettings, KoreSettings},
+    KoreApi,
+};
+
+/// Fallback ports of each node listening on a free port.
+const FALLBACK_PORTS: usize = 3;
+
+/// Boot nodes of each node of a cluster. A node only joins nodes with a lower index.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Topology {
+    /// Every node joins the first one.
+    #[default]
+    Star,
+    /// Every node joins the previous one.
+    Chain,
+    /// Every node joins all the previous ones.
+    Mesh,
+    /// Indexes of the boot nodes of each node.
+    Custom(Vec<Vec<usize>>),
+}
+
+impl Topology {
+    /// Boot nodes of each of `nodes` nodes.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - A custom topology of another size, or with a node
+    ///   that joins itself or a later node
+    ///
+    fn boot_indexes(&self, nodes: usize) -> Result<Vec<Vec<usize>>, NodeError> {
+        let indexes = match self {
+            Topology::Star => (0..nodes).map(|node| (0..node.min(1)).collect()).collect(),
+            Topology::Chain => (0..nodes)
+                .map(|node| node.checked_sub(1).into_iter().collect())
+                .collect(),
+            Topology::Mesh => (0..nodes).map(|node| (0..node).collect()).collect(),
+            Topology::Custom(indexes) => {
+                if indexes.len() != nodes {
+                    return Err(NodeError::InvalidParameter(format!(
+                        "topology of {} nodes for a cluster of {}",
+                        indexes.len(),
+                        nodes
+                    )));
+                }
+                for (node, boot) in indexes.iter().enumerate() {
+                    if let Some(later) = boot.iter().find(|boot| **boot >= node) {
+                        return Err(NodeError::InvalidParameter(format!(
+                            "node {} cannot join node {}, only previous nodes",
+                            node, later
+                        )));
+                    }
+                }
+                indexes.clone()
+            }
+        };
+        Ok(indexes)
+    }
+}
+
+/// Nodes of a cluster that start together, each after the waves of its boot nodes.
+fn waves(boot_indexes: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut levels: Vec<usize> = Vec::with_capacity(boot_indexes.len());
+    let mut waves: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (node, boot) in boot_indexes.iter().enumerate() {
+        let level = boot.iter().map(|boot| levels[*boot] + 1).max().unwrap_or(0);
+        levels.push(level);
+        waves.entry(level).or_default().push(node);
+    }
+    waves.into_values().collect()
+}
+
+/// Builder of a cluster.
+pub struct KoreClusterBuilder {
+    nodes: usize,
+    topology: Topology,
+    settings: KoreSettings,
+    password: String,
+    base_port: Option<u16>,
+    dir: Option<PathBuf>,
+}
+
+impl KoreClusterBuilder {
+    /// Number of nodes, 2 by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `nodes` - Number of nodes
+    ///
+    pub fn nodes(mut self, nodes: usize) -> Self {
+        self.nodes = nodes;
+        self
+    }
+
+    /// Boot nodes of each node, `Topology::Star` by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `topology` - Topology of the cluster
+    ///
+    pub fn topology(mut self, topology: Topology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Settings of every node, before its keys, database and addresses are set.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Template of the settings
+    ///
+    pub fn settings(mut self, settings: KoreSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Password of the node keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `password` - Password of every node
+    ///
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = password.to_owned();
+        self
+    }
+
+    /// Listen on `base_port` and the next ports, instead of free ports picked by the system.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_port` - Port of the first node
+    ///
+    pub fn base_port(mut self, base_port: u16) -> Self {
+        self.base_port = Some(base_port);
+        self
+    }
+
+    /// Keep the keys and databases in `dir`, `node<index>` for each node, instead of a
+    /// temporary directory removed with the cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Directory of the cluster
+    ///
+    pub fn dir(mut self, dir: &Path) -> Self {
+        self.dir = Some(dir.to_owned());
+        self
+    }
+
+    /// Start the nodes, wave after wave.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<KoreCluster, NodeError>` - Running cluster
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - No nodes, an invalid topology, too many ports or a
+    ///   database that cannot be given to each node
+    /// * `NodeError::Network` - No free port
+    /// * `NodeError::Database` - The schemas of the nodes could not be created on PostgreSQL
+    /// * Any error of `KoreNodeBuilder::build`; the nodes already started are stopped
+    ///
+    pub async fn start(self) -> Result<KoreCluster, NodeError> {
+        if self.nodes == 0 {
+            return Err(NodeError::InvalidParameter(
+                "a cluster needs a node".to_owned(),
+            ));
+        }
+        let boot_indexes = self.topology.boot_indexes(self.nodes)?;
+        let (temp_dir, dir) = match &self.dir {
+            Some(dir) => (None, dir.clone()),
+            None => {
+                let temp_dir = tempfile::tempdir().map_err(|e| {
+                    NodeError::InternalApi(format!("Cluster directory not created: {}", e))
+                })?;
+                let dir = temp_dir.path().to_owned();
+                (Some(temp_dir), dir)
+            }
+        };
+
+        #[cfg(feature = "postgres")]
+        if let DbSettings::Postgres { url, .. } = &self.settings.db {
+            let url = url.clone();
+            let schemas = (0..self.nodes)
+                .map(|node| postgres_schema(&dir, node))
+                .collect::<Vec<String>>();
+            tokio::task::spawn_blocking(move || {
+                let manager = PostgresManager::new(&url, 1)?;
+                schemas
+                    .iter()
+                    .try_for_each(|schema| manager.create_schema(schema))
+            })
+            .await
+            .map_err(|e| NodeError::InternalApi(format!("Schemas not created: {}", e)))??;
+        }
+
+        let cancellation = CancellationToken::new();
+        let mut started: Vec<Option<(DatabaseNode, RoutingNode)>> =
+            (0..self.nodes).map(|_| None).collect();
+        for wave in waves(&boot_indexes) {
+            let ports = self.ports(&wave)?;
+            let builds = wave
+                .iter()
+                .zip(ports)
+                .map(|(node, (port, fallback_ports))| {
+                    let boot_nodes = boot_indexes[*node]
+                        .iter()
+                        .filter_map(|boot| {
+                            started[*boot].as_ref().map(|(_, routing)| routing.clone())
+                        })
+                        .collect();
+                    let settings =
+                        self.node_settings(&dir, *node, port, fallback_ports, boot_nodes);
+                    let password = self.password.clone();
+                    tokio::spawn(
+                        async move { KoreNodeBuilder::new(settings?, &password).build().await },
+                    )
+                });
+            let results = futures::future::join_all(builds).await;
+            let mut error = None;
+            for (node, result) in wave.iter().zip(results) {
+                let result = result.unwrap_or_else(|e| {
+                    Err(NodeError::InternalApi(format!(
+                        "Node {} not built: {}",
+                        node, e
+                    )))
+                });
+                match result {
+                    Ok(built) => {
+                        built.bind_with_shutdown(cancellation.clone().cancelled_owned());
+                        // Fallback ports applied.
+                        let routing = RoutingNode {
+                            peer_id: built.api().get_peer_id(),
+                            address: built.api().node_info().listen_addresses,
+                        };
+                        started[*node] = Some((built, routing));
+                    }
+                    Err(e) => error = error.or(Some(e)),
+                }
+            }
+            if let Some(error) = error {
+                cancellation.cancel();
+                return Err(error);
+            }
+        }
+
+        Ok(KoreCluster {
+            nodes: started
+                .into_iter()
+                .flatten()
+                .map(|(node, _)| node)
+                .collect(),
+            cancellation,
+            _temp_dir: temp_dir,
+        })
+    }
+
+    /// Listen port and fallback ports of each node of a wave. Free ports are picked when the
+    /// wave starts, so that they are still free when its nodes bind them.
+    fn ports(&self, wave: &[usize]) -> Result<Vec<(u16, Vec<u16>)>, NodeError> {
+        if let Some(base_port) = self.base_port {
+            return wave
+                .iter()
+                .map(|node| {
+                    u16::try_from(*node)
+                        .ok()
+                        .and_then(|node| base_port.checked_add(node))
+                        .map(|port| (port, vec![]))
+                        .ok_or_else(|| {
+                            NodeError::InvalidParameter(format!(
+                                "{} nodes from port {}",
+                                self.nodes, base_port
+                            ))
+                        })
+                })
+                .collect();
+        }
+        // Every listener is kept until all ports are picked, so that they differ.
+        let listeners = (0..wave.len() * (1 + FALLBACK_PORTS))
+            .map(|_| TcpListener::bind(("127.0.0.1", 0)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| NodeError::Network("/ip4/127.0.0.1/tcp/0".to_owned()))?;
+        let ports = listeners
+            .iter()
+            .map(|listener| {
+                listener
+                    .local_addr()
+                    .map(|address| address.port())
+                    .map_err(|_| NodeError::Network("/ip4/127.0.0.1/tcp/0".to_owned()))
+            })
+            .collect::<Result<Vec<u16>, NodeError>>()?;
+        Ok(ports
+            .chunks(1 + FALLBACK_PORTS)
+            .map(|ports| (ports[0], ports[1..].to_vec()))
+            .collect())
+    }
+
+    /// Settings of a node of the cluster.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - The database of the template cannot be given to each
+    ///   node
+    ///
+    fn node_settings(
+        &self,
+        dir: &Path,
+        node: usize,
+        port: u16,
+        fallback_ports: Vec<u16>,
+        boot_nodes: Vec<RoutingNode>,
+    ) -> Result<KoreSettings, NodeError> {
+        let node_dir = dir.join(format!("node{}", node));
+        let mut settings = self.settings.clone();
+        settings.keys_path = node_dir.join("keys").to_string_lossy().into_owned();
+        settings.db = match settings.db {
+            #[cfg(feature = "leveldb")]
+            DbSettings::LevelDB(_) => {
+                DbSettings::LevelDB(node_dir.join("leveldb").to_string_lossy().into_owned())
+            }
+            #[cfg(feature = "sled")]
+            DbSettings::Sled(_) => {
+                DbSettings::Sled(node_dir.join("sled").to_string_lossy().into_owned())
+            }
+            #[cfg(feature = "redb")]
+            DbSettings::Redb(_) => DbSettings::Redb(
+                node_dir
+                    .join("redb")
+                    .join("database")
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            #[cfg(feature = "sqlite")]
+            DbSettings::Sqlite(_) => DbSettings::Sqlite(
+                node_dir
+                    .join("sqlite")
+                    .join("database")
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            #[cfg(feature = "postgres")]
+            DbSettings::Postgres { url, pool_size } => {
+                let search_path = format!("-csearch_path%3D{}", postgres_schema(dir, node));
+                DbSettings::Postgres {
+                    url: connection_url(
+                        &url,
+                        &BTreeMap::from([("options".to_owned(), search_path)]),
+                    ),
+                    pool_size,
+                }
+            }
+            #[allow(unreachable_patterns)]
+            _ => {
+                return Err(NodeError::InvalidParameter(
+                    "the database of the template cannot be given to each node of a cluster"
+                        .to_owned(),
+                ))
+            }
+        };
+        settings.settings.network.listen_addresses = vec![listen_address(port)];
+        settings.settings.network.routing =
+            with_boot_nodes(&settings.settings.network.routing, boot_nodes);
+        settings.bootstrap = BootstrapSettings::default();
+        settings.listen_fallback_ports = fallback_ports;
+        if !settings.prometheus.is_empty() {
+            settings.prometheus = "127.0.0.1:0".to_owned();
+        }
+        if !settings.http_api.is_empty() {
+            settings.http_api = "127.0.0.1:0".to_owned();
+        }
+        Ok(settings)
+    }
+}
+
+/// Schema of a node of a cluster on PostgreSQL, named after the directory of the cluster so
+/// that clusters sharing a database do not share nodes.
+#[cfg(feature = "postgres")]
+fn postgres_schema(dir: &Path, node: usize) -> String {
+    let cluster = dir
+        .file_name()
+        .map(|name| {
+            name.to_string_lossy()
+                .chars()
+                .filter(char::is_ascii_alphanumeric)
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .unwrap_or_default();
+    format!("kore_{}_node{}", cluster, node)
+}
+
+/// Listen address of a node of the cluster.
+fn listen_address(port: u16) -> String {
+    format!("/ip4/127.0.0.1/tcp/{}", port)
+}
+
+/// Running nodes of a cluster, stopped together.
+pub struct KoreCluster {
+    nodes: Vec<DatabaseNode>,
+    cancellation: CancellationToken,
+    /// Directory of the nodes, when temporary.
+    _temp_dir: Option<TempDir>,
+}
+
+impl KoreCluster {
+    /// Builder of a cluster of two nodes in a star, with the default settings and password.
+    pub fn builder() -> KoreClusterBuilder {
+        KoreClusterBuilder {
+            nodes: 2,
+            topology: Topology::default(),
+            settings: KoreSettings::default(),
+            password: "kore".to_owned(),
+            base_port: None,
+            dir: None,
+        }
+    }
+
+    /// Nodes, in the order of the topology.
+    pub fn nodes(&self) -> &[DatabaseNode] {
+        &self.nodes
+    }
+
+    /// Kore API of each node, in the order of the topology.
+    pub fn apis(&self) -> Vec<KoreApi> {
+        self.nodes.iter().map(|node| node.api().clone()).collect()
+    }
+
+    /// Token that stops every node when cancelled.
+    pub fn token(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Stop every node.
+    pub fn shutdown(&self) {
+        self.cancellation.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_topology_waves() {
+        let star = Topology::Star.boot_indexes(4).unwrap();
+        assert_eq!(star, vec![vec![], vec![0], vec![0], vec![0]]);
+        assert_eq!(waves(&star), vec![vec![0], vec![1, 2, 3]]);
+
+        let chain = Topology::Chain.boot_indexes(3).unwrap();
+        assert_eq!(chain, vec![vec![], vec![0], vec![1]]);
+        assert_eq!(waves(&chain), vec![vec![0], vec![1], vec![2]]);
+
+        let mesh = Topology::Mesh.boot_indexes(3).unwrap();
+        assert_eq!(mesh, vec![vec![], vec![0], vec![0, 1]]);
+
+        let custom = Topology::Custom(vec![vec![], vec![], vec![0, 1], vec![1]]);
+        assert_eq!(
+            waves(&custom.boot_indexes(4).unwrap()),
+            vec![vec![0, 1], vec![2, 3]]
+        );
+        assert!(custom.boot_indexes(3).is_err());
+        assert!(Topology::Custom(vec![vec![1], vec![]])
+            .boot_indexes(2)
+            .is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sqlite_cluster() {
+        let settings = KoreSettings {
+            db: DbSettings::Sqlite(String::new()),
+            keys: crate::settings::KeysSettings {
+                iterations: crate::utils::MIN_PBKDF2_ITERATIONS,
+                ..Default::default()
+            },
+            prometheus: String::new(),
+            ..Default::default()
+        };
+        let cluster = KoreCluster::builder()
+            .nodes(3)
+            .topology(Topology::Chain)
+            .settings(settings)
+            .start()
+            .await
+            .unwrap();
+        let apis = cluster.apis();
+        assert_eq!(apis.len(), 3);
+        assert_ne!(apis[0].get_peer_id(), apis[1].get_peer_id());
+        cluster.shutdown();
+        tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            cluster.nodes()[2].token().cancelled(),
+        )
+        .await
+        .unwrap();
+    }
+}
@@ -1,66 +1,271 @@
-use std::env;
-
-use crate::settings::KoreSettings;
-use config::Config;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+
+use crate::{
+    error::{ConfigError, NodeError},
+    settings::KoreSettings,
+};
+
+use super::{params::Params, password::PasswordSource};
+
+/// Extensions of the files read from a configuration directory.
+pub const CONFIG_EXTENSIONS: [&str; 4] = ["toml", "yaml", "yml", "json"];
+
+/// Source that takes precedence when the environment variables and the file set the same key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConfigPrecedence {
+    /// The file overrides the environment variables.
+    #[default]
+    File,
+    /// The environment variables override the file, as usual in twelve-factor deployments.
+    Env,
+}
 
-use super::params::Params;
+/// Sources of the node settings. A key takes the value of the command line arguments, then of
+/// the file or the environment variables following `precedence`, then its default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigSources {
+    /// Whether to read the `KORE_*` environment variables.
+    pub env: bool,
+    /// Configuration files (json, yaml or toml) or directories, see `config_files`, none when
+    /// empty.
+    pub file: String,
+    /// Source that wins between the environment variables and the file.
+    pub precedence: ConfigPrecedence,
+    /// `<key>=<value>` pairs of the command line, which override the other sources.
+    pub args: Vec<String>,
+}
 
-pub fn build_config(env: bool, file: &str) -> KoreSettings {
-    // Env configuration
-    let mut params_env = Params::default();
-    if env {
-        params_env = Params::from_env();
+impl ConfigSources {
+    /// Sources without command line arguments, the file taking precedence.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - Whether to read the `KORE_*` environment variables
+    /// * `file` - Configuration files or directories, see `config_files`, none when empty
+    ///
+    pub fn new(env: bool, file: &str) -> Self {
+        Self {
+            env,
+            file: file.to_owned(),
+            ..Default::default()
+        }
     }
 
-    // file configuration (json, yaml or toml)
-    let mut params_file = Params::default();
-    if !file.is_empty() {
-        let mut config = Config::builder();
+    /// Set the source that wins between the environment variables and the file.
+    pub fn with_precedence(mut self, precedence: ConfigPrecedence) -> Self {
+        self.precedence = precedence;
+        self
+    }
 
-        config = config.add_source(config::File::with_name(file));
+    /// Set the `<key>=<value>` pairs of the command line.
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
 
-        let config = config
-            .build()
-            .map_err(|e| {
-                println!("Error building config: {}", e);
+    /// Build the node settings from the sources. The settings are validated, and every problem
+    /// found is reported at once.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Config` - Invalid values, unreadable file or invalid settings
+    ///
+    pub fn build(&self) -> Result<KoreSettings, NodeError> {
+        // Env configuration
+        let params_env = if self.env {
+            Params::from_env()
+        } else {
+            Ok(Params::default())
+        };
+
+        // file configuration (json, yaml or toml), merged in order
+        let params_file = config_files(&self.file)
+            .map_err(|error| vec![error])
+            .and_then(|files| {
+                let mut errors = vec![];
+                let mut params = Params::default();
+                for file in files {
+                    match Params::from_file(&file.to_string_lossy()) {
+                        Ok(file) => params = params.mix_config(file),
+                        Err(error) => errors.extend(error),
+                    }
+                }
+                if errors.is_empty() {
+                    Ok(params)
+                } else {
+                    Err(errors)
+                }
             })
-            .unwrap();
+            .map(|params| {
+                if self.env && self.precedence == ConfigPrecedence::Env {
+                    params.yield_to_env()
+                } else {
+                    params
+                }
+            });
+
+        // Command line configuration
+        let params_args = Params::from_args(&self.args);
+
+        // Mix configurations.
+        let settings = match (params_env, params_file, params_args) {
+            (Ok(params_env), Ok(params_file), Ok(params_args)) => {
+                KoreSettings::from(params_env.mix_config(params_file).mix_config(params_args))
+            }
+            (params_env, params_file, params_args) => {
+                let errors = params_env
+                    .err()
+                    .into_iter()
+                    .chain(params_file.err())
+                    .chain(params_args.err())
+                    .flatten()
+                    .collect();
+                return Err(NodeError::Config(errors));
+            }
+        };
+        settings.validate()?;
+        Ok(settings)
+    }
+}
 
-        params_file = config
-            .try_deserialize()
-            .map_err(|e| {
-                println!("Error try deserialize config: {}", e);
-            })
-            .unwrap();
+/// Build the node settings from the environment variables and the configuration files.
+/// The settings are validated, and every problem found is reported at once.
+///
+/// # Arguments
+///
+/// * `env` - Whether to read the `KORE_*` environment variables
+/// * `file` - Configuration files or directories, see `config_files`, none when empty
+/// * `precedence` - Source that wins when both set a key
+///
+/// # Errors
+///
+/// * `NodeError::Config` - Invalid values, unreadable file or invalid settings
+///
+pub fn build_config(
+    env: bool,
+    file: &str,
+    precedence: ConfigPrecedence,
+) -> Result<KoreSettings, NodeError> {
+    ConfigSources::new(env, file)
+        .with_precedence(precedence)
+        .build()
+}
+
+/// Paths of a list separated as in `PATH`, by `:` on Unix and `;` on Windows, in order.
+///
+/// # Arguments
+///
+/// * `file` - Configuration files or directories, none when empty
+///
+pub fn config_paths(file: &str) -> Vec<PathBuf> {
+    env::split_paths(file)
+        .filter(|path| !path.as_os_str().is_empty())
+        .collect()
+}
+
+/// Configuration files in the order they are merged, each one overriding the keys it writes.
+/// The paths of the list are files (json, yaml or toml, the extension may be left out), or
+/// directories whose files with an extension of `CONFIG_EXTENSIONS` are read by name, as in
+/// `conf.d`, e.g. `base.toml:production.toml:secrets.toml` or `/etc/kore/conf.d`.
+///
+/// # Arguments
+///
+/// * `file` - Configuration files or directories, none when empty
+///
+/// # Errors
+///
+/// * `ConfigError` - A directory cannot be read
+///
+pub fn config_files(file: &str) -> Result<Vec<PathBuf>, ConfigError> {
+    let mut files = vec![];
+    for path in config_paths(file) {
+        if path.is_dir() {
+            let mut entries = path
+                .read_dir()
+                .and_then(|entries| {
+                    entries
+                        .map(|entry| entry.map(|entry| entry.path()))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .map_err(|error| ConfigError::new(path.to_string_lossy(), error.to_string()))?;
+            entries.retain(|entry| entry.is_file() && has_config_extension(entry));
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path);
+        }
     }
+    Ok(files)
+}
 
-    // Mix configurations.
-    KoreSettings::from(params_env.mix_config(params_file))
+/// Whether a file has an extension of `CONFIG_EXTENSIONS`.
+pub(crate) fn has_config_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| CONFIG_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
 }
 
-pub fn build_password() -> String {
-    env::var("KORE_PASSWORD").unwrap()
+/// Password of the node key, from `KORE_PASSWORD`, `KORE_PASSWORD_FILE`, `KORE_PASSWORD_COMMAND`
+/// or the terminal, see [`PasswordSource`].
+///
+/// # Errors
+///
+/// * `NodeError::Config` - No source is set or the password cannot be read
+///
+pub fn build_password() -> Result<String, NodeError> {
+    PasswordSource::from_env()?.read()
 }
 
 pub fn build_file_path() -> String {
     env::var("KORE_FILE_PATH").unwrap_or_default()
 }
 
+/// Source that takes precedence over the other, from `KORE_CONFIG_PRECEDENCE` (`file` or
+/// `env`), the file when it is not set.
+///
+/// # Errors
+///
+/// * `NodeError::Config` - The variable holds another value
+///
+pub fn build_config_precedence() -> Result<ConfigPrecedence, NodeError> {
+    match env::var("KORE_CONFIG_PRECEDENCE") {
+        Ok(value) if !value.trim().is_empty() => ConfigPrecedence::from_str(value.trim(), true)
+            .map_err(|_| {
+                NodeError::Config(vec![ConfigError::new(
+                    "KORE_CONFIG_PRECEDENCE",
+                    format!("{} is not file or env", value),
+                )])
+            }),
+        _ => Ok(ConfigPrecedence::default()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{num::NonZeroUsize, time::Duration};
 
-    use crate::settings::DbSettings;
+    use crate::{
+        error::NodeError,
+        settings::{default_data_path, DbSettings},
+    };
     use kore_base::{DigestDerivator, KeyDerivator, NodeType, RoutingNode};
     use serial_test::serial;
     use tempfile::TempDir;
 
-    use super::build_config;
+    use super::{
+        build_config, build_config_precedence, build_password, config_files, ConfigPrecedence,
+        ConfigSources,
+    };
 
     #[test]
     #[serial]
     fn test_env_empty() {
-        let config = build_config(true, "");
+        let config = build_config(true, "", ConfigPrecedence::File).unwrap();
 
         assert_eq!(config.settings.network.port_reuse, false);
         assert_eq!(config.settings.network.user_agent, "kore-node");
@@ -128,14 +333,14 @@ mod tests {
         #[cfg(feature = "leveldb")]
         assert_eq!(
             config.db,
-            DbSettings::LevelDB("examples/leveldb".to_owned())
+            DbSettings::LevelDB(default_data_path("leveldb"))
         );
         #[cfg(feature = "sqlite")]
         assert_eq!(
             config.db,
-            DbSettings::Sqlite("examples/sqlitedb".to_owned())
+            DbSettings::Sqlite(default_data_path("sqlitedb"))
         );
-        assert_eq!(config.keys_path, "examples/keys".to_owned());
+        assert_eq!(config.keys_path, default_data_path("keys"));
         assert_eq!(config.prometheus, "0.0.0.0:3050".to_owned());
 
         assert!(config.settings.network.control_list.get_allow_list().is_empty());
@@ -195,7 +400,7 @@ mod tests {
         std::env::set_var("KORE_NETWORK_CONTROL_LIST_SERVICE_BLOCK_LIST", "http://90.0.0.1:3000/block_list,http://90.0.0.2:4000/block_list");
         std::env::set_var("KORE_NETWORK_CONTROL_LIST_INTERVAL_REQUEST", "58");
 
-        let config = build_config(true, "");
+        let config = build_config(true, "", ConfigPrecedence::File).unwrap();
 
         let boot_nodes = vec![
             RoutingNode {
@@ -362,7 +567,12 @@ mod tests {
         let temp_file_path = temp_dir.path().join("config.json");
         std::fs::write(&temp_file_path, content.to_string().as_bytes()).unwrap();
 
-        let config = build_config(false, temp_file_path.to_str().unwrap());
+        let config = build_config(
+            false,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        )
+        .unwrap();
 
         assert_eq!(config.settings.network.port_reuse, false);
         assert_eq!(config.settings.network.user_agent, "kore-node");
@@ -430,14 +640,14 @@ mod tests {
         #[cfg(feature = "leveldb")]
         assert_eq!(
             config.db,
-            DbSettings::LevelDB("examples/leveldb".to_owned())
+            DbSettings::LevelDB(default_data_path("leveldb"))
         );
         #[cfg(feature = "sqlite")]
         assert_eq!(
             config.db,
-            DbSettings::Sqlite("examples/sqlitedb".to_owned())
+            DbSettings::Sqlite(default_data_path("sqlitedb"))
         );
-        assert_eq!(config.keys_path, "examples/keys".to_owned());
+        assert_eq!(config.keys_path, default_data_path("keys"));
         assert_eq!(config.prometheus, "0.0.0.0:3050".to_owned());
 
         assert!(config.settings.network.control_list.get_allow_list().is_empty());
@@ -500,7 +710,12 @@ mod tests {
         let temp_file_path = temp_dir.path().join("config.json");
         std::fs::write(&temp_file_path, content.to_string().as_bytes()).unwrap();
 
-        let config = build_config(false, temp_file_path.to_str().unwrap());
+        let config = build_config(
+            false,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        )
+        .unwrap();
 
         let boot_nodes = vec![
             RoutingNode {
@@ -661,7 +876,12 @@ mod tests {
         let temp_file_path = temp_dir.path().join("config.json");
         std::fs::write(&temp_file_path, content.to_string().as_bytes()).unwrap();
 
-        let config = build_config(false, temp_file_path.to_str().unwrap());
+        let config = build_config(
+            false,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        )
+        .unwrap();
 
         let boot_nodes = vec![
             RoutingNode {
@@ -758,14 +978,14 @@ mod tests {
         #[cfg(feature = "leveldb")]
         assert_eq!(
             config.db,
-            DbSettings::LevelDB("examples/leveldb".to_owned())
+            DbSettings::LevelDB(default_data_path("leveldb"))
         );
         #[cfg(feature = "sqlite")]
         assert_eq!(
             config.db,
-            DbSettings::Sqlite("examples/sqlitedb".to_owned())
+            DbSettings::Sqlite(default_data_path("sqlitedb"))
         );
-        assert_eq!(config.keys_path, "examples/keys".to_owned());
+        assert_eq!(config.keys_path, default_data_path("keys"));
         assert_eq!(config.prometheus, "0.0.0.0:3050".to_owned());
 
         assert_eq!(config.settings.network.control_list.get_allow_list(), vec!["Peer200", "Peer300"]);
@@ -840,7 +1060,12 @@ mod tests {
         let temp_file_path = temp_dir.path().join("config.json");
         std::fs::write(&temp_file_path, content.to_string().as_bytes()).unwrap();
 
-        let config = build_config(true, temp_file_path.to_str().unwrap());
+        let config = build_config(
+            true,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        )
+        .unwrap();
 
         let boot_nodes = vec![
             RoutingNode {
@@ -990,7 +1215,12 @@ mod tests {
         let temp_file_path = temp_dir.path().join("config.yaml");
         std::fs::write(&temp_file_path, content.to_string().as_bytes()).unwrap();
 
-        let config = build_config(false, temp_file_path.to_str().unwrap());
+        let config = build_config(
+            false,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        )
+        .unwrap();
 
         assert_eq!(config.settings.network.port_reuse, false);
         assert_eq!(config.settings.network.user_agent, "kore-node");
@@ -1058,14 +1288,14 @@ mod tests {
         #[cfg(feature = "leveldb")]
         assert_eq!(
             config.db,
-            DbSettings::LevelDB("examples/leveldb".to_owned())
+            DbSettings::LevelDB(default_data_path("leveldb"))
         );
         #[cfg(feature = "sqlite")]
         assert_eq!(
             config.db,
-            DbSettings::Sqlite("examples/sqlitedb".to_owned())
+            DbSettings::Sqlite(default_data_path("sqlitedb"))
         );
-        assert_eq!(config.keys_path, "examples/keys".to_owned());
+        assert_eq!(config.keys_path, default_data_path("keys"));
         assert_eq!(config.prometheus, "0.0.0.0:3050".to_owned());
 
         assert!(config.settings.network.control_list.get_allow_list().is_empty());
@@ -1138,7 +1368,12 @@ mod tests {
         let temp_file_path = temp_dir.path().join("config.yaml");
         std::fs::write(&temp_file_path, content.to_string().as_bytes()).unwrap();
 
-        let config = build_config(false, temp_file_path.to_str().unwrap());
+        let config = build_config(
+            false,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        )
+        .unwrap();
 
         let boot_nodes = vec![
             RoutingNode {
@@ -1299,7 +1534,12 @@ mod tests {
         let temp_file_path = temp_dir.path().join("config.yaml");
         std::fs::write(&temp_file_path, content.to_string().as_bytes()).unwrap();
 
-        let config = build_config(false, temp_file_path.to_str().unwrap());
+        let config = build_config(
+            false,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        )
+        .unwrap();
 
         let boot_nodes = vec![
             RoutingNode {
@@ -1396,14 +1636,14 @@ mod tests {
         #[cfg(feature = "leveldb")]
         assert_eq!(
             config.db,
-            DbSettings::LevelDB("examples/leveldb".to_owned())
+            DbSettings::LevelDB(default_data_path("leveldb"))
         );
         #[cfg(feature = "sqlite")]
         assert_eq!(
             config.db,
-            DbSettings::Sqlite("examples/sqlitedb".to_owned())
+            DbSettings::Sqlite(default_data_path("sqlitedb"))
         );
-        assert_eq!(config.keys_path, "examples/keys".to_owned());
+        assert_eq!(config.keys_path, default_data_path("keys"));
         assert_eq!(config.prometheus, "0.0.0.0:3050".to_owned());
 
 
@@ -1480,7 +1720,12 @@ mod tests {
         let temp_file_path = temp_dir.path().join("config.yaml");
         std::fs::write(&temp_file_path, content.to_string().as_bytes()).unwrap();
 
-        let config = build_config(true, temp_file_path.to_str().unwrap());
+        let config = build_config(
+            true,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        )
+        .unwrap();
 
         let boot_nodes = vec![
             RoutingNode {
@@ -1630,7 +1875,12 @@ mod tests {
         let temp_file_path = temp_dir.path().join("config.toml");
         std::fs::write(&temp_file_path, content.to_string().as_bytes()).unwrap();
 
-        let config = build_config(false, temp_file_path.to_str().unwrap());
+        let config = build_config(
+            false,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        )
+        .unwrap();
 
         assert_eq!(config.settings.network.port_reuse, false);
         assert_eq!(config.settings.network.user_agent, "kore-node");
@@ -1698,14 +1948,14 @@ mod tests {
         #[cfg(feature = "leveldb")]
         assert_eq!(
             config.db,
-            DbSettings::LevelDB("examples/leveldb".to_owned())
+            DbSettings::LevelDB(default_data_path("leveldb"))
         );
         #[cfg(feature = "sqlite")]
         assert_eq!(
             config.db,
-            DbSettings::Sqlite("examples/sqlitedb".to_owned())
+            DbSettings::Sqlite(default_data_path("sqlitedb"))
         );
-        assert_eq!(config.keys_path, "examples/keys".to_owned());
+        assert_eq!(config.keys_path, default_data_path("keys"));
         assert_eq!(config.prometheus, "0.0.0.0:3050".to_owned());
 
         assert!(config.settings.network.control_list.get_allow_list().is_empty());
@@ -1766,7 +2016,12 @@ mod tests {
         let temp_file_path = temp_dir.path().join("config.toml");
         std::fs::write(&temp_file_path, content.to_string().as_bytes()).unwrap();
 
-        let config = build_config(false, temp_file_path.to_str().unwrap());
+        let config = build_config(
+            false,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        )
+        .unwrap();
 
         let boot_nodes = vec![
             RoutingNode {
@@ -1923,7 +2178,12 @@ mod tests {
         let temp_file_path = temp_dir.path().join("config.toml");
         std::fs::write(&temp_file_path, content.to_string().as_bytes()).unwrap();
 
-        let config = build_config(false, temp_file_path.to_str().unwrap());
+        let config = build_config(
+            false,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        )
+        .unwrap();
 
         let boot_nodes = vec![
             RoutingNode {
@@ -2020,14 +2280,14 @@ mod tests {
         #[cfg(feature = "leveldb")]
         assert_eq!(
             config.db,
-            DbSettings::LevelDB("examples/leveldb".to_owned())
+            DbSettings::LevelDB(default_data_path("leveldb"))
         );
         #[cfg(feature = "sqlite")]
         assert_eq!(
             config.db,
-            DbSettings::Sqlite("examples/sqlitedb".to_owned())
+            DbSettings::Sqlite(default_data_path("sqlitedb"))
         );
-        assert_eq!(config.keys_path, "examples/keys".to_owned());
+        assert_eq!(config.keys_path, default_data_path("keys"));
         assert_eq!(config.prometheus, "0.0.0.0:3050".to_owned());
 
 
@@ -2099,7 +2359,12 @@ mod tests {
         let temp_file_path = temp_dir.path().join("config.toml");
         std::fs::write(&temp_file_path, content.to_string().as_bytes()).unwrap();
 
-        let config = build_config(true, temp_file_path.to_str().unwrap());
+        let config = build_config(
+            true,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        )
+        .unwrap();
 
         let boot_nodes = vec![
             RoutingNode {
@@ -2239,4 +2504,173 @@ mod tests {
         std::env::remove_var("KORE_NETWORK_CONTROL_LIST_BLOCK_LIST");
         std::env::remove_var("KORE_NETWORK_CONTROL_LIST_SERVICE_BLOCK_LIST");
     }
+
+    #[test]
+    fn test_json_invalid_values() {
+        let content = r#"
+            {
+            "kore": {
+              "db_read_pool_size": 0,
+              "prometheus": "3050",
+              "access_log": {
+                "sample_rate": 2.0
+              }
+            }
+          }"#;
+        let temp_dir = TempDir::new().unwrap();
+        let temp_file_path = temp_dir.path().join("config.json");
+        std::fs::write(&temp_file_path, content.to_string().as_bytes()).unwrap();
+
+        let Err(NodeError::Config(errors)) = build_config(
+            false,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        ) else {
+            panic!("invalid settings accepted");
+        };
+        let locations = errors
+            .iter()
+            .map(|error| error.location.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            vec![
+                "kore.db_read_pool_size",
+                "kore.prometheus",
+                "kore.access_log.sample_rate"
+            ]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_env_and_file_errors() {
+        std::env::set_var("KORE_NODE_TIMEOUT", "soon");
+        let temp_dir = TempDir::new().unwrap();
+        let temp_file_path = temp_dir.path().join("missing.json");
+
+        let Err(NodeError::Config(errors)) = build_config(
+            true,
+            temp_file_path.to_str().unwrap(),
+            ConfigPrecedence::File,
+        ) else {
+            panic!("invalid settings accepted");
+        };
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].location, "KORE_NODE_*");
+        assert_eq!(errors[1].location, temp_file_path.to_str().unwrap());
+
+        std::env::remove_var("KORE_NODE_TIMEOUT");
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_precedence() {
+        std::env::set_var("KORE_NETWORK_PORT_REUSE", "true");
+        std::env::set_var("KORE_NODE_TIMEOUT", "40");
+        let content = r#"
+        [kore.network]
+        user_agent = "Kore3.0"
+        port_reuse = false
+
+        [kore.node]
+        timeout = 30
+        "#;
+        let temp_dir = TempDir::new().unwrap();
+        let temp_file_path = temp_dir.path().join("config.toml");
+        std::fs::write(&temp_file_path, content.as_bytes()).unwrap();
+        let file = temp_file_path.to_str().unwrap();
+
+        let config = build_config(true, file, ConfigPrecedence::File).unwrap();
+        assert!(!config.settings.network.port_reuse);
+        assert_eq!(config.settings.node.timeout, 30);
+
+        let config = build_config(true, file, ConfigPrecedence::Env).unwrap();
+        assert!(config.settings.network.port_reuse);
+        assert_eq!(config.settings.node.timeout, 40);
+        assert_eq!(config.settings.network.user_agent, "Kore3.0");
+
+        // The arguments override both sources.
+        let config = ConfigSources::new(true, file)
+            .with_precedence(ConfigPrecedence::Env)
+            .with_args(vec![
+                "kore.network.port_reuse=false".to_owned(),
+                "kore.network.user_agent = Kore4.0".to_owned(),
+            ])
+            .build()
+            .unwrap();
+        assert!(!config.settings.network.port_reuse);
+        assert_eq!(config.settings.node.timeout, 40);
+        assert_eq!(config.settings.network.user_agent, "Kore4.0");
+
+        let Err(NodeError::Config(errors)) = ConfigSources::new(false, "")
+            .with_args(vec![
+                "kore.network.port_reuse".to_owned(),
+                "kore.node.timeout=soon".to_owned(),
+            ])
+            .build()
+        else {
+            panic!("invalid arguments accepted");
+        };
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].location, "--set kore.network.port_reuse");
+
+        std::env::set_var("KORE_CONFIG_PRECEDENCE", "Env");
+        assert_eq!(build_config_precedence().unwrap(), ConfigPrecedence::Env);
+        std::env::set_var("KORE_CONFIG_PRECEDENCE", "args");
+        assert!(matches!(
+            build_config_precedence(),
+            Err(NodeError::Config(_))
+        ));
+
+        std::env::remove_var("KORE_CONFIG_PRECEDENCE");
+        std::env::remove_var("KORE_NETWORK_PORT_REUSE");
+        std::env::remove_var("KORE_NODE_TIMEOUT");
+    }
+
+    #[test]
+    fn test_config_layers() {
+        let temp_dir = TempDir::new().unwrap();
+        let conf_d = temp_dir.path().join("conf.d");
+        std::fs::create_dir(&conf_d).unwrap();
+        let base = conf_d.join("10-base.toml");
+        std::fs::write(
+            &base,
+            "[kore.network]\nuser_agent = \"Kore3.0\"\n\n[kore.node]\ntimeout = 30\n",
+        )
+        .unwrap();
+        let overlay = conf_d.join("20-production.yaml");
+        std::fs::write(&overlay, "kore:\n  node:\n    timeout: 40\n").unwrap();
+        std::fs::write(conf_d.join("README.md"), "Not read").unwrap();
+        let secrets = temp_dir.path().join("secrets.json");
+        std::fs::write(&secrets, r#"{"kore": {"auth": {"api_keys": ["secret-key"]}}}"#).unwrap();
+
+        let list = std::env::join_paths([&base, &overlay, &secrets]).unwrap();
+        let directory = std::env::join_paths([&conf_d, &secrets]).unwrap();
+        for file in [list, directory] {
+            assert_eq!(
+                config_files(file.to_str().unwrap()).unwrap(),
+                vec![base.clone(), overlay.clone(), secrets.clone()]
+            );
+            let config =
+                build_config(false, file.to_str().unwrap(), ConfigPrecedence::File).unwrap();
+            assert_eq!(config.settings.network.user_agent, "Kore3.0");
+            assert_eq!(config.settings.node.timeout, 40);
+            assert_eq!(config.auth.api_keys, vec!["secret-key"]);
+        }
+        assert!(config_files("").unwrap().is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn test_build_password() {
+        std::env::remove_var("KORE_PASSWORD");
+        std::env::remove_var("KORE_PASSWORD_FILE");
+        std::env::remove_var("KORE_PASSWORD_COMMAND");
+        assert!(matches!(build_password(), Err(NodeError::Config(_))));
+
+        std::env::set_var("KORE_PASSWORD", "kore");
+        assert_eq!(build_password().unwrap(), "kore");
+        std::env::remove_var("KORE_PASSWORD");
+    }
 }
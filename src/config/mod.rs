@@ -1,3 +1,8 @@
 pub mod build;
 pub mod command;
 mod params;
+pub mod password;
+pub mod render;
+pub mod units;
+pub mod validate;
+pub mod watcher;
@@ -1,25 +1,145 @@
-use std::{time::Duration, vec};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+    time::Duration,
+    vec,
+};
 
 use kore_base::{NodeSettings, NodeType, RoutingNode};
-use serde::{Deserialize, Deserializer};
-
-use crate::settings::{DbSettings, KoreSettings};
+use libp2p_identity::PeerId;
+use multiaddr::Multiaddr;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+
+use super::units::{
+    deserialize_duration_millis, deserialize_duration_secs, deserialize_size, parse_duration,
+};
+use super::validate::multiaddr;
+use crate::error::ConfigError;
+use crate::settings::{
+    default_data_path, AccessLogSettings, ApiAuthSettings, ApiCallSettings, ApprovalRule,
+    ArchivalSettings, AuthSettings, BackupSettings, BootGroup, BootstrapSettings, CallLimit,
+    DbBatchSettings, DbSettings, DbTtlSettings, GrpcSettings, KeyKdf, KeysBackend, KeysSettings,
+    KoreSettings, LimitsSettings, LogFormat, LoggingSettings, MetricsPushMode, MetricsPushSettings,
+    Pkcs11Settings, ReplicationMode, ReplicationSettings, Schedule, ServicesSettings,
+    SignatureCheck, SigningPolicy, SoakSettings, SubjectQuota, SupervisorSettings, TimestampFormat,
+    VaultEngine, VaultSettings, WarmUpSettings, WebhookSettings,
+};
 
 #[derive(Debug, Deserialize, Default)]
 pub struct Params {
     kore: KoreParams,
+    /// Keys written in the source, empty for the environment, which never overrides the file.
+    #[serde(skip)]
+    explicit: Explicit,
 }
 
 impl Params {
-    pub fn from_env() -> Self {
-        Self {
-            kore: KoreParams::from_env("KORE"),
+    pub fn from_env() -> Result<Self, Vec<ConfigError>> {
+        Ok(Self {
+            kore: KoreParams::from_env("KORE")?,
+            explicit: Explicit::default(),
+        })
+    }
+
+    pub fn from_file(file: &str) -> Result<Self, Vec<ConfigError>> {
+        Self::from_source(file, config::File::with_name(file))
+    }
+
+    /// Parameters of the command line, each one a `<key>=<value>` pair such as
+    /// `kore.network.port_reuse=false`. The value is read as a TOML value, or as a string when
+    /// it is not one.
+    ///
+    /// # Errors
+    ///
+    /// * `ConfigError` - A pair without key, or a value of the wrong type.
+    ///
+    pub fn from_args(args: &[String]) -> Result<Self, Vec<ConfigError>> {
+        if args.is_empty() {
+            return Ok(Self::default());
+        }
+        let mut lines = vec![];
+        let mut errors = vec![];
+        for arg in args {
+            match arg.split_once('=') {
+                Some((key, value)) if is_arg_key(key.trim()) => {
+                    let (key, value) = (key.trim(), value.trim());
+                    let line = format!("{key} = {value}");
+                    if !value.contains(['\n', '\r']) && parse_toml(&line) {
+                        lines.push(line);
+                    } else {
+                        let value = serde_json::Value::from(value);
+                        lines.push(format!("{key} = {value}"));
+                    }
+                }
+                _ => errors.push(ConfigError::new(
+                    format!("--set {arg}"),
+                    "is not <key>=<value>, with a dotted key such as kore.network.port_reuse",
+                )),
+            }
         }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let source = config::File::from_str(&lines.join("\n"), config::FileFormat::Toml);
+        Self::from_source("command line", source)
+    }
+
+    /// Parameters of a configuration source, with the keys it writes.
+    fn from_source<T>(location: &str, source: T) -> Result<Self, Vec<ConfigError>>
+    where
+        T: config::Source + Send + Sync + 'static,
+    {
+        let config = config::Config::builder()
+            .add_source(source)
+            .build()
+            .map_err(|error| vec![ConfigError::new(location, error.to_string())])?;
+        let written = config
+            .clone()
+            .try_deserialize::<serde_json::Value>()
+            .map_err(|error| vec![ConfigError::new(location, error.to_string())])?;
+        let mut params: Self = config
+            .try_deserialize()
+            .map_err(|error| vec![ConfigError::new(location, error.to_string())])?;
+        params.explicit = Explicit::of(&written);
+        Ok(params)
+    }
+
+    /// Forget the keys that the `KORE_*` environment variables also set, so that the
+    /// environment takes precedence over this source when they are mixed.
+    pub fn yield_to_env(mut self) -> Self {
+        let vars: Vec<String> = std::env::vars()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with("KORE_"))
+            .collect();
+        self.explicit.0.retain(|key| {
+            let name = key.to_uppercase().replace('.', "_");
+            !vars.iter().any(|var| match var.strip_prefix(&name) {
+                Some("") => true,
+                // Indexed variables, such as the boot nodes.
+                Some(rest) => rest
+                    .strip_prefix('_')
+                    .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit())),
+                None => false,
+            })
+        });
+        self
     }
 
+    /// Mix two sources, the values written in `other_config` taking precedence over the
+    /// values of this one, even when they are the defaults. The keys written in either source
+    /// are written in the result.
     pub fn mix_config(&self, other_config: Params) -> Self {
         Self {
-            kore: self.kore.mix_config(other_config.kore),
+            kore: self
+                .kore
+                .mix_config(other_config.kore, &other_config.explicit.scope("kore")),
+            explicit: Explicit(
+                self.explicit
+                    .0
+                    .union(&other_config.explicit.0)
+                    .cloned()
+                    .collect(),
+            ),
         }
     }
 }
@@ -53,16 +173,184 @@ impl From<Params> for KoreSettings {
             .with_service_allow_list(params.kore.network.control_list.service_allow_list)
             .with_service_block_list(params.kore.network.control_list.service_block_list);
 
+        let db = params.kore.db.with_legacy_path(&params.kore.db_path);
+
         Self {
-            db: params.kore.db_path,
+            db: db.settings(params.kore.db_read_pool_size),
+            db_options: db.options,
+            db_read_pool_size: params.kore.db_read_pool_size,
+            db_batch: DbBatchSettings {
+                max_writes: params.kore.db_batch.max_writes,
+                sync: params.kore.db_batch.sync,
+            },
+            db_ttl: DbTtlSettings {
+                sweep_interval: params.kore.db_ttl.sweep_interval,
+                collections: params.kore.db_ttl.collections,
+            },
+            db_encryption: params.kore.db_encryption,
+            db_encryption_key: params.kore.db_encryption_key,
+            listen_fallback_ports: params.kore.network.listen_fallback_ports,
+            bootstrap: BootstrapSettings {
+                groups: params.kore.network.bootstrap.groups,
+                probe_timeout: params.kore.network.bootstrap.probe_timeout,
+                health_interval: params.kore.network.bootstrap.health_interval,
+                dns_refresh: params.kore.network.bootstrap.dns_refresh,
+            },
+            subject_quota: SubjectQuota {
+                max_subjects: params.kore.quota.max_subjects,
+                window: params.kore.quota.window,
+            },
+            signature_check: SignatureCheck {
+                enabled: params.kore.signature_check.enabled,
+                max_age: params.kore.signature_check.max_age,
+                max_future: params.kore.signature_check.max_future,
+            },
+            api: ApiCallSettings {
+                timeout: Duration::from_millis(params.kore.api.timeout_ms.into()),
+                retries: params.kore.api.retries,
+                backoff: params.kore.api.backoff,
+            },
+            limits: LimitsSettings {
+                reads: CallLimit {
+                    rate: params.kore.limits.read_rate,
+                    burst: params.kore.limits.read_burst,
+                    max_in_flight: params.kore.limits.read_max_in_flight,
+                },
+                writes: CallLimit {
+                    rate: params.kore.limits.write_rate,
+                    burst: params.kore.limits.write_burst,
+                    max_in_flight: params.kore.limits.write_max_in_flight,
+                },
+            },
+            access_log: AccessLogSettings {
+                sample_rate: params.kore.access_log.sample_rate,
+                slow_threshold: params.kore.access_log.slow_threshold,
+            },
+            logging: LoggingSettings {
+                level: params.kore.logging.level,
+                targets: params.kore.logging.targets,
+                format: params.kore.logging.format,
+                file: params.kore.logging.file,
+                max_file_size: params.kore.logging.max_file_size,
+                max_files: params.kore.logging.max_files,
+            },
+            schedules: params.kore.schedules,
+            signing_policies: params.kore.signing_policies,
+            approval_rules: params.kore.approval_rules,
             keys_path: params.kore.keys_path,
+            regenerate_corrupted_keys: params.kore.regenerate_corrupted_keys,
+            migrate_legacy_data: params.kore.migrate_legacy_data,
+            lifecycle_events: params.kore.lifecycle_events,
+            keys: KeysSettings {
+                kdf: params.kore.keys.kdf,
+                iterations: params.kore.keys.iterations,
+                scrypt_log_n: params.kore.keys.scrypt_log_n,
+                scrypt_r: params.kore.keys.scrypt_r,
+                scrypt_p: params.kore.keys.scrypt_p,
+                vault: VaultSettings {
+                    address: params.kore.keys.vault.address,
+                    token: params.kore.keys.vault.token,
+                    role_id: params.kore.keys.vault.role_id,
+                    secret_id: params.kore.keys.vault.secret_id,
+                    engine: params.kore.keys.vault.engine,
+                    mount: params.kore.keys.vault.mount,
+                    path: params.kore.keys.vault.path,
+                },
+            },
+            keys_backend: params.kore.keys_backend,
+            timestamp_format: params.kore.timestamp_format,
+            pkcs11: Pkcs11Settings {
+                module: params.kore.pkcs11.module,
+                token: params.kore.pkcs11.token,
+                label: params.kore.pkcs11.label,
+            },
             prometheus: params.kore.prometheus,
+            metrics_push: MetricsPushSettings {
+                url: params.kore.metrics.push.url,
+                mode: params.kore.metrics.push.mode,
+                interval: params.kore.metrics.push.interval,
+                job: params.kore.metrics.push.job,
+                labels: params.kore.metrics.push.labels,
+                username: params.kore.metrics.push.username,
+                password: params.kore.metrics.push.password,
+            },
+            http_api: params.kore.http_api,
+            grpc: GrpcSettings {
+                listen: params.kore.grpc.listen,
+                tls_cert: params.kore.grpc.tls_cert,
+                tls_key: params.kore.grpc.tls_key,
+            },
+            webhooks: WebhookSettings {
+                urls: params.kore.webhooks.urls,
+                secret: params.kore.webhooks.secret,
+                max_retries: params.kore.webhooks.max_retries,
+                retry_backoff: params.kore.webhooks.retry_backoff,
+            },
+            warm_up: WarmUpSettings {
+                subjects: params.kore.warm_up.subjects,
+                recent: params.kore.warm_up.recent,
+                timeout: params.kore.warm_up.timeout,
+            },
+            supervisor: SupervisorSettings {
+                enabled: params.kore.supervisor.enabled,
+                max_restarts: params.kore.supervisor.max_restarts,
+                window: params.kore.supervisor.window,
+                backoff: params.kore.supervisor.backoff,
+                max_backoff: params.kore.supervisor.max_backoff,
+            },
+            api_auth: ApiAuthSettings {
+                public_token: params.kore.api_auth.public_token,
+                admin_token: params.kore.api_auth.admin_token,
+            },
+            auth: AuthSettings {
+                api_keys: params.kore.auth.api_keys,
+                issuer: params.kore.auth.issuer,
+                audience: params.kore.auth.audience,
+                jwks_url: params.kore.auth.jwks_url,
+            },
+            services: ServicesSettings {
+                rest_url: params.kore.services.rest_url,
+                metrics_url: params.kore.services.metrics_url,
+                peers: params.kore.services.peers,
+                interval: params.kore.services.interval,
+            },
+            backup: BackupSettings {
+                directory: params.kore.backup.directory,
+                interval: params.kore.backup.interval,
+                keep: params.kore.backup.keep,
+            },
+            archival: ArchivalSettings {
+                destination: params.kore.archival.destination,
+                max_age: params.kore.archival.max_age,
+                max_size: params.kore.archival.max_size,
+                interval: params.kore.archival.interval,
+            },
+            soak: SoakSettings {
+                rate: params.kore.soak.rate,
+                duration: params.kore.soak.duration,
+                governance_id: params.kore.soak.governance_id,
+                schema_id: params.kore.soak.schema_id,
+                subjects: params.kore.soak.subjects,
+                payload: params.kore.soak.payload,
+                max_in_flight: params.kore.soak.max_in_flight,
+                report_interval: params.kore.soak.report_interval,
+            },
+            replication: ReplicationSettings {
+                remote_url: params.kore.replication.remote_url,
+                token: params.kore.replication.token,
+                subjects: params.kore.replication.subjects,
+                mode: params.kore.replication.mode,
+                poll_interval: params.kore.replication.poll_interval,
+                request_timeout: params.kore.replication.request_timeout,
+                max_attempts: params.kore.replication.max_attempts,
+            },
+            features: params.kore.features,
             settings: kore_base::Settings {
                 network: kore_base::NetworkConfig {
                     user_agent: params.kore.network.user_agent,
                     node_type: params.kore.network.node_type,
-                    listen_addresses: params.kore.network.listen_addresses,
-                    external_addresses: params.kore.network.external_addresses,
+                    listen_addresses: to_strings(params.kore.network.listen_addresses),
+                    external_addresses: to_strings(params.kore.network.external_addresses),
                     tell,
                     routing,
                     port_reuse: params.kore.network.port_reuse,
@@ -84,95 +372,526 @@ impl From<Params> for KoreSettings {
     }
 }
 
+/// Keys written in a source, as dotted paths to its tables and values, so that a value equal to
+/// its default still overrides the other source when it is written.
+#[derive(Debug, Clone, Default)]
+struct Explicit(BTreeSet<String>);
+
+impl Explicit {
+    /// Keys of a configuration tree. Arrays are values, their items are not keys.
+    fn of(value: &serde_json::Value) -> Self {
+        fn collect(value: &serde_json::Value, path: &str, keys: &mut BTreeSet<String>) {
+            if let serde_json::Value::Object(table) = value {
+                for (key, value) in table {
+                    let key = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{path}.{key}")
+                    };
+                    collect(value, &key, keys);
+                    keys.insert(key);
+                }
+            }
+        }
+        let mut keys = BTreeSet::new();
+        collect(value, "", &mut keys);
+        Self(keys)
+    }
+
+    /// Keys under a table, relative to it.
+    fn scope(&self, table: &str) -> Self {
+        let prefix = format!("{table}.");
+        Self(
+            self.0
+                .iter()
+                .filter_map(|key| key.strip_prefix(&prefix).map(str::to_owned))
+                .collect(),
+        )
+    }
+
+    /// Whether a key is written.
+    fn has(&self, key: &str) -> bool {
+        self.0.contains(key)
+    }
+
+    /// Value of the other source when its key is written, the current one otherwise.
+    fn pick<T>(&self, key: &str, other: T, current: T) -> T {
+        if self.has(key) {
+            other
+        } else {
+            current
+        }
+    }
+}
+
+/// Whether a key of the command line is a dotted path of names.
+fn is_arg_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.split('.').all(|name| {
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        })
+}
+
+/// Whether a line is a valid TOML document.
+fn parse_toml(line: &str) -> bool {
+    config::Config::builder()
+        .add_source(config::File::from_str(line, config::FileFormat::Toml))
+        .build()
+        .is_ok()
+}
+
+/// Deserialize the parameters given by the environment variables under `prefix`.
+fn deserialize_env<T: DeserializeOwned>(
+    prefix: &str,
+    source: config::Environment,
+) -> Result<T, Vec<ConfigError>> {
+    config::Config::builder()
+        .add_source(source)
+        .build()
+        .and_then(|config| config.try_deserialize())
+        .map_err(|error| vec![ConfigError::new(format!("{prefix}_*"), error.to_string())])
+}
+
+/// Keep the value, or its errors so that they are reported along with the rest.
+fn collect<T>(result: Result<T, Vec<ConfigError>>, errors: &mut Vec<ConfigError>) -> Option<T> {
+    result.map_err(|error| errors.extend(error)).ok()
+}
+
 #[derive(Debug, Deserialize)]
 struct KoreParams {
     #[serde(default)]
     network: NetworkParams,
     #[serde(default)]
     node: NodeParams,
-    #[serde(default = "default_db_path", deserialize_with = "deserialize_db_path")]
-    db_path: DbSettings,
+    #[serde(default)]
+    db: DbParams,
+    #[serde(default)]
+    db_path: String,
+    #[serde(default = "default_db_read_pool_size")]
+    db_read_pool_size: usize,
+    #[serde(default)]
+    db_batch: DbBatchParams,
+    #[serde(default)]
+    db_ttl: DbTtlParams,
+    #[serde(default)]
+    db_encryption: bool,
+    #[serde(default)]
+    db_encryption_key: String,
     #[serde(default = "default_keys_path")]
     keys_path: String,
+    #[serde(default)]
+    regenerate_corrupted_keys: bool,
+    #[serde(default)]
+    migrate_legacy_data: bool,
+    #[serde(default)]
+    lifecycle_events: bool,
+    #[serde(default)]
+    keys: KeysParams,
+    #[serde(default = "default_keys_backend")]
+    keys_backend: KeysBackend,
+    #[serde(default = "default_timestamp_format")]
+    timestamp_format: TimestampFormat,
+    #[serde(default)]
+    pkcs11: Pkcs11Params,
     #[serde(default = "default_prometheus")]
     prometheus: String,
+    #[serde(default)]
+    http_api: String,
+    #[serde(default)]
+    metrics: MetricsParams,
+    #[serde(default)]
+    api_auth: ApiAuthParams,
+    #[serde(default)]
+    auth: AuthParams,
+    #[serde(default)]
+    grpc: GrpcParams,
+    #[serde(default)]
+    webhooks: WebhookParams,
+    #[serde(default)]
+    services: ServicesParams,
+    #[serde(default)]
+    warm_up: WarmUpParams,
+    #[serde(default)]
+    supervisor: SupervisorParams,
+    #[serde(default)]
+    backup: BackupParams,
+    #[serde(default)]
+    archival: ArchivalParams,
+    #[serde(default)]
+    soak: SoakParams,
+    #[serde(default)]
+    replication: ReplicationParams,
+    #[serde(default, deserialize_with = "deserialize_feature_flags")]
+    features: BTreeMap<String, bool>,
+    #[serde(default)]
+    quota: QuotaParams,
+    #[serde(default)]
+    signature_check: SignatureCheckParams,
+    #[serde(default)]
+    api: ApiCallParams,
+    #[serde(default)]
+    limits: LimitsParams,
+    #[serde(default)]
+    access_log: AccessLogParams,
+    #[serde(default)]
+    logging: LoggingParams,
+    #[serde(default)]
+    schedules: Vec<Schedule>,
+    #[serde(default)]
+    signing_policies: Vec<SigningPolicy>,
+    #[serde(default)]
+    approval_rules: Vec<ApprovalRule>,
 }
 
 impl KoreParams {
-    fn from_env(parent: &str) -> Self {
-        let mut config = config::Config::builder();
-        config = config.add_source(config::Environment::with_prefix(parent));
-
-        let config = config
-            .build()
-            .map_err(|e| {
-                println!("Error building config: {}", e);
-            })
-            .unwrap();
-
-        let kore_params: KoreParams = config
-            .try_deserialize()
-            .map_err(|e| {
-                println!("Error try deserialize config: {}", e);
-            })
-            .unwrap();
-
-        Self {
-            network: NetworkParams::from_env(&format!("{parent}_")),
-            node: NodeParams::from_env(&format!("{parent}_")),
-            db_path: kore_params.db_path,
-            keys_path: kore_params.keys_path,
-            prometheus: kore_params.prometheus,
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let mut errors = vec![];
+        let kore_params = collect(
+            deserialize_env::<KoreParams>(parent, config::Environment::with_prefix(parent)),
+            &mut errors,
+        );
+        let schedules = collect(env_schedules(parent), &mut errors);
+        let signing_policies = collect(env_signing_policies(parent), &mut errors);
+        let approval_rules = collect(env_approval_rules(parent), &mut errors);
+        let parent = &format!("{parent}_");
+        let network = collect(NetworkParams::from_env(parent), &mut errors);
+        let node = collect(NodeParams::from_env(parent), &mut errors);
+        let db = collect(DbParams::from_env(parent), &mut errors);
+        let db_batch = collect(DbBatchParams::from_env(parent), &mut errors);
+        let db_ttl = collect(DbTtlParams::from_env(parent), &mut errors);
+        let quota = collect(QuotaParams::from_env(parent), &mut errors);
+        let signature_check = collect(SignatureCheckParams::from_env(parent), &mut errors);
+        let api = collect(ApiCallParams::from_env(parent), &mut errors);
+        let limits = collect(LimitsParams::from_env(parent), &mut errors);
+        let access_log = collect(AccessLogParams::from_env(parent), &mut errors);
+        let logging = collect(LoggingParams::from_env(parent), &mut errors);
+        let metrics = collect(MetricsParams::from_env(parent), &mut errors);
+        let api_auth = collect(ApiAuthParams::from_env(parent), &mut errors);
+        let auth = collect(AuthParams::from_env(parent), &mut errors);
+        let grpc = collect(GrpcParams::from_env(parent), &mut errors);
+        let webhooks = collect(WebhookParams::from_env(parent), &mut errors);
+        let services = collect(ServicesParams::from_env(parent), &mut errors);
+        let warm_up = collect(WarmUpParams::from_env(parent), &mut errors);
+        let supervisor = collect(SupervisorParams::from_env(parent), &mut errors);
+        let backup = collect(BackupParams::from_env(parent), &mut errors);
+        let archival = collect(ArchivalParams::from_env(parent), &mut errors);
+        let soak = collect(SoakParams::from_env(parent), &mut errors);
+        let replication = collect(ReplicationParams::from_env(parent), &mut errors);
+        let keys = collect(KeysParams::from_env(parent), &mut errors);
+        let pkcs11 = collect(Pkcs11Params::from_env(parent), &mut errors);
+
+        match (
+            kore_params,
+            network,
+            node,
+            db,
+            db_batch,
+            db_ttl,
+            quota,
+            signature_check,
+            api,
+            limits,
+            access_log,
+            logging,
+            metrics,
+            api_auth,
+            auth,
+            grpc,
+            webhooks,
+            services,
+            warm_up,
+            supervisor,
+            backup,
+            archival,
+            soak,
+            replication,
+            keys,
+            pkcs11,
+            schedules,
+            signing_policies,
+            approval_rules,
+        ) {
+            (
+                Some(kore_params),
+                Some(network),
+                Some(node),
+                Some(db),
+                Some(db_batch),
+                Some(db_ttl),
+                Some(quota),
+                Some(signature_check),
+                Some(api),
+                Some(limits),
+                Some(access_log),
+                Some(logging),
+                Some(metrics),
+                Some(api_auth),
+                Some(auth),
+                Some(grpc),
+                Some(webhooks),
+                Some(services),
+                Some(warm_up),
+                Some(supervisor),
+                Some(backup),
+                Some(archival),
+                Some(soak),
+                Some(replication),
+                Some(keys),
+                Some(pkcs11),
+                Some(schedules),
+                Some(signing_policies),
+                Some(approval_rules),
+            ) => Ok(Self {
+                network,
+                node,
+                db,
+                db_path: kore_params.db_path,
+                db_read_pool_size: kore_params.db_read_pool_size,
+                db_batch,
+                db_ttl,
+                db_encryption: kore_params.db_encryption,
+                db_encryption_key: kore_params.db_encryption_key,
+                keys_path: kore_params.keys_path,
+                regenerate_corrupted_keys: kore_params.regenerate_corrupted_keys,
+                migrate_legacy_data: kore_params.migrate_legacy_data,
+                lifecycle_events: kore_params.lifecycle_events,
+                keys,
+                keys_backend: kore_params.keys_backend,
+                timestamp_format: kore_params.timestamp_format,
+                pkcs11,
+                prometheus: kore_params.prometheus,
+                http_api: kore_params.http_api,
+                metrics,
+                api_auth,
+                auth,
+                grpc,
+                webhooks,
+                services,
+                warm_up,
+                supervisor,
+                backup,
+                archival,
+                soak,
+                replication,
+                features: kore_params.features,
+                quota,
+                signature_check,
+                api,
+                limits,
+                access_log,
+                logging,
+                schedules,
+                signing_policies,
+                approval_rules,
+            }),
+            _ => Err(errors),
         }
     }
 
-    fn mix_config(&self, other_config: KoreParams) -> Self {
-        let keys_path = if other_config.keys_path != default_keys_path() {
-            other_config.keys_path
-        } else {
-            self.keys_path.clone()
-        };
-        let db_path = if other_config.db_path != default_db_path() {
-            other_config.db_path
-        } else {
-            self.db_path.clone()
-        };
-        let prometheus = if other_config.prometheus != default_prometheus() {
-            other_config.prometheus
-        } else {
-            self.prometheus.clone()
-        };
+    fn mix_config(&self, other_config: KoreParams, explicit: &Explicit) -> Self {
+        let keys_path = explicit.pick("keys_path", other_config.keys_path, self.keys_path.clone());
+        let keys_backend =
+            explicit.pick("keys_backend", other_config.keys_backend, self.keys_backend);
+        let timestamp_format = explicit.pick(
+            "timestamp_format",
+            other_config.timestamp_format,
+            self.timestamp_format,
+        );
+        // Each source keeps its legacy path, so that it does not override the other source.
+        let mut db_explicit = explicit.scope("db");
+        if explicit.has("db_path") {
+            db_explicit.0.extend(["path".to_owned(), "url".to_owned()]);
+        }
+        let db = self.db.with_legacy_path(&self.db_path).mix_config(
+            other_config.db.with_legacy_path(&other_config.db_path),
+            &db_explicit,
+        );
+        let db_read_pool_size = explicit.pick(
+            "db_read_pool_size",
+            other_config.db_read_pool_size,
+            self.db_read_pool_size,
+        );
+        let db_encryption_key = explicit.pick(
+            "db_encryption_key",
+            other_config.db_encryption_key,
+            self.db_encryption_key.clone(),
+        );
+        let prometheus = explicit.pick(
+            "prometheus",
+            other_config.prometheus,
+            self.prometheus.clone(),
+        );
+        let http_api = explicit.pick("http_api", other_config.http_api, self.http_api.clone());
+        let schedules = explicit.pick("schedules", other_config.schedules, self.schedules.clone());
+        let features = explicit.pick("features", other_config.features, self.features.clone());
+        let signing_policies = explicit.pick(
+            "signing_policies",
+            other_config.signing_policies,
+            self.signing_policies.clone(),
+        );
+        let approval_rules = explicit.pick(
+            "approval_rules",
+            other_config.approval_rules,
+            self.approval_rules.clone(),
+        );
         Self {
-            network: self.network.mix_config(other_config.network),
-            node: self.node.mix_config(other_config.node),
-            db_path,
+            network: self
+                .network
+                .mix_config(other_config.network, &explicit.scope("network")),
+            node: self
+                .node
+                .mix_config(other_config.node, &explicit.scope("node")),
+            db,
+            db_path: String::default(),
+            db_read_pool_size,
+            db_batch: self
+                .db_batch
+                .mix_config(other_config.db_batch, &explicit.scope("db_batch")),
+            db_ttl: self
+                .db_ttl
+                .mix_config(other_config.db_ttl, &explicit.scope("db_ttl")),
+            db_encryption: explicit.pick(
+                "db_encryption",
+                other_config.db_encryption,
+                self.db_encryption,
+            ),
+            db_encryption_key,
             keys_path,
+            regenerate_corrupted_keys: explicit.pick(
+                "regenerate_corrupted_keys",
+                other_config.regenerate_corrupted_keys,
+                self.regenerate_corrupted_keys,
+            ),
+            migrate_legacy_data: explicit.pick(
+                "migrate_legacy_data",
+                other_config.migrate_legacy_data,
+                self.migrate_legacy_data,
+            ),
+            lifecycle_events: explicit.pick(
+                "lifecycle_events",
+                other_config.lifecycle_events,
+                self.lifecycle_events,
+            ),
+            keys: self
+                .keys
+                .mix_config(other_config.keys, &explicit.scope("keys")),
+            keys_backend,
+            timestamp_format,
+            pkcs11: self
+                .pkcs11
+                .mix_config(other_config.pkcs11, &explicit.scope("pkcs11")),
             prometheus,
+            metrics: self
+                .metrics
+                .mix_config(other_config.metrics, &explicit.scope("metrics")),
+            http_api,
+            grpc: self
+                .grpc
+                .mix_config(other_config.grpc, &explicit.scope("grpc")),
+            webhooks: self
+                .webhooks
+                .mix_config(other_config.webhooks, &explicit.scope("webhooks")),
+            api_auth: self
+                .api_auth
+                .mix_config(other_config.api_auth, &explicit.scope("api_auth")),
+            auth: self
+                .auth
+                .mix_config(other_config.auth, &explicit.scope("auth")),
+            services: self
+                .services
+                .mix_config(other_config.services, &explicit.scope("services")),
+            warm_up: self
+                .warm_up
+                .mix_config(other_config.warm_up, &explicit.scope("warm_up")),
+            supervisor: self
+                .supervisor
+                .mix_config(other_config.supervisor, &explicit.scope("supervisor")),
+            backup: self
+                .backup
+                .mix_config(other_config.backup, &explicit.scope("backup")),
+            archival: self
+                .archival
+                .mix_config(other_config.archival, &explicit.scope("archival")),
+            soak: self
+                .soak
+                .mix_config(other_config.soak, &explicit.scope("soak")),
+            replication: self
+                .replication
+                .mix_config(other_config.replication, &explicit.scope("replication")),
+            features,
+            quota: self
+                .quota
+                .mix_config(other_config.quota, &explicit.scope("quota")),
+            signature_check: self.signature_check.mix_config(
+                other_config.signature_check,
+                &explicit.scope("signature_check"),
+            ),
+            api: self
+                .api
+                .mix_config(other_config.api, &explicit.scope("api")),
+            limits: self
+                .limits
+                .mix_config(other_config.limits, &explicit.scope("limits")),
+            access_log: self
+                .access_log
+                .mix_config(other_config.access_log, &explicit.scope("access_log")),
+            logging: self
+                .logging
+                .mix_config(other_config.logging, &explicit.scope("logging")),
+            schedules,
+            signing_policies,
+            approval_rules,
         }
     }
 }
 
-fn deserialize_db_path<'de, D>(deserializer: D) -> Result<DbSettings, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s: String = String::deserialize(deserializer)?;
-    #[cfg(feature = "leveldb")]
-    return Ok(DbSettings::LevelDB(s));
-    #[cfg(feature = "sqlite")]
-    return Ok(DbSettings::Sqlite(s));
-    #[cfg(feature = "cassandra")]
-    return DbSettings::Cassandra;
-}
-
 impl Default for KoreParams {
     fn default() -> Self {
         Self {
             network: NetworkParams::default(),
             node: NodeParams::default(),
-            db_path: default_db_path(),
+            db: DbParams::default(),
+            db_path: String::default(),
+            db_read_pool_size: default_db_read_pool_size(),
+            db_batch: DbBatchParams::default(),
+            db_ttl: DbTtlParams::default(),
+            db_encryption: false,
+            db_encryption_key: String::default(),
             keys_path: default_keys_path(),
+            regenerate_corrupted_keys: false,
+            migrate_legacy_data: false,
+            lifecycle_events: false,
+            keys: KeysParams::default(),
+            keys_backend: default_keys_backend(),
+            timestamp_format: default_timestamp_format(),
+            pkcs11: Pkcs11Params::default(),
             prometheus: default_prometheus(),
+            metrics: MetricsParams::default(),
+            http_api: String::default(),
+            grpc: GrpcParams::default(),
+            webhooks: WebhookParams::default(),
+            api_auth: ApiAuthParams::default(),
+            auth: AuthParams::default(),
+            services: ServicesParams::default(),
+            warm_up: WarmUpParams::default(),
+            supervisor: SupervisorParams::default(),
+            backup: BackupParams::default(),
+            archival: ArchivalParams::default(),
+            soak: SoakParams::default(),
+            replication: ReplicationParams::default(),
+            features: BTreeMap::new(),
+            quota: QuotaParams::default(),
+            signature_check: SignatureCheckParams::default(),
+            api: ApiCallParams::default(),
+            limits: LimitsParams::default(),
+            access_log: AccessLogParams::default(),
+            logging: LoggingParams::default(),
+            schedules: vec![],
+            signing_policies: vec![],
+            approval_rules: vec![],
         }
     }
 }
@@ -181,189 +900,1961 @@ fn default_prometheus() -> String {
     "0.0.0.0:3050".to_owned()
 }
 
-fn default_db_path() -> DbSettings {
+fn default_db_read_pool_size() -> usize {
+    4
+}
+
+/// Database backends compiled in the binary. Other names are rejected when they are read.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum DbType {
     #[cfg(feature = "leveldb")]
-    return DbSettings::LevelDB("examples/leveldb".to_owned());
+    #[serde(rename = "leveldb")]
+    LevelDB,
     #[cfg(feature = "sqlite")]
-    return DbSettings::Sqlite("examples/sqlitedb".to_owned());
-    #[cfg(feature = "cassandra")]
-    return DbSettings::Cassandra;
+    Sqlite,
+    #[cfg(feature = "postgres")]
+    Postgres,
+    #[cfg(feature = "sled")]
+    Sled,
+    #[cfg(feature = "redb")]
+    Redb,
 }
 
-fn default_keys_path() -> String {
-    "examples/keys".to_owned()
+/// Backend used when `kore.db.type` is not set: the first compiled of LevelDB, SQLite,
+/// PostgreSQL, sled and redb, as when a single path was given.
+#[allow(unreachable_code)]
+fn default_db_type() -> DbType {
+    #[cfg(feature = "leveldb")]
+    return DbType::LevelDB;
+    #[cfg(feature = "sqlite")]
+    return DbType::Sqlite;
+    #[cfg(feature = "postgres")]
+    return DbType::Postgres;
+    #[cfg(feature = "sled")]
+    return DbType::Sled;
+    #[cfg(feature = "redb")]
+    return DbType::Redb;
 }
 
-#[derive(Debug, Deserialize)]
-struct NetworkParams {
-    #[serde(default = "default_user_agent")]
-    user_agent: String,
-    #[serde(default = "default_node_type")]
-    node_type: NodeType,
-    #[serde(default)]
-    listen_addresses: Vec<String>,
-    #[serde(default)]
-    external_addresses: Vec<String>,
+#[derive(Debug, Deserialize, Clone, Default)]
+struct DbParams {
+    #[serde(default, rename = "type")]
+    db_type: Option<DbType>,
     #[serde(default)]
-    tell: TellParams,
-    #[serde(default)]
-    routing: RoutingParams,
-    #[serde(default)]
-    port_reuse: bool,
+    path: String,
     #[serde(default)]
-    control_list: ControlListParams,
+    url: String,
+    #[serde(default, deserialize_with = "deserialize_db_options")]
+    options: BTreeMap<String, String>,
 }
 
-impl NetworkParams {
-    fn from_env(parent: &str) -> Self {
-        let mut config = config::Config::builder();
-        config = config.add_source(
-            config::Environment::with_prefix(&format!("{parent}NETWORK"))
-                .list_separator(",")
-                .with_list_parse_key("listen_addresses")
-                .try_parsing(true)
-                .list_separator(",")
-                .with_list_parse_key("external_addresses")
-                .try_parsing(true),
-        );
-
-        let config = config
-            .build()
-            .map_err(|e| {
-                println!("Error building config: {}", e);
-            })
-            .unwrap();
-
-        let network: NetworkParams = config
-            .try_deserialize()
-            .map_err(|e| {
-                println!("Error try deserialize config: {}", e);
-            })
-            .unwrap();
+impl DbParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}DB");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
 
-        let parent = &format!("{parent}NETWORK_");
+    /// Fill the path and URL that are not set with the legacy `kore.db_path`, which held
+    /// either of them depending on the compiled backend.
+    fn with_legacy_path(&self, db_path: &str) -> Self {
+        let pick = |value: &String| {
+            if value.is_empty() {
+                db_path.to_owned()
+            } else {
+                value.clone()
+            }
+        };
         Self {
-            user_agent: network.user_agent,
-            node_type: network.node_type,
-            listen_addresses: network.listen_addresses,
-            external_addresses: network.external_addresses,
-            tell: TellParams::from_env(parent),
-            routing: RoutingParams::from_env(parent),
-            port_reuse: network.port_reuse,
-            control_list: ControlListParams::from_env(parent),
+            db_type: self.db_type,
+            path: pick(&self.path),
+            url: pick(&self.url),
+            options: self.options.clone(),
         }
     }
 
-    fn mix_config(&self, other_config: NetworkParams) -> Self {
-        let user_agent = if other_config.user_agent != default_user_agent() {
-            other_config.user_agent
-        } else {
-            self.user_agent.clone()
-        };
+    fn mix_config(&self, other_config: DbParams, explicit: &Explicit) -> Self {
+        let options = explicit.pick("options", other_config.options, self.options.clone());
+        Self {
+            db_type: other_config.db_type.or(self.db_type),
+            path: explicit.pick("path", other_config.path, self.path.clone()),
+            url: explicit.pick("url", other_config.url, self.url.clone()),
+            options,
+        }
+    }
 
-        let node_type = if other_config.node_type != default_node_type() {
-            other_config.node_type
-        } else {
-            self.node_type.clone()
+    /// Settings of the chosen backend, with the default location when none is set.
+    #[cfg_attr(not(feature = "postgres"), allow(unused_variables))]
+    fn settings(&self, pool_size: usize) -> DbSettings {
+        let or_default = |value: &String, default: &str| {
+            if value.is_empty() {
+                default.to_owned()
+            } else {
+                value.clone()
+            }
         };
+        match self.db_type.unwrap_or_else(default_db_type) {
+            #[cfg(feature = "leveldb")]
+            DbType::LevelDB => {
+                DbSettings::LevelDB(or_default(&self.path, &default_data_path("leveldb")))
+            }
+            #[cfg(feature = "sqlite")]
+            DbType::Sqlite => {
+                DbSettings::Sqlite(or_default(&self.path, &default_data_path("sqlitedb")))
+            }
+            #[cfg(feature = "postgres")]
+            DbType::Postgres => DbSettings::Postgres {
+                url: or_default(&self.url, "postgres://postgres@localhost/kore"),
+                pool_size,
+            },
+            #[cfg(feature = "sled")]
+            DbType::Sled => DbSettings::Sled(or_default(&self.path, &default_data_path("sled"))),
+            #[cfg(feature = "redb")]
+            DbType::Redb => {
+                DbSettings::Redb(or_default(&self.path, &default_data_path("redb/database")))
+            }
+        }
+    }
+}
 
-        let listen_addresses = if !other_config.listen_addresses.is_empty() {
-            other_config.listen_addresses
-        } else {
-            self.listen_addresses.clone()
-        };
+/// Options of the backend, as a table in files or as `<name>=<value>,...` in env vars.
+fn deserialize_db_options<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Options {
+        Table(BTreeMap<String, String>),
+        Text(String),
+    }
+    match Options::deserialize(deserializer)? {
+        Options::Table(options) => Ok(options),
+        Options::Text(text) => text
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.split_once('=') {
+                Some((name, value)) => Ok((name.trim().to_owned(), value.trim().to_owned())),
+                None => Err(serde::de::Error::custom(format!(
+                    "'{}' is not <name>=<value>",
+                    pair
+                ))),
+            })
+            .collect(),
+    }
+}
 
-        let external_addresses = if !other_config.external_addresses.is_empty() {
-            other_config.external_addresses
-        } else {
-            self.external_addresses.clone()
-        };
+#[derive(Debug, Deserialize)]
+struct DbBatchParams {
+    #[serde(default = "default_db_batch_max_writes")]
+    max_writes: usize,
+    #[serde(default = "default_true")]
+    sync: bool,
+}
 
-        let port_reuse = if other_config.port_reuse {
-            other_config.port_reuse
-        } else {
-            self.port_reuse
-        };
+impl DbBatchParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}DB_BATCH");
+        deserialize_env(
+            &prefix,
+            config::Environment::with_prefix(&prefix).try_parsing(true),
+        )
+    }
 
+    fn mix_config(&self, other_config: DbBatchParams, explicit: &Explicit) -> Self {
+        let max_writes = explicit.pick("max_writes", other_config.max_writes, self.max_writes);
         Self {
-            user_agent,
-            node_type,
-            listen_addresses,
-            external_addresses,
-            tell: self.tell.mix_config(other_config.tell),
-            routing: self.routing.mix_config(other_config.routing),
-            port_reuse,
-            control_list: self.control_list.mix_config(other_config.control_list),
+            max_writes,
+            sync: explicit.pick("sync", other_config.sync, self.sync),
         }
     }
 }
 
-fn default_user_agent() -> String {
-    "kore-node".to_owned()
-}
-
-fn default_node_type() -> NodeType {
-    NodeType::Bootstrap
-}
-
-impl Default for NetworkParams {
+impl Default for DbBatchParams {
     fn default() -> Self {
         Self {
-            user_agent: default_user_agent(),
-            node_type: default_node_type(),
-            listen_addresses: vec![],
-            external_addresses: vec![],
-            tell: TellParams::default(),
-            routing: RoutingParams::default(),
-            port_reuse: false,
-            control_list: ControlListParams::default(),
+            max_writes: default_db_batch_max_writes(),
+            sync: true,
         }
     }
 }
 
+fn default_db_batch_max_writes() -> usize {
+    1000
+}
+
 #[derive(Debug, Deserialize)]
-struct ControlListParams {
-    #[serde(default)]
-    enable: bool,
-    #[serde(default)]
-    allow_list: Vec<String>,
-    #[serde(default)]
-    block_list: Vec<String>,
-    #[serde(default)]
-    service_allow_list: Vec<String>,
-    #[serde(default)]
-    service_block_list: Vec<String>,
+struct DbTtlParams {
     #[serde(
-        default = "default_interval_request_secs",
+        default = "default_db_ttl_sweep_interval",
         deserialize_with = "deserialize_duration_secs"
     )]
-    interval_request: Duration,
+    sweep_interval: Duration,
+    #[serde(default, deserialize_with = "deserialize_ttl_collections")]
+    collections: BTreeMap<String, Duration>,
 }
 
-impl Default for ControlListParams {
+impl DbTtlParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}DB_TTL");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
+
+    fn mix_config(&self, other_config: DbTtlParams, explicit: &Explicit) -> Self {
+        let sweep_interval = explicit.pick(
+            "sweep_interval",
+            other_config.sweep_interval,
+            self.sweep_interval,
+        );
+        let collections = explicit.pick(
+            "collections",
+            other_config.collections,
+            self.collections.clone(),
+        );
+        Self {
+            sweep_interval,
+            collections,
+        }
+    }
+}
+
+impl Default for DbTtlParams {
     fn default() -> Self {
         Self {
-            allow_list: vec![],
-            block_list: vec![],
-            enable: false,
-            interval_request: default_interval_request_secs(),
-            service_allow_list: vec![],
-            service_block_list: vec![],
+            sweep_interval: default_db_ttl_sweep_interval(),
+            collections: BTreeMap::new(),
         }
     }
 }
 
-fn default_interval_request_secs() -> Duration {
-    Duration::from_secs(60)
+fn default_db_ttl_sweep_interval() -> Duration {
+    Duration::from_secs(300)
 }
 
-impl ControlListParams {
-    fn from_env(parent: &str) -> Self {
-        let mut config = config::Config::builder();
-        config = config.add_source(
-            config::Environment::with_prefix(&format!("{parent}CONTROL_LIST"))
-                .list_separator(",")
-                .with_list_parse_key("allow_list")
+/// Time to live of each collection, as a table in files or as `<name>=<duration>,...` in env
+/// vars. Durations without unit are seconds.
+fn deserialize_ttl_collections<'de, D>(
+    deserializer: D,
+) -> Result<BTreeMap<String, Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Ttl(#[serde(deserialize_with = "deserialize_duration_secs")] Duration);
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Collections {
+        Table(BTreeMap<String, Ttl>),
+        Text(String),
+    }
+    match Collections::deserialize(deserializer)? {
+        Collections::Table(collections) => Ok(collections
+            .into_iter()
+            .map(|(name, Ttl(ttl))| (name, ttl))
+            .collect()),
+        Collections::Text(text) => text
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.split_once('=') {
+                Some((name, ttl)) => parse_duration(ttl.trim(), Duration::from_secs(1))
+                    .map(|ttl| (name.trim().to_owned(), ttl))
+                    .map_err(serde::de::Error::custom),
+                None => Err(serde::de::Error::custom(format!(
+                    "'{}' is not <name>=<duration>",
+                    pair
+                ))),
+            })
+            .collect(),
+    }
+}
+
+fn default_keys_path() -> String {
+    default_data_path("keys")
+}
+
+#[derive(Debug, Deserialize)]
+struct QuotaParams {
+    #[serde(default)]
+    max_subjects: u64,
+    #[serde(
+        default = "default_quota_window",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    window: Duration,
+}
+
+impl QuotaParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}QUOTA");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
+
+    fn mix_config(&self, other_config: QuotaParams, explicit: &Explicit) -> Self {
+        let max_subjects =
+            explicit.pick("max_subjects", other_config.max_subjects, self.max_subjects);
+        let window = explicit.pick("window", other_config.window, self.window);
+        Self {
+            max_subjects,
+            window,
+        }
+    }
+}
+
+impl Default for QuotaParams {
+    fn default() -> Self {
+        Self {
+            max_subjects: 0,
+            window: default_quota_window(),
+        }
+    }
+}
+
+fn default_quota_window() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+#[derive(Debug, Deserialize)]
+struct SignatureCheckParams {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(
+        default = "default_signature_max_age",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    max_age: Duration,
+    #[serde(
+        default = "default_signature_max_future",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    max_future: Duration,
+}
+
+impl SignatureCheckParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}SIGNATURE_CHECK");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
+
+    fn mix_config(&self, other_config: SignatureCheckParams, explicit: &Explicit) -> Self {
+        let max_age = explicit.pick("max_age", other_config.max_age, self.max_age);
+        let max_future = explicit.pick("max_future", other_config.max_future, self.max_future);
+        Self {
+            enabled: explicit.pick("enabled", other_config.enabled, self.enabled),
+            max_age,
+            max_future,
+        }
+    }
+}
+
+impl Default for SignatureCheckParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_age: default_signature_max_age(),
+            max_future: default_signature_max_future(),
+        }
+    }
+}
+
+fn default_signature_max_age() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+fn default_signature_max_future() -> Duration {
+    Duration::from_secs(30)
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiCallParams {
+    #[serde(default, deserialize_with = "deserialize_duration_millis")]
+    timeout_ms: u32,
+    #[serde(default)]
+    retries: u32,
+    #[serde(
+        default = "default_api_backoff",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    backoff: Duration,
+}
+
+impl ApiCallParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}API");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
+
+    fn mix_config(&self, other_config: ApiCallParams, explicit: &Explicit) -> Self {
+        let timeout_ms = explicit.pick("timeout_ms", other_config.timeout_ms, self.timeout_ms);
+        let retries = explicit.pick("retries", other_config.retries, self.retries);
+        let backoff = explicit.pick("backoff", other_config.backoff, self.backoff);
+        Self {
+            timeout_ms,
+            retries,
+            backoff,
+        }
+    }
+}
+
+impl Default for ApiCallParams {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 0,
+            retries: 0,
+            backoff: default_api_backoff(),
+        }
+    }
+}
+
+fn default_api_backoff() -> Duration {
+    Duration::from_millis(100)
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct LimitsParams {
+    #[serde(default)]
+    read_rate: f64,
+    #[serde(default)]
+    read_burst: u32,
+    #[serde(default)]
+    read_max_in_flight: usize,
+    #[serde(default)]
+    write_rate: f64,
+    #[serde(default)]
+    write_burst: u32,
+    #[serde(default)]
+    write_max_in_flight: usize,
+}
+
+impl LimitsParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}LIMITS");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
+
+    fn mix_config(&self, other_config: LimitsParams, explicit: &Explicit) -> Self {
+        Self {
+            read_rate: explicit.pick("read_rate", other_config.read_rate, self.read_rate),
+            read_burst: explicit.pick("read_burst", other_config.read_burst, self.read_burst),
+            read_max_in_flight: explicit.pick(
+                "read_max_in_flight",
+                other_config.read_max_in_flight,
+                self.read_max_in_flight,
+            ),
+            write_rate: explicit.pick("write_rate", other_config.write_rate, self.write_rate),
+            write_burst: explicit.pick("write_burst", other_config.write_burst, self.write_burst),
+            write_max_in_flight: explicit.pick(
+                "write_max_in_flight",
+                other_config.write_max_in_flight,
+                self.write_max_in_flight,
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AccessLogParams {
+    #[serde(default = "default_access_log_sample_rate")]
+    sample_rate: f64,
+    #[serde(
+        default = "default_access_log_slow_threshold",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    slow_threshold: Duration,
+}
+
+impl AccessLogParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}ACCESS_LOG");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
+
+    fn mix_config(&self, other_config: AccessLogParams, explicit: &Explicit) -> Self {
+        let sample_rate = explicit.pick("sample_rate", other_config.sample_rate, self.sample_rate);
+        let slow_threshold = explicit.pick(
+            "slow_threshold",
+            other_config.slow_threshold,
+            self.slow_threshold,
+        );
+        Self {
+            sample_rate,
+            slow_threshold,
+        }
+    }
+}
+
+impl Default for AccessLogParams {
+    fn default() -> Self {
+        Self {
+            sample_rate: default_access_log_sample_rate(),
+            slow_threshold: default_access_log_slow_threshold(),
+        }
+    }
+}
+
+fn default_access_log_sample_rate() -> f64 {
+    1.0
+}
+
+fn default_access_log_slow_threshold() -> Duration {
+    Duration::from_secs(1)
+}
+
+#[derive(Debug, Deserialize)]
+struct LoggingParams {
+    #[serde(default = "default_logging_level")]
+    level: String,
+    #[serde(default, deserialize_with = "deserialize_log_targets")]
+    targets: BTreeMap<String, String>,
+    #[serde(default = "default_logging_format")]
+    format: LogFormat,
+    #[serde(default)]
+    file: String,
+    #[serde(
+        default = "default_logging_max_file_size",
+        deserialize_with = "deserialize_size"
+    )]
+    max_file_size: u64,
+    #[serde(default = "default_logging_max_files")]
+    max_files: u32,
+}
+
+impl LoggingParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}LOGGING");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
+
+    fn mix_config(&self, other_config: LoggingParams, explicit: &Explicit) -> Self {
+        let level = explicit.pick("level", other_config.level, self.level.clone());
+        let mut targets = self.targets.clone();
+        targets.extend(other_config.targets);
+        let format = explicit.pick("format", other_config.format, self.format);
+        let file = explicit.pick("file", other_config.file, self.file.clone());
+        let max_file_size = explicit.pick(
+            "max_file_size",
+            other_config.max_file_size,
+            self.max_file_size,
+        );
+        let max_files = explicit.pick("max_files", other_config.max_files, self.max_files);
+        Self {
+            level,
+            targets,
+            format,
+            file,
+            max_file_size,
+            max_files,
+        }
+    }
+}
+
+impl Default for LoggingParams {
+    fn default() -> Self {
+        Self {
+            level: default_logging_level(),
+            targets: BTreeMap::new(),
+            format: default_logging_format(),
+            file: String::default(),
+            max_file_size: default_logging_max_file_size(),
+            max_files: default_logging_max_files(),
+        }
+    }
+}
+
+fn default_logging_level() -> String {
+    "info".to_owned()
+}
+
+fn default_logging_format() -> LogFormat {
+    LogFormat::Pretty
+}
+
+fn default_logging_max_file_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_logging_max_files() -> u32 {
+    5
+}
+
+/// Levels by target, as a table in files or as `<target>=<level>,...` in env vars.
+fn deserialize_log_targets<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Targets {
+        Table(BTreeMap<String, String>),
+        Text(String),
+    }
+    match Targets::deserialize(deserializer)? {
+        Targets::Table(targets) => Ok(targets),
+        Targets::Text(text) => text
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.split_once('=') {
+                Some((target, level)) => Ok((target.trim().to_owned(), level.trim().to_owned())),
+                None => Err(serde::de::Error::custom(format!(
+                    "'{}' is not <target>=<level>",
+                    pair
+                ))),
+            })
+            .collect(),
+    }
+}
+
+/// Feature flags, as a table in files or as `<flag>=<true|false>,...` in env vars.
+fn deserialize_feature_flags<'de, D>(deserializer: D) -> Result<BTreeMap<String, bool>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flags {
+        Table(BTreeMap<String, bool>),
+        Text(String),
+    }
+    match Flags::deserialize(deserializer)? {
+        Flags::Table(flags) => Ok(flags),
+        Flags::Text(text) => text
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                pair.split_once('=')
+                    .and_then(|(flag, enabled)| {
+                        Some((flag.trim().to_owned(), enabled.trim().parse().ok()?))
+                    })
+                    .ok_or_else(|| {
+                        serde::de::Error::custom(format!("'{}' is not <flag>=<true|false>", pair))
+                    })
+            })
+            .collect(),
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct GrpcParams {
+    #[serde(default)]
+    listen: String,
+    #[serde(default)]
+    tls_cert: String,
+    #[serde(default)]
+    tls_key: String,
+}
+
+impl GrpcParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}GRPC");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
+
+    fn mix_config(&self, other_config: GrpcParams, explicit: &Explicit) -> Self {
+        Self {
+            listen: explicit.pick("listen", other_config.listen, self.listen.clone()),
+            tls_cert: explicit.pick("tls_cert", other_config.tls_cert, self.tls_cert.clone()),
+            tls_key: explicit.pick("tls_key", other_config.tls_key, self.tls_key.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookParams {
+    #[serde(default)]
+    urls: Vec<String>,
+    #[serde(default)]
+    secret: String,
+    #[serde(default = "default_webhook_max_retries")]
+    max_retries: u32,
+    #[serde(
+        default = "default_webhook_retry_backoff",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    retry_backoff: Duration,
+}
+
+impl WebhookParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}WEBHOOKS");
+        deserialize_env(
+            &prefix,
+            config::Environment::with_prefix(&prefix)
+                .list_separator(",")
+                .with_list_parse_key("urls")
+                .try_parsing(true),
+        )
+    }
+
+    fn mix_config(&self, other_config: WebhookParams, explicit: &Explicit) -> Self {
+        let urls = explicit.pick("urls", other_config.urls, self.urls.clone());
+        let secret = explicit.pick("secret", other_config.secret, self.secret.clone());
+        let max_retries = explicit.pick("max_retries", other_config.max_retries, self.max_retries);
+        let retry_backoff = explicit.pick(
+            "retry_backoff",
+            other_config.retry_backoff,
+            self.retry_backoff,
+        );
+        Self {
+            urls,
+            secret,
+            max_retries,
+            retry_backoff,
+        }
+    }
+}
+
+impl Default for WebhookParams {
+    fn default() -> Self {
+        Self {
+            urls: vec![],
+            secret: String::default(),
+            max_retries: default_webhook_max_retries(),
+            retry_backoff: default_webhook_retry_backoff(),
+        }
+    }
+}
+
+fn default_webhook_max_retries() -> u32 {
+    3
+}
+
+fn default_webhook_retry_backoff() -> Duration {
+    Duration::from_secs(1)
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct MetricsParams {
+    #[serde(default)]
+    push: MetricsPushParams,
+}
+
+impl MetricsParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}METRICS");
+        let push = MetricsPushParams::from_env(&format!("{prefix}_"))?;
+        Ok(Self { push })
+    }
+
+    fn mix_config(&self, other_config: MetricsParams, explicit: &Explicit) -> Self {
+        Self {
+            push: self
+                .push
+                .mix_config(other_config.push, &explicit.scope("push")),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MetricsPushParams {
+    #[serde(default)]
+    url: String,
+    #[serde(default = "default_metrics_push_mode")]
+    mode: MetricsPushMode,
+    #[serde(
+        default = "default_metrics_push_interval",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    interval: Duration,
+    #[serde(default = "default_metrics_push_job")]
+    job: String,
+    #[serde(default, deserialize_with = "deserialize_metric_labels")]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    password: String,
+}
+
+impl MetricsPushParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}PUSH");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
+
+    fn mix_config(&self, other_config: MetricsPushParams, explicit: &Explicit) -> Self {
+        let url = explicit.pick("url", other_config.url, self.url.clone());
+        let mode = explicit.pick("mode", other_config.mode, self.mode);
+        let interval = explicit.pick("interval", other_config.interval, self.interval);
+        let job = explicit.pick("job", other_config.job, self.job.clone());
+        let labels = explicit.pick("labels", other_config.labels, self.labels.clone());
+        let username = explicit.pick("username", other_config.username, self.username.clone());
+        let password = explicit.pick("password", other_config.password, self.password.clone());
+        Self {
+            url,
+            mode,
+            interval,
+            job,
+            labels,
+            username,
+            password,
+        }
+    }
+}
+
+impl Default for MetricsPushParams {
+    fn default() -> Self {
+        Self {
+            url: String::default(),
+            mode: default_metrics_push_mode(),
+            interval: default_metrics_push_interval(),
+            job: default_metrics_push_job(),
+            labels: BTreeMap::new(),
+            username: String::default(),
+            password: String::default(),
+        }
+    }
+}
+
+fn default_metrics_push_mode() -> MetricsPushMode {
+    MetricsPushMode::Pushgateway
+}
+
+fn default_metrics_push_interval() -> Duration {
+    Duration::from_secs(15)
+}
+
+fn default_metrics_push_job() -> String {
+    "kore-node".to_owned()
+}
+
+/// Labels of the pushed metrics, as a table in files or as `<name>=<value>,...` in env vars.
+fn deserialize_metric_labels<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Labels {
+        Table(BTreeMap<String, String>),
+        Text(String),
+    }
+    match Labels::deserialize(deserializer)? {
+        Labels::Table(labels) => Ok(labels),
+        Labels::Text(text) => text
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.split_once('=') {
+                Some((name, value)) => Ok((name.trim().to_owned(), value.trim().to_owned())),
+                None => Err(serde::de::Error::custom(format!(
+                    "'{}' is not <name>=<value>",
+                    pair
+                ))),
+            })
+            .collect(),
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct ApiAuthParams {
+    #[serde(default)]
+    public_token: String,
+    #[serde(default)]
+    admin_token: String,
+}
+
+impl ApiAuthParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}API_AUTH");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
+
+    fn mix_config(&self, other_config: ApiAuthParams, explicit: &Explicit) -> Self {
+        Self {
+            public_token: explicit.pick(
+                "public_token",
+                other_config.public_token,
+                self.public_token.clone(),
+            ),
+            admin_token: explicit.pick(
+                "admin_token",
+                other_config.admin_token,
+                self.admin_token.clone(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct AuthParams {
+    #[serde(default)]
+    api_keys: Vec<String>,
+    #[serde(default)]
+    issuer: String,
+    #[serde(default)]
+    audience: String,
+    #[serde(default)]
+    jwks_url: String,
+}
+
+impl AuthParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}AUTH");
+        deserialize_env(
+            &prefix,
+            config::Environment::with_prefix(&prefix)
+                .list_separator(",")
+                .with_list_parse_key("api_keys")
+                .try_parsing(true),
+        )
+    }
+
+    fn mix_config(&self, other_config: AuthParams, explicit: &Explicit) -> Self {
+        let api_keys = explicit.pick("api_keys", other_config.api_keys, self.api_keys.clone());
+        Self {
+            api_keys,
+            issuer: explicit.pick("issuer", other_config.issuer, self.issuer.clone()),
+            audience: explicit.pick("audience", other_config.audience, self.audience.clone()),
+            jwks_url: explicit.pick("jwks_url", other_config.jwks_url, self.jwks_url.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ServicesParams {
+    #[serde(default)]
+    rest_url: String,
+    #[serde(default)]
+    metrics_url: String,
+    #[serde(default, deserialize_with = "deserialize_service_peers")]
+    peers: BTreeMap<String, String>,
+    #[serde(
+        default = "default_services_interval",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    interval: Duration,
+}
+
+impl ServicesParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}SERVICES");
+        deserialize_env(
+            &prefix,
+            config::Environment::with_prefix(&prefix).try_parsing(true),
+        )
+    }
+
+    fn mix_config(&self, other_config: ServicesParams, explicit: &Explicit) -> Self {
+        let peers = explicit.pick("peers", other_config.peers, self.peers.clone());
+        let interval = explicit.pick("interval", other_config.interval, self.interval);
+        Self {
+            rest_url: explicit.pick("rest_url", other_config.rest_url, self.rest_url.clone()),
+            metrics_url: explicit.pick(
+                "metrics_url",
+                other_config.metrics_url,
+                self.metrics_url.clone(),
+            ),
+            peers,
+            interval,
+        }
+    }
+}
+
+impl Default for ServicesParams {
+    fn default() -> Self {
+        Self {
+            rest_url: String::default(),
+            metrics_url: String::default(),
+            peers: BTreeMap::new(),
+            interval: default_services_interval(),
+        }
+    }
+}
+
+fn default_services_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+/// Trusted peers, as a table in files or as `<controller id>=<url>,...` in env vars.
+fn deserialize_service_peers<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Peers {
+        Table(BTreeMap<String, String>),
+        Text(String),
+    }
+    match Peers::deserialize(deserializer)? {
+        Peers::Table(peers) => Ok(peers),
+        Peers::Text(text) => text
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.split_once('=') {
+                Some((peer, url)) => Ok((peer.trim().to_owned(), url.trim().to_owned())),
+                None => Err(serde::de::Error::custom(format!(
+                    "'{}' is not <controller id>=<url>",
+                    pair
+                ))),
+            })
+            .collect(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WarmUpParams {
+    #[serde(default)]
+    subjects: Vec<String>,
+    #[serde(default)]
+    recent: usize,
+    #[serde(
+        default = "default_warm_up_timeout",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    timeout: Duration,
+}
+
+impl WarmUpParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}WARM_UP");
+        deserialize_env(
+            &prefix,
+            config::Environment::with_prefix(&prefix)
+                .list_separator(",")
+                .with_list_parse_key("subjects")
+                .try_parsing(true),
+        )
+    }
+
+    fn mix_config(&self, other_config: WarmUpParams, explicit: &Explicit) -> Self {
+        let subjects = explicit.pick("subjects", other_config.subjects, self.subjects.clone());
+        let recent = explicit.pick("recent", other_config.recent, self.recent);
+        let timeout = explicit.pick("timeout", other_config.timeout, self.timeout);
+        Self {
+            subjects,
+            recent,
+            timeout,
+        }
+    }
+}
+
+impl Default for WarmUpParams {
+    fn default() -> Self {
+        Self {
+            subjects: vec![],
+            recent: 0,
+            timeout: default_warm_up_timeout(),
+        }
+    }
+}
+
+fn default_warm_up_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+#[derive(Debug, Deserialize)]
+struct SupervisorParams {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "default_supervisor_max_restarts")]
+    max_restarts: u32,
+    #[serde(
+        default = "default_supervisor_window",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    window: Duration,
+    #[serde(
+        default = "default_supervisor_backoff",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    backoff: Duration,
+    #[serde(
+        default = "default_supervisor_max_backoff",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    max_backoff: Duration,
+}
+
+impl SupervisorParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}SUPERVISOR");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
+
+    fn mix_config(&self, other_config: SupervisorParams, explicit: &Explicit) -> Self {
+        let max_restarts =
+            explicit.pick("max_restarts", other_config.max_restarts, self.max_restarts);
+        let window = explicit.pick("window", other_config.window, self.window);
+        let backoff = explicit.pick("backoff", other_config.backoff, self.backoff);
+        let max_backoff = explicit.pick("max_backoff", other_config.max_backoff, self.max_backoff);
+        Self {
+            enabled: explicit.pick("enabled", other_config.enabled, self.enabled),
+            max_restarts,
+            window,
+            backoff,
+            max_backoff,
+        }
+    }
+}
+
+impl Default for SupervisorParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_restarts: default_supervisor_max_restarts(),
+            window: default_supervisor_window(),
+            backoff: default_supervisor_backoff(),
+            max_backoff: default_supervisor_max_backoff(),
+        }
+    }
+}
+
+fn default_supervisor_max_restarts() -> u32 {
+    5
+}
+
+fn default_supervisor_window() -> Duration {
+    Duration::from_secs(600)
+}
+
+fn default_supervisor_backoff() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_supervisor_max_backoff() -> Duration {
+    Duration::from_secs(60)
+}
+
+#[derive(Debug, Deserialize)]
+struct BackupParams {
+    #[serde(default)]
+    directory: String,
+    #[serde(default, deserialize_with = "deserialize_duration_secs")]
+    interval: Duration,
+    #[serde(default = "default_backup_keep")]
+    keep: usize,
+}
+
+impl BackupParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}BACKUP");
+        deserialize_env(
+            &prefix,
+            config::Environment::with_prefix(&prefix).try_parsing(true),
+        )
+    }
+
+    fn mix_config(&self, other_config: BackupParams, explicit: &Explicit) -> Self {
+        let directory = explicit.pick("directory", other_config.directory, self.directory.clone());
+        let interval = explicit.pick("interval", other_config.interval, self.interval);
+        let keep = explicit.pick("keep", other_config.keep, self.keep);
+        Self {
+            directory,
+            interval,
+            keep,
+        }
+    }
+}
+
+impl Default for BackupParams {
+    fn default() -> Self {
+        Self {
+            directory: String::default(),
+            interval: Duration::ZERO,
+            keep: default_backup_keep(),
+        }
+    }
+}
+
+fn default_backup_keep() -> usize {
+    7
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ArchivalParams {
+    #[serde(default)]
+    destination: String,
+    #[serde(default, deserialize_with = "deserialize_duration_secs")]
+    max_age: Duration,
+    #[serde(default, deserialize_with = "deserialize_size")]
+    max_size: u64,
+    #[serde(default, deserialize_with = "deserialize_duration_secs")]
+    interval: Duration,
+}
+
+impl ArchivalParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}ARCHIVAL");
+        deserialize_env(
+            &prefix,
+            config::Environment::with_prefix(&prefix).try_parsing(true),
+        )
+    }
+
+    fn mix_config(&self, other_config: ArchivalParams, explicit: &Explicit) -> Self {
+        let destination = explicit.pick(
+            "destination",
+            other_config.destination,
+            self.destination.clone(),
+        );
+        let max_age = explicit.pick("max_age", other_config.max_age, self.max_age);
+        let max_size = explicit.pick("max_size", other_config.max_size, self.max_size);
+        let interval = explicit.pick("interval", other_config.interval, self.interval);
+        Self {
+            destination,
+            max_age,
+            max_size,
+            interval,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SoakParams {
+    #[serde(default)]
+    rate: u32,
+    #[serde(default, deserialize_with = "deserialize_duration_secs")]
+    duration: Duration,
+    #[serde(default)]
+    governance_id: String,
+    #[serde(default = "default_soak_schema_id")]
+    schema_id: String,
+    #[serde(default = "default_soak_subjects")]
+    subjects: usize,
+    #[serde(default = "default_soak_payload")]
+    payload: String,
+    #[serde(default = "default_soak_max_in_flight")]
+    max_in_flight: usize,
+    #[serde(
+        default = "default_soak_report_interval",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    report_interval: Duration,
+}
+
+impl SoakParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}SOAK");
+        deserialize_env(
+            &prefix,
+            config::Environment::with_prefix(&prefix).try_parsing(true),
+        )
+    }
+
+    fn mix_config(&self, other_config: SoakParams, explicit: &Explicit) -> Self {
+        let rate = explicit.pick("rate", other_config.rate, self.rate);
+        let duration = explicit.pick("duration", other_config.duration, self.duration);
+        let governance_id = explicit.pick(
+            "governance_id",
+            other_config.governance_id,
+            self.governance_id.clone(),
+        );
+        let schema_id = explicit.pick("schema_id", other_config.schema_id, self.schema_id.clone());
+        let subjects = explicit.pick("subjects", other_config.subjects, self.subjects);
+        let payload = explicit.pick("payload", other_config.payload, self.payload.clone());
+        let max_in_flight = explicit.pick(
+            "max_in_flight",
+            other_config.max_in_flight,
+            self.max_in_flight,
+        );
+        let report_interval = explicit.pick(
+            "report_interval",
+            other_config.report_interval,
+            self.report_interval,
+        );
+        Self {
+            rate,
+            duration,
+            governance_id,
+            schema_id,
+            subjects,
+            payload,
+            max_in_flight,
+            report_interval,
+        }
+    }
+}
+
+impl Default for SoakParams {
+    fn default() -> Self {
+        Self {
+            rate: 0,
+            duration: Duration::ZERO,
+            governance_id: String::default(),
+            schema_id: default_soak_schema_id(),
+            subjects: default_soak_subjects(),
+            payload: default_soak_payload(),
+            max_in_flight: default_soak_max_in_flight(),
+            report_interval: default_soak_report_interval(),
+        }
+    }
+}
+
+fn default_soak_schema_id() -> String {
+    "governance".to_owned()
+}
+
+fn default_soak_subjects() -> usize {
+    10
+}
+
+fn default_soak_payload() -> String {
+    "{}".to_owned()
+}
+
+fn default_soak_max_in_flight() -> usize {
+    100
+}
+
+fn default_soak_report_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplicationParams {
+    #[serde(default)]
+    remote_url: String,
+    #[serde(default)]
+    token: String,
+    #[serde(default)]
+    subjects: Vec<String>,
+    #[serde(default = "default_replication_mode")]
+    mode: ReplicationMode,
+    #[serde(
+        default = "default_replication_poll_interval",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    poll_interval: Duration,
+    #[serde(
+        default = "default_replication_request_timeout",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    request_timeout: Duration,
+    #[serde(default = "default_replication_max_attempts")]
+    max_attempts: u32,
+}
+
+impl ReplicationParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}REPLICATION");
+        deserialize_env(
+            &prefix,
+            config::Environment::with_prefix(&prefix)
+                .list_separator(",")
+                .with_list_parse_key("subjects")
+                .try_parsing(true),
+        )
+    }
+
+    fn mix_config(&self, other_config: ReplicationParams, explicit: &Explicit) -> Self {
+        let remote_url = explicit.pick(
+            "remote_url",
+            other_config.remote_url,
+            self.remote_url.clone(),
+        );
+        let token = explicit.pick("token", other_config.token, self.token.clone());
+        let subjects = explicit.pick("subjects", other_config.subjects, self.subjects.clone());
+        let mode = explicit.pick("mode", other_config.mode, self.mode);
+        let poll_interval = explicit.pick(
+            "poll_interval",
+            other_config.poll_interval,
+            self.poll_interval,
+        );
+        let request_timeout = explicit.pick(
+            "request_timeout",
+            other_config.request_timeout,
+            self.request_timeout,
+        );
+        let max_attempts =
+            explicit.pick("max_attempts", other_config.max_attempts, self.max_attempts);
+        Self {
+            remote_url,
+            token,
+            subjects,
+            mode,
+            poll_interval,
+            request_timeout,
+            max_attempts,
+        }
+    }
+}
+
+impl Default for ReplicationParams {
+    fn default() -> Self {
+        Self {
+            remote_url: String::default(),
+            token: String::default(),
+            subjects: vec![],
+            mode: default_replication_mode(),
+            poll_interval: default_replication_poll_interval(),
+            request_timeout: default_replication_request_timeout(),
+            max_attempts: default_replication_max_attempts(),
+        }
+    }
+}
+
+fn default_replication_mode() -> ReplicationMode {
+    ReplicationMode::Resubmit
+}
+
+fn default_replication_poll_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_replication_request_timeout() -> Duration {
+    Duration::from_secs(120)
+}
+
+fn default_replication_max_attempts() -> u32 {
+    5
+}
+
+#[derive(Debug, Deserialize)]
+struct KeysParams {
+    #[serde(default = "default_keys_kdf")]
+    kdf: KeyKdf,
+    #[serde(default = "default_keys_iterations")]
+    iterations: u32,
+    #[serde(default = "default_scrypt_log_n")]
+    scrypt_log_n: u8,
+    #[serde(default = "default_scrypt_r")]
+    scrypt_r: u32,
+    #[serde(default = "default_scrypt_p")]
+    scrypt_p: u32,
+    #[serde(default)]
+    vault: VaultParams,
+}
+
+impl KeysParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}KEYS");
+        let mut errors = vec![];
+        let keys = collect(
+            deserialize_env::<KeysParams>(
+                &prefix,
+                config::Environment::with_prefix(&prefix).try_parsing(true),
+            ),
+            &mut errors,
+        );
+        let vault = collect(VaultParams::from_env(&format!("{prefix}_")), &mut errors);
+        match (keys, vault) {
+            (Some(keys), Some(vault)) => Ok(Self { vault, ..keys }),
+            _ => Err(errors),
+        }
+    }
+
+    fn mix_config(&self, other_config: KeysParams, explicit: &Explicit) -> Self {
+        let kdf = explicit.pick("kdf", other_config.kdf, self.kdf);
+        let iterations = explicit.pick("iterations", other_config.iterations, self.iterations);
+        let scrypt_log_n =
+            explicit.pick("scrypt_log_n", other_config.scrypt_log_n, self.scrypt_log_n);
+        let scrypt_r = explicit.pick("scrypt_r", other_config.scrypt_r, self.scrypt_r);
+        let scrypt_p = explicit.pick("scrypt_p", other_config.scrypt_p, self.scrypt_p);
+        Self {
+            kdf,
+            iterations,
+            scrypt_log_n,
+            scrypt_r,
+            scrypt_p,
+            vault: self
+                .vault
+                .mix_config(other_config.vault, &explicit.scope("vault")),
+        }
+    }
+}
+
+impl Default for KeysParams {
+    fn default() -> Self {
+        Self {
+            kdf: default_keys_kdf(),
+            iterations: default_keys_iterations(),
+            scrypt_log_n: default_scrypt_log_n(),
+            scrypt_r: default_scrypt_r(),
+            scrypt_p: default_scrypt_p(),
+            vault: VaultParams::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct VaultParams {
+    #[serde(default)]
+    address: String,
+    #[serde(default)]
+    token: String,
+    #[serde(default)]
+    role_id: String,
+    #[serde(default)]
+    secret_id: String,
+    #[serde(default = "default_vault_engine")]
+    engine: VaultEngine,
+    #[serde(default = "default_vault_mount")]
+    mount: String,
+    #[serde(default = "default_vault_path")]
+    path: String,
+}
+
+impl VaultParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}VAULT");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
+
+    fn mix_config(&self, other_config: VaultParams, explicit: &Explicit) -> Self {
+        let engine = explicit.pick("engine", other_config.engine, self.engine);
+        Self {
+            address: explicit.pick("address", other_config.address, self.address.clone()),
+            token: explicit.pick("token", other_config.token, self.token.clone()),
+            role_id: explicit.pick("role_id", other_config.role_id, self.role_id.clone()),
+            secret_id: explicit.pick("secret_id", other_config.secret_id, self.secret_id.clone()),
+            engine,
+            mount: explicit.pick("mount", other_config.mount, self.mount.clone()),
+            path: explicit.pick("path", other_config.path, self.path.clone()),
+        }
+    }
+}
+
+impl Default for VaultParams {
+    fn default() -> Self {
+        Self {
+            address: String::default(),
+            token: String::default(),
+            role_id: String::default(),
+            secret_id: String::default(),
+            engine: default_vault_engine(),
+            mount: default_vault_mount(),
+            path: default_vault_path(),
+        }
+    }
+}
+
+fn default_vault_engine() -> VaultEngine {
+    VaultEngine::Kv
+}
+
+fn default_vault_mount() -> String {
+    "secret".to_owned()
+}
+
+fn default_vault_path() -> String {
+    "kore-node".to_owned()
+}
+
+fn default_keys_backend() -> KeysBackend {
+    KeysBackend::File
+}
+
+fn default_timestamp_format() -> TimestampFormat {
+    TimestampFormat::Epoch
+}
+
+#[derive(Debug, Deserialize)]
+struct Pkcs11Params {
+    #[serde(default)]
+    module: String,
+    #[serde(default)]
+    token: String,
+    #[serde(default = "default_pkcs11_label")]
+    label: String,
+}
+
+impl Pkcs11Params {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}PKCS11");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
+    }
+
+    fn mix_config(&self, other_config: Pkcs11Params, explicit: &Explicit) -> Self {
+        let module = explicit.pick("module", other_config.module, self.module.clone());
+        let token = explicit.pick("token", other_config.token, self.token.clone());
+        let label = explicit.pick("label", other_config.label, self.label.clone());
+        Self {
+            module,
+            token,
+            label,
+        }
+    }
+}
+
+impl Default for Pkcs11Params {
+    fn default() -> Self {
+        Self {
+            module: String::default(),
+            token: String::default(),
+            label: default_pkcs11_label(),
+        }
+    }
+}
+
+fn default_pkcs11_label() -> String {
+    "kore-node".to_owned()
+}
+
+fn default_keys_kdf() -> KeyKdf {
+    KeyKdf::Pbkdf2
+}
+
+fn default_keys_iterations() -> u32 {
+    600_000
+}
+
+fn default_scrypt_log_n() -> u8 {
+    15
+}
+
+fn default_scrypt_r() -> u32 {
+    8
+}
+
+fn default_scrypt_p() -> u32 {
+    1
+}
+
+#[derive(Debug, Deserialize)]
+struct NetworkParams {
+    #[serde(default = "default_user_agent")]
+    user_agent: String,
+    #[serde(default = "default_node_type")]
+    node_type: NodeType,
+    #[serde(default, deserialize_with = "deserialize_multiaddrs")]
+    listen_addresses: Vec<Multiaddr>,
+    #[serde(default, deserialize_with = "deserialize_multiaddrs")]
+    external_addresses: Vec<Multiaddr>,
+    #[serde(default)]
+    tell: TellParams,
+    #[serde(default)]
+    routing: RoutingParams,
+    #[serde(default)]
+    port_reuse: bool,
+    #[serde(default)]
+    listen_fallback_ports: Vec<u16>,
+    #[serde(default)]
+    bootstrap: BootstrapParams,
+    #[serde(default)]
+    control_list: ControlListParams,
+}
+
+impl NetworkParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}NETWORK");
+        let mut errors = vec![];
+        let network = collect(
+            deserialize_env::<NetworkParams>(
+                &prefix,
+                config::Environment::with_prefix(&prefix)
+                    .list_separator(",")
+                    .with_list_parse_key("listen_addresses")
+                    .try_parsing(true)
+                    .list_separator(",")
+                    .with_list_parse_key("external_addresses")
+                    .try_parsing(true)
+                    .list_separator(",")
+                    .with_list_parse_key("listen_fallback_ports")
+                    .try_parsing(true),
+            ),
+            &mut errors,
+        );
+        let parent = &format!("{prefix}_");
+        let tell = collect(TellParams::from_env(parent), &mut errors);
+        let routing = collect(RoutingParams::from_env(parent), &mut errors);
+        let bootstrap = collect(BootstrapParams::from_env(parent), &mut errors);
+        let control_list = collect(ControlListParams::from_env(parent), &mut errors);
+
+        match (network, tell, routing, bootstrap, control_list) {
+            (Some(network), Some(tell), Some(routing), Some(bootstrap), Some(control_list)) => {
+                Ok(Self {
+                    user_agent: network.user_agent,
+                    node_type: network.node_type,
+                    listen_addresses: network.listen_addresses,
+                    external_addresses: network.external_addresses,
+                    tell,
+                    routing,
+                    port_reuse: network.port_reuse,
+                    listen_fallback_ports: network.listen_fallback_ports,
+                    bootstrap,
+                    control_list,
+                })
+            }
+            _ => Err(errors),
+        }
+    }
+
+    fn mix_config(&self, other_config: NetworkParams, explicit: &Explicit) -> Self {
+        let user_agent = explicit.pick(
+            "user_agent",
+            other_config.user_agent,
+            self.user_agent.clone(),
+        );
+
+        let node_type = explicit.pick("node_type", other_config.node_type, self.node_type.clone());
+
+        let listen_addresses = explicit.pick(
+            "listen_addresses",
+            other_config.listen_addresses,
+            self.listen_addresses.clone(),
+        );
+
+        let external_addresses = explicit.pick(
+            "external_addresses",
+            other_config.external_addresses,
+            self.external_addresses.clone(),
+        );
+
+        let port_reuse = explicit.pick("port_reuse", other_config.port_reuse, self.port_reuse);
+
+        let listen_fallback_ports = explicit.pick(
+            "listen_fallback_ports",
+            other_config.listen_fallback_ports,
+            self.listen_fallback_ports.clone(),
+        );
+
+        Self {
+            user_agent,
+            node_type,
+            listen_addresses,
+            external_addresses,
+            tell: self
+                .tell
+                .mix_config(other_config.tell, &explicit.scope("tell")),
+            routing: self
+                .routing
+                .mix_config(other_config.routing, &explicit.scope("routing")),
+            port_reuse,
+            listen_fallback_ports,
+            bootstrap: self
+                .bootstrap
+                .mix_config(other_config.bootstrap, &explicit.scope("bootstrap")),
+            control_list: self
+                .control_list
+                .mix_config(other_config.control_list, &explicit.scope("control_list")),
+        }
+    }
+}
+
+/// Deserialize multiaddresses, rejecting the first invalid one with its position.
+fn deserialize_multiaddrs<'de, D>(deserializer: D) -> Result<Vec<Multiaddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .enumerate()
+        .map(|(index, address)| {
+            multiaddr(address).map_err(|message| {
+                serde::de::Error::custom(format!("entry {}: {}", index, message))
+            })
+        })
+        .collect()
+}
+
+/// Multiaddresses as Kore Base takes them.
+fn to_strings(addresses: Vec<Multiaddr>) -> Vec<String> {
+    addresses.iter().map(Multiaddr::to_string).collect()
+}
+
+fn default_user_agent() -> String {
+    "kore-node".to_owned()
+}
+
+fn default_node_type() -> NodeType {
+    NodeType::Bootstrap
+}
+
+impl Default for NetworkParams {
+    fn default() -> Self {
+        Self {
+            user_agent: default_user_agent(),
+            node_type: default_node_type(),
+            listen_addresses: vec![],
+            external_addresses: vec![],
+            tell: TellParams::default(),
+            routing: RoutingParams::default(),
+            port_reuse: false,
+            listen_fallback_ports: vec![],
+            bootstrap: BootstrapParams::default(),
+            control_list: ControlListParams::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BootstrapParams {
+    #[serde(default, deserialize_with = "deserialize_boot_groups")]
+    groups: Vec<BootGroup>,
+    #[serde(
+        default = "default_probe_timeout",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    probe_timeout: Duration,
+    #[serde(
+        default = "default_health_interval",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    health_interval: Duration,
+    #[serde(
+        default = "default_dns_refresh",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    dns_refresh: Duration,
+}
+
+impl BootstrapParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}BOOTSTRAP");
+        deserialize_env(
+            &prefix,
+            config::Environment::with_prefix(&prefix).try_parsing(true),
+        )
+    }
+
+    fn mix_config(&self, other_config: BootstrapParams, explicit: &Explicit) -> Self {
+        let groups = explicit.pick("groups", other_config.groups, self.groups.clone());
+        let probe_timeout = explicit.pick(
+            "probe_timeout",
+            other_config.probe_timeout,
+            self.probe_timeout,
+        );
+        let health_interval = explicit.pick(
+            "health_interval",
+            other_config.health_interval,
+            self.health_interval,
+        );
+        let dns_refresh = explicit.pick("dns_refresh", other_config.dns_refresh, self.dns_refresh);
+        Self {
+            groups,
+            probe_timeout,
+            health_interval,
+            dns_refresh,
+        }
+    }
+}
+
+impl Default for BootstrapParams {
+    fn default() -> Self {
+        Self {
+            groups: vec![],
+            probe_timeout: default_probe_timeout(),
+            health_interval: default_health_interval(),
+            dns_refresh: default_dns_refresh(),
+        }
+    }
+}
+
+fn default_probe_timeout() -> Duration {
+    Duration::from_secs(3)
+}
+
+fn default_health_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_dns_refresh() -> Duration {
+    Duration::from_secs(300)
+}
+
+/// Boot node groups, as an array of tables with `label` and `boot_nodes` in files, or as
+/// `<label>=<boot node> <boot node>,...` in env vars, with the boot nodes in the legacy form.
+fn deserialize_boot_groups<'de, D>(deserializer: D) -> Result<Vec<BootGroup>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Group {
+        label: String,
+        #[serde(default)]
+        boot_nodes: Vec<BootNodeEntry>,
+    }
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Groups {
+        List(Vec<Group>),
+        Text(String),
+    }
+    let groups = match Groups::deserialize(deserializer)? {
+        Groups::List(groups) => groups,
+        Groups::Text(text) => text
+            .split(',')
+            .map(str::trim)
+            .filter(|group| !group.is_empty())
+            .map(|group| match group.split_once('=') {
+                Some((label, boot_nodes)) => Ok(Group {
+                    label: label.trim().to_owned(),
+                    boot_nodes: boot_nodes
+                        .split_whitespace()
+                        .map(|node| BootNodeEntry::Text(node.to_owned()))
+                        .collect(),
+                }),
+                None => Err(serde::de::Error::custom(format!(
+                    "'{}' is not <label>=<boot nodes>",
+                    group
+                ))),
+            })
+            .collect::<Result<_, _>>()?,
+    };
+    groups
+        .into_iter()
+        .map(|group| {
+            let boot_nodes = group
+                .boot_nodes
+                .iter()
+                .enumerate()
+                .map(|(index, entry)| {
+                    entry.parse().map_err(|message| {
+                        serde::de::Error::custom(format!(
+                            "group {}, boot node {}: {}",
+                            group.label, index, message
+                        ))
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(BootGroup {
+                label: group.label,
+                boot_nodes,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct ControlListParams {
+    #[serde(default)]
+    enable: bool,
+    #[serde(default)]
+    allow_list: Vec<String>,
+    #[serde(default)]
+    block_list: Vec<String>,
+    #[serde(default)]
+    service_allow_list: Vec<String>,
+    #[serde(default)]
+    service_block_list: Vec<String>,
+    #[serde(
+        default = "default_interval_request_secs",
+        deserialize_with = "deserialize_duration_secs"
+    )]
+    interval_request: Duration,
+}
+
+impl Default for ControlListParams {
+    fn default() -> Self {
+        Self {
+            allow_list: vec![],
+            block_list: vec![],
+            enable: false,
+            interval_request: default_interval_request_secs(),
+            service_allow_list: vec![],
+            service_block_list: vec![],
+        }
+    }
+}
+
+fn default_interval_request_secs() -> Duration {
+    Duration::from_secs(60)
+}
+
+impl ControlListParams {
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}CONTROL_LIST");
+        deserialize_env(
+            &prefix,
+            config::Environment::with_prefix(&prefix)
+                .list_separator(",")
+                .with_list_parse_key("allow_list")
                 .try_parsing(true)
                 .list_separator(",")
                 .with_list_parse_key("block_list")
@@ -374,59 +2865,41 @@ impl ControlListParams {
                 .list_separator(",")
                 .with_list_parse_key("service_block_list")
                 .try_parsing(true),
-        );
-
-        let config = config
-            .build()
-            .map_err(|e| {
-                println!("Error building config: {}", e);
-            })
-            .unwrap();
-
-        config
-            .try_deserialize()
-            .map_err(|e| {
-                println!("Error try deserialize config: {}", e);
-            })
-            .unwrap()
+        )
     }
 
-    fn mix_config(&self, other_config: ControlListParams) -> Self {
-        let enable = if other_config.enable {
-            true
-        } else {
-            self.enable.clone()
-        };
+    fn mix_config(&self, other_config: ControlListParams, explicit: &Explicit) -> Self {
+        let enable = explicit.pick("enable", other_config.enable, self.enable);
 
-        let allow_list = if !other_config.allow_list.is_empty() {
-            other_config.allow_list
-        } else {
-            self.allow_list.clone()
-        };
+        let allow_list = explicit.pick(
+            "allow_list",
+            other_config.allow_list,
+            self.allow_list.clone(),
+        );
 
-        let block_list = if !other_config.block_list.is_empty() {
-            other_config.block_list
-        } else {
-            self.block_list.clone()
-        };
+        let block_list = explicit.pick(
+            "block_list",
+            other_config.block_list,
+            self.block_list.clone(),
+        );
 
-        let service_allow_list = if !other_config.service_allow_list.is_empty() {
-            other_config.service_allow_list
-        } else {
-            self.service_allow_list.clone()
-        };
+        let service_allow_list = explicit.pick(
+            "service_allow_list",
+            other_config.service_allow_list,
+            self.service_allow_list.clone(),
+        );
 
-        let service_block_list = if !other_config.service_block_list.is_empty() {
-            other_config.service_block_list
-        } else {
-            self.service_block_list.clone()
-        };
+        let service_block_list = explicit.pick(
+            "service_block_list",
+            other_config.service_block_list,
+            self.service_block_list.clone(),
+        );
 
-        let interval_request = if other_config.interval_request != default_interval_request_secs() {
-            other_config.interval_request
-        } else {
-            self.interval_request
-        };
+        let interval_request = explicit.pick(
+            "interval_request",
+            other_config.interval_request,
+            self.interval_request,
+        );
 
         Self {
             allow_list,
@@ -451,39 +2924,23 @@ struct TellParams {
 }
 
 impl TellParams {
-    fn from_env(parent: &str) -> Self {
-        let mut config = config::Config::builder();
-        config = config.add_source(config::Environment::with_prefix(&format!("{parent}TELL")));
-
-        let config = config
-            .build()
-            .map_err(|e| {
-                println!("Error building config: {}", e);
-            })
-            .unwrap();
-
-        config
-            .try_deserialize()
-            .map_err(|e| {
-                println!("Error try deserialize config: {}", e);
-            })
-            .unwrap()
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}TELL");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
     }
 
-    fn mix_config(&self, other_config: TellParams) -> Self {
-        let message_timeout_secs =
-            if other_config.message_timeout_secs != default_message_timeout_secs() {
-                other_config.message_timeout_secs
-            } else {
-                self.message_timeout_secs
-            };
+    fn mix_config(&self, other_config: TellParams, explicit: &Explicit) -> Self {
+        let message_timeout_secs = explicit.pick(
+            "message_timeout_secs",
+            other_config.message_timeout_secs,
+            self.message_timeout_secs,
+        );
 
-        let max_concurrent_streams =
-            if other_config.max_concurrent_streams != default_max_concurrent_streams() {
-                other_config.max_concurrent_streams
-            } else {
-                self.max_concurrent_streams
-            };
+        let max_concurrent_streams = explicit.pick(
+            "max_concurrent_streams",
+            other_config.max_concurrent_streams,
+            self.max_concurrent_streams,
+        );
         Self {
             message_timeout_secs,
             max_concurrent_streams,
@@ -500,14 +2957,6 @@ impl Default for TellParams {
     }
 }
 
-fn deserialize_duration_secs<'de, D>(deserializer: D) -> Result<Duration, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let u: u64 = u64::deserialize(deserializer)?;
-    Ok(Duration::from_secs(u))
-}
-
 fn default_max_concurrent_streams() -> usize {
     100
 }
@@ -539,78 +2988,62 @@ struct RoutingParams {
 }
 
 impl RoutingParams {
-    fn from_env(parent: &str) -> Self {
-        let mut config = config::Config::builder();
-        config = config.add_source(
-            config::Environment::with_prefix(&format!("{parent}ROUTING"))
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}ROUTING");
+        let mut params: Self = deserialize_env(
+            &prefix,
+            config::Environment::with_prefix(&prefix)
                 .list_separator(",")
                 .with_list_parse_key("protocol_names")
                 .with_list_parse_key("boot_nodes")
                 .try_parsing(true),
-        );
-
-        let config = config
-            .build()
-            .map_err(|e| {
-                println!("Error building config: {}", e);
-            })
-            .unwrap();
-
-        config
-            .try_deserialize()
-            .map_err(|e| {
-                println!("Error try deserialize config: {}", e);
-            })
-            .unwrap()
+        )?;
+        params.boot_nodes.extend(env_boot_nodes(&prefix)?);
+        Ok(params)
     }
 
-    fn mix_config(&self, other_config: RoutingParams) -> Self {
-        let boot_nodes = if !other_config.boot_nodes.is_empty() {
-            other_config.boot_nodes
-        } else {
-            self.boot_nodes.clone()
-        };
-        let dht_random_walk = if !other_config.dht_random_walk {
-            other_config.dht_random_walk
-        } else {
-            self.dht_random_walk
-        };
-        let discovery_only_if_under_num =
-            if other_config.discovery_only_if_under_num != default_discovery_only_if_under_num() {
-                other_config.discovery_only_if_under_num
-            } else {
-                self.discovery_only_if_under_num
-            };
-        let allow_non_globals_in_dht = if other_config.allow_non_globals_in_dht {
-            other_config.allow_non_globals_in_dht
-        } else {
-            self.allow_non_globals_in_dht
-        };
-        let allow_private_ip = if other_config.allow_private_ip {
-            other_config.allow_private_ip
-        } else {
-            self.allow_private_ip
-        };
-        let enable_mdns = if !other_config.enable_mdns {
-            other_config.enable_mdns
-        } else {
-            self.enable_mdns
-        };
-        let kademlia_disjoint_query_paths = if !other_config.kademlia_disjoint_query_paths {
-            other_config.kademlia_disjoint_query_paths
-        } else {
-            self.kademlia_disjoint_query_paths
-        };
-        let kademlia_replication_factor = if other_config.kademlia_replication_factor != 0 {
-            other_config.kademlia_replication_factor
-        } else {
-            self.kademlia_replication_factor
-        };
-        let protocol_names = if other_config.protocol_names != default_protocol_name() {
-            other_config.protocol_names
-        } else {
-            self.protocol_names.clone()
-        };
+    fn mix_config(&self, other_config: RoutingParams, explicit: &Explicit) -> Self {
+        let boot_nodes = explicit.pick(
+            "boot_nodes",
+            other_config.boot_nodes,
+            self.boot_nodes.clone(),
+        );
+        let dht_random_walk = explicit.pick(
+            "dht_random_walk",
+            other_config.dht_random_walk,
+            self.dht_random_walk,
+        );
+        let discovery_only_if_under_num = explicit.pick(
+            "discovery_only_if_under_num",
+            other_config.discovery_only_if_under_num,
+            self.discovery_only_if_under_num,
+        );
+        let allow_non_globals_in_dht = explicit.pick(
+            "allow_non_globals_in_dht",
+            other_config.allow_non_globals_in_dht,
+            self.allow_non_globals_in_dht,
+        );
+        let allow_private_ip = explicit.pick(
+            "allow_private_ip",
+            other_config.allow_private_ip,
+            self.allow_private_ip,
+        );
+        let enable_mdns = explicit.pick("enable_mdns", other_config.enable_mdns, self.enable_mdns);
+        let kademlia_disjoint_query_paths = explicit.pick(
+            "kademlia_disjoint_query_paths",
+            other_config.kademlia_disjoint_query_paths,
+            self.kademlia_disjoint_query_paths,
+        );
+        let kademlia_replication_factor = explicit.pick(
+            "kademlia_replication_factor",
+            other_config.kademlia_replication_factor,
+            self.kademlia_replication_factor,
+        );
+        let protocol_names = explicit.pick(
+            "protocol_names",
+            other_config.protocol_names,
+            self.protocol_names.clone(),
+        );
 
         Self {
             boot_nodes,
@@ -642,31 +3075,239 @@ impl Default for RoutingParams {
     }
 }
 
-fn deserialize_boot_nodes<'de, D>(deserializer: D) -> Result<Vec<RoutingNode>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let v: Vec<String> = Vec::deserialize(deserializer)?;
-
-    Ok(v.into_iter()
-        .map(|element| {
-            if let Some(pos) = element.find("/p2p/") {
-                // La parte antes de "/p2p/" (no incluye "/p2p/")
-                let address = &element[..pos].to_owned();
-                // La parte después de "/p2p/"
-                let peer_id = &element[pos + 5..].to_owned();
-                RoutingNode {
-                    address: address.split('_').map(|e| e.to_owned()).collect(),
-                    peer_id: peer_id.clone(),
+/// Boot node as written in the settings.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum BootNodeEntry {
+    /// Table with the peer id and the addresses of the node.
+    Node {
+        peer_id: String,
+        addresses: Vec<String>,
+    },
+    /// Legacy form, see `boot_node`.
+    Text(String),
+}
+
+impl BootNodeEntry {
+    fn is_blank(&self) -> bool {
+        matches!(self, BootNodeEntry::Text(text) if text.trim().is_empty())
+    }
+
+    fn parse(&self) -> Result<RoutingNode, String> {
+        match self {
+            BootNodeEntry::Node { peer_id, addresses } => routing_node(peer_id, addresses),
+            BootNodeEntry::Text(text) => boot_node(text.trim()),
+        }
+    }
+}
+
+/// Boot nodes, as an array of tables with `peer_id` and `addresses` in files, or as strings in
+/// the legacy form, which env vars use.
+fn deserialize_boot_nodes<'de, D>(deserializer: D) -> Result<Vec<RoutingNode>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let v: Vec<BootNodeEntry> = Vec::deserialize(deserializer)?;
+
+    // Entries are numbered as written, blank ones included.
+    v.iter()
+        .enumerate()
+        .filter(|(_, entry)| !entry.is_blank())
+        .map(|(index, entry)| {
+            entry.parse().map_err(|message| {
+                serde::de::Error::custom(format!("boot node {}: {}", index, message))
+            })
+        })
+        .collect()
+}
+
+/// Entries of a list given one per index, as `<prefix>_<n>_<FIELD>` variables, by index and
+/// then by field.
+fn env_indexed(prefix: &str) -> BTreeMap<u32, BTreeMap<String, String>> {
+    let var_prefix = format!("{prefix}_");
+    let mut entries: BTreeMap<u32, BTreeMap<String, String>> = BTreeMap::new();
+    for (name, value) in std::env::vars() {
+        let Some((index, field)) = name
+            .strip_prefix(&var_prefix)
+            .and_then(|name| name.split_once('_'))
+        else {
+            continue;
+        };
+        let Ok(index) = index.parse::<u32>() else {
+            continue;
+        };
+        entries
+            .entry(index)
+            .or_default()
+            .insert(field.to_owned(), value);
+    }
+    entries
+}
+
+/// Items of a list separated by commas.
+fn env_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Entries of a list given one per index, see `env_indexed`, in index order.
+fn env_entries<T, F>(prefix: &str, entry: F) -> Result<Vec<T>, Vec<ConfigError>>
+where
+    F: Fn(BTreeMap<String, String>) -> Result<T, String>,
+{
+    let mut errors = vec![];
+    let entries = env_indexed(prefix)
+        .into_iter()
+        .filter_map(|(index, fields)| {
+            entry(fields)
+                .map_err(|message| {
+                    errors.push(ConfigError::new(format!("{prefix}_{index}_*"), message))
+                })
+                .ok()
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(entries)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Boot nodes given one per index, as `<prefix>_BOOT_NODES_<n>_PEER_ID` and
+/// `<prefix>_BOOT_NODES_<n>_ADDRESSES` with the addresses separated by commas, in index order.
+fn env_boot_nodes(prefix: &str) -> Result<Vec<RoutingNode>, Vec<ConfigError>> {
+    env_entries(&format!("{prefix}_BOOT_NODES"), |fields| {
+        let addresses = fields
+            .get("ADDRESSES")
+            .map(|addresses| env_list(addresses))
+            .unwrap_or_default();
+        match fields.get("PEER_ID") {
+            Some(peer_id) => routing_node(peer_id, &addresses),
+            None => Err("the peer id is missing".to_owned()),
+        }
+    })
+}
+
+/// Schedules given one per index, as `<prefix>_SCHEDULES_<n>_NAME`, `_INTERVAL` and `_ACTION`,
+/// in index order. The action is a JSON table, e.g. `{"type": "verify", "subject_id": "..."}`,
+/// or the type of an action without fields, e.g. `report`.
+fn env_schedules(prefix: &str) -> Result<Vec<Schedule>, Vec<ConfigError>> {
+    env_entries(&format!("{prefix}_SCHEDULES"), |fields| {
+        let mut schedule = serde_json::Map::new();
+        if let Some(name) = fields.get("NAME") {
+            schedule.insert("name".to_owned(), name.as_str().into());
+        }
+        if let Some(interval) = fields.get("INTERVAL") {
+            schedule.insert("interval".to_owned(), interval.as_str().into());
+        }
+        if let Some(action) = fields.get("ACTION").map(|action| action.trim()) {
+            let action = if action.starts_with('{') {
+                serde_json::from_str(action).map_err(|error| error.to_string())?
+            } else {
+                serde_json::json!({ "type": action.to_lowercase() })
+            };
+            schedule.insert("action".to_owned(), action);
+        }
+        serde_json::from_value(schedule.into()).map_err(|error| error.to_string())
+    })
+}
+
+/// Signing policies given one per index, as `<prefix>_SIGNING_POLICIES_<n>_REQUEST_TYPES` and
+/// `_SIGNERS` separated by commas, and `_EXTERNAL_SIGNER`, in index order.
+fn env_signing_policies(prefix: &str) -> Result<Vec<SigningPolicy>, Vec<ConfigError>> {
+    env_entries(&format!("{prefix}_SIGNING_POLICIES"), |fields| {
+        let list = |field: &str| fields.get(field).map(|value| env_list(value));
+        let external_signer = match fields.get("EXTERNAL_SIGNER").map(|value| value.trim()) {
+            Some(value) => Some(
+                value
+                    .parse::<bool>()
+                    .map_err(|_| format!("'{}' is not true or false", value))?,
+            ),
+            None => None,
+        };
+        let mut policy = serde_json::Map::new();
+        if let Some(request_types) = list("REQUEST_TYPES") {
+            policy.insert("request_types".to_owned(), request_types.into());
+        }
+        if let Some(signers) = list("SIGNERS") {
+            policy.insert("signers".to_owned(), signers.into());
+        }
+        if let Some(external_signer) = external_signer {
+            policy.insert("external_signer".to_owned(), external_signer.into());
+        }
+        serde_json::from_value(policy.into()).map_err(|error| error.to_string())
+    })
+}
+
+/// Approval rules given one per index, as `<prefix>_APPROVAL_RULES_<n>_GOVERNANCE_ID` and
+/// `_SCHEMA_ID`, in index order.
+fn env_approval_rules(prefix: &str) -> Result<Vec<ApprovalRule>, Vec<ConfigError>> {
+    env_entries(&format!("{prefix}_APPROVAL_RULES"), |fields| {
+        Ok(ApprovalRule {
+            governance_id: fields
+                .get("GOVERNANCE_ID")
+                .map(|id| id.trim().to_owned())
+                .unwrap_or_default(),
+            schema_id: fields
+                .get("SCHEMA_ID")
+                .map(|id| id.trim().to_owned())
+                .unwrap_or_default(),
+        })
+    })
+}
+
+/// Check a boot node given by its peer id and its addresses.
+fn routing_node(peer_id: &str, addresses: &[String]) -> Result<RoutingNode, String> {
+    if PeerId::from_str(peer_id).is_err() {
+        return Err(format!("'{}' is not a peer id", peer_id));
+    }
+    if addresses.is_empty() {
+        return Err(format!("peer {} has no addresses", peer_id));
+    }
+    let address = addresses
+        .iter()
+        .map(|address| multiaddr(address).map(|address| address.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(RoutingNode {
+        address,
+        peer_id: peer_id.to_owned(),
+    })
+}
+
+/// Parse a boot node in the legacy form, its addresses separated by `_` and followed by
+/// `/p2p/<peer id>`: once after the last one, `<address>_<address>/p2p/<peer id>`, or after
+/// each of them, `<address>/p2p/<peer id>_<address>/p2p/<peer id>`, always with the same peer
+/// id.
+fn boot_node(element: &str) -> Result<RoutingNode, String> {
+    let mut peer_id: Option<&str> = None;
+    let mut address = vec![];
+    for part in element.split('_') {
+        let part = match part.rsplit_once("/p2p/") {
+            Some((part, id)) => {
+                if PeerId::from_str(id).is_err() {
+                    return Err(format!("'{}' is not a peer id", id));
                 }
-            } else {
-                RoutingNode {
-                    address: vec![],
-                    peer_id: String::default(),
+                if peer_id.is_some_and(|peer_id| peer_id != id) {
+                    return Err(format!("'{}' has addresses of different peers", element));
                 }
+                peer_id = Some(id);
+                part
             }
-        })
-        .collect())
+            None => part,
+        };
+        address.push(multiaddr(part)?.to_string());
+    }
+    let Some(peer_id) = peer_id else {
+        return Err(format!("'{}' is not <addresses>/p2p/<peer id>", element));
+    };
+    Ok(RoutingNode {
+        address,
+        peer_id: peer_id.to_owned(),
+    })
 }
 
 fn default_true() -> bool {
@@ -692,7 +3333,10 @@ struct NodeParams {
     digest_derivator: DigestDerivatorParams,
     #[serde(default = "default_replication_factor")]
     replication_factor: f64,
-    #[serde(default = "default_timeout")]
+    #[serde(
+        default = "default_timeout",
+        deserialize_with = "deserialize_duration_millis"
+    )]
     timeout: u32,
     #[serde(default)]
     passvotation: u8,
@@ -701,64 +3345,40 @@ struct NodeParams {
 }
 
 impl NodeParams {
-    fn from_env(parent: &str) -> Self {
-        let mut config = config::Config::builder();
-        config = config.add_source(config::Environment::with_prefix(&format!("{parent}NODE")));
-
-        let config = config
-            .build()
-            .map_err(|e| {
-                println!("Error building config: {}", e);
-            })
-            .unwrap();
-
-        config
-            .try_deserialize()
-            .map_err(|e| {
-                println!("Error try deserialize config: {}", e);
-            })
-            .unwrap()
+    fn from_env(parent: &str) -> Result<Self, Vec<ConfigError>> {
+        let prefix = format!("{parent}NODE");
+        deserialize_env(&prefix, config::Environment::with_prefix(&prefix))
     }
 
-    fn mix_config(&self, other_config: NodeParams) -> Self {
-        let key_derivator = if other_config.key_derivator != KeyDerivatorParams::default() {
-            other_config.key_derivator
-        } else {
-            self.key_derivator.clone()
-        };
+    fn mix_config(&self, other_config: NodeParams, explicit: &Explicit) -> Self {
+        let key_derivator = explicit.pick(
+            "key_derivator",
+            other_config.key_derivator,
+            self.key_derivator.clone(),
+        );
 
-        let digest_derivator = if other_config.digest_derivator != DigestDerivatorParams::default()
-        {
-            other_config.digest_derivator
-        } else {
-            self.digest_derivator.clone()
-        };
+        let digest_derivator = explicit.pick(
+            "digest_derivator",
+            other_config.digest_derivator,
+            self.digest_derivator.clone(),
+        );
 
-        let replication_factor = if other_config.replication_factor != default_replication_factor()
-        {
-            other_config.replication_factor
-        } else {
-            self.replication_factor
-        };
+        let replication_factor = explicit.pick(
+            "replication_factor",
+            other_config.replication_factor,
+            self.replication_factor,
+        );
 
-        let timeout = if other_config.timeout != default_timeout() {
-            other_config.timeout
-        } else {
-            self.timeout
-        };
+        let timeout = explicit.pick("timeout", other_config.timeout, self.timeout);
 
-        let passvotation = if other_config.passvotation != 0 {
-            other_config.passvotation
-        } else {
-            self.passvotation
-        };
+        let passvotation =
+            explicit.pick("passvotation", other_config.passvotation, self.passvotation);
 
-        let smartcontracts_directory =
-            if other_config.smartcontracts_directory != default_smartcontracts_directory() {
-                other_config.smartcontracts_directory
-            } else {
-                self.smartcontracts_directory.clone()
-            };
+        let smartcontracts_directory = explicit.pick(
+            "smartcontracts_directory",
+            other_config.smartcontracts_directory,
+            self.smartcontracts_directory.clone(),
+        );
 
         Self {
             key_derivator,
@@ -811,142 +3431,923 @@ impl From<KeyDerivatorParams> for kore_base::KeyDerivator {
             KeyDerivatorParams::Secp256k1 => kore_base::KeyDerivator::Secp256k1,
         }
     }
-}
+}
+
+/// Key derivators availables
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub enum DigestDerivatorParams {
+    Blake3_256,
+    Blake3_512,
+    SHA2_256,
+    SHA2_512,
+    SHA3_256,
+    SHA3_512,
+}
+
+impl From<DigestDerivatorParams> for kore_base::DigestDerivator {
+    fn from(val: DigestDerivatorParams) -> Self {
+        match val {
+            DigestDerivatorParams::Blake3_256 => kore_base::DigestDerivator::Blake3_256,
+            DigestDerivatorParams::Blake3_512 => kore_base::DigestDerivator::Blake3_512,
+            DigestDerivatorParams::SHA2_256 => kore_base::DigestDerivator::SHA2_256,
+            DigestDerivatorParams::SHA2_512 => kore_base::DigestDerivator::SHA2_512,
+            DigestDerivatorParams::SHA3_256 => kore_base::DigestDerivator::SHA3_256,
+            DigestDerivatorParams::SHA3_512 => kore_base::DigestDerivator::SHA3_512,
+        }
+    }
+}
+
+impl Default for KeyDerivatorParams {
+    fn default() -> Self {
+        Self::Ed25519
+    }
+}
+
+impl Default for DigestDerivatorParams {
+    fn default() -> Self {
+        Self::Blake3_256
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, time::Duration};
+
+    use kore_base::{NodeType, RoutingNode};
+    use serial_test::serial;
+
+    use crate::config::render::settings_document;
+    use crate::settings::{KeyKdf, KeysBackend, LogFormat, TimestampFormat, VaultEngine};
+    use crate::{
+        config::params::{
+            to_strings, AccessLogParams, ApiCallParams, ArchivalParams, AuthParams, BackupParams,
+            BootstrapParams, ControlListParams, DbBatchParams, DbParams, DbTtlParams,
+            DigestDerivatorParams, GrpcParams, KeyDerivatorParams, KeysParams, KoreParams,
+            LimitsParams, LoggingParams, NetworkParams, NodeParams, Params, QuotaParams,
+            ReplicationParams, RoutingParams, ServicesParams, SignatureCheckParams, SoakParams,
+            SupervisorParams, WarmUpParams, WebhookParams,
+        },
+        settings::{default_data_path, DbBatchSettings, DbSettings, KoreSettings, ReplicationMode},
+    };
+
+    use super::{boot_node, Explicit, TellParams};
+
+    fn written(keys: &[&str]) -> Explicit {
+        Explicit(keys.iter().map(|key| (*key).to_owned()).collect())
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_tell_default() {
+        let tell = TellParams::from_env("KORE_NETWORK_").unwrap();
+
+        assert_eq!(tell.message_timeout_secs, Duration::from_secs(10));
+        assert_eq!(tell.max_concurrent_streams, 100);
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_control_list_default() {
+        let control_list = ControlListParams::from_env("KORE_NETWORK_").unwrap();
+
+        assert!(control_list.allow_list.is_empty());
+        assert!(control_list.block_list.is_empty());
+        assert!(control_list.service_allow_list.is_empty());
+        assert!(control_list.service_block_list.is_empty());
+        assert!(!control_list.enable);
+        assert_eq!(control_list.interval_request, Duration::from_secs(60));
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_routing_default() {
+        let routing = RoutingParams::from_env("KORE_NETWORK_").unwrap();
+        println!("{:?}", routing.boot_nodes);
+        assert!(routing.boot_nodes.is_empty());
+
+        assert_eq!(routing.dht_random_walk, true);
+        assert_eq!(routing.discovery_only_if_under_num, std::u64::MAX);
+        assert_eq!(routing.allow_non_globals_in_dht, false);
+        assert_eq!(routing.allow_private_ip, false);
+        assert_eq!(routing.enable_mdns, true);
+        assert_eq!(routing.kademlia_disjoint_query_paths, true);
+        assert_eq!(routing.kademlia_replication_factor, 0);
+        assert_eq!(routing.protocol_names[0], "/kore/routing/1.0.0".to_owned());
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_node_default() {
+        let node: NodeParams = NodeParams::from_env("KORE_").unwrap();
+
+        assert_eq!(node.key_derivator, KeyDerivatorParams::Ed25519);
+        assert_eq!(node.digest_derivator, DigestDerivatorParams::Blake3_256);
+        assert_eq!(node.replication_factor, 0.25f64);
+        assert_eq!(node.timeout, 3000u32);
+        assert_eq!(node.passvotation, 0);
+        assert_eq!(node.smartcontracts_directory, "./contracts");
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_network_default() {
+        let network = NetworkParams::from_env("KORE_").unwrap();
+
+        assert_eq!(network.port_reuse, false);
+        assert_eq!(network.user_agent, "kore-node");
+        assert_eq!(network.node_type, NodeType::Bootstrap);
+        assert!(network.listen_addresses.is_empty());
+        assert!(network.external_addresses.is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_kore_params_default() {
+        let kore = KoreParams::from_env("KORE").unwrap();
+
+        #[cfg(feature = "leveldb")]
+        assert_eq!(
+            kore.db.settings(kore.db_read_pool_size),
+            DbSettings::LevelDB(default_data_path("leveldb"))
+        );
+        #[cfg(feature = "sqlite")]
+        assert_eq!(
+            kore.db.settings(kore.db_read_pool_size),
+            DbSettings::Sqlite(default_data_path("sqlitedb"))
+        );
+        assert_eq!(kore.db_read_pool_size, 4);
+        assert_eq!(kore.keys_path, default_data_path("keys"));
+        assert!(!kore.regenerate_corrupted_keys);
+        assert!(!kore.migrate_legacy_data);
+        assert!(!kore.lifecycle_events);
+        assert_eq!(kore.keys_backend, KeysBackend::File);
+        assert_eq!(kore.timestamp_format, TimestampFormat::Epoch);
+        assert_eq!(kore.pkcs11.label, "kore-node".to_owned());
+        assert_eq!(kore.prometheus, "0.0.0.0:3050".to_owned());
+        assert!(kore.http_api.is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_quota_values() {
+        let quota = QuotaParams::from_env("KORE_").unwrap();
+        assert_eq!(quota.max_subjects, 0);
+        assert_eq!(quota.window, Duration::from_secs(3600));
+
+        std::env::set_var("KORE_QUOTA_MAX_SUBJECTS", "10");
+        std::env::set_var("KORE_QUOTA_WINDOW", "5m");
+
+        let quota = QuotaParams::from_env("KORE_").unwrap();
+
+        assert_eq!(quota.max_subjects, 10);
+        assert_eq!(quota.window, Duration::from_secs(300));
+
+        std::env::remove_var("KORE_QUOTA_MAX_SUBJECTS");
+        std::env::remove_var("KORE_QUOTA_WINDOW");
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_signature_check_values() {
+        let check = SignatureCheckParams::from_env("KORE_").unwrap();
+        assert!(!check.enabled);
+        assert_eq!(check.max_age, Duration::from_secs(300));
+        assert_eq!(check.max_future, Duration::from_secs(30));
+
+        std::env::set_var("KORE_SIGNATURE_CHECK_ENABLED", "true");
+        std::env::set_var("KORE_SIGNATURE_CHECK_MAX_AGE", "1m");
+
+        let check = SignatureCheckParams::from_env("KORE_").unwrap();
+
+        assert!(check.enabled);
+        assert_eq!(check.max_age, Duration::from_secs(60));
+        assert_eq!(check.max_future, Duration::from_secs(30));
+
+        std::env::remove_var("KORE_SIGNATURE_CHECK_ENABLED");
+        std::env::remove_var("KORE_SIGNATURE_CHECK_MAX_AGE");
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_api_call_values() {
+        let api = ApiCallParams::from_env("KORE_").unwrap();
+        assert_eq!(api.timeout_ms, 0);
+        assert_eq!(api.retries, 0);
+        assert_eq!(api.backoff, Duration::from_millis(100));
+
+        std::env::set_var("KORE_API_TIMEOUT_MS", "2s");
+        std::env::set_var("KORE_API_RETRIES", "3");
+        std::env::set_var("KORE_API_BACKOFF", "250ms");
+
+        let api = ApiCallParams::from_env("KORE_").unwrap();
+
+        assert_eq!(api.timeout_ms, 2000);
+        assert_eq!(api.retries, 3);
+        assert_eq!(api.backoff, Duration::from_millis(250));
+
+        std::env::remove_var("KORE_API_TIMEOUT_MS");
+        std::env::remove_var("KORE_API_RETRIES");
+        std::env::remove_var("KORE_API_BACKOFF");
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_limits_values() {
+        let limits = LimitsParams::from_env("KORE_").unwrap();
+        assert_eq!(limits.read_rate, 0.0);
+        assert_eq!(limits.write_max_in_flight, 0);
+
+        std::env::set_var("KORE_LIMITS_WRITE_RATE", "2.5");
+        std::env::set_var("KORE_LIMITS_WRITE_BURST", "10");
+        std::env::set_var("KORE_LIMITS_READ_MAX_IN_FLIGHT", "64");
+
+        let limits = LimitsParams::from_env("KORE_").unwrap();
+
+        assert_eq!(limits.write_rate, 2.5);
+        assert_eq!(limits.write_burst, 10);
+        assert_eq!(limits.read_max_in_flight, 64);
+        assert_eq!(limits.read_rate, 0.0);
+
+        let mixed = LimitsParams {
+            read_rate: 100.0,
+            ..Default::default()
+        }
+        .mix_config(
+            limits,
+            &written(&["write_rate", "write_burst", "read_max_in_flight"]),
+        );
+        assert_eq!(mixed.read_rate, 100.0);
+        assert_eq!(mixed.write_rate, 2.5);
+
+        std::env::remove_var("KORE_LIMITS_WRITE_RATE");
+        std::env::remove_var("KORE_LIMITS_WRITE_BURST");
+        std::env::remove_var("KORE_LIMITS_READ_MAX_IN_FLIGHT");
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_auth_values() {
+        std::env::set_var("KORE_AUTH_API_KEYS", "first-key,second-key");
+        std::env::set_var("KORE_AUTH_ISSUER", "https://issuer.example.com/");
+        std::env::set_var("KORE_AUTH_JWKS_URL", "https://issuer.example.com/jwks.json");
+
+        let auth = AuthParams::from_env("KORE_").unwrap();
+
+        assert_eq!(auth.api_keys, vec!["first-key", "second-key"]);
+        assert_eq!(auth.issuer, "https://issuer.example.com/");
+        assert_eq!(auth.jwks_url, "https://issuer.example.com/jwks.json");
+        assert!(auth.audience.is_empty());
+
+        let mixed = AuthParams {
+            audience: "kore-node".to_owned(),
+            ..Default::default()
+        }
+        .mix_config(auth, &written(&["api_keys", "issuer", "jwks_url"]));
+        assert_eq!(mixed.audience, "kore-node");
+        assert_eq!(mixed.api_keys.len(), 2);
+
+        std::env::remove_var("KORE_AUTH_API_KEYS");
+        std::env::remove_var("KORE_AUTH_ISSUER");
+        std::env::remove_var("KORE_AUTH_JWKS_URL");
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_access_log_values() {
+        let access_log = AccessLogParams::from_env("KORE_").unwrap();
+        assert_eq!(access_log.sample_rate, 1.0);
+        assert_eq!(access_log.slow_threshold, Duration::from_secs(1));
+
+        std::env::set_var("KORE_ACCESS_LOG_SAMPLE_RATE", "0.25");
+        std::env::set_var("KORE_ACCESS_LOG_SLOW_THRESHOLD", "300ms");
+
+        let access_log = AccessLogParams::from_env("KORE_").unwrap();
+
+        assert_eq!(access_log.sample_rate, 0.25);
+        assert_eq!(access_log.slow_threshold, Duration::from_millis(300));
+
+        std::env::remove_var("KORE_ACCESS_LOG_SAMPLE_RATE");
+        std::env::remove_var("KORE_ACCESS_LOG_SLOW_THRESHOLD");
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_logging_values() {
+        let logging = LoggingParams::from_env("KORE_").unwrap();
+        assert_eq!(logging.level, "info");
+        assert!(logging.targets.is_empty());
+        assert_eq!(logging.format, LogFormat::Pretty);
+        assert_eq!(logging.max_file_size, 10 * 1024 * 1024);
+
+        std::env::set_var("KORE_LOGGING_LEVEL", "debug");
+        std::env::set_var("KORE_LOGGING_TARGETS", "kore_base=warn, libp2p=error");
+        std::env::set_var("KORE_LOGGING_FORMAT", "json");
+        std::env::set_var("KORE_LOGGING_FILE", "logs/node.log");
+        std::env::set_var("KORE_LOGGING_MAX_FILE_SIZE", "1MiB");
+        std::env::set_var("KORE_LOGGING_MAX_FILES", "2");
+
+        let logging = LoggingParams::from_env("KORE_").unwrap();
+
+        assert_eq!(logging.level, "debug");
+        assert_eq!(
+            logging.targets,
+            BTreeMap::from([
+                ("kore_base".to_owned(), "warn".to_owned()),
+                ("libp2p".to_owned(), "error".to_owned())
+            ])
+        );
+        assert_eq!(logging.format, LogFormat::Json);
+        assert_eq!(logging.file, "logs/node.log");
+        assert_eq!(logging.max_file_size, 1 << 20);
+        assert_eq!(logging.max_files, 2);
+
+        std::env::remove_var("KORE_LOGGING_LEVEL");
+        std::env::remove_var("KORE_LOGGING_TARGETS");
+        std::env::remove_var("KORE_LOGGING_FORMAT");
+        std::env::remove_var("KORE_LOGGING_FILE");
+        std::env::remove_var("KORE_LOGGING_MAX_FILE_SIZE");
+        std::env::remove_var("KORE_LOGGING_MAX_FILES");
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_grpc_values() {
+        let grpc = GrpcParams::from_env("KORE_").unwrap();
+        assert!(grpc.listen.is_empty());
+
+        std::env::set_var("KORE_GRPC_LISTEN", "0.0.0.0:50051");
+        std::env::set_var("KORE_GRPC_TLS_CERT", "certs/node.pem");
+        std::env::set_var("KORE_GRPC_TLS_KEY", "certs/node.key");
+
+        let grpc = GrpcParams::from_env("KORE_").unwrap();
+
+        assert_eq!(grpc.listen, "0.0.0.0:50051");
+        assert_eq!(grpc.tls_cert, "certs/node.pem");
+        assert_eq!(grpc.tls_key, "certs/node.key");
+
+        std::env::remove_var("KORE_GRPC_LISTEN");
+        std::env::remove_var("KORE_GRPC_TLS_CERT");
+        std::env::remove_var("KORE_GRPC_TLS_KEY");
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_webhook_values() {
+        let webhooks = WebhookParams::from_env("KORE_").unwrap();
+        assert!(webhooks.urls.is_empty());
+        assert_eq!(webhooks.max_retries, 3);
+        assert_eq!(webhooks.retry_backoff, Duration::from_secs(1));
+
+        std::env::set_var(
+            "KORE_WEBHOOKS_URLS",
+            "https://erp.example/kore,http://10.0.0.2:8080/hook",
+        );
+        std::env::set_var("KORE_WEBHOOKS_SECRET", "s3cr3t");
+        std::env::set_var("KORE_WEBHOOKS_MAX_RETRIES", "5");
+        std::env::set_var("KORE_WEBHOOKS_RETRY_BACKOFF", "500ms");
+
+        let webhooks = WebhookParams::from_env("KORE_").unwrap();
+
+        assert_eq!(
+            webhooks.urls,
+            vec!["https://erp.example/kore", "http://10.0.0.2:8080/hook"]
+        );
+        assert_eq!(webhooks.secret, "s3cr3t");
+        assert_eq!(webhooks.max_retries, 5);
+        assert_eq!(webhooks.retry_backoff, Duration::from_millis(500));
+
+        std::env::remove_var("KORE_WEBHOOKS_URLS");
+        std::env::remove_var("KORE_WEBHOOKS_SECRET");
+        std::env::remove_var("KORE_WEBHOOKS_MAX_RETRIES");
+        std::env::remove_var("KORE_WEBHOOKS_RETRY_BACKOFF");
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_warm_up_values() {
+        let warm_up = WarmUpParams::from_env("KORE_").unwrap();
+        assert!(warm_up.subjects.is_empty());
+        assert_eq!(warm_up.recent, 0);
+        assert_eq!(warm_up.timeout, Duration::from_secs(30));
+
+        std::env::set_var(
+            "KORE_WARM_UP_SUBJECTS",
+            "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE,Jq6Zk8-tUCmZtqZtzCbYV4hmFvVdnkXG1wFxdhc5Vmx8",
+        );
+        std::env::set_var("KORE_WARM_UP_RECENT", "20");
+        std::env::set_var("KORE_WARM_UP_TIMEOUT", "2m");
+
+        let warm_up = WarmUpParams::from_env("KORE_").unwrap();
+
+        assert_eq!(warm_up.subjects.len(), 2);
+        assert_eq!(warm_up.recent, 20);
+        assert_eq!(warm_up.timeout, Duration::from_secs(120));
+
+        std::env::remove_var("KORE_WARM_UP_SUBJECTS");
+        std::env::remove_var("KORE_WARM_UP_RECENT");
+        std::env::remove_var("KORE_WARM_UP_TIMEOUT");
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_supervisor_values() {
+        let supervisor = SupervisorParams::from_env("KORE_").unwrap();
+        assert!(!supervisor.enabled);
+        assert_eq!(supervisor.max_restarts, 5);
+        assert_eq!(supervisor.window, Duration::from_secs(600));
+        assert_eq!(supervisor.backoff, Duration::from_secs(1));
+
+        std::env::set_var("KORE_SUPERVISOR_ENABLED", "true");
+        std::env::set_var("KORE_SUPERVISOR_MAX_RESTARTS", "3");
+        std::env::set_var("KORE_SUPERVISOR_MAX_BACKOFF", "5m");
+
+        let supervisor = SupervisorParams::from_env("KORE_").unwrap();
+
+        assert!(supervisor.enabled);
+        assert_eq!(supervisor.max_restarts, 3);
+        assert_eq!(supervisor.max_backoff, Duration::from_secs(300));
+        let mixed = SupervisorParams {
+            window: Duration::from_secs(60),
+            ..Default::default()
+        }
+        .mix_config(
+            supervisor,
+            &written(&["enabled", "max_restarts", "max_backoff"]),
+        );
+        assert!(mixed.enabled);
+        assert_eq!(mixed.max_restarts, 3);
+        assert_eq!(mixed.window, Duration::from_secs(60));
+
+        std::env::remove_var("KORE_SUPERVISOR_ENABLED");
+        std::env::remove_var("KORE_SUPERVISOR_MAX_RESTARTS");
+        std::env::remove_var("KORE_SUPERVISOR_MAX_BACKOFF");
+    }
 
-/// Key derivators availables
-#[derive(Deserialize, Debug, PartialEq, Clone)]
-pub enum DigestDerivatorParams {
-    Blake3_256,
-    Blake3_512,
-    SHA2_256,
-    SHA2_512,
-    SHA3_256,
-    SHA3_512,
-}
+    #[test]
+    #[serial]
+    fn test_from_env_backup_values() {
+        let backup = BackupParams::from_env("KORE_").unwrap();
+        assert!(backup.directory.is_empty());
+        assert_eq!(backup.interval, Duration::ZERO);
+        assert_eq!(backup.keep, 7);
 
-impl From<DigestDerivatorParams> for kore_base::DigestDerivator {
-    fn from(val: DigestDerivatorParams) -> Self {
-        match val {
-            DigestDerivatorParams::Blake3_256 => kore_base::DigestDerivator::Blake3_256,
-            DigestDerivatorParams::Blake3_512 => kore_base::DigestDerivator::Blake3_512,
-            DigestDerivatorParams::SHA2_256 => kore_base::DigestDerivator::SHA2_256,
-            DigestDerivatorParams::SHA2_512 => kore_base::DigestDerivator::SHA2_512,
-            DigestDerivatorParams::SHA3_256 => kore_base::DigestDerivator::SHA3_256,
-            DigestDerivatorParams::SHA3_512 => kore_base::DigestDerivator::SHA3_512,
-        }
+        std::env::set_var("KORE_BACKUP_DIRECTORY", "backups");
+        std::env::set_var("KORE_BACKUP_INTERVAL", "6h");
+        std::env::set_var("KORE_BACKUP_KEEP", "3");
+
+        let backup = BackupParams::from_env("KORE_").unwrap();
+
+        assert_eq!(backup.directory, "backups");
+        assert_eq!(backup.interval, Duration::from_secs(6 * 3600));
+        assert_eq!(backup.keep, 3);
+
+        std::env::remove_var("KORE_BACKUP_DIRECTORY");
+        std::env::remove_var("KORE_BACKUP_INTERVAL");
+        std::env::remove_var("KORE_BACKUP_KEEP");
     }
-}
 
-impl Default for KeyDerivatorParams {
-    fn default() -> Self {
-        Self::Ed25519
+    #[test]
+    #[serial]
+    fn test_from_env_archival_values() {
+        let archival = ArchivalParams::from_env("KORE_").unwrap();
+        assert!(archival.destination.is_empty());
+        assert_eq!(archival.max_age, Duration::ZERO);
+        assert_eq!(archival.max_size, 0);
+
+        std::env::set_var("KORE_ARCHIVAL_DESTINATION", "s3://kore/archive");
+        std::env::set_var("KORE_ARCHIVAL_MAX_AGE", "30d");
+        std::env::set_var("KORE_ARCHIVAL_MAX_SIZE", "1GiB");
+        std::env::set_var("KORE_ARCHIVAL_INTERVAL", "1h");
+
+        let archival = ArchivalParams::from_env("KORE_").unwrap();
+
+        assert_eq!(archival.destination, "s3://kore/archive");
+        assert_eq!(archival.max_age, Duration::from_secs(30 * 86400));
+        assert_eq!(archival.max_size, 1 << 30);
+        assert_eq!(archival.interval, Duration::from_secs(3600));
+
+        std::env::remove_var("KORE_ARCHIVAL_DESTINATION");
+        std::env::remove_var("KORE_ARCHIVAL_MAX_AGE");
+        std::env::remove_var("KORE_ARCHIVAL_MAX_SIZE");
+        std::env::remove_var("KORE_ARCHIVAL_INTERVAL");
     }
-}
 
-impl Default for DigestDerivatorParams {
-    fn default() -> Self {
-        Self::Blake3_256
+    #[test]
+    #[serial]
+    fn test_from_env_soak_values() {
+        let soak = SoakParams::from_env("KORE_").unwrap();
+        assert_eq!(soak.rate, 0);
+        assert_eq!(soak.schema_id, "governance");
+        assert_eq!(soak.subjects, 10);
+        assert_eq!(soak.report_interval, Duration::from_secs(10));
+
+        std::env::set_var("KORE_SOAK_RATE", "50");
+        std::env::set_var("KORE_SOAK_DURATION", "1h");
+        std::env::set_var(
+            "KORE_SOAK_GOVERNANCE_ID",
+            "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+        );
+        std::env::set_var("KORE_SOAK_SCHEMA_ID", "Wine");
+        std::env::set_var("KORE_SOAK_PAYLOAD", r#"{"Harvest":{"liters":10}}"#);
+        std::env::set_var("KORE_SOAK_MAX_IN_FLIGHT", "500");
+
+        let soak = SoakParams::from_env("KORE_").unwrap();
+
+        assert_eq!(soak.rate, 50);
+        assert_eq!(soak.duration, Duration::from_secs(3600));
+        assert_eq!(
+            soak.governance_id,
+            "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE"
+        );
+        assert_eq!(soak.schema_id, "Wine");
+        assert_eq!(soak.payload, r#"{"Harvest":{"liters":10}}"#);
+        assert_eq!(soak.max_in_flight, 500);
+
+        std::env::remove_var("KORE_SOAK_RATE");
+        std::env::remove_var("KORE_SOAK_DURATION");
+        std::env::remove_var("KORE_SOAK_GOVERNANCE_ID");
+        std::env::remove_var("KORE_SOAK_SCHEMA_ID");
+        std::env::remove_var("KORE_SOAK_PAYLOAD");
+        std::env::remove_var("KORE_SOAK_MAX_IN_FLIGHT");
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::time::Duration;
+    #[test]
+    #[serial]
+    fn test_from_env_db_values() {
+        std::env::set_var("KORE_DB_PATH", "./fake/db/path");
+        std::env::set_var("KORE_DB_URL", "postgres://kore@db.example.com/kore");
+        std::env::set_var("KORE_DB_OPTIONS", "cache_size=-4000, mmap_size=0");
 
-    use kore_base::{NodeType, RoutingNode};
-    use serial_test::serial;
+        let db = DbParams::from_env("KORE_").unwrap();
 
-    use crate::{
-        config::params::{
-            ControlListParams, DigestDerivatorParams, KeyDerivatorParams, KoreParams,
-            NetworkParams, NodeParams, Params, RoutingParams,
-        },
-        settings::DbSettings,
-    };
+        assert_eq!(db.db_type, None);
+        assert_eq!(db.path, "./fake/db/path");
+        assert_eq!(db.url, "postgres://kore@db.example.com/kore");
+        assert_eq!(
+            db.options,
+            BTreeMap::from([
+                ("cache_size".to_owned(), "-4000".to_owned()),
+                ("mmap_size".to_owned(), "0".to_owned())
+            ])
+        );
+
+        std::env::set_var("KORE_DB_TYPE", "sqlite");
+        let db = DbParams::from_env("KORE_");
+        #[cfg(feature = "sqlite")]
+        assert_eq!(
+            db.unwrap().settings(4),
+            DbSettings::Sqlite("./fake/db/path".to_owned())
+        );
+        #[cfg(not(feature = "sqlite"))]
+        assert!(db.is_err());
 
-    use super::TellParams;
+        std::env::set_var("KORE_DB_TYPE", "cassandra");
+        let errors = DbParams::from_env("KORE_").unwrap_err();
+        assert_eq!(errors[0].location, "KORE_DB_*");
+
+        std::env::remove_var("KORE_DB_PATH");
+        std::env::remove_var("KORE_DB_URL");
+        std::env::remove_var("KORE_DB_OPTIONS");
+        std::env::remove_var("KORE_DB_TYPE");
+    }
 
     #[test]
     #[serial]
-    fn test_from_env_tell_default() {
-        let tell = TellParams::from_env("KORE_NETWORK_");
+    fn test_mix_config_written_defaults() {
+        std::env::set_var("KORE_NETWORK_PORT_REUSE", "true");
+        std::env::set_var("KORE_LIMITS_READ_RATE", "5.0");
+        std::env::set_var("KORE_LIMITS_WRITE_BURST", "7");
+        std::env::set_var("KORE_DB_BATCH_SYNC", "false");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[kore.network]\nport_reuse = false\n\n\
+             [kore.limits]\nread_rate = 0.0\n\n\
+             [kore.db_batch]\nsync = true\n",
+        )
+        .unwrap();
+        let file = Params::from_file(path.to_str().unwrap()).unwrap();
+        let env = Params::from_env().unwrap();
+        let settings = KoreSettings::from(env.mix_config(file));
+
+        // The values written in the file win even when they are the defaults.
+        assert!(!settings.settings.network.port_reuse);
+        assert_eq!(settings.limits.reads.rate, 0.0);
+        assert!(settings.db_batch.sync);
+        // The values not written in the file keep those of the environment.
+        assert_eq!(settings.limits.writes.burst, 7);
 
-        assert_eq!(tell.message_timeout_secs, Duration::from_secs(10));
-        assert_eq!(tell.max_concurrent_streams, 100);
+        std::env::remove_var("KORE_NETWORK_PORT_REUSE");
+        std::env::remove_var("KORE_LIMITS_READ_RATE");
+        std::env::remove_var("KORE_LIMITS_WRITE_BURST");
+        std::env::remove_var("KORE_DB_BATCH_SYNC");
     }
 
     #[test]
-    #[serial]
-    fn test_from_env_control_list_default() {
-        let control_list = ControlListParams::from_env("KORE_NETWORK_");
+    fn test_explicit_keys() {
+        let explicit = Explicit::of(&serde_json::json!({
+            "kore": {
+                "network": { "port_reuse": false, "listen_addresses": [] },
+                "db_path": "./db",
+            }
+        }));
+        let network = explicit.scope("kore").scope("network");
+        assert!(explicit.has("kore.network"));
+        assert!(network.has("port_reuse"));
+        assert!(network.has("listen_addresses"));
+        assert!(!network.has("user_agent"));
+        assert!(!network.pick("port_reuse", false, true));
+        assert_eq!(network.pick("user_agent", "file", "env"), "env");
+    }
 
-        assert!(control_list.allow_list.is_empty());
-        assert!(control_list.block_list.is_empty());
-        assert!(control_list.service_allow_list.is_empty());
-        assert!(control_list.service_block_list.is_empty());
-        assert!(!control_list.enable);
-        assert_eq!(control_list.interval_request, Duration::from_secs(60));
+    #[test]
+    fn test_db_params_legacy_path() {
+        let env = DbParams {
+            path: "./env/db".to_owned(),
+            ..Default::default()
+        };
+        // The legacy path of the file overrides the path of the environment.
+        let db = env.mix_config(
+            DbParams::default().with_legacy_path("./file/db"),
+            &written(&["path", "url"]),
+        );
+        assert_eq!(db.path, "./file/db");
+        assert_eq!(db.url, "./file/db");
+
+        // The section overrides the legacy path of the same source.
+        let file = DbParams {
+            path: "./file/section".to_owned(),
+            ..Default::default()
+        };
+        let db = env.mix_config(
+            file.with_legacy_path("./file/db"),
+            &written(&["path", "url"]),
+        );
+        assert_eq!(db.path, "./file/section");
+
+        let db = DbParams::default().with_legacy_path("");
+        #[cfg(feature = "leveldb")]
+        assert_eq!(
+            db.settings(4),
+            DbSettings::LevelDB(default_data_path("leveldb"))
+        );
+        #[cfg(all(feature = "sqlite", not(feature = "leveldb")))]
+        assert_eq!(
+            db.settings(4),
+            DbSettings::Sqlite(default_data_path("sqlitedb"))
+        );
+        #[cfg(all(
+            feature = "postgres",
+            not(any(feature = "leveldb", feature = "sqlite"))
+        ))]
+        assert_eq!(
+            db.settings(4),
+            DbSettings::Postgres {
+                url: "postgres://postgres@localhost/kore".to_owned(),
+                pool_size: 4
+            }
+        );
+        #[cfg(all(
+            feature = "sled",
+            not(any(feature = "leveldb", feature = "sqlite", feature = "postgres"))
+        ))]
+        assert_eq!(db.settings(4), DbSettings::Sled(default_data_path("sled")));
+        #[cfg(all(
+            feature = "redb",
+            not(any(
+                feature = "leveldb",
+                feature = "sqlite",
+                feature = "postgres",
+                feature = "sled"
+            ))
+        ))]
+        assert_eq!(
+            db.settings(4),
+            DbSettings::Redb(default_data_path("redb/database"))
+        );
     }
 
     #[test]
     #[serial]
-    fn test_from_env_routing_default() {
-        let routing = RoutingParams::from_env("KORE_NETWORK_");
-        println!("{:?}", routing.boot_nodes);
-        assert!(routing.boot_nodes.is_empty());
+    fn test_from_env_replication_values() {
+        let replication = ReplicationParams::from_env("KORE_").unwrap();
+        assert!(replication.remote_url.is_empty());
+        assert_eq!(replication.mode, ReplicationMode::Resubmit);
+        assert_eq!(replication.poll_interval, Duration::from_secs(5));
+
+        std::env::set_var("KORE_REPLICATION_REMOTE_URL", "https://core.example.com");
+        std::env::set_var("KORE_REPLICATION_TOKEN", "secret");
+        std::env::set_var(
+            "KORE_REPLICATION_SUBJECTS",
+            "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE,Jp7_QFbTOlFCv-NhDrFMsFLDd2LVSNwoQjBYpEw0Fp3E",
+        );
+        std::env::set_var("KORE_REPLICATION_MODE", "verify");
+        std::env::set_var("KORE_REPLICATION_POLL_INTERVAL", "1m");
+
+        let replication = ReplicationParams::from_env("KORE_").unwrap();
+
+        assert_eq!(replication.remote_url, "https://core.example.com");
+        assert_eq!(replication.token, "secret");
+        assert_eq!(replication.subjects.len(), 2);
+        assert_eq!(replication.mode, ReplicationMode::Verify);
+        assert_eq!(replication.poll_interval, Duration::from_secs(60));
+        assert_eq!(replication.request_timeout, Duration::from_secs(120));
+        assert_eq!(replication.max_attempts, 5);
+
+        std::env::remove_var("KORE_REPLICATION_REMOTE_URL");
+        std::env::remove_var("KORE_REPLICATION_TOKEN");
+        std::env::remove_var("KORE_REPLICATION_SUBJECTS");
+        std::env::remove_var("KORE_REPLICATION_MODE");
+        std::env::remove_var("KORE_REPLICATION_POLL_INTERVAL");
+    }
 
-        assert_eq!(routing.dht_random_walk, true);
-        assert_eq!(routing.discovery_only_if_under_num, std::u64::MAX);
-        assert_eq!(routing.allow_non_globals_in_dht, false);
-        assert_eq!(routing.allow_private_ip, false);
-        assert_eq!(routing.enable_mdns, true);
-        assert_eq!(routing.kademlia_disjoint_query_paths, true);
-        assert_eq!(routing.kademlia_replication_factor, 0);
-        assert_eq!(routing.protocol_names[0], "/kore/routing/1.0.0".to_owned());
+    #[test]
+    #[serial]
+    fn test_from_env_services_values() {
+        let services = ServicesParams::from_env("KORE_").unwrap();
+        assert!(services.rest_url.is_empty());
+        assert!(services.peers.is_empty());
+        assert_eq!(services.interval, Duration::from_secs(60));
+
+        std::env::set_var("KORE_SERVICES_REST_URL", "https://node1.example.com");
+        std::env::set_var(
+            "KORE_SERVICES_PEERS",
+            "EPeer2=https://node2.example.com, EPeer3=http://10.0.0.3:3000",
+        );
+        std::env::set_var("KORE_SERVICES_INTERVAL", "5m");
+
+        let services = ServicesParams::from_env("KORE_").unwrap();
+
+        assert_eq!(services.rest_url, "https://node1.example.com");
+        assert!(services.metrics_url.is_empty());
+        assert_eq!(
+            services.peers,
+            BTreeMap::from([
+                ("EPeer2".to_owned(), "https://node2.example.com".to_owned()),
+                ("EPeer3".to_owned(), "http://10.0.0.3:3000".to_owned())
+            ])
+        );
+        assert_eq!(services.interval, Duration::from_secs(300));
+
+        std::env::remove_var("KORE_SERVICES_REST_URL");
+        std::env::remove_var("KORE_SERVICES_PEERS");
+        std::env::remove_var("KORE_SERVICES_INTERVAL");
     }
 
     #[test]
     #[serial]
-    fn test_from_env_node_default() {
-        let node: NodeParams = NodeParams::from_env("KORE_");
+    fn test_from_env_bootstrap_values() {
+        let bootstrap = BootstrapParams::from_env("KORE_NETWORK_").unwrap();
+        assert!(bootstrap.groups.is_empty());
+        assert_eq!(bootstrap.probe_timeout, Duration::from_secs(3));
+        assert_eq!(bootstrap.health_interval, Duration::from_secs(30));
+        assert_eq!(bootstrap.dns_refresh, Duration::from_secs(300));
 
-        assert_eq!(node.key_derivator, KeyDerivatorParams::Ed25519);
-        assert_eq!(node.digest_derivator, DigestDerivatorParams::Blake3_256);
-        assert_eq!(node.replication_factor, 0.25f64);
-        assert_eq!(node.timeout, 3000u32);
-        assert_eq!(node.passvotation, 0);
-        assert_eq!(node.smartcontracts_directory, "./contracts");
+        std::env::set_var(
+            "KORE_NETWORK_BOOTSTRAP_GROUPS",
+            "eu-west=/ip4/172.17.0.1/tcp/50000/p2p/12D3KooWLXexpg81PjdjnrhmHUxN7U5EtfXJgr9cahei1SJ9Ub3B /dns4/eu.example.com/tcp/50000/p2p/12D3KooWLXexpg81PjdjnrhmHUxN7U5EtfXJgr9cahei1SJ9Ub3B, us-east=/ip4/11.11.0.11/tcp/10000_/ip4/12.22.33.44/tcp/55511/p2p/12D3KooWRS3QVwqBtNp7rUCG4SF3nBrinQqJYC1N5qc1Wdr4jrze",
+        );
+        std::env::set_var("KORE_NETWORK_BOOTSTRAP_PROBE_TIMEOUT", "500ms");
+        std::env::set_var("KORE_NETWORK_BOOTSTRAP_HEALTH_INTERVAL", "1m");
+        std::env::set_var("KORE_NETWORK_BOOTSTRAP_DNS_REFRESH", "0s");
+
+        let bootstrap = BootstrapParams::from_env("KORE_NETWORK_").unwrap();
+
+        let labels = bootstrap
+            .groups
+            .iter()
+            .map(|group| group.label.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["eu-west", "us-east"]);
+        assert_eq!(bootstrap.groups[0].boot_nodes.len(), 2);
+        assert_eq!(
+            bootstrap.groups[1].boot_nodes[0].address,
+            vec![
+                "/ip4/11.11.0.11/tcp/10000".to_owned(),
+                "/ip4/12.22.33.44/tcp/55511".to_owned()
+            ]
+        );
+        assert_eq!(bootstrap.probe_timeout, Duration::from_millis(500));
+        assert_eq!(bootstrap.health_interval, Duration::from_secs(60));
+        assert_eq!(bootstrap.dns_refresh, Duration::ZERO);
+
+        std::env::set_var(
+            "KORE_NETWORK_BOOTSTRAP_GROUPS",
+            "eu-west=/ip4/172.17.0.1/tcp/50000/p2p/nobody",
+        );
+        let errors = BootstrapParams::from_env("KORE_NETWORK_").unwrap_err();
+        assert!(errors[0]
+            .to_string()
+            .contains("group eu-west, boot node 0: 'nobody' is not a peer id"));
+
+        std::env::remove_var("KORE_NETWORK_BOOTSTRAP_GROUPS");
+        std::env::remove_var("KORE_NETWORK_BOOTSTRAP_PROBE_TIMEOUT");
+        std::env::remove_var("KORE_NETWORK_BOOTSTRAP_HEALTH_INTERVAL");
+        std::env::remove_var("KORE_NETWORK_BOOTSTRAP_DNS_REFRESH");
     }
 
     #[test]
     #[serial]
-    fn test_from_env_network_default() {
-        let network = NetworkParams::from_env("KORE_");
+    fn test_from_env_db_batch_values() {
+        let db_batch = DbBatchParams::from_env("KORE_").unwrap();
+        assert_eq!(db_batch.max_writes, 1000);
+        assert!(db_batch.sync);
 
-        assert_eq!(network.port_reuse, false);
-        assert_eq!(network.user_agent, "kore-node");
-        assert_eq!(network.node_type, NodeType::Bootstrap);
-        assert!(network.listen_addresses.is_empty());
-        assert!(network.external_addresses.is_empty());
+        std::env::set_var("KORE_DB_BATCH_MAX_WRITES", "250");
+        std::env::set_var("KORE_DB_BATCH_SYNC", "false");
+
+        let db_batch = DbBatchParams::from_env("KORE_").unwrap();
+        assert_eq!(db_batch.max_writes, 250);
+        assert!(!db_batch.sync);
+
+        let settings = KoreSettings::from(Params::from_env().unwrap());
+        assert_eq!(
+            settings.db_batch,
+            DbBatchSettings {
+                max_writes: 250,
+                sync: false,
+            }
+        );
+
+        std::env::remove_var("KORE_DB_BATCH_MAX_WRITES");
+        std::env::remove_var("KORE_DB_BATCH_SYNC");
     }
 
     #[test]
     #[serial]
-    fn test_from_env_kore_params_default() {
-        let kore = KoreParams::from_env("KORE");
+    fn test_from_env_db_ttl_values() {
+        let db_ttl = DbTtlParams::from_env("KORE_").unwrap();
+        assert_eq!(db_ttl.sweep_interval, Duration::from_secs(300));
+        assert!(db_ttl.collections.is_empty());
 
-        #[cfg(feature = "leveldb")]
+        std::env::set_var("KORE_DB_TTL_SWEEP_INTERVAL", "30s");
+        std::env::set_var("KORE_DB_TTL_COLLECTIONS", "idempotency=1d, cache=90");
+
+        let db_ttl = DbTtlParams::from_env("KORE_").unwrap();
+        assert_eq!(db_ttl.sweep_interval, Duration::from_secs(30));
         assert_eq!(
-            kore.db_path,
-            DbSettings::LevelDB("examples/leveldb".to_owned())
+            db_ttl.collections,
+            BTreeMap::from([
+                ("cache".to_owned(), Duration::from_secs(90)),
+                ("idempotency".to_owned(), Duration::from_secs(24 * 60 * 60)),
+            ])
         );
-        #[cfg(feature = "sqlite")]
+
+        let settings = KoreSettings::from(Params::from_env().unwrap());
+        assert_eq!(settings.db_ttl.sweep_interval, Duration::from_secs(30));
         assert_eq!(
-            kore.db_path,
-            DbSettings::Sqlite("examples/sqlitedb".to_owned())
+            settings.db_ttl.ttl("cache", Duration::from_secs(1)),
+            Duration::from_secs(90)
         );
-        assert_eq!(kore.keys_path, "examples/keys".to_owned());
-        assert_eq!(kore.prometheus, "0.0.0.0:3050".to_owned());
+        assert_eq!(
+            settings.db_ttl.ttl("quarantine", Duration::from_secs(1)),
+            Duration::from_secs(1)
+        );
+
+        std::env::set_var("KORE_DB_TTL_COLLECTIONS", "cache");
+        let errors = DbTtlParams::from_env("KORE_").unwrap_err();
+        assert_eq!(errors[0].location, "KORE_DB_TTL_*");
+
+        std::env::remove_var("KORE_DB_TTL_SWEEP_INTERVAL");
+        std::env::remove_var("KORE_DB_TTL_COLLECTIONS");
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_keys_values() {
+        let keys = KeysParams::from_env("KORE_").unwrap();
+        assert_eq!(keys.kdf, KeyKdf::Pbkdf2);
+        assert_eq!(keys.iterations, 600_000);
+        assert_eq!(keys.scrypt_log_n, 15);
+
+        std::env::set_var("KORE_KEYS_PATH", "./fake/keys/path");
+        std::env::set_var("KORE_KEYS_KDF", "scrypt");
+        std::env::set_var("KORE_KEYS_SCRYPT_LOG_N", "17");
+        std::env::set_var("KORE_KEYS_SCRYPT_P", "2");
+        std::env::set_var("KORE_KEYS_VAULT_ADDRESS", "https://vault.example:8200");
+        std::env::set_var("KORE_KEYS_VAULT_ROLE_ID", "kore-role");
+        std::env::set_var("KORE_KEYS_VAULT_ENGINE", "transit");
+
+        let keys = KeysParams::from_env("KORE_").unwrap();
+
+        assert_eq!(keys.kdf, KeyKdf::Scrypt);
+        assert_eq!(keys.scrypt_log_n, 17);
+        assert_eq!(keys.scrypt_r, 8);
+        assert_eq!(keys.scrypt_p, 2);
+        assert_eq!(keys.vault.address, "https://vault.example:8200");
+        assert_eq!(keys.vault.role_id, "kore-role");
+        assert_eq!(keys.vault.engine, VaultEngine::Transit);
+        assert_eq!(keys.vault.mount, "secret");
+
+        std::env::remove_var("KORE_KEYS_PATH");
+        std::env::remove_var("KORE_KEYS_KDF");
+        std::env::remove_var("KORE_KEYS_SCRYPT_LOG_N");
+        std::env::remove_var("KORE_KEYS_SCRYPT_P");
+        std::env::remove_var("KORE_KEYS_VAULT_ADDRESS");
+        std::env::remove_var("KORE_KEYS_VAULT_ROLE_ID");
+        std::env::remove_var("KORE_KEYS_VAULT_ENGINE");
     }
 
     #[test]
@@ -955,7 +4356,7 @@ mod tests {
         std::env::set_var("KORE_NETWORK_TELL_MESSAGE_TIMEOUT_SECS", "58");
         std::env::set_var("KORE_NETWORK_TELL_MAX_CONCURRENT_STREAMS", "166");
 
-        let tell = TellParams::from_env("KORE_NETWORK_");
+        let tell = TellParams::from_env("KORE_NETWORK_").unwrap();
 
         assert_eq!(tell.message_timeout_secs, Duration::from_secs(58));
         assert_eq!(tell.max_concurrent_streams, 166);
@@ -978,9 +4379,9 @@ mod tests {
             "KORE_NETWORK_CONTROL_LIST_SERVICE_BLOCK_LIST",
             "http://90.0.0.1:3000/block_list,http://90.0.0.2:4000/block_list",
         );
-        std::env::set_var("KORE_NETWORK_CONTROL_LIST_INTERVAL_REQUEST", "58");
+        std::env::set_var("KORE_NETWORK_CONTROL_LIST_INTERVAL_REQUEST", "2m");
 
-        let control_list = ControlListParams::from_env("KORE_NETWORK_");
+        let control_list = ControlListParams::from_env("KORE_NETWORK_").unwrap();
 
         assert_eq!(control_list.allow_list, vec!["Peer200", "Peer300"]);
         assert_eq!(control_list.block_list, vec!["Peer1", "Peer2"]);
@@ -999,7 +4400,7 @@ mod tests {
             ]
         );
         assert!(control_list.enable);
-        assert_eq!(control_list.interval_request, Duration::from_secs(58));
+        assert_eq!(control_list.interval_request, Duration::from_secs(120));
 
         std::env::remove_var("KORE_NETWORK_CONTROL_LIST_ENABLE");
         std::env::remove_var("KORE_NETWORK_CONTROL_LIST_ALLOW_LIST");
@@ -1031,7 +4432,7 @@ mod tests {
         );
         std::env::set_var("KORE_NETWORK_ROUTINGPORT_REUSE", "true");
 
-        let routing = RoutingParams::from_env("KORE_NETWORK_");
+        let routing = RoutingParams::from_env("KORE_NETWORK_").unwrap();
         let boot_nodes = vec![
             RoutingNode {
                 address: vec![
@@ -1091,7 +4492,7 @@ mod tests {
         std::env::set_var("KORE_NODE_PASSVOTATION", "50");
         std::env::set_var("KORE_NODE_SMARTCONTRACTS_DIRECTORY", "./fake_route");
 
-        let node = NodeParams::from_env("KORE_");
+        let node = NodeParams::from_env("KORE_").unwrap();
 
         assert_eq!(node.key_derivator, KeyDerivatorParams::Secp256k1);
         assert_eq!(node.digest_derivator, DigestDerivatorParams::Blake3_512);
@@ -1122,13 +4523,14 @@ mod tests {
             "KORE_NETWORK_EXTERNAL_ADDRESSES",
             "/ip4/90.0.0.1/tcp/50000,/ip4/90.0.0.2/tcp/50000",
         );
-        let network = NetworkParams::from_env("KORE_");
+        std::env::set_var("KORE_NETWORK_LISTEN_FALLBACK_PORTS", "50010,50011");
+        let network = NetworkParams::from_env("KORE_").unwrap();
 
         assert_eq!(network.port_reuse, true);
         assert_eq!(network.user_agent, "Kore2.0");
         assert_eq!(network.node_type, NodeType::Addressable);
         assert_eq!(
-            network.listen_addresses,
+            to_strings(network.listen_addresses.clone()),
             vec![
                 "/ip4/127.0.0.1/tcp/50000".to_owned(),
                 "/ip4/127.0.0.1/tcp/50001".to_owned(),
@@ -1137,45 +4539,261 @@ mod tests {
         );
 
         assert_eq!(
-            network.external_addresses,
+            to_strings(network.external_addresses.clone()),
             vec![
                 "/ip4/90.0.0.1/tcp/50000".to_owned(),
                 "/ip4/90.0.0.2/tcp/50000".to_owned(),
             ]
         );
+        assert_eq!(network.listen_fallback_ports, vec![50010, 50011]);
 
         std::env::remove_var("KORE_NETWORK_PORT_REUSE");
+        std::env::remove_var("KORE_NETWORK_LISTEN_FALLBACK_PORTS");
         std::env::remove_var("KORE_NETWORK_USER_AGENT");
         std::env::remove_var("KORE_NETWORK_NODE_TYPE");
         std::env::remove_var("KORE_NETWORK_LISTEN_ADDRESSES");
         std::env::remove_var("KORE_NETWORK_EXTERNAL_ADDRESSES");
     }
 
+    #[test]
+    #[serial]
+    fn test_from_env_invalid_multiaddr() {
+        std::env::set_var(
+            "KORE_NETWORK_LISTEN_ADDRESSES",
+            "/ip4/127.0.0.1/tcp/50000,127.0.0.1:50001",
+        );
+        let errors = NetworkParams::from_env("KORE_").unwrap_err();
+        assert!(errors[0].to_string().contains("entry 1"));
+        assert!(errors[0].to_string().contains("'127.0.0.1:50001'"));
+        std::env::remove_var("KORE_NETWORK_LISTEN_ADDRESSES");
+
+        std::env::set_var(
+            "KORE_NETWORK_ROUTING_BOOT_NODES",
+            "/ip4/172.17.0.1/tcp/50000/p2p/12D3KooWLXexpg81PjdjnrhmHUxN7U5EtfXJgr9cahei1SJ9Ub3B,/ip4/11.11.0.11/tcp/10000_/ip4/12.22.33/tcp/1/p2p/12D3KooWRS3QVwqBtNp7rUCG4SF3nBrinQqJYC1N5qc1Wdr4jrze",
+        );
+        let errors = RoutingParams::from_env("KORE_NETWORK_").unwrap_err();
+        assert!(errors[0].to_string().contains("boot node 1"));
+        assert!(errors[0].to_string().contains("'/ip4/12.22.33/tcp/1'"));
+
+        std::env::set_var(
+            "KORE_NETWORK_ROUTING_BOOT_NODES",
+            "/ip4/172.17.0.1/tcp/50000/p2p/nobody",
+        );
+        let errors = RoutingParams::from_env("KORE_NETWORK_").unwrap_err();
+        assert!(errors[0]
+            .to_string()
+            .contains("boot node 0: 'nobody' is not a peer id"));
+
+        std::env::set_var(
+            "KORE_NETWORK_ROUTING_BOOT_NODES",
+            ",/ip4/172.17.0.1/tcp/50000",
+        );
+        let errors = RoutingParams::from_env("KORE_NETWORK_").unwrap_err();
+        assert!(errors[0].to_string().contains("boot node 1"));
+        std::env::remove_var("KORE_NETWORK_ROUTING_BOOT_NODES");
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_env_indexed_boot_nodes() {
+        let first = "12D3KooWLXexpg81PjdjnrhmHUxN7U5EtfXJgr9cahei1SJ9Ub3B";
+        let second = "12D3KooWRS3QVwqBtNp7rUCG4SF3nBrinQqJYC1N5qc1Wdr4jrze";
+        std::env::set_var(
+            "KORE_NETWORK_ROUTING_BOOT_NODES",
+            format!("/ip4/10.0.0.1/tcp/50000/p2p/{first}"),
+        );
+        std::env::set_var("KORE_NETWORK_ROUTING_BOOT_NODES_10_PEER_ID", first);
+        std::env::set_var(
+            "KORE_NETWORK_ROUTING_BOOT_NODES_10_ADDRESSES",
+            "/ip4/10.0.0.3/tcp/50000",
+        );
+        std::env::set_var("KORE_NETWORK_ROUTING_BOOT_NODES_2_PEER_ID", second);
+        std::env::set_var(
+            "KORE_NETWORK_ROUTING_BOOT_NODES_2_ADDRESSES",
+            "/ip4/10.0.0.2/tcp/50000, /dns4/boot.example.com/tcp/50000",
+        );
+
+        let routing = RoutingParams::from_env("KORE_NETWORK_").unwrap();
+        assert_eq!(
+            routing.boot_nodes,
+            vec![
+                RoutingNode {
+                    peer_id: first.to_owned(),
+                    address: vec!["/ip4/10.0.0.1/tcp/50000".to_owned()],
+                },
+                RoutingNode {
+                    peer_id: second.to_owned(),
+                    address: vec![
+                        "/ip4/10.0.0.2/tcp/50000".to_owned(),
+                        "/dns4/boot.example.com/tcp/50000".to_owned()
+                    ],
+                },
+                RoutingNode {
+                    peer_id: first.to_owned(),
+                    address: vec!["/ip4/10.0.0.3/tcp/50000".to_owned()],
+                },
+            ]
+        );
+
+        std::env::remove_var("KORE_NETWORK_ROUTING_BOOT_NODES_2_PEER_ID");
+        let errors = RoutingParams::from_env("KORE_NETWORK_").unwrap_err();
+        assert_eq!(errors[0].location, "KORE_NETWORK_ROUTING_BOOT_NODES_2_*");
+        assert!(errors[0].message.contains("the peer id is missing"));
+
+        std::env::remove_var("KORE_NETWORK_ROUTING_BOOT_NODES");
+        std::env::remove_var("KORE_NETWORK_ROUTING_BOOT_NODES_10_PEER_ID");
+        std::env::remove_var("KORE_NETWORK_ROUTING_BOOT_NODES_10_ADDRESSES");
+        std::env::remove_var("KORE_NETWORK_ROUTING_BOOT_NODES_2_ADDRESSES");
+    }
+
+    #[test]
+    fn test_boot_node_tables() {
+        let first = "12D3KooWLXexpg81PjdjnrhmHUxN7U5EtfXJgr9cahei1SJ9Ub3B";
+        let routing: RoutingParams = serde_json::from_value(serde_json::json!({
+            "boot_nodes": [
+                { "peer_id": first, "addresses": ["/ip4/10.0.0.1/tcp/50000"] },
+                format!("/ip4/10.0.0.2/tcp/50000/p2p/{first}"),
+            ]
+        }))
+        .unwrap();
+        let addresses = routing
+            .boot_nodes
+            .iter()
+            .map(|node| node.address.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            addresses,
+            vec![
+                vec!["/ip4/10.0.0.1/tcp/50000".to_owned()],
+                vec!["/ip4/10.0.0.2/tcp/50000".to_owned()]
+            ]
+        );
+
+        for (node, message) in [
+            (
+                serde_json::json!({ "peer_id": first, "addresses": [] }),
+                "boot node 0: peer 12D3KooW",
+            ),
+            (
+                serde_json::json!({ "peer_id": first, "addresses": ["10.0.0.1:50000"] }),
+                "boot node 0: '10.0.0.1:50000' is not a multiaddress",
+            ),
+        ] {
+            let error = serde_json::from_value::<RoutingParams>(serde_json::json!({
+                "boot_nodes": [node]
+            }))
+            .unwrap_err();
+            assert!(error.to_string().contains(message));
+        }
+    }
+
+    #[test]
+    fn test_boot_node() {
+        let first = "12D3KooWLXexpg81PjdjnrhmHUxN7U5EtfXJgr9cahei1SJ9Ub3B";
+        let second = "12D3KooWRS3QVwqBtNp7rUCG4SF3nBrinQqJYC1N5qc1Wdr4jrze";
+        let expected = RoutingNode {
+            peer_id: first.to_owned(),
+            address: vec![
+                "/ip4/172.17.0.1/tcp/50000".to_owned(),
+                "/dns4/boot.example.com/tcp/50000".to_owned(),
+            ],
+        };
+        for element in [
+            format!("/ip4/172.17.0.1/tcp/50000_/dns4/boot.example.com/tcp/50000/p2p/{first}"),
+            format!(
+                "/ip4/172.17.0.1/tcp/50000/p2p/{first}_/dns4/boot.example.com/tcp/50000/p2p/{first}"
+            ),
+        ] {
+            assert_eq!(boot_node(&element).unwrap(), expected);
+        }
+
+        for (element, message) in [
+            (
+                "/ip4/172.17.0.1/tcp/50000".to_owned(),
+                "is not <addresses>/p2p/<peer id>",
+            ),
+            (
+                format!("/ip4/172.17.0.1/tcp/50000/p2p/{first}_/ip4/10.0.0.1/tcp/1/p2p/{second}"),
+                "has addresses of different peers",
+            ),
+            (
+                format!("/ip4/172.17.0.1/tcp/50000_10.0.0.1:1/p2p/{first}"),
+                "'10.0.0.1:1' is not a multiaddress",
+            ),
+            (
+                "/ip4/172.17.0.1/tcp/50000/p2p/nobody".to_owned(),
+                "'nobody' is not a peer id",
+            ),
+        ] {
+            assert!(boot_node(&element).unwrap_err().contains(message));
+        }
+    }
+
     #[test]
     #[serial]
     fn test_from_env_kore_params_value() {
         std::env::set_var("KORE_DB_PATH", "./fake/db/path");
+        std::env::set_var("KORE_DB_READ_POOL_SIZE", "8");
         std::env::set_var("KORE_KEYS_PATH", "./fake/keys/path");
+        std::env::set_var("KORE_REGENERATE_CORRUPTED_KEYS", "true");
+        std::env::set_var("KORE_MIGRATE_LEGACY_DATA", "true");
+        std::env::set_var("KORE_LIFECYCLE_EVENTS", "true");
+        std::env::set_var("KORE_KEYS_BACKEND", "pkcs11");
+        std::env::set_var("KORE_TIMESTAMP_FORMAT", "rfc3339");
+        std::env::set_var("KORE_PKCS11_MODULE", "/usr/lib/softhsm/libsofthsm2.so");
+        std::env::set_var("KORE_PKCS11_TOKEN", "kore");
         std::env::set_var("KORE_PROMETHEUS", "10.0.0.0:3030");
+        std::env::set_var("KORE_HTTP_API", "10.0.0.0:3000");
+        std::env::set_var("KORE_FEATURES", "auto_approval=true, other=false");
 
-        let kore = KoreParams::from_env("KORE");
+        let kore = KoreParams::from_env("KORE").unwrap();
 
         #[cfg(feature = "leveldb")]
         assert_eq!(
-            kore.db_path,
+            kore.db.settings(kore.db_read_pool_size),
             DbSettings::LevelDB("./fake/db/path".to_owned())
         );
         #[cfg(feature = "sqlite")]
         assert_eq!(
-            kore.db_path,
+            kore.db.settings(kore.db_read_pool_size),
             DbSettings::Sqlite("./fake/db/path".to_owned())
         );
+        assert_eq!(kore.db_read_pool_size, 8);
         assert_eq!(kore.keys_path, "./fake/keys/path".to_owned());
+        assert!(kore.regenerate_corrupted_keys);
+        assert!(kore.migrate_legacy_data);
+        assert!(kore.lifecycle_events);
+        assert_eq!(kore.keys_backend, KeysBackend::Pkcs11);
+        assert_eq!(kore.timestamp_format, TimestampFormat::Rfc3339);
+        assert_eq!(
+            kore.pkcs11.module,
+            "/usr/lib/softhsm/libsofthsm2.so".to_owned()
+        );
+        assert_eq!(kore.pkcs11.token, "kore".to_owned());
+        assert_eq!(kore.pkcs11.label, "kore-node".to_owned());
         assert_eq!(kore.prometheus, "10.0.0.0:3030".to_owned());
+        assert_eq!(kore.http_api, "10.0.0.0:3000".to_owned());
+        assert_eq!(
+            kore.features,
+            BTreeMap::from([
+                ("auto_approval".to_owned(), true),
+                ("other".to_owned(), false)
+            ])
+        );
 
+        std::env::remove_var("KORE_FEATURES");
         std::env::remove_var("KORE_DB_PATH");
+        std::env::remove_var("KORE_DB_READ_POOL_SIZE");
         std::env::remove_var("KORE_KEYS_PATH");
+        std::env::remove_var("KORE_REGENERATE_CORRUPTED_KEYS");
+        std::env::remove_var("KORE_MIGRATE_LEGACY_DATA");
+        std::env::remove_var("KORE_LIFECYCLE_EVENTS");
+        std::env::remove_var("KORE_KEYS_BACKEND");
+        std::env::remove_var("KORE_TIMESTAMP_FORMAT");
+        std::env::remove_var("KORE_PKCS11_MODULE");
+        std::env::remove_var("KORE_PKCS11_TOKEN");
         std::env::remove_var("KORE_PROMETHEUS");
+        std::env::remove_var("KORE_HTTP_API");
     }
 
     #[test]
@@ -1234,7 +4852,7 @@ mod tests {
         );
         std::env::set_var("KORE_NETWORK_CONTROL_LIST_INTERVAL_REQUEST", "58");
 
-        let params = Params::from_env();
+        let params = Params::from_env().unwrap();
         let boot_nodes = vec![
             RoutingNode {
                 address: vec![
@@ -1256,7 +4874,7 @@ mod tests {
         assert_eq!(params.kore.network.user_agent, "Kore2.0");
         assert_eq!(params.kore.network.node_type, NodeType::Addressable);
         assert_eq!(
-            params.kore.network.listen_addresses,
+            to_strings(params.kore.network.listen_addresses.clone()),
             vec![
                 "/ip4/127.0.0.1/tcp/50000".to_owned(),
                 "/ip4/127.0.0.1/tcp/50001".to_owned(),
@@ -1265,7 +4883,7 @@ mod tests {
         );
 
         assert_eq!(
-            params.kore.network.external_addresses,
+            to_strings(params.kore.network.external_addresses.clone()),
             vec![
                 "/ip4/90.0.0.1/tcp/50000".to_owned(),
                 "/ip4/90.0.0.2/tcp/50000".to_owned(),
@@ -1325,12 +4943,12 @@ mod tests {
 
         #[cfg(feature = "leveldb")]
         assert_eq!(
-            params.kore.db_path,
+            params.kore.db.settings(params.kore.db_read_pool_size),
             DbSettings::LevelDB("./fake/db/path".to_owned())
         );
         #[cfg(feature = "sqlite")]
         assert_eq!(
-            params.kore.db_path,
+            params.kore.db.settings(params.kore.db_read_pool_size),
             DbSettings::Sqlite("./fake/db/path".to_owned())
         );
         assert_eq!(params.kore.keys_path, "./fake/keys/path".to_owned());
@@ -1397,4 +5015,512 @@ mod tests {
         std::env::remove_var("KORE_NETWORK_CONTROL_LIST_SERVICE_BLOCK_LIST");
         std::env::remove_var("KORE_NETWORK_CONTROL_LIST_INTERVAL_REQUEST");
     }
+
+    #[test]
+    #[serial]
+    fn test_env_schedules_and_policies_errors() {
+        std::env::set_var("KORE_SCHEDULES_0_NAME", "heartbeat");
+        std::env::set_var("KORE_SCHEDULES_0_INTERVAL", "5m");
+        std::env::set_var("KORE_SCHEDULES_0_ACTION", "{\"type\": ");
+        std::env::set_var("KORE_SIGNING_POLICIES_3_REQUEST_TYPES", "Fact");
+        std::env::set_var("KORE_SIGNING_POLICIES_3_EXTERNAL_SIGNER", "maybe");
+
+        let errors = KoreParams::from_env("KORE").unwrap_err();
+        let locations: Vec<_> = errors.iter().map(|error| error.location.as_str()).collect();
+        assert!(locations.contains(&"KORE_SCHEDULES_0_*"));
+        assert!(locations.contains(&"KORE_SIGNING_POLICIES_3_*"));
+
+        std::env::remove_var("KORE_SCHEDULES_0_NAME");
+        std::env::remove_var("KORE_SCHEDULES_0_INTERVAL");
+        std::env::remove_var("KORE_SCHEDULES_0_ACTION");
+        std::env::remove_var("KORE_SIGNING_POLICIES_3_REQUEST_TYPES");
+        std::env::remove_var("KORE_SIGNING_POLICIES_3_EXTERNAL_SIGNER");
+    }
+
+    #[cfg(feature = "sqlite")]
+    const DB_TYPE: &str = "sqlite";
+    #[cfg(all(feature = "leveldb", not(feature = "sqlite")))]
+    const DB_TYPE: &str = "leveldb";
+    #[cfg(all(
+        feature = "postgres",
+        not(any(feature = "sqlite", feature = "leveldb"))
+    ))]
+    const DB_TYPE: &str = "postgres";
+    #[cfg(all(
+        feature = "sled",
+        not(any(feature = "sqlite", feature = "leveldb", feature = "postgres"))
+    ))]
+    const DB_TYPE: &str = "sled";
+    #[cfg(all(
+        feature = "redb",
+        not(any(
+            feature = "sqlite",
+            feature = "leveldb",
+            feature = "postgres",
+            feature = "sled"
+        ))
+    ))]
+    const DB_TYPE: &str = "redb";
+
+    /// Every key of the settings with a value other than its default. `KORE_DB_PATH` sets both
+    /// `db.path` and the legacy `db_path`, so the file sets both.
+    const FILE_MATRIX: &str = r#"
+[kore]
+db_path = "./fake/db"
+db_read_pool_size = 8
+db_encryption = true
+db_encryption_key = "secret"
+keys_path = "./fake/keys"
+regenerate_corrupted_keys = true
+migrate_legacy_data = true
+lifecycle_events = true
+keys_backend = "vault"
+timestamp_format = "rfc3339"
+prometheus = "10.0.0.1:3030"
+http_api = "127.0.0.1:3000"
+features = { beta = true }
+
+[[kore.schedules]]
+name = "heartbeat"
+interval = "5m"
+action = { type = "report" }
+
+[[kore.schedules]]
+name = "check"
+interval = "1h"
+action = { type = "verify", subject_id = "Jsub" }
+
+[[kore.signing_policies]]
+request_types = ["Create", "Fact"]
+signers = ["Ekey"]
+external_signer = true
+
+[[kore.approval_rules]]
+governance_id = "Jgov"
+schema_id = "governance"
+
+[kore.network]
+user_agent = "Kore9.0"
+node_type = "Addressable"
+listen_addresses = ["/ip4/127.0.0.1/tcp/50001", "/ip4/127.0.0.1/tcp/50002"]
+external_addresses = ["/ip4/90.1.0.60/tcp/50000"]
+port_reuse = true
+listen_fallback_ports = [50010, 50011]
+
+[kore.network.tell]
+message_timeout_secs = "58s"
+max_concurrent_streams = 166
+
+[kore.network.routing]
+dht_random_walk = false
+discovery_only_if_under_num = 55
+allow_non_globals_in_dht = true
+allow_private_ip = true
+enable_mdns = false
+kademlia_disjoint_query_paths = false
+kademlia_replication_factor = 30
+protocol_names = ["/kore/routing/2.2.2", "/kore/routing/1.1.1"]
+
+[[kore.network.routing.boot_nodes]]
+peer_id = "12D3KooWLXexpg81PjdjnrhmHUxN7U5EtfXJgr9cahei1SJ9Ub3B"
+addresses = ["/ip4/172.17.0.1/tcp/50000"]
+
+[kore.network.bootstrap]
+probe_timeout = "3s"
+health_interval = "2m"
+dns_refresh = "10m"
+
+[[kore.network.bootstrap.groups]]
+label = "eu"
+boot_nodes = [
+    "/ip4/172.17.0.2/tcp/50000/p2p/12D3KooWLXexpg81PjdjnrhmHUxN7U5EtfXJgr9cahei1SJ9Ub3B",
+]
+
+[kore.network.control_list]
+enable = true
+allow_list = ["Peer200", "Peer300"]
+block_list = ["Peer1"]
+service_allow_list = ["http://90.0.0.1:3000/allow_list"]
+service_block_list = ["http://90.0.0.2:4000/block_list"]
+interval_request = "99s"
+
+[kore.node]
+key_derivator = "Secp256k1"
+digest_derivator = "Blake3_512"
+replication_factor = 0.555
+timeout = 30
+passvotation = 50
+smartcontracts_directory = "./fake_route"
+
+[kore.db]
+path = "./fake/db"
+url = "postgres://kore@localhost/kore"
+options = { journal_mode = "wal" }
+
+[kore.db_batch]
+max_writes = 64
+sync = false
+
+[kore.db_ttl]
+sweep_interval = "5m"
+collections = { events = "7d" }
+
+[kore.keys]
+kdf = "scrypt"
+iterations = 200000
+scrypt_log_n = 14
+scrypt_r = 9
+scrypt_p = 2
+
+[kore.keys.vault]
+address = "http://vault:8200"
+token = "vault-token"
+role_id = "role"
+secret_id = "secret"
+engine = "transit"
+mount = "kv2"
+path = "kore/node"
+
+[kore.pkcs11]
+module = "/usr/lib/softhsm.so"
+token = "kore"
+label = "node-key"
+
+[kore.api_auth]
+public_token = "public"
+admin_token = "admin"
+
+[kore.auth]
+api_keys = ["first-key", "second-key"]
+issuer = "https://issuer"
+audience = "kore"
+jwks_url = "https://issuer/jwks"
+
+[kore.grpc]
+listen = "127.0.0.1:50051"
+tls_cert = "cert.pem"
+tls_key = "key.pem"
+
+[kore.webhooks]
+urls = ["http://hooks/a", "http://hooks/b"]
+secret = "hook"
+max_retries = 7
+retry_backoff = "3s"
+
+[kore.services]
+rest_url = "http://rest:3000"
+metrics_url = "http://metrics:3030"
+peers = { eu = "http://eu:3000" }
+interval = "45s"
+
+[kore.warm_up]
+subjects = ["Jx", "Jy"]
+recent = 5
+timeout = "20s"
+
+[kore.supervisor]
+enabled = true
+max_restarts = 3
+window = "1m"
+backoff = "2s"
+max_backoff = "5m"
+
+[kore.backup]
+directory = "backups"
+interval = "6h"
+keep = 3
+
+[kore.archival]
+destination = "archive"
+max_age = "30d"
+max_size = "1GiB"
+interval = "1h"
+
+[kore.soak]
+rate = 10
+duration = "1h"
+governance_id = "Jgov"
+schema_id = "soak"
+subjects = 5
+payload = "data"
+max_in_flight = 16
+report_interval = "30s"
+
+[kore.replication]
+remote_url = "http://primary:3000"
+token = "replica"
+subjects = ["Ja"]
+mode = "verify"
+poll_interval = "30s"
+request_timeout = "5s"
+max_attempts = 3
+
+[kore.quota]
+max_subjects = 100
+window = "2h"
+
+[kore.signature_check]
+enabled = true
+max_age = "10m"
+max_future = "1m"
+
+[kore.api]
+timeout_ms = 5000
+retries = 2
+backoff = "500ms"
+
+[kore.limits]
+read_rate = 50.5
+read_burst = 60
+read_max_in_flight = 70
+write_rate = 5.5
+write_burst = 6
+write_max_in_flight = 7
+
+[kore.metrics.push]
+url = "http://10.0.0.9:9091"
+mode = "remote_write"
+interval = "30s"
+job = "edge"
+labels = { region = "eu" }
+username = "pusher"
+password = "push-secret"
+
+[kore.access_log]
+sample_rate = 0.5
+slow_threshold = "300ms"
+
+[kore.logging]
+level = "debug"
+targets = { kore_base = "warn" }
+format = "json"
+file = "node.log"
+max_file_size = "10MB"
+max_files = 3
+"#;
+
+    /// The same values of `FILE_MATRIX` as environment variables.
+    const ENV_MATRIX: [(&str, &str); 155] = [
+        ("KORE_DB_READ_POOL_SIZE", "8"),
+        ("KORE_DB_ENCRYPTION", "true"),
+        ("KORE_DB_ENCRYPTION_KEY", "secret"),
+        ("KORE_KEYS_PATH", "./fake/keys"),
+        ("KORE_REGENERATE_CORRUPTED_KEYS", "true"),
+        ("KORE_MIGRATE_LEGACY_DATA", "true"),
+        ("KORE_LIFECYCLE_EVENTS", "true"),
+        ("KORE_KEYS_BACKEND", "vault"),
+        ("KORE_TIMESTAMP_FORMAT", "rfc3339"),
+        ("KORE_PROMETHEUS", "10.0.0.1:3030"),
+        ("KORE_HTTP_API", "127.0.0.1:3000"),
+        ("KORE_FEATURES", "beta=true"),
+        ("KORE_SCHEDULES_0_NAME", "heartbeat"),
+        ("KORE_SCHEDULES_0_INTERVAL", "5m"),
+        ("KORE_SCHEDULES_0_ACTION", "report"),
+        ("KORE_SCHEDULES_1_NAME", "check"),
+        ("KORE_SCHEDULES_1_INTERVAL", "1h"),
+        (
+            "KORE_SCHEDULES_1_ACTION",
+            r#"{"type": "verify", "subject_id": "Jsub"}"#,
+        ),
+        ("KORE_SIGNING_POLICIES_0_REQUEST_TYPES", "Create,Fact"),
+        ("KORE_SIGNING_POLICIES_0_SIGNERS", "Ekey"),
+        ("KORE_SIGNING_POLICIES_0_EXTERNAL_SIGNER", "true"),
+        ("KORE_APPROVAL_RULES_0_GOVERNANCE_ID", "Jgov"),
+        ("KORE_APPROVAL_RULES_0_SCHEMA_ID", "governance"),
+        ("KORE_NETWORK_USER_AGENT", "Kore9.0"),
+        ("KORE_NETWORK_NODE_TYPE", "Addressable"),
+        (
+            "KORE_NETWORK_LISTEN_ADDRESSES",
+            "/ip4/127.0.0.1/tcp/50001,/ip4/127.0.0.1/tcp/50002",
+        ),
+        (
+            "KORE_NETWORK_EXTERNAL_ADDRESSES",
+            "/ip4/90.1.0.60/tcp/50000",
+        ),
+        ("KORE_NETWORK_PORT_REUSE", "true"),
+        ("KORE_NETWORK_LISTEN_FALLBACK_PORTS", "50010,50011"),
+        ("KORE_NETWORK_TELL_MESSAGE_TIMEOUT_SECS", "58s"),
+        ("KORE_NETWORK_TELL_MAX_CONCURRENT_STREAMS", "166"),
+        ("KORE_NETWORK_ROUTING_DHT_RANDOM_WALK", "false"),
+        ("KORE_NETWORK_ROUTING_DISCOVERY_ONLY_IF_UNDER_NUM", "55"),
+        ("KORE_NETWORK_ROUTING_ALLOW_NON_GLOBALS_IN_DHT", "true"),
+        ("KORE_NETWORK_ROUTING_ALLOW_PRIVATE_IP", "true"),
+        ("KORE_NETWORK_ROUTING_ENABLE_MDNS", "false"),
+        (
+            "KORE_NETWORK_ROUTING_KADEMLIA_DISJOINT_QUERY_PATHS",
+            "false",
+        ),
+        ("KORE_NETWORK_ROUTING_KADEMLIA_REPLICATION_FACTOR", "30"),
+        (
+            "KORE_NETWORK_ROUTING_PROTOCOL_NAMES",
+            "/kore/routing/2.2.2,/kore/routing/1.1.1",
+        ),
+        (
+            "KORE_NETWORK_ROUTING_BOOT_NODES_0_PEER_ID",
+            "12D3KooWLXexpg81PjdjnrhmHUxN7U5EtfXJgr9cahei1SJ9Ub3B",
+        ),
+        (
+            "KORE_NETWORK_ROUTING_BOOT_NODES_0_ADDRESSES",
+            "/ip4/172.17.0.1/tcp/50000",
+        ),
+        ("KORE_NETWORK_BOOTSTRAP_PROBE_TIMEOUT", "3s"),
+        ("KORE_NETWORK_BOOTSTRAP_HEALTH_INTERVAL", "2m"),
+        ("KORE_NETWORK_BOOTSTRAP_DNS_REFRESH", "10m"),
+        (
+            "KORE_NETWORK_BOOTSTRAP_GROUPS",
+            "eu=/ip4/172.17.0.2/tcp/50000/p2p/12D3KooWLXexpg81PjdjnrhmHUxN7U5EtfXJgr9cahei1SJ9Ub3B",
+        ),
+        ("KORE_NETWORK_CONTROL_LIST_ENABLE", "true"),
+        ("KORE_NETWORK_CONTROL_LIST_ALLOW_LIST", "Peer200,Peer300"),
+        ("KORE_NETWORK_CONTROL_LIST_BLOCK_LIST", "Peer1"),
+        (
+            "KORE_NETWORK_CONTROL_LIST_SERVICE_ALLOW_LIST",
+            "http://90.0.0.1:3000/allow_list",
+        ),
+        (
+            "KORE_NETWORK_CONTROL_LIST_SERVICE_BLOCK_LIST",
+            "http://90.0.0.2:4000/block_list",
+        ),
+        ("KORE_NETWORK_CONTROL_LIST_INTERVAL_REQUEST", "99s"),
+        ("KORE_NODE_KEY_DERIVATOR", "Secp256k1"),
+        ("KORE_NODE_DIGEST_DERIVATOR", "Blake3_512"),
+        ("KORE_NODE_REPLICATION_FACTOR", "0.555"),
+        ("KORE_NODE_TIMEOUT", "30"),
+        ("KORE_NODE_PASSVOTATION", "50"),
+        ("KORE_NODE_SMARTCONTRACTS_DIRECTORY", "./fake_route"),
+        ("KORE_DB_PATH", "./fake/db"),
+        ("KORE_DB_URL", "postgres://kore@localhost/kore"),
+        ("KORE_DB_OPTIONS", "journal_mode=wal"),
+        ("KORE_DB_BATCH_MAX_WRITES", "64"),
+        ("KORE_DB_BATCH_SYNC", "false"),
+        ("KORE_DB_TTL_SWEEP_INTERVAL", "5m"),
+        ("KORE_DB_TTL_COLLECTIONS", "events=7d"),
+        ("KORE_KEYS_KDF", "scrypt"),
+        ("KORE_KEYS_ITERATIONS", "200000"),
+        ("KORE_KEYS_SCRYPT_LOG_N", "14"),
+        ("KORE_KEYS_SCRYPT_R", "9"),
+        ("KORE_KEYS_SCRYPT_P", "2"),
+        ("KORE_KEYS_VAULT_ADDRESS", "http://vault:8200"),
+        ("KORE_KEYS_VAULT_TOKEN", "vault-token"),
+        ("KORE_KEYS_VAULT_ROLE_ID", "role"),
+        ("KORE_KEYS_VAULT_SECRET_ID", "secret"),
+        ("KORE_KEYS_VAULT_ENGINE", "transit"),
+        ("KORE_KEYS_VAULT_MOUNT", "kv2"),
+        ("KORE_KEYS_VAULT_PATH", "kore/node"),
+        ("KORE_PKCS11_MODULE", "/usr/lib/softhsm.so"),
+        ("KORE_PKCS11_TOKEN", "kore"),
+        ("KORE_PKCS11_LABEL", "node-key"),
+        ("KORE_API_AUTH_PUBLIC_TOKEN", "public"),
+        ("KORE_API_AUTH_ADMIN_TOKEN", "admin"),
+        ("KORE_AUTH_API_KEYS", "first-key,second-key"),
+        ("KORE_AUTH_ISSUER", "https://issuer"),
+        ("KORE_AUTH_AUDIENCE", "kore"),
+        ("KORE_AUTH_JWKS_URL", "https://issuer/jwks"),
+        ("KORE_GRPC_LISTEN", "127.0.0.1:50051"),
+        ("KORE_GRPC_TLS_CERT", "cert.pem"),
+        ("KORE_GRPC_TLS_KEY", "key.pem"),
+        ("KORE_WEBHOOKS_URLS", "http://hooks/a,http://hooks/b"),
+        ("KORE_WEBHOOKS_SECRET", "hook"),
+        ("KORE_WEBHOOKS_MAX_RETRIES", "7"),
+        ("KORE_WEBHOOKS_RETRY_BACKOFF", "3s"),
+        ("KORE_SERVICES_REST_URL", "http://rest:3000"),
+        ("KORE_SERVICES_METRICS_URL", "http://metrics:3030"),
+        ("KORE_SERVICES_PEERS", "eu=http://eu:3000"),
+        ("KORE_SERVICES_INTERVAL", "45s"),
+        ("KORE_WARM_UP_SUBJECTS", "Jx,Jy"),
+        ("KORE_WARM_UP_RECENT", "5"),
+        ("KORE_WARM_UP_TIMEOUT", "20s"),
+        ("KORE_SUPERVISOR_ENABLED", "true"),
+        ("KORE_SUPERVISOR_MAX_RESTARTS", "3"),
+        ("KORE_SUPERVISOR_WINDOW", "1m"),
+        ("KORE_SUPERVISOR_BACKOFF", "2s"),
+        ("KORE_SUPERVISOR_MAX_BACKOFF", "5m"),
+        ("KORE_BACKUP_DIRECTORY", "backups"),
+        ("KORE_BACKUP_INTERVAL", "6h"),
+        ("KORE_BACKUP_KEEP", "3"),
+        ("KORE_ARCHIVAL_DESTINATION", "archive"),
+        ("KORE_ARCHIVAL_MAX_AGE", "30d"),
+        ("KORE_ARCHIVAL_MAX_SIZE", "1GiB"),
+        ("KORE_ARCHIVAL_INTERVAL", "1h"),
+        ("KORE_SOAK_RATE", "10"),
+        ("KORE_SOAK_DURATION", "1h"),
+        ("KORE_SOAK_GOVERNANCE_ID", "Jgov"),
+        ("KORE_SOAK_SCHEMA_ID", "soak"),
+        ("KORE_SOAK_SUBJECTS", "5"),
+        ("KORE_SOAK_PAYLOAD", "data"),
+        ("KORE_SOAK_MAX_IN_FLIGHT", "16"),
+        ("KORE_SOAK_REPORT_INTERVAL", "30s"),
+        ("KORE_REPLICATION_REMOTE_URL", "http://primary:3000"),
+        ("KORE_REPLICATION_TOKEN", "replica"),
+        ("KORE_REPLICATION_SUBJECTS", "Ja"),
+        ("KORE_REPLICATION_MODE", "verify"),
+        ("KORE_REPLICATION_POLL_INTERVAL", "30s"),
+        ("KORE_REPLICATION_REQUEST_TIMEOUT", "5s"),
+        ("KORE_REPLICATION_MAX_ATTEMPTS", "3"),
+        ("KORE_QUOTA_MAX_SUBJECTS", "100"),
+        ("KORE_QUOTA_WINDOW", "2h"),
+        ("KORE_SIGNATURE_CHECK_ENABLED", "true"),
+        ("KORE_SIGNATURE_CHECK_MAX_AGE", "10m"),
+        ("KORE_SIGNATURE_CHECK_MAX_FUTURE", "1m"),
+        ("KORE_API_TIMEOUT_MS", "5000"),
+        ("KORE_API_RETRIES", "2"),
+        ("KORE_API_BACKOFF", "500ms"),
+        ("KORE_LIMITS_READ_RATE", "50.5"),
+        ("KORE_LIMITS_READ_BURST", "60"),
+        ("KORE_LIMITS_READ_MAX_IN_FLIGHT", "70"),
+        ("KORE_LIMITS_WRITE_RATE", "5.5"),
+        ("KORE_LIMITS_WRITE_BURST", "6"),
+        ("KORE_LIMITS_WRITE_MAX_IN_FLIGHT", "7"),
+        ("KORE_METRICS_PUSH_URL", "http://10.0.0.9:9091"),
+        ("KORE_METRICS_PUSH_MODE", "remote_write"),
+        ("KORE_METRICS_PUSH_INTERVAL", "30s"),
+        ("KORE_METRICS_PUSH_JOB", "edge"),
+        ("KORE_METRICS_PUSH_LABELS", "region=eu"),
+        ("KORE_METRICS_PUSH_USERNAME", "pusher"),
+        ("KORE_METRICS_PUSH_PASSWORD", "push-secret"),
+        ("KORE_ACCESS_LOG_SAMPLE_RATE", "0.5"),
+        ("KORE_ACCESS_LOG_SLOW_THRESHOLD", "300ms"),
+        ("KORE_LOGGING_LEVEL", "debug"),
+        ("KORE_LOGGING_TARGETS", "kore_base=warn"),
+        ("KORE_LOGGING_FORMAT", "json"),
+        ("KORE_LOGGING_FILE", "node.log"),
+        ("KORE_LOGGING_MAX_FILE_SIZE", "10MB"),
+        ("KORE_LOGGING_MAX_FILES", "3"),
+    ];
+
+    #[test]
+    #[serial]
+    fn test_env_matrix() {
+        // Only the variables of the matrix are read.
+        for (name, _) in std::env::vars().filter(|(name, _)| name.starts_with("KORE_")) {
+            std::env::remove_var(name);
+        }
+        for (name, value) in ENV_MATRIX {
+            std::env::set_var(name, value);
+        }
+        std::env::set_var("KORE_DB_TYPE", DB_TYPE);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let document = FILE_MATRIX.replacen(
+            "[kore.db]\n",
+            &format!("[kore.db]\ntype = \"{}\"\n", DB_TYPE),
+            1,
+        );
+        std::fs::write(&path, document).unwrap();
+        let file = Params::from_file(path.to_str().unwrap()).unwrap();
+        let env = Params::from_env().unwrap();
+
+        for (name, _) in ENV_MATRIX {
+            std::env::remove_var(name);
+        }
+        std::env::remove_var("KORE_DB_TYPE");
+
+        // The file sets every key of the settings.
+        let keys = Explicit::of(&settings_document(&KoreSettings::default()));
+        let missing: Vec<_> = keys.0.difference(&file.explicit.0).collect();
+        assert!(missing.is_empty(), "keys not in the matrix: {:?}", missing);
+        // And the environment sets each of them to the same value.
+        assert_eq!(format!("{:?}", env.kore), format!("{:?}", file.kore));
+    }
 }
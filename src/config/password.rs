@@ -0,0 +1,202 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Password of the node key.
+//!
+//! A password in `KORE_PASSWORD` can be read by anyone who lists the environment of the process,
+//! so the password can be taken from the first of these sources that is set:
+//!
+//! | Source | Variable | Password |
+//! |---|---|---|
+//! | Value | `KORE_PASSWORD` | The value itself |
+//! | File | `KORE_PASSWORD_FILE` | Content of the file without the final line break, e.g. a Docker or Kubernetes secret |
+//! | Command | `KORE_PASSWORD_COMMAND` | Standard output of the command run by the shell, e.g. `pass show kore/node` |
+//! | Prompt | | Typed on the terminal without echo, with the `cli` feature when the standard input is a terminal |
+//!
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+use crate::error::{ConfigError, NodeError};
+
+/// Variable of the password itself.
+const PASSWORD: &str = "KORE_PASSWORD";
+/// Variable of the file with the password.
+const PASSWORD_FILE: &str = "KORE_PASSWORD_FILE";
+/// Variable of the command that prints the password.
+const PASSWORD_COMMAND: &str = "KORE_PASSWORD_COMMAND";
+
+/// Where the password of the node key comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PasswordSource {
+    /// The password itself.
+    Value(String),
+    /// File whose content is the password.
+    File(PathBuf),
+    /// Shell command that prints the password.
+    Command(String),
+    /// Terminal prompt.
+    #[cfg(feature = "cli")]
+    Prompt,
+}
+
+impl PasswordSource {
+    /// Source given by the environment variables, the prompt when none is set and the standard
+    /// input is a terminal.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Config` - No source available
+    ///
+    pub fn from_env() -> Result<Self, NodeError> {
+        if let Ok(password) = env::var(PASSWORD) {
+            return Ok(PasswordSource::Value(password));
+        }
+        if let Some(path) = env::var_os(PASSWORD_FILE) {
+            return Ok(PasswordSource::File(PathBuf::from(path)));
+        }
+        if let Ok(command) = env::var(PASSWORD_COMMAND) {
+            return Ok(PasswordSource::Command(command));
+        }
+        #[cfg(feature = "cli")]
+        {
+            use std::io::IsTerminal;
+            if std::io::stdin().is_terminal() {
+                return Ok(PasswordSource::Prompt);
+            }
+        }
+        Err(NodeError::Config(vec![ConfigError::new(
+            PASSWORD, "not set",
+        )
+        .with_hint(format!(
+            "set {}, {} or {}",
+            PASSWORD, PASSWORD_FILE, PASSWORD_COMMAND
+        ))]))
+    }
+
+    /// Read the password.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Config` - The file cannot be read, the command fails or the password is empty
+    ///
+    pub fn read(&self) -> Result<String, NodeError> {
+        match self {
+            PasswordSource::Value(password) => Ok(password.clone()),
+            PasswordSource::File(path) => {
+                let content = fs::read_to_string(path)
+                    .map_err(|e| error(PASSWORD_FILE, format!("{}: {}", path.display(), e)))?;
+                not_empty(PASSWORD_FILE, trim_line_break(&content))
+            }
+            PasswordSource::Command(command) => {
+                let output = shell(command)
+                    .output()
+                    .map_err(|e| error(PASSWORD_COMMAND, e.to_string()))?;
+                if !output.status.success() {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    return Err(error(
+                        PASSWORD_COMMAND,
+                        format!("{}: {}", output.status, stderr.trim()),
+                    ));
+                }
+                let stdout = String::from_utf8(output.stdout)
+                    .map_err(|_| error(PASSWORD_COMMAND, "output is not UTF-8"))?;
+                not_empty(PASSWORD_COMMAND, trim_line_break(&stdout))
+            }
+            #[cfg(feature = "cli")]
+            PasswordSource::Prompt => {
+                let password = rpassword::prompt_password("Password of the node key: ")
+                    .map_err(|e| error(PASSWORD, e.to_string()))?;
+                not_empty(PASSWORD, &password)
+            }
+        }
+    }
+}
+
+/// Command run by the shell of the platform.
+fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+/// Text without its final line break, which editors and `echo` add.
+fn trim_line_break(text: &str) -> &str {
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    text.strip_suffix('\r').unwrap_or(text)
+}
+
+/// Password read, which cannot be empty.
+fn not_empty(variable: &str, password: &str) -> Result<String, NodeError> {
+    if password.is_empty() {
+        Err(error(variable, "the password is empty"))
+    } else {
+        Ok(password.to_owned())
+    }
+}
+
+/// Configuration error of a source.
+fn error(variable: &str, message: impl Into<String>) -> NodeError {
+    NodeError::Config(vec![ConfigError::new(variable, message)])
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serial_test::serial;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_password_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("password");
+        fs::write(&path, "kore secret\n").unwrap();
+        let source = PasswordSource::File(path.clone());
+        assert_eq!(source.read().unwrap(), "kore secret");
+
+        fs::write(&path, "\r\n").unwrap();
+        assert!(matches!(source.read(), Err(NodeError::Config(_))));
+        let missing = PasswordSource::File(dir.path().join("missing"));
+        assert!(matches!(missing.read(), Err(NodeError::Config(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_password_command() {
+        let source = PasswordSource::Command("echo kore".to_owned());
+        assert_eq!(source.read().unwrap(), "kore");
+        let failing = PasswordSource::Command("echo denied >&2; exit 3".to_owned());
+        let Err(NodeError::Config(errors)) = failing.read() else {
+            panic!("the command should fail");
+        };
+        assert!(errors[0].message.contains("denied"));
+    }
+
+    #[test]
+    #[serial]
+    fn test_password_source_from_env() {
+        env::remove_var(PASSWORD);
+        env::remove_var(PASSWORD_COMMAND);
+        env::set_var(PASSWORD_FILE, "/run/secrets/kore");
+        assert_eq!(
+            PasswordSource::from_env().unwrap(),
+            PasswordSource::File(PathBuf::from("/run/secrets/kore"))
+        );
+        env::set_var(PASSWORD, "kore");
+        assert_eq!(
+            PasswordSource::from_env().unwrap(),
+            PasswordSource::Value("kore".to_owned())
+        );
+        env::remove_var(PASSWORD);
+        env::remove_var(PASSWORD_FILE);
+    }
+}
@@ -0,0 +1,1158 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Configuration rendering.
+//!
+//! The settings written back as a configuration file, with the keys and forms that
+//! `build_config` reads: `KoreSettings::to_file` writes the effective settings, and
+//! `kore-node config init` writes them with the description of every key as a comment, so the
+//! file is also the reference of what can be tuned.
+//!
+//! Durations are written with a unit (`"30s"`, `"5m"`), boot nodes as
+//! `<addresses>/p2p/<peer id>`, and the discovery limit of the routing is capped to the largest
+//! integer of TOML. Secrets, such as tokens and `db_encryption_key`, are written as they are.
+//!
+
+use std::{fs, path::Path};
+
+use serde_json::{json, Map, Value};
+
+use super::units::format_duration;
+use crate::{
+    error::{ConfigError, NodeError},
+    settings::{DbSettings, KoreSettings},
+};
+
+/// Format of a configuration file.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ConfigFormat {
+    /// TOML, with comments in templates.
+    Toml,
+    /// YAML, with comments in templates.
+    Yaml,
+    /// JSON, which has no comments.
+    Json,
+}
+
+impl ConfigFormat {
+    /// Format given by the extension of a file: `toml`, `yaml`, `yml` or `json`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Configuration file
+    ///
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            "json" => Some(ConfigFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Description of each key, written as a comment above it in templates.
+const DESCRIPTIONS: &[(&str, &str)] = &[
+    (
+        "kore",
+        "Settings of the node. Every key is optional, missing keys take their default.",
+    ),
+    ("kore.network", "Peer-to-peer network of the ledger."),
+    ("kore.network.user_agent", "Name announced to the peers."),
+    (
+        "kore.network.node_type",
+        "Bootstrap, Addressable or Ephemeral.",
+    ),
+    (
+        "kore.network.listen_addresses",
+        "Multiaddresses the node listens on.",
+    ),
+    (
+        "kore.network.external_addresses",
+        "Multiaddresses announced to the peers.",
+    ),
+    (
+        "kore.network.port_reuse",
+        "Reuse the listen port for outgoing connections.",
+    ),
+    (
+        "kore.network.listen_fallback_ports",
+        "Ports tried, in order, when a listen address is in use.",
+    ),
+    ("kore.network.tell", "Messages between peers."),
+    (
+        "kore.network.tell.message_timeout_secs",
+        "Time allowed to deliver a message.",
+    ),
+    (
+        "kore.network.tell.max_concurrent_streams",
+        "Streams open at the same time.",
+    ),
+    ("kore.network.routing", "Discovery of the peers."),
+    (
+        "kore.network.routing.boot_nodes",
+        "Boot nodes, as [{ peer_id = \"...\", addresses = [\"...\"] }].",
+    ),
+    (
+        "kore.network.routing.dht_random_walk",
+        "Walk the DHT randomly to find peers.",
+    ),
+    (
+        "kore.network.routing.discovery_only_if_under_num",
+        "Stop the discovery once this many peers are known.",
+    ),
+    (
+        "kore.network.routing.allow_non_globals_in_dht",
+        "Keep non-global addresses in the DHT.",
+    ),
+    (
+        "kore.network.routing.allow_private_ip",
+        "Dial private IP addresses.",
+    ),
+    (
+        "kore.network.routing.enable_mdns",
+        "Find peers of the local network with mDNS.",
+    ),
+    (
+        "kore.network.routing.kademlia_disjoint_query_paths",
+        "Use disjoint paths in Kademlia queries.",
+    ),
+    (
+        "kore.network.routing.kademlia_replication_factor",
+        "Kademlia replication factor, 0 for the default.",
+    ),
+    (
+        "kore.network.routing.protocol_names",
+        "Protocols of the routing.",
+    ),
+    (
+        "kore.network.bootstrap",
+        "Groups of boot nodes tried in failover order.",
+    ),
+    (
+        "kore.network.bootstrap.groups",
+        "Groups, as [{ label = \"eu-west\", boot_nodes = [\"...\"] }]; nearest first.",
+    ),
+    (
+        "kore.network.bootstrap.probe_timeout",
+        "Time allowed to reach a boot node.",
+    ),
+    (
+        "kore.network.bootstrap.health_interval",
+        "Time between health checks of the groups.",
+    ),
+    (
+        "kore.network.bootstrap.dns_refresh",
+        "Time between resolutions of the boot node names; 0 only on start.",
+    ),
+    ("kore.network.control_list", "Peers allowed to connect."),
+    (
+        "kore.network.control_list.enable",
+        "Check the peers against the lists.",
+    ),
+    ("kore.network.control_list.allow_list", "Peers allowed."),
+    ("kore.network.control_list.block_list", "Peers blocked."),
+    (
+        "kore.network.control_list.service_allow_list",
+        "URLs of services returning peers allowed.",
+    ),
+    (
+        "kore.network.control_list.service_block_list",
+        "URLs of services returning peers blocked.",
+    ),
+    (
+        "kore.network.control_list.interval_request",
+        "Time between requests to the services.",
+    ),
+    ("kore.node", "Ledger protocol."),
+    ("kore.node.key_derivator", "Ed25519 or Secp256k1."),
+    (
+        "kore.node.digest_derivator",
+        "Blake3_256, Blake3_512, SHA2_256, SHA2_512, SHA3_256 or SHA3_512.",
+    ),
+    (
+        "kore.node.replication_factor",
+        "Fraction of the witnesses an event is sent to.",
+    ),
+    (
+        "kore.node.timeout",
+        "Time allowed to the protocol requests.",
+    ),
+    (
+        "kore.node.passvotation",
+        "Automatic vote on approvals, 0 disables it.",
+    ),
+    (
+        "kore.node.smartcontracts_directory",
+        "Directory where contracts are compiled.",
+    ),
+    ("kore.db", "Database of the node."),
+    (
+        "kore.db.type",
+        "Backend: leveldb, sled, redb, sqlite or postgres, as compiled in the binary.",
+    ),
+    (
+        "kore.db.path",
+        "Directory (LevelDB, sled) or file (redb, SQLite) of the database.",
+    ),
+    ("kore.db.url", "Connection string of PostgreSQL."),
+    (
+        "kore.db.options",
+        "SQLite PRAGMAs or PostgreSQL parameters, by name.",
+    ),
+    (
+        "kore.db_read_pool_size",
+        "Read connections per collection (SQLite) or to the server (PostgreSQL).",
+    ),
+    ("kore.db_batch", "Writes of several keys applied at once."),
+    (
+        "kore.db_batch.max_writes",
+        "Largest number of writes applied at once.",
+    ),
+    (
+        "kore.db_batch.sync",
+        "Writes reach the disk before returning.",
+    ),
+    (
+        "kore.db_ttl",
+        "Expiry of the entries of auxiliary collections.",
+    ),
+    (
+        "kore.db_ttl.sweep_interval",
+        "Time between deletions of expired entries, 0 disables them.",
+    ),
+    (
+        "kore.db_ttl.collections",
+        "Time to live of the entries of each collection, by name.",
+    ),
+    (
+        "kore.db_encryption",
+        "Encrypt the values of the database (encryption feature).",
+    ),
+    (
+        "kore.db_encryption_key",
+        "Secret of the database key, the node password when empty.",
+    ),
+    ("kore.keys_path", "Directory of the node key files."),
+    (
+        "kore.regenerate_corrupted_keys",
+        "Replace a corrupted node key with a new one instead of failing.",
+    ),
+    (
+        "kore.migrate_legacy_data",
+        "Move the keys and database of the legacy examples/ locations to the configured ones.",
+    ),
+    (
+        "kore.lifecycle_events",
+        "Write ready, listening, degraded and shutdown events to stdout as JSON lines.",
+    ),
+    ("kore.keys", "Encryption of the node key files."),
+    (
+        "kore.keys.kdf",
+        "Key derivation function: pbkdf2 or scrypt.",
+    ),
+    ("kore.keys.iterations", "PBKDF2 iterations."),
+    (
+        "kore.keys.scrypt_log_n",
+        "scrypt cost, as the base 2 logarithm of N.",
+    ),
+    ("kore.keys.scrypt_r", "scrypt block size."),
+    ("kore.keys.scrypt_p", "scrypt parallelization."),
+    (
+        "kore.keys.vault",
+        "HashiCorp Vault of the vault keys backend.",
+    ),
+    ("kore.keys.vault.address", "Address of the Vault server."),
+    (
+        "kore.keys.vault.token",
+        "Vault token, the AppRole credentials are used when empty.",
+    ),
+    ("kore.keys.vault.role_id", "AppRole role id."),
+    ("kore.keys.vault.secret_id", "AppRole secret id."),
+    ("kore.keys.vault.engine", "Secrets engine: kv or transit."),
+    ("kore.keys.vault.mount", "Mount path of the secrets engine."),
+    (
+        "kore.keys.vault.path",
+        "Path of the KV secret, or name of the Transit key.",
+    ),
+    (
+        "kore.keys_backend",
+        "Where the node key pair is kept: file, pkcs11 or vault.",
+    ),
+    (
+        "kore.timestamp_format",
+        "Timestamps of the API responses: epoch, rfc3339 or both.",
+    ),
+    ("kore.pkcs11", "PKCS#11 token of the pkcs11 keys backend."),
+    ("kore.pkcs11.module", "PKCS#11 library of the token vendor."),
+    ("kore.pkcs11.token", "Label of the token."),
+    (
+        "kore.pkcs11.label",
+        "Label of the object that holds the key pair.",
+    ),
+    (
+        "kore.prometheus",
+        "Address or unix:// socket of the metrics server, empty disables it.",
+    ),
+    ("kore.metrics", "Metrics of the node."),
+    (
+        "kore.metrics.push",
+        "Metrics pushed periodically, for nodes that cannot be scraped.",
+    ),
+    (
+        "kore.metrics.push.url",
+        "Pushgateway base URL or remote-write endpoint, empty disables the push.",
+    ),
+    ("kore.metrics.push.mode", "pushgateway or remote_write."),
+    ("kore.metrics.push.interval", "Time between two pushes."),
+    ("kore.metrics.push.job", "job label of the pushed metrics."),
+    (
+        "kore.metrics.push.labels",
+        "Other labels: the grouping key in a Pushgateway, or labels of every series.",
+    ),
+    (
+        "kore.metrics.push.username",
+        "User of the basic authentication, empty sends none.",
+    ),
+    (
+        "kore.metrics.push.password",
+        "Password of the basic authentication.",
+    ),
+    (
+        "kore.http_api",
+        "Address or unix:// socket of the REST API server, empty disables it.",
+    ),
+    (
+        "kore.api_auth",
+        "Bearer tokens of the REST and gRPC servers.",
+    ),
+    (
+        "kore.api_auth.public_token",
+        "Token of the public surface, empty allows any client.",
+    ),
+    (
+        "kore.api_auth.admin_token",
+        "Token of the admin surface, empty allows only loopback clients.",
+    ),
+    (
+        "kore.auth",
+        "Credentials required by the metrics and REST endpoints.",
+    ),
+    (
+        "kore.auth.api_keys",
+        "Keys accepted in the x-api-key header.",
+    ),
+    (
+        "kore.auth.issuer",
+        "Required issuer of the JWTs, empty allows any.",
+    ),
+    (
+        "kore.auth.audience",
+        "Required audience of the JWTs, empty allows any.",
+    ),
+    (
+        "kore.auth.jwks_url",
+        "JWK set that signs the JWTs, empty rejects them.",
+    ),
+    ("kore.grpc", "gRPC server."),
+    (
+        "kore.grpc.listen",
+        "Address of the server, empty disables it.",
+    ),
+    (
+        "kore.grpc.tls_cert",
+        "PEM certificate chain, TLS is enabled when set.",
+    ),
+    ("kore.grpc.tls_key", "PEM private key of the certificate."),
+    (
+        "kore.webhooks",
+        "Notifications of approvals and request results.",
+    ),
+    ("kore.webhooks.urls", "Endpoints notified."),
+    (
+        "kore.webhooks.secret",
+        "Key of the HMAC-SHA256 signature of the payloads.",
+    ),
+    (
+        "kore.webhooks.max_retries",
+        "Attempts after a failed delivery.",
+    ),
+    (
+        "kore.webhooks.retry_backoff",
+        "Wait before the first retry, doubled after each attempt.",
+    ),
+    (
+        "kore.services",
+        "Service endpoints exchanged with trusted peers.",
+    ),
+    (
+        "kore.services.rest_url",
+        "URL of the REST API advertised to the peers.",
+    ),
+    (
+        "kore.services.metrics_url",
+        "URL of the metrics advertised to the peers.",
+    ),
+    (
+        "kore.services.peers",
+        "Base URL of the REST API of each trusted peer, by controller id.",
+    ),
+    (
+        "kore.services.interval",
+        "Time between fetches of the endpoints of the peers.",
+    ),
+    ("kore.warm_up", "Subjects read at startup."),
+    ("kore.warm_up.subjects", "Subjects always read."),
+    (
+        "kore.warm_up.recent",
+        "Subjects with the most recent requests read.",
+    ),
+    (
+        "kore.warm_up.timeout",
+        "Longest time the APIs wait for the warm-up.",
+    ),
+    ("kore.supervisor", "Restarts of the node after a crash."),
+    (
+        "kore.supervisor.enabled",
+        "Rebuild the node after a panic or a fatal error instead of exiting.",
+    ),
+    (
+        "kore.supervisor.max_restarts",
+        "Restarts allowed within the window before the node stops for good.",
+    ),
+    (
+        "kore.supervisor.window",
+        "Time over which the restarts are counted.",
+    ),
+    (
+        "kore.supervisor.backoff",
+        "Wait before the first restart, doubled for each further one.",
+    ),
+    (
+        "kore.supervisor.max_backoff",
+        "Longest wait before a restart.",
+    ),
+    ("kore.backup", "Scheduled backups of the database."),
+    (
+        "kore.backup.directory",
+        "Directory of the backups, empty disables them.",
+    ),
+    (
+        "kore.backup.interval",
+        "Time between backups, 0 disables them.",
+    ),
+    ("kore.backup.keep", "Backups kept."),
+    ("kore.archival", "Archival of old events to object storage."),
+    (
+        "kore.archival.destination",
+        "Directory or bucket URL of the archive, empty disables it.",
+    ),
+    (
+        "kore.archival.max_age",
+        "Age of the events archived, 0 never for their age.",
+    ),
+    (
+        "kore.archival.max_size",
+        "Bytes of events kept locally, 0 no budget.",
+    ),
+    (
+        "kore.archival.interval",
+        "Time between archival runs, 0 disables them.",
+    ),
+    ("kore.soak", "Load generated against the node."),
+    (
+        "kore.soak.rate",
+        "Requests per second, 0 disables the load.",
+    ),
+    (
+        "kore.soak.duration",
+        "Longest time the load runs, 0 until the node stops.",
+    ),
+    (
+        "kore.soak.governance_id",
+        "Governance of the subjects, created when empty.",
+    ),
+    ("kore.soak.schema_id", "Schema of the subjects."),
+    ("kore.soak.subjects", "Subjects created."),
+    ("kore.soak.payload", "JSON payload of the fact events."),
+    ("kore.soak.max_in_flight", "Requests followed at once."),
+    (
+        "kore.soak.report_interval",
+        "Time between reports in the logs.",
+    ),
+    (
+        "kore.replication",
+        "Replication of subjects to another node.",
+    ),
+    (
+        "kore.replication.remote_url",
+        "Base URL of the REST API of the remote node, empty disables it.",
+    ),
+    ("kore.replication.token", "Bearer token of the remote API."),
+    ("kore.replication.subjects", "Subjects replicated."),
+    ("kore.replication.mode", "resubmit or verify."),
+    (
+        "kore.replication.poll_interval",
+        "Time between reads of the events.",
+    ),
+    (
+        "kore.replication.request_timeout",
+        "Longest time a request is followed on the remote node.",
+    ),
+    (
+        "kore.replication.max_attempts",
+        "Attempts of a failing event before it is given up as a dead letter.",
+    ),
+    (
+        "kore.features",
+        "Feature flags, by name, e.g. auto_approval = true.",
+    ),
+    ("kore.quota", "Quota of subject creation per identity."),
+    (
+        "kore.quota.max_subjects",
+        "Subjects per window, 0 disables the quota.",
+    ),
+    ("kore.quota.window", "Window in which subjects are counted."),
+    ("kore.access_log", "Access logs of the API."),
+    (
+        "kore.access_log.sample_rate",
+        "Fraction of the requests logged, from 0 to 1.",
+    ),
+    (
+        "kore.access_log.slow_threshold",
+        "Slower requests are always logged.",
+    ),
+    ("kore.logging", "Logs of the node."),
+    (
+        "kore.logging.level",
+        "off, error, warn, info, debug or trace.",
+    ),
+    (
+        "kore.logging.targets",
+        "Levels of some targets, e.g. kore_base = \"warn\".",
+    ),
+    ("kore.logging.format", "pretty or json."),
+    (
+        "kore.logging.file",
+        "File of the logs, the standard error when empty.",
+    ),
+    (
+        "kore.logging.max_file_size",
+        "Bytes at which the file is rotated, 0 never.",
+    ),
+    ("kore.logging.max_files", "Rotated files kept."),
+    (
+        "kore.schedules",
+        "Periodic actions, as [{ name, interval, action = { type = \"report\" } }].",
+    ),
+    (
+        "kore.signing_policies",
+        "Signers allowed, as [{ request_types = [\"Fact\"], signers = [\"...\"] }].",
+    ),
+    (
+        "kore.approval_rules",
+        "Approvals accepted by auto_approval, as [{ governance_id = \"...\", schema_id }].",
+    ),
+    (
+        "kore.signature_check",
+        "Checks of the signatures sent by clients.",
+    ),
+    (
+        "kore.signature_check.enabled",
+        "Reject mismatched or stale signatures before sending the requests.",
+    ),
+    (
+        "kore.signature_check.max_age",
+        "Age after which a signature is stale.",
+    ),
+    (
+        "kore.signature_check.max_future",
+        "Time a signature may be ahead of the node clock.",
+    ),
+    ("kore.api", "Timeout and retries of the calls to Kore Base."),
+    (
+        "kore.api.timeout_ms",
+        "Milliseconds allowed to each attempt of a call, 0 without limit.",
+    ),
+    (
+        "kore.api.retries",
+        "Retries of the reads that time out or fail in Kore Base.",
+    ),
+    (
+        "kore.api.backoff",
+        "Wait before the first retry, doubled for each next one.",
+    ),
+    (
+        "kore.limits",
+        "Rate and concurrency of the calls to Kore Base.",
+    ),
+    (
+        "kore.limits.read_rate",
+        "Reads per second, 0 without limit.",
+    ),
+    (
+        "kore.limits.read_burst",
+        "Reads allowed at once above the rate.",
+    ),
+    (
+        "kore.limits.read_max_in_flight",
+        "Reads running at the same time, 0 without limit.",
+    ),
+    (
+        "kore.limits.write_rate",
+        "Writes per second, 0 without limit.",
+    ),
+    (
+        "kore.limits.write_burst",
+        "Writes allowed at once above the rate.",
+    ),
+    (
+        "kore.limits.write_max_in_flight",
+        "Writes running at the same time, 0 without limit.",
+    ),
+];
+
+/// Effective settings in the layout of the configuration files.
+///
+/// # Arguments
+///
+/// * `settings` - Settings of the node
+///
+pub(crate) fn settings_document(settings: &KoreSettings) -> Value {
+    let network = &settings.settings.network;
+    let routing = &network.routing;
+    let control_list = &network.control_list;
+    let node = &settings.settings.node;
+    let (db_type, db_path, db_url) = match &settings.db {
+        #[cfg(feature = "leveldb")]
+        DbSettings::LevelDB(path) => ("leveldb", path.as_str(), ""),
+        #[cfg(feature = "sled")]
+        DbSettings::Sled(path) => ("sled", path.as_str(), ""),
+        #[cfg(feature = "redb")]
+        DbSettings::Redb(path) => ("redb", path.as_str(), ""),
+        #[cfg(feature = "sqlite")]
+        DbSettings::Sqlite(path) => ("sqlite", path.as_str(), ""),
+        #[cfg(feature = "postgres")]
+        DbSettings::Postgres { url, .. } => ("postgres", "", url.as_str()),
+    };
+    let boot_nodes = |nodes: &[kore_base::RoutingNode]| -> Vec<Value> {
+        nodes
+            .iter()
+            .map(|node| json!({ "peer_id": node.peer_id, "addresses": node.address }))
+            .collect()
+    };
+    let durations = |durations: &std::collections::BTreeMap<String, std::time::Duration>| {
+        durations
+            .iter()
+            .map(|(name, duration)| (name.clone(), json!(format_duration(*duration))))
+            .collect::<Map<_, _>>()
+    };
+    let schedules: Vec<Value> = settings
+        .schedules
+        .iter()
+        .map(|schedule| {
+            json!({
+                "name": schedule.name,
+                "interval": format_duration(schedule.interval),
+                "action": schedule.action,
+            })
+        })
+        .collect();
+    // Apart from the document, which is near the recursion limit of `json!`.
+    let auth = json!({
+        "api_keys": settings.auth.api_keys,
+        "issuer": settings.auth.issuer,
+        "audience": settings.auth.audience,
+        "jwks_url": settings.auth.jwks_url,
+    });
+    let limits = json!({
+        "read_rate": settings.limits.reads.rate,
+        "read_burst": settings.limits.reads.burst,
+        "read_max_in_flight": settings.limits.reads.max_in_flight,
+        "write_rate": settings.limits.writes.rate,
+        "write_burst": settings.limits.writes.burst,
+        "write_max_in_flight": settings.limits.writes.max_in_flight,
+    });
+    let supervisor = json!({
+        "enabled": settings.supervisor.enabled,
+        "max_restarts": settings.supervisor.max_restarts,
+        "window": format_duration(settings.supervisor.window),
+        "backoff": format_duration(settings.supervisor.backoff),
+        "max_backoff": format_duration(settings.supervisor.max_backoff),
+    });
+    let archival = json!({
+        "destination": settings.archival.destination,
+        "max_age": format_duration(settings.archival.max_age),
+        "max_size": settings.archival.max_size,
+        "interval": format_duration(settings.archival.interval),
+    });
+    let mut document = json!({ "kore": {
+        "network": {
+            "user_agent": network.user_agent,
+            "node_type": network.node_type,
+            "listen_addresses": network.listen_addresses,
+            "external_addresses": network.external_addresses,
+            "port_reuse": network.port_reuse,
+            "listen_fallback_ports": settings.listen_fallback_ports,
+            "tell": {
+                "message_timeout_secs": format_duration(network.tell.get_message_timeout()),
+                "max_concurrent_streams": network.tell.get_max_concurrent_streams(),
+            },
+            "routing": {
+                "boot_nodes": boot_nodes(&routing.boot_nodes()),
+                "dht_random_walk": routing.get_dht_random_walk(),
+                "discovery_only_if_under_num": routing.get_discovery_limit().min(i64::MAX as u64),
+                "allow_non_globals_in_dht": routing.get_allow_non_globals_in_dht(),
+                "allow_private_ip": routing.get_allow_private_ip(),
+                "enable_mdns": routing.get_mdns(),
+                "kademlia_disjoint_query_paths": routing.get_kademlia_disjoint_query_paths(),
+                "kademlia_replication_factor": routing
+                    .get_kademlia_replication_factor()
+                    .map_or(0, usize::from),
+                "protocol_names": routing.get_protocol_names(),
+            },
+            "bootstrap": {
+                "groups": settings.bootstrap.groups.iter().map(|group| json!({
+                    "label": group.label,
+                    "boot_nodes": boot_nodes(&group.boot_nodes),
+                })).collect::<Vec<_>>(),
+                "probe_timeout": format_duration(settings.bootstrap.probe_timeout),
+                "health_interval": format_duration(settings.bootstrap.health_interval),
+                "dns_refresh": format_duration(settings.bootstrap.dns_refresh),
+            },
+            "control_list": {
+                "enable": control_list.get_enable(),
+                "allow_list": control_list.get_allow_list(),
+                "block_list": control_list.get_block_list(),
+                "service_allow_list": control_list.get_service_allow_list(),
+                "service_block_list": control_list.get_service_block_list(),
+                "interval_request": format_duration(control_list.get_interval_request()),
+            },
+        },
+        "node": {
+            "key_derivator": node.key_derivator,
+            "digest_derivator": node.digest_derivator,
+            "replication_factor": node.replication_factor,
+            "timeout": format_duration(std::time::Duration::from_millis(node.timeout.into())),
+            "passvotation": node.passvotation,
+            "smartcontracts_directory": node.smartcontracts_directory,
+        },
+        "db": {
+            "type": db_type,
+            "path": db_path,
+            "url": db_url,
+            "options": settings.db_options,
+        },
+        "db_read_pool_size": settings.db_read_pool_size,
+        "db_batch": {
+            "max_writes": settings.db_batch.max_writes,
+            "sync": settings.db_batch.sync,
+        },
+        "db_ttl": {
+            "sweep_interval": format_duration(settings.db_ttl.sweep_interval),
+            "collections": durations(&settings.db_ttl.collections),
+        },
+        "db_encryption": settings.db_encryption,
+        "db_encryption_key": settings.db_encryption_key,
+        "keys_path": settings.keys_path,
+        "regenerate_corrupted_keys": settings.regenerate_corrupted_keys,
+        "migrate_legacy_data": settings.migrate_legacy_data,
+        "lifecycle_events": settings.lifecycle_events,
+        "keys": {
+            "kdf": settings.keys.kdf,
+            "iterations": settings.keys.iterations,
+            "scrypt_log_n": settings.keys.scrypt_log_n,
+            "scrypt_r": settings.keys.scrypt_r,
+            "scrypt_p": settings.keys.scrypt_p,
+            "vault": {
+                "address": settings.keys.vault.address,
+                "token": settings.keys.vault.token,
+                "role_id": settings.keys.vault.role_id,
+                "secret_id": settings.keys.vault.secret_id,
+                "engine": settings.keys.vault.engine,
+                "mount": settings.keys.vault.mount,
+                "path": settings.keys.vault.path,
+            },
+        },
+        "keys_backend": settings.keys_backend,
+        "timestamp_format": settings.timestamp_format,
+        "pkcs11": {
+            "module": settings.pkcs11.module,
+            "token": settings.pkcs11.token,
+            "label": settings.pkcs11.label,
+        },
+        "prometheus": settings.prometheus,
+        "metrics": {
+            "push": {
+                "url": settings.metrics_push.url,
+                "mode": settings.metrics_push.mode,
+                "interval": format_duration(settings.metrics_push.interval),
+                "job": settings.metrics_push.job,
+                "labels": settings.metrics_push.labels,
+                "username": settings.metrics_push.username,
+                "password": settings.metrics_push.password,
+            },
+        },
+        "http_api": settings.http_api,
+        "api_auth": {
+            "public_token": settings.api_auth.public_token,
+            "admin_token": settings.api_auth.admin_token,
+        },
+        "auth": auth,
+        "grpc": {
+            "listen": settings.grpc.listen,
+            "tls_cert": settings.grpc.tls_cert,
+            "tls_key": settings.grpc.tls_key,
+        },
+        "webhooks": {
+            "urls": settings.webhooks.urls,
+            "secret": settings.webhooks.secret,
+            "max_retries": settings.webhooks.max_retries,
+            "retry_backoff": format_duration(settings.webhooks.retry_backoff),
+        },
+        "services": {
+            "rest_url": settings.services.rest_url,
+            "metrics_url": settings.services.metrics_url,
+            "peers": settings.services.peers,
+            "interval": format_duration(settings.services.interval),
+        },
+        "warm_up": {
+            "subjects": settings.warm_up.subjects,
+            "recent": settings.warm_up.recent,
+            "timeout": format_duration(settings.warm_up.timeout),
+        },
+        "supervisor": supervisor,
+        "backup": {
+            "directory": settings.backup.directory,
+            "interval": format_duration(settings.backup.interval),
+            "keep": settings.backup.keep,
+        },
+        "soak": {
+            "rate": settings.soak.rate,
+            "duration": format_duration(settings.soak.duration),
+            "governance_id": settings.soak.governance_id,
+            "schema_id": settings.soak.schema_id,
+            "subjects": settings.soak.subjects,
+            "payload": settings.soak.payload,
+            "max_in_flight": settings.soak.max_in_flight,
+            "report_interval": format_duration(settings.soak.report_interval),
+        },
+        "replication": {
+            "remote_url": settings.replication.remote_url,
+            "token": settings.replication.token,
+            "subjects": settings.replication.subjects,
+            "mode": settings.replication.mode,
+            "poll_interval": format_duration(settings.replication.poll_interval),
+            "request_timeout": format_duration(settings.replication.request_timeout),
+            "max_attempts": settings.replication.max_attempts,
+        },
+        "features": settings.features,
+        "quota": {
+            "max_subjects": settings.subject_quota.max_subjects,
+            "window": format_duration(settings.subject_quota.window),
+        },
+        "access_log": {
+            "sample_rate": settings.access_log.sample_rate,
+            "slow_threshold": format_duration(settings.access_log.slow_threshold),
+        },
+        "logging": {
+            "level": settings.logging.level,
+            "targets": settings.logging.targets,
+            "format": settings.logging.format,
+            "file": settings.logging.file,
+            "max_file_size": settings.logging.max_file_size,
+            "max_files": settings.logging.max_files,
+        },
+        "schedules": schedules,
+        "signing_policies": settings.signing_policies,
+        "approval_rules": settings.approval_rules,
+        "signature_check": {
+            "enabled": settings.signature_check.enabled,
+            "max_age": format_duration(settings.signature_check.max_age),
+            "max_future": format_duration(settings.signature_check.max_future),
+        },
+        "api": {
+            "timeout_ms": settings.api.timeout.as_millis() as u64,
+            "retries": settings.api.retries,
+            "backoff": format_duration(settings.api.backoff),
+        },
+        "limits": limits,
+    }});
+    // Inserted once built, the document is at the recursion limit of `json!`.
+    document["kore"]["archival"] = archival;
+    document
+}
+
+/// Render the settings in a format.
+///
+/// # Arguments
+///
+/// * `settings` - Settings of the node
+/// * `format` - Format of the file
+/// * `commented` - Write the description of each key above it (TOML and YAML)
+///
+/// # Errors
+///
+/// * `String` - A value has no form in the format, such as a null payload in TOML
+///
+pub(crate) fn render(
+    settings: &KoreSettings,
+    format: ConfigFormat,
+    commented: bool,
+) -> Result<String, String> {
+    let document = settings_document(settings);
+    let Value::Object(root) = &document else {
+        unreachable!("the document is an object");
+    };
+    let mut out = String::new();
+    if commented && format != ConfigFormat::Json {
+        out.push_str("# Configuration of kore-node, checked by `kore-node config check`.\n");
+        out.push_str("# Durations take a unit: ms, s, m, h or d.\n\n");
+    }
+    match format {
+        ConfigFormat::Toml => toml_table(&mut out, &[], root, commented)?,
+        ConfigFormat::Yaml => yaml_mapping(&mut out, &[], root, commented),
+        ConfigFormat::Json => {
+            out = serde_json::to_string_pretty(&document).map_err(|error| error.to_string())?;
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+/// Write the settings to a file, see `KoreSettings::to_file`.
+pub(crate) fn write_settings(
+    settings: &KoreSettings,
+    path: &Path,
+    format: ConfigFormat,
+    commented: bool,
+) -> Result<(), NodeError> {
+    let error = |message: String| {
+        NodeError::Config(vec![ConfigError::new(path.display().to_string(), message)])
+    };
+    let content = render(settings, format, commented).map_err(error)?;
+    fs::write(path, content).map_err(|io_error| error(io_error.to_string()))
+}
+
+/// Comment with the description of a key, when there is one.
+fn comment(out: &mut String, indent: usize, path: &[&str], commented: bool) {
+    if !commented {
+        return;
+    }
+    let key = path.join(".");
+    if let Some((_, description)) = DESCRIPTIONS.iter().find(|(name, _)| *name == key) {
+        out.push_str(&format!(
+            "{:indent$}# {}\n",
+            "",
+            description,
+            indent = indent
+        ));
+    }
+}
+
+/// Key as written in the file, quoted unless it is made of letters, digits, `_` and `-`.
+fn key(name: &str) -> String {
+    let bare = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        name.to_owned()
+    } else {
+        Value::from(name).to_string()
+    }
+}
+
+/// Write a TOML table: its values first, then its subtables under their headers.
+fn toml_table(
+    out: &mut String,
+    path: &[&str],
+    table: &Map<String, Value>,
+    commented: bool,
+) -> Result<(), String> {
+    for (name, value) in table.iter().filter(|(_, value)| !value.is_object()) {
+        let path = [path, &[name.as_str()]].concat();
+        comment(out, 0, &path, commented);
+        out.push_str(&format!("{} = {}\n", key(name), toml_value(value, &path)?));
+    }
+    for (name, value) in table.iter() {
+        let Value::Object(subtable) = value else {
+            continue;
+        };
+        let path = [path, &[name.as_str()]].concat();
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        comment(out, 0, &path, commented);
+        let header: Vec<String> = path.iter().map(|name| key(name)).collect();
+        out.push_str(&format!("[{}]\n", header.join(".")));
+        toml_table(out, &path, subtable, commented)?;
+    }
+    Ok(())
+}
+
+/// Inline TOML form of a value. TOML strings take the escapes of JSON strings.
+fn toml_value(value: &Value, path: &[&str]) -> Result<String, String> {
+    Ok(match value {
+        Value::Null => return Err(format!("{} holds a null, which TOML lacks", path.join("."))),
+        Value::Bool(_) | Value::Number(_) | Value::String(_) => value.to_string(),
+        Value::Array(items) => {
+            let items = items
+                .iter()
+                .map(|item| toml_value(item, path))
+                .collect::<Result<Vec<_>, _>>()?;
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(table) if table.is_empty() => "{}".to_owned(),
+        Value::Object(table) => {
+            let entries = table
+                .iter()
+                .map(|(name, value)| Ok(format!("{} = {}", key(name), toml_value(value, path)?)))
+                .collect::<Result<Vec<_>, String>>()?;
+            format!("{{ {} }}", entries.join(", "))
+        }
+    })
+}
+
+/// Write a YAML block mapping. Values other than non-empty mappings are written in flow
+/// style, as JSON, which YAML reads.
+fn yaml_mapping(out: &mut String, path: &[&str], mapping: &Map<String, Value>, commented: bool) {
+    let indent = path.len() * 2;
+    for (name, value) in mapping {
+        let path = [path, &[name.as_str()]].concat();
+        let nested = matches!(value, Value::Object(submapping) if !submapping.is_empty());
+        if commented && nested && !out.ends_with(":\n") && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        comment(out, indent, &path, commented);
+        match value {
+            Value::Object(submapping) if !submapping.is_empty() => {
+                out.push_str(&format!("{:indent$}{}:\n", "", key(name), indent = indent));
+                yaml_mapping(out, &path, submapping, commented);
+            }
+            value => out.push_str(&format!(
+                "{:indent$}{}: {}\n",
+                "",
+                key(name),
+                value,
+                indent = indent
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::{collections::BTreeMap, time::Duration};
+
+    use super::*;
+    use crate::{
+        config::params::Params,
+        settings::{ApprovalRule, MetricsPushMode, Schedule, ScheduledAction, SigningPolicy},
+    };
+
+    /// Keys of a document that hold values, as dotted paths.
+    fn leaves(path: &str, value: &Value, keys: &mut Vec<String>) {
+        match value {
+            Value::Object(table) if !table.is_empty() => {
+                for (name, value) in table {
+                    leaves(&format!("{}.{}", path, name), value, keys);
+                }
+            }
+            _ => keys.push(path.to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_config_format() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("node.toml")),
+            Some(ConfigFormat::Toml)
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("conf/node.YML")),
+            Some(ConfigFormat::Yaml)
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("node.json")),
+            Some(ConfigFormat::Json)
+        );
+        assert_eq!(ConfigFormat::from_path(Path::new("node.ini")), None);
+        assert_eq!(ConfigFormat::from_path(Path::new("node")), None);
+    }
+
+    #[test]
+    fn test_every_key_described() {
+        let document = settings_document(&KoreSettings::default());
+        let mut keys = vec![];
+        leaves("kore", &document["kore"], &mut keys);
+        let missing: Vec<_> = keys
+            .iter()
+            .filter(|key| !DESCRIPTIONS.iter().any(|(name, _)| name == key))
+            .collect();
+        assert!(
+            missing.is_empty(),
+            "keys without description: {:?}",
+            missing
+        );
+
+        let template = render(&KoreSettings::default(), ConfigFormat::Toml, true).unwrap();
+        assert!(template.contains("# Backups kept.\nkeep = 7\n"));
+        assert!(template.contains("\n[kore.db_batch]\n"));
+        let plain = render(&KoreSettings::default(), ConfigFormat::Yaml, false).unwrap();
+        assert!(!plain.contains('#'));
+        assert!(plain.starts_with("kore:\n  "));
+    }
+
+    #[test]
+    fn test_to_file_round_trip() {
+        let mut settings = KoreSettings::default();
+        settings.db_ttl.collections =
+            BTreeMap::from([("cache".to_owned(), Duration::from_secs(90))]);
+        settings.logging.targets = BTreeMap::from([("kore_base".to_owned(), "warn".to_owned())]);
+        settings.webhooks.urls = vec!["https://hooks.example/kore".to_owned()];
+        settings.webhooks.secret = "quote \" and \\ backslash".to_owned();
+        settings.access_log.slow_threshold = Duration::from_millis(1500);
+        settings.metrics_push.mode = MetricsPushMode::RemoteWrite;
+        settings.metrics_push.labels = BTreeMap::from([("region".to_owned(), "eu".to_owned())]);
+        settings.schedules = vec![Schedule {
+            name: "heartbeat".to_owned(),
+            interval: Duration::from_secs(300),
+            action: ScheduledAction::Fact {
+                subject_id: "subject".to_owned(),
+                payload: json!({"alive": true, "count": [1, 2]}),
+            },
+        }];
+        settings.signing_policies = vec![SigningPolicy {
+            request_types: vec!["Fact".to_owned()],
+            signers: vec![],
+            external_signer: true,
+        }];
+        settings.approval_rules = vec![ApprovalRule {
+            governance_id: String::default(),
+            schema_id: "governance".to_owned(),
+        }];
+        let expected = settings_document(&settings);
+
+        let tempdir = tempfile::tempdir().unwrap();
+        for (file, commented) in [
+            ("node.toml", true),
+            ("node.yaml", true),
+            ("node.json", false),
+        ] {
+            let path = tempdir.path().join(file);
+            let format = ConfigFormat::from_path(&path).unwrap();
+            write_settings(&settings, &path, format, commented).unwrap();
+            let read = KoreSettings::from(Params::from_file(path.to_str().unwrap()).unwrap());
+            assert_eq!(settings_document(&read), expected, "{}", file);
+        }
+
+        settings.schedules[0].action = ScheduledAction::Fact {
+            subject_id: "subject".to_owned(),
+            payload: Value::Null,
+        };
+        let error = render(&settings, ConfigFormat::Toml, false).unwrap_err();
+        assert!(error.contains("kore.schedules"));
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Configuration units.
+//!
+//! Parsing of human-friendly durations (`"500ms"`, `"30s"`, `"5m"`, `"1h"`, `"1d"`) and sizes
+//! (`"512B"`, `"64KB"`, `"512MB"`, `"1GiB"`). Values without unit keep the unit the setting had
+//! before, so existing configurations remain valid. `format_duration` writes durations back in
+//! the same form.
+//!
+
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer};
+
+/// Parse a duration, using `default_unit` for values without unit.
+///
+/// # Arguments
+///
+/// * `value` - Duration, e.g. `"30s"` or `"30"`
+/// * `default_unit` - Duration of one unit when no unit is given
+///
+/// # Errors
+///
+/// * `String` - Description of the invalid value
+///
+pub fn parse_duration(value: &str, default_unit: Duration) -> Result<Duration, String> {
+    let (number, unit) = split_unit(value)?;
+    let unit = match unit.to_lowercase().as_str() {
+        "" => default_unit,
+        "ms" => Duration::from_millis(1),
+        "s" => Duration::from_secs(1),
+        "m" => Duration::from_secs(60),
+        "h" => Duration::from_secs(60 * 60),
+        "d" => Duration::from_secs(24 * 60 * 60),
+        other => return Err(format!("unknown duration unit '{}' in '{}'", other, value)),
+    };
+    u32::try_from(number)
+        .ok()
+        .and_then(|number| unit.checked_mul(number))
+        .ok_or_else(|| format!("duration '{}' out of range", value))
+}
+
+/// Parse a size in bytes. `KB`, `MB`, `GB` and `TB` are decimal, `KiB`, `MiB`, `GiB` and `TiB`
+/// binary; values without unit are bytes.
+///
+/// # Arguments
+///
+/// * `value` - Size, e.g. `"512MB"`
+///
+/// # Errors
+///
+/// * `String` - Description of the invalid value
+///
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let (number, unit) = split_unit(value)?;
+    let multiplier: u64 = match unit.to_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        other => return Err(format!("unknown size unit '{}' in '{}'", other, value)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{}' out of range", value))
+}
+
+/// Format a duration with the largest unit that keeps it exact, as `parse_duration` reads it
+/// back: `"1500ms"`, `"30s"`, `"5m"`, `"2h"` or `"1d"`. Fractions of a millisecond are dropped.
+///
+/// # Arguments
+///
+/// * `duration` - Duration to format
+///
+pub fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    if !millis.is_multiple_of(1000) {
+        return format!("{}ms", millis);
+    }
+    let secs = duration.as_secs();
+    [(24 * 60 * 60, "d"), (60 * 60, "h"), (60, "m")]
+        .iter()
+        .find(|(unit, _)| secs != 0 && secs.is_multiple_of(*unit))
+        .map(|(unit, name)| format!("{}{}", secs / unit, name))
+        .unwrap_or_else(|| format!("{}s", secs))
+}
+
+/// Split `"<number><unit>"`, allowing spaces between both.
+fn split_unit(value: &str) -> Result<(u64, &str), String> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let number = value[..end]
+        .parse::<u64>()
+        .map_err(|_| format!("invalid value '{}'", value))?;
+    Ok((number, value[end..].trim()))
+}
+
+/// Integer or string, as values may come typed from files or as text from env vars.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawValue {
+    Number(u64),
+    Text(String),
+}
+
+/// Deserialize a duration given in seconds when it has no unit.
+pub(crate) fn deserialize_duration_secs<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match RawValue::deserialize(deserializer)? {
+        RawValue::Number(secs) => Ok(Duration::from_secs(secs)),
+        RawValue::Text(text) => {
+            parse_duration(&text, Duration::from_secs(1)).map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+/// Deserialize a duration in milliseconds, given in milliseconds when it has no unit.
+pub(crate) fn deserialize_duration_millis<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let duration = match RawValue::deserialize(deserializer)? {
+        RawValue::Number(millis) => Duration::from_millis(millis),
+        RawValue::Text(text) => {
+            parse_duration(&text, Duration::from_millis(1)).map_err(serde::de::Error::custom)?
+        }
+    };
+    u32::try_from(duration.as_millis()).map_err(serde::de::Error::custom)
+}
+
+/// Deserialize a size in bytes, given in bytes when it has no unit.
+pub(crate) fn deserialize_size<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match RawValue::deserialize(deserializer)? {
+        RawValue::Number(bytes) => Ok(bytes),
+        RawValue::Text(text) => parse_size(&text).map_err(serde::de::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        let secs = Duration::from_secs(1);
+        assert_eq!(parse_duration("30", secs), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("30s", secs), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5m", secs), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration(" 2 h ", secs), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d", secs), Ok(Duration::from_secs(86400)));
+        assert_eq!(
+            parse_duration("500ms", secs),
+            Ok(Duration::from_millis(500))
+        );
+        assert_eq!(
+            parse_duration("500", Duration::from_millis(1)),
+            Ok(Duration::from_millis(500))
+        );
+        assert!(parse_duration("5w", secs).is_err());
+        assert!(parse_duration("s", secs).is_err());
+        assert!(parse_duration("-1s", secs).is_err());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+        assert_eq!(format_duration(Duration::from_millis(1500)), "1500ms");
+        assert_eq!(format_duration(Duration::from_secs(90)), "90s");
+        assert_eq!(format_duration(Duration::from_secs(300)), "5m");
+        assert_eq!(format_duration(Duration::from_secs(7200)), "2h");
+        assert_eq!(format_duration(Duration::from_secs(172800)), "2d");
+        for duration in [Duration::from_millis(250), Duration::from_secs(3660)] {
+            assert_eq!(
+                parse_duration(&format_duration(duration), Duration::from_secs(1)),
+                Ok(duration)
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("512B"), Ok(512));
+        assert_eq!(parse_size("64KB"), Ok(64_000));
+        assert_eq!(parse_size("512MB"), Ok(512_000_000));
+        assert_eq!(parse_size("1GiB"), Ok(1 << 30));
+        assert_eq!(parse_size("2 mib"), Ok(2 << 20));
+        assert!(parse_size("1PB").is_err());
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("99999999999TB").is_err());
+    }
+}
@@ -0,0 +1,1546 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Configuration validation.
+//!
+//! Checks the assembled settings before the node starts: address syntax, value ranges and the
+//! directories the node writes to. Every check runs, so all the problems of a configuration are
+//! reported together, each one located by its key and, where useful, with a hint to fix it.
+//!
+
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    net::SocketAddr,
+    path::Path,
+    str::FromStr,
+};
+
+use libp2p_identity::PeerId;
+use multiaddr::Multiaddr;
+
+#[cfg(feature = "export")]
+use crate::export::FIXED_COLUMNS;
+use kore_base::{DigestIdentifier, KeyIdentifier};
+
+use crate::{
+    error::{ConfigError, NodeError},
+    features::{feature_description, FEATURE_FLAGS},
+    listener::unix_socket_path,
+    logging::parse_level,
+    model::REQUEST_TYPES,
+    settings::{
+        DbSettings, KeyKdf, KeysBackend, KoreSettings, Pkcs11Settings, ReplicationMode,
+        ScheduledAction, VaultEngine, VaultSettings,
+    },
+    utils::{scrypt_params, MIN_PBKDF2_ITERATIONS},
+};
+
+/// Violations found while validating.
+#[derive(Default)]
+struct Diagnostics(Vec<ConfigError>);
+
+impl Diagnostics {
+    /// Record a violation unless `valid`.
+    fn check(&mut self, valid: bool, key: &str, message: &str) {
+        if !valid {
+            self.0.push(ConfigError::new(key, message));
+        }
+    }
+
+    /// Record a violation with a hint unless `valid`.
+    fn check_hint(&mut self, valid: bool, key: &str, message: &str, hint: &str) {
+        if !valid {
+            self.0.push(ConfigError::new(key, message).with_hint(hint));
+        }
+    }
+
+    /// Record the error of a result.
+    fn check_result(&mut self, result: Result<(), String>, key: &str, hint: &str) {
+        if let Err(message) = result {
+            self.check_hint(false, key, &message, hint);
+        }
+    }
+}
+
+/// Check the settings, reporting every violation at once.
+///
+/// # Arguments
+///
+/// * `settings` - Assembled settings.
+///
+/// # Errors
+///
+/// * `NodeError::Config` - Every invalid value, with its key in the configuration file
+///
+pub fn validate(settings: &KoreSettings) -> Result<(), NodeError> {
+    let mut diagnostics = Diagnostics::default();
+    validate_storage(settings, &mut diagnostics);
+    validate_network(settings, &mut diagnostics);
+    validate_node(settings, &mut diagnostics);
+    validate_services(settings, &mut diagnostics);
+
+    if diagnostics.0.is_empty() {
+        Ok(())
+    } else {
+        Err(NodeError::Config(diagnostics.0))
+    }
+}
+
+/// Names and values of the database options, which end up in SQL statements or in the
+/// connection string, so they are limited to plain words and numbers.
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn validate_db_options(settings: &KoreSettings, hint: &str, diagnostics: &mut Diagnostics) {
+    let plain = |text: &str, extra: &[char]| {
+        !text.is_empty()
+            && text
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || extra.contains(&c))
+    };
+    for (name, value) in settings.db_options.iter() {
+        diagnostics.check_hint(
+            plain(name, &[]) && plain(value, &['-', '.']),
+            &format!("kore.db.options.{}", name),
+            &format!("'{}={}' is not a valid option", name, value),
+            hint,
+        );
+    }
+}
+
+/// Database, keys and connection pool.
+fn validate_storage(settings: &KoreSettings, diagnostics: &mut Diagnostics) {
+    const WRITABLE_HINT: &str = "create the directory or give the node write permission";
+    match &settings.db {
+        #[cfg(feature = "leveldb")]
+        DbSettings::LevelDB(path) => {
+            diagnostics.check_result(writable_dir(path), "kore.db.path", WRITABLE_HINT);
+            diagnostics.check_hint(
+                settings.db_options.is_empty(),
+                "kore.db.options",
+                "LevelDB takes no options",
+                "remove the options, or use another database type",
+            );
+        }
+        #[cfg(feature = "sled")]
+        DbSettings::Sled(path) => {
+            diagnostics.check_result(writable_dir(path), "kore.db.path", WRITABLE_HINT);
+            diagnostics.check_hint(
+                settings.db_options.is_empty(),
+                "kore.db.options",
+                "sled takes no options",
+                "remove the options, or use another database type",
+            );
+        }
+        #[cfg(feature = "redb")]
+        DbSettings::Redb(path) => {
+            let dir = Path::new(path).parent().and_then(Path::to_str);
+            diagnostics.check_result(
+                match dir {
+                    Some(dir) if !dir.is_empty() => writable_dir(dir),
+                    _ => Err(format!("'{}' has no directory", path)),
+                },
+                "kore.db.path",
+                "use <directory>/<database name>, e.g. data/redb/database",
+            );
+            diagnostics.check_hint(
+                settings.db_options.is_empty(),
+                "kore.db.options",
+                "redb takes no options",
+                "remove the options, or use another database type",
+            );
+        }
+        #[cfg(feature = "sqlite")]
+        DbSettings::Sqlite(path) => {
+            let dir = Path::new(path).parent().and_then(Path::to_str);
+            diagnostics.check_result(
+                match dir {
+                    Some(dir) if !dir.is_empty() => writable_dir(dir),
+                    _ => Err(format!("'{}' has no directory", path)),
+                },
+                "kore.db.path",
+                "use <directory>/<database name>, e.g. data/sqlitedb/database",
+            );
+            validate_db_options(
+                settings,
+                "use PRAGMAs of the connection, e.g. cache_size=-64000",
+                diagnostics,
+            );
+        }
+        #[cfg(feature = "postgres")]
+        DbSettings::Postgres { url, .. } => {
+            diagnostics.check_hint(
+                url.starts_with("postgres://") || url.starts_with("postgresql://"),
+                "kore.db.url",
+                "expected a connection string",
+                "use postgres://<user>:<password>@<host>/<database>",
+            );
+            validate_db_options(
+                settings,
+                "use parameters of the connection string, e.g. connect_timeout=10",
+                diagnostics,
+            );
+        }
+    }
+    diagnostics.check(
+        settings.db_read_pool_size > 0,
+        "kore.db_read_pool_size",
+        "must be greater than 0",
+    );
+    diagnostics.check(
+        settings.db_batch.max_writes > 0,
+        "kore.db_batch.max_writes",
+        "must be greater than 0",
+    );
+    for (collection, ttl) in settings.db_ttl.collections.iter() {
+        diagnostics.check(
+            !ttl.is_zero(),
+            &format!("kore.db_ttl.collections.{}", collection),
+            "must be greater than 0",
+        );
+    }
+    if settings.db_encryption {
+        diagnostics.check_hint(
+            cfg!(feature = "encryption"),
+            "kore.db_encryption",
+            "database encryption is not available in this build",
+            "build the node with the encryption feature, or disable it",
+        );
+    }
+    let backup = &settings.backup;
+    if !backup.directory.is_empty() {
+        diagnostics.check_result(
+            writable_dir(&backup.directory),
+            "kore.backup.directory",
+            WRITABLE_HINT,
+        );
+        #[cfg(feature = "postgres")]
+        diagnostics.check_hint(
+            !matches!(settings.db, DbSettings::Postgres { .. }),
+            "kore.backup.directory",
+            "PostgreSQL databases are not backed up by the node",
+            "use pg_dump, or remove kore.backup.directory",
+        );
+    }
+    diagnostics.check(
+        !backup.is_scheduled() || backup.keep > 0,
+        "kore.backup.keep",
+        "must be greater than 0 when backups are scheduled",
+    );
+    let archival = &settings.archival;
+    if archival.destination.contains("://") {
+        diagnostics.check_hint(
+            cfg!(feature = "object-store"),
+            "kore.archival.destination",
+            "object storage is not available in this build",
+            "build the node with the object-store feature, or archive to a directory",
+        );
+    } else if !archival.destination.is_empty() {
+        diagnostics.check_result(
+            writable_dir(&archival.destination),
+            "kore.archival.destination",
+            WRITABLE_HINT,
+        );
+    }
+    diagnostics.check(
+        !archival.is_scheduled() || !archival.max_age.is_zero() || archival.max_size > 0,
+        "kore.archival.interval",
+        "archival runs need kore.archival.max_age or kore.archival.max_size",
+    );
+    let keys_path = |diagnostics: &mut Diagnostics| {
+        diagnostics.check_result(
+            writable_dir(&settings.keys_path),
+            "kore.keys_path",
+            WRITABLE_HINT,
+        )
+    };
+    match settings.keys_backend {
+        KeysBackend::File => keys_path(diagnostics),
+        KeysBackend::Pkcs11 => validate_pkcs11(&settings.pkcs11, diagnostics),
+        KeysBackend::Vault => {
+            validate_vault(&settings.keys.vault, diagnostics);
+            // Transit keeps the ciphertext of the key pair in the keys directory.
+            if settings.keys.vault.engine == VaultEngine::Transit {
+                keys_path(diagnostics);
+            }
+        }
+    }
+    let keys = &settings.keys;
+    match keys.kdf {
+        KeyKdf::Pbkdf2 => diagnostics.check_hint(
+            keys.iterations >= MIN_PBKDF2_ITERATIONS,
+            "kore.keys.iterations",
+            &format!("must be at least {}", MIN_PBKDF2_ITERATIONS),
+            "use 600000 or more for PBKDF2-HMAC-SHA256",
+        ),
+        KeyKdf::Scrypt => diagnostics.check_result(
+            scrypt_params(keys.scrypt_log_n, keys.scrypt_r, keys.scrypt_p)
+                .map(|_| ())
+                .map_err(|error| format!("invalid scrypt parameters: {}", error)),
+            "kore.keys",
+            "check scrypt_log_n (below 64), scrypt_r and scrypt_p (greater than 0)",
+        ),
+    }
+}
+
+/// Token of the `pkcs11` keys backend.
+fn validate_pkcs11(pkcs11: &Pkcs11Settings, diagnostics: &mut Diagnostics) {
+    diagnostics.check_hint(
+        cfg!(feature = "hsm"),
+        "kore.keys_backend",
+        "pkcs11 is not available in this build",
+        "build the node with the hsm feature, or use the file backend",
+    );
+    diagnostics.check_hint(
+        Path::new(&pkcs11.module).is_file(),
+        "kore.pkcs11.module",
+        &format!("'{}' is not a file", pkcs11.module),
+        "set the PKCS#11 library of the token, e.g. /usr/lib/softhsm/libsofthsm2.so",
+    );
+    diagnostics.check(
+        !pkcs11.token.is_empty(),
+        "kore.pkcs11.token",
+        "must not be empty",
+    );
+    diagnostics.check(
+        !pkcs11.label.is_empty(),
+        "kore.pkcs11.label",
+        "must not be empty",
+    );
+}
+
+/// Vault of the `vault` keys backend.
+fn validate_vault(vault: &VaultSettings, diagnostics: &mut Diagnostics) {
+    diagnostics.check_hint(
+        cfg!(feature = "vault"),
+        "kore.keys_backend",
+        "vault is not available in this build",
+        "build the node with the vault feature, or use the file backend",
+    );
+    diagnostics.check_hint(
+        vault.address.starts_with("http://") || vault.address.starts_with("https://"),
+        "kore.keys.vault.address",
+        &format!("'{}' is not an HTTP URL", vault.address),
+        "use the address of the Vault server, e.g. https://vault.example:8200",
+    );
+    diagnostics.check_hint(
+        !vault.token.is_empty() || (!vault.role_id.is_empty() && !vault.secret_id.is_empty()),
+        "kore.keys.vault",
+        "no credentials",
+        "set token, or role_id and secret_id to log in with AppRole",
+    );
+    diagnostics.check(
+        !vault.mount.is_empty() && !vault.path.is_empty(),
+        "kore.keys.vault",
+        "mount and path must not be empty",
+    );
+}
+
+/// Addresses of the network and the prometheus server.
+fn validate_network(settings: &KoreSettings, diagnostics: &mut Diagnostics) {
+    const MULTIADDR_HINT: &str = "use the form /ip4/<address>/tcp/<port>";
+    let network = &settings.settings.network;
+    for (key, addresses) in [
+        ("kore.network.listen_addresses", &network.listen_addresses),
+        (
+            "kore.network.external_addresses",
+            &network.external_addresses,
+        ),
+    ] {
+        for (index, address) in addresses.iter().enumerate() {
+            diagnostics.check_result(
+                multiaddr(address)
+                    .map(|_| ())
+                    .map_err(|message| format!("entry {}: {}", index, message)),
+                key,
+                MULTIADDR_HINT,
+            );
+        }
+    }
+
+    for (index, node) in network.routing.boot_nodes().iter().enumerate() {
+        let key = "kore.network.routing.boot_nodes";
+        for address in node.address.iter() {
+            diagnostics.check_result(
+                multiaddr(address)
+                    .map(|_| ())
+                    .map_err(|message| format!("entry {}: {}", index, message)),
+                key,
+                MULTIADDR_HINT,
+            );
+        }
+        diagnostics.check_hint(
+            PeerId::from_str(&node.peer_id).is_ok(),
+            key,
+            &format!("entry {}: '{}' is not a peer id", index, node.peer_id),
+            "use <addresses>/p2p/<peer id>, the peer id is printed by the node on start",
+        );
+    }
+
+    let bootstrap = &settings.bootstrap;
+    let mut labels = HashSet::new();
+    for (index, group) in bootstrap.groups.iter().enumerate() {
+        let key = format!("kore.network.bootstrap.groups.{}", index);
+        diagnostics.check(!group.label.is_empty(), &key, "the label must not be empty");
+        diagnostics.check(
+            labels.insert(group.label.as_str()),
+            &key,
+            &format!("label '{}' used by another group", group.label),
+        );
+        diagnostics.check(
+            !group.boot_nodes.is_empty(),
+            &key,
+            &format!("group '{}' has no boot nodes", group.label),
+        );
+    }
+    if !bootstrap.groups.is_empty() {
+        diagnostics.check(
+            !bootstrap.probe_timeout.is_zero(),
+            "kore.network.bootstrap.probe_timeout",
+            "must be greater than 0 when there are groups",
+        );
+        diagnostics.check(
+            !bootstrap.health_interval.is_zero(),
+            "kore.network.bootstrap.health_interval",
+            "must be greater than 0 when there are groups",
+        );
+    }
+
+    diagnostics.check_hint(
+        settings.prometheus.is_empty() || listen_address(&settings.prometheus),
+        "kore.prometheus",
+        &format!("'{}' is not an address", settings.prometheus),
+        "use <host>:<port>, e.g. 0.0.0.0:3050, or unix://<path>; empty disables the metrics",
+    );
+    diagnostics.check_hint(
+        settings.http_api.is_empty() || listen_address(&settings.http_api),
+        "kore.http_api",
+        &format!("'{}' is not an address", settings.http_api),
+        "use <host>:<port>, e.g. 0.0.0.0:3000, or unix://<path>; empty disables the server",
+    );
+    let api_auth = &settings.api_auth;
+    diagnostics.check_hint(
+        api_auth.admin_token.is_empty() || api_auth.admin_token != api_auth.public_token,
+        "kore.api_auth.admin_token",
+        "must differ from the public token",
+        "use another token, or the public token gives access to the admin API",
+    );
+    let auth = &settings.auth;
+    diagnostics.check(
+        auth.api_keys.iter().all(|key| !key.is_empty()),
+        "kore.auth.api_keys",
+        "empty API key",
+    );
+    if !auth.jwks_url.is_empty() {
+        diagnostics.check_hint(
+            cfg!(feature = "jwt"),
+            "kore.auth.jwks_url",
+            "JWTs are not accepted in this build",
+            "build the node with the jwt feature, or use API keys",
+        );
+        diagnostics.check_hint(
+            auth.jwks_url.starts_with("https://") || auth.jwks_url.starts_with("http://"),
+            "kore.auth.jwks_url",
+            &format!("'{}' is not an HTTP URL", auth.jwks_url),
+            "use the URL of the JWK set of the issuer, e.g. https://<issuer>/.well-known/jwks.json",
+        );
+        // Both are sent as `Authorization: Bearer`, a client of the REST API cannot hold both.
+        diagnostics.check_hint(
+            settings.http_api.is_empty()
+                || (api_auth.public_token.is_empty() && api_auth.admin_token.is_empty()),
+            "kore.auth.jwks_url",
+            "JWTs and the tokens of kore.api_auth share the Authorization header",
+            "use API keys, or leave the tokens of kore.api_auth empty",
+        );
+    }
+
+    let grpc = &settings.grpc;
+    diagnostics.check_hint(
+        grpc.listen.is_empty() || grpc.listen.parse::<SocketAddr>().is_ok(),
+        "kore.grpc.listen",
+        &format!("'{}' is not an address", grpc.listen),
+        "use <ip>:<port>, e.g. 0.0.0.0:50051, or leave it empty to disable the server",
+    );
+    for (key, path) in [
+        ("kore.grpc.tls_cert", &grpc.tls_cert),
+        ("kore.grpc.tls_key", &grpc.tls_key),
+    ] {
+        diagnostics.check(
+            path.is_empty() || Path::new(path).is_file(),
+            key,
+            &format!("'{}' is not a file", path),
+        );
+    }
+    diagnostics.check_hint(
+        grpc.tls_cert.is_empty() == grpc.tls_key.is_empty(),
+        "kore.grpc",
+        "tls_cert and tls_key must be set together",
+        "set both PEM files to serve with TLS, or neither to serve without it",
+    );
+}
+
+/// Kore Base node settings.
+fn validate_node(settings: &KoreSettings, diagnostics: &mut Diagnostics) {
+    let node = &settings.settings.node;
+    diagnostics.check_hint(
+        node.replication_factor > 0.0 && node.replication_factor <= 1.0,
+        "kore.node.replication_factor",
+        "must be greater than 0 and at most 1",
+        "use the fraction of witnesses that must receive the events, e.g. 0.25",
+    );
+    diagnostics.check(
+        node.timeout > 0,
+        "kore.node.timeout",
+        "must be greater than 0",
+    );
+    diagnostics.check_result(
+        writable_dir(&node.smartcontracts_directory),
+        "kore.node.smartcontracts_directory",
+        "the contracts are compiled into this directory, it must be writable",
+    );
+}
+
+/// Quota, access logs, signing policies, webhooks, warm-up and schedules.
+fn validate_services(settings: &KoreSettings, diagnostics: &mut Diagnostics) {
+    diagnostics.check(
+        settings.subject_quota.max_subjects == 0 || !settings.subject_quota.window.is_zero(),
+        "kore.quota.window",
+        "must be greater than 0 when the quota is enabled",
+    );
+    diagnostics.check(
+        !settings.signature_check.enabled || !settings.signature_check.max_age.is_zero(),
+        "kore.signature_check.max_age",
+        "must be greater than 0 when the signatures are checked",
+    );
+    diagnostics.check(
+        settings.api.retries == 0 || !settings.api.backoff.is_zero(),
+        "kore.api.backoff",
+        "must be greater than 0 when the calls are retried",
+    );
+    for (class, limit) in [
+        ("read", &settings.limits.reads),
+        ("write", &settings.limits.writes),
+    ] {
+        diagnostics.check(
+            limit.rate >= 0.0 && limit.rate.is_finite(),
+            &format!("kore.limits.{}_rate", class),
+            "must be a number of calls per second, 0 without limit",
+        );
+    }
+    diagnostics.check(
+        (0.0..=1.0).contains(&settings.access_log.sample_rate),
+        "kore.access_log.sample_rate",
+        "must be between 0 and 1",
+    );
+    const LEVEL_HINT: &str = "use off, error, warn, info, debug or trace";
+    diagnostics.check_result(
+        parse_level(&settings.logging.level).map(|_| ()),
+        "kore.logging.level",
+        LEVEL_HINT,
+    );
+    for (target, level) in settings.logging.targets.iter() {
+        diagnostics.check_result(
+            parse_level(level).map(|_| ()),
+            &format!("kore.logging.targets.{}", target),
+            LEVEL_HINT,
+        );
+    }
+    let known_flags = FEATURE_FLAGS
+        .iter()
+        .map(|(flag, _)| *flag)
+        .collect::<Vec<_>>()
+        .join(", ");
+    for flag in settings.features.keys() {
+        diagnostics.check_hint(
+            feature_description(flag).is_some(),
+            &format!("kore.features.{}", flag),
+            "unknown feature flag",
+            &format!("known flags: {}", known_flags),
+        );
+    }
+    if !settings.logging.file.is_empty() {
+        let dir = Path::new(&settings.logging.file)
+            .parent()
+            .and_then(Path::to_str)
+            .filter(|dir| !dir.is_empty())
+            .unwrap_or(".");
+        diagnostics.check_result(
+            writable_dir(dir),
+            "kore.logging.file",
+            "create the directory or give the node write permission",
+        );
+    }
+
+    let mut restricted = HashSet::new();
+    for (index, policy) in settings.signing_policies.iter().enumerate() {
+        let key = format!("kore.signing_policies.{}", index);
+        diagnostics.check(
+            !policy.request_types.is_empty(),
+            &key,
+            "empty request_types",
+        );
+        for request_type in policy.request_types.iter() {
+            diagnostics.check_hint(
+                REQUEST_TYPES.contains(&request_type.as_str()),
+                &key,
+                &format!("unknown request type '{}'", request_type),
+                "use Create, Fact, Transfer or EOL",
+            );
+            diagnostics.check(
+                restricted.insert(request_type.as_str()),
+                &key,
+                &format!("request type {} is in several policies", request_type),
+            );
+        }
+        for signer in policy.signers.iter() {
+            diagnostics.check(
+                KeyIdentifier::from_str(signer).is_ok(),
+                &key,
+                &format!("'{}' is not a key identifier", signer),
+            );
+        }
+        diagnostics.check_hint(
+            !policy.signers.is_empty() || policy.external_signer,
+            &key,
+            "no signer is allowed",
+            "add the key identifiers of the signers, or set external_signer",
+        );
+    }
+
+    for (index, rule) in settings.approval_rules.iter().enumerate() {
+        diagnostics.check_hint(
+            rule.governance_id.is_empty()
+                || DigestIdentifier::from_str(&rule.governance_id).is_ok(),
+            &format!("kore.approval_rules.{}", index),
+            &format!("'{}' is not a governance identifier", rule.governance_id),
+            "leave it empty to accept the approvals of any governance",
+        );
+    }
+
+    for url in settings.webhooks.urls.iter() {
+        diagnostics.check_hint(
+            url.starts_with("http://") || url.starts_with("https://"),
+            "kore.webhooks.urls",
+            &format!("'{}' is not an HTTP URL", url),
+            "use http://<host>[:<port>]/<path>, or https://",
+        );
+    }
+
+    let http_url = |url: &str| url.starts_with("http://") || url.starts_with("https://");
+    for (key, url) in [
+        ("kore.services.rest_url", &settings.services.rest_url),
+        ("kore.services.metrics_url", &settings.services.metrics_url),
+    ] {
+        diagnostics.check(
+            url.is_empty() || http_url(url),
+            key,
+            &format!("'{}' is not an HTTP URL", url),
+        );
+    }
+    for (controller_id, url) in settings.services.peers.iter() {
+        let key = format!("kore.services.peers.{}", controller_id);
+        diagnostics.check(
+            KeyIdentifier::from_str(controller_id).is_ok(),
+            &key,
+            &format!("'{}' is not a controller id", controller_id),
+        );
+        diagnostics.check_hint(
+            http_url(url),
+            &key,
+            &format!("'{}' is not an HTTP URL", url),
+            "use the base URL of the REST API of the peer, e.g. https://<host>[:<port>]",
+        );
+    }
+    if !settings.services.peers.is_empty() {
+        diagnostics.check_hint(
+            cfg!(feature = "services"),
+            "kore.services.peers",
+            "peers are not fetched in this build",
+            "build the node with the services feature, or remove the peers",
+        );
+        diagnostics.check(
+            !settings.services.interval.is_zero(),
+            "kore.services.interval",
+            "must be greater than 0 when there are peers",
+        );
+    }
+
+    let push = &settings.metrics_push;
+    if push.is_enabled() {
+        diagnostics.check_hint(
+            cfg!(feature = "metrics-push"),
+            "kore.metrics.push.url",
+            "metrics are not pushed in this build",
+            "build the node with the metrics-push feature, or remove the url",
+        );
+        diagnostics.check_hint(
+            http_url(&push.url),
+            "kore.metrics.push.url",
+            &format!("'{}' is not an HTTP URL", push.url),
+            "use the base URL of the Pushgateway, e.g. http://<host>:9091, or the remote-write \
+             endpoint",
+        );
+        diagnostics.check(
+            !push.interval.is_zero(),
+            "kore.metrics.push.interval",
+            "must be greater than 0 when the metrics are pushed",
+        );
+        diagnostics.check(!push.job.is_empty(), "kore.metrics.push.job", "empty job");
+        for name in push.labels.keys() {
+            diagnostics.check_hint(
+                label_name(name) && name != "job",
+                &format!("kore.metrics.push.labels.{}", name),
+                &format!("'{}' is not a label name", name),
+                "use letters, digits and underscores, not starting with a digit nor with __; \
+                 the job is set by kore.metrics.push.job",
+            );
+        }
+        diagnostics.check(
+            push.password.is_empty() || !push.username.is_empty(),
+            "kore.metrics.push.username",
+            "empty user with a password",
+        );
+    }
+
+    for subject_id in settings.warm_up.subjects.iter() {
+        diagnostics.check(
+            DigestIdentifier::from_str(subject_id).is_ok(),
+            "kore.warm_up.subjects",
+            &format!("'{}' is not a subject identifier", subject_id),
+        );
+    }
+    diagnostics.check(
+        !settings.warm_up.is_enabled() || !settings.warm_up.timeout.is_zero(),
+        "kore.warm_up.timeout",
+        "must be greater than 0 when the warm-up is enabled",
+    );
+
+    let supervisor = &settings.supervisor;
+    if supervisor.enabled {
+        diagnostics.check(
+            !supervisor.window.is_zero(),
+            "kore.supervisor.window",
+            "must be greater than 0 when the supervisor is enabled",
+        );
+        diagnostics.check(
+            supervisor.backoff <= supervisor.max_backoff,
+            "kore.supervisor.backoff",
+            "must not be greater than kore.supervisor.max_backoff",
+        );
+    }
+
+    let soak = &settings.soak;
+    if soak.is_enabled() {
+        diagnostics.check_hint(
+            cfg!(feature = "soak"),
+            "kore.soak.rate",
+            "the soak test is not available in this build",
+            "build the node with the soak feature, or set the rate to 0",
+        );
+        diagnostics.check_hint(
+            if soak.governance_id.is_empty() {
+                soak.schema_id == "governance"
+            } else {
+                DigestIdentifier::from_str(&soak.governance_id).is_ok()
+            },
+            "kore.soak.governance_id",
+            &format!("'{}' is not a governance identifier", soak.governance_id),
+            "only governances can be created without a governance_id",
+        );
+        diagnostics.check(
+            !soak.schema_id.is_empty(),
+            "kore.soak.schema_id",
+            "empty schema_id",
+        );
+        diagnostics.check(
+            soak.subjects > 0,
+            "kore.soak.subjects",
+            "must be greater than 0",
+        );
+        diagnostics.check_result(
+            serde_json::from_str::<serde_json::Value>(&soak.payload)
+                .map(|_| ())
+                .map_err(|error| format!("invalid JSON: {}", error)),
+            "kore.soak.payload",
+            "use the JSON payload of a fact event of the schema",
+        );
+        diagnostics.check(
+            soak.max_in_flight > 0,
+            "kore.soak.max_in_flight",
+            "must be greater than 0",
+        );
+        diagnostics.check(
+            !soak.report_interval.is_zero(),
+            "kore.soak.report_interval",
+            "must be greater than 0",
+        );
+    }
+
+    let replication = &settings.replication;
+    if replication.is_enabled() {
+        diagnostics.check_hint(
+            cfg!(feature = "replication"),
+            "kore.replication.remote_url",
+            "replication is not available in this build",
+            "build the node with the replication feature, or remove the remote_url",
+        );
+        diagnostics.check_hint(
+            http_url(&replication.remote_url),
+            "kore.replication.remote_url",
+            &format!("'{}' is not an HTTP URL", replication.remote_url),
+            "use the base URL of the REST API of the remote node, e.g. https://<host>[:<port>]",
+        );
+        diagnostics.check(
+            !replication.subjects.is_empty(),
+            "kore.replication.subjects",
+            "no subject to replicate",
+        );
+        for subject_id in replication.subjects.iter() {
+            diagnostics.check(
+                DigestIdentifier::from_str(subject_id).is_ok(),
+                "kore.replication.subjects",
+                &format!("'{}' is not a subject identifier", subject_id),
+            );
+        }
+        diagnostics.check(
+            !replication.poll_interval.is_zero(),
+            "kore.replication.poll_interval",
+            "must be greater than 0",
+        );
+        diagnostics.check(
+            replication.mode == ReplicationMode::Verify || !replication.request_timeout.is_zero(),
+            "kore.replication.request_timeout",
+            "must be greater than 0 when requests are resubmitted",
+        );
+        diagnostics.check(
+            replication.max_attempts > 0,
+            "kore.replication.max_attempts",
+            "must be greater than 0",
+        );
+    }
+
+    let mut names = HashSet::new();
+    for schedule in settings.schedules.iter() {
+        let key = format!("kore.schedules.{}", schedule.name);
+        diagnostics.check(
+            names.insert(schedule.name.as_str()),
+            &key,
+            "duplicated schedule name",
+        );
+        diagnostics.check(
+            !schedule.interval.is_zero(),
+            &key,
+            "interval must be greater than 0",
+        );
+        match &schedule.action {
+            ScheduledAction::Fact { subject_id, .. } | ScheduledAction::Verify { subject_id } => {
+                diagnostics.check(!subject_id.is_empty(), &key, "empty subject_id");
+            }
+            ScheduledAction::Report | ScheduledAction::Compact => {}
+            #[cfg(feature = "export")]
+            ScheduledAction::Export(export) => {
+                diagnostics.check(
+                    !export.governance_id.is_empty(),
+                    &key,
+                    "empty governance_id",
+                );
+                diagnostics.check(!export.destination.is_empty(), &key, "empty destination");
+                diagnostics.check(export.workers > 0, &key, "workers must be greater than 0");
+                if !export.checkpoint_dir.is_empty() {
+                    diagnostics.check_result(
+                        writable_dir(&export.checkpoint_dir),
+                        &key,
+                        "create the checkpoint directory or give the node write permission",
+                    );
+                }
+                let mut columns = HashSet::new();
+                for column in export.columns.iter() {
+                    diagnostics.check(
+                        !FIXED_COLUMNS.contains(&column.name.as_str())
+                            && columns.insert(column.name.as_str()),
+                        &key,
+                        &format!("duplicated column {}", column.name),
+                    );
+                    diagnostics.check_hint(
+                        column.path.is_empty() || column.path.starts_with('/'),
+                        &key,
+                        &format!("path of column {} is not a JSON pointer", column.name),
+                        "use the path of the value in the payload, e.g. /temperature",
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Parse a multiaddress, describing the problem.
+pub(crate) fn multiaddr(address: &str) -> Result<Multiaddr, String> {
+    Multiaddr::from_str(address)
+        .map_err(|error| format!("'{}' is not a multiaddress: {}", address, error))
+}
+
+/// Whether `address` has the form `<host>:<port>`, the host being an IP address or a DNS name.
+fn socket_address(address: &str) -> bool {
+    address.parse::<SocketAddr>().is_ok()
+        || address
+            .rsplit_once(':')
+            .is_some_and(|(host, port)| host_name(host) && port.parse::<u16>().is_ok())
+}
+
+/// Whether `host` is a DNS name: labels of letters, digits and hyphens, the last one not
+/// numeric, so that a mistyped IPv4 address is not taken for a name.
+fn host_name(host: &str) -> bool {
+    let label = |label: &str| {
+        (1..=63).contains(&label.len())
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    host.len() <= 253
+        && host.split('.').all(label)
+        && host
+            .rsplit('.')
+            .next()
+            .is_some_and(|last| !last.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Whether `name` is a Prometheus label name that is not reserved.
+fn label_name(name: &str) -> bool {
+    !name.starts_with("__")
+        && name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether an HTTP server can listen on `address`: `<host>:<port>`, or `unix://<path>` on Unix.
+fn listen_address(address: &str) -> bool {
+    socket_address(address) || (cfg!(unix) && unix_socket_path(address).is_some())
+}
+
+/// Check that `path` is a writable directory, or that it can be created.
+fn writable_dir(path: &str) -> Result<(), String> {
+    if path.is_empty() {
+        return Err("empty path".to_owned());
+    }
+    // Nearest existing ancestor, where missing directories would be created.
+    let mut dir = Path::new(path);
+    loop {
+        match fs::metadata(dir) {
+            Ok(metadata) if metadata.is_dir() => break,
+            Ok(_) => return Err(format!("'{}' is not a directory", dir.display())),
+            Err(_) => {
+                dir = match dir.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent,
+                    _ => Path::new("."),
+                }
+            }
+        }
+    }
+    let probe = dir.join(format!(".kore-write-check-{}", std::process::id()));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .map_err(|error| format!("'{}' is not writable: {}", dir.display(), error))?;
+    let _ = fs::remove_file(probe);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::settings::{
+        ApiCallSettings, ApprovalRule, ArchivalSettings, BackupSettings, BootGroup,
+        BootstrapSettings, DbTtlSettings, GrpcSettings, KeysSettings, LoggingSettings,
+        ReplicationSettings, Schedule, ServicesSettings, SignatureCheck, SigningPolicy,
+        SoakSettings, WarmUpSettings, WebhookSettings,
+    };
+    use std::{collections::BTreeMap, time::Duration};
+
+    #[test]
+    fn test_validate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file = tempdir.path().join("file");
+        fs::write(&file, b"").unwrap();
+
+        let mut settings = KoreSettings {
+            keys_path: file.join("keys").to_str().unwrap().to_owned(),
+            keys: KeysSettings {
+                iterations: 2048,
+                ..Default::default()
+            },
+            http_api: "3000".to_owned(),
+            grpc: GrpcSettings {
+                listen: "localhost:50051".to_owned(),
+                tls_cert: file.to_str().unwrap().to_owned(),
+                tls_key: String::default(),
+            },
+            webhooks: WebhookSettings {
+                urls: vec!["ftp://erp.example/kore".to_owned()],
+                ..Default::default()
+            },
+            warm_up: WarmUpSettings {
+                subjects: vec!["dashboard".to_owned()],
+                recent: 10,
+                timeout: Duration::ZERO,
+            },
+            signing_policies: vec![SigningPolicy {
+                request_types: vec!["EOL".to_owned(), "Burn".to_owned()],
+                signers: vec![],
+                external_signer: false,
+            }],
+            approval_rules: vec![ApprovalRule {
+                governance_id: "governance".to_owned(),
+                schema_id: String::default(),
+            }],
+            signature_check: SignatureCheck {
+                enabled: true,
+                max_age: Duration::ZERO,
+                ..Default::default()
+            },
+            api: ApiCallSettings {
+                retries: 3,
+                backoff: Duration::ZERO,
+                ..Default::default()
+            },
+            schedules: vec![Schedule {
+                name: "report".to_owned(),
+                interval: Duration::ZERO,
+                action: ScheduledAction::Report,
+            }],
+            ..Default::default()
+        };
+        settings.settings.network.listen_addresses = vec![
+            "/ip4/127.0.0.1/tcp/50000".to_owned(),
+            "127.0.0.1:50001".to_owned(),
+        ];
+        settings.settings.network.external_addresses = vec!["/ip4/1.2.3/tcp/50000".to_owned()];
+        settings.settings.node.replication_factor = 0.0;
+
+        let Err(NodeError::Config(errors)) = validate(&settings) else {
+            panic!("invalid settings accepted");
+        };
+        let locations = errors
+            .iter()
+            .map(|error| error.location.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            vec![
+                "kore.keys_path",
+                "kore.keys.iterations",
+                "kore.network.listen_addresses",
+                "kore.network.external_addresses",
+                "kore.http_api",
+                "kore.grpc.listen",
+                "kore.grpc",
+                "kore.node.replication_factor",
+                "kore.signature_check.max_age",
+                "kore.api.backoff",
+                "kore.signing_policies.0",
+                "kore.signing_policies.0",
+                "kore.approval_rules.0",
+                "kore.webhooks.urls",
+                "kore.warm_up.subjects",
+                "kore.warm_up.timeout",
+                "kore.schedules.report",
+            ]
+        );
+        assert!(errors[2].to_string().contains("127.0.0.1:50001"));
+        assert!(errors[2].hint.is_some());
+    }
+
+    #[test]
+    fn test_validate_pkcs11() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let settings = KoreSettings {
+            keys_backend: KeysBackend::Pkcs11,
+            pkcs11: Pkcs11Settings {
+                module: file.path().to_str().unwrap().to_owned(),
+                token: String::default(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let Err(NodeError::Config(errors)) = validate(&settings) else {
+            panic!("invalid settings accepted");
+        };
+        let locations = errors
+            .iter()
+            .map(|error| error.location.as_str())
+            .filter(|location| {
+                location.starts_with("kore.pkcs11") || location.starts_with("kore.keys")
+            })
+            .collect::<Vec<_>>();
+        let mut expected = vec!["kore.pkcs11.token"];
+        if !cfg!(feature = "hsm") {
+            expected.insert(0, "kore.keys_backend");
+        }
+        assert_eq!(locations, expected);
+    }
+
+    #[test]
+    fn test_validate_vault() {
+        let mut settings = KoreSettings {
+            keys_backend: KeysBackend::Vault,
+            ..Default::default()
+        };
+        settings.keys.vault = VaultSettings {
+            address: "vault.example:8200".to_owned(),
+            role_id: "node".to_owned(),
+            ..Default::default()
+        };
+        let Err(NodeError::Config(errors)) = validate(&settings) else {
+            panic!("invalid settings accepted");
+        };
+        let locations = errors
+            .iter()
+            .map(|error| error.location.as_str())
+            .filter(|location| location.starts_with("kore.keys"))
+            .collect::<Vec<_>>();
+        let mut expected = vec!["kore.keys.vault.address", "kore.keys.vault"];
+        if !cfg!(feature = "vault") {
+            expected.insert(0, "kore.keys_backend");
+        }
+        assert_eq!(locations, expected);
+    }
+
+    #[test]
+    fn test_validate_logging() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file = tempdir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        let settings = KoreSettings {
+            logging: LoggingSettings {
+                level: "verbose".to_owned(),
+                targets: [("kore_base".to_owned(), "warn".to_owned())].into(),
+                file: file.join("node.log").to_str().unwrap().to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let Err(NodeError::Config(errors)) = validate(&settings) else {
+            panic!("invalid settings accepted");
+        };
+        let locations = errors
+            .iter()
+            .map(|error| error.location.as_str())
+            .filter(|location| location.starts_with("kore.logging"))
+            .collect::<Vec<_>>();
+        assert_eq!(locations, vec!["kore.logging.level", "kore.logging.file"]);
+    }
+
+    #[test]
+    fn test_validate_backup() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file = tempdir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        let settings = KoreSettings {
+            backup: BackupSettings {
+                directory: file.to_str().unwrap().to_owned(),
+                interval: Duration::from_secs(3600),
+                keep: 0,
+            },
+            ..Default::default()
+        };
+        let Err(NodeError::Config(errors)) = validate(&settings) else {
+            panic!("invalid settings accepted");
+        };
+        let locations = errors
+            .iter()
+            .map(|error| error.location.as_str())
+            .filter(|location| location.starts_with("kore.backup"))
+            .collect::<Vec<_>>();
+        assert_eq!(locations, vec!["kore.backup.directory", "kore.backup.keep"]);
+    }
+
+    #[test]
+    fn test_validate_archival() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file = tempdir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        let settings = KoreSettings {
+            archival: ArchivalSettings {
+                destination: file.to_str().unwrap().to_owned(),
+                interval: Duration::from_secs(3600),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let Err(NodeError::Config(errors)) = validate(&settings) else {
+            panic!("invalid settings accepted");
+        };
+        let locations = errors
+            .iter()
+            .map(|error| error.location.as_str())
+            .filter(|location| location.starts_with("kore.archival"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            vec!["kore.archival.destination", "kore.archival.interval"]
+        );
+    }
+
+    #[test]
+    fn test_validate_features() {
+        let mut settings = KoreSettings {
+            features: [("auto_approval".to_owned(), true)].into(),
+            ..Default::default()
+        };
+        let feature_errors = |settings: &KoreSettings| match validate(settings) {
+            Err(NodeError::Config(errors)) => errors
+                .into_iter()
+                .filter(|error| error.location.starts_with("kore.features"))
+                .map(|error| error.location)
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
+        assert!(feature_errors(&settings).is_empty());
+
+        settings.features.insert("turbo".to_owned(), false);
+        assert_eq!(feature_errors(&settings), vec!["kore.features.turbo"]);
+    }
+
+    #[test]
+    fn test_validate_bootstrap() {
+        let group = |label: &str, boot_nodes: usize| BootGroup {
+            label: label.to_owned(),
+            boot_nodes: vec![
+                kore_base::RoutingNode {
+                    peer_id: "12D3KooWLXexpg81PjdjnrhmHUxN7U5EtfXJgr9cahei1SJ9Ub3B".to_owned(),
+                    address: vec!["/ip4/127.0.0.1/tcp/50000".to_owned()],
+                };
+                boot_nodes
+            ],
+        };
+        let mut settings = KoreSettings {
+            bootstrap: BootstrapSettings {
+                groups: vec![group("eu-west", 1), group("us-east", 2)],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let bootstrap_errors = |settings: &KoreSettings| match validate(settings) {
+            Err(NodeError::Config(errors)) => errors
+                .into_iter()
+                .filter(|error| error.location.starts_with("kore.network.bootstrap"))
+                .map(|error| error.location)
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
+        assert!(bootstrap_errors(&settings).is_empty());
+
+        settings.bootstrap.groups.push(group("eu-west", 0));
+        settings.bootstrap.probe_timeout = Duration::ZERO;
+        assert_eq!(
+            bootstrap_errors(&settings),
+            vec![
+                "kore.network.bootstrap.groups.2",
+                "kore.network.bootstrap.groups.2",
+                "kore.network.bootstrap.probe_timeout"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_services() {
+        let peer = "EFXv0jBIr6BtoqFMR7G_JBSuozRc2jZnu5VGUH2gy6-w";
+        let mut settings = KoreSettings {
+            services: ServicesSettings {
+                rest_url: "https://node.example.com".to_owned(),
+                peers: [(peer.to_owned(), "https://peer.example.com".to_owned())].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let service_errors = |settings: &KoreSettings| match validate(settings) {
+            Err(NodeError::Config(errors)) => errors
+                .into_iter()
+                .filter(|error| error.location.starts_with("kore.services"))
+                .map(|error| error.location)
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
+        let feature_error = (!cfg!(feature = "services")).then_some("kore.services.peers");
+        assert_eq!(
+            service_errors(&settings),
+            feature_error.into_iter().collect::<Vec<_>>()
+        );
+
+        settings.services.metrics_url = "node.example.com:3050".to_owned();
+        settings
+            .services
+            .peers
+            .insert(peer.to_owned(), "ftp://peer.example.com".to_owned());
+        settings.services.interval = Duration::ZERO;
+        let errors = service_errors(&settings);
+        assert!(errors.contains(&"kore.services.metrics_url".to_owned()));
+        assert!(errors.contains(&format!("kore.services.peers.{}", peer)));
+        assert!(errors.contains(&"kore.services.interval".to_owned()));
+        assert!(!errors.contains(&"kore.services.rest_url".to_owned()));
+    }
+
+    #[test]
+    fn test_validate_metrics_push() {
+        let mut settings = KoreSettings::default();
+        settings.metrics_push.url = "pushgateway.example.com:9091".to_owned();
+        settings.metrics_push.interval = Duration::ZERO;
+        settings.metrics_push.labels = [
+            ("region".to_owned(), "eu".to_owned()),
+            ("job".to_owned(), "other".to_owned()),
+            ("__name__".to_owned(), "up".to_owned()),
+        ]
+        .into();
+        settings.metrics_push.password = "secret".to_owned();
+        let Err(NodeError::Config(errors)) = validate(&settings) else {
+            panic!("invalid push accepted");
+        };
+        let mut locations = errors
+            .iter()
+            .map(|error| error.location.as_str())
+            .filter(|location| location.starts_with("kore.metrics"))
+            .collect::<Vec<_>>();
+        locations.dedup();
+        assert_eq!(
+            locations,
+            vec![
+                "kore.metrics.push.url",
+                "kore.metrics.push.interval",
+                "kore.metrics.push.labels.__name__",
+                "kore.metrics.push.labels.job",
+                "kore.metrics.push.username",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_limits() {
+        let mut settings = KoreSettings::default();
+        settings.limits.reads.rate = f64::INFINITY;
+        settings.limits.writes.rate = -1.0;
+        let Err(NodeError::Config(errors)) = validate(&settings) else {
+            panic!("invalid limits accepted");
+        };
+        let locations = errors
+            .iter()
+            .map(|error| error.location.as_str())
+            .filter(|location| location.starts_with("kore.limits"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            vec!["kore.limits.read_rate", "kore.limits.write_rate"]
+        );
+    }
+
+    #[test]
+    fn test_validate_auth() {
+        let mut settings = KoreSettings::default();
+        settings.auth.api_keys = vec!["metrics-key".to_owned(), String::new()];
+        settings.auth.jwks_url = "issuer.example.com/jwks.json".to_owned();
+        settings.http_api = "127.0.0.1:3000".to_owned();
+        settings.api_auth.public_token = "public-token".to_owned();
+        let Err(NodeError::Config(errors)) = validate(&settings) else {
+            panic!("invalid auth accepted");
+        };
+        let locations = errors
+            .iter()
+            .map(|error| error.location.as_str())
+            .filter(|location| location.starts_with("kore.auth"))
+            .collect::<Vec<_>>();
+        let mut expected = vec!["kore.auth.api_keys"];
+        if !cfg!(feature = "jwt") {
+            expected.push("kore.auth.jwks_url");
+        }
+        expected.extend(["kore.auth.jwks_url", "kore.auth.jwks_url"]);
+        assert_eq!(locations, expected);
+    }
+
+    #[test]
+    fn test_validate_supervisor() {
+        let locations = |settings: &KoreSettings| match validate(settings) {
+            Err(NodeError::Config(errors)) => errors
+                .into_iter()
+                .map(|error| error.location)
+                .filter(|location| location.starts_with("kore.supervisor"))
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
+        let mut settings = KoreSettings::default();
+        settings.supervisor.window = Duration::ZERO;
+        settings.supervisor.backoff = Duration::from_secs(120);
+        assert!(locations(&settings).is_empty());
+
+        settings.supervisor.enabled = true;
+        assert_eq!(
+            locations(&settings),
+            vec!["kore.supervisor.window", "kore.supervisor.backoff"]
+        );
+    }
+
+    #[test]
+    fn test_listen_address() {
+        assert!(listen_address("0.0.0.0:3050"));
+        assert!(listen_address("localhost:0"));
+        assert!(listen_address("[::1]:3050"));
+        assert!(listen_address("metrics.example.com:3050"));
+        assert!(!listen_address("999.0.0.1:3050"));
+        assert!(!listen_address("0.0.0.0:70000"));
+        assert!(!listen_address("bad host:3050"));
+        assert!(!listen_address(":3050"));
+        assert_eq!(listen_address("unix:///var/run/kore.sock"), cfg!(unix));
+        assert!(!listen_address("unix://"));
+        assert!(!listen_address("/var/run/kore.sock"));
+    }
+
+    #[test]
+    fn test_validate_db_encryption() {
+        let settings = KoreSettings {
+            db_encryption: true,
+            ..Default::default()
+        };
+        let encryption_error = match validate(&settings) {
+            Err(NodeError::Config(errors)) => errors
+                .iter()
+                .any(|error| error.location == "kore.db_encryption"),
+            _ => false,
+        };
+        assert_eq!(encryption_error, !cfg!(feature = "encryption"));
+    }
+
+    #[test]
+    fn test_validate_db_ttl() {
+        let settings = KoreSettings {
+            db_ttl: DbTtlSettings {
+                collections: BTreeMap::from([
+                    ("cache".to_owned(), Duration::from_secs(60)),
+                    ("idempotency".to_owned(), Duration::ZERO),
+                ]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let locations = match validate(&settings) {
+            Err(NodeError::Config(errors)) => errors
+                .into_iter()
+                .map(|error| error.location)
+                .filter(|location| location.starts_with("kore.db_ttl"))
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
+        assert_eq!(locations, vec!["kore.db_ttl.collections.idempotency"]);
+    }
+
+    #[test]
+    fn test_validate_replication() {
+        let locations = |replication: ReplicationSettings| match validate(&KoreSettings {
+            replication,
+            ..Default::default()
+        }) {
+            Err(NodeError::Config(errors)) => errors
+                .into_iter()
+                .map(|error| error.location)
+                .filter(|location| location.starts_with("kore.replication"))
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
+        assert!(locations(ReplicationSettings::default()).is_empty());
+
+        let mut expected = if cfg!(feature = "replication") {
+            vec![]
+        } else {
+            vec!["kore.replication.remote_url".to_owned()]
+        };
+        let replication = ReplicationSettings {
+            remote_url: "https://core.example.com".to_owned(),
+            subjects: vec!["JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(locations(replication.clone()), expected);
+
+        let replication = ReplicationSettings {
+            subjects: vec![],
+            request_timeout: Duration::ZERO,
+            max_attempts: 0,
+            ..replication
+        };
+        expected.extend(
+            [
+                "kore.replication.subjects",
+                "kore.replication.request_timeout",
+                "kore.replication.max_attempts",
+            ]
+            .map(str::to_owned),
+        );
+        assert_eq!(locations(replication.clone()), expected);
+
+        // Verified events are not followed on the remote node.
+        let replication = ReplicationSettings {
+            mode: ReplicationMode::Verify,
+            ..replication
+        };
+        expected.remove(expected.len() - 2);
+        assert_eq!(locations(replication), expected);
+    }
+
+    #[test]
+    fn test_validate_soak() {
+        let locations = |soak: SoakSettings| match validate(&KoreSettings {
+            soak,
+            ..Default::default()
+        }) {
+            Err(NodeError::Config(errors)) => errors
+                .into_iter()
+                .map(|error| error.location)
+                .filter(|location| location.starts_with("kore.soak"))
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
+        assert!(locations(SoakSettings::default()).is_empty());
+
+        let expected = if cfg!(feature = "soak") {
+            vec![]
+        } else {
+            vec!["kore.soak.rate".to_owned()]
+        };
+        let soak = SoakSettings {
+            rate: 10,
+            ..Default::default()
+        };
+        assert_eq!(locations(soak), expected);
+
+        let soak = SoakSettings {
+            rate: 10,
+            schema_id: "Wine".to_owned(),
+            payload: "{".to_owned(),
+            ..Default::default()
+        };
+        let mut expected = expected;
+        expected.extend(["kore.soak.governance_id", "kore.soak.payload"].map(str::to_owned));
+        assert_eq!(locations(soak), expected);
+    }
+
+    #[test]
+    fn test_writable_dir() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path().join("a/b");
+        assert!(writable_dir(dir.to_str().unwrap()).is_ok());
+        // Nothing is created.
+        assert!(fs::metadata(tempdir.path().join("a")).is_err());
+        assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 0);
+
+        let file = tempdir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        assert!(writable_dir(file.join("db").to_str().unwrap()).is_err());
+        assert!(writable_dir("").is_err());
+    }
+
+    #[test]
+    fn test_multiaddr() {
+        assert!(multiaddr("/ip4/0.0.0.0/tcp/50000").is_ok());
+        assert!(multiaddr("/dns4/node.kore-ledger.net/tcp/50000").is_ok());
+        assert!(multiaddr("/ip4/0.0.0.0/tcp/port").is_err());
+        assert!(multiaddr("0.0.0.0:50000").is_err());
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Configuration watcher.
+//!
+//! Watches the configuration files of a running node, and the files added to or removed from its
+//! configuration directories, and reads them again on every change. The node compares the new
+//! settings with the live ones and applies those that can change without restart; Kore Base
+//! settings (network, node) are fixed once the node is built, so changes to them are only
+//! reported.
+//!
+
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use super::build::{config_paths, has_config_extension, ConfigSources};
+use crate::{error::NodeError, settings::KoreSettings};
+
+/// Time without file events before the file is read.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Settings applied by a running node without restart.
+pub const RELOADABLE_SETTINGS: [&str; 11] = [
+    "prometheus",
+    "auth",
+    "subject_quota",
+    "access_log",
+    "signing_policies",
+    "approval_rules",
+    "signature_check",
+    "api",
+    "limits",
+    "timestamp_format",
+    "features",
+];
+
+/// Change of a setting found when reloading the configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingChange {
+    /// Setting key.
+    pub key: String,
+    /// Whether the new value is in use; otherwise the node must be restarted.
+    pub applied: bool,
+    /// Why the new value could not be applied, none when it only waits for a restart.
+    pub error: Option<String>,
+}
+
+/// Event of the configuration reload.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigEvent {
+    /// A setting changed.
+    Changed(SettingChange),
+    /// The configuration file could not be read, the live settings are kept.
+    Error(String),
+}
+
+/// Keys of the settings that differ between `old` and `new`.
+pub fn diff_settings(old: &KoreSettings, new: &KoreSettings) -> Vec<&'static str> {
+    let changes = [
+        (
+            "network",
+            format!("{:?}", old.settings.network) != format!("{:?}", new.settings.network),
+        ),
+        (
+            "node",
+            format!("{:?}", old.settings.node) != format!("{:?}", new.settings.node),
+        ),
+        ("db", old.db != new.db || old.db_options != new.db_options),
+        (
+            "db_read_pool_size",
+            old.db_read_pool_size != new.db_read_pool_size,
+        ),
+        ("db_batch", old.db_batch != new.db_batch),
+        ("db_ttl", old.db_ttl != new.db_ttl),
+        (
+            "db_encryption",
+            old.db_encryption != new.db_encryption
+                || old.db_encryption_key != new.db_encryption_key,
+        ),
+        (
+            "listen_fallback_ports",
+            old.listen_fallback_ports != new.listen_fallback_ports,
+        ),
+        ("bootstrap", old.bootstrap != new.bootstrap),
+        ("keys_path", old.keys_path != new.keys_path),
+        (
+            "regenerate_corrupted_keys",
+            old.regenerate_corrupted_keys != new.regenerate_corrupted_keys,
+        ),
+        (
+            "migrate_legacy_data",
+            old.migrate_legacy_data != new.migrate_legacy_data,
+        ),
+        (
+            "lifecycle_events",
+            old.lifecycle_events != new.lifecycle_events,
+        ),
+        ("keys", old.keys != new.keys),
+        ("keys_backend", old.keys_backend != new.keys_backend),
+        ("pkcs11", old.pkcs11 != new.pkcs11),
+        ("prometheus", old.prometheus != new.prometheus),
+        ("metrics_push", old.metrics_push != new.metrics_push),
+        ("http_api", old.http_api != new.http_api),
+        ("api_auth", old.api_auth != new.api_auth),
+        ("auth", old.auth != new.auth),
+        ("grpc", old.grpc != new.grpc),
+        ("webhooks", old.webhooks != new.webhooks),
+        ("warm_up", old.warm_up != new.warm_up),
+        ("supervisor", old.supervisor != new.supervisor),
+        ("backup", old.backup != new.backup),
+        ("archival", old.archival != new.archival),
+        ("soak", old.soak != new.soak),
+        ("replication", old.replication != new.replication),
+        ("services", old.services != new.services),
+        ("features", old.features != new.features),
+        ("subject_quota", old.subject_quota != new.subject_quota),
+        ("access_log", old.access_log != new.access_log),
+        ("logging", old.logging != new.logging),
+        ("schedules", old.schedules != new.schedules),
+        (
+            "signing_policies",
+            old.signing_policies != new.signing_policies,
+        ),
+        ("approval_rules", old.approval_rules != new.approval_rules),
+        (
+            "signature_check",
+            old.signature_check != new.signature_check,
+        ),
+        ("api", old.api != new.api),
+        ("limits", old.limits != new.limits),
+        (
+            "timestamp_format",
+            old.timestamp_format != new.timestamp_format,
+        ),
+    ];
+    changes
+        .into_iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(key, _)| key)
+        .collect()
+}
+
+/// Watcher of the configuration files. Watching stops when it is dropped.
+pub struct ConfigWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl ConfigWatcher {
+    /// Watch the configuration files, and the files added to the configuration directories.
+    ///
+    /// # Arguments
+    ///
+    /// * `sources` - Sources of the settings, their files are the ones watched
+    ///
+    /// # Returns
+    ///
+    /// * The watcher and a receiver of the settings read after each change.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InternalApi` - A file cannot be watched
+    ///
+    #[allow(clippy::type_complexity)]
+    pub fn new(
+        sources: ConfigSources,
+    ) -> Result<(Self, UnboundedReceiver<Result<KoreSettings, NodeError>>), NodeError> {
+        let targets: Vec<Target> = config_paths(&sources.file)
+            .into_iter()
+            .map(Target::new)
+            .collect();
+        let mut directories: Vec<PathBuf> = vec![];
+        for target in &targets {
+            if !directories.contains(&target.directory) {
+                directories.push(target.directory.clone());
+            }
+        }
+        let (sender, receiver) = unbounded_channel();
+        let (changes, changed) = mpsc::channel();
+
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            let modified = matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            );
+            let config = |changed: &PathBuf| targets.iter().any(|target| target.matches(changed));
+            if modified && event.paths.iter().any(config) {
+                let _ = changes.send(());
+            }
+        })
+        .map_err(|error| NodeError::InternalApi(format!("Error watching config: {}", error)))?;
+
+        // Ends when the watcher is dropped, as it owns the sender of the changes.
+        thread::spawn(move || {
+            while changed.recv().is_ok() {
+                // A write usually produces several events, the files are read once they settle.
+                while changed.recv_timeout(DEBOUNCE).is_ok() {}
+                if sender.send(sources.build()).is_err() {
+                    break;
+                }
+            }
+        });
+        for directory in directories {
+            watcher
+                .watch(&directory, RecursiveMode::NonRecursive)
+                .map_err(|error| {
+                    NodeError::InternalApi(format!("Error watching config: {}", error))
+                })?;
+        }
+
+        Ok((Self { _watcher: watcher }, receiver))
+    }
+}
+
+/// Path of the configuration sources and the directory watched for it.
+struct Target {
+    /// Directory watched, the path itself when it is a configuration directory.
+    directory: PathBuf,
+    /// Configuration file, none when the path is a directory.
+    file: Option<PathBuf>,
+}
+
+impl Target {
+    fn new(path: PathBuf) -> Self {
+        if path.is_dir() {
+            // Changes are reported under the canonical path of the directory.
+            let directory = path.canonicalize().unwrap_or(path);
+            return Self {
+                directory,
+                file: None,
+            };
+        }
+        // Editors usually replace the file, so its directory is watched.
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        Self {
+            directory,
+            file: Some(path),
+        }
+    }
+
+    /// Whether a change of `changed` changes the configuration.
+    fn matches(&self, changed: &Path) -> bool {
+        match &self.file {
+            Some(file) => is_config(changed, file),
+            None => {
+                changed.parent() == Some(self.directory.as_path()) && has_config_extension(changed)
+            }
+        }
+    }
+}
+
+/// Whether `changed` is the configuration file, which may be given without extension.
+fn is_config(changed: &Path, config: &Path) -> bool {
+    if config.extension().is_some() {
+        changed.file_name() == config.file_name()
+    } else {
+        changed.file_stem() == config.file_name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::settings::SubjectQuota;
+
+    #[test]
+    fn test_diff_settings() {
+        let old = KoreSettings::default();
+        let mut new = old.clone();
+        assert!(diff_settings(&old, &new).is_empty());
+
+        new.prometheus = "127.0.0.1:3051".to_owned();
+        new.subject_quota = SubjectQuota {
+            max_subjects: 5,
+            window: Duration::from_secs(60),
+        };
+        new.keys_path = "other/keys".to_owned();
+        assert_eq!(
+            diff_settings(&old, &new),
+            vec!["keys_path", "prometheus", "subject_quota"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_config_watcher() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file = tempdir.path().join("config.json");
+        std::fs::write(&file, r#"{"kore": {"prometheus": "127.0.0.1:3060"}}"#).unwrap();
+
+        let sources = ConfigSources::new(false, file.to_str().unwrap());
+        let (_watcher, mut receiver) = ConfigWatcher::new(sources).unwrap();
+        std::fs::write(&file, r#"{"kore": {"prometheus": "127.0.0.1:3061"}}"#).unwrap();
+
+        let settings = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(settings.prometheus, "127.0.0.1:3061");
+    }
+
+    #[tokio::test]
+    async fn test_config_watcher_directory() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let base = tempdir.path().join("10-base.json");
+        std::fs::write(&base, r#"{"kore": {"prometheus": "127.0.0.1:3060"}}"#).unwrap();
+
+        let sources = ConfigSources::new(false, tempdir.path().to_str().unwrap());
+        let (_watcher, mut receiver) = ConfigWatcher::new(sources).unwrap();
+        // A file added to the directory overrides the previous ones.
+        let overlay = tempdir.path().join("20-overlay.json");
+        std::fs::write(&overlay, r#"{"kore": {"prometheus": "127.0.0.1:3061"}}"#).unwrap();
+
+        let settings = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(settings.prometheus, "127.0.0.1:3061");
+    }
+}
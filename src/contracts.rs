@@ -0,0 +1,353 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Smart contracts.
+//!
+//! Kore Base compiles the contracts of the governance schemas into
+//! `kore.node.smartcontracts_directory` and loads that directory once at start, so a module
+//! added or changed there is only run by Kore Base after the node restarts. The node keeps the
+//! list of the WebAssembly modules found there, `<name>.wasm`, and watches the directory so that
+//! an update shipped by the operators is checked before that restart: each new or changed
+//! module is validated with `wasmparser`, as a WebAssembly module that exports the
+//! `main_function` entry point of the contracts, and one that fails emits the `contract_failed`
+//! lifecycle event. The node never loads the modules itself.
+//!
+//! `KoreApi::list_contracts` returns the modules and their state, and
+//! `KoreApi::reload_contracts` reads the directory again on demand, e.g. when the directory is
+//! on a file system that does not report changes.
+//!
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use tokio::sync::mpsc::unbounded_channel;
+use wasmparser::{ExternalKind, Parser, Payload, Validator};
+
+use crate::{
+    error::NodeError,
+    lifecycle::{LifecycleEvent, LifecycleEvents},
+    model::{NodeContract, NodeContractStatus},
+    tasks::NodeTasks,
+};
+
+/// Extension of the contract modules.
+const CONTRACT_EXTENSION: &str = "wasm";
+
+/// Entry point exported by every contract.
+const ENTRY_POINT: &str = "main_function";
+
+/// Time without changes before the directory is read, as copying a module produces several.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Magic number of the WebAssembly binary format.
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+/// Contract modules of the contracts directory, cheap to clone. Its clones share the modules.
+#[derive(Clone)]
+pub struct Contracts {
+    directory: PathBuf,
+    modules: Arc<Mutex<BTreeMap<String, NodeContract>>>,
+    lifecycle: LifecycleEvents,
+}
+
+impl Contracts {
+    /// Contracts of a directory, none until the first `reload`.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - Contracts directory, `kore.node.smartcontracts_directory`.
+    /// * `lifecycle` - Output of the `contract_failed` events.
+    ///
+    pub fn new(directory: impl Into<PathBuf>, lifecycle: LifecycleEvents) -> Self {
+        Self {
+            directory: directory.into(),
+            modules: Arc::default(),
+            lifecycle,
+        }
+    }
+
+    /// Contracts directory.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Modules read so far, by name.
+    pub fn list(&self) -> Vec<NodeContract> {
+        self.modules
+            .lock()
+            .map(|modules| modules.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Read the directory again. New and changed modules are validated, a `contract_failed`
+    /// event is emitted for each one that fails, and the modules removed are forgotten. A
+    /// missing directory has no contracts.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<NodeContract>` - Modules of the directory, by name.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InternalApi` - The directory cannot be read.
+    ///
+    pub fn reload(&self) -> Result<Vec<NodeContract>, NodeError> {
+        let files = match contract_files(&self.directory) {
+            Ok(files) => files,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => vec![],
+            Err(error) => {
+                return Err(NodeError::InternalApi(format!(
+                    "Error reading contracts directory {}: {}",
+                    self.directory.display(),
+                    error
+                )))
+            }
+        };
+        let mut modules = self
+            .modules
+            .lock()
+            .map_err(|_| NodeError::InternalApi("Contracts lock poisoned".to_owned()))?;
+        let mut current = BTreeMap::new();
+        for (name, path, size, modified) in files {
+            let known = modules
+                .remove(&name)
+                .filter(|module| module.size == size && module.modified == modified);
+            let module = match known {
+                Some(module) => module,
+                None => {
+                    let error = fs::read(&path)
+                        .map_err(|error| error.to_string())
+                        .and_then(|bytes| validate_module(&bytes))
+                        .err();
+                    if let Some(reason) = &error {
+                        log::warn!("Contract {} not valid: {}", name, reason);
+                        self.lifecycle.emit(LifecycleEvent::ContractFailed {
+                            contract: name.clone(),
+                            reason: reason.clone(),
+                        });
+                    }
+                    NodeContract {
+                        name: name.clone(),
+                        size,
+                        modified,
+                        status: match error {
+                            Some(_) => NodeContractStatus::Failed,
+                            None => NodeContractStatus::Validated,
+                        },
+                        error,
+                    }
+                }
+            };
+            current.insert(name, module);
+        }
+        *modules = current;
+        Ok(modules.values().cloned().collect())
+    }
+}
+
+/// Name, path, size and modification time of the modules of a directory.
+fn contract_files(directory: &Path) -> io::Result<Vec<(String, PathBuf, u64, u64)>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some(CONTRACT_EXTENSION) {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let metadata = fs::metadata(&path)?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        files.push((name.to_owned(), path.clone(), metadata.len(), modified));
+    }
+    Ok(files)
+}
+
+/// Check that `bytes` is a valid WebAssembly module exporting the entry point of the contracts
+/// as a function.
+fn validate_module(bytes: &[u8]) -> Result<(), String> {
+    if !bytes.starts_with(WASM_MAGIC) {
+        return Err("not a WebAssembly module".to_owned());
+    }
+    Validator::new()
+        .validate_all(bytes)
+        .map_err(|error| error.to_string())?;
+    for payload in Parser::new(0).parse_all(bytes) {
+        let Payload::ExportSection(exports) = payload.map_err(|error| error.to_string())? else {
+            continue;
+        };
+        for export in exports {
+            let export = export.map_err(|error| error.to_string())?;
+            if export.name == ENTRY_POINT && export.kind == ExternalKind::Func {
+                return Ok(());
+            }
+        }
+    }
+    Err(format!("the module does not export `{}`", ENTRY_POINT))
+}
+
+/// Read the contracts directory again after each change, until the node is cancelled.
+/// When the directory cannot be watched, a `degraded` event is emitted and the contracts are
+/// only read again through `KoreApi::reload_contracts`.
+///
+/// # Arguments
+///
+/// * `contracts` - Contracts of the directory watched.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_contracts_watcher(contracts: Contracts, tasks: &NodeTasks) {
+    // Kore Base creates it on the first compilation, which may come later.
+    if let Err(error) = fs::create_dir_all(contracts.directory()) {
+        log::debug!("Contracts directory not created: {}", error);
+    }
+    let (changes, mut changed) = unbounded_channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        let modified = matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        );
+        let contract = |path: &PathBuf| {
+            path.extension().and_then(|extension| extension.to_str()) == Some(CONTRACT_EXTENSION)
+        };
+        if modified && event.paths.iter().any(contract) {
+            let _ = changes.send(());
+        }
+    })
+    .and_then(|mut watcher| {
+        watcher
+            .watch(contracts.directory(), RecursiveMode::NonRecursive)
+            .map(|_| watcher)
+    });
+    let watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(error) => {
+            log::warn!(
+                "Contracts directory {} not watched: {}",
+                contracts.directory.display(),
+                error
+            );
+            contracts
+                .lifecycle
+                .emit(LifecycleEvent::degraded("contracts", error.to_string()));
+            return;
+        }
+    };
+
+    let cancellation = tasks.token();
+    tasks.spawn(async move {
+        // Changes are reported while the watcher lives.
+        let _watcher = watcher;
+        loop {
+            tokio::select! {
+                _ = cancellation.cancelled() => break,
+                change = changed.recv() => if change.is_none() { break },
+            }
+            while let Ok(Some(())) = tokio::time::timeout(DEBOUNCE, changed.recv()).await {}
+            if let Err(error) = contracts.reload() {
+                log::warn!("{}", error);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Module with a function exported as `name`.
+    fn module(name: &str) -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        // Type `() -> ()` and a function of that type.
+        module.extend([1, 4, 1, 0x60, 0, 0, 3, 2, 1, 0]);
+        module.extend([7, name.len() as u8 + 4, 1, name.len() as u8]);
+        module.extend(name.as_bytes());
+        module.extend([0, 0]);
+        // Body of the function: no locals, `end`.
+        module.extend([10, 4, 1, 2, 0, 0x0b]);
+        module
+    }
+
+    #[test]
+    fn test_validate_module() {
+        assert_eq!(validate_module(&module(ENTRY_POINT)), Ok(()));
+        assert_eq!(
+            validate_module(&module("other")),
+            Err("the module does not export `main_function`".to_owned())
+        );
+        assert_eq!(
+            validate_module(b"fn main() {}"),
+            Err("not a WebAssembly module".to_owned())
+        );
+        let mut truncated = module(ENTRY_POINT);
+        truncated.truncate(truncated.len() - 2);
+        assert!(validate_module(&truncated).is_err());
+        // The body of the function leaves a value on the stack of a `() -> ()` function.
+        let mut invalid = module(ENTRY_POINT);
+        invalid.truncate(invalid.len() - 6);
+        invalid.extend([10, 6, 1, 4, 0, 0x41, 0, 0x0b]);
+        assert!(validate_module(&invalid).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reload_contracts() {
+        let directory = tempfile::tempdir().unwrap();
+        let lifecycle = LifecycleEvents::default();
+        let mut events = lifecycle.subscribe();
+        let contracts = Contracts::new(directory.path(), lifecycle);
+        fs::write(directory.path().join("token.wasm"), module(ENTRY_POINT)).unwrap();
+        fs::write(directory.path().join("broken.wasm"), b"fn main() {}").unwrap();
+        fs::write(directory.path().join("notes.txt"), b"not a contract").unwrap();
+
+        let loaded = contracts.reload().unwrap();
+        assert_eq!(
+            loaded
+                .iter()
+                .map(|contract| (contract.name.as_str(), contract.status))
+                .collect::<Vec<_>>(),
+            vec![
+                ("broken", NodeContractStatus::Failed),
+                ("token", NodeContractStatus::Validated)
+            ]
+        );
+        assert_eq!(loaded[0].error.as_deref(), Some("not a WebAssembly module"));
+        assert_eq!(loaded[1].size, module(ENTRY_POINT).len() as u64);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            LifecycleEvent::ContractFailed {
+                contract: "broken".to_owned(),
+                reason: "not a WebAssembly module".to_owned(),
+            }
+        );
+
+        // Unchanged modules are not validated again.
+        assert_eq!(contracts.reload().unwrap(), loaded);
+        assert!(events.try_recv().is_err());
+
+        fs::write(directory.path().join("broken.wasm"), module(ENTRY_POINT)).unwrap();
+        fs::remove_file(directory.path().join("token.wasm")).unwrap();
+        let reloaded = contracts.reload().unwrap();
+        assert_eq!(reloaded.len(), 1);
+        assert_eq!(reloaded[0].status, NodeContractStatus::Validated);
+        assert_eq!(contracts.list(), reloaded);
+
+        let missing = Contracts::new(directory.path().join("missing"), LifecycleEvents::default());
+        assert_eq!(missing.reload().unwrap(), vec![]);
+    }
+}
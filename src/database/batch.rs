@@ -0,0 +1,227 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Write batches.
+//!
+//! A batch applies the puts and deletes of several keys at once: a LevelDB `WriteBatch`, or a
+//! transaction of SQLite and PostgreSQL. Either every write of the batch is applied, or none.
+//!
+//! Batches longer than `kore.db_batch.max_writes` are split in parts of that size, applied in
+//! order. Each part is atomic, but a failure leaves the parts before it applied.
+//!
+//! Kore Base writes one key at a time. Its writes go through a `GroupCommit`, so that the
+//! writes of concurrent callers are applied in one batch, see the [ledger](../ledger/index.html)
+//! collections.
+//!
+
+use std::{
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    sync::{Condvar, Mutex, PoisonError},
+};
+
+use kore_base::{DatabaseCollection, DbError};
+
+/// Write of a batch: the key, and the value to put, or `None` to delete it.
+pub type BatchWrite = (String, Option<Vec<u8>>);
+
+/// Collection that applies batches of writes.
+pub trait BatchCollection: DatabaseCollection {
+    /// Apply `writes` in order, in parts of `kore.db_batch.max_writes` writes at most.
+    ///
+    /// # Arguments
+    ///
+    /// * `writes` - Keys to put, with their value, or to delete.
+    ///
+    /// # Errors
+    ///
+    /// * `DbError` - A part could not be applied. The parts before it remain applied.
+    ///
+    fn write_batch(&self, writes: Vec<BatchWrite>) -> Result<(), DbError>;
+
+    /// Iterate the entries of `prefix` as `iter` does, ending with the error that stops the
+    /// iteration, where `iter` ends as if the prefix had no more entries. Collections whose
+    /// reads can fail override it; by default the iteration does not fail.
+    ///
+    /// # Arguments
+    ///
+    /// * `reverse` - Iterate in reverse order.
+    /// * `prefix` - Prefix of the keys iterated, removed from the keys returned.
+    ///
+    fn try_iter<'a>(
+        &'a self,
+        reverse: bool,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = Result<(String, Vec<u8>), DbError>> + 'a> {
+        Box::new(self.iter(reverse, prefix).map(Ok))
+    }
+
+    /// Iterate the entries of `prefix` whose key, without the prefix, is `from` or after it,
+    /// in order, as `try_iter` does. Collections that seek to `from` override it; by default
+    /// the entries before it are read and skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - Prefix of the keys iterated, removed from the keys returned.
+    /// * `from` - First key returned, without the prefix.
+    ///
+    fn try_iter_from<'a>(
+        &'a self,
+        prefix: &str,
+        from: &str,
+    ) -> Box<dyn Iterator<Item = Result<(String, Vec<u8>), DbError>> + 'a> {
+        let from = from.to_owned();
+        Box::new(
+            self.try_iter(false, prefix)
+                .skip_while(move |entry| entry.as_ref().is_ok_and(|(key, _)| *key < from)),
+        )
+    }
+}
+
+/// Writes of concurrent callers to a collection, applied together. While a batch is written,
+/// the writes that arrive are gathered, and the first of their callers to run writes them as
+/// the next batch once the previous one is done. Each caller returns once its write is applied,
+/// with the result of its batch.
+#[derive(Default)]
+pub(crate) struct GroupCommit {
+    state: Mutex<GroupState>,
+    written: Condvar,
+}
+
+/// Batches of a `GroupCommit`.
+#[derive(Default)]
+struct GroupState {
+    /// Writes of the batch being gathered.
+    pending: Vec<BatchWrite>,
+    /// Callers of the batch being gathered.
+    callers: usize,
+    /// Number of the batch being gathered.
+    gathering: u64,
+    /// Whether a batch is being written.
+    writing: bool,
+    /// Results of the batches written, with their callers that did not return yet.
+    results: HashMap<u64, (Result<(), String>, usize)>,
+}
+
+impl GroupCommit {
+    /// Apply a write to `collection` in the next batch, waiting for it to be written.
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - Collection the writes of this group are applied to.
+    /// * `write` - Key to put, with its value, or to delete.
+    ///
+    /// # Errors
+    ///
+    /// * `DbError::CustomError` - The batch of the write could not be applied.
+    ///
+    pub fn write<C: BatchCollection>(
+        &self,
+        collection: &C,
+        write: BatchWrite,
+    ) -> Result<(), DbError> {
+        // The state is never left half updated.
+        let lock = || self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut state = lock();
+        let batch = state.gathering;
+        state.pending.push(write);
+        state.callers += 1;
+        loop {
+            if let Some((result, callers)) = state.results.get_mut(&batch) {
+                let result = result.clone();
+                *callers -= 1;
+                if *callers == 0 {
+                    state.results.remove(&batch);
+                }
+                return result.map_err(DbError::CustomError);
+            }
+            if state.writing {
+                state = self
+                    .written
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+                continue;
+            }
+            // The batch of this write is the one being gathered.
+            state.writing = true;
+            state.gathering += 1;
+            let writes = std::mem::take(&mut state.pending);
+            let callers = std::mem::take(&mut state.callers);
+            drop(state);
+            let result = panic::catch_unwind(AssertUnwindSafe(|| collection.write_batch(writes)))
+                .map_err(|_| DbError::CustomError("Batch write panicked".to_owned()))
+                .and_then(|result| result)
+                .map_err(|error| error.to_string());
+            state = lock();
+            state.writing = false;
+            state.results.insert(batch, (result, callers));
+            self.written.notify_all();
+        }
+    }
+}
+
+/// Split `writes` in parts of `max_writes` writes at most, treating 0 as 1.
+pub(crate) fn chunks(writes: Vec<BatchWrite>, max_writes: usize) -> Vec<Vec<BatchWrite>> {
+    let max_writes = max_writes.max(1);
+    let mut parts = vec![];
+    let mut writes = writes.into_iter().peekable();
+    while writes.peek().is_some() {
+        parts.push(writes.by_ref().take(max_writes).collect());
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_chunks() {
+        let writes = (0..5)
+            .map(|index| (index.to_string(), Some(vec![index])))
+            .collect::<Vec<_>>();
+        let parts = chunks(writes.clone(), 2);
+        assert_eq!(
+            parts.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        assert_eq!(parts.concat(), writes);
+        assert_eq!(chunks(writes.clone(), 0).len(), 5);
+        assert!(chunks(vec![], 10).is_empty());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_group_commit() {
+        use crate::database::sqlite::SqliteManager;
+        use kore_base::DatabaseManager;
+        use std::sync::Arc;
+
+        let collection = Arc::new(SqliteManager::default().create_collection("group"));
+        let group = Arc::new(GroupCommit::default());
+        let writers = (0..8)
+            .map(|writer| {
+                let (collection, group) = (collection.clone(), group.clone());
+                std::thread::spawn(move || {
+                    for index in 0..20 {
+                        let key = format!("{}.{:02}", writer, index);
+                        group
+                            .write(collection.as_ref(), (key, Some(vec![writer])))
+                            .unwrap();
+                    }
+                    group
+                        .write(collection.as_ref(), (format!("{}.00", writer), None))
+                        .unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(collection.iter(false, "").count(), 8 * 19);
+        assert_eq!(collection.get("7.19").unwrap(), vec![7]);
+        let state = group.state.lock().unwrap();
+        assert!(state.pending.is_empty() && state.results.is_empty());
+    }
+}
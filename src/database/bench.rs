@@ -0,0 +1,175 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Benchmarks of the embedded backends.
+//!
+//! Compares the embedded backends compiled in the binary on the same workload, to choose the
+//! database of a device. They are ignored tests, run in release mode:
+//!
+//! ```text
+//! cargo test --release --no-default-features --features "leveldb redb" bench_backends -- --ignored --nocapture
+//! ```
+//!
+//! Each backend writes `KORE_BENCH_KEYS` keys (10000 by default) of one collection, one by one
+//! and in batches, reads them one by one and by prefix, then deletes them in batches. Writes
+//! are not synced, so that the backends are compared rather than the disk. The report gives
+//! the operations per second of each step, and the bytes on disk once the keys are written.
+//!
+
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use kore_base::DatabaseManager;
+
+use super::batch::BatchCollection;
+use crate::settings::DbBatchSettings;
+
+/// Keys written when `KORE_BENCH_KEYS` is not set.
+const DEFAULT_KEYS: usize = 10_000;
+
+/// Bytes of each value.
+const VALUE_SIZE: usize = 256;
+
+/// Batch settings of the benchmarks: parts of the default size, writes not synced.
+fn batch_settings() -> DbBatchSettings {
+    DbBatchSettings {
+        sync: false,
+        ..Default::default()
+    }
+}
+
+/// Operations per second of each step, and bytes on disk.
+struct Report {
+    backend: &'static str,
+    steps: Vec<(&'static str, f64)>,
+    disk_size: u64,
+}
+
+/// Run the workload on an empty collection whose files are in `dir`.
+fn run<C: BatchCollection>(backend: &'static str, collection: &C, dir: &Path) -> Report {
+    let keys = std::env::var("KORE_BENCH_KEYS")
+        .ok()
+        .and_then(|keys| keys.parse().ok())
+        .unwrap_or(DEFAULT_KEYS);
+    let value = vec![7u8; VALUE_SIZE];
+    let key = |index: usize| format!("bench\u{10FFFF}{:010}", index);
+    let mut steps = vec![];
+    let mut step = |name, operations: usize, elapsed: Duration| {
+        steps.push((name, operations as f64 / elapsed.as_secs_f64()));
+    };
+
+    let start = Instant::now();
+    for index in 0..keys {
+        collection.put(&key(index), &value).unwrap();
+    }
+    step("put", keys, start.elapsed());
+
+    let writes = (0..keys)
+        .map(|index| (key(index), Some(value.clone())))
+        .collect();
+    let start = Instant::now();
+    collection.write_batch(writes).unwrap();
+    step("batch put", keys, start.elapsed());
+
+    let start = Instant::now();
+    for index in 0..keys {
+        collection.get(&key(index)).unwrap();
+    }
+    step("get", keys, start.elapsed());
+
+    let start = Instant::now();
+    assert_eq!(collection.iter(false, "bench\u{10FFFF}").count(), keys);
+    step("iterate", keys, start.elapsed());
+
+    let start = Instant::now();
+    assert_eq!(collection.iter(true, "bench\u{10FFFF}").count(), keys);
+    step("iterate reverse", keys, start.elapsed());
+
+    let disk_size = dir_size(dir);
+    let writes = (0..keys).map(|index| (key(index), None)).collect();
+    let start = Instant::now();
+    collection.write_batch(writes).unwrap();
+    step("batch delete", keys, start.elapsed());
+
+    Report {
+        backend,
+        steps,
+        disk_size,
+    }
+}
+
+/// Bytes of the files of a directory, those of its subdirectories included.
+fn dir_size(path: &Path) -> u64 {
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|entry| {
+                    let metadata = entry.metadata().ok()?;
+                    Some(if metadata.is_dir() {
+                        dir_size(&entry.path())
+                    } else {
+                        metadata.len()
+                    })
+                })
+                .sum()
+        })
+        .unwrap_or_default()
+}
+
+#[test]
+#[ignore = "benchmark, run in release mode with --ignored --nocapture"]
+fn bench_backends() {
+    let mut reports = vec![];
+
+    #[cfg(feature = "leveldb")]
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let db = super::leveldb::open_db(dir.path());
+        let manager = super::leveldb::LeveldbManager::new(db).with_batch(batch_settings());
+        reports.push(run(
+            "leveldb",
+            &manager.create_collection("bench"),
+            dir.path(),
+        ));
+    }
+
+    #[cfg(feature = "redb")]
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let db = super::redb::open_db(&dir.path().join("database")).unwrap();
+        let manager = super::redb::RedbManager::new(db).with_batch(batch_settings());
+        reports.push(run("redb", &manager.create_collection("bench"), dir.path()));
+    }
+
+    #[cfg(feature = "sled")]
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let db = super::sled::open_db(dir.path()).unwrap();
+        let manager = super::sled::SledManager::new(db).with_batch(batch_settings());
+        reports.push(run("sled", &manager.create_collection("bench"), dir.path()));
+    }
+
+    #[cfg(feature = "sqlite")]
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("database");
+        let manager =
+            super::sqlite::SqliteManager::new(path.to_str().unwrap()).with_batch(batch_settings());
+        reports.push(run(
+            "sqlite",
+            &manager.create_collection("bench"),
+            dir.path(),
+        ));
+    }
+
+    for report in reports {
+        println!("{} ({} bytes on disk)", report.backend, report.disk_size);
+        for (step, rate) in report.steps {
+            println!("  {:<16} {:>12.0} ops/s", step, rate);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Value codecs.
+//!
+//! This module contains the encodings that can be used for the values stored by the database
+//! adapters. Every encoded value is prefixed with a version byte that identifies the codec, so
+//! stored data can be checked (and migrated) when the format or the backend changes.
+//!
+//! ## Codecs
+//!
+//! * `BorshCodec` - Borsh encoding (default).
+//! * `BincodeCodec` - Bincode encoding (feature `bincode`).
+//! * `CborCodec` - CBOR encoding (feature `cbor`).
+//!
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::NodeError;
+
+/// Version byte of the Borsh codec.
+pub const BORSH_VERSION: u8 = 1;
+/// Version byte of the Bincode codec.
+pub const BINCODE_VERSION: u8 = 2;
+/// Version byte of the CBOR codec.
+pub const CBOR_VERSION: u8 = 3;
+
+/// Encoding of the values stored in a database collection.
+pub trait ValueCodec: Send + Sync {
+    /// Version byte written before every encoded value.
+    fn version(&self) -> u8;
+
+    /// Encode a value without version byte.
+    fn encode_value<T>(&self, value: &T) -> Result<Vec<u8>, NodeError>
+    where
+        T: BorshSerialize + Serialize;
+
+    /// Decode a value without version byte.
+    fn decode_value<T>(&self, bytes: &[u8]) -> Result<T, NodeError>
+    where
+        T: BorshDeserialize + DeserializeOwned;
+
+    /// Encode a value prefixed with the version byte of the codec.
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, NodeError>
+    where
+        T: BorshSerialize + Serialize,
+    {
+        let mut bytes = vec![self.version()];
+        bytes.extend(self.encode_value(value)?);
+        Ok(bytes)
+    }
+
+    /// Decode a value, checking that it was written by this codec.
+    fn decode<T>(&self, bytes: &[u8]) -> Result<T, NodeError>
+    where
+        T: BorshDeserialize + DeserializeOwned,
+    {
+        match bytes.split_first() {
+            Some((version, payload)) if *version == self.version() => self.decode_value(payload),
+            Some((version, _)) => Err(NodeError::database(format!(
+                "Unexpected codec version {}, expected {}",
+                version,
+                self.version()
+            ))),
+            None => Err(NodeError::database("Empty value".to_owned())),
+        }
+    }
+}
+
+/// Get the codec version byte of an encoded value.
+pub fn encoded_version(bytes: &[u8]) -> Option<u8> {
+    bytes.first().copied()
+}
+
+/// Borsh codec.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BorshCodec;
+
+impl ValueCodec for BorshCodec {
+    fn version(&self) -> u8 {
+        BORSH_VERSION
+    }
+
+    fn encode_value<T>(&self, value: &T) -> Result<Vec<u8>, NodeError>
+    where
+        T: BorshSerialize + Serialize,
+    {
+        borsh::to_vec(value)
+            .map_err(|error| NodeError::database(format!("Borsh encode error: {}", error)))
+    }
+
+    fn decode_value<T>(&self, bytes: &[u8]) -> Result<T, NodeError>
+    where
+        T: BorshDeserialize + DeserializeOwned,
+    {
+        T::try_from_slice(bytes)
+            .map_err(|error| NodeError::database(format!("Borsh decode error: {}", error)))
+    }
+}
+
+/// Bincode codec.
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "bincode")]
+impl ValueCodec for BincodeCodec {
+    fn version(&self) -> u8 {
+        BINCODE_VERSION
+    }
+
+    fn encode_value<T>(&self, value: &T) -> Result<Vec<u8>, NodeError>
+    where
+        T: BorshSerialize + Serialize,
+    {
+        bincode::serialize(value)
+            .map_err(|error| NodeError::database(format!("Bincode encode error: {}", error)))
+    }
+
+    fn decode_value<T>(&self, bytes: &[u8]) -> Result<T, NodeError>
+    where
+        T: BorshDeserialize + DeserializeOwned,
+    {
+        bincode::deserialize(bytes)
+            .map_err(|error| NodeError::database(format!("Bincode decode error: {}", error)))
+    }
+}
+
+/// CBOR codec.
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl ValueCodec for CborCodec {
+    fn version(&self) -> u8 {
+        CBOR_VERSION
+    }
+
+    fn encode_value<T>(&self, value: &T) -> Result<Vec<u8>, NodeError>
+    where
+        T: BorshSerialize + Serialize,
+    {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes)
+            .map_err(|error| NodeError::database(format!("CBOR encode error: {}", error)))?;
+        Ok(bytes)
+    }
+
+    fn decode_value<T>(&self, bytes: &[u8]) -> Result<T, NodeError>
+    where
+        T: BorshDeserialize + DeserializeOwned,
+    {
+        ciborium::from_reader(bytes)
+            .map_err(|error| NodeError::database(format!("CBOR decode error: {}", error)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, PartialEq, Debug)]
+    struct Data {
+        id: usize,
+        value: String,
+    }
+
+    fn data() -> Data {
+        Data {
+            id: 1,
+            value: "aa".into(),
+        }
+    }
+
+    #[test]
+    fn test_borsh_codec() {
+        let codec = BorshCodec;
+        let bytes = codec.encode(&data()).unwrap();
+        assert_eq!(encoded_version(&bytes), Some(BORSH_VERSION));
+        assert_eq!(codec.decode::<Data>(&bytes).unwrap(), data());
+        assert!(codec.decode::<Data>(&[]).is_err());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_bincode_codec() {
+        let codec = BincodeCodec;
+        let bytes = codec.encode(&data()).unwrap();
+        assert_eq!(encoded_version(&bytes), Some(BINCODE_VERSION));
+        assert_eq!(codec.decode::<Data>(&bytes).unwrap(), data());
+        assert!(BorshCodec.decode::<Data>(&bytes).is_err());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor_codec() {
+        let codec = CborCodec;
+        let bytes = codec.encode(&data()).unwrap();
+        assert_eq!(encoded_version(&bytes), Some(CBOR_VERSION));
+        assert_eq!(codec.decode::<Data>(&bytes).unwrap(), data());
+        assert!(BorshCodec.decode::<Data>(&bytes).is_err());
+    }
+}
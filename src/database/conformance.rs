@@ -0,0 +1,143 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Conformance tests.
+//!
+//! Checks every backend must pass, so that collections behave the same whatever the database.
+//! Each backend runs them on a new collection from its own tests.
+//!
+
+use std::collections::BTreeMap;
+
+use kore_base::{DatabaseCollection, DbError};
+
+use super::batch::BatchCollection;
+
+/// Keys written by `check_prefix_iteration`, besides a page of `n<index>` keys.
+const KEYS: &[&str] = &[
+    "a",
+    "A",
+    "a%",
+    "a_b",
+    "aa",
+    "ab",
+    "abab",
+    "ab\u{10FFFF}1",
+    "ab\u{10FFFF}\u{10FFFF}",
+    "ac",
+    "b",
+    "é",
+    "\u{10FFFF}",
+];
+
+/// Prefixes iterated by `check_prefix_iteration`.
+const PREFIXES: &[&str] = &[
+    "",
+    "a",
+    "A",
+    "ab",
+    "ab\u{10FFFF}",
+    "a_",
+    "a%",
+    "n",
+    "n1",
+    "é",
+    "\u{10FFFF}",
+    "z",
+];
+
+/// Check the prefix iteration of a collection against the semantics of the `prefix` module.
+/// The collection must be empty.
+pub(crate) fn check_prefix_iteration<C: DatabaseCollection>(collection: &C) {
+    let mut expected = BTreeMap::new();
+    let keys = KEYS
+        .iter()
+        .map(|key| key.to_string())
+        .chain((0..250).map(|index| format!("n{:03}", index)));
+    for key in keys {
+        let value = key.as_bytes().to_vec();
+        collection.put(&key, &value).unwrap();
+        expected.insert(key, value);
+    }
+
+    for prefix in PREFIXES {
+        let forward = expected
+            .iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix(prefix)?.to_owned(), value.clone())))
+            .collect::<Vec<_>>();
+        let mut backward = forward.clone();
+        backward.reverse();
+        assert_eq!(
+            collection.iter(false, prefix).collect::<Vec<_>>(),
+            forward,
+            "forward iteration of prefix {:?}",
+            prefix
+        );
+        assert_eq!(
+            collection.iter(true, prefix).collect::<Vec<_>>(),
+            backward,
+            "reverse iteration of prefix {:?}",
+            prefix
+        );
+    }
+
+    for key in expected.keys() {
+        collection.del(key).unwrap();
+    }
+    assert!(matches!(collection.get("a"), Err(DbError::EntryNotFound)));
+    assert_eq!(collection.iter(false, "").count(), 0);
+    assert_eq!(collection.iter(true, "").count(), 0);
+}
+
+/// Check the write batches of a collection, whose parts hold 2 writes at most, and its
+/// iteration from a key. The collection must be empty.
+pub(crate) fn check_write_batch<C: BatchCollection>(collection: &C) {
+    collection.put("deleted", b"old").unwrap();
+    collection.put("replaced", b"old").unwrap();
+    collection
+        .write_batch(vec![
+            ("a".to_owned(), Some(b"1".to_vec())),
+            ("deleted".to_owned(), None),
+            ("replaced".to_owned(), Some(b"new".to_vec())),
+            ("b".to_owned(), Some(b"2".to_vec())),
+            ("b".to_owned(), Some(b"3".to_vec())),
+            ("c".to_owned(), Some(b"4".to_vec())),
+            ("c".to_owned(), None),
+            ("missing".to_owned(), None),
+        ])
+        .unwrap();
+    assert_eq!(
+        collection.iter(false, "").collect::<Vec<_>>(),
+        vec![
+            ("a".to_owned(), b"1".to_vec()),
+            ("b".to_owned(), b"3".to_vec()),
+            ("replaced".to_owned(), b"new".to_vec()),
+        ]
+    );
+    assert!(matches!(
+        collection.get("deleted"),
+        Err(DbError::EntryNotFound)
+    ));
+
+    // Iteration from a key starts at it, or at the first key after it.
+    let keys_from = |prefix, from| {
+        collection
+            .try_iter_from(prefix, from)
+            .map(|entry| entry.unwrap().0)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(keys_from("", "b"), vec!["b", "replaced"]);
+    assert_eq!(keys_from("", "ba"), vec!["replaced"]);
+    assert_eq!(keys_from("re", "pl"), vec!["placed"]);
+    assert!(keys_from("re", "q").is_empty());
+
+    collection.write_batch(vec![]).unwrap();
+    collection
+        .write_batch(vec![
+            ("a".to_owned(), None),
+            ("b".to_owned(), None),
+            ("replaced".to_owned(), None),
+        ])
+        .unwrap();
+    assert_eq!(collection.iter(false, "").count(), 0);
+}
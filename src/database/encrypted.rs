@@ -0,0 +1,464 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Encrypted database.
+//!
+//! Wraps a database manager so that the values of its collections are encrypted at rest with
+//! AES-256-GCM, when `kore.db_encryption` is set. Keys stay in the clear, so lookups and prefix
+//! iteration work as before. Each value is sealed with a random nonce and its key as additional
+//! data: a value copied under another key does not decrypt.
+//!
+//! The encryption key is derived with PBKDF2-HMAC-SHA256 from `kore.db_encryption_key`, or from
+//! the node password when that is empty. The salt and rounds of the derivation are kept in the
+//! database under `ENCRYPTION_HEADER`, along with a check value, so that a wrong key is reported
+//! when the node starts instead of on the first read.
+//!
+//! Only databases created with encryption are encrypted: a database that already holds data in
+//! the clear is not opened with encryption, since its values would no longer decrypt, and an
+//! encrypted database is not opened without it.
+//!
+
+use std::{marker::PhantomData, num::NonZeroU32, sync::Arc};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use kore_base::{DatabaseCollection, DatabaseManager, DbError};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+
+use super::{
+    batch::{BatchCollection, BatchWrite},
+    index::INDEX_COLLECTION,
+    ledger::{SIGNATURE_COLLECTION, SUBJECT_COLLECTION},
+};
+use crate::{archival::EVENT_COLLECTION, error::NodeError};
+
+/// Key of the encryption header, in the collection `HEADER_COLLECTION`.
+pub const ENCRYPTION_HEADER: &str = "\u{10FFFF}encryption";
+
+/// Collection of the encryption header.
+const HEADER_COLLECTION: &str = "encryption";
+
+/// Format of the encrypted values, their first byte.
+const VERSION: u8 = 1;
+
+/// Plain text of the check value of the header.
+const CHECK: &[u8] = b"kore-node";
+
+/// Length of the salt of the key derivation.
+const SALT_LEN: usize = 16;
+
+/// Collections written by the node and by Kore Base, checked for data in the clear before the
+/// header of a new encrypted database is written.
+const DATA_COLLECTIONS: [&str; 5] = [
+    "node",
+    INDEX_COLLECTION,
+    EVENT_COLLECTION,
+    SUBJECT_COLLECTION,
+    SIGNATURE_COLLECTION,
+];
+
+/// Derivation of the encryption key, and check value sealed with it.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct EncryptionHeader {
+    salt: Vec<u8>,
+    iterations: u32,
+    check: Vec<u8>,
+}
+
+/// AES-256-GCM key of a database.
+pub struct DbCipher {
+    key: LessSafeKey,
+    random: SystemRandom,
+}
+
+impl DbCipher {
+    /// Derive the key from `secret`.
+    fn derive(secret: &str, salt: &[u8], iterations: NonZeroU32) -> Self {
+        let mut key = [0u8; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            salt,
+            secret.as_bytes(),
+            &mut key,
+        );
+        Self {
+            key: LessSafeKey::new(
+                UnboundKey::new(&AES_256_GCM, &key).expect("AES-256 key of 32 bytes"),
+            ),
+            random: SystemRandom::new(),
+        }
+    }
+
+    /// Encrypt the value of `key`.
+    fn seal(&self, key: &str, value: &[u8]) -> Result<Vec<u8>, DbError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.random
+            .fill(&mut nonce)
+            .map_err(|_| DbError::CustomError("Error generating a nonce".to_owned()))?;
+        let mut sealed = value.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(key.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| DbError::CustomError(format!("Error encrypting {}", key)))?;
+        let mut output = Vec::with_capacity(1 + NONCE_LEN + sealed.len());
+        output.push(VERSION);
+        output.extend_from_slice(&nonce);
+        output.extend_from_slice(&sealed);
+        Ok(output)
+    }
+
+    /// Decrypt the value of `key`.
+    fn open(&self, key: &str, value: &[u8]) -> Result<Vec<u8>, DbError> {
+        let error = || DbError::CustomError(format!("Error decrypting {}", key));
+        let (&version, rest) = value.split_first().ok_or_else(error)?;
+        if version != VERSION || rest.len() < NONCE_LEN {
+            return Err(error());
+        }
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| error())?;
+        let mut sealed = sealed.to_vec();
+        let plain = self
+            .key
+            .open_in_place(nonce, Aad::from(key.as_bytes()), &mut sealed)
+            .map_err(|_| error())?;
+        Ok(plain.to_vec())
+    }
+}
+
+/// Manager whose collections encrypt their values, or keep them in the clear when built without
+/// a secret.
+pub struct EncryptedManager<M, C> {
+    manager: M,
+    cipher: Option<Arc<DbCipher>>,
+    collection: PhantomData<fn() -> C>,
+}
+
+impl<M: DatabaseManager<C>, C: DatabaseCollection> EncryptedManager<M, C> {
+    /// Encrypt the collections of `manager` with a key derived from `secret`.
+    /// A new database gets a header with a random salt and `iterations` rounds of derivation.
+    ///
+    /// # Arguments
+    ///
+    /// * `manager` - Manager of the database.
+    /// * `secret` - Secret of the encryption key, `None` to keep values in the clear.
+    /// * `iterations` - PBKDF2 rounds for a new database.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The key is wrong, the header cannot be read or written, the
+    ///   database is encrypted and no secret is provided, or it holds data in the clear and a
+    ///   secret is provided.
+    ///
+    pub fn new(manager: M, secret: Option<&str>, iterations: u32) -> Result<Self, NodeError> {
+        let headers = manager.create_collection(HEADER_COLLECTION);
+        let header = match headers.get(ENCRYPTION_HEADER) {
+            Ok(bytes) => Some(EncryptionHeader::try_from_slice(&bytes).map_err(|error| {
+                NodeError::database(format!("Encryption header cannot be read: {}", error))
+            })?),
+            Err(DbError::EntryNotFound) => None,
+            Err(error) => return Err(NodeError::from(error)),
+        };
+        let cipher = match (secret, header) {
+            (None, None) => None,
+            (None, Some(_)) => {
+                return Err(NodeError::database(
+                    "The database is encrypted, set kore.db_encryption to open it",
+                ))
+            }
+            (Some(secret), Some(header)) => {
+                let iterations = NonZeroU32::new(header.iterations).ok_or_else(|| {
+                    NodeError::database("Encryption header without derivation rounds")
+                })?;
+                let cipher = DbCipher::derive(secret, &header.salt, iterations);
+                if cipher
+                    .open(ENCRYPTION_HEADER, &header.check)
+                    .ok()
+                    .as_deref()
+                    != Some(CHECK)
+                {
+                    return Err(NodeError::database(
+                        "Wrong database encryption key, check kore.db_encryption_key or the \
+                         node password",
+                    ));
+                }
+                Some(cipher)
+            }
+            (Some(secret), None) => {
+                if DATA_COLLECTIONS.iter().any(|identifier| {
+                    manager
+                        .create_collection(identifier)
+                        .iter(false, "")
+                        .next()
+                        .is_some()
+                }) {
+                    return Err(NodeError::database(
+                        "The database holds data in the clear and cannot be encrypted, unset \
+                         kore.db_encryption or start from an empty database",
+                    ));
+                }
+                let iterations = NonZeroU32::new(iterations)
+                    .ok_or_else(|| NodeError::database("PBKDF2 iterations must not be 0"))?;
+                let mut salt = vec![0u8; SALT_LEN];
+                SystemRandom::new()
+                    .fill(&mut salt)
+                    .map_err(|_| NodeError::database("Error generating the encryption salt"))?;
+                let cipher = DbCipher::derive(secret, &salt, iterations);
+                let header = EncryptionHeader {
+                    salt,
+                    iterations: iterations.get(),
+                    check: cipher.seal(ENCRYPTION_HEADER, CHECK)?,
+                };
+                let bytes = borsh::to_vec(&header).map_err(|error| {
+                    NodeError::database(format!("Encryption header cannot be written: {}", error))
+                })?;
+                headers.put(ENCRYPTION_HEADER, &bytes)?;
+                Some(cipher)
+            }
+        };
+        Ok(Self {
+            manager,
+            cipher: cipher.map(Arc::new),
+            collection: PhantomData,
+        })
+    }
+}
+
+impl<M: DatabaseManager<C>, C: DatabaseCollection> DatabaseManager<EncryptedCollection<C>>
+    for EncryptedManager<M, C>
+{
+    fn default() -> Self {
+        Self {
+            manager: M::default(),
+            cipher: None,
+            collection: PhantomData,
+        }
+    }
+
+    fn create_collection(&self, identifier: &str) -> EncryptedCollection<C> {
+        EncryptedCollection {
+            collection: self.manager.create_collection(identifier),
+            cipher: self.cipher.clone(),
+        }
+    }
+}
+
+/// Collection that encrypts its values.
+pub struct EncryptedCollection<C> {
+    collection: C,
+    cipher: Option<Arc<DbCipher>>,
+}
+
+impl<C: DatabaseCollection> DatabaseCollection for EncryptedCollection<C> {
+    fn get(&self, key: &str) -> Result<Vec<u8>, DbError> {
+        let value = self.collection.get(key)?;
+        match &self.cipher {
+            Some(cipher) => cipher.open(key, &value),
+            None => Ok(value),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), DbError> {
+        match &self.cipher {
+            Some(cipher) => self.collection.put(key, &cipher.seal(key, data)?),
+            None => self.collection.put(key, data),
+        }
+    }
+
+    fn del(&self, key: &str) -> Result<(), DbError> {
+        self.collection.del(key)
+    }
+
+    fn iter<'a>(
+        &'a self,
+        reverse: bool,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a> {
+        let iter = self.collection.iter(reverse, prefix);
+        let Some(cipher) = &self.cipher else {
+            return iter;
+        };
+        let prefix = prefix.to_owned();
+        // Entries that do not decrypt cannot be returned as errors here, so they are skipped.
+        Box::new(
+            iter.filter_map(move |entry| match open_entry(cipher, &prefix, entry)? {
+                Ok(entry) => Some(entry),
+                Err(error) => {
+                    log::warn!("Entry skipped: {:?}", error);
+                    None
+                }
+            }),
+        )
+    }
+}
+
+impl<C> EncryptedCollection<C> {
+    /// Decrypt the entries of `prefix` read from the inner collection, failing on those that do
+    /// not decrypt.
+    fn try_opened<'a>(
+        &'a self,
+        iter: Box<dyn Iterator<Item = Result<(String, Vec<u8>), DbError>> + 'a>,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = Result<(String, Vec<u8>), DbError>> + 'a> {
+        let Some(cipher) = &self.cipher else {
+            return iter;
+        };
+        let prefix = prefix.to_owned();
+        Box::new(iter.filter_map(move |entry| match entry {
+            Ok(entry) => open_entry(cipher, &prefix, entry),
+            Err(error) => Some(Err(error)),
+        }))
+    }
+}
+
+/// Decrypt an entry of `prefix`, `None` for the header, which shares the key space of the
+/// collections in LevelDB.
+fn open_entry(
+    cipher: &DbCipher,
+    prefix: &str,
+    (key, value): (String, Vec<u8>),
+) -> Option<Result<(String, Vec<u8>), DbError>> {
+    let full_key = format!("{}{}", prefix, key);
+    if full_key == ENCRYPTION_HEADER {
+        return None;
+    }
+    Some(cipher.open(&full_key, &value).map(|value| (key, value)))
+}
+
+impl<C: BatchCollection> BatchCollection for EncryptedCollection<C> {
+    fn write_batch(&self, writes: Vec<BatchWrite>) -> Result<(), DbError> {
+        let Some(cipher) = &self.cipher else {
+            return self.collection.write_batch(writes);
+        };
+        let writes = writes
+            .into_iter()
+            .map(|(key, value)| {
+                let value = value.map(|value| cipher.seal(&key, &value)).transpose()?;
+                Ok((key, value))
+            })
+            .collect::<Result<Vec<_>, DbError>>()?;
+        self.collection.write_batch(writes)
+    }
+
+    fn try_iter<'a>(
+        &'a self,
+        reverse: bool,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = Result<(String, Vec<u8>), DbError>> + 'a> {
+        self.try_opened(self.collection.try_iter(reverse, prefix), prefix)
+    }
+
+    fn try_iter_from<'a>(
+        &'a self,
+        prefix: &str,
+        from: &str,
+    ) -> Box<dyn Iterator<Item = Result<(String, Vec<u8>), DbError>> + 'a> {
+        self.try_opened(self.collection.try_iter_from(prefix, from), prefix)
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+
+    use super::*;
+    use crate::database::{
+        conformance::{check_prefix_iteration, check_write_batch},
+        sqlite::{SqliteCollection, SqliteManager},
+    };
+    use crate::settings::DbBatchSettings;
+
+    /// Few rounds, so that the tests do not spend their time in the derivation.
+    const ITERATIONS: u32 = 16;
+
+    fn manager(
+        path: &str,
+        secret: Option<&str>,
+    ) -> Result<EncryptedManager<SqliteManager, SqliteCollection>, NodeError> {
+        EncryptedManager::new(SqliteManager::new(path), secret, ITERATIONS)
+    }
+
+    #[test]
+    fn test_encrypted_collection() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("database");
+        let path = path.to_str().unwrap();
+
+        let db = manager(path, Some("secret")).unwrap();
+        let collection = db.create_collection("node");
+        collection.put("a", b"ledger payload").unwrap();
+        assert_eq!(collection.get("a").unwrap(), b"ledger payload");
+        assert_eq!(
+            collection.iter(false, "").collect::<Vec<_>>(),
+            vec![("a".to_owned(), b"ledger payload".to_vec())]
+        );
+
+        // Stored encrypted, and bound to its key.
+        let plain = SqliteManager::new(path).create_collection("node");
+        let stored = plain.get("a").unwrap();
+        assert!(!stored
+            .windows(b"ledger payload".len())
+            .any(|window| window == b"ledger payload"));
+        plain.put("b", &stored).unwrap();
+        assert!(collection.get("b").is_err());
+        assert_eq!(collection.iter(false, "").count(), 1);
+        drop(db);
+
+        // Opened again with the same key, the header keeps the salt and rounds.
+        let db = EncryptedManager::new(SqliteManager::new(path), Some("secret"), 1).unwrap();
+        assert_eq!(
+            db.create_collection("node").get("a").unwrap(),
+            b"ledger payload"
+        );
+        assert!(manager(path, Some("other")).is_err());
+        assert!(manager(path, None).is_err());
+    }
+
+    #[test]
+    fn test_plain_database_not_encrypted() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("database");
+        let path = path.to_str().unwrap();
+
+        let plain = SqliteManager::new(path).create_collection("node");
+        plain.put("a", b"ledger payload").unwrap();
+        assert!(manager(path, Some("secret")).is_err());
+
+        // No header was written, the data still reads in the clear.
+        let db = manager(path, None).unwrap();
+        assert_eq!(
+            db.create_collection("node").get("a").unwrap(),
+            b"ledger payload"
+        );
+    }
+
+    #[test]
+    fn test_encrypted_conformance() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("database");
+        let db = EncryptedManager::new(
+            SqliteManager::new(path.to_str().unwrap()).with_batch(DbBatchSettings {
+                max_writes: 2,
+                sync: true,
+            }),
+            Some("secret"),
+            ITERATIONS,
+        )
+        .unwrap();
+        check_prefix_iteration(&db.create_collection("iteration_example"));
+        check_write_batch(&db.create_collection("batch_example"));
+    }
+
+    #[test]
+    fn test_clear_collection() {
+        let db = manager(":memory:", None).unwrap();
+        let collection = db.create_collection("node");
+        collection.put("a", b"1").unwrap();
+        assert_eq!(collection.get("a").unwrap(), b"1");
+    }
+}
@@ -0,0 +1,617 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Secondary indexes.
+//!
+//! Kore Base only lists the subjects by governance, and reading the subjects of an owner or a
+//! namespace means reading every subject. The node keeps a copy of each subject in a dedicated
+//! collection, `INDEX_COLLECTION`, with a secondary index per `IndexKey`:
+//!
+//! | Key | Value |
+//! |-----|-------|
+//! | `idx:subject:<subject id>` | Copy of the subject |
+//! | `idx:ns:<namespace>:<subject id>` | Nothing |
+//! | `idx:schema:<schema id>:<subject id>` | Nothing |
+//! | `idx:owner:<owner key>:<subject id>` | Nothing |
+//! | `idx:gov:<governance id>:<subject id>` | Nothing |
+//! | `idx:active:<true or false>:<subject id>` | Nothing |
+//!
+//! The parts of a key are joined with the separator of the [store](../store/index.html), under
+//! the generation of the indexes, `idx:<generation>:...`. A subject is rewritten when Kore Base
+//! writes an event or the subject itself, see `LedgerCommits`, seen as a new sequence number or
+//! active flag, and moved between the entries of each index in the same batch.
+//!
+//! The indexes are read by `KoreApi::get_subjects_by` and `KoreApi::search_subjects`, and rebuilt
+//! from Kore Base by `KoreApi::reindex_subjects` (`kore-node db reindex`) on databases written
+//! before them. A rebuild writes a new generation, which the subjects written meanwhile are also
+//! written to, and the indexes switch to it once it is complete: until then, the previous one is
+//! read.
+//!
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    batch::BatchCollection,
+    store::{NodeStore, StoreBatch},
+};
+use crate::{error::NodeError, model::NodeSubjectData};
+
+/// Collection of the secondary indexes.
+pub const INDEX_COLLECTION: &str = "subject_index";
+
+/// Prefix of the keys of the secondary indexes.
+const INDEX_PREFIX: &str = "idx";
+
+/// Key of the generation of the indexes that is read.
+const GENERATION_KEY: &str = "generation";
+
+/// Secondary index of the subjects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexKey {
+    /// Namespace of the subject.
+    Namespace,
+    /// Schema of the subject.
+    Schema,
+    /// Owner of the subject.
+    Owner,
+    /// Governance of the subject.
+    Governance,
+    /// Whether the subject is active.
+    Active,
+}
+
+impl IndexKey {
+    /// Every index, in the order of the entries written for a subject.
+    const ALL: [IndexKey; 5] = [
+        IndexKey::Namespace,
+        IndexKey::Schema,
+        IndexKey::Owner,
+        IndexKey::Governance,
+        IndexKey::Active,
+    ];
+
+    /// Part of the keys of the index.
+    fn name(&self) -> &'static str {
+        match self {
+            IndexKey::Namespace => "ns",
+            IndexKey::Schema => "schema",
+            IndexKey::Owner => "owner",
+            IndexKey::Governance => "gov",
+            IndexKey::Active => "active",
+        }
+    }
+}
+
+/// Copy of a subject in the index, its properties as JSON.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct IndexedSubject {
+    governance_id: String,
+    sn: u64,
+    public_key: String,
+    namespace: String,
+    name: String,
+    schema_id: String,
+    owner: String,
+    creator: String,
+    properties: String,
+    active: bool,
+}
+
+impl IndexedSubject {
+    /// Value of the subject in an index.
+    fn key(&self, index: IndexKey) -> String {
+        match index {
+            IndexKey::Namespace => self.namespace.clone(),
+            IndexKey::Schema => self.schema_id.clone(),
+            IndexKey::Owner => self.owner.clone(),
+            IndexKey::Governance => self.governance_id.clone(),
+            IndexKey::Active => self.active.to_string(),
+        }
+    }
+
+    /// Subject as returned by the API, not archived.
+    fn subject(self, subject_id: String) -> NodeSubjectData {
+        NodeSubjectData {
+            subject_id,
+            governance_id: self.governance_id,
+            sn: self.sn,
+            public_key: self.public_key,
+            namespace: self.namespace,
+            name: self.name,
+            schema_id: self.schema_id,
+            owner: self.owner,
+            creator: self.creator,
+            properties: serde_json::from_str(&self.properties).unwrap_or_default(),
+            active: self.active,
+            archived: false,
+        }
+    }
+}
+
+impl From<&NodeSubjectData> for IndexedSubject {
+    fn from(subject: &NodeSubjectData) -> Self {
+        Self {
+            governance_id: subject.governance_id.clone(),
+            sn: subject.sn,
+            public_key: subject.public_key.clone(),
+            namespace: subject.namespace.clone(),
+            name: subject.name.clone(),
+            schema_id: subject.schema_id.clone(),
+            owner: subject.owner.clone(),
+            creator: subject.creator.clone(),
+            properties: subject.properties.to_string(),
+            active: subject.active,
+        }
+    }
+}
+
+/// Generations of the indexes.
+#[derive(Debug, Clone, Copy, Default)]
+struct Generations {
+    /// Generation read and written.
+    current: u64,
+    /// Generation being rebuilt, also written.
+    rebuilding: Option<u64>,
+}
+
+/// Rebuild of the indexes into a new generation, see `SubjectIndex::start_rebuild`.
+pub struct Rebuild<'a> {
+    index: &'a SubjectIndex,
+    generation: u64,
+    finished: bool,
+}
+
+impl Rebuild<'_> {
+    /// Write subjects to the new generation only.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The indexes could not be read or written.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<String>` - Identifiers of the subjects written.
+    ///
+    pub fn write(&self, subjects: &[NodeSubjectData]) -> Result<Vec<String>, NodeError> {
+        self.index
+            .write(&self.index.generation(self.generation), subjects)
+    }
+
+    /// Read the new generation from now on, and delete the previous one.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The new generation could not be recorded, and was deleted, or
+    ///   the previous one could not be deleted.
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - Keys deleted with the previous generation.
+    ///
+    pub fn finish(mut self) -> Result<usize, NodeError> {
+        self.finished = true;
+        self.index.end_rebuild(self.generation, true)
+    }
+}
+
+impl Drop for Rebuild<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            match self.index.end_rebuild(self.generation, false) {
+                Ok(deleted) => log::warn!("Subject index rebuild abandoned, {} keys", deleted),
+                Err(error) => log::warn!("Subject index rebuild not deleted: {}", error),
+            }
+        }
+    }
+}
+
+/// Copies of the subjects and their secondary indexes.
+#[derive(Clone)]
+pub struct SubjectIndex {
+    store: NodeStore,
+    generations: Arc<Mutex<Generations>>,
+}
+
+impl SubjectIndex {
+    /// Indexes kept in a collection, `INDEX_COLLECTION` of the node database.
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - Collection of the indexes.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The generation of the indexes could not be read.
+    ///
+    pub fn new(collection: Arc<dyn BatchCollection>) -> Result<Self, NodeError> {
+        let store = NodeStore::new(collection, INDEX_PREFIX);
+        let current = store.get::<u64>(GENERATION_KEY)?.unwrap_or_default();
+        Ok(Self {
+            store,
+            generations: Arc::new(Mutex::new(Generations {
+                current,
+                rebuilding: None,
+            })),
+        })
+    }
+
+    /// Generations of the indexes. They are only read or replaced whole, so a poisoned lock
+    /// still holds a consistent value.
+    fn generations(&self) -> MutexGuard<'_, Generations> {
+        self.generations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Store of a generation of the indexes.
+    fn generation(&self, generation: u64) -> NodeStore {
+        self.store.scope(&generation.to_string())
+    }
+
+    /// Store of the generation that is read.
+    fn current(&self) -> NodeStore {
+        let current = self.generations().current;
+        self.generation(current)
+    }
+
+    /// Store of the copies, subject id to indexed subject.
+    fn subjects(store: &NodeStore) -> NodeStore {
+        store.scope("subject")
+    }
+
+    /// Store of the entries of an index with a value, subject id to nothing.
+    fn entries(store: &NodeStore, index: IndexKey, value: &str) -> NodeStore {
+        store.scope(index.name()).scope(value)
+    }
+
+    /// Write the subjects whose sequence number or active flag changed to a generation, and
+    /// move them between the entries of its indexes, all at once. Copies of a later sequence
+    /// number are kept.
+    fn write(
+        &self,
+        store: &NodeStore,
+        subjects: &[NodeSubjectData],
+    ) -> Result<Vec<String>, NodeError> {
+        let mut batch = StoreBatch::default();
+        let mut updated = vec![];
+        for subject in subjects {
+            let previous = Self::subjects(store).get::<IndexedSubject>(&subject.subject_id)?;
+            if let Some(previous) = &previous {
+                if previous.sn > subject.sn
+                    || (previous.sn == subject.sn && previous.active == subject.active)
+                {
+                    continue;
+                }
+                for index in IndexKey::ALL {
+                    Self::entries(store, index, &previous.key(index))
+                        .batch_del(&mut batch, &subject.subject_id);
+                }
+            }
+            let indexed = IndexedSubject::from(subject);
+            for index in IndexKey::ALL {
+                Self::entries(store, index, &indexed.key(index)).batch_put(
+                    &mut batch,
+                    &subject.subject_id,
+                    &(),
+                )?;
+            }
+            Self::subjects(store).batch_put(&mut batch, &subject.subject_id, &indexed)?;
+            updated.push(subject.subject_id.clone());
+        }
+        if !batch.is_empty() {
+            store.write(batch)?;
+        }
+        Ok(updated)
+    }
+
+    /// Write the subjects whose sequence number or active flag changed, and move them between
+    /// the entries of the indexes, all at once. They are also written to the generation being
+    /// rebuilt, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `subjects` - Subjects as listed by Kore Base.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The indexes could not be read or written.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<String>` - Identifiers of the subjects written.
+    ///
+    pub fn update(&self, subjects: &[NodeSubjectData]) -> Result<Vec<String>, NodeError> {
+        let generations = *self.generations();
+        if let Some(rebuilding) = generations.rebuilding {
+            self.write(&self.generation(rebuilding), subjects)?;
+        }
+        self.write(&self.generation(generations.current), subjects)
+    }
+
+    /// Delete the copy of a subject Kore Base no longer holds, and its entries.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject identifier.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The indexes could not be read or written.
+    ///
+    pub fn remove(&self, subject_id: &str) -> Result<(), NodeError> {
+        let generations = *self.generations();
+        for generation in [Some(generations.current), generations.rebuilding]
+            .into_iter()
+            .flatten()
+        {
+            let store = self.generation(generation);
+            let Some(previous) = Self::subjects(&store).get::<IndexedSubject>(subject_id)? else {
+                continue;
+            };
+            let mut batch = StoreBatch::default();
+            for index in IndexKey::ALL {
+                Self::entries(&store, index, &previous.key(index))
+                    .batch_del(&mut batch, subject_id);
+            }
+            Self::subjects(&store).batch_del(&mut batch, subject_id);
+            store.write(batch)?;
+        }
+        Ok(())
+    }
+
+    /// Start a rebuild of the indexes into a new generation, empty.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Conflict` - A rebuild is already running.
+    /// * `NodeError::Database` - The leftovers of a failed rebuild could not be deleted.
+    ///
+    /// # Returns
+    ///
+    /// * `Rebuild` - Rebuild, abandoned if dropped before it is finished.
+    ///
+    pub fn start_rebuild(&self) -> Result<Rebuild<'_>, NodeError> {
+        let rebuilding = {
+            let mut generations = self.generations();
+            if generations.rebuilding.is_some() {
+                return Err(NodeError::Conflict(
+                    "subject indexes being rebuilt".to_owned(),
+                ));
+            }
+            let rebuilding = generations.current + 1;
+            generations.rebuilding = Some(rebuilding);
+            rebuilding
+        };
+        let rebuild = Rebuild {
+            index: self,
+            generation: rebuilding,
+            finished: false,
+        };
+        self.generation(rebuilding).clear()?;
+        Ok(rebuild)
+    }
+
+    /// End the rebuild of a generation: read it when `complete` and it could be recorded as
+    /// the current one, and delete the generation left behind.
+    fn end_rebuild(&self, rebuilt: u64, complete: bool) -> Result<usize, NodeError> {
+        let mut recorded = Ok(());
+        let left = {
+            let mut generations = self.generations();
+            generations.rebuilding = None;
+            if complete {
+                recorded = self.store.put(GENERATION_KEY, &rebuilt);
+            }
+            if recorded.is_ok() && complete {
+                std::mem::replace(&mut generations.current, rebuilt)
+            } else {
+                rebuilt
+            }
+        };
+        let deleted = self.generation(left).clear()?;
+        recorded.map(|_| deleted)
+    }
+
+    /// Get the copy of a subject, `None` if it is not indexed.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject identifier.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The copy could not be read.
+    ///
+    pub fn get(&self, subject_id: &str) -> Result<Option<NodeSubjectData>, NodeError> {
+        Ok(Self::subjects(&self.current())
+            .get::<IndexedSubject>(subject_id)?
+            .map(|subject| subject.subject(subject_id.to_owned())))
+    }
+
+    /// Copies of every subject, ordered by subject id.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The copies could not be read.
+    ///
+    pub fn all(&self) -> Result<Vec<NodeSubjectData>, NodeError> {
+        Ok(Self::subjects(&self.current())
+            .entries::<IndexedSubject>()?
+            .into_iter()
+            .map(|(subject_id, subject)| subject.subject(subject_id))
+            .collect())
+    }
+
+    /// Identifiers of the subjects found in every index with its value.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - Indexes to read, with the value of the subjects.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The indexes could not be read.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<BTreeSet<String>>` - Subject ids in order, `None` when no index is read.
+    ///
+    pub fn lookup(
+        &self,
+        keys: &[(IndexKey, String)],
+    ) -> Result<Option<BTreeSet<String>>, NodeError> {
+        let store = self.current();
+        let mut found: Option<BTreeSet<String>> = None;
+        for (index, value) in keys {
+            let entries = Self::entries(&store, *index, value)
+                .entries::<()>()?
+                .into_iter()
+                .map(|(subject_id, _)| subject_id);
+            found = Some(match found {
+                Some(found) => entries.filter(|id| found.contains(id)).collect(),
+                None => entries.collect(),
+            });
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+
+    use kore_base::{DatabaseCollection, DatabaseManager};
+    use serde_json::json;
+
+    use super::*;
+    use crate::database::sqlite::SqliteManager;
+
+    fn subject(id: &str, owner: &str, namespace: &str, sn: u64) -> NodeSubjectData {
+        NodeSubjectData {
+            subject_id: id.to_owned(),
+            governance_id: "JGovernance".to_owned(),
+            sn,
+            public_key: "EKey".to_owned(),
+            namespace: namespace.to_owned(),
+            name: format!("Bottle {}", id),
+            schema_id: "wine".to_owned(),
+            owner: owner.to_owned(),
+            creator: owner.to_owned(),
+            properties: json!({ "grape": "malbec" }),
+            active: true,
+            archived: false,
+        }
+    }
+
+    #[test]
+    fn test_subject_index() {
+        let collection = Arc::new(SqliteManager::default().create_collection(INDEX_COLLECTION));
+        let index = SubjectIndex::new(collection.clone()).unwrap();
+        let keys = || collection.iter(false, "").count();
+        let subjects = vec![
+            subject("J1", "EAlice", "wines", 0),
+            subject("J2", "EBob", "wines", 2),
+            subject("J3", "EAlice", "cheeses", 1),
+        ];
+        assert_eq!(index.update(&subjects).unwrap(), ["J1", "J2", "J3"]);
+        assert!(index.update(&subjects).unwrap().is_empty());
+        // A copy and five entries per subject.
+        assert_eq!(keys(), 18);
+
+        let ids = |keys: &[(IndexKey, &str)]| {
+            let keys = keys
+                .iter()
+                .map(|(index, value)| (*index, value.to_string()))
+                .collect::<Vec<_>>();
+            index
+                .lookup(&keys)
+                .unwrap()
+                .map(|ids| ids.into_iter().collect::<Vec<_>>())
+        };
+        assert_eq!(ids(&[]), None);
+        assert_eq!(
+            ids(&[(IndexKey::Owner, "EAlice")]),
+            Some(vec!["J1".to_owned(), "J3".to_owned()])
+        );
+        assert_eq!(
+            ids(&[(IndexKey::Owner, "EAlice"), (IndexKey::Namespace, "wines")]),
+            Some(vec!["J1".to_owned()])
+        );
+        assert_eq!(
+            ids(&[(IndexKey::Governance, "JGovernance")]).map(|ids| ids.len()),
+            Some(3)
+        );
+
+        // An event moves the subject to the entries of its new owner.
+        assert_eq!(
+            index.update(&[subject("J1", "EBob", "wines", 1)]).unwrap(),
+            ["J1"]
+        );
+        assert_eq!(
+            ids(&[(IndexKey::Owner, "EBob")]),
+            Some(vec!["J1".to_owned(), "J2".to_owned()])
+        );
+        assert_eq!(index.get("J1").unwrap().unwrap().owner, "EBob");
+        assert_eq!(keys(), 18);
+
+        // A stale copy does not overwrite a later one.
+        assert!(index
+            .update(&[subject("J1", "EAlice", "wines", 0)])
+            .unwrap()
+            .is_empty());
+        index.remove("J3").unwrap();
+        assert!(index.get("J3").unwrap().is_none());
+        assert_eq!(ids(&[(IndexKey::Owner, "EAlice")]), Some(vec![]));
+        assert_eq!(keys(), 12);
+    }
+
+    #[test]
+    fn test_rebuild_subject_index() {
+        let collection = Arc::new(SqliteManager::default().create_collection(INDEX_COLLECTION));
+        let index = SubjectIndex::new(collection.clone()).unwrap();
+        index
+            .update(&[
+                subject("J1", "EAlice", "wines", 0),
+                subject("J2", "EBob", "wines", 0),
+            ])
+            .unwrap();
+
+        let rebuild = index.start_rebuild().unwrap();
+        assert!(matches!(index.start_rebuild(), Err(NodeError::Conflict(_))));
+        assert_eq!(
+            rebuild
+                .write(&[subject("J1", "EAlice", "wines", 0)])
+                .unwrap(),
+            ["J1"]
+        );
+        // Written meanwhile, to both generations.
+        index
+            .update(&[subject("J2", "EAlice", "wines", 1)])
+            .unwrap();
+        // The previous generation is read until the rebuild is finished.
+        assert_eq!(index.all().unwrap().len(), 2);
+        assert_eq!(rebuild.finish().unwrap(), 12);
+        // The generation, and a copy and five entries per subject.
+        assert_eq!(collection.iter(false, "").count(), 13);
+        assert_eq!(index.get("J2").unwrap().unwrap().owner, "EAlice");
+        assert_eq!(index.all().unwrap().len(), 2);
+
+        // A new handle reads the same generation.
+        let reopened = SubjectIndex::new(collection.clone()).unwrap();
+        assert_eq!(reopened.all().unwrap().len(), 2);
+
+        // A rebuild dropped before it is finished is deleted.
+        let rebuild = index.start_rebuild().unwrap();
+        rebuild
+            .write(&[subject("J1", "EAlice", "wines", 0)])
+            .unwrap();
+        drop(rebuild);
+        assert_eq!(collection.iter(false, "").count(), 13);
+        assert_eq!(index.all().unwrap().len(), 2);
+        assert!(index.start_rebuild().is_ok());
+    }
+}
@@ -0,0 +1,402 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Ledger collections.
+//!
+//! Wraps the database manager handed to Kore Base, so that the collections of the ledger keep
+//! answering for the data the node moved elsewhere.
+//!
+//! The events moved to the archive by the [archival](../../archival/index.html) are read
+//! through: `get` returns the archived event of a key with a marker, and `iter` merges the
+//! markers into the entries of the collection, in the order of their keys, fetching each event
+//! from the archive as the iterator reaches it. An entry still in the collection wins over its
+//! marker. Archived events that cannot be fetched are an error of `get`, and are skipped with a
+//! warning by `iter`.
+//!
+//! The subjects Kore Base writes an event or the subject itself to are recorded in
+//! `LedgerCommits`, for the tasks that follow the ledger, e.g. the secondary indexes.
+//!
+//! Kore Base only keeps the validation proof of the last event of each subject, in its
+//! `signature` collection, so `LedgerProofs` records each proof in the node store as Kore Base
+//! writes it, before the next event replaces it. The proofs held by the collection when it is
+//! created are recorded too, so those written before the node recorded them are not lost.
+//!
+//! Kore Base writes one key at a time, so the writes of concurrent callers to a collection are
+//! applied together in one batch, see `GroupCommit`.
+//!
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashSet},
+    iter::Peekable,
+    marker::PhantomData,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use kore_base::{
+    signature::Signature, DatabaseCollection, DatabaseManager, DbError, ValidationProof,
+};
+use tokio::sync::Notify;
+
+use crate::{
+    archival::{ArchiveMarker, ArchiveReader, EVENT_COLLECTION, SEPARATOR},
+    database::{
+        batch::{BatchCollection, GroupCommit},
+        store::NodeStore,
+    },
+    error::NodeError,
+    model::NodeProof,
+    tasks::Lease,
+};
+
+/// Collection of the subjects in Kore Base, also the first part of their keys.
+pub(crate) const SUBJECT_COLLECTION: &str = "subject";
+
+/// Collection of Kore Base holding the signatures and the validation proof of the last event
+/// of each subject, also the first part of their keys.
+pub(crate) const SIGNATURE_COLLECTION: &str = "signature";
+
+/// Scope of the node store holding the validation proofs.
+pub(crate) const PROOFS_SCOPE: &str = "proofs";
+
+/// Key of the validation proof of an event, sorted by sequence number.
+pub(crate) fn proof_key(sn: u64) -> String {
+    format!("{:020}", sn)
+}
+
+/// Subjects written by Kore Base, shared by the collections and the tasks that follow them.
+#[derive(Clone, Default)]
+pub struct LedgerCommits {
+    subjects: Arc<Mutex<BTreeSet<String>>>,
+    notify: Arc<Notify>,
+}
+
+impl LedgerCommits {
+    /// Record the subject of a key of the events or the subjects, `<collection>:<subject id>...`.
+    fn record(&self, key: &str) {
+        let Some(subject_id) = key.split(SEPARATOR).nth(1).filter(|id| !id.is_empty()) else {
+            return;
+        };
+        // A set of identifiers is never left half written.
+        self.subjects
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(subject_id.to_owned());
+        self.notify.notify_one();
+    }
+
+    /// Take the subjects written since the last call, waiting for one if there is none.
+    pub async fn changed(&self) -> BTreeSet<String> {
+        loop {
+            let subjects =
+                std::mem::take(&mut *self.subjects.lock().unwrap_or_else(PoisonError::into_inner));
+            if !subjects.is_empty() {
+                return subjects;
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+/// Validation proofs of the events, recorded as Kore Base writes them, see the module
+/// documentation.
+#[derive(Clone)]
+pub struct LedgerProofs {
+    store: NodeStore,
+}
+
+impl LedgerProofs {
+    /// Proofs recorded in `store`, the `PROOFS_SCOPE` of the node store.
+    pub fn new(store: NodeStore) -> Self {
+        Self { store }
+    }
+
+    /// Store of the proofs of a subject, sequence number to proof.
+    pub fn subject(&self, subject_id: &str) -> NodeStore {
+        self.store.scope(subject_id)
+    }
+
+    /// Record the proof Kore Base writes for a subject: its sequence number, the signatures of
+    /// the validators and the proof, Borsh encoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Subject id.
+    /// * `data` - Value written by Kore Base.
+    /// * `overwrite` - Whether a proof already recorded for the event is written again.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InternalApi` - The value is not a validation proof.
+    /// * `NodeError::Database` - The proof could not be read or written.
+    ///
+    fn record(&self, subject_id: &str, data: &[u8], overwrite: bool) -> Result<(), NodeError> {
+        let (_, signatures, proof) =
+            borsh::from_slice::<(u64, HashSet<Signature>, ValidationProof)>(data).map_err(
+                |error| {
+                    NodeError::InternalApi(format!(
+                        "Validation proof of {} not decoded: {}",
+                        subject_id, error
+                    ))
+                },
+            )?;
+        let proof = NodeProof::from((signatures, proof));
+        let store = self.subject(subject_id);
+        let key = proof_key(proof.proof.sn);
+        if !overwrite && store.get::<NodeProof>(&key)?.is_some() {
+            return Ok(());
+        }
+        store.put(&key, &proof)
+    }
+
+    /// Record the proofs of `collection`, the signatures of Kore Base, not recorded yet.
+    fn backfill(&self, collection: &impl DatabaseCollection) -> usize {
+        let prefix = format!("{}{}", SIGNATURE_COLLECTION, SEPARATOR);
+        let mut recorded = 0;
+        for (subject_id, data) in collection.iter(false, &prefix) {
+            match self.record(&subject_id, &data, false) {
+                Ok(()) => recorded += 1,
+                Err(error) => log::warn!("Validation proof not recorded: {}", error),
+            }
+        }
+        recorded
+    }
+}
+
+/// Manager of the collections of Kore Base.
+pub struct LedgerManager<M, C> {
+    manager: M,
+    archive: Option<ArchiveReader>,
+    commits: Option<LedgerCommits>,
+    proofs: Option<LedgerProofs>,
+    lease: Option<Lease>,
+    collection: PhantomData<fn() -> C>,
+}
+
+impl<M, C> LedgerManager<M, C> {
+    /// Collections of `manager`, reading the archived events through `archive` if any.
+    pub fn new(manager: M, archive: Option<ArchiveReader>) -> Self {
+        Self {
+            manager,
+            archive,
+            commits: None,
+            proofs: None,
+            lease: None,
+            collection: PhantomData,
+        }
+    }
+
+    /// Record the subjects written to the events and the subjects in `commits`.
+    pub fn with_commits(mut self, commits: LedgerCommits) -> Self {
+        self.commits = Some(commits);
+        self
+    }
+
+    /// Record the validation proofs written to the signatures in `proofs`.
+    pub fn with_proofs(mut self, proofs: LedgerProofs) -> Self {
+        self.proofs = Some(proofs);
+        self
+    }
+
+    /// Hold `lease` in the manager and in each collection, so that the node knows when Kore
+    /// Base drops them, see `NodeTasks::stopped`.
+    pub(crate) fn with_lease(mut self, lease: Lease) -> Self {
+        self.lease = Some(lease);
+        self
+    }
+}
+
+impl<M: DatabaseManager<C>, C: BatchCollection> DatabaseManager<LedgerCollection<C>>
+    for LedgerManager<M, C>
+{
+    fn default() -> Self {
+        Self::new(M::default(), None)
+    }
+
+    fn create_collection(&self, identifier: &str) -> LedgerCollection<C> {
+        let collection = self.manager.create_collection(identifier);
+        let proofs = self
+            .proofs
+            .clone()
+            .filter(|_| identifier == SIGNATURE_COLLECTION);
+        if let Some(proofs) = &proofs {
+            let recorded = proofs.backfill(&collection);
+            log::debug!("Validation proofs of {} subjects checked", recorded);
+        }
+        LedgerCollection {
+            collection,
+            archive: self
+                .archive
+                .clone()
+                .filter(|_| identifier == EVENT_COLLECTION),
+            commits: self
+                .commits
+                .clone()
+                .filter(|_| [EVENT_COLLECTION, SUBJECT_COLLECTION].contains(&identifier)),
+            proofs,
+            group: GroupCommit::default(),
+            _lease: self.lease.clone(),
+        }
+    }
+}
+
+/// Collection of Kore Base.
+pub struct LedgerCollection<C> {
+    collection: C,
+    /// Archived events, only for the collection of the events.
+    archive: Option<ArchiveReader>,
+    /// Subjects written, only for the collections of the events and the subjects.
+    commits: Option<LedgerCommits>,
+    /// Validation proofs, only for the collection of the signatures.
+    proofs: Option<LedgerProofs>,
+    /// Writes of the concurrent callers, applied together.
+    group: GroupCommit,
+    _lease: Option<Lease>,
+}
+
+impl<C: BatchCollection> DatabaseCollection for LedgerCollection<C> {
+    fn get(&self, key: &str) -> Result<Vec<u8>, DbError> {
+        let error = match self.collection.get(key) {
+            Err(DbError::EntryNotFound) => DbError::EntryNotFound,
+            result => return result,
+        };
+        let Some(archive) = &self.archive else {
+            return Err(error);
+        };
+        match archive.marker(key) {
+            Ok(Some(marker)) => archive
+                .read(&marker)
+                .map_err(|error| DbError::CustomError(error.to_string())),
+            Ok(None) => Err(error),
+            Err(error) => Err(DbError::CustomError(error.to_string())),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), DbError> {
+        self.group
+            .write(&self.collection, (key.to_owned(), Some(data.to_vec())))?;
+        if let Some(commits) = &self.commits {
+            commits.record(key);
+        }
+        if let Some(proofs) = &self.proofs {
+            // The write of Kore Base stands even if its proof is not recorded.
+            let subject_id = key.split(SEPARATOR).nth(1).unwrap_or_default();
+            if let Err(error) = proofs.record(subject_id, data, true) {
+                log::warn!("Validation proof not recorded: {}", error);
+            }
+        }
+        Ok(())
+    }
+
+    fn del(&self, key: &str) -> Result<(), DbError> {
+        self.group.write(&self.collection, (key.to_owned(), None))?;
+        if let Some(archive) = &self.archive {
+            archive
+                .unmark(key)
+                .map_err(|error| DbError::CustomError(error.to_string()))?;
+        }
+        if let Some(commits) = &self.commits {
+            commits.record(key);
+        }
+        Ok(())
+    }
+
+    fn iter<'a>(
+        &'a self,
+        reverse: bool,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a> {
+        let local = self.collection.iter(reverse, prefix);
+        let Some(archive) = &self.archive else {
+            return local;
+        };
+        let skipped = prefix.len();
+        let archived: Box<dyn Iterator<Item = (String, ArchiveMarker)> + 'a> = Box::new(
+            archive
+                .markers(prefix, reverse)
+                .map(move |(key, marker)| (key[skipped..].to_owned(), marker)),
+        );
+        Box::new(ArchivedIter {
+            local: local.peekable(),
+            archived: archived.peekable(),
+            archive,
+            reverse,
+        })
+    }
+}
+
+/// Entries of a collection merged with its archived events.
+struct ArchivedIter<'a> {
+    local: Peekable<Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a>>,
+    archived: Peekable<Box<dyn Iterator<Item = (String, ArchiveMarker)> + 'a>>,
+    archive: &'a ArchiveReader,
+    reverse: bool,
+}
+
+impl Iterator for ArchivedIter<'_> {
+    type Item = (String, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let order = match (self.local.peek(), self.archived.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((local, _)), Some((archived, _))) if self.reverse => archived.cmp(local),
+                (Some((local, _)), Some((archived, _))) => local.cmp(archived),
+            };
+            match order {
+                Ordering::Less => return self.local.next(),
+                Ordering::Equal => {
+                    self.archived.next();
+                    return self.local.next();
+                }
+                Ordering::Greater => {}
+            }
+            let (key, marker) = self.archived.next()?;
+            match self.archive.read(&marker) {
+                Ok(value) => return Some((key, value)),
+                Err(error) => log::warn!("Archived event skipped: {}", error),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+
+    use super::*;
+    use crate::database::sqlite::SqliteManager;
+
+    #[tokio::test]
+    async fn test_ledger_commits() {
+        let commits = LedgerCommits::default();
+        let manager =
+            LedgerManager::new(SqliteManager::default(), None).with_commits(commits.clone());
+        let key = |collection: &str, subject_id: &str| {
+            format!("{}{}{}{}0", collection, SEPARATOR, subject_id, SEPARATOR)
+        };
+        let events = manager.create_collection(EVENT_COLLECTION);
+        events.put(&key(EVENT_COLLECTION, "J1"), b"event").unwrap();
+        events.put(&key(EVENT_COLLECTION, "J1"), b"event").unwrap();
+        manager
+            .create_collection(SUBJECT_COLLECTION)
+            .put(
+                &format!("{}{}J2", SUBJECT_COLLECTION, SEPARATOR),
+                b"subject",
+            )
+            .unwrap();
+        // Other collections are not followed.
+        manager
+            .create_collection("signature")
+            .put(&key("signature", "J3"), b"signature")
+            .unwrap();
+        assert_eq!(
+            commits.changed().await,
+            BTreeSet::from(["J1".to_owned(), "J2".to_owned()])
+        );
+
+        events.del(&key(EVENT_COLLECTION, "J1")).unwrap();
+        assert_eq!(commits.changed().await, BTreeSet::from(["J1".to_owned()]));
+    }
+}
@@ -5,21 +5,38 @@
 //! LevelDB implementation for Kore Ledger.
 //!
 //! LevelDB is a key-value storage library developed by Google, which provides ordered mapping
-//! from string keys to string values.
+//! from string keys to string values. IO errors of LevelDB are retryable.
+//!
+//! Batches are applied with a `WriteBatch`, synced like single writes when `kore.db_batch.sync`
+//! is set.
+//!
+//! Collections share the key space of the database, so its size report groups the keys by
+//! prefix: the text before their first `char::MAX` separator.
 
 use db_key;
 use leveldb::options::Options as LevelDBOptions;
 use leveldb::{
+    batch::{Batch, Writebatch},
+    compaction::Compaction,
     database::Database,
     iterator::{Iterable, Iterator as LevelIterator, LevelDBIterator, RevIterator},
     kv::KV,
+    snapshots::Snapshots,
 };
 use std::cell::Cell;
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::sync::Arc;
 
 use kore_base::{DatabaseCollection, DatabaseManager, DbError as Error};
 
+use super::{
+    batch::{chunks, BatchCollection, BatchWrite},
+    prefix::prefix_end,
+    retry::{retryable, with_retries},
+};
+use crate::{error::NodeError, model::NodeCollectionStats, settings::DbBatchSettings};
+
 /// String key type for LevelDB.
 #[derive(Debug, PartialEq, Eq)]
 pub struct StringKey(pub String);
@@ -67,17 +84,83 @@ pub fn open_db(path: &Path) -> Arc<Database<StringKey>> {
     }
 }
 
+/// Open the database in `path`, failing instead of panicking, e.g. when a node holds its lock.
+pub fn try_open_db(path: &Path) -> Result<Arc<Database<StringKey>>, NodeError> {
+    Database::<StringKey>::open(path, get_initial_options())
+        .map(Arc::new)
+        .map_err(|error| {
+            NodeError::database(format!(
+                "Error opening database {}: {}",
+                path.display(),
+                error
+            ))
+        })
+}
+
+/// Copy the database to a new one in `target`.
+/// The copy is read from a snapshot, so writes made while it runs are left out.
+pub fn snapshot(db: &Database<StringKey>, target: &Path) -> Result<(), NodeError> {
+    let copy = Database::<StringKey>::open(target, get_initial_options())
+        .map_err(|error| NodeError::database(format!("Error creating the copy: {}", error)))?;
+    let snapshot = db.snapshot();
+    for (key, value) in snapshot.iter(leveldb::options::ReadOptions::new()) {
+        copy.put(leveldb::options::WriteOptions::new(), key, &value)
+            .map_err(|error| NodeError::database(format!("Error copying data: {}", error)))?;
+    }
+    Ok(())
+}
+
+/// Keys and bytes of the keys and values under each prefix, sorted by prefix.
+/// They are read from a snapshot, like the backups.
+pub fn stats(db: &Database<StringKey>) -> Vec<NodeCollectionStats> {
+    let mut collections = BTreeMap::<String, NodeCollectionStats>::new();
+    let snapshot = db.snapshot();
+    for (key, value) in snapshot.iter(leveldb::options::ReadOptions::new()) {
+        let name = key.0.split(char::MAX).next().unwrap_or_default();
+        let collection =
+            collections
+                .entry(name.to_owned())
+                .or_insert_with(|| NodeCollectionStats {
+                    name: name.to_owned(),
+                    keys: 0,
+                    size: 0,
+                });
+        collection.keys += 1;
+        collection.size += (key.0.len() + value.len()) as u64;
+    }
+    collections.into_values().collect()
+}
+
+/// Compact the key space, discarding deleted and overwritten values.
+/// The range ends at four `char::MAX`, above the keys written by the node.
+pub fn compact(db: &Database<StringKey>) {
+    db.compact(
+        &StringKey(String::new()),
+        &StringKey(char::MAX.to_string().repeat(4)),
+    );
+}
+
 pub struct SyncCell<T>(Cell<T>);
 unsafe impl<T> Sync for SyncCell<T> {}
 
 pub struct LeveldbManager {
     db: Arc<Database<StringKey>>,
+    batch: DbBatchSettings,
 }
 
 #[allow(dead_code)]
 impl LeveldbManager {
     pub fn new(db: Arc<Database<StringKey>>) -> Self {
-        Self { db }
+        Self {
+            db,
+            batch: DbBatchSettings::default(),
+        }
+    }
+
+    /// Apply the batch settings to the collections created from now on.
+    pub fn with_batch(mut self, batch: DbBatchSettings) -> Self {
+        self.batch = batch;
+        self
     }
 }
 
@@ -85,14 +168,17 @@ impl DatabaseManager<LeveldbCollection> for LeveldbManager {
     fn default() -> Self {
         let temp_dir = tempfile::tempdir().unwrap();
         let db = open_db(temp_dir.path());
-        Self { db }
+        Self::new(db)
     }
 
     fn create_collection(&self, _identifier: &str) -> LeveldbCollection {
+        let mut write_options = leveldb::options::WriteOptions::new();
+        write_options.sync = self.batch.sync;
         LeveldbCollection {
             data: self.db.clone(),
             read_options: SyncCell(Cell::new(None)),
-            write_options: SyncCell(Cell::new(None)),
+            write_options: SyncCell(Cell::new(Some(write_options))),
+            max_writes: self.batch.max_writes,
         }
     }
 }
@@ -101,6 +187,7 @@ pub struct LeveldbCollection {
     data: Arc<Database<StringKey>>,
     read_options: SyncCell<Option<ReadOptions>>,
     write_options: SyncCell<Option<leveldb::options::WriteOptions>>,
+    max_writes: usize,
 }
 
 impl LeveldbCollection {
@@ -129,29 +216,34 @@ impl LeveldbCollection {
 
 impl DatabaseCollection for LeveldbCollection {
     fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
-        let key = self.generate_key(key);
-        let result = self.data.get(self.get_read_options(), key);
-        match result {
-            Err(_) => Err(Error::EntryNotFound),
-            Ok(data) => match data {
-                Some(value) => Ok(value),
-                None => Err(Error::EntryNotFound),
-            },
-        }
+        with_retries(|| {
+            let key = self.generate_key(key);
+            match self.data.get(self.get_read_options(), key) {
+                Err(error) => Err(db_error("Error getting data", error)),
+                Ok(data) => match data {
+                    Some(value) => Ok(value),
+                    None => Err(Error::EntryNotFound),
+                },
+            }
+        })
     }
 
     fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
-        let key = self.generate_key(key);
-        self.data
-            .put(self.get_write_options(), key, data)
-            .map_err(|error| Error::CustomError(format!("Error putting data: {}", error)))
+        with_retries(|| {
+            let key = self.generate_key(key);
+            self.data
+                .put(self.get_write_options(), key, data)
+                .map_err(|error| db_error("Error putting data", error))
+        })
     }
 
     fn del(&self, key: &str) -> Result<(), Error> {
-        let key = self.generate_key(key);
-        self.data
-            .delete(self.get_write_options(), key)
-            .map_err(|error| Error::CustomError(format!("Error deletting data: {}", error)))
+        with_retries(|| {
+            let key = self.generate_key(key);
+            self.data
+                .delete(self.get_write_options(), key)
+                .map_err(|error| db_error("Error deletting data", error))
+        })
     }
 
     fn iter<'a>(
@@ -160,17 +252,17 @@ impl DatabaseCollection for LeveldbCollection {
         prefix: &str,
     ) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a> {
         if reverse {
-            let iter = self.data.iter(self.get_read_options()).reverse();
-            iter.seek(&StringKey(format!("{}{}{}", prefix, char::MAX, char::MAX)));
-            let mut alt_iter = iter.peekable();
-            let iter = if alt_iter.peek().is_some() {
-                let mut iter = self.data.iter(self.get_read_options()).reverse();
-                iter.seek(&StringKey(format!("{}{}{}", prefix, char::MAX, char::MAX)));
-                iter.advance();
-                iter
-            } else {
-                self.data.iter(self.get_read_options()).reverse()
-            };
+            // A new reverse iterator is on the last key. Otherwise it is moved to the first key
+            // after the prefix, which is skipped, or to the last key when there is none.
+            let mut iter = self.data.iter(self.get_read_options()).reverse();
+            if let Some(end) = prefix_end(prefix) {
+                iter.seek(&StringKey(end));
+                if iter.valid() {
+                    iter.advance();
+                } else {
+                    iter.seek_to_last();
+                }
+            }
             Box::new(RevLeveldbIterator::new(iter, prefix))
         } else {
             Box::new(LeveldbIterator::new(
@@ -181,6 +273,44 @@ impl DatabaseCollection for LeveldbCollection {
     }
 }
 
+impl BatchCollection for LeveldbCollection {
+    fn write_batch(&self, writes: Vec<BatchWrite>) -> Result<(), Error> {
+        for part in chunks(writes, self.max_writes) {
+            with_retries(|| {
+                let mut batch = Writebatch::new();
+                for (key, value) in &part {
+                    match value {
+                        Some(value) => batch.put(self.generate_key(key), value),
+                        None => batch.delete(self.generate_key(key)),
+                    }
+                }
+                self.data
+                    .write(self.get_write_options(), &batch)
+                    .map_err(|error| db_error("Error writing batch", error))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Database error of a failed operation, retryable for IO errors of LevelDB.
+fn db_error(context: &str, error: leveldb::error::Error) -> Error {
+    let message = format!("{}: {}", context, error);
+    if is_io_error(&error.to_string()) {
+        retryable(message)
+    } else {
+        Error::CustomError(message)
+    }
+}
+
+/// Whether a LevelDB error is an IO error, e.g. a file that could not be opened or written.
+fn is_io_error(error: &str) -> bool {
+    error
+        .strip_prefix("LevelDB error: ")
+        .unwrap_or(error)
+        .starts_with("IO error")
+}
+
 pub struct LeveldbIterator<'a> {
     iter: LevelIterator<'a, StringKey>,
     table_name: String,
@@ -200,13 +330,8 @@ impl<'a> Iterator for LeveldbIterator<'a> {
     type Item = (String, Vec<u8>);
     fn next(&mut self) -> Option<(String, Vec<u8>)> {
         let item = self.iter.next()?;
-        let key = {
-            let StringKey(value) = item.0;
-            if !value.starts_with(&self.table_name) {
-                return None;
-            }
-            value.replace(&self.table_name, "")
-        };
+        let StringKey(key) = item.0;
+        let key = key.strip_prefix(&self.table_name)?.to_owned();
         Some((key, item.1))
     }
 }
@@ -229,13 +354,8 @@ impl<'a> Iterator for RevLeveldbIterator<'a> {
     type Item = (String, Vec<u8>);
     fn next(&mut self) -> Option<Self::Item> {
         let item = self.iter.next()?;
-        let key = {
-            let StringKey(value) = item.0;
-            if !value.starts_with(&self.table_name) {
-                return None;
-            }
-            value.replace(&self.table_name, "")
-        };
+        let StringKey(key) = item.0;
+        let key = key.strip_prefix(&self.table_name)?.to_owned();
         Some((key, item.1))
     }
 }
@@ -244,9 +364,70 @@ impl<'a> Iterator for RevLeveldbIterator<'a> {
 mod tests {
 
     use super::*;
+    use crate::database::conformance::{check_prefix_iteration, check_write_batch};
     use kore_base::{test_database_manager_trait, DbError as Error};
 
     test_database_manager_trait! {
         unit_test_leveldb_manager:LeveldbManager:LeveldbCollection
     }
+
+    #[test]
+    fn test_leveldb_prefix_iteration() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db = LeveldbManager::new(open_db(tempdir.path()));
+        let collection = db.create_collection("iteration_example");
+        check_prefix_iteration(&collection);
+    }
+
+    #[test]
+    fn test_leveldb_write_batch() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db = LeveldbManager::new(open_db(tempdir.path())).with_batch(DbBatchSettings {
+            max_writes: 2,
+            sync: false,
+        });
+        let collection = db.create_collection("batch_example");
+        assert!(!collection.get_write_options().sync);
+        check_write_batch(&collection);
+    }
+
+    #[test]
+    fn test_leveldb_stats_and_compact() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db = open_db(tempdir.path());
+        let collection = LeveldbManager::new(db.clone()).create_collection("stats_example");
+        collection.put("node\u{10FFFF}a", b"value").unwrap();
+        collection.put("node\u{10FFFF}b", b"value").unwrap();
+        collection.put("subject", b"v").unwrap();
+
+        assert_eq!(
+            stats(&db),
+            vec![
+                NodeCollectionStats {
+                    name: "node".to_owned(),
+                    keys: 2,
+                    size: 2 * (9 + 5),
+                },
+                NodeCollectionStats {
+                    name: "subject".to_owned(),
+                    keys: 1,
+                    size: 7 + 1,
+                },
+            ]
+        );
+
+        collection.del("subject").unwrap();
+        compact(&db);
+        assert_eq!(stats(&db).len(), 1);
+    }
+
+    #[test]
+    fn test_leveldb_io_error() {
+        assert!(is_io_error(
+            "LevelDB error: IO error: node/LOCK: Resource temporarily unavailable"
+        ));
+        assert!(!is_io_error(
+            "LevelDB error: Corruption: bad block contents"
+        ));
+    }
 }
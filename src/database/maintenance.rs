@@ -0,0 +1,204 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Maintenance.
+//!
+//! Size reports and compaction of the database of the node, behind `KoreApi::db_stats` and
+//! `KoreApi::compact_db`. Long-running nodes accumulate SST files of overwritten values in
+//! LevelDB, and free pages and a growing write-ahead log in SQLite; compaction gives that space
+//! back to the file system. Both operations block, so the API runs them out of the runtime.
+//!
+//! | Backend | Collections | Compaction |
+//! |---|---|---|
+//! | LevelDB | Key prefixes | `compact_range` over the key space |
+//! | Sled | Key prefixes | A flush, sled reclaims the space by itself |
+//! | Redb | Key prefixes | `compact` of the database file, once no read is in progress |
+//! | SQLite | Tables | `VACUUM` and a truncating checkpoint of the write-ahead log |
+//! | PostgreSQL | Tables of the current schema | `VACUUM`, files are not shrunk |
+//!
+
+#[cfg(feature = "leveldb")]
+use std::sync::Arc;
+#[cfg(any(feature = "leveldb", feature = "sled"))]
+use std::{fs, path::Path};
+
+#[cfg(feature = "leveldb")]
+use leveldb::database::Database;
+#[cfg(feature = "sled")]
+use sled::Db;
+
+#[cfg(feature = "leveldb")]
+use super::leveldb::StringKey;
+#[cfg(feature = "postgres")]
+use super::postgres::PostgresManager;
+#[cfg(feature = "redb")]
+use super::redb::RedbDatabase;
+use crate::{error::NodeError, model::NodeCollectionStats};
+
+/// Database of the node, as handled by the maintenance operations.
+#[derive(Clone)]
+pub(crate) enum DbMaintenance {
+    /// Open LevelDB database and its directory.
+    #[cfg(feature = "leveldb")]
+    LevelDB {
+        db: Arc<Database<StringKey>>,
+        path: String,
+    },
+    /// Open sled database and its directory.
+    #[cfg(feature = "sled")]
+    Sled { db: Db, path: String },
+    /// Open redb database and its file.
+    #[cfg(feature = "redb")]
+    Redb { db: RedbDatabase, path: String },
+    /// Path of the SQLite database file.
+    #[cfg(feature = "sqlite")]
+    Sqlite(String),
+    /// Pool of the PostgreSQL database.
+    #[cfg(feature = "postgres")]
+    Postgres(PostgresManager),
+}
+
+impl DbMaintenance {
+    /// Backend name, as in the reports.
+    pub(crate) fn backend(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "leveldb")]
+            DbMaintenance::LevelDB { .. } => "leveldb",
+            #[cfg(feature = "sled")]
+            DbMaintenance::Sled { .. } => "sled",
+            #[cfg(feature = "redb")]
+            DbMaintenance::Redb { .. } => "redb",
+            #[cfg(feature = "sqlite")]
+            DbMaintenance::Sqlite(_) => "sqlite",
+            #[cfg(feature = "postgres")]
+            DbMaintenance::Postgres(_) => "postgres",
+        }
+    }
+
+    /// Collections of the database, sorted by name, and bytes used on disk.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The database could not be read.
+    ///
+    pub(crate) fn stats(&self) -> Result<(Vec<NodeCollectionStats>, u64), NodeError> {
+        match *self {
+            #[cfg(feature = "leveldb")]
+            DbMaintenance::LevelDB { ref db, ref path } => {
+                Ok((super::leveldb::stats(db), dir_size(Path::new(path))))
+            }
+            #[cfg(feature = "sled")]
+            DbMaintenance::Sled { ref db, ref path } => {
+                Ok((super::sled::stats(db)?, dir_size(Path::new(path))))
+            }
+            #[cfg(feature = "redb")]
+            DbMaintenance::Redb { ref db, ref path } => {
+                Ok((super::redb::stats(db)?, super::redb::disk_size(path)))
+            }
+            #[cfg(feature = "sqlite")]
+            DbMaintenance::Sqlite(ref path) => {
+                Ok((super::sqlite::stats(path)?, super::sqlite::disk_size(path)))
+            }
+            #[cfg(feature = "postgres")]
+            DbMaintenance::Postgres(ref manager) => manager.stats(),
+        }
+    }
+
+    /// Bytes used on disk.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The server could not be queried (PostgreSQL).
+    ///
+    pub(crate) fn disk_size(&self) -> Result<u64, NodeError> {
+        match *self {
+            #[cfg(feature = "leveldb")]
+            DbMaintenance::LevelDB { ref path, .. } => Ok(dir_size(Path::new(path))),
+            #[cfg(feature = "sled")]
+            DbMaintenance::Sled { ref path, .. } => Ok(dir_size(Path::new(path))),
+            #[cfg(feature = "redb")]
+            DbMaintenance::Redb { ref path, .. } => Ok(super::redb::disk_size(path)),
+            #[cfg(feature = "sqlite")]
+            DbMaintenance::Sqlite(ref path) => Ok(super::sqlite::disk_size(path)),
+            #[cfg(feature = "postgres")]
+            DbMaintenance::Postgres(ref manager) => manager.disk_size(),
+        }
+    }
+
+    /// Compact the database, see the table of the module.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The compaction failed, retryable when the database was busy.
+    ///
+    pub(crate) fn compact(&self) -> Result<(), NodeError> {
+        match *self {
+            #[cfg(feature = "leveldb")]
+            DbMaintenance::LevelDB { ref db, .. } => {
+                super::leveldb::compact(db);
+                Ok(())
+            }
+            #[cfg(feature = "sled")]
+            DbMaintenance::Sled { ref db, .. } => super::sled::compact(db),
+            #[cfg(feature = "redb")]
+            DbMaintenance::Redb { ref db, .. } => super::redb::compact(db),
+            #[cfg(feature = "sqlite")]
+            DbMaintenance::Sqlite(ref path) => super::sqlite::vacuum(path),
+            #[cfg(feature = "postgres")]
+            DbMaintenance::Postgres(ref manager) => manager.vacuum(),
+        }
+    }
+}
+
+/// Bytes of the files of a directory, those of its subdirectories included.
+#[cfg(any(feature = "leveldb", feature = "sled"))]
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some(if metadata.is_dir() {
+                dir_size(&entry.path())
+            } else {
+                metadata.len()
+            })
+        })
+        .sum()
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+
+    use super::*;
+    use crate::database::sqlite::SqliteManager;
+    use kore_base::{DatabaseCollection, DatabaseManager};
+
+    #[test]
+    fn test_sqlite_maintenance() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("database");
+        let path = path.to_str().unwrap().to_owned();
+        let collection = SqliteManager::new(&path).create_collection("node");
+        for index in 0..50 {
+            collection
+                .put(&format!("key{}", index), &[1; 2048])
+                .unwrap();
+        }
+        let maintenance = DbMaintenance::Sqlite(path);
+        assert_eq!(maintenance.backend(), "sqlite");
+
+        let (collections, disk_size) = maintenance.stats().unwrap();
+        assert_eq!(collections.len(), 1);
+        assert_eq!(collections[0].keys, 50);
+        assert_eq!(disk_size, maintenance.disk_size().unwrap());
+
+        for index in 0..50 {
+            collection.del(&format!("key{}", index)).unwrap();
+        }
+        maintenance.compact().unwrap();
+        assert!(maintenance.disk_size().unwrap() < disk_size);
+    }
+}
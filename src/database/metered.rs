@@ -0,0 +1,205 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Metered database.
+//!
+//! Wraps a database manager so that its collections record the latency of every `get`, `put`,
+//! `del` and write batch in the node metrics, labelled with the collection identifier.
+//!
+
+use std::{marker::PhantomData, time::Instant};
+
+use kore_base::{DatabaseCollection, DatabaseManager, DbError};
+
+use super::batch::{BatchCollection, BatchWrite};
+use crate::metrics::NodeMetrics;
+
+/// Manager whose collections are metered.
+pub struct MeteredManager<M, C> {
+    manager: M,
+    metrics: NodeMetrics,
+    collection: PhantomData<fn() -> C>,
+}
+
+impl<M, C> MeteredManager<M, C> {
+    /// Meter the collections of `manager` in `metrics`.
+    pub fn new(manager: M, metrics: NodeMetrics) -> Self {
+        Self {
+            manager,
+            metrics,
+            collection: PhantomData,
+        }
+    }
+}
+
+impl<M: DatabaseManager<C>, C: DatabaseCollection> DatabaseManager<MeteredCollection<C>>
+    for MeteredManager<M, C>
+{
+    fn default() -> Self {
+        Self::new(M::default(), NodeMetrics::default())
+    }
+
+    fn create_collection(&self, identifier: &str) -> MeteredCollection<C> {
+        MeteredCollection {
+            collection: self.manager.create_collection(identifier),
+            identifier: identifier.to_owned(),
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+/// Collection that records the latency of its operations.
+pub struct MeteredCollection<C> {
+    collection: C,
+    identifier: String,
+    metrics: NodeMetrics,
+}
+
+impl<C> MeteredCollection<C> {
+    fn measure<T>(&self, operation: &str, run: impl FnOnce(&C) -> T) -> T {
+        let start = Instant::now();
+        let result = run(&self.collection);
+        self.metrics
+            .db_operation(&self.identifier, operation, start.elapsed());
+        result
+    }
+}
+
+impl<C: DatabaseCollection> DatabaseCollection for MeteredCollection<C> {
+    fn get(&self, key: &str) -> Result<Vec<u8>, DbError> {
+        self.measure("get", |collection| collection.get(key))
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), DbError> {
+        self.measure("put", |collection| collection.put(key, data))
+    }
+
+    fn del(&self, key: &str) -> Result<(), DbError> {
+        self.measure("del", |collection| collection.del(key))
+    }
+
+    fn iter<'a>(
+        &'a self,
+        reverse: bool,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a> {
+        self.collection.iter(reverse, prefix)
+    }
+}
+
+impl<C: BatchCollection> BatchCollection for MeteredCollection<C> {
+    fn write_batch(&self, writes: Vec<BatchWrite>) -> Result<(), DbError> {
+        self.measure("batch", |collection| collection.write_batch(writes))
+    }
+
+    fn try_iter<'a>(
+        &'a self,
+        reverse: bool,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = Result<(String, Vec<u8>), DbError>> + 'a> {
+        self.collection.try_iter(reverse, prefix)
+    }
+
+    fn try_iter_from<'a>(
+        &'a self,
+        prefix: &str,
+        from: &str,
+    ) -> Box<dyn Iterator<Item = Result<(String, Vec<u8>), DbError>> + 'a> {
+        self.collection.try_iter_from(prefix, from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::{collections::BTreeMap, sync::Mutex};
+
+    use prometheus_client::{encoding::text::encode, registry::Registry};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryCollection(Mutex<BTreeMap<String, Vec<u8>>>);
+
+    impl DatabaseCollection for MemoryCollection {
+        fn get(&self, key: &str) -> Result<Vec<u8>, DbError> {
+            self.0
+                .lock()
+                .unwrap()
+                .get(key)
+                .cloned()
+                .ok_or(DbError::EntryNotFound)
+        }
+
+        fn put(&self, key: &str, data: &[u8]) -> Result<(), DbError> {
+            self.0.lock().unwrap().insert(key.to_owned(), data.to_vec());
+            Ok(())
+        }
+
+        fn del(&self, key: &str) -> Result<(), DbError> {
+            self.0.lock().unwrap().remove(key);
+            Ok(())
+        }
+
+        fn iter<'a>(
+            &'a self,
+            _reverse: bool,
+            _prefix: &str,
+        ) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a> {
+            Box::new(self.0.lock().unwrap().clone().into_iter())
+        }
+    }
+
+    impl BatchCollection for MemoryCollection {
+        fn write_batch(&self, writes: Vec<BatchWrite>) -> Result<(), DbError> {
+            let mut data = self.0.lock().unwrap();
+            for (key, value) in writes {
+                match value {
+                    Some(value) => data.insert(key, value),
+                    None => data.remove(&key),
+                };
+            }
+            Ok(())
+        }
+    }
+
+    struct MemoryManager;
+
+    impl DatabaseManager<MemoryCollection> for MemoryManager {
+        fn default() -> Self {
+            MemoryManager
+        }
+
+        fn create_collection(&self, _identifier: &str) -> MemoryCollection {
+            MemoryCollection::default()
+        }
+    }
+
+    #[test]
+    fn test_metered_collection() {
+        let mut registry = <Registry>::default();
+        let manager = MeteredManager::new(MemoryManager, NodeMetrics::register(&mut registry));
+        let collection = manager.create_collection("subject");
+        collection.put("a", b"1").unwrap();
+        assert_eq!(collection.get("a").unwrap(), b"1");
+        collection.del("a").unwrap();
+        assert!(collection.get("a").is_err());
+        assert_eq!(collection.iter(false, "").count(), 0);
+        collection
+            .write_batch(vec![
+                ("a".to_owned(), Some(b"2".to_vec())),
+                ("b".to_owned(), None),
+            ])
+            .unwrap();
+        assert_eq!(collection.iter(false, "").count(), 1);
+
+        let mut text = String::new();
+        encode(&mut text, &registry).unwrap();
+        for (operation, count) in [("get", 2), ("put", 1), ("del", 1), ("batch", 1)] {
+            assert!(text.contains(&format!(
+                r#"db_operation_duration_seconds_count{{collection="subject",operation="{}"}} {}"#,
+                operation, count
+            )));
+        }
+    }
+}
@@ -10,13 +10,68 @@
 //! The following database implementations are available:
 //!
 //! * [Leveldb](leveldb/index.html)
+//! * [Sled](sled/index.html), in pure Rust
+//! * [Redb](redb/index.html), in a single file
 //! * [Sqlite](sqlite/index.html)
 //! * [Cassandra](cassandra/index.html)
 //!
+//! Data owned by the node is kept in a [store](store/index.html), whose values are encoded with
+//! a [codec](codec/index.html). Entries of a store may expire after a time to live.
+//!
+//! Backend errors are classified as retryable or fatal, and retryable operations are
+//! [retried](retry/index.html) before the error reaches the caller.
+//!
+//! With the `encryption` feature, the values of the collections may be
+//! [encrypted](encrypted/index.html) at rest.
+//!
+//! Collections are [metered](metered/index.html), recording the latency of their operations in
+//! the node metrics.
+//!
+//! Writes of several keys are applied at once in a [batch](batch/index.html), split in parts of
+//! `kore.db_batch.max_writes` writes.
+//!
+//! Each backend reports the keys and size of its collections, and compacts its files, for the
+//! [maintenance](maintenance/index.html) of long-running nodes.
+//!
+//! The collections handed to Kore Base are wrapped by the [ledger](ledger/index.html) manager,
+//! which reads the archived events through and records the subjects and the validation proofs
+//! Kore Base writes.
+//!
+//! The subjects are looked up by namespace, schema, owner, governance and active flag through
+//! [secondary indexes](index/index.html) kept in a collection of their own, updated from the
+//! subjects written.
+//!
+//! Every backend iterates a collection by [prefix](prefix/index.html) with the same semantics,
+//! checked by a conformance test-suite that each of them runs.
+//!
+//! The embedded backends are compared by the [benchmarks](bench/index.html), ignored tests run
+//! on demand.
+//!
 
+pub mod batch;
+#[cfg(test)]
+mod bench;
 #[cfg(feature = "cassandra")]
 pub mod cassandra;
+pub mod codec;
+#[cfg(test)]
+pub(crate) mod conformance;
+#[cfg(feature = "encryption")]
+pub mod encrypted;
+pub mod index;
+pub mod ledger;
 #[cfg(feature = "leveldb")]
 pub mod leveldb;
+pub mod maintenance;
+pub mod metered;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod prefix;
+#[cfg(feature = "redb")]
+pub mod redb;
+pub mod retry;
+#[cfg(feature = "sled")]
+pub mod sled;
 #[cfg(feature = "sqlite")]
 pub mod sqlite;
+pub mod store;
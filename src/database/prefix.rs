@@ -0,0 +1,61 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Prefix iteration.
+//!
+//! Semantics of `DatabaseCollection::iter(reverse, prefix)`, shared by every backend:
+//!
+//! * Entries whose key starts with `prefix` are returned, and no other. An empty prefix returns
+//!   the whole collection.
+//! * Keys are returned without the leading `prefix`; occurrences of it later in the key are kept.
+//! * Entries are ordered by the bytes of their UTF-8 keys, ascending, or descending when `reverse`
+//!   is set. Both directions return the same entries.
+//! * The prefix is matched literally: no character is a wildcard, and the match is case sensitive.
+//!
+//! Since UTF-8 preserves the order of code points, the keys with a prefix are the range from the
+//! prefix itself to `prefix_end`, which backends use to seek or query them.
+//!
+
+/// Smallest key after every key that starts with `prefix`, `None` when there is no such key
+/// (empty prefix, or only `char::MAX` characters).
+#[cfg(any(feature = "leveldb", feature = "redb", feature = "sqlite"))]
+pub(crate) fn prefix_end(prefix: &str) -> Option<String> {
+    let mut chars = prefix.chars().collect::<Vec<_>>();
+    while let Some(last) = chars.pop() {
+        if let Some(next) = next_char(last) {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+/// Next code point that is a `char`, skipping the surrogates.
+#[cfg(any(feature = "leveldb", feature = "redb", feature = "sqlite"))]
+fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        char::MAX => None,
+        c => char::from_u32(c as u32 + 1),
+    }
+}
+
+#[cfg(all(test, any(feature = "leveldb", feature = "sqlite")))]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end(""), None);
+        assert_eq!(prefix_end("ab"), Some("ac".to_owned()));
+        assert_eq!(prefix_end("a\u{D7FF}"), Some("a\u{E000}".to_owned()));
+        assert_eq!(prefix_end("a\u{10FFFF}"), Some("b".to_owned()));
+        assert_eq!(prefix_end("\u{10FFFF}\u{10FFFF}"), None);
+
+        let end = prefix_end("ab").unwrap();
+        for key in ["ab", "abz", "ab\u{10FFFF}\u{10FFFF}"] {
+            assert!(key.as_bytes() < end.as_bytes());
+        }
+    }
+}
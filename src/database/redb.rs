@@ -0,0 +1,419 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Redb
+//! Redb implementation for Kore Ledger.
+//!
+//! Redb is an embedded key-value store written in Rust, kept in a single memory-mapped file.
+//! Its small footprint suits the gateways of the Raspberry class. IO errors of redb are
+//! retryable.
+//!
+//! Like in LevelDB, collections share the key space of the database, a single table, and its
+//! size report groups the keys by prefix: the text before their first `char::MAX` separator.
+//!
+//! Each write, and each part of a batch, is a write transaction. Transactions are committed
+//! with `Immediate` durability when `kore.db_batch.sync` is set, `Eventual` otherwise. Reads
+//! see the snapshot of a read transaction, which the iterators keep open until dropped.
+//!
+//! Compaction needs the database for itself: it waits for the writes in progress, and fails
+//! with a retryable error while a read transaction is open.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    ops::Bound,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use kore_base::{DatabaseCollection, DatabaseManager, DbError as Error};
+use redb::{
+    backends::InMemoryBackend, AccessGuard, Database, Durability, ReadOnlyTable, ReadableTable,
+    ReadableTableMetadata, StorageError, Table, TableDefinition,
+};
+
+use super::{
+    batch::{chunks, BatchCollection, BatchWrite},
+    prefix::prefix_end,
+    retry::{retryable, with_retries},
+};
+use crate::{error::NodeError, model::NodeCollectionStats, settings::DbBatchSettings};
+
+/// Table of the key space.
+const TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("kore");
+
+/// Open database, shared by the collections and locked by the compaction.
+pub type RedbDatabase = Arc<RwLock<Database>>;
+
+/// Open the database of a file, creating it if missing.
+///
+/// # Errors
+///
+/// * `NodeError::Database` - The database could not be opened, e.g. it is used by another
+///   process.
+///
+pub fn open_db(path: &Path) -> Result<RedbDatabase, NodeError> {
+    let db = Database::create(path).map_err(|error| {
+        NodeError::database(format!(
+            "Error opening database {}: {}",
+            path.display(),
+            error
+        ))
+    })?;
+    create_table(db)
+}
+
+/// Create the table of the key space, so that reads always find it.
+fn create_table(db: Database) -> Result<RedbDatabase, NodeError> {
+    let transaction = db.begin_write().map_err(node_error)?;
+    transaction.open_table(TABLE).map_err(node_error)?;
+    transaction.commit().map_err(node_error)?;
+    Ok(Arc::new(RwLock::new(db)))
+}
+
+/// Whether the database of a file has no keys: the file is missing, empty, or holds no keys.
+/// A database that cannot be opened, e.g. one in use, is not empty.
+pub fn is_empty(path: &Path) -> bool {
+    if fs::metadata(path).map_or(true, |metadata| metadata.len() == 0) {
+        return true;
+    }
+    let Ok(db) = Database::open(path) else {
+        return false;
+    };
+    let Ok(transaction) = db.begin_read() else {
+        return false;
+    };
+    match transaction.open_table(TABLE) {
+        Ok(table) => table.is_empty().unwrap_or(false),
+        Err(redb::TableError::TableDoesNotExist(_)) => true,
+        Err(_) => false,
+    }
+}
+
+/// Copy the database to a new one in the file `target`.
+/// The copy is read from a read transaction, so writes made while it runs are left out.
+pub fn snapshot(db: &RedbDatabase, target: &Path) -> Result<(), NodeError> {
+    let source = read_table(db).map_err(NodeError::from)?;
+    let copy = Database::create(target)
+        .map_err(|error| NodeError::database(format!("Error creating the copy: {}", error)))?;
+    let transaction = copy.begin_write().map_err(node_error)?;
+    {
+        let mut table = transaction.open_table(TABLE).map_err(node_error)?;
+        for entry in source.iter().map_err(node_error)? {
+            let (key, value) = entry.map_err(node_error)?;
+            table
+                .insert(key.value(), value.value())
+                .map_err(|error| NodeError::database(format!("Error copying data: {}", error)))?;
+        }
+    }
+    transaction.commit().map_err(node_error)
+}
+
+/// Keys and bytes of the keys and values under each prefix, sorted by prefix.
+/// They are read from a read transaction, like the backups.
+///
+/// # Errors
+///
+/// * `NodeError::Database` - The database could not be read.
+///
+pub fn stats(db: &RedbDatabase) -> Result<Vec<NodeCollectionStats>, NodeError> {
+    let mut collections = BTreeMap::<String, NodeCollectionStats>::new();
+    let table = read_table(db).map_err(NodeError::from)?;
+    for entry in table.iter().map_err(node_error)? {
+        let (key, value) = entry.map_err(node_error)?;
+        let (key, value) = (key.value(), value.value());
+        let name = key.split(char::MAX).next().unwrap_or_default();
+        let collection =
+            collections
+                .entry(name.to_owned())
+                .or_insert_with(|| NodeCollectionStats {
+                    name: name.to_owned(),
+                    keys: 0,
+                    size: 0,
+                });
+        collection.keys += 1;
+        collection.size += (key.len() + value.len()) as u64;
+    }
+    Ok(collections.into_values().collect())
+}
+
+/// Bytes of the database file, 0 when it is missing.
+pub fn disk_size(path: &str) -> u64 {
+    fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+/// Compact the database file, giving the free pages back to the file system.
+///
+/// # Errors
+///
+/// * `NodeError::Database` - The compaction failed, retryable while a read transaction is open.
+///
+pub fn compact(db: &RedbDatabase) -> Result<(), NodeError> {
+    let mut db = db.write().map_err(|_| poisoned())?;
+    match db.compact() {
+        Ok(_) => Ok(()),
+        Err(redb::CompactionError::TransactionInProgress) => Err(NodeError::from(retryable(
+            "Error compacting the database: a transaction is in progress",
+        ))),
+        Err(error) => Err(node_error(error)),
+    }
+}
+
+/// Read the table of the key space from a new read transaction.
+fn read_table(db: &RedbDatabase) -> Result<ReadOnlyTable<&'static str, &'static [u8]>, Error> {
+    let db = db.read().map_err(|_| poisoned())?;
+    let transaction = db
+        .begin_read()
+        .map_err(|error| db_error("Error reading data", error))?;
+    transaction
+        .open_table(TABLE)
+        .map_err(|error| db_error("Error reading data", error))
+}
+
+pub struct RedbManager {
+    db: RedbDatabase,
+    batch: DbBatchSettings,
+}
+
+impl RedbManager {
+    pub fn new(db: RedbDatabase) -> Self {
+        Self {
+            db,
+            batch: DbBatchSettings::default(),
+        }
+    }
+
+    /// Apply the batch settings to the collections created from now on.
+    pub fn with_batch(mut self, batch: DbBatchSettings) -> Self {
+        self.batch = batch;
+        self
+    }
+}
+
+impl DatabaseManager<RedbCollection> for RedbManager {
+    fn default() -> Self {
+        let db = Database::builder()
+            .create_with_backend(InMemoryBackend::new())
+            .unwrap();
+        Self::new(create_table(db).unwrap())
+    }
+
+    fn create_collection(&self, _identifier: &str) -> RedbCollection {
+        RedbCollection {
+            data: self.db.clone(),
+            durability: if self.batch.sync {
+                Durability::Immediate
+            } else {
+                Durability::Eventual
+            },
+            max_writes: self.batch.max_writes,
+        }
+    }
+}
+
+pub struct RedbCollection {
+    data: RedbDatabase,
+    durability: Durability,
+    max_writes: usize,
+}
+
+impl RedbCollection {
+    /// Apply writes to the table in a write transaction.
+    fn write(
+        &self,
+        context: &str,
+        writes: impl Fn(&mut Table<&str, &[u8]>) -> Result<(), StorageError>,
+    ) -> Result<(), Error> {
+        let db = self.data.read().map_err(|_| poisoned())?;
+        let mut transaction = db.begin_write().map_err(|error| db_error(context, error))?;
+        transaction.set_durability(self.durability);
+        {
+            let mut table = transaction
+                .open_table(TABLE)
+                .map_err(|error| db_error(context, error))?;
+            writes(&mut table).map_err(|error| db_error(context, error))?;
+        }
+        transaction
+            .commit()
+            .map_err(|error| db_error(context, error))
+    }
+}
+
+impl DatabaseCollection for RedbCollection {
+    fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        with_retries(|| {
+            let table = read_table(&self.data)?;
+            match table.get(key) {
+                Err(error) => Err(db_error("Error getting data", error)),
+                Ok(Some(value)) => Ok(value.value().to_vec()),
+                Ok(None) => Err(Error::EntryNotFound),
+            }
+        })
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+        with_retries(|| {
+            self.write("Error putting data", |table| {
+                table.insert(key, data).map(|_| ())
+            })
+        })
+    }
+
+    fn del(&self, key: &str) -> Result<(), Error> {
+        with_retries(|| self.write("Error deleting data", |table| table.remove(key).map(|_| ())))
+    }
+
+    fn iter<'a>(
+        &'a self,
+        reverse: bool,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a> {
+        let end = prefix_end(prefix);
+        let bounds = (
+            Bound::Included(prefix),
+            end.as_deref().map_or(Bound::Unbounded, Bound::Excluded),
+        );
+        let range = read_table(&self.data).and_then(|table| {
+            table
+                .range::<&str>(bounds)
+                .map_err(|error| db_error("Error reading data", error))
+        });
+        let Ok(range) = range else {
+            return Box::new(std::iter::empty());
+        };
+        let prefix = prefix.to_owned();
+        // Iteration stops at the first error, as in LevelDB.
+        let entry = move |entry: Result<(AccessGuard<&str>, AccessGuard<&[u8]>), StorageError>| {
+            let (key, value) = entry.ok()?;
+            Some((
+                key.value().strip_prefix(&prefix)?.to_owned(),
+                value.value().to_vec(),
+            ))
+        };
+        if reverse {
+            Box::new(range.rev().map_while(entry))
+        } else {
+            Box::new(range.map_while(entry))
+        }
+    }
+}
+
+impl BatchCollection for RedbCollection {
+    fn write_batch(&self, writes: Vec<BatchWrite>) -> Result<(), Error> {
+        for part in chunks(writes, self.max_writes) {
+            with_retries(|| {
+                self.write("Error writing batch", |table| {
+                    for (key, value) in &part {
+                        match value {
+                            Some(value) => table.insert(key.as_str(), value.as_slice())?,
+                            None => table.remove(key.as_str())?,
+                        };
+                    }
+                    Ok(())
+                })
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Database error of a failed operation, retryable for IO errors of redb.
+fn db_error(context: &str, error: impl Into<redb::Error>) -> Error {
+    let error = error.into();
+    let message = format!("{}: {}", context, error);
+    if matches!(error, redb::Error::Io(_)) {
+        retryable(message)
+    } else {
+        Error::CustomError(message)
+    }
+}
+
+/// Node error of a failed operation on the whole database.
+fn node_error(error: impl Into<redb::Error>) -> NodeError {
+    NodeError::from(db_error("Database error", error))
+}
+
+/// Error of a lock of the database poisoned by a panic.
+fn poisoned() -> Error {
+    Error::CustomError("Database lock poisoned".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::database::conformance::{check_prefix_iteration, check_write_batch};
+    use kore_base::{test_database_manager_trait, DbError as Error};
+
+    test_database_manager_trait! {
+        unit_test_redb_manager:RedbManager:RedbCollection
+    }
+
+    #[test]
+    fn test_redb_prefix_iteration() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db = RedbManager::new(open_db(&tempdir.path().join("database")).unwrap());
+        let collection = db.create_collection("iteration_example");
+        check_prefix_iteration(&collection);
+    }
+
+    #[test]
+    fn test_redb_write_batch() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db = RedbManager::new(open_db(&tempdir.path().join("database")).unwrap()).with_batch(
+            DbBatchSettings {
+                max_writes: 2,
+                sync: false,
+            },
+        );
+        let collection = db.create_collection("batch_example");
+        assert!(matches!(collection.durability, Durability::Eventual));
+        check_write_batch(&collection);
+    }
+
+    #[test]
+    fn test_redb_stats_snapshot_and_compact() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("database");
+        let db = open_db(&path).unwrap();
+        let collection = RedbManager::new(db.clone()).create_collection("stats_example");
+        collection.put("node\u{10FFFF}a", b"value").unwrap();
+        collection.put("node\u{10FFFF}b", b"value").unwrap();
+        collection.put("subject", b"v").unwrap();
+
+        let expected = vec![
+            NodeCollectionStats {
+                name: "node".to_owned(),
+                keys: 2,
+                size: 2 * (9 + 5),
+            },
+            NodeCollectionStats {
+                name: "subject".to_owned(),
+                keys: 1,
+                size: 7 + 1,
+            },
+        ];
+        assert_eq!(stats(&db).unwrap(), expected);
+
+        let copy = tempdir.path().join("copy");
+        assert!(is_empty(&copy));
+        snapshot(&db, &copy).unwrap();
+        assert!(!is_empty(&copy));
+        assert_eq!(stats(&open_db(&copy).unwrap()).unwrap(), expected);
+
+        // An open iterator holds a read transaction.
+        let iter = collection.iter(false, "");
+        assert!(matches!(
+            compact(&db),
+            Err(NodeError::Database {
+                retryable: true,
+                ..
+            })
+        ));
+        drop(iter);
+        collection.del("subject").unwrap();
+        compact(&db).unwrap();
+        assert_eq!(stats(&db).unwrap().len(), 1);
+        assert!(disk_size(path.to_str().unwrap()) > 0);
+    }
+}
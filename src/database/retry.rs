@@ -0,0 +1,109 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Retries of transient database errors.
+//!
+//! Backends classify their errors as retryable (the database is busy or locked, an IO error of
+//! LevelDB, a lost PostgreSQL connection) or fatal. Retryable operations are repeated a bounded
+//! number of times with an increasing delay before the error reaches the caller.
+//!
+//! `DbError` belongs to Kore Base, so the class travels in the message of a
+//! `DbError::CustomError`, marked with `RETRYABLE`. `NodeError::Database` keeps the class as
+//! `retryable`.
+//!
+
+use std::{thread, time::Duration};
+
+use kore_base::DbError;
+
+/// Prefix of the message of retryable errors.
+const RETRYABLE: &str = "retryable: ";
+
+/// Attempts of an operation, including the first one.
+pub const MAX_ATTEMPTS: u32 = 4;
+
+/// Delay before the first retry, doubled on each further retry.
+const BACKOFF: Duration = Duration::from_millis(10);
+
+/// Error that may not happen again if the operation is repeated.
+pub fn retryable(message: impl AsRef<str>) -> DbError {
+    DbError::CustomError(format!("{}{}", RETRYABLE, message.as_ref()))
+}
+
+/// Whether the error was classified as retryable by the backend.
+pub fn is_retryable(error: &DbError) -> bool {
+    matches!(error, DbError::CustomError(message) if message.starts_with(RETRYABLE))
+}
+
+/// Message of the error, without the retryable mark.
+pub fn message(error: &DbError) -> String {
+    match error {
+        DbError::CustomError(message) => message
+            .strip_prefix(RETRYABLE)
+            .unwrap_or(message)
+            .to_owned(),
+        error => error.to_string(),
+    }
+}
+
+/// Run a database operation, repeating it while it fails with a retryable error.
+///
+/// # Arguments
+///
+/// * `operation` - Operation to run, up to `MAX_ATTEMPTS` times.
+///
+/// # Returns
+///
+/// The result of the first attempt that succeeds or fails with a fatal error, or the error of
+/// the last attempt.
+///
+pub fn with_retries<T>(mut operation: impl FnMut() -> Result<T, DbError>) -> Result<T, DbError> {
+    let mut delay = BACKOFF;
+    for _ in 1..MAX_ATTEMPTS {
+        match operation() {
+            Err(error) if is_retryable(&error) => {
+                log::debug!("Retrying database operation: {}", message(&error));
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    operation()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_with_retries() {
+        let mut attempts = 0;
+        let result = with_retries(|| {
+            attempts += 1;
+            if attempts < 3 {
+                Err(retryable("database is locked"))
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut attempts = 0;
+        let result: Result<(), _> = with_retries(|| {
+            attempts += 1;
+            Err(retryable("database is locked"))
+        });
+        assert!(is_retryable(&result.unwrap_err()));
+        assert_eq!(attempts, MAX_ATTEMPTS);
+
+        let mut attempts = 0;
+        let result: Result<(), _> = with_retries(|| {
+            attempts += 1;
+            Err(DbError::CustomError("disk image is malformed".to_owned()))
+        });
+        assert_eq!(message(&result.unwrap_err()), "disk image is malformed");
+        assert_eq!(attempts, 1);
+    }
+}
@@ -0,0 +1,297 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Sled
+//! Sled implementation for Kore Ledger.
+//!
+//! Sled is an embedded key-value store written in Rust, with keys ordered by their bytes like
+//! LevelDB. It needs no C toolchain, so it suits the builds of the node for musl or ARM targets.
+//! IO errors of sled are retryable.
+//!
+//! Like in LevelDB, collections share the key space of the database, the default tree of sled,
+//! and its size report groups the keys by prefix: the text before their first `char::MAX`
+//! separator.
+//!
+//! Batches are applied with a `Batch`. Sled writes to its log in the background, so writes are
+//! flushed to disk before they return when `kore.db_batch.sync` is set.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use kore_base::{DatabaseCollection, DatabaseManager, DbError as Error};
+use sled::{Batch, Db, IVec, Tree};
+
+use super::{
+    batch::{chunks, BatchCollection, BatchWrite},
+    retry::{retryable, with_retries},
+};
+use crate::{error::NodeError, model::NodeCollectionStats, settings::DbBatchSettings};
+
+/// Open the database of a directory, creating it if missing.
+///
+/// # Errors
+///
+/// * `NodeError::Database` - The database could not be opened, e.g. it is used by another
+///   process.
+///
+pub fn open_db(path: &Path) -> Result<Db, NodeError> {
+    sled::open(path).map_err(|error| {
+        NodeError::database(format!(
+            "Error opening database {}: {}",
+            path.display(),
+            error
+        ))
+    })
+}
+
+/// Copy the database to a new one in `target`.
+/// Sled has no snapshots, so writes made while it runs may be copied or not.
+pub fn snapshot(db: &Db, target: &Path) -> Result<(), NodeError> {
+    let copy = sled::open(target)
+        .map_err(|error| NodeError::database(format!("Error creating the copy: {}", error)))?;
+    for entry in db.iter() {
+        let (key, value) =
+            entry.map_err(|error| NodeError::database(format!("Error reading data: {}", error)))?;
+        copy.insert(key, value)
+            .map_err(|error| NodeError::database(format!("Error copying data: {}", error)))?;
+    }
+    copy.flush()
+        .map_err(|error| NodeError::database(format!("Error copying data: {}", error)))?;
+    Ok(())
+}
+
+/// Keys and bytes of the keys and values under each prefix, sorted by prefix.
+///
+/// # Errors
+///
+/// * `NodeError::Database` - The database could not be read.
+///
+pub fn stats(db: &Db) -> Result<Vec<NodeCollectionStats>, NodeError> {
+    let mut collections = BTreeMap::<String, NodeCollectionStats>::new();
+    for entry in db.iter() {
+        let (key, value) =
+            entry.map_err(|error| NodeError::database(format!("Error reading data: {}", error)))?;
+        let key = String::from_utf8_lossy(&key);
+        let name = key.split(char::MAX).next().unwrap_or_default();
+        let collection =
+            collections
+                .entry(name.to_owned())
+                .or_insert_with(|| NodeCollectionStats {
+                    name: name.to_owned(),
+                    keys: 0,
+                    size: 0,
+                });
+        collection.keys += 1;
+        collection.size += (key.len() + value.len()) as u64;
+    }
+    Ok(collections.into_values().collect())
+}
+
+/// Flush the database to disk. Sled reclaims the space of deleted and overwritten values by
+/// itself, as it rewrites its segments, and has no compaction to run on demand.
+///
+/// # Errors
+///
+/// * `NodeError::Database` - The database could not be flushed, retryable on IO errors.
+///
+pub fn compact(db: &Db) -> Result<(), NodeError> {
+    db.flush()
+        .map(|_| ())
+        .map_err(|error| NodeError::from(db_error("Error flushing the database", error)))
+}
+
+pub struct SledManager {
+    db: Db,
+    batch: DbBatchSettings,
+}
+
+impl SledManager {
+    pub fn new(db: Db) -> Self {
+        Self {
+            db,
+            batch: DbBatchSettings::default(),
+        }
+    }
+
+    /// Apply the batch settings to the collections created from now on.
+    pub fn with_batch(mut self, batch: DbBatchSettings) -> Self {
+        self.batch = batch;
+        self
+    }
+}
+
+impl DatabaseManager<SledCollection> for SledManager {
+    fn default() -> Self {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        Self::new(db)
+    }
+
+    fn create_collection(&self, _identifier: &str) -> SledCollection {
+        SledCollection {
+            data: Tree::clone(&self.db),
+            sync: self.batch.sync,
+            max_writes: self.batch.max_writes,
+        }
+    }
+}
+
+pub struct SledCollection {
+    data: Tree,
+    sync: bool,
+    max_writes: usize,
+}
+
+impl SledCollection {
+    /// Flush the writes to disk when the collection syncs them.
+    fn flush(&self) -> Result<(), Error> {
+        if self.sync {
+            self.data
+                .flush()
+                .map_err(|error| db_error("Error flushing data", error))?;
+        }
+        Ok(())
+    }
+}
+
+impl DatabaseCollection for SledCollection {
+    fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        with_retries(|| match self.data.get(key) {
+            Err(error) => Err(db_error("Error getting data", error)),
+            Ok(Some(value)) => Ok(value.to_vec()),
+            Ok(None) => Err(Error::EntryNotFound),
+        })
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+        with_retries(|| {
+            self.data
+                .insert(key, data)
+                .map_err(|error| db_error("Error putting data", error))?;
+            self.flush()
+        })
+    }
+
+    fn del(&self, key: &str) -> Result<(), Error> {
+        with_retries(|| {
+            self.data
+                .remove(key)
+                .map_err(|error| db_error("Error deleting data", error))?;
+            self.flush()
+        })
+    }
+
+    fn iter<'a>(
+        &'a self,
+        reverse: bool,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a> {
+        let iter = self.data.scan_prefix(prefix);
+        let prefix = prefix.to_owned();
+        // Iteration stops at the first error, as in LevelDB.
+        let entry = move |entry: sled::Result<(IVec, IVec)>| {
+            let (key, value) = entry.ok()?;
+            let key = String::from_utf8(key.to_vec()).ok()?;
+            Some((key.strip_prefix(&prefix)?.to_owned(), value.to_vec()))
+        };
+        if reverse {
+            Box::new(iter.rev().map_while(entry))
+        } else {
+            Box::new(iter.map_while(entry))
+        }
+    }
+}
+
+impl BatchCollection for SledCollection {
+    fn write_batch(&self, writes: Vec<BatchWrite>) -> Result<(), Error> {
+        for part in chunks(writes, self.max_writes) {
+            with_retries(|| {
+                let mut batch = Batch::default();
+                for (key, value) in &part {
+                    match value {
+                        Some(value) => batch.insert(key.as_str(), value.as_slice()),
+                        None => batch.remove(key.as_str()),
+                    }
+                }
+                self.data
+                    .apply_batch(batch)
+                    .map_err(|error| db_error("Error writing batch", error))?;
+                self.flush()
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Database error of a failed operation, retryable for IO errors of sled.
+fn db_error(context: &str, error: sled::Error) -> Error {
+    let message = format!("{}: {}", context, error);
+    if matches!(error, sled::Error::Io(_)) {
+        retryable(message)
+    } else {
+        Error::CustomError(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::database::conformance::{check_prefix_iteration, check_write_batch};
+    use kore_base::{test_database_manager_trait, DbError as Error};
+
+    test_database_manager_trait! {
+        unit_test_sled_manager:SledManager:SledCollection
+    }
+
+    #[test]
+    fn test_sled_prefix_iteration() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db = SledManager::new(open_db(tempdir.path()).unwrap());
+        let collection = db.create_collection("iteration_example");
+        check_prefix_iteration(&collection);
+    }
+
+    #[test]
+    fn test_sled_write_batch() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db = SledManager::new(open_db(tempdir.path()).unwrap()).with_batch(DbBatchSettings {
+            max_writes: 2,
+            sync: false,
+        });
+        let collection = db.create_collection("batch_example");
+        assert!(!collection.sync);
+        check_write_batch(&collection);
+    }
+
+    #[test]
+    fn test_sled_stats_and_snapshot() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db = open_db(&tempdir.path().join("db")).unwrap();
+        let collection = SledManager::new(db.clone()).create_collection("stats_example");
+        collection.put("node\u{10FFFF}a", b"value").unwrap();
+        collection.put("node\u{10FFFF}b", b"value").unwrap();
+        collection.put("subject", b"v").unwrap();
+
+        let expected = vec![
+            NodeCollectionStats {
+                name: "node".to_owned(),
+                keys: 2,
+                size: 2 * (9 + 5),
+            },
+            NodeCollectionStats {
+                name: "subject".to_owned(),
+                keys: 1,
+                size: 7 + 1,
+            },
+        ];
+        assert_eq!(stats(&db).unwrap(), expected);
+
+        snapshot(&db, &tempdir.path().join("copy")).unwrap();
+        let copy = open_db(&tempdir.path().join("copy")).unwrap();
+        assert_eq!(stats(&copy).unwrap(), expected);
+
+        collection.del("subject").unwrap();
+        compact(&db).unwrap();
+        assert_eq!(stats(&db).unwrap().len(), 1);
+    }
+}
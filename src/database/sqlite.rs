@@ -3,21 +3,48 @@
 
 //! # SQLite database backend.
 //!
-//! This module contains the SQLite database backend implementation.
+//! This module contains the SQLite database backend implementation. A busy or locked database
+//! is a retryable error.
+//!
+//! Each part of a batch is written in a transaction. Unless `kore.db_batch.sync` is set,
+//! transactions are committed with `synchronous=OFF`, leaving the flush to the OS.
+//!
+//! The database is compacted with `VACUUM`, followed by a checkpoint that truncates the
+//! write-ahead log.
 //!
 
+use std::collections::BTreeMap;
+use std::fs;
 use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
 
-use rusqlite::{params, Connection, OpenFlags, Result as SQLiteResult};
+use rusqlite::{
+    params, params_from_iter, Connection, ErrorCode, OpenFlags, Result as SQLiteResult,
+};
 
 use kore_base::{DatabaseCollection, DatabaseManager, DbError as Error};
 
-use crate::error::NodeError;
+use super::{
+    batch::{chunks, BatchCollection, BatchWrite},
+    prefix::prefix_end,
+    retry::{retryable, with_retries},
+};
+use crate::{error::NodeError, model::NodeCollectionStats, settings::DbBatchSettings};
+
+/// Entries read per query while iterating a collection.
+const ITER_BATCH: usize = 100;
+
+/// Time a compaction waits for the writes of the node before failing.
+const VACUUM_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// SQLite database manager.
 pub struct SqliteManager {
     path: String,
+    readers: usize,
+    batch: DbBatchSettings,
+    pragmas: String,
 }
 
 impl SqliteManager {
@@ -25,8 +52,36 @@ impl SqliteManager {
     pub fn new(path: &str) -> Self {
         Self {
             path: path.to_owned(),
+            readers: 0,
+            batch: DbBatchSettings::default(),
+            pragmas: String::default(),
         }
     }
+
+    /// Set the number of read-only connections opened for each collection.
+    /// Reads are served by this pool while the single writer connection handles mutations,
+    /// so queries do not wait behind writes (WAL mode). In-memory databases ignore it. A
+    /// connection that cannot be opened fails the collection, as the writer does.
+    pub fn with_readers(mut self, readers: usize) -> Self {
+        self.readers = readers;
+        self
+    }
+
+    /// Set the size of the batch parts, and whether the writes of each collection are synced.
+    pub fn with_batch(mut self, batch: DbBatchSettings) -> Self {
+        self.batch = batch;
+        self
+    }
+
+    /// Set PRAGMAs run on every connection once opened, after those of the node, e.g.
+    /// `cache_size` or `mmap_size`. Names and values are not escaped.
+    pub fn with_pragmas(mut self, pragmas: &BTreeMap<String, String>) -> Self {
+        self.pragmas = pragmas
+            .iter()
+            .map(|(name, value)| format!("PRAGMA {}={};", name, value))
+            .collect();
+        self
+    }
 }
 
 impl DatabaseManager<SqliteCollection> for SqliteManager {
@@ -42,15 +97,39 @@ impl DatabaseManager<SqliteCollection> for SqliteManager {
         );
         conn.execute(stmt.as_str(), ())
             .expect("Cannot create table"); // empty list of parameters.
-                                            //let conn = open(&self.path).expect("fail SQLite open connection");
-        SqliteCollection::new(conn, identifier)
+        if !self.batch.sync {
+            conn.execute_batch("PRAGMA synchronous=OFF;")
+                .expect("Cannot disable synchronous writes");
+        }
+        conn.execute_batch(&self.pragmas)
+            .expect("Cannot apply the SQLite options");
+        let readers = if self.path != ":memory:" {
+            (0..self.readers)
+                .map(|_| {
+                    let reader =
+                        open_read_only(&self.path).expect("fail SQLite open read connection");
+                    reader
+                        .execute_batch(&self.pragmas)
+                        .expect("Cannot apply the SQLite options");
+                    reader
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+        let mut collection = SqliteCollection::new(conn, identifier).with_readers(readers);
+        collection.max_writes = self.batch.max_writes;
+        collection
     }
 }
 
 /// SQLite collection
 pub struct SqliteCollection {
     conn: Arc<Mutex<Connection>>,
+    readers: Arc<Vec<Mutex<Connection>>>,
+    next_reader: AtomicUsize,
     table: String,
+    max_writes: usize,
 }
 
 impl SqliteCollection {
@@ -58,69 +137,80 @@ impl SqliteCollection {
     pub fn new(conn: Connection, table: &str) -> Self {
         Self {
             conn: Arc::new(Mutex::new(conn)),
+            readers: Arc::new(vec![]),
+            next_reader: AtomicUsize::new(0),
             table: table.to_owned(),
+            max_writes: DbBatchSettings::default().max_writes,
         }
     }
 
-    /// Create a new iterartor filtering by prefix.
-    fn make_iter<'a>(
-        &'a self,
-        reverse: bool,
-        prefix: &str,
-    ) -> SQLiteResult<Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a>> {
-        let order = if reverse { "DESC" } else { "ASC" };
-        let conn = self.conn.lock().expect("open connection");
-        let query = format!("SELECT id, value FROM {} ORDER BY id {}", self.table, order);
-        let mut stmt = conn.prepare(&query)?;
-        let mut rows = stmt.query([])?;
-        let mut values = Vec::new();
-        while let Some(row) = rows.next()? {
-            let key: String = row.get(0)?;
-            if !key.starts_with(prefix) {
-                continue;
+    /// Set the read-only connections used for queries.
+    pub fn with_readers(mut self, readers: Vec<Connection>) -> Self {
+        self.readers = Arc::new(readers.into_iter().map(Mutex::new).collect());
+        self
+    }
+
+    /// Get a connection for reading.
+    /// Takes the first idle connection of the read pool (round robin), waiting on one of them
+    /// if all are busy. Without read pool, the writer connection is used.
+    fn reader(&self) -> Result<MutexGuard<'_, Connection>, Error> {
+        if self.readers.is_empty() {
+            return self
+                .conn
+                .lock()
+                .map_err(|_| Error::CustomError("open connection".to_owned()));
+        }
+        let start = self.next_reader.fetch_add(1, Ordering::Relaxed) % self.readers.len();
+        for i in 0..self.readers.len() {
+            let index = (start + i) % self.readers.len();
+            if let Ok(conn) = self.readers[index].try_lock() {
+                return Ok(conn);
             }
-            let key = key.replace(prefix, "");
-            values.push((key.to_string(), row.get(1)?));
         }
-        Ok(Box::new(values.into_iter()))
+        self.readers[start]
+            .lock()
+            .map_err(|_| Error::CustomError("open connection".to_owned()))
     }
 }
 
 impl DatabaseCollection for SqliteCollection {
     fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
-        let conn = self
-            .conn
-            .lock()
-            .map_err(|_| Error::CustomError("open connection".to_owned()))?;
         let query = format!("SELECT value FROM {} WHERE id = ?1", &self.table);
-        let row: Vec<u8> = conn
-            .query_row(&query, params![key], |row| row.get(0))
-            .map_err(|_| Error::EntryNotFound)?;
-
-        Ok(row)
+        with_retries(|| {
+            let conn = self.reader()?;
+            conn.query_row(&query, params![key], |row| row.get(0))
+                .map_err(|error| match error {
+                    rusqlite::Error::QueryReturnedNoRows => Error::EntryNotFound,
+                    error => db_error("select error", error),
+                })
+        })
     }
     fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
-        let conn = self
-            .conn
-            .lock()
-            .map_err(|_| Error::CustomError("open connection".to_owned()))?;
         let stmt = format!(
             "INSERT OR REPLACE INTO {} (id, value) VALUES (?1, ?2)",
             &self.table
         );
-        conn.execute(&stmt, params![key, data])
-            .map_err(|_| Error::CustomError("insert error".to_owned()))?;
+        with_retries(|| {
+            let conn = self
+                .conn
+                .lock()
+                .map_err(|_| Error::CustomError("open connection".to_owned()))?;
+            conn.execute(&stmt, params![key, data])
+                .map_err(|error| db_error("insert error", error))
+        })?;
         Ok(())
     }
 
     fn del(&self, key: &str) -> Result<(), Error> {
-        let conn = self
-            .conn
-            .lock()
-            .map_err(|_| Error::CustomError("open connection".to_owned()))?;
         let stmt = format!("DELETE FROM {} WHERE id = ?1", &self.table);
-        conn.execute(&stmt, params![key])
-            .map_err(|_| Error::CustomError("delete error".to_owned()))?;
+        with_retries(|| {
+            let conn = self
+                .conn
+                .lock()
+                .map_err(|_| Error::CustomError("open connection".to_owned()))?;
+            conn.execute(&stmt, params![key])
+                .map_err(|error| db_error("delete error", error))
+        })?;
         Ok(())
     }
 
@@ -129,25 +219,161 @@ impl DatabaseCollection for SqliteCollection {
         reverse: bool,
         prefix: &str,
     ) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a> {
-        match self.make_iter(reverse, prefix) {
-            Ok(iter) => {
-                let iterator = SQLiteIterator { iter };
-                Box::new(iterator)
-            }
-            Err(_) => Box::new(std::iter::empty()),
+        let table = &self.table;
+        Box::new(
+            SQLiteIterator::new(self, reverse, prefix, "").map_while(move |entry| {
+                entry
+                    .map_err(|error| log::warn!("Iteration of {} stopped: {:?}", table, error))
+                    .ok()
+            }),
+        )
+    }
+}
+
+impl BatchCollection for SqliteCollection {
+    fn write_batch(&self, writes: Vec<BatchWrite>) -> Result<(), Error> {
+        let put = format!(
+            "INSERT OR REPLACE INTO {} (id, value) VALUES (?1, ?2)",
+            &self.table
+        );
+        let del = format!("DELETE FROM {} WHERE id = ?1", &self.table);
+        for part in chunks(writes, self.max_writes) {
+            with_retries(|| {
+                let conn = self
+                    .conn
+                    .lock()
+                    .map_err(|_| Error::CustomError("open connection".to_owned()))?;
+                let write = || -> SQLiteResult<()> {
+                    // Rolled back when dropped without commit.
+                    let transaction = conn.unchecked_transaction()?;
+                    for (key, value) in &part {
+                        match value {
+                            Some(value) => transaction.execute(&put, params![key, value])?,
+                            None => transaction.execute(&del, params![key])?,
+                        };
+                    }
+                    transaction.commit()
+                };
+                write().map_err(|error| db_error("batch error", error))
+            })?;
         }
+        Ok(())
+    }
+
+    fn try_iter<'a>(
+        &'a self,
+        reverse: bool,
+        prefix: &str,
+    ) -> Box<dyn Iterator<Item = Result<(String, Vec<u8>), Error>> + 'a> {
+        Box::new(SQLiteIterator::new(self, reverse, prefix, ""))
+    }
+
+    fn try_iter_from<'a>(
+        &'a self,
+        prefix: &str,
+        from: &str,
+    ) -> Box<dyn Iterator<Item = Result<(String, Vec<u8>), Error>> + 'a> {
+        Box::new(SQLiteIterator::new(self, false, prefix, from))
     }
 }
 
+/// Iterator over the keys of a prefix, read in batches of `ITER_BATCH` entries.
+/// Each batch is a range query over the primary key, from the last key read on, so the
+/// connection is only held while a batch is read. A batch that cannot be read after the retries
+/// ends the iteration with its error.
 pub struct SQLiteIterator<'a> {
-    pub iter: Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a>,
+    collection: &'a SqliteCollection,
+    reverse: bool,
+    prefix: String,
+    /// Lowest key read: the prefix, or the key iterated from.
+    start: String,
+    end: Option<String>,
+    /// Last key read, excluded from the next batch.
+    last: Option<String>,
+    batch: std::vec::IntoIter<(String, Vec<u8>)>,
+    exhausted: bool,
+}
+
+impl<'a> SQLiteIterator<'a> {
+    fn new(collection: &'a SqliteCollection, reverse: bool, prefix: &str, from: &str) -> Self {
+        Self {
+            collection,
+            reverse,
+            prefix: prefix.to_owned(),
+            start: format!("{}{}", prefix, from),
+            end: prefix_end(prefix),
+            last: None,
+            batch: Vec::new().into_iter(),
+            exhausted: false,
+        }
+    }
+
+    /// Read the entries following the last key read.
+    fn read_batch(&self) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        // Text is compared byte by byte (BINARY collation), unlike the case insensitive `LIKE`.
+        let mut conditions = vec!["id >= ?"];
+        let mut values = vec![self.start.as_str()];
+        if let Some(end) = &self.end {
+            conditions.push("id < ?");
+            values.push(end);
+        }
+        if let Some(last) = &self.last {
+            conditions.push(if self.reverse { "id < ?" } else { "id > ?" });
+            values.push(last);
+        }
+        let query = format!(
+            "SELECT id, value FROM {} WHERE {} ORDER BY id {} LIMIT {}",
+            self.collection.table,
+            conditions.join(" AND "),
+            if self.reverse { "DESC" } else { "ASC" },
+            ITER_BATCH
+        );
+        let conn = self.collection.reader()?;
+        let read = || -> SQLiteResult<Vec<(String, Vec<u8>)>> {
+            let mut stmt = conn.prepare(&query)?;
+            let rows = stmt.query_map(params_from_iter(values), |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+            rows.collect()
+        };
+        read().map_err(|error| db_error("select error", error))
+    }
 }
 
 impl Iterator for SQLiteIterator<'_> {
-    type Item = (String, Vec<u8>);
+    type Item = Result<(String, Vec<u8>), Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next()
+        loop {
+            if let Some((key, value)) = self.batch.next() {
+                let suffix = key[self.prefix.len()..].to_owned();
+                self.last = Some(key);
+                return Some(Ok((suffix, value)));
+            }
+            if self.exhausted {
+                return None;
+            }
+            match with_retries(|| self.read_batch()) {
+                Ok(batch) => {
+                    self.exhausted = batch.len() < ITER_BATCH;
+                    self.batch = batch.into_iter();
+                }
+                Err(error) => {
+                    self.exhausted = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+/// Database error of a failed statement, retryable when the database is busy or locked.
+fn db_error(context: &str, error: rusqlite::Error) -> Error {
+    match error.sqlite_error_code() {
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) => {
+            retryable(format!("{}: {}", context, error))
+        }
+        _ => Error::CustomError(format!("{}: {}", context, error)),
     }
 }
 
@@ -158,21 +384,94 @@ pub fn open<P: AsRef<Path>>(path: P) -> Result<Connection, NodeError> {
     flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
     flags.insert(OpenFlags::SQLITE_OPEN_CREATE);
     let conn = Connection::open_with_flags(path, flags)
-        .map_err(|_| NodeError::Database("SQLite fail open connection".to_owned()))?;
+        .map_err(|_| NodeError::database("SQLite fail open connection".to_owned()))?;
     conn.execute_batch(
         "
         PRAGMA journal_mode=WAL;
         PRAGMA synchronous=NORMAL;
         ",
     )
-    .map_err(|_| NodeError::Database("SQListe fail execute batch".to_owned()))?;
+    .map_err(|_| NodeError::database("SQListe fail execute batch".to_owned()))?;
     Ok(conn)
 }
 
+/// Open a read-only SQLite database connection.
+pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Connection, NodeError> {
+    let path = path.as_ref();
+    let mut flags = OpenFlags::default();
+    flags.remove(OpenFlags::SQLITE_OPEN_READ_WRITE);
+    flags.remove(OpenFlags::SQLITE_OPEN_CREATE);
+    flags.insert(OpenFlags::SQLITE_OPEN_READ_ONLY);
+    Connection::open_with_flags(path, flags)
+        .map_err(|_| NodeError::database("SQLite fail open read-only connection".to_owned()))
+}
+
+/// Copy the database to the file `target`, which must not exist.
+/// `VACUUM INTO` reads a consistent state of the database while the node keeps writing.
+pub fn snapshot(path: &str, target: &Path) -> Result<(), NodeError> {
+    let conn = open_read_only(path)?;
+    conn.execute("VACUUM INTO ?1", params![target.to_string_lossy()])
+        .map_err(|error| NodeError::database(format!("Error copying the database: {}", error)))?;
+    Ok(())
+}
+
+/// Keys and bytes of the keys and values of each table of the database, sorted by name.
+pub fn stats(path: &str) -> Result<Vec<NodeCollectionStats>, NodeError> {
+    let conn = open_read_only(path)?;
+    let tables = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| row.get::<_, String>(0))?
+                .collect::<SQLiteResult<Vec<_>>>()
+        })
+        .map_err(|error| NodeError::database(format!("Error listing the tables: {}", error)))?;
+    tables
+        .into_iter()
+        .filter(|table| !table.starts_with("sqlite_"))
+        .map(|table| {
+            let query = format!(
+                "SELECT COUNT(*), COALESCE(SUM(LENGTH(CAST(id AS BLOB)) + LENGTH(value)), 0) \
+                 FROM \"{}\"",
+                table.replace('"', "\"\"")
+            );
+            conn.query_row(&query, [], |row| {
+                Ok(NodeCollectionStats {
+                    keys: row.get::<_, i64>(0)? as u64,
+                    size: row.get::<_, i64>(1)? as u64,
+                    name: table.clone(),
+                })
+            })
+            .map_err(|error| {
+                NodeError::database(format!("Error reading the table {}: {}", table, error))
+            })
+        })
+        .collect()
+}
+
+/// Bytes of the database file and of its write-ahead log.
+pub fn disk_size(path: &str) -> u64 {
+    ["", "-wal", "-shm"]
+        .iter()
+        .filter_map(|suffix| fs::metadata(format!("{}{}", path, suffix)).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Rebuild the database without its free pages, then truncate the write-ahead log.
+/// Writes of the node wait while it runs; when they keep the database busy for longer than
+/// `VACUUM_BUSY_TIMEOUT`, it fails with a retryable error.
+pub fn vacuum(path: &str) -> Result<(), NodeError> {
+    let conn = open(path)?;
+    conn.busy_timeout(VACUUM_BUSY_TIMEOUT)
+        .and_then(|_| conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);"))
+        .map_err(|error| NodeError::from(db_error("Error compacting the database", error)))
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::database::conformance::{check_prefix_iteration, check_write_batch};
     use kore_base::{test_database_manager_trait, DbError as Error};
 
     test_database_manager_trait! {
@@ -206,6 +505,179 @@ mod tests {
         assert!(iter.next().is_none());
     }
 
+    #[test]
+    fn test_sqlite_prefix_iteration() {
+        let db = SqliteManager::default();
+        let collection = db.create_collection("iteration_example");
+        check_prefix_iteration(&collection);
+    }
+
+    #[test]
+    fn test_sqlite_iteration_error() {
+        let db = SqliteManager::default();
+        let collection = db.create_collection("dropped_example");
+        collection.put("a", b"1").unwrap();
+        collection
+            .conn
+            .lock()
+            .unwrap()
+            .execute_batch("DROP TABLE dropped_example")
+            .unwrap();
+        // The error ends the fallible iteration, and only stops the other one.
+        let entries = collection.try_iter(false, "").collect::<Vec<_>>();
+        assert!(matches!(entries.as_slice(), [Err(Error::CustomError(_))]));
+        assert_eq!(collection.iter(false, "").count(), 0);
+    }
+
+    #[test]
+    fn test_sqlite_write_batch() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("database");
+        let db = SqliteManager::new(path.to_str().unwrap()).with_batch(DbBatchSettings {
+            max_writes: 2,
+            sync: false,
+        });
+        let collection = db.create_collection("batch_example");
+        let synchronous: i64 = collection
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("PRAGMA synchronous", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(synchronous, 0);
+        check_write_batch(&collection);
+
+        // A failed part is rolled back, leaving the parts before it.
+        collection
+            .conn
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER reject BEFORE INSERT ON batch_example WHEN NEW.id = 'd' \
+                 BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+            )
+            .unwrap();
+        let writes = ["a", "b", "c", "d"]
+            .iter()
+            .map(|key| (key.to_string(), Some(key.as_bytes().to_vec())))
+            .collect();
+        assert!(collection.write_batch(writes).is_err());
+        assert_eq!(
+            collection
+                .iter(false, "")
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn test_sqlite_stats_and_vacuum() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("database");
+        let path = path.to_str().unwrap();
+        let db = SqliteManager::new(path);
+        let collection = db.create_collection("stats_example");
+        db.create_collection("empty_example");
+        let value = vec![7; 4096];
+        for index in 0..100 {
+            collection.put(&format!("key{:03}", index), &value).unwrap();
+        }
+
+        let collections = stats(path).unwrap();
+        assert_eq!(
+            collections,
+            vec![
+                NodeCollectionStats {
+                    name: "empty_example".to_owned(),
+                    keys: 0,
+                    size: 0,
+                },
+                NodeCollectionStats {
+                    name: "stats_example".to_owned(),
+                    keys: 100,
+                    size: 100 * (6 + 4096),
+                },
+            ]
+        );
+
+        for index in 0..100 {
+            collection.del(&format!("key{:03}", index)).unwrap();
+        }
+        let before = disk_size(path);
+        vacuum(path).unwrap();
+        assert!(disk_size(path) < before);
+        assert_eq!(stats(path).unwrap()[1].keys, 0);
+    }
+
+    #[test]
+    fn test_sqlite_read_pool() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("database");
+        let db = SqliteManager::new(path.to_str().unwrap()).with_readers(2);
+        let collection = db.create_collection("pool_example");
+        assert_eq!(collection.readers.len(), 2);
+
+        build_state(&collection);
+        let data = get_data().unwrap();
+        for _ in 0..4 {
+            assert_eq!(collection.get("a1").unwrap(), data[0]);
+        }
+        assert_eq!(collection.iter(false, "a").count(), 2);
+
+        collection.del("a1").unwrap();
+        assert!(collection.get("a1").is_err());
+    }
+
+    #[test]
+    fn test_sqlite_pragmas() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("database");
+        let pragmas = BTreeMap::from([("cache_size".to_owned(), "-4000".to_owned())]);
+        let db = SqliteManager::new(path.to_str().unwrap())
+            .with_readers(1)
+            .with_pragmas(&pragmas);
+        let collection = db.create_collection("pragma_example");
+        let cache_size = |conn: &Connection| -> i64 {
+            conn.query_row("PRAGMA cache_size", [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(cache_size(&collection.conn.lock().unwrap()), -4000);
+        assert_eq!(cache_size(&collection.readers[0].lock().unwrap()), -4000);
+    }
+
+    #[test]
+    fn test_sqlite_locked_retries() {
+        use crate::database::retry::is_retryable;
+        use std::time::Duration;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("database");
+        let db = SqliteManager::new(path.to_str().unwrap());
+        let collection = db.create_collection("locked_example");
+        collection
+            .conn
+            .lock()
+            .unwrap()
+            .busy_timeout(Duration::ZERO)
+            .unwrap();
+
+        let other = open(&path).unwrap();
+        other.execute_batch("BEGIN EXCLUSIVE").unwrap();
+        let error = collection.put("a1", b"1").unwrap_err();
+        assert!(is_retryable(&error));
+
+        // A lock released while retrying is not seen by the caller.
+        let unlock = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(15));
+            other.execute_batch("COMMIT").unwrap();
+        });
+        collection.put("a1", b"1").unwrap();
+        unlock.join().unwrap();
+        assert_eq!(collection.get("a1").unwrap(), b"1".to_vec());
+        assert!(matches!(collection.get("a2"), Err(Error::EntryNotFound)));
+    }
+
     fn build_state(collection: &SqliteCollection) {
         let data = get_data().unwrap();
         let result = collection.put("a1", &data[0]);
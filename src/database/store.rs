@@ -0,0 +1,431 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Node store.
+//!
+//! Storage for the data owned by the Kore Node itself (not by Kore Base). Values are kept in a
+//! collection of the node database under a prefix, and encoded with a [codec](../codec/index.html).
+//!
+//! Writes that must be applied together, possibly across scopes of the same collection, are
+//! gathered in a `StoreBatch` and written at once.
+//!
+//! Entries of a store with a time to live (`with_ttl`) expire: the time of expiry is written
+//! before the encoded value, expired entries are no longer read, and `sweep` deletes them. The
+//! time to live is fixed when an entry is written, so a new policy applies to new writes.
+//!
+
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use kore_base::DbError;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    batch::{BatchCollection, BatchWrite},
+    codec::{BorshCodec, ValueCodec},
+};
+use crate::error::NodeError;
+
+/// Separator between the prefix and the key.
+const SEPARATOR: char = char::MAX;
+
+/// Bytes of the time of expiry written before the values of a store with a time to live.
+const EXPIRY_SIZE: usize = 8;
+
+/// Writes gathered from the stores of a collection, applied by `NodeStore::write`.
+#[derive(Debug, Default)]
+pub struct StoreBatch(Vec<BatchWrite>);
+
+impl StoreBatch {
+    /// Whether the batch holds no write.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Prefixed and typed view over a database collection.
+#[derive(Clone)]
+pub struct NodeStore<V: ValueCodec + Clone = BorshCodec> {
+    collection: Arc<dyn BatchCollection>,
+    prefix: String,
+    codec: V,
+    ttl: Option<Duration>,
+}
+
+impl NodeStore<BorshCodec> {
+    /// Create a new store with the default codec.
+    pub fn new(collection: Arc<dyn BatchCollection>, prefix: &str) -> Self {
+        Self::with_codec(collection, prefix, BorshCodec)
+    }
+}
+
+impl<V: ValueCodec + Clone> NodeStore<V> {
+    /// Create a new store with the provided codec.
+    pub fn with_codec(collection: Arc<dyn BatchCollection>, prefix: &str, codec: V) -> Self {
+        Self {
+            collection,
+            prefix: prefix.to_owned(),
+            codec,
+            ttl: None,
+        }
+    }
+
+    /// Expire the entries written through this store and its nested stores `ttl` after they
+    /// are written. A store must always be used with or always without a time to live.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Get a store nested under this one, sharing its collection and time to live.
+    pub fn scope(&self, name: &str) -> Self {
+        Self {
+            collection: self.collection.clone(),
+            prefix: format!("{}{}{}", self.prefix, SEPARATOR, name),
+            codec: self.codec.clone(),
+            ttl: self.ttl,
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}{}", self.prefix, SEPARATOR, key)
+    }
+
+    /// Encode a value, after its time of expiry when the store has a time to live.
+    fn encode<T>(&self, value: &T) -> Result<Vec<u8>, NodeError>
+    where
+        T: BorshSerialize + Serialize,
+    {
+        let Some(ttl) = self.ttl else {
+            return self.codec.encode(value);
+        };
+        let expires_at = now_millis().saturating_add(ttl.as_millis() as u64);
+        let mut bytes = expires_at.to_be_bytes().to_vec();
+        bytes.extend(self.codec.encode(value)?);
+        Ok(bytes)
+    }
+
+    /// Decode a value, `None` if it expired before `now`.
+    fn decode<T>(&self, bytes: &[u8], now: u64) -> Result<Option<T>, NodeError>
+    where
+        T: BorshDeserialize + DeserializeOwned,
+    {
+        if self.ttl.is_none() {
+            return self.codec.decode(bytes).map(Some);
+        }
+        match expiry(bytes) {
+            Some(expires_at) if expires_at > now => {
+                self.codec.decode(&bytes[EXPIRY_SIZE..]).map(Some)
+            }
+            Some(_) => Ok(None),
+            None => Err(NodeError::InternalApi(
+                "Stored value without time of expiry".to_owned(),
+            )),
+        }
+    }
+
+    /// Decode an entry read from the collection, `None` for the entries of nested stores and the
+    /// expired ones. Read errors are kept, so iterations fail instead of ending early.
+    fn decode_entry<T>(
+        &self,
+        entry: Result<(String, Vec<u8>), DbError>,
+        now: u64,
+    ) -> Option<Result<(String, T), NodeError>>
+    where
+        T: BorshDeserialize + DeserializeOwned,
+    {
+        let (key, bytes) = match entry {
+            Ok(entry) => entry,
+            Err(error) => return Some(Err(error.into())),
+        };
+        if key.contains(SEPARATOR) {
+            return None;
+        }
+        match self.decode(&bytes, now) {
+            Ok(Some(value)) => Some(Ok((key, value))),
+            Ok(None) => None,
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    /// Get a value, `None` if the key does not exist.
+    pub fn get<T>(&self, key: &str) -> Result<Option<T>, NodeError>
+    where
+        T: BorshDeserialize + DeserializeOwned,
+    {
+        match self.collection.get(&self.key(key)) {
+            Ok(bytes) => self.decode(&bytes, now_millis()),
+            Err(DbError::EntryNotFound) => Ok(None),
+            Err(error) => Err(NodeError::from(error)),
+        }
+    }
+
+    /// Put a value.
+    pub fn put<T>(&self, key: &str, value: &T) -> Result<(), NodeError>
+    where
+        T: BorshSerialize + Serialize,
+    {
+        let bytes = self.encode(value)?;
+        self.collection
+            .put(&self.key(key), &bytes)
+            .map_err(NodeError::from)
+    }
+
+    /// Delete a value.
+    pub fn del(&self, key: &str) -> Result<(), NodeError> {
+        self.collection.del(&self.key(key)).map_err(NodeError::from)
+    }
+
+    /// Add the put of a value to `batch`.
+    pub fn batch_put<T>(
+        &self,
+        batch: &mut StoreBatch,
+        key: &str,
+        value: &T,
+    ) -> Result<(), NodeError>
+    where
+        T: BorshSerialize + Serialize,
+    {
+        batch.0.push((self.key(key), Some(self.encode(value)?)));
+        Ok(())
+    }
+
+    /// Add the delete of a value to `batch`.
+    pub fn batch_del(&self, batch: &mut StoreBatch, key: &str) {
+        batch.0.push((self.key(key), None));
+    }
+
+    /// Apply the writes of `batch`, added from stores sharing the collection of this one.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - Some writes could not be applied. Parts of the batch bigger
+    ///   than `kore.db_batch.max_writes` may remain applied.
+    ///
+    pub fn write(&self, batch: StoreBatch) -> Result<(), NodeError> {
+        self.collection
+            .write_batch(batch.0)
+            .map_err(NodeError::from)
+    }
+
+    /// Get all the values directly under this store, ordered by key.
+    /// Entries of nested stores and expired entries are skipped.
+    pub fn entries<T>(&self) -> Result<Vec<(String, T)>, NodeError>
+    where
+        T: BorshDeserialize + DeserializeOwned,
+    {
+        let prefix = format!("{}{}", self.prefix, SEPARATOR);
+        let now = now_millis();
+        self.collection
+            .try_iter(false, &prefix)
+            .filter_map(|entry| self.decode_entry(entry, now))
+            .collect()
+    }
+
+    /// Iterate the values directly under this store whose key starts with `prefix`, ordered by
+    /// key, or in reverse order. Entries are read and decoded as the iterator advances, so
+    /// callers that stop early do not read the rest of the store.
+    /// Entries of nested stores and expired entries are skipped.
+    pub fn iter<'a, T>(
+        &'a self,
+        prefix: &str,
+        reverse: bool,
+    ) -> Box<dyn Iterator<Item = Result<(String, T), NodeError>> + 'a>
+    where
+        T: BorshDeserialize + DeserializeOwned + 'a,
+    {
+        let prefix = format!("{}{}{}", self.prefix, SEPARATOR, prefix);
+        let skipped = self.prefix.len() + SEPARATOR.len_utf8();
+        let now = now_millis();
+        Box::new(
+            self.collection
+                .try_iter(reverse, &prefix)
+                .map(move |entry| {
+                    entry.map(|(key, bytes)| (format!("{}{}", &prefix[skipped..], key), bytes))
+                })
+                .filter_map(move |entry| self.decode_entry(entry, now)),
+        )
+    }
+
+    /// Iterate the values directly under this store whose key is `from` or after it, ordered by
+    /// key. The collection seeks to `from` where it can, see `BatchCollection::try_iter_from`, and
+    /// callers that stop early do not read the rest of the store.
+    /// Entries of nested stores and expired entries are skipped.
+    pub fn iter_from<'a, T>(
+        &'a self,
+        from: &'a str,
+    ) -> Box<dyn Iterator<Item = Result<(String, T), NodeError>> + 'a>
+    where
+        T: BorshDeserialize + DeserializeOwned + 'a,
+    {
+        let prefix = format!("{}{}", self.prefix, SEPARATOR);
+        let now = now_millis();
+        Box::new(
+            self.collection
+                .try_iter_from(&prefix, from)
+                .filter_map(move |entry| self.decode_entry(entry, now)),
+        )
+    }
+
+    /// Delete the expired entries of this store and of its nested stores, which must have been
+    /// written with a time to live.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The entries could not be read or deleted.
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - Entries deleted.
+    ///
+    pub fn sweep(&self) -> Result<usize, NodeError> {
+        let prefix = format!("{}{}", self.prefix, SEPARATOR);
+        let now = now_millis();
+        let mut expired: Vec<BatchWrite> = Vec::new();
+        for entry in self.collection.try_iter(false, &prefix) {
+            let (key, bytes) = entry?;
+            if expiry(&bytes).is_some_and(|expires_at| expires_at <= now) {
+                expired.push((format!("{}{}", prefix, key), None));
+            }
+        }
+        let count = expired.len();
+        if count > 0 {
+            self.collection
+                .write_batch(expired)
+                .map_err(NodeError::from)?;
+        }
+        Ok(count)
+    }
+
+    /// Delete every entry of this store and of its nested stores.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The entries could not be read or deleted.
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - Entries deleted.
+    ///
+    pub fn clear(&self) -> Result<usize, NodeError> {
+        let prefix = format!("{}{}", self.prefix, SEPARATOR);
+        let entries: Vec<BatchWrite> = self
+            .collection
+            .try_iter(false, &prefix)
+            .map(|entry| entry.map(|(key, _)| (format!("{}{}", prefix, key), None)))
+            .collect::<Result<_, DbError>>()?;
+        let count = entries.len();
+        if count > 0 {
+            self.collection
+                .write_batch(entries)
+                .map_err(NodeError::from)?;
+        }
+        Ok(count)
+    }
+}
+
+/// Time of expiry written before a value, in milliseconds since UNIX epoch.
+fn expiry(bytes: &[u8]) -> Option<u64> {
+    let expiry = bytes.get(..EXPIRY_SIZE)?;
+    Some(u64::from_be_bytes(expiry.try_into().ok()?))
+}
+
+/// Milliseconds since UNIX epoch.
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+
+    use super::*;
+    use crate::database::sqlite::SqliteManager;
+    use kore_base::DatabaseManager;
+
+    #[test]
+    fn test_node_store() {
+        let manager = SqliteManager::default();
+        let store = NodeStore::new(Arc::new(manager.create_collection("node")), "node");
+        let nested = store.scope("nested");
+
+        store.put("b", &2u64).unwrap();
+        store.put("a", &1u64).unwrap();
+        nested.put("c", &3u64).unwrap();
+
+        assert_eq!(store.get::<u64>("a").unwrap(), Some(1));
+        assert_eq!(store.get::<u64>("z").unwrap(), None);
+        assert_eq!(
+            store.entries::<u64>().unwrap(),
+            vec![("a".to_owned(), 1), ("b".to_owned(), 2)]
+        );
+        assert_eq!(nested.entries::<u64>().unwrap(), vec![("c".to_owned(), 3)]);
+        let keys = |prefix: &str, reverse: bool| {
+            store
+                .iter::<u64>(prefix, reverse)
+                .map(|entry| entry.unwrap().0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys("", true), vec!["b".to_owned(), "a".to_owned()]);
+        assert_eq!(keys("b", false), vec!["b".to_owned()]);
+        assert!(keys("nested", false).is_empty());
+        let keys_from = |from: &str| {
+            store
+                .iter_from::<u64>(from)
+                .map(|entry| entry.unwrap().0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys_from(""), vec!["a".to_owned(), "b".to_owned()]);
+        assert_eq!(keys_from("aa"), vec!["b".to_owned()]);
+        assert!(keys_from("c").is_empty());
+
+        store.del("a").unwrap();
+        assert_eq!(store.get::<u64>("a").unwrap(), None);
+
+        let mut batch = StoreBatch::default();
+        store.batch_put(&mut batch, "a", &4u64).unwrap();
+        store.batch_del(&mut batch, "b");
+        nested.batch_put(&mut batch, "c", &5u64).unwrap();
+        assert!(!batch.is_empty());
+        store.write(batch).unwrap();
+        assert_eq!(store.entries::<u64>().unwrap(), vec![("a".to_owned(), 4)]);
+        assert_eq!(nested.get::<u64>("c").unwrap(), Some(5));
+
+        assert_eq!(nested.clear().unwrap(), 1);
+        assert_eq!(store.clear().unwrap(), 1);
+        assert!(store.entries::<u64>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_node_store_ttl() {
+        let manager = SqliteManager::default();
+        let store = NodeStore::new(Arc::new(manager.create_collection("node")), "node")
+            .scope("expiring")
+            .with_ttl(Duration::from_secs(3600));
+        let expired = store.clone().with_ttl(Duration::ZERO);
+
+        store.put("live", &1u64).unwrap();
+        expired.put("old", &2u64).unwrap();
+        expired.scope("nested").put("old", &3u64).unwrap();
+        let mut batch = StoreBatch::default();
+        store.batch_put(&mut batch, "batched", &4u64).unwrap();
+        store.write(batch).unwrap();
+
+        assert_eq!(store.get::<u64>("live").unwrap(), Some(1));
+        assert_eq!(store.get::<u64>("old").unwrap(), None);
+        assert_eq!(
+            store.entries::<u64>().unwrap(),
+            vec![("batched".to_owned(), 4), ("live".to_owned(), 1)]
+        );
+
+        assert_eq!(store.sweep().unwrap(), 2);
+        assert_eq!(store.sweep().unwrap(), 0);
+        assert_eq!(store.get::<u64>("live").unwrap(), Some(1));
+        assert_eq!(expired.scope("nested").get::<u64>("old").unwrap(), None);
+    }
+}
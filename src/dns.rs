@@ -0,0 +1,350 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Names of the boot nodes.
+//!
+//! The boot nodes of `kore.network.routing` and `kore.network.bootstrap.groups` may be given by
+//! name, as `/dns/<host>`, `/dns4/<host>` or `/dns6/<host>` multiaddresses, or as
+//! `/dnsaddr/<host>`, whose addresses are the `dnsaddr=<multiaddress>` TXT records of
+//! `_dnsaddr.<host>`. Kore Base dials IP addresses only, so the names are resolved on the
+//! runtime of the node before it is built: host names with the resolver of the system, as
+//! `tokio::net::lookup_host`, and the TXT records with the system DNS settings. The records of a
+//! `/dnsaddr` name are followed up to `MAX_DNSADDR_DEPTH` levels, and the ones that end in
+//! `/p2p/<peer id>` are kept only for that peer. An address whose name cannot be resolved is
+//! kept as written.
+//!
+//! While the node runs the names are resolved again every `kore.network.bootstrap.dns_refresh`.
+//! The new addresses are reported by `KoreApi::boot_nodes` at once, but Kore Base takes its boot
+//! nodes only when it is built and has no way to dial new ones while it runs: the node must be
+//! restarted for Kore Base to dial the new addresses.
+//!
+
+use std::{net::IpAddr, time::Duration};
+
+use hickory_resolver::{
+    config::{ResolverConfig, ResolverOpts},
+    error::ResolveError,
+    TokioAsyncResolver,
+};
+use kore_base::RoutingNode;
+use tokio::{
+    net::lookup_host,
+    time::{interval, MissedTickBehavior},
+};
+
+use crate::{api::KoreApi, tasks::NodeTasks};
+
+/// Levels of `/dnsaddr` records followed from an address.
+pub const MAX_DNSADDR_DEPTH: usize = 4;
+
+/// Name of a multiaddress to resolve.
+#[derive(Debug, Clone, PartialEq)]
+enum DnsName<'a> {
+    /// `/dns`, `/dns4` or `/dns6` name, with the IP versions taken and the rest of the address.
+    Host {
+        host: &'a str,
+        ipv4: bool,
+        ipv6: bool,
+        rest: &'a str,
+    },
+    /// `/dnsaddr` name.
+    Addr { host: &'a str },
+}
+
+/// Name of an address, none when it is not given by name.
+fn dns_name(address: &str) -> Option<DnsName<'_>> {
+    let (protocol, address) = address.strip_prefix('/')?.split_once('/')?;
+    let (host, rest) = match address.find('/') {
+        Some(pos) => address.split_at(pos),
+        None => (address, ""),
+    };
+    if host.is_empty() {
+        return None;
+    }
+    let (ipv4, ipv6) = match protocol {
+        "dns" => (true, true),
+        "dns4" => (true, false),
+        "dns6" => (false, true),
+        "dnsaddr" => return Some(DnsName::Addr { host }),
+        _ => return None,
+    };
+    Some(DnsName::Host {
+        host,
+        ipv4,
+        ipv6,
+        rest,
+    })
+}
+
+/// Addresses of the `dnsaddr` TXT records that belong to a peer, without their `/p2p/` suffix.
+fn dnsaddr_entries<I>(records: I, peer_id: &str) -> Vec<String>
+where
+    I: IntoIterator<Item = String>,
+{
+    records
+        .into_iter()
+        .filter_map(|record| record.strip_prefix("dnsaddr=").map(str::to_owned))
+        .filter_map(|address| match address.split_once("/p2p/") {
+            Some((address, peer)) if peer == peer_id => Some(address.to_owned()),
+            Some(_) => None,
+            None => Some(address),
+        })
+        .collect()
+}
+
+/// Resolves the names of the boot nodes. Its clones share the DNS client of the TXT records.
+#[derive(Clone)]
+pub struct BootNodeResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl BootNodeResolver {
+    /// Resolver with the system DNS settings, or with the default ones when they cannot be
+    /// read. It must be created within a Tokio runtime.
+    pub fn new() -> Self {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|error| {
+            log::warn!(
+                "System DNS settings not read, using the defaults: {}",
+                error
+            );
+            TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+        });
+        Self { resolver }
+    }
+
+    /// Resolve the names of the boot nodes, see the module documentation.
+    ///
+    /// # Arguments
+    ///
+    /// * `boot_nodes` - Boot nodes as configured.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<RoutingNode>` - Boot nodes in the same order, with their addresses resolved.
+    ///
+    pub async fn resolve(&self, boot_nodes: Vec<RoutingNode>) -> Vec<RoutingNode> {
+        let mut resolved = Vec::with_capacity(boot_nodes.len());
+        for node in boot_nodes {
+            let mut addresses: Vec<String> = vec![];
+            for address in &node.address {
+                for address in self.resolve_address(address, &node.peer_id).await {
+                    if !addresses.contains(&address) {
+                        addresses.push(address);
+                    }
+                }
+            }
+            resolved.push(RoutingNode {
+                peer_id: node.peer_id,
+                address: addresses,
+            });
+        }
+        resolved
+    }
+
+    /// Addresses of a multiaddress, itself when it is not given by name.
+    async fn resolve_address(&self, address: &str, peer_id: &str) -> Vec<String> {
+        let mut resolved = vec![];
+        let mut pending = vec![(address.to_owned(), 0)];
+        while let Some((address, depth)) = pending.pop() {
+            match dns_name(&address) {
+                None => resolved.push(address),
+                Some(DnsName::Host {
+                    host,
+                    ipv4,
+                    ipv6,
+                    rest,
+                }) => match lookup_host((host, 0)).await {
+                    Ok(sockets) => {
+                        for ip in sockets.map(|socket| socket.ip()) {
+                            let address = match ip {
+                                IpAddr::V4(ip) if ipv4 => format!("/ip4/{}{}", ip, rest),
+                                IpAddr::V6(ip) if ipv6 => format!("/ip6/{}{}", ip, rest),
+                                _ => continue,
+                            };
+                            if !resolved.contains(&address) {
+                                resolved.push(address);
+                            }
+                        }
+                    }
+                    Err(error) => {
+                        log::warn!("Boot node address {} not resolved: {}", address, error);
+                        resolved.push(address.clone());
+                    }
+                },
+                Some(DnsName::Addr { .. }) if depth >= MAX_DNSADDR_DEPTH => {
+                    log::warn!(
+                        "Boot node address {} nests too many dnsaddr records",
+                        address
+                    );
+                }
+                Some(DnsName::Addr { host }) => match self.dnsaddr_records(host).await {
+                    // Followed in the order of the records.
+                    Ok(records) => pending.extend(
+                        dnsaddr_entries(records, peer_id)
+                            .into_iter()
+                            .rev()
+                            .map(|entry| (entry, depth + 1)),
+                    ),
+                    Err(error) => {
+                        log::warn!("Boot node address {} not resolved: {}", address, error);
+                        resolved.push(address.clone());
+                    }
+                },
+            }
+        }
+        resolved
+    }
+
+    /// TXT records of `_dnsaddr.<host>`.
+    async fn dnsaddr_records(&self, host: &str) -> Result<Vec<String>, ResolveError> {
+        let txts = self
+            .resolver
+            .txt_lookup(format!("_dnsaddr.{}", host))
+            .await?;
+        Ok(txts
+            .iter()
+            .map(|txt| {
+                txt.txt_data()
+                    .iter()
+                    .map(|data| String::from_utf8_lossy(data))
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+impl Default for BootNodeResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolve the names of the boot nodes, see the module documentation. Boot nodes without names
+/// are returned at once.
+///
+/// # Arguments
+///
+/// * `boot_nodes` - Boot nodes as configured.
+///
+pub async fn resolve_boot_nodes(boot_nodes: Vec<RoutingNode>) -> Vec<RoutingNode> {
+    if !boot_nodes
+        .iter()
+        .flat_map(|node| node.address.iter())
+        .any(|address| dns_name(address).is_some())
+    {
+        return boot_nodes;
+    }
+    BootNodeResolver::new().resolve(boot_nodes).await
+}
+
+/// Resolve the names of the boot nodes periodically, until the node is cancelled, and
+/// give the new addresses to the API. Kore Base dials them from the next start of the node.
+///
+/// # Arguments
+///
+/// * `api` - Node API, which gets the boot nodes when their addresses change.
+/// * `boot_nodes` - Boot nodes as configured.
+/// * `resolved` - Boot nodes resolved when the node started.
+/// * `refresh` - Time between two resolutions.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_dns_refresh(
+    api: KoreApi,
+    boot_nodes: Vec<RoutingNode>,
+    mut resolved: Vec<RoutingNode>,
+    refresh: Duration,
+    tasks: &NodeTasks,
+) {
+    let cancellation = tasks.token();
+    tasks.spawn(async move {
+        let resolver = BootNodeResolver::new();
+        let mut interval = interval(refresh.max(Duration::from_secs(1)));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        // The first tick completes at once.
+        interval.tick().await;
+        loop {
+            tokio::select! {
+                _ = cancellation.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            let current = resolver.resolve(boot_nodes.clone()).await;
+            if current != resolved {
+                log::warn!(
+                    "Boot node addresses changed, restart the node for Kore Base to dial them"
+                );
+                api.set_boot_nodes(current.clone());
+                resolved = current;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_dns_name() {
+        assert_eq!(
+            dns_name("/dns4/boot.example.com/tcp/50000"),
+            Some(DnsName::Host {
+                host: "boot.example.com",
+                ipv4: true,
+                ipv6: false,
+                rest: "/tcp/50000",
+            })
+        );
+        assert_eq!(
+            dns_name("/dns/boot.example.com/tcp/50000"),
+            Some(DnsName::Host {
+                host: "boot.example.com",
+                ipv4: true,
+                ipv6: true,
+                rest: "/tcp/50000",
+            })
+        );
+        assert_eq!(
+            dns_name("/dnsaddr/bootstrap.example.com"),
+            Some(DnsName::Addr {
+                host: "bootstrap.example.com"
+            })
+        );
+        assert_eq!(dns_name("/ip4/10.0.0.1/tcp/50000"), None);
+        assert_eq!(dns_name("/dns6/"), None);
+    }
+
+    #[test]
+    fn test_dnsaddr_entries() {
+        let records = [
+            "dnsaddr=/ip4/10.0.0.1/tcp/50000/p2p/12D3KooWBoot",
+            "dnsaddr=/ip4/10.0.0.2/tcp/50000/p2p/12D3KooWOther",
+            "dnsaddr=/dnsaddr/eu.bootstrap.example.com",
+            "v=spf1 -all",
+        ];
+        assert_eq!(
+            dnsaddr_entries(records.map(str::to_owned), "12D3KooWBoot"),
+            vec![
+                "/ip4/10.0.0.1/tcp/50000".to_owned(),
+                "/dnsaddr/eu.bootstrap.example.com".to_owned()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_boot_nodes() {
+        let boot_nodes = vec![RoutingNode {
+            peer_id: "12D3KooWBoot".to_owned(),
+            address: vec!["/ip4/10.0.0.1/tcp/50000".to_owned()],
+        }];
+        assert_eq!(resolve_boot_nodes(boot_nodes.clone()).await, boot_nodes);
+        let named = vec![RoutingNode {
+            peer_id: "12D3KooWBoot".to_owned(),
+            address: vec!["/dns4/localhost/tcp/50000".to_owned()],
+        }];
+        assert_eq!(
+            resolve_boot_nodes(named).await[0].address,
+            vec!["/ip4/127.0.0.1/tcp/50000".to_owned()]
+        );
+    }
+}
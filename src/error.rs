@@ -6,8 +6,62 @@
 //! This module contains the different errors that can be returned by the Kore Node.
 //!  
 
+use std::{error::Error as StdError, fmt, sync::Arc};
+
+use kore_base::DbError;
 use thiserror::Error;
 
+use crate::database::retry;
+
+/// Invalid configuration value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    /// Setting key, environment variables or file where the value comes from.
+    pub location: String,
+    /// Description of the problem.
+    pub message: String,
+    /// How to fix it, when it is not obvious from the message.
+    pub hint: Option<String>,
+}
+
+impl ConfigError {
+    /// Create a configuration error.
+    ///
+    /// # Arguments
+    ///
+    /// * `location` - Setting key, environment variables or file of the value.
+    /// * `message` - Description of the problem.
+    ///
+    pub fn new(location: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            location: location.into(),
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    /// Add a hint on how to fix the value.
+    ///
+    /// # Arguments
+    ///
+    /// * `hint` - How to fix the value.
+    ///
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)?;
+        match &self.hint {
+            Some(hint) => write!(f, " ({})", hint),
+            None => Ok(()),
+        }
+    }
+}
+
 /// Kore Node errors.
 #[derive(Error, Debug, Clone)]
 pub enum NodeError {
@@ -17,10 +71,109 @@ pub enum NodeError {
     /// API error
     #[error("API error: {0}")]
     InternalApi(String),
-    /// Database error
-    #[error("Database error: {0}")]
-    Database(String),
+    /// Entity unknown by the node.
+    #[error("Not found: {0}")]
+    NotFound(String),
+    /// Request in conflict with the current state, e.g. a vote on a decided approval.
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    /// Failure of Kore Base, with the call that failed and the original error.
+    #[error("Internal error in {context}: {source}")]
+    Internal {
+        /// Call that failed.
+        context: String,
+        /// Error returned by Kore Base.
+        #[source]
+        source: Arc<dyn StdError + Send + Sync>,
+    },
+    /// Database error.
+    #[error("Database error: {message}")]
+    Database {
+        /// Description of the error.
+        message: String,
+        /// Whether repeating the operation may succeed, e.g. when the database was busy.
+        retryable: bool,
+    },
     /// Keys Error
     #[error("Keys error: {0}")]
     Keys(String),
+    /// Listen address already in use.
+    #[error("Network error: address {0} is not available")]
+    Network(String),
+    /// Address of a server that cannot be bound.
+    #[error("Network error: address {address} cannot be bound: {cause}")]
+    Bind {
+        /// Address to bind.
+        address: String,
+        /// Error reported by the system, e.g. the address is in use.
+        cause: String,
+    },
+    /// Invalid configuration, with every problem found.
+    #[error(
+        "Config error: {}",
+        .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+    )]
+    Config(Vec<ConfigError>),
+    /// Data export error.
+    #[error("Export error: {0}")]
+    Export(String),
+    /// Error of the archive of old events, see the `archival` module.
+    #[error("Archive error: {0}")]
+    Archive(String),
+    /// Request without valid credentials, see the `auth` module.
+    #[error("Unauthenticated: {0}")]
+    Unauthenticated(String),
+    /// Request not allowed for its signer.
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    /// Signature of a request that does not match it, or made too long ago or ahead of time.
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+    /// Quota of the requester exhausted.
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+    /// Call over the rate or the concurrency allowed to its class, see the `limits` module.
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+    /// Deadline of the call exceeded.
+    #[error("Deadline exceeded")]
+    Timeout,
+    /// Call cancelled by the caller.
+    #[error("Call cancelled")]
+    Cancelled,
+}
+
+impl NodeError {
+    /// Fatal database error.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - Description of the error.
+    ///
+    pub fn database(message: impl Into<String>) -> Self {
+        NodeError::Database {
+            message: message.into(),
+            retryable: false,
+        }
+    }
+
+    /// Whether the failed operation may succeed if repeated later.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            NodeError::Database {
+                retryable: true,
+                ..
+            }
+        )
+    }
+}
+
+impl From<DbError> for NodeError {
+    fn from(error: DbError) -> Self {
+        NodeError::Database {
+            message: retry::message(&error),
+            retryable: retry::is_retryable(&error),
+        }
+    }
 }
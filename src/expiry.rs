@@ -0,0 +1,82 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Expiry.
+//!
+//! Auxiliary collections, such as caches or records kept for a while after a request, get their
+//! store from `KoreApi::expiring_store` instead of deleting their old entries themselves. Each
+//! entry expires after the time to live of its collection, set in `kore.db_ttl.collections` or
+//! given by the code that uses it; expired entries are no longer read. The sent requests
+//! (`requests`), the node history (`history`), the idempotency keys (`idempotency`) and the
+//! replicated events given up (`dead_letters`) are kept this way, as are the subject creations
+//! counted by the quota, for one quota window.
+//!
+//! The sweeper deletes the expired entries of every such collection each
+//! `kore.db_ttl.sweep_interval`, so that they do not take space in the database.
+//!
+
+use std::time::Duration;
+
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
+use crate::tasks::NodeTasks;
+use crate::KoreApi;
+
+/// Delete the expired entries periodically, in the background.
+///
+/// # Arguments
+///
+/// * `api` - Kore API of the node.
+/// * `sweep_interval` - Time between sweeps, zero disables the sweeper.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_sweeper(api: KoreApi, sweep_interval: Duration, tasks: &NodeTasks) {
+    if sweep_interval.is_zero() {
+        return;
+    }
+    let cancellation = tasks.token();
+    tasks.spawn(async move {
+        let mut interval = interval_at(Instant::now() + sweep_interval, sweep_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = cancellation.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            match api.sweep_expired().await {
+                Ok(0) => {}
+                Ok(deleted) => log::debug!("{} expired entries deleted", deleted),
+                Err(error) => log::warn!("Expired entries not deleted: {}", error),
+            }
+        }
+    });
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::settings::DbTtlSettings;
+
+    #[tokio::test]
+    async fn test_sqlite_expiring_store() {
+        let api = crate::node::tests::export_sqlite_api(231, vec![])
+            .await
+            .with_db_ttl(DbTtlSettings {
+                collections: BTreeMap::from([("expired".to_owned(), Duration::ZERO)]),
+                ..Default::default()
+            });
+        let live = api.expiring_store("live", Duration::from_secs(3600));
+        let expired = api.expiring_store("expired", Duration::from_secs(3600));
+        live.put("key", &1u64).unwrap();
+        expired.put("key", &2u64).unwrap();
+
+        assert_eq!(live.get::<u64>("key").unwrap(), Some(1));
+        assert_eq!(expired.get::<u64>("key").unwrap(), None);
+        assert_eq!(api.sweep_expired().await.unwrap(), 1);
+        assert_eq!(api.sweep_expired().await.unwrap(), 0);
+        assert_eq!(live.get::<u64>("key").unwrap(), Some(1));
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Checkpoints of an export.
+//!
+//! The rows of each event range read are written to a part file of the checkpoint directory, and
+//! a manifest lists the finished ranges with the SHA-256 digest of their part. An interrupted
+//! export takes the ranges whose part still matches its digest from disk and only reads the rest
+//! from the node. The manifest is written every `MANIFEST_INTERVAL` ranges and when the export
+//! stops; parts written after the last manifest are read again.
+//!
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::ExportRow;
+use crate::{error::NodeError, settings::ExportColumn};
+
+/// Ranges finished between two writes of the manifest.
+const MANIFEST_INTERVAL: usize = 16;
+
+/// File of the manifest in the checkpoint directory.
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Finished ranges of an export.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+struct Manifest {
+    /// Name and path of the payload columns; the parts of other columns are not reused.
+    columns: Vec<(String, String)>,
+    /// Digest of the part of each finished range, by range key.
+    ranges: BTreeMap<String, String>,
+}
+
+/// Progress of the export of a governance.
+pub(super) struct Checkpoint {
+    dir: PathBuf,
+    manifest: Manifest,
+    unsaved: usize,
+}
+
+impl Checkpoint {
+    /// Open the checkpoint of a governance, keeping the finished ranges of a previous export
+    /// with the same columns.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Export` - The checkpoint directory cannot be created.
+    ///
+    pub(super) fn open(
+        checkpoint_dir: &str,
+        governance_id: &str,
+        columns: &[ExportColumn],
+    ) -> Result<Self, NodeError> {
+        let dir = Path::new(checkpoint_dir).join(governance_id);
+        fs::create_dir_all(&dir).map_err(|error| export_error(&dir, error))?;
+        let columns = columns
+            .iter()
+            .map(|column| (column.name.clone(), column.path.clone()))
+            .collect::<Vec<_>>();
+        let manifest = fs::read(dir.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Manifest>(&data).ok())
+            .filter(|manifest| manifest.columns == columns)
+            .unwrap_or(Manifest {
+                columns,
+                ranges: BTreeMap::new(),
+            });
+        Ok(Self {
+            dir,
+            manifest,
+            unsaved: 0,
+        })
+    }
+
+    /// Whether a range is finished and its part matches the digest of the manifest.
+    pub(super) fn has(&self, key: &str) -> bool {
+        self.verified_part(key).is_some()
+    }
+
+    /// Rows of a finished range, `None` when the range is not finished or its part does not
+    /// match the digest of the manifest.
+    pub(super) fn load(&self, key: &str) -> Option<Vec<ExportRow>> {
+        serde_json::from_slice(&self.verified_part(key)?).ok()
+    }
+
+    /// Part of a finished range, when it matches the digest of the manifest.
+    fn verified_part(&self, key: &str) -> Option<Vec<u8>> {
+        let digest = self.manifest.ranges.get(key)?;
+        let path = self.part(key);
+        let data = fs::read(&path).ok()?;
+        if &sha256(&data) != digest {
+            log::warn!("Export part {} does not match its digest", path.display());
+            return None;
+        }
+        Some(data)
+    }
+
+    /// Keep the rows of a finished range.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Export` - The part or the manifest cannot be written.
+    ///
+    pub(super) fn save(&mut self, key: &str, rows: &[ExportRow]) -> Result<(), NodeError> {
+        let data = serde_json::to_vec(rows)
+            .map_err(|error| NodeError::Export(format!("{}: {}", key, error)))?;
+        write(&self.part(key), &data)?;
+        self.manifest.ranges.insert(key.to_owned(), sha256(&data));
+        self.unsaved += 1;
+        if self.unsaved >= MANIFEST_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write the manifest.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Export` - The manifest cannot be written.
+    ///
+    pub(super) fn flush(&mut self) -> Result<(), NodeError> {
+        let data = serde_json::to_vec_pretty(&self.manifest)
+            .map_err(|error| NodeError::Export(format!("{}: {}", MANIFEST_FILE, error)))?;
+        write(&self.dir.join(MANIFEST_FILE), &data)?;
+        self.unsaved = 0;
+        Ok(())
+    }
+
+    /// Remove the checkpoint once the export is stored.
+    pub(super) fn finish(self) {
+        if let Err(error) = fs::remove_dir_all(&self.dir) {
+            log::warn!(
+                "Export checkpoint {} not removed: {}",
+                self.dir.display(),
+                error
+            );
+        }
+    }
+
+    /// Part file of a range.
+    fn part(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+/// Hex SHA-256 digest.
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Write a file through a temporary one, so that it is never left half written.
+fn write(path: &Path, data: &[u8]) -> Result<(), NodeError> {
+    let partial = path.with_extension("partial");
+    fs::write(&partial, data)
+        .and_then(|_| fs::rename(&partial, path))
+        .map_err(|error| export_error(path, error))
+}
+
+fn export_error(path: &Path, error: std::io::Error) -> NodeError {
+    NodeError::Export(format!("{}: {}", path.display(), error))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::export::tests::rows;
+
+    #[test]
+    fn test_checkpoint() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path().to_str().unwrap();
+        let (columns, rows) = rows();
+
+        let mut checkpoint = Checkpoint::open(dir, "JGovernance", &columns).unwrap();
+        assert_eq!(checkpoint.load("JSubject.0-999"), None);
+        checkpoint.save("JSubject.0-999", &rows).unwrap();
+        checkpoint.save("JOther.0-999", &rows[..1]).unwrap();
+        assert_eq!(checkpoint.load("JSubject.0-999"), Some(rows.clone()));
+        assert!(checkpoint.has("JOther.0-999"));
+        // Ranges finished after the last manifest are read again.
+        let reopened = Checkpoint::open(dir, "JGovernance", &columns).unwrap();
+        assert_eq!(reopened.load("JSubject.0-999"), None);
+
+        checkpoint.flush().unwrap();
+        let reopened = Checkpoint::open(dir, "JGovernance", &columns).unwrap();
+        assert_eq!(reopened.load("JSubject.0-999"), Some(rows.clone()));
+
+        // Damaged parts and parts of other columns are not reused.
+        fs::write(reopened.part("JOther.0-999"), b"[]").unwrap();
+        assert_eq!(reopened.load("JOther.0-999"), None);
+        assert!(!reopened.has("JOther.0-999"));
+        let reopened = Checkpoint::open(dir, "JGovernance", &columns[..1]).unwrap();
+        assert_eq!(reopened.load("JSubject.0-999"), None);
+
+        reopened.finish();
+        assert!(!tempdir.path().join("JGovernance").exists());
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! CSV encoding of the exported rows and usage summaries.
+//!
+
+use std::io::Write;
+
+use super::{ExportRow, FIXED_COLUMNS};
+use crate::{error::NodeError, model::NodeUsage, settings::ExportColumn};
+
+/// CSV writer of the exported rows, with a header row. Missing payload values are empty fields.
+pub(super) struct CsvRows<W: Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: Write> CsvRows<W> {
+    /// Start the file, writing its header row to `output`.
+    pub(super) fn new(columns: &[ExportColumn], output: W) -> Result<Self, NodeError> {
+        let mut writer = csv::Writer::from_writer(output);
+        let header = FIXED_COLUMNS
+            .iter()
+            .copied()
+            .chain(columns.iter().map(|column| column.name.as_str()));
+        writer.write_record(header).map_err(csv_error)?;
+        Ok(Self { writer })
+    }
+
+    /// Write rows after the ones already written.
+    pub(super) fn write(&mut self, rows: &[ExportRow]) -> Result<(), NodeError> {
+        for row in rows {
+            let fixed = [
+                row.subject_id.clone(),
+                row.schema_id.clone(),
+                row.sn.to_string(),
+                row.gov_version.to_string(),
+                row.event_type.clone(),
+                row.signer.clone(),
+                row.timestamp.to_string(),
+                row.eval_success.to_string(),
+                row.approved.to_string(),
+            ];
+            let values = row
+                .values
+                .iter()
+                .map(|value| value.clone().unwrap_or_default());
+            self.writer
+                .write_record(fixed.into_iter().chain(values))
+                .map_err(csv_error)?;
+        }
+        Ok(())
+    }
+
+    /// End the file, returning the output.
+    pub(super) fn finish(self) -> Result<W, NodeError> {
+        self.writer
+            .into_inner()
+            .map_err(|error| NodeError::Export(format!("CSV: {}", error)))
+    }
+}
+
+/// Encode usage summaries as CSV, with a header row.
+pub(super) fn encode_usage(usage: &[NodeUsage]) -> Result<Vec<u8>, NodeError> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer
+        .write_record(["caller", "from", "to", "requests", "bytes"])
+        .map_err(csv_error)?;
+    for usage in usage {
+        writer
+            .write_record([
+                usage.caller.clone(),
+                usage.from.to_string(),
+                usage.to.to_string(),
+                usage.requests.to_string(),
+                usage.bytes.to_string(),
+            ])
+            .map_err(csv_error)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|error| NodeError::Export(format!("CSV: {}", error)))
+}
+
+fn csv_error(error: csv::Error) -> NodeError {
+    NodeError::Export(format!("CSV: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::export::tests::rows;
+
+    #[test]
+    fn test_csv_rows() {
+        let (columns, rows) = rows();
+        let mut writer = CsvRows::new(&columns, vec![]).unwrap();
+        writer.write(&rows[..1]).unwrap();
+        writer.write(&rows[1..]).unwrap();
+        let data = String::from_utf8(writer.finish().unwrap()).unwrap();
+        let lines = data.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                "subject_id,schema_id,sn,gov_version,event_type,signer,timestamp,eval_success,approved,temperature,place",
+                "JSubject,Sensor,0,1,create,ESigner,1700000000000,true,true,,",
+                "JSubject,Sensor,1,1,fact,ESigner,1700000000001,true,true,21.5,\"Lab, room 1\"",
+                "JSubject,Sensor,2,1,fact,ESigner,1700000000002,true,false,22,",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encode_usage_csv() {
+        let usage = vec![NodeUsage {
+            caller: "10.0.0.2".to_owned(),
+            from: 1700000000000,
+            to: 1700003600000,
+            requests: 12,
+            bytes: 3400,
+        }];
+        let data = String::from_utf8(encode_usage(&usage).unwrap()).unwrap();
+        assert_eq!(
+            data.lines().collect::<Vec<_>>(),
+            vec![
+                "caller,from,to,requests,bytes",
+                "10.0.0.2,1700000000000,1700003600000,12,3400"
+            ]
+        );
+    }
+}
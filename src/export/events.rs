@@ -0,0 +1,300 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Event archives.
+//!
+//! The signed events of a subject, or of a governance and every subject of it, written one
+//! record at a time to any `AsyncWrite` as JSON Lines or, with the `cbor` feature, as a CBOR
+//! sequence (RFC 8742). The events of each subject are written in order, followed by the
+//! validation proof of its last event when the node holds one. Events are read page by page, so
+//! the node never holds a whole history in memory.
+//!
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    error::NodeError,
+    model::{EventContentResponse, NodeProof, NodeSigned, NodeSubjectData, PaginatorFromNumber},
+    KoreApi,
+};
+
+/// Events read from the node at a time.
+const PAGE_SIZE: i64 = 100;
+
+/// Schema of the governance subjects.
+const GOVERNANCE_SCHEMA: &str = "governance";
+
+/// Encoding of an event archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventExportFormat {
+    /// One JSON object per line.
+    #[default]
+    JsonLines,
+    /// Concatenated CBOR items.
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+/// Record of an event archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+pub enum EventRecord {
+    /// Signed event, with the signature of its request and those of its evaluators and
+    /// approvers.
+    Event {
+        /// Subject of the event.
+        subject_id: String,
+        /// Event signed by the subject owner.
+        event: Box<NodeSigned<EventContentResponse>>,
+    },
+    /// Validation proof of the last event of a subject, with the signatures of its validators.
+    Proof {
+        /// Subject of the proof.
+        subject_id: String,
+        /// Proof and signatures.
+        proof: Box<NodeProof>,
+    },
+}
+
+/// Bytes of a record in `format`.
+fn encode(record: &EventRecord, format: EventExportFormat) -> Result<Vec<u8>, NodeError> {
+    match format {
+        EventExportFormat::JsonLines => {
+            let mut line = serde_json::to_vec(record)
+                .map_err(|error| NodeError::Export(format!("JSON encode error: {}", error)))?;
+            line.push(b'\n');
+            Ok(line)
+        }
+        #[cfg(feature = "cbor")]
+        EventExportFormat::Cbor => {
+            let mut bytes = Vec::new();
+            ciborium::into_writer(record, &mut bytes)
+                .map_err(|error| NodeError::Export(format!("CBOR encode error: {}", error)))?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// Encode a record and write it.
+async fn write_record<W>(
+    writer: &mut W,
+    record: &EventRecord,
+    format: EventExportFormat,
+) -> Result<(), NodeError>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer
+        .write_all(&encode(record, format)?)
+        .await
+        .map_err(|error| NodeError::Export(error.to_string()))
+}
+
+/// Write the events and the validation proof of a subject.
+async fn write_subject<W>(
+    api: &KoreApi,
+    subject: &NodeSubjectData,
+    writer: &mut W,
+    format: EventExportFormat,
+) -> Result<usize, NodeError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut written = 0;
+    let mut from = 0;
+    loop {
+        let page = api
+            .get_events_of_subject(
+                &subject.subject_id,
+                PaginatorFromNumber {
+                    from: Some(from),
+                    quantity: Some(PAGE_SIZE),
+                },
+            )
+            .await?;
+        if let Some(last) = page.items.last() {
+            from = last.content.sn as i64 + 1;
+        }
+        for event in page.items {
+            let record = EventRecord::Event {
+                subject_id: subject.subject_id.clone(),
+                event: Box::new(event),
+            };
+            write_record(writer, &record, format).await?;
+            written += 1;
+        }
+        if page.next_cursor.is_none() {
+            break;
+        }
+    }
+    match api.get_validation_proof(&subject.subject_id).await {
+        Ok(proof) => {
+            let record = EventRecord::Proof {
+                subject_id: subject.subject_id.clone(),
+                proof: Box::new(proof),
+            };
+            write_record(writer, &record, format).await?;
+        }
+        // Only the validators of the last event, and the owner, keep its proof.
+        Err(NodeError::NotFound(_)) => {}
+        Err(error) => return Err(error),
+    }
+    Ok(written)
+}
+
+/// Write the events of a subject, and of every subject of it when it is a governance.
+///
+/// # Arguments
+///
+/// * `api` - Kore API.
+/// * `subject_id` - Subject, or governance, to export.
+/// * `writer` - Output of the archive, flushed at the end.
+/// * `format` - Encoding of the records.
+///
+/// # Errors
+///
+/// * `NodeError::InvalidParameter` - Invalid subject identifier.
+/// * `NodeError::NotFound` - The subject is not known by the node.
+/// * `NodeError::Internal` - Kore Base failed while reading the events.
+/// * `NodeError::Export` - The records could not be encoded or written.
+///
+/// # Returns
+///
+/// * `usize` - Number of events written.
+///
+pub(crate) async fn write_events<W>(
+    api: &KoreApi,
+    subject_id: &str,
+    writer: &mut W,
+    format: EventExportFormat,
+) -> Result<usize, NodeError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let subject = api.get_subject(subject_id).await?;
+    let mut subjects = vec![];
+    if subject.schema_id == GOVERNANCE_SCHEMA {
+        subjects = api.get_all_subjects_of_governance(subject_id).await?;
+        subjects.retain(|governed| governed.subject_id != subject.subject_id);
+    }
+    subjects.insert(0, subject);
+
+    let mut written = 0;
+    for subject in subjects.iter() {
+        written += write_subject(api, subject, writer, format).await?;
+    }
+    writer
+        .flush()
+        .await
+        .map_err(|error| NodeError::Export(error.to_string()))?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn event_record(sn: u64) -> EventRecord {
+        let signature = serde_json::json!({
+            "signer": "EnyisBz0lX9sRvvV0H-BXTrVtARjUa0YDHzaxFHWH-N4",
+            "timestamp": 1,
+            "value": "SEYml_XhryHvxRylu023oyR0nIjlwVCyw2ZC_Tgvf9gH5ChnCqG9cSDB3Fo3a6jBIhO7Cxg9DDeIZn1Ej-VNXRCg",
+            "content_hash": "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+        });
+        serde_json::from_value(serde_json::json!({
+            "record": "event",
+            "subject_id": "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+            "event": {
+                "subject_id": "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+                "event_request": {
+                    "Fact": {
+                        "subject_id": "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+                        "payload": { "temperature": 12 },
+                    },
+                    "signature": signature,
+                },
+                "gov_version": 0,
+                "sn": sn,
+                "patch": [],
+                "state_hash": "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+                "eval_success": true,
+                "appr_required": false,
+                "approved": true,
+                "hash_prev_event": "",
+                "evaluators": [],
+                "approvers": [],
+                "signature": signature,
+            },
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_json_lines_records() {
+        let mut archive = Vec::new();
+        for sn in 0..2 {
+            write_record(
+                &mut archive,
+                &event_record(sn),
+                EventExportFormat::JsonLines,
+            )
+            .await
+            .unwrap();
+        }
+        let lines = String::from_utf8(archive).unwrap();
+        let records = lines
+            .lines()
+            .map(|line| serde_json::from_str::<EventRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 2);
+        let EventRecord::Event { event, .. } = &records[1] else {
+            panic!("event record expected");
+        };
+        assert_eq!(event.content.sn, 1);
+        assert_eq!(
+            event.signature.signer(),
+            "EnyisBz0lX9sRvvV0H-BXTrVtARjUa0YDHzaxFHWH-N4"
+        );
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor_records() {
+        let mut archive = encode(&event_record(0), EventExportFormat::Cbor).unwrap();
+        archive.extend(encode(&event_record(1), EventExportFormat::Cbor).unwrap());
+        let mut reader = archive.as_slice();
+        let first: EventRecord = ciborium::from_reader(&mut reader).unwrap();
+        let second: EventRecord = ciborium::from_reader(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        assert!(matches!(first, EventRecord::Event { event, .. } if event.content.sn == 0));
+        assert!(matches!(second, EventRecord::Event { event, .. } if event.content.sn == 1));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_export_events() {
+        use crate::{api::tests::create_event, node::tests::export_sqlite_api};
+
+        let api = export_sqlite_api(235, vec![]).await;
+        let governance_id = create_event(&api, "", "governance", "archive").await;
+        let mut archive = Vec::new();
+        let written = api
+            .export_events(&governance_id, &mut archive, EventExportFormat::JsonLines)
+            .await
+            .unwrap();
+        assert_eq!(written, 1);
+        let records = String::from_utf8(archive)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<EventRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            &records[0],
+            EventRecord::Event { subject_id, .. } if *subject_id == governance_id
+        ));
+        assert!(matches!(records.last(), Some(EventRecord::Proof { .. })));
+    }
+}
@@ -0,0 +1,758 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Governance data export.
+//!
+//! Flattens the events of the subjects of a governance into one row per event, with the event
+//! metadata and the payload values selected in the export settings, and writes them as a CSV or
+//! Parquet file to a local directory or, with the `object-store` feature, to object storage.
+//!
+//! The events are read in ranges of `RANGE_SIZE` per subject, several at a time, and each range
+//! is written to the file once the ranges before it are, so that only the ranges read ahead are
+//! held in memory. The file is written next to its destination, or to the temporary directory of
+//! the system for object storage, and stored once complete. With a checkpoint directory the
+//! ranges already read survive an interruption of the export.
+//!
+//! The usage summaries of the node API, see `KoreApi::usage_summary`, are exported as CSV to the
+//! same destinations.
+//!
+//! The signed events themselves, with their signatures and validation proofs, are archived
+//! without flattening by `KoreApi::export_events`, see `events`, and checked by
+//! `KoreApi::verify_events`, see `verify`. Archives cannot seed a node, which is restored from a
+//! backup of its database.
+//!
+
+mod checkpoint;
+mod csv;
+mod events;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod verify;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{BufWriter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use self::checkpoint::Checkpoint;
+pub(crate) use self::events::write_events;
+pub use self::events::{EventExportFormat, EventRecord};
+pub(crate) use self::verify::verify_events;
+pub use self::verify::VerifyReport;
+use crate::{
+    api::timestamp_millis,
+    error::NodeError,
+    model::{
+        EventContentResponse, NodeEventRequest, NodeSigned, NodeSubjectData, PaginatorFromNumber,
+    },
+    settings::{ExportColumn, ExportFormat, ExportSettings},
+    KoreApi,
+};
+
+/// Events of a subject read by a worker at a time.
+pub const RANGE_SIZE: u64 = 1000;
+
+/// Bytes of a file read at a time while it is uploaded to object storage.
+#[cfg(feature = "object-store")]
+const UPLOAD_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Parts of a file uploaded at the same time.
+#[cfg(feature = "object-store")]
+const UPLOAD_CONCURRENCY: usize = 4;
+
+/// Columns with the event metadata, written before the payload columns.
+pub const FIXED_COLUMNS: [&str; 9] = [
+    "subject_id",
+    "schema_id",
+    "sn",
+    "gov_version",
+    "event_type",
+    "signer",
+    "timestamp",
+    "eval_success",
+    "approved",
+];
+
+/// Event flattened for the export.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportRow {
+    /// Subject identifier.
+    pub subject_id: String,
+    /// Schema of the subject.
+    pub schema_id: String,
+    /// Sequence number of the event.
+    pub sn: u64,
+    /// Governance version of the event.
+    pub gov_version: u64,
+    /// Type of the event request: `create`, `fact`, `transfer` or `eol`.
+    pub event_type: String,
+    /// Signer of the event request.
+    pub signer: String,
+    /// Timestamp of the event.
+    pub timestamp: u64,
+    /// Whether the evaluation succeeded.
+    pub eval_success: bool,
+    /// Whether the event was approved.
+    pub approved: bool,
+    /// Payload values, in the order of the export columns.
+    pub values: Vec<Option<String>>,
+}
+
+impl ExportRow {
+    /// Flatten an event of a subject.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - Subject of the event.
+    /// * `event` - Signed event.
+    /// * `columns` - Payload values to export.
+    ///
+    pub fn new(
+        subject: &NodeSubjectData,
+        event: &NodeSigned<EventContentResponse>,
+        columns: &[ExportColumn],
+    ) -> Self {
+        let request = &event.content.event_request;
+        let (event_type, payload) = match &request.content {
+            NodeEventRequest::Create(_) => ("create", None),
+            NodeEventRequest::Fact(fact) => ("fact", Some(&fact.payload)),
+            NodeEventRequest::Transfer(_) => ("transfer", None),
+            NodeEventRequest::EOL(_) => ("eol", None),
+        };
+        Self {
+            subject_id: subject.subject_id.clone(),
+            schema_id: subject.schema_id.clone(),
+            sn: event.content.sn,
+            gov_version: event.content.gov_version,
+            event_type: event_type.to_owned(),
+            signer: request.signature.signer().to_owned(),
+            timestamp: event.signature.timestamp(),
+            eval_success: event.content.eval_success,
+            approved: event.content.approved,
+            values: columns
+                .iter()
+                .map(|column| payload.and_then(|payload| column_value(payload, &column.path)))
+                .collect(),
+        }
+    }
+}
+
+/// Result of an export.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportReport {
+    /// Path or URL of the written file.
+    pub location: String,
+    /// Number of exported subjects.
+    pub subjects: usize,
+    /// Number of exported events.
+    pub events: usize,
+    /// Event ranges taken from the checkpoint of an interrupted export.
+    pub resumed: usize,
+}
+
+/// Events `from..=to` of a subject.
+#[derive(Debug, Clone)]
+struct EventRange<'a> {
+    subject: &'a NodeSubjectData,
+    from: u64,
+    to: u64,
+}
+
+impl EventRange<'_> {
+    /// Key of the range in the checkpoint. The last range of a subject that grew gets another
+    /// key, so it is read again.
+    fn key(&self) -> String {
+        format!("{}.{}-{}", self.subject.subject_id, self.from, self.to)
+    }
+}
+
+/// Split the events of the subjects into ranges of `RANGE_SIZE`.
+fn event_ranges(subjects: &[NodeSubjectData]) -> Vec<EventRange<'_>> {
+    subjects
+        .iter()
+        .flat_map(|subject| {
+            (0..=subject.sn)
+                .step_by(RANGE_SIZE as usize)
+                .map(move |from| EventRange {
+                    subject,
+                    from,
+                    to: (from + RANGE_SIZE - 1).min(subject.sn),
+                })
+        })
+        .collect()
+}
+
+/// Read the events of a range, returned with the index of the range.
+async fn read_range(
+    api: &KoreApi,
+    index: usize,
+    range: &EventRange<'_>,
+    columns: &[ExportColumn],
+) -> (usize, Result<Vec<ExportRow>, NodeError>) {
+    (index, read_range_rows(api, range, columns).await)
+}
+
+/// Rows of the events of a range, read page by page.
+async fn read_range_rows(
+    api: &KoreApi,
+    range: &EventRange<'_>,
+    columns: &[ExportColumn],
+) -> Result<Vec<ExportRow>, NodeError> {
+    let mut rows = vec![];
+    let mut next = range.from;
+    while next <= range.to {
+        let page = api
+            .get_events_of_subject(
+                &range.subject.subject_id,
+                PaginatorFromNumber {
+                    from: Some(next as i64),
+                    quantity: Some((range.to - next + 1) as i64),
+                },
+            )
+            .await?;
+        let Some(last) = page.items.last() else {
+            break;
+        };
+        next = last.content.sn + 1;
+        rows.extend(
+            page.items
+                .iter()
+                .filter(|event| event.content.sn <= range.to)
+                .map(|event| ExportRow::new(range.subject, event, columns)),
+        );
+    }
+    Ok(rows)
+}
+
+/// Writer of the rows of an export in its format.
+enum RowWriter<W: Write + Send> {
+    Csv(csv::CsvRows<W>),
+    #[cfg(feature = "parquet")]
+    Parquet(parquet::ParquetRows<W>),
+}
+
+impl<W: Write + Send> RowWriter<W> {
+    /// Start the file in `output`.
+    fn new(format: ExportFormat, columns: &[ExportColumn], output: W) -> Result<Self, NodeError> {
+        match format {
+            ExportFormat::Csv => Ok(Self::Csv(csv::CsvRows::new(columns, output)?)),
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => Ok(Self::Parquet(parquet::ParquetRows::new(columns, output)?)),
+        }
+    }
+
+    /// Write rows after the ones already written.
+    fn write(&mut self, rows: &[ExportRow]) -> Result<(), NodeError> {
+        match self {
+            Self::Csv(writer) => writer.write(rows),
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer) => writer.write(rows),
+        }
+    }
+
+    /// End the file, returning the output.
+    fn finish(self) -> Result<W, NodeError> {
+        match self {
+            Self::Csv(writer) => writer.finish(),
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer) => writer.finish(),
+        }
+    }
+}
+
+/// Extension of the files in `format`.
+fn extension(format: ExportFormat) -> &'static str {
+    match format {
+        ExportFormat::Csv => "csv",
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet => "parquet",
+    }
+}
+
+/// Export the events of the subjects of a governance, archived ones included.
+/// Up to `export.workers` event ranges are read at a time, and never more than that ahead of the
+/// next range to write, so that the memory taken does not grow with the governance. With
+/// `export.checkpoint_dir`, the ranges read are kept there until the file is stored, and the
+/// ranges kept by an interrupted export are not read again.
+///
+/// # Arguments
+///
+/// * `api` - Kore API.
+/// * `export` - Export settings.
+///
+/// # Errors
+///
+/// * `NodeError::Internal` - The subjects or their events could not be read.
+/// * `NodeError::Export` - The file could not be encoded or written.
+///
+/// # Returns
+///
+/// * `ExportReport` - Location and size of the export.
+///
+pub async fn export_governance(
+    api: &KoreApi,
+    export: &ExportSettings,
+) -> Result<ExportReport, NodeError> {
+    let subjects = api
+        .get_all_subjects_of_governance(&export.governance_id)
+        .await?;
+    let ranges = event_ranges(&subjects);
+    let mut checkpoint = if export.checkpoint_dir.is_empty() {
+        None
+    } else {
+        Some(Checkpoint::open(
+            &export.checkpoint_dir,
+            &export.governance_id,
+            &export.columns,
+        )?)
+    };
+    let resumed = match &checkpoint {
+        Some(checkpoint) => (0..ranges.len())
+            .filter(|index| checkpoint.has(&ranges[*index].key()))
+            .collect(),
+        None => HashSet::new(),
+    };
+
+    let file_name = format!(
+        "{}-{}.{}",
+        export.governance_id,
+        timestamp_millis(),
+        extension(export.format)
+    );
+    let staged = staging_path(&export.destination, &file_name)?;
+    let location = match write_rows(api, export, &ranges, &resumed, &mut checkpoint, &staged).await
+    {
+        Ok(events) => store_file(&export.destination, &file_name, &staged)
+            .await
+            .map(|location| (location, events)),
+        Err(error) => {
+            let _ = fs::remove_file(&staged);
+            Err(error)
+        }
+    };
+    if let Some(mut checkpoint) = checkpoint {
+        match location {
+            Ok(_) => checkpoint.finish(),
+            // Keep the progress for the next attempt.
+            Err(_) => checkpoint.flush()?,
+        }
+    }
+
+    let (location, events) = location?;
+    Ok(ExportReport {
+        location,
+        subjects: subjects.len(),
+        events,
+        resumed: resumed.len(),
+    })
+}
+
+/// Write the rows of the ranges to `path`, in the order of the ranges, taking the `resumed` ones
+/// from the checkpoint and reading the others from the node.
+///
+/// # Returns
+///
+/// * `usize` - Rows written.
+///
+async fn write_rows(
+    api: &KoreApi,
+    export: &ExportSettings,
+    ranges: &[EventRange<'_>],
+    resumed: &HashSet<usize>,
+    checkpoint: &mut Option<Checkpoint>,
+    path: &Path,
+) -> Result<usize, NodeError> {
+    let file = File::create(path)
+        .map_err(|error| NodeError::Export(format!("{}: {}", path.display(), error)))?;
+    let mut writer = RowWriter::new(export.format, &export.columns, BufWriter::new(file))?;
+    let workers = export.workers.max(1);
+    let mut pending = (0..ranges.len())
+        .filter(|index| !resumed.contains(index))
+        .peekable();
+    let mut reads = FuturesUnordered::new();
+    let mut read = HashMap::new();
+    let mut next = 0;
+    let mut written = 0;
+    while next < ranges.len() {
+        let rows = if resumed.contains(&next) {
+            let key = ranges[next].key();
+            checkpoint
+                .as_ref()
+                .and_then(|checkpoint| checkpoint.load(&key))
+                .ok_or_else(|| NodeError::Export(format!("checkpoint of {} changed", key)))?
+        } else if let Some(rows) = read.remove(&next) {
+            rows
+        } else {
+            while reads.len() < workers {
+                match pending.next_if(|index| *index < next + workers) {
+                    Some(index) => {
+                        reads.push(read_range(api, index, &ranges[index], &export.columns))
+                    }
+                    None => break,
+                }
+            }
+            let Some((index, rows)) = reads.next().await else {
+                return Err(NodeError::Export(format!(
+                    "range {} was not read",
+                    ranges[next].key()
+                )));
+            };
+            let rows = rows?;
+            if let Some(checkpoint) = checkpoint.as_mut() {
+                checkpoint.save(&ranges[index].key(), &rows)?;
+            }
+            read.insert(index, rows);
+            continue;
+        };
+        writer.write(&rows)?;
+        written += rows.len();
+        next += 1;
+    }
+    writer
+        .finish()?
+        .into_inner()
+        .map_err(|error| NodeError::Export(format!("{}: {}", path.display(), error.error())))?;
+    Ok(written)
+}
+
+/// Export the usage of the node API by caller over `range` as a CSV file.
+///
+/// # Arguments
+///
+/// * `api` - Kore API.
+/// * `range` - Milliseconds since UNIX epoch.
+/// * `destination` - Local directory, or object storage URL with the `object-store` feature.
+///
+/// # Errors
+///
+/// * `NodeError::Database` - The usage could not be read.
+/// * `NodeError::Export` - The file could not be encoded or written.
+///
+/// # Returns
+///
+/// * `String` - Location of the file.
+///
+pub async fn export_usage(
+    api: &KoreApi,
+    range: Range<u64>,
+    destination: &str,
+) -> Result<String, NodeError> {
+    let usage = api.usage_summary(range.clone())?;
+    let data = csv::encode_usage(&usage)?;
+    let file_name = format!("usage-{}-{}.csv", range.start, range.end);
+    store(destination, &file_name, data).await
+}
+
+/// Text of the payload value at `path`: strings as they are, other values as JSON.
+fn column_value(payload: &Value, path: &str) -> Option<String> {
+    match payload.pointer(path)? {
+        Value::Null => None,
+        Value::String(value) => Some(value.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+/// File where an export is written before it is stored: next to its destination, or in the
+/// temporary directory of the system for object storage.
+fn staging_path(destination: &str, file_name: &str) -> Result<PathBuf, NodeError> {
+    let partial = format!("{}.partial", file_name);
+    if destination.contains("://") {
+        return Ok(std::env::temp_dir().join(partial));
+    }
+    fs::create_dir_all(destination)
+        .map_err(|error| NodeError::Export(format!("{}: {}", destination, error)))?;
+    Ok(Path::new(destination).join(partial))
+}
+
+/// Store a file written by `staging_path` at its destination, returning its location.
+async fn store_file(
+    destination: &str,
+    file_name: &str,
+    staged: &Path,
+) -> Result<String, NodeError> {
+    if destination.contains("://") {
+        let location = upload_remote(destination, file_name, staged).await;
+        let _ = fs::remove_file(staged);
+        return location;
+    }
+    let path = Path::new(destination).join(file_name);
+    fs::rename(staged, &path)
+        .map_err(|error| NodeError::Export(format!("{}: {}", path.display(), error)))?;
+    Ok(path.display().to_string())
+}
+
+/// Write the file to its destination, returning its location.
+async fn store(destination: &str, file_name: &str, data: Vec<u8>) -> Result<String, NodeError> {
+    if destination.contains("://") {
+        return store_remote(destination, file_name, data).await;
+    }
+    fs::create_dir_all(destination)
+        .map_err(|error| NodeError::Export(format!("{}: {}", destination, error)))?;
+    let path = Path::new(destination).join(file_name);
+    fs::write(&path, data)
+        .map_err(|error| NodeError::Export(format!("{}: {}", path.display(), error)))?;
+    Ok(path.display().to_string())
+}
+
+/// Upload the file to object storage. The credentials are taken from the environment, e.g.
+/// `AWS_ACCESS_KEY_ID` or `GOOGLE_SERVICE_ACCOUNT`.
+#[cfg(feature = "object-store")]
+async fn store_remote(
+    destination: &str,
+    file_name: &str,
+    data: Vec<u8>,
+) -> Result<String, NodeError> {
+    let url = url::Url::parse(destination)
+        .map_err(|error| NodeError::Export(format!("{}: {}", destination, error)))?;
+    let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+    let (store, path) = object_store::parse_url_opts(&url, options)
+        .map_err(|error| NodeError::Export(format!("{}: {}", destination, error)))?;
+    let path = path.child(file_name);
+    store
+        .put(&path, data.into())
+        .await
+        .map_err(|error| NodeError::Export(format!("{}: {}", destination, error)))?;
+    Ok(format!(
+        "{}/{}",
+        destination.trim_end_matches('/'),
+        file_name
+    ))
+}
+
+/// Upload a local file to object storage in parts, so that it is never held in memory.
+#[cfg(feature = "object-store")]
+async fn upload_remote(
+    destination: &str,
+    file_name: &str,
+    local: &Path,
+) -> Result<String, NodeError> {
+    use object_store::WriteMultipart;
+    use tokio::io::AsyncReadExt;
+
+    let export_error =
+        |error: &dyn std::fmt::Display| NodeError::Export(format!("{}: {}", destination, error));
+    let url = url::Url::parse(destination).map_err(|error| export_error(&error))?;
+    let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+    let (store, path) =
+        object_store::parse_url_opts(&url, options).map_err(|error| export_error(&error))?;
+    let path = path.child(file_name);
+    let mut file = tokio::fs::File::open(local)
+        .await
+        .map_err(|error| NodeError::Export(format!("{}: {}", local.display(), error)))?;
+    let upload = store
+        .put_multipart(&path)
+        .await
+        .map_err(|error| export_error(&error))?;
+    let mut upload = WriteMultipart::new(upload);
+    let mut buffer = vec![0; UPLOAD_CHUNK_SIZE];
+    let written = loop {
+        let read = match file.read(&mut buffer).await {
+            Ok(0) => break Ok(()),
+            Ok(read) => read,
+            Err(error) => break Err(NodeError::Export(format!("{}: {}", local.display(), error))),
+        };
+        if let Err(error) = upload.wait_for_capacity(UPLOAD_CONCURRENCY).await {
+            break Err(export_error(&error));
+        }
+        upload.write(&buffer[..read]);
+    };
+    match written {
+        Ok(()) => {
+            upload
+                .finish()
+                .await
+                .map_err(|error| export_error(&error))?;
+        }
+        Err(error) => {
+            // Parts already uploaded are discarded.
+            let _ = upload.abort().await;
+            return Err(error);
+        }
+    }
+    Ok(format!(
+        "{}/{}",
+        destination.trim_end_matches('/'),
+        file_name
+    ))
+}
+
+#[cfg(not(feature = "object-store"))]
+async fn upload_remote(
+    destination: &str,
+    _file_name: &str,
+    _local: &Path,
+) -> Result<String, NodeError> {
+    Err(NodeError::Export(format!(
+        "{}: object storage requires the object-store feature",
+        destination
+    )))
+}
+
+#[cfg(not(feature = "object-store"))]
+async fn store_remote(
+    destination: &str,
+    _file_name: &str,
+    _data: Vec<u8>,
+) -> Result<String, NodeError> {
+    Err(NodeError::Export(format!(
+        "{}: object storage requires the object-store feature",
+        destination
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::json;
+
+    pub(super) fn rows() -> (Vec<ExportColumn>, Vec<ExportRow>) {
+        let columns = vec![
+            ExportColumn {
+                name: "temperature".to_owned(),
+                path: "/temperature".to_owned(),
+            },
+            ExportColumn {
+                name: "place".to_owned(),
+                path: "/place/name".to_owned(),
+            },
+        ];
+        let row = |sn: u64, event_type: &'static str, values: Vec<Option<&str>>| ExportRow {
+            subject_id: "JSubject".to_owned(),
+            schema_id: "Sensor".to_owned(),
+            sn,
+            gov_version: 1,
+            event_type: event_type.to_owned(),
+            signer: "ESigner".to_owned(),
+            timestamp: 1_700_000_000_000 + sn,
+            eval_success: true,
+            approved: sn != 2,
+            values: values
+                .into_iter()
+                .map(|value| value.map(str::to_owned))
+                .collect(),
+        };
+        let rows = vec![
+            row(0, "create", vec![None, None]),
+            row(1, "fact", vec![Some("21.5"), Some("Lab, room 1")]),
+            row(2, "fact", vec![Some("22"), None]),
+        ];
+        (columns, rows)
+    }
+
+    #[test]
+    fn test_column_value() {
+        let payload = json!({"temperature": 21.5, "place": {"name": "Lab"}, "note": null});
+        assert_eq!(
+            column_value(&payload, "/temperature"),
+            Some("21.5".to_owned())
+        );
+        assert_eq!(
+            column_value(&payload, "/place/name"),
+            Some("Lab".to_owned())
+        );
+        assert_eq!(
+            column_value(&payload, "/place"),
+            Some(r#"{"name":"Lab"}"#.to_owned())
+        );
+        assert_eq!(column_value(&payload, "/note"), None);
+        assert_eq!(column_value(&payload, "/missing"), None);
+    }
+
+    #[test]
+    fn test_event_ranges() {
+        let subject = |subject_id: &str, sn: u64| NodeSubjectData {
+            subject_id: subject_id.to_owned(),
+            governance_id: String::new(),
+            sn,
+            public_key: String::new(),
+            namespace: String::new(),
+            name: String::new(),
+            schema_id: "sensor".to_owned(),
+            owner: String::new(),
+            creator: String::new(),
+            properties: json!({}),
+            active: true,
+            archived: false,
+        };
+        let subjects = vec![subject("JFirst", 2), subject("JSecond", 2 * RANGE_SIZE)];
+        let keys = event_ranges(&subjects)
+            .iter()
+            .map(EventRange::key)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                "JFirst.0-2",
+                "JSecond.0-999",
+                "JSecond.1000-1999",
+                "JSecond.2000-2000"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_local() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let destination = tempdir.path().join("exports");
+        let location = store(destination.to_str().unwrap(), "gov.csv", b"a,b\n".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(fs::read(location).unwrap(), b"a,b\n".to_vec());
+
+        #[cfg(not(feature = "object-store"))]
+        assert!(matches!(
+            store("s3://bucket/exports", "gov.csv", vec![]).await,
+            Err(NodeError::Export(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_store_file_local() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let destination = tempdir.path().join("exports");
+        let destination = destination.to_str().unwrap();
+        let staged = staging_path(destination, "gov.csv").unwrap();
+        fs::write(&staged, b"a,b\n").unwrap();
+        let location = store_file(destination, "gov.csv", &staged).await.unwrap();
+        assert_eq!(fs::read(location).unwrap(), b"a,b\n".to_vec());
+        assert!(!staged.exists());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_export_governance() {
+        use crate::{api::tests::create_event, node::tests::export_sqlite_api};
+
+        let api = export_sqlite_api(238, vec![]).await;
+        let governance_id = create_event(&api, "", "governance", "export").await;
+        let tempdir = tempfile::tempdir().unwrap();
+        let (destination, checkpoints) = (
+            tempdir.path().join("exports"),
+            tempdir.path().join("checkpoints"),
+        );
+        let export = ExportSettings {
+            governance_id: governance_id.clone(),
+            format: ExportFormat::Csv,
+            columns: vec![],
+            destination: destination.to_str().unwrap().to_owned(),
+            workers: 2,
+            checkpoint_dir: checkpoints.to_str().unwrap().to_owned(),
+        };
+        let report = export_governance(&api, &export).await.unwrap();
+        assert_eq!((report.subjects, report.events, report.resumed), (1, 1, 0));
+        let data = fs::read_to_string(&report.location).unwrap();
+        assert_eq!(data.lines().count(), 2);
+        assert!(data.lines().nth(1).unwrap().starts_with(&governance_id));
+        // Only the stored file is left, and the checkpoint is removed.
+        assert_eq!(fs::read_dir(&destination).unwrap().count(), 1);
+        assert!(!checkpoints.join(&governance_id).exists());
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Parquet encoding of the exported rows.
+//!
+
+use std::{io::Write, sync::Arc};
+
+use arrow_array::{ArrayRef, BooleanArray, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+
+use super::{ExportRow, FIXED_COLUMNS};
+use crate::{error::NodeError, settings::ExportColumn};
+
+/// Rows of a row group, the part of the file held in memory while it is written.
+const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// Parquet writer of the exported rows, compressed with Snappy. Metadata columns keep their
+/// types; payload columns are nullable strings.
+pub(super) struct ParquetRows<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: SchemaRef,
+    columns: usize,
+}
+
+impl<W: Write + Send> ParquetRows<W> {
+    /// Start the file in `output`.
+    pub(super) fn new(columns: &[ExportColumn], output: W) -> Result<Self, NodeError> {
+        let types = [
+            DataType::Utf8,
+            DataType::Utf8,
+            DataType::UInt64,
+            DataType::UInt64,
+            DataType::Utf8,
+            DataType::Utf8,
+            DataType::UInt64,
+            DataType::Boolean,
+            DataType::Boolean,
+        ];
+        let fields = FIXED_COLUMNS
+            .iter()
+            .zip(types)
+            .map(|(name, data_type)| Field::new(*name, data_type, false))
+            .chain(
+                columns
+                    .iter()
+                    .map(|column| Field::new(&column.name, DataType::Utf8, true)),
+            )
+            .collect::<Vec<_>>();
+        let schema = Arc::new(Schema::new(fields));
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(ROW_GROUP_SIZE)
+            .build();
+        let writer = ArrowWriter::try_new(output, schema.clone(), Some(properties))
+            .map_err(parquet_error)?;
+        Ok(Self {
+            writer,
+            schema,
+            columns: columns.len(),
+        })
+    }
+
+    /// Write rows after the ones already written.
+    pub(super) fn write(&mut self, rows: &[ExportRow]) -> Result<(), NodeError> {
+        let mut arrays: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|row| &row.subject_id),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|row| &row.schema_id),
+            )),
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(|row| row.sn))),
+            Arc::new(UInt64Array::from_iter_values(
+                rows.iter().map(|row| row.gov_version),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|row| row.event_type.as_str()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|row| &row.signer),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                rows.iter().map(|row| row.timestamp),
+            )),
+            Arc::new(BooleanArray::from_iter(
+                rows.iter().map(|row| Some(row.eval_success)),
+            )),
+            Arc::new(BooleanArray::from_iter(
+                rows.iter().map(|row| Some(row.approved)),
+            )),
+        ];
+        for index in 0..self.columns {
+            arrays.push(Arc::new(StringArray::from_iter(
+                rows.iter().map(|row| row.values[index].as_deref()),
+            )));
+        }
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays).map_err(parquet_error)?;
+        self.writer.write(&batch).map_err(parquet_error)
+    }
+
+    /// End the file, writing its footer, and return the output.
+    pub(super) fn finish(self) -> Result<W, NodeError> {
+        self.writer.into_inner().map_err(parquet_error)
+    }
+}
+
+fn parquet_error(error: impl std::fmt::Display) -> NodeError {
+    NodeError::Export(format!("Parquet: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::export::tests::rows;
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn test_parquet_rows() {
+        let (columns, rows) = rows();
+        let file = tempfile::tempfile().unwrap();
+        let mut writer = ParquetRows::new(&columns, file.try_clone().unwrap()).unwrap();
+        writer.write(&rows[..1]).unwrap();
+        writer.write(&rows[1..]).unwrap();
+        writer.finish().unwrap();
+
+        let batch = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(batch.num_columns(), FIXED_COLUMNS.len() + 2);
+
+        let sn = batch
+            .column_by_name("sn")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap();
+        assert_eq!(sn.values().to_vec(), vec![0, 1, 2]);
+        let temperature = batch
+            .column_by_name("temperature")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert!(temperature.is_null(0));
+        assert_eq!(temperature.value(1), "21.5");
+    }
+}
@@ -0,0 +1,475 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Event archive verification.
+//!
+//! Reads an archive written by `KoreApi::export_events` and checks every subject in it: the
+//! events must start at 0 and follow each other, the request of each event and the event itself
+//! must carry valid signatures, and each event must point to the hash of the one before it, as
+//! the validation proof must to the last one.
+//!
+//! Kore Base only takes event requests, never stored events: a node is restored from a backup of
+//! its database, and its archives serve to audit a ledger outside of it.
+//!
+//! The records of a subject are held until the subject is checked, never the whole archive,
+//! except for CBOR archives, which are read at once.
+//!
+
+use kore_base::{
+    signature::Signature as BaseSignature, DigestDerivator, DigestIdentifier,
+    EventRequest as BaseEventRequest,
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader, Lines};
+
+use super::events::{EventExportFormat, EventRecord};
+use crate::{
+    error::NodeError,
+    model::{EventContentResponse, NodeProof, NodeSigned},
+};
+
+/// Outcome of the verification of an archive.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyReport {
+    /// Subjects in the archive.
+    pub subjects: usize,
+    /// Events whose signatures and chaining were checked.
+    pub verified: usize,
+    /// Subjects whose last event has its validation proof in the archive.
+    pub proven: usize,
+}
+
+/// Records of an archive, one at a time.
+enum RecordReader<R> {
+    /// Lines of a JSON Lines archive, and the number of the last one read.
+    JsonLines(Lines<BufReader<R>>, usize),
+    /// Bytes of a CBOR archive, and the position of the next item.
+    #[cfg(feature = "cbor")]
+    Cbor(Vec<u8>, usize),
+}
+
+impl<R> RecordReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    /// Reader of an archive in `format`.
+    async fn new(reader: R, format: EventExportFormat) -> Result<Self, NodeError> {
+        match format {
+            EventExportFormat::JsonLines => {
+                Ok(RecordReader::JsonLines(BufReader::new(reader).lines(), 0))
+            }
+            #[cfg(feature = "cbor")]
+            EventExportFormat::Cbor => {
+                use tokio::io::AsyncReadExt;
+
+                let mut reader = reader;
+                let mut bytes = Vec::new();
+                reader
+                    .read_to_end(&mut bytes)
+                    .await
+                    .map_err(|error| NodeError::Export(error.to_string()))?;
+                Ok(RecordReader::Cbor(bytes, 0))
+            }
+        }
+    }
+
+    /// Next record, `None` at the end of the archive.
+    async fn next(&mut self) -> Result<Option<EventRecord>, NodeError> {
+        match self {
+            RecordReader::JsonLines(lines, number) => loop {
+                let line = lines
+                    .next_line()
+                    .await
+                    .map_err(|error| NodeError::Export(error.to_string()))?;
+                let Some(line) = line else {
+                    return Ok(None);
+                };
+                *number += 1;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                return serde_json::from_str(&line).map(Some).map_err(|error| {
+                    NodeError::InvalidParameter(format!("record in line {}: {}", number, error))
+                });
+            },
+            #[cfg(feature = "cbor")]
+            RecordReader::Cbor(bytes, position) => {
+                if *position >= bytes.len() {
+                    return Ok(None);
+                }
+                let mut remaining = &bytes[*position..];
+                let length = remaining.len();
+                let record = ciborium::from_reader(&mut remaining).map_err(|error| {
+                    NodeError::InvalidParameter(format!("record at byte {}: {}", position, error))
+                })?;
+                *position += length - remaining.len();
+                Ok(Some(record))
+            }
+        }
+    }
+}
+
+/// Records of a subject in an archive.
+struct ArchivedSubject {
+    /// Subject identifier.
+    subject_id: String,
+    /// Events of the subject, in the order of the archive.
+    events: Vec<NodeSigned<EventContentResponse>>,
+    /// Validation proof of the last event, if archived.
+    proof: Option<Box<NodeProof>>,
+}
+
+/// Records of an archive, one subject at a time.
+struct ArchiveSubjects<R> {
+    records: RecordReader<R>,
+    /// First record of the next subject, read with the last one.
+    pending: Option<EventRecord>,
+}
+
+impl<R> ArchiveSubjects<R>
+where
+    R: AsyncRead + Unpin,
+{
+    /// Reader of an archive in `format`.
+    async fn new(reader: R, format: EventExportFormat) -> Result<Self, NodeError> {
+        Ok(Self {
+            records: RecordReader::new(reader, format).await?,
+            pending: None,
+        })
+    }
+
+    /// Records of the next subject, `None` at the end of the archive.
+    async fn next(&mut self) -> Result<Option<ArchivedSubject>, NodeError> {
+        let mut subject: Option<ArchivedSubject> = None;
+        loop {
+            let record = match self.pending.take() {
+                Some(record) => record,
+                None => match self.records.next().await? {
+                    Some(record) => record,
+                    None => return Ok(subject),
+                },
+            };
+            let (EventRecord::Event { subject_id, .. } | EventRecord::Proof { subject_id, .. }) =
+                &record;
+            // The records of a subject are contiguous, and the proof comes after its events.
+            if let Some(current) = &subject {
+                if current.subject_id != *subject_id || current.proof.is_some() {
+                    self.pending = Some(record);
+                    return Ok(subject);
+                }
+            }
+            let current = subject.get_or_insert_with(|| ArchivedSubject {
+                subject_id: subject_id.clone(),
+                events: vec![],
+                proof: None,
+            });
+            match record {
+                EventRecord::Event { event, .. } => current.events.push(*event),
+                EventRecord::Proof { proof, .. } => current.proof = Some(proof),
+            }
+        }
+    }
+}
+
+/// Check the signatures of an event and of its request, and that the event signature was made
+/// on the event itself.
+fn verify_event(
+    event: &NodeSigned<EventContentResponse>,
+    digest_derivator: DigestDerivator,
+) -> Result<(), NodeError> {
+    let sn = event.content.sn;
+    let invalid = |what: &str| {
+        NodeError::InvalidSignature(format!(
+            "{} of event {} of {}",
+            what, sn, event.content.subject_id
+        ))
+    };
+    let request = &event.content.event_request;
+    let content = BaseEventRequest::try_from(request.content.clone())?;
+    BaseSignature::try_from(request.signature.clone())?
+        .verify(&content)
+        .map_err(|_| invalid("signature of the request"))?;
+
+    let content = kore_base::Event::try_from(event.content.clone())?;
+    let hash = DigestIdentifier::from_serializable_borsh(&content, digest_derivator)
+        .map_err(|_| NodeError::InvalidParameter(format!("event {}", sn)))?;
+    let signature = BaseSignature::try_from(event.signature.clone())?;
+    if signature.content_hash != hash {
+        return Err(invalid("content hash of the signature"));
+    }
+    signature
+        .verify(&content)
+        .map_err(|_| invalid("signature"))?;
+    Ok(())
+}
+
+/// Check that the events of a subject start at 0 and point to the hash signed for the previous
+/// one, and that the validation proof is that of the last one.
+///
+/// # Errors
+///
+/// * `NodeError::InvalidParameter` - The events do not follow each other, or do not belong to
+///   the subject.
+///
+fn check_chain(
+    subject_id: &str,
+    events: &[NodeSigned<EventContentResponse>],
+    proof: Option<&NodeProof>,
+) -> Result<(), NodeError> {
+    let broken = |message: String| {
+        NodeError::InvalidParameter(format!("chain of {}: {}", subject_id, message))
+    };
+    let mut previous_hash = "";
+    for (sn, event) in events.iter().enumerate() {
+        let content = &event.content;
+        if content.subject_id != subject_id {
+            return Err(broken(format!(
+                "event {} belongs to {}",
+                content.sn, content.subject_id
+            )));
+        }
+        if content.sn != sn as u64 {
+            return Err(broken(format!(
+                "event {} expected, {} found",
+                sn, content.sn
+            )));
+        }
+        if content.hash_prev_event != previous_hash {
+            return Err(broken(format!(
+                "event {} does not follow the previous one",
+                sn
+            )));
+        }
+        previous_hash = event.signature.content_hash();
+    }
+    if let Some(proof) = proof {
+        let proof = &proof.proof;
+        let last = events.len() as u64;
+        if proof.subject_id != subject_id || last == 0 || proof.sn != last - 1 {
+            return Err(broken(format!("proof of event {} found", proof.sn)));
+        }
+        if proof.event_hash != previous_hash {
+            return Err(broken("the proof does not match the last event".to_owned()));
+        }
+    }
+    Ok(())
+}
+
+/// Check the chaining of the events of a subject, then their signatures.
+///
+/// # Errors
+///
+/// * `NodeError::InvalidParameter` - The events do not follow each other.
+/// * `NodeError::InvalidSignature` - A signature is not valid, or was not made on its event.
+///
+fn verify_subject(
+    subject_id: &str,
+    events: &[NodeSigned<EventContentResponse>],
+    proof: Option<&NodeProof>,
+    digest_derivator: DigestDerivator,
+) -> Result<(), NodeError> {
+    check_chain(subject_id, events, proof)?;
+    for event in events {
+        verify_event(event, digest_derivator)?;
+    }
+    Ok(())
+}
+
+/// Check an event archive, subject by subject, stopping at the first invalid one.
+///
+/// # Arguments
+///
+/// * `reader` - Archive written by `write_events`.
+/// * `format` - Encoding of the records.
+/// * `digest_derivator` - Derivator of the hashes of the events.
+///
+/// # Errors
+///
+/// * `NodeError::Export` - The archive could not be read.
+/// * `NodeError::InvalidParameter` - A record could not be decoded, or the events of a subject
+///   do not follow each other.
+/// * `NodeError::InvalidSignature` - A signature is not valid.
+///
+/// # Returns
+///
+/// * `VerifyReport` - Subjects and events checked.
+///
+pub(crate) async fn verify_events<R>(
+    reader: R,
+    format: EventExportFormat,
+    digest_derivator: DigestDerivator,
+) -> Result<VerifyReport, NodeError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut subjects = ArchiveSubjects::new(reader, format).await?;
+    let mut report = VerifyReport::default();
+    while let Some(subject) = subjects.next().await? {
+        verify_subject(
+            &subject.subject_id,
+            &subject.events,
+            subject.proof.as_deref(),
+            digest_derivator,
+        )?;
+        report.subjects += 1;
+        report.verified += subject.events.len();
+        report.proven += usize::from(subject.proof.is_some());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn signature() -> serde_json::Value {
+        serde_json::json!({
+            "signer": "EnyisBz0lX9sRvvV0H-BXTrVtARjUa0YDHzaxFHWH-N4",
+            "timestamp": 1,
+            "value": "SEYml_XhryHvxRylu023oyR0nIjlwVCyw2ZC_Tgvf9gH5ChnCqG9cSDB3Fo3a6jBIhO7Cxg9DDeIZn1Ej-VNXRCg",
+            "content_hash": "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+        })
+    }
+
+    fn event(sn: u64, hash_prev_event: &str) -> NodeSigned<EventContentResponse> {
+        serde_json::from_value(serde_json::json!({
+            "subject_id": "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+            "event_request": {
+                "Fact": {
+                    "subject_id": "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+                    "payload": { "temperature": 12 },
+                },
+                "signature": signature(),
+            },
+            "gov_version": 0,
+            "sn": sn,
+            "patch": [],
+            "state_hash": "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+            "eval_success": true,
+            "appr_required": false,
+            "approved": true,
+            "hash_prev_event": hash_prev_event,
+            "evaluators": [],
+            "approvers": [],
+            "signature": signature(),
+        }))
+        .unwrap()
+    }
+
+    /// Hashes signed for the events of the tests.
+    const HASHES: [&str; 3] = [
+        "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+        "Jp7_QFbTOlFCv-NhDrFMsFLDd2LVSNwoQjBYpEw0Fp3E",
+        "JBvSw8SyW6rCf3dQCEqNh8CqLHMMwsRDgBDuR9UHDhJg",
+    ];
+
+    /// Events of a subject, each pointing to the hash signed for the previous one.
+    fn chain(length: usize) -> Vec<NodeSigned<EventContentResponse>> {
+        let mut events: Vec<NodeSigned<EventContentResponse>> = Vec::new();
+        for (sn, hash) in HASHES.iter().enumerate().take(length) {
+            let previous_hash = events
+                .last()
+                .map(|previous| previous.signature.content_hash().to_owned())
+                .unwrap_or_default();
+            let mut event = event(sn as u64, &previous_hash);
+            let mut signature = signature();
+            signature["content_hash"] = serde_json::json!(hash);
+            event.signature = serde_json::from_value(signature).unwrap();
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn test_check_chain() {
+        let subject_id = HASHES[0];
+        let events = chain(3);
+        assert!(check_chain(subject_id, &events, None).is_ok());
+
+        let gap = vec![events[0].clone(), events[2].clone()];
+        assert!(matches!(
+            check_chain(subject_id, &gap, None),
+            Err(NodeError::InvalidParameter(_))
+        ));
+
+        let mut unchained = events.clone();
+        unchained[2].content.hash_prev_event = HASHES[0].to_owned();
+        assert!(matches!(
+            check_chain(subject_id, &unchained, None),
+            Err(NodeError::InvalidParameter(_))
+        ));
+
+        assert!(matches!(
+            check_chain(HASHES[1], &events, None),
+            Err(NodeError::InvalidParameter(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_event() {
+        // The signature of the fixture was not made on its event.
+        assert!(matches!(
+            verify_subject(HASHES[0], &chain(1), None, DigestDerivator::Blake3_256),
+            Err(NodeError::InvalidSignature(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_json_lines_reader() {
+        let mut archive = String::from("\n");
+        for event in chain(2) {
+            let record = EventRecord::Event {
+                subject_id: event.content.subject_id.clone(),
+                event: Box::new(event),
+            };
+            archive.push_str(&serde_json::to_string(&record).unwrap());
+            archive.push('\n');
+        }
+        let mut records = RecordReader::new(archive.as_bytes(), EventExportFormat::JsonLines)
+            .await
+            .unwrap();
+        for sn in 0..2 {
+            let record = records.next().await.unwrap();
+            assert!(
+                matches!(record, Some(EventRecord::Event { event, .. }) if event.content.sn == sn)
+            );
+        }
+        assert!(records.next().await.unwrap().is_none());
+
+        let mut invalid = RecordReader::new("{}\n".as_bytes(), EventExportFormat::JsonLines)
+            .await
+            .unwrap();
+        assert!(matches!(
+            invalid.next().await,
+            Err(NodeError::InvalidParameter(_))
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_verify_events() {
+        use crate::{api::tests::create_event, node::tests::export_sqlite_api};
+
+        let api = export_sqlite_api(236, vec![]).await;
+        let governance_id = create_event(&api, "", "governance", "audited").await;
+        let mut archive = Vec::new();
+        api.export_events(&governance_id, &mut archive, EventExportFormat::JsonLines)
+            .await
+            .unwrap();
+        let report = api
+            .verify_events(&mut archive.as_slice(), EventExportFormat::JsonLines)
+            .await
+            .unwrap();
+        assert_eq!((report.subjects, report.verified), (1, 1));
+
+        // A tampered archive is refused.
+        let tampered = String::from_utf8(archive)
+            .unwrap()
+            .replace("\"sn\":0", "\"sn\":1");
+        assert!(api
+            .verify_events(&mut tampered.as_bytes(), EventExportFormat::JsonLines)
+            .await
+            .is_err());
+    }
+}
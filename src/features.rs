@@ -0,0 +1,197 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Feature flags.
+//!
+//! Experimental behaviors of the node are gated by flags, so that operators can roll them out
+//! gradually across a fleet. Flags start with the values of `kore.features` (off when missing),
+//! and are toggled while the node runs with `KoreApi::set_feature_flag`, the admin routes of the
+//! REST API or a reload of the configuration. Every toggle is recorded in the node history.
+//!
+//! | Flag | Behavior |
+//! |------|----------|
+//! | `auto_approval` | Accept the approvals pending a vote of the node that match `approvalRules` |
+//!
+//! `auto_approval` only accepts the approvals of the subjects matched by a rule of
+//! `approvalRules`, by governance, schema or both; the others are left for a manual vote, and so
+//! is every approval when there is no rule.
+//!
+
+use std::time::Duration;
+
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::{
+    model::{NodeApprovalEntity, NodeEventRequest, NodeSubjectData, PatchVote},
+    settings::ApprovalRule,
+    tasks::NodeTasks,
+    KoreApi,
+};
+
+/// Accept the approvals pending a vote of the node that match the approval rules.
+pub const AUTO_APPROVAL: &str = "auto_approval";
+
+/// Known flags and the behavior each one gates.
+pub const FEATURE_FLAGS: &[(&str, &str)] = &[(
+    AUTO_APPROVAL,
+    "Accept the approvals pending a vote of the node that match the approval rules",
+)];
+
+/// Schema of the governances.
+const GOVERNANCE_SCHEMA: &str = "governance";
+
+/// Time between two votes of the pending approvals.
+const AUTO_APPROVAL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Identity of the votes of the auto approval, in the access logs.
+const AUTO_APPROVAL_SOURCE: &str = "auto_approval";
+
+/// Behavior gated by a flag, none for unknown flags.
+pub fn feature_description(name: &str) -> Option<&'static str> {
+    FEATURE_FLAGS
+        .iter()
+        .find(|(flag, _)| *flag == name)
+        .map(|(_, description)| *description)
+}
+
+/// Whether a rule accepts the approvals of a subject. A governance is matched by its own
+/// identifier.
+///
+/// # Arguments
+///
+/// * `rules` - Approval rules.
+/// * `subject` - Subject of the approval.
+///
+fn approval_accepted(rules: &[ApprovalRule], subject: &NodeSubjectData) -> bool {
+    let governance_id = if subject.schema_id == GOVERNANCE_SCHEMA {
+        &subject.subject_id
+    } else {
+        &subject.governance_id
+    };
+    rules.iter().any(|rule| {
+        (rule.governance_id.is_empty() || rule.governance_id == *governance_id)
+            && (rule.schema_id.is_empty() || rule.schema_id == subject.schema_id)
+    })
+}
+
+/// Subject of a pending approval, none for requests without one.
+fn approval_subject(approval: &NodeApprovalEntity) -> Option<&str> {
+    match &approval.request.content.event_request.request {
+        NodeEventRequest::Fact(request) => Some(&request.subject_id),
+        NodeEventRequest::Transfer(request) => Some(&request.subject_id),
+        NodeEventRequest::EOL(request) => Some(&request.subject_id),
+        NodeEventRequest::Create(_) => None,
+    }
+}
+
+/// Accept the pending approvals that match the approval rules periodically while
+/// `auto_approval` is on, until the node is cancelled.
+///
+/// # Arguments
+///
+/// * `api` - Kore API of the node.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_auto_approval(api: KoreApi, tasks: &NodeTasks) {
+    let cancellation = tasks.token();
+    let api = api
+        .with_cancellation(cancellation.clone())
+        .background(AUTO_APPROVAL_SOURCE);
+    tasks.spawn(async move {
+        let mut interval = interval(AUTO_APPROVAL_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = cancellation.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            if !api.is_feature_enabled(AUTO_APPROVAL) {
+                continue;
+            }
+            let pending = match api.get_all_pending_approvals().await {
+                Ok(pending) => pending,
+                Err(error) => {
+                    log::debug!("Pending approvals not read: {}", error);
+                    continue;
+                }
+            };
+            let rules = api.approval_rules();
+            for approval in pending {
+                let Some(subject_id) = approval_subject(&approval) else {
+                    continue;
+                };
+                match api.get_subject(subject_id).await {
+                    Ok(subject) if approval_accepted(&rules, &subject) => {}
+                    Ok(_) => {
+                        log::debug!("Approval {} matches no approval rule", approval.id);
+                        continue;
+                    }
+                    Err(error) => {
+                        log::debug!("Subject of approval {} not read: {}", approval.id, error);
+                        continue;
+                    }
+                }
+                match api
+                    .approval_request(&approval.id, PatchVote::RespondedAccepted)
+                    .await
+                {
+                    Ok(_) => log::info!("Approval {} accepted by {}", approval.id, AUTO_APPROVAL),
+                    Err(error) => log::warn!("Approval {} not accepted: {}", approval.id, error),
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_feature_description() {
+        assert!(feature_description(AUTO_APPROVAL).is_some());
+        assert!(feature_description("sync_scheduler").is_none());
+    }
+
+    #[test]
+    fn test_approval_accepted() {
+        let subject = |subject_id: &str, governance_id: &str, schema_id: &str| {
+            serde_json::from_value::<NodeSubjectData>(serde_json::json!({
+                "subject_id": subject_id,
+                "governance_id": governance_id,
+                "sn": 0,
+                "public_key": "",
+                "namespace": "",
+                "name": "",
+                "schema_id": schema_id,
+                "owner": "",
+                "creator": "",
+                "properties": {},
+                "active": true,
+            }))
+            .unwrap()
+        };
+        let rule = |governance_id: &str, schema_id: &str| ApprovalRule {
+            governance_id: governance_id.to_owned(),
+            schema_id: schema_id.to_owned(),
+        };
+        let governance = subject("Jgov", "", GOVERNANCE_SCHEMA);
+        let car = subject("Jcar", "Jgov", "car");
+
+        assert!(!approval_accepted(&[], &governance));
+        assert!(approval_accepted(&[rule("", "")], &car));
+        assert!(approval_accepted(&[rule("Jgov", "")], &governance));
+        assert!(approval_accepted(&[rule("Jgov", "")], &car));
+        assert!(approval_accepted(
+            &[rule("", GOVERNANCE_SCHEMA)],
+            &governance
+        ));
+        assert!(!approval_accepted(&[rule("", GOVERNANCE_SCHEMA)], &car));
+        assert!(!approval_accepted(&[rule("Jother", "car")], &car));
+        assert!(approval_accepted(
+            &[rule("Jother", "car"), rule("Jgov", "car")],
+            &car
+        ));
+    }
+}
@@ -0,0 +1,663 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Governance updates.
+//!
+//! Typed changes of a governance, sent as a single Fact event whose JSON patch is written from
+//! the current properties of the governance:
+//!
+//! ```ignore
+//! let change = api
+//!     .governance(&governance_id)
+//!     .add_member(&controller_id, "Node2")
+//!     .add_role(GovernanceRole::new("WITNESS", RoleSchema::All, RoleWho::Members))
+//!     .auto_approve(Duration::from_secs(30))
+//!     .submit()
+//!     .await?;
+//! ```
+//!
+//! Members and schemas are appended, and refused when their id, or the name of a member, is
+//! already in the governance; `set_policy` replaces the policy with the same id, or appends it,
+//! and `set_contract` replaces the contract of a schema already in the governance.
+//! With `auto_approve`, the node votes to accept the approval of the change once it is pending,
+//! which only succeeds when the node is an approver of the governance.
+//!
+
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::time::Instant;
+
+use crate::{
+    api::STATE_POLL_INTERVAL,
+    error::NodeError,
+    model::{
+        NodeEventRequest, NodeFactRequest, NodeGetApprovals, NodeRequestOrigin,
+        NodeSignedEventRequest, PatchVote,
+    },
+    KoreApi,
+};
+
+/// Source of the requests sent by the governance updates.
+const GOVERNANCE_SOURCE: &str = "governance";
+
+/// Schemas a role applies to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RoleSchema {
+    /// The schema with this id, e.g. `governance`.
+    Id(String),
+    /// Every schema.
+    All,
+    /// Every schema but the governance.
+    NotGovernance,
+}
+
+/// Who holds a role.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RoleWho {
+    /// Member with this key identifier.
+    Id(String),
+    /// Member with this name.
+    Name(String),
+    /// Every member.
+    Members,
+    /// Anyone.
+    All,
+    /// Anyone who is not a member.
+    NotMembers,
+}
+
+/// Role of the governance.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GovernanceRole {
+    /// Namespace of the subjects, empty for all of them.
+    pub namespace: String,
+    /// Role, e.g. `WITNESS`, `APPROVER`, `EVALUATOR`, `VALIDATOR`, `CREATOR` or `ISSUER`.
+    pub role: String,
+    /// Schemas of the subjects.
+    pub schema: RoleSchema,
+    /// Holders of the role.
+    pub who: RoleWho,
+}
+
+impl GovernanceRole {
+    /// Role over every namespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `role` - Role, e.g. `WITNESS`.
+    /// * `schema` - Schemas of the subjects.
+    /// * `who` - Holders of the role.
+    ///
+    pub fn new(role: &str, schema: RoleSchema, who: RoleWho) -> Self {
+        Self {
+            namespace: String::new(),
+            role: role.to_owned(),
+            schema,
+            who,
+        }
+    }
+}
+
+/// Votes needed by a stage of a policy.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Quorum {
+    /// More than half of the voters.
+    Majority,
+    /// A number of voters.
+    Fixed(u64),
+    /// A share of the voters, from 0 to 1.
+    Percentage(f64),
+}
+
+/// Policy of a schema: the quorums of its approval, evaluation and validation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GovernancePolicy {
+    /// Schema id.
+    pub id: String,
+    /// Quorum of the approvers.
+    pub approve: Quorum,
+    /// Quorum of the evaluators.
+    pub evaluate: Quorum,
+    /// Quorum of the validators.
+    pub validate: Quorum,
+}
+
+impl GovernancePolicy {
+    /// Policy with a majority at every stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Schema id.
+    ///
+    pub fn majority(id: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            approve: Quorum::Majority,
+            evaluate: Quorum::Majority,
+            validate: Quorum::Majority,
+        }
+    }
+
+    /// Policy as written in the governance.
+    fn value(&self) -> Value {
+        json!({
+            "id": self.id,
+            "approve": { "quorum": self.approve },
+            "evaluate": { "quorum": self.evaluate },
+            "validate": { "quorum": self.validate },
+        })
+    }
+}
+
+/// Schema of the subjects of the governance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GovernanceSchema {
+    /// Schema id.
+    pub id: String,
+    /// JSON schema of the properties.
+    pub schema: Value,
+    /// Properties of new subjects.
+    pub initial_value: Value,
+    /// Contract, base64 encoded source.
+    pub contract: String,
+}
+
+impl GovernanceSchema {
+    /// Schema as written in the governance.
+    fn value(&self) -> Value {
+        json!({
+            "id": self.id,
+            "schema": self.schema,
+            "initial_value": self.initial_value,
+            "contract": { "raw": self.contract },
+        })
+    }
+}
+
+/// Change of a governance.
+#[derive(Debug, Clone, PartialEq)]
+enum Change {
+    Member { id: String, name: String },
+    Role(GovernanceRole),
+    Policy(GovernancePolicy),
+    Schema(GovernanceSchema),
+    Contract { schema_id: String, source: String },
+}
+
+/// Changes sent to a governance.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GovernanceChange {
+    /// JSON patch operations of the Fact event.
+    pub patch: Vec<Value>,
+    /// Fact request sent.
+    pub request_id: String,
+    /// Approval voted by the node, with `auto_approve`.
+    pub approval_id: Option<String>,
+}
+
+/// Changes of a governance, sent together by `submit`. See `KoreApi::governance`.
+pub struct GovernanceUpdate {
+    api: KoreApi,
+    governance_id: String,
+    changes: Vec<Change>,
+    auto_approve: Option<Duration>,
+}
+
+impl GovernanceUpdate {
+    /// Update of a governance.
+    ///
+    /// # Arguments
+    ///
+    /// * `api` - Kore API that signs and sends the Fact event.
+    /// * `governance_id` - Governance identifier.
+    ///
+    pub fn new(api: KoreApi, governance_id: &str) -> Self {
+        Self {
+            api,
+            governance_id: governance_id.to_owned(),
+            changes: vec![],
+            auto_approve: None,
+        }
+    }
+
+    /// Add a member.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Key identifier of the member, e.g. the controller id of a node.
+    /// * `name` - Name of the member, unique in the governance.
+    ///
+    pub fn add_member(mut self, id: &str, name: &str) -> Self {
+        self.changes.push(Change::Member {
+            id: id.to_owned(),
+            name: name.to_owned(),
+        });
+        self
+    }
+
+    /// Add a role.
+    ///
+    /// # Arguments
+    ///
+    /// * `role` - Role to add.
+    ///
+    pub fn add_role(mut self, role: GovernanceRole) -> Self {
+        self.changes.push(Change::Role(role));
+        self
+    }
+
+    /// Set the policy of a schema, replacing the current one.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Policy to set.
+    ///
+    pub fn set_policy(mut self, policy: GovernancePolicy) -> Self {
+        self.changes.push(Change::Policy(policy));
+        self
+    }
+
+    /// Register a schema.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - Schema to register.
+    ///
+    pub fn register_schema(mut self, schema: GovernanceSchema) -> Self {
+        self.changes.push(Change::Schema(schema));
+        self
+    }
+
+    /// Replace the contract of a schema of the governance.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema_id` - Schema id.
+    /// * `source` - Source of the contract, base64 encoded by the update.
+    ///
+    pub fn set_contract(mut self, schema_id: &str, source: &str) -> Self {
+        self.changes.push(Change::Contract {
+            schema_id: schema_id.to_owned(),
+            source: source.to_owned(),
+        });
+        self
+    }
+
+    /// Accept the approval of the change with the node key, waiting for it at most `timeout`.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Time to wait for the approval to be pending.
+    ///
+    pub fn auto_approve(mut self, timeout: Duration) -> Self {
+        self.auto_approve = Some(timeout);
+        self
+    }
+
+    /// JSON patch of the changes over the current properties of the governance.
+    ///
+    /// # Arguments
+    ///
+    /// * `properties` - Properties of the governance.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - No changes.
+    /// * `NodeError::Conflict` - A member or schema whose id is already in the governance.
+    /// * `NodeError::NotFound` - A contract of a schema that is not in the governance.
+    ///
+    pub fn patch(&self, properties: &Value) -> Result<Vec<Value>, NodeError> {
+        changes_patch(&self.changes, properties)
+    }
+
+    /// Send the changes as a Fact event signed by the node, and accept its approval with
+    /// `auto_approve`.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - The subject is not a governance, or no changes.
+    /// * `NodeError::Conflict` - A member or schema whose id is already in the governance.
+    /// * `NodeError::NotFound` - A contract of a schema that is not in the governance.
+    /// * `NodeError::Timeout` - The approval was not pending before the timeout.
+    /// * Any error of `get_subject`, `send_event_request` or `approval_request`.
+    ///
+    /// # Returns
+    ///
+    /// * `GovernanceChange` - Patch, request and approval.
+    ///
+    pub async fn submit(self) -> Result<GovernanceChange, NodeError> {
+        let governance = self.api.get_subject(&self.governance_id).await?;
+        if governance.schema_id != "governance" {
+            return Err(NodeError::InvalidParameter(format!(
+                "{} is not a governance",
+                self.governance_id
+            )));
+        }
+        let patch = self.patch(&governance.properties)?;
+        let payload = json!({ "Patch": { "data": patch } });
+        let response = self
+            .api
+            .send_event_request(NodeSignedEventRequest {
+                request: NodeEventRequest::Fact(NodeFactRequest {
+                    subject_id: self.governance_id.clone(),
+                    payload: payload.clone(),
+                }),
+                signature: None,
+                origin: Some(NodeRequestOrigin {
+                    source: Some(GOVERNANCE_SOURCE.to_owned()),
+                    device_id: None,
+                    geo_hint: None,
+                }),
+            })
+            .await?;
+        let approval_id = match self.auto_approve {
+            Some(timeout) => Some(self.approve(&payload, timeout).await?),
+            None => None,
+        };
+        Ok(GovernanceChange {
+            patch,
+            request_id: response.request_id,
+            approval_id,
+        })
+    }
+
+    /// Accept the pending approval of the Fact event with `payload`.
+    async fn approve(&self, payload: &Value, timeout: Duration) -> Result<String, NodeError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let pending = self
+                .api
+                .get_approvals(NodeGetApprovals {
+                    status: Some("pending".to_owned()),
+                    from: None,
+                    quantity: None,
+                })
+                .await?;
+            let approval = pending.items.into_iter().find(|approval| {
+                matches!(
+                    &approval.request.content.event_request.request,
+                    NodeEventRequest::Fact(fact)
+                        if fact.subject_id == self.governance_id && &fact.payload == payload
+                )
+            });
+            if let Some(approval) = approval {
+                self.api
+                    .approval_request(&approval.id, PatchVote::RespondedAccepted)
+                    .await?;
+                return Ok(approval.id);
+            }
+            if Instant::now() >= deadline {
+                return Err(NodeError::Timeout);
+            }
+            tokio::time::sleep_until((Instant::now() + STATE_POLL_INTERVAL).min(deadline)).await;
+        }
+    }
+}
+
+/// JSON patch of changes over the properties of a governance, see `GovernanceUpdate::patch`.
+fn changes_patch(changes: &[Change], properties: &Value) -> Result<Vec<Value>, NodeError> {
+    if changes.is_empty() {
+        return Err(NodeError::InvalidParameter(
+            "no changes to the governance".to_owned(),
+        ));
+    }
+    let mut current = properties.clone();
+    let mut patch = vec![];
+    for change in changes {
+        if let Change::Contract { schema_id, source } = change {
+            let index = schema_index(&current, schema_id)?;
+            let value = json!({ "raw": STANDARD.encode(source) });
+            patch.push(json!({
+                "op": "replace",
+                "path": format!("/schemas/{}/contract", index),
+                "value": value,
+            }));
+            current["schemas"][index]["contract"] = value;
+            continue;
+        }
+        let (section, unique, value) = match change {
+            Change::Member { id, name } => (
+                "members",
+                vec![("id", id), ("name", name)],
+                json!({ "id": id, "name": name }),
+            ),
+            Change::Role(role) => ("roles", vec![], json!(role)),
+            Change::Policy(policy) => ("policies", vec![], policy.value()),
+            Change::Schema(schema) => ("schemas", vec![("id", &schema.id)], schema.value()),
+            Change::Contract { .. } => continue,
+        };
+        if current.get(section).and_then(Value::as_array).is_none() {
+            current[section] = json!([]);
+        }
+        let Some(entries) = current[section].as_array_mut() else {
+            continue;
+        };
+        for (field, unique) in unique {
+            if entries.iter().any(|entry| entry[field] == **unique) {
+                return Err(NodeError::Conflict(format!(
+                    "{} already has an entry with {} {}",
+                    section, field, unique
+                )));
+            }
+        }
+        let replaced = match change {
+            Change::Policy(policy) => entries.iter().position(|entry| entry["id"] == *policy.id),
+            _ => None,
+        };
+        match replaced {
+            Some(index) => {
+                patch.push(json!({
+                    "op": "replace",
+                    "path": format!("/{}/{}", section, index),
+                    "value": value,
+                }));
+                entries[index] = value;
+            }
+            None => {
+                patch.push(json!({
+                    "op": "add",
+                    "path": format!("/{}/-", section),
+                    "value": value,
+                }));
+                entries.push(value);
+            }
+        }
+    }
+    Ok(patch)
+}
+
+/// Position of a schema in the properties of a governance.
+fn schema_index(properties: &Value, schema_id: &str) -> Result<usize, NodeError> {
+    properties
+        .get("schemas")
+        .and_then(Value::as_array)
+        .and_then(|schemas| schemas.iter().position(|schema| schema["id"] == schema_id))
+        .ok_or_else(|| {
+            NodeError::NotFound(format!("schema {} is not in the governance", schema_id))
+        })
+}
+
+/// Source of the contract of a schema, from the properties of its governance.
+///
+/// # Arguments
+///
+/// * `properties` - Properties of the governance.
+/// * `schema_id` - Schema id.
+///
+/// # Errors
+///
+/// * `NodeError::NotFound` - The schema is not in the governance.
+/// * `NodeError::InvalidParameter` - The contract is not base64 encoded UTF-8.
+///
+pub(crate) fn contract_source(properties: &Value, schema_id: &str) -> Result<String, NodeError> {
+    let index = schema_index(properties, schema_id)?;
+    let raw = properties["schemas"][index]["contract"]["raw"]
+        .as_str()
+        .unwrap_or_default();
+    STANDARD
+        .decode(raw)
+        .ok()
+        .and_then(|source| String::from_utf8(source).ok())
+        .ok_or_else(|| {
+            NodeError::InvalidParameter(format!(
+                "the contract of schema {} is not base64 encoded UTF-8",
+                schema_id
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_role_value() {
+        let role = GovernanceRole::new(
+            "WITNESS",
+            RoleSchema::Id("governance".to_owned()),
+            RoleWho::Name("Node2".to_owned()),
+        );
+        assert_eq!(
+            json!(role),
+            json!({
+                "namespace": "",
+                "role": "WITNESS",
+                "schema": { "ID": "governance" },
+                "who": { "NAME": "Node2" }
+            })
+        );
+        let role = GovernanceRole::new("CREATOR", RoleSchema::NotGovernance, RoleWho::Members);
+        assert_eq!(json!(role)["schema"], "NOT_GOVERNANCE");
+        assert_eq!(json!(role)["who"], "MEMBERS");
+        assert_eq!(
+            GovernancePolicy {
+                approve: Quorum::Fixed(2),
+                ..GovernancePolicy::majority("wine")
+            }
+            .value()["approve"],
+            json!({ "quorum": { "FIXED": 2 } })
+        );
+    }
+
+    #[test]
+    fn test_changes_patch() {
+        let properties = json!({
+            "members": [{ "id": "EOwner", "name": "Owner" }],
+            "roles": [],
+            "schemas": [],
+            "policies": [GovernancePolicy::majority("governance").value()]
+        });
+        let member = |id: &str, name: &str| Change::Member {
+            id: id.to_owned(),
+            name: name.to_owned(),
+        };
+        let changes = vec![
+            member("ENode2", "Node2"),
+            Change::Policy(GovernancePolicy {
+                approve: Quorum::Percentage(0.5),
+                ..GovernancePolicy::majority("governance")
+            }),
+            Change::Policy(GovernancePolicy::majority("wine")),
+            Change::Policy(GovernancePolicy::majority("wine")),
+        ];
+        let patch = changes_patch(&changes, &properties).unwrap();
+        assert_eq!(patch.len(), 4);
+        assert_eq!(patch[0]["path"], "/members/-");
+        assert_eq!(patch[1]["op"], "replace");
+        assert_eq!(patch[1]["path"], "/policies/0");
+        assert_eq!(patch[2]["path"], "/policies/-");
+        assert_eq!(patch[3]["path"], "/policies/1");
+
+        let res = changes_patch(&[member("EOther", "Owner")], &properties);
+        assert!(matches!(res, Err(NodeError::Conflict(_))));
+        let res = changes_patch(
+            &[member("ENode2", "Node2"), member("ENode2", "Other")],
+            &json!({}),
+        );
+        assert!(matches!(res, Err(NodeError::Conflict(_))));
+        assert!(matches!(
+            changes_patch(&[], &properties),
+            Err(NodeError::InvalidParameter(_))
+        ));
+    }
+
+    #[test]
+    fn test_contract_patch() {
+        let schema = GovernanceSchema {
+            id: "wine".to_owned(),
+            schema: json!({}),
+            initial_value: json!({}),
+            contract: STANDARD.encode("fn main() {}"),
+        };
+        let mut properties = json!({ "schemas": [schema.value()] });
+        assert_eq!(
+            contract_source(&properties, "wine").unwrap(),
+            "fn main() {}"
+        );
+
+        let contract = |schema_id: &str| Change::Contract {
+            schema_id: schema_id.to_owned(),
+            source: "fn main() { run() }".to_owned(),
+        };
+        let patch = changes_patch(&[contract("wine")], &properties).unwrap();
+        assert_eq!(
+            patch,
+            vec![json!({
+                "op": "replace",
+                "path": "/schemas/0/contract",
+                "value": { "raw": STANDARD.encode("fn main() { run() }") },
+            })]
+        );
+        properties["schemas"][0]["contract"] = patch[0]["value"].clone();
+        assert_eq!(
+            contract_source(&properties, "wine").unwrap(),
+            "fn main() { run() }"
+        );
+
+        // A schema registered by the same update has a contract to replace.
+        let changes = [
+            Change::Schema(GovernanceSchema {
+                id: "cheese".to_owned(),
+                ..schema
+            }),
+            contract("cheese"),
+        ];
+        let patch = changes_patch(&changes, &properties).unwrap();
+        assert_eq!(patch[1]["path"], "/schemas/1/contract");
+
+        assert!(matches!(
+            changes_patch(&[contract("beer")], &properties),
+            Err(NodeError::NotFound(_))
+        ));
+        properties["schemas"][0]["contract"]["raw"] = json!("not base64!");
+        assert!(matches!(
+            contract_source(&properties, "wine"),
+            Err(NodeError::InvalidParameter(_))
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_governance_update() {
+        use crate::api::tests::create_event;
+
+        let api = crate::node::tests::export_sqlite_api(233, vec![]).await;
+        let governance_id = create_event(&api, "", "governance", "typed").await;
+        let change = api
+            .governance(&governance_id)
+            .add_member("EnyisBz0lX9sRvvV0H-BXTrVtARjUa0YDHzaxFHWH-N4", "Test1")
+            .auto_approve(Duration::from_secs(30))
+            .submit()
+            .await
+            .unwrap();
+        assert_eq!(change.patch.len(), 1);
+        assert!(change.approval_id.is_some());
+    }
+}
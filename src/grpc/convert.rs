@@ -0,0 +1,273 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Conversions between the protobuf messages and the node model.
+//!
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use super::proto;
+use crate::{
+    error::NodeError,
+    model::{
+        KeyAlgorithms, NodeApprovalEntity, NodeEOLRequest, NodeEventRequest, NodeFactRequest,
+        NodeGetApprovals, NodeKeys, NodeKoreRequestState, NodeRequestOrigin,
+        NodeSignedEventRequest, NodeStartRequest, NodeSubjectData, NodeSubjectKeys, NodeSubjects,
+        NodeTransferRequest, Page,
+    },
+};
+
+/// Parse a JSON encoded field.
+fn from_json<T: DeserializeOwned>(field: &str, json: &str) -> Result<T, NodeError> {
+    serde_json::from_str(json)
+        .map_err(|error| NodeError::InvalidParameter(format!("{}: {}", field, error)))
+}
+
+/// Encode a value as JSON, empty if it cannot be encoded.
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// Name of a unit variant, as in the REST API.
+fn variant<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(name)) => name,
+        Ok(other) => other.to_string(),
+        Err(_) => String::default(),
+    }
+}
+
+impl TryFrom<proto::EventRequest> for NodeSignedEventRequest {
+    type Error = NodeError;
+
+    fn try_from(message: proto::EventRequest) -> Result<Self, Self::Error> {
+        use proto::event_request::Request;
+
+        let request = match message.request {
+            Some(Request::Create(create)) => NodeEventRequest::Create(NodeStartRequest {
+                governance_id: create.governance_id,
+                schema_id: create.schema_id,
+                namespace: create.namespace,
+                name: create.name,
+                public_key: create.public_key,
+            }),
+            Some(Request::Fact(fact)) => NodeEventRequest::Fact(NodeFactRequest {
+                payload: from_json("payload_json", &fact.payload_json)?,
+                subject_id: fact.subject_id,
+            }),
+            Some(Request::Transfer(transfer)) => NodeEventRequest::Transfer(NodeTransferRequest {
+                subject_id: transfer.subject_id,
+                public_key: transfer.public_key,
+            }),
+            Some(Request::Eol(eol)) => NodeEventRequest::EOL(NodeEOLRequest {
+                subject_id: eol.subject_id,
+            }),
+            None => {
+                return Err(NodeError::InvalidParameter(
+                    "missing event request".to_owned(),
+                ))
+            }
+        };
+        let signature = message
+            .signature_json
+            .map(|json| from_json("signature_json", &json))
+            .transpose()?;
+        Ok(Self {
+            request,
+            signature,
+            origin: message.origin.map(|origin| NodeRequestOrigin {
+                source: origin.source,
+                device_id: origin.device_id,
+                geo_hint: origin.geo_hint,
+            }),
+        })
+    }
+}
+
+impl From<NodeKoreRequestState> for proto::EventRequestState {
+    fn from(state: NodeKoreRequestState) -> Self {
+        Self {
+            id: state.id,
+            subject_id: state.subject_id,
+            sn: state.sn,
+            state: variant(&state.state),
+            success: state.success,
+        }
+    }
+}
+
+impl From<proto::GetApprovalsRequest> for NodeGetApprovals {
+    fn from(message: proto::GetApprovalsRequest) -> Self {
+        Self {
+            status: message.status,
+            from: message.from,
+            quantity: message.quantity,
+        }
+    }
+}
+
+impl From<NodeApprovalEntity> for proto::Approval {
+    fn from(approval: NodeApprovalEntity) -> Self {
+        let request = approval.request.content;
+        Self {
+            id: approval.id,
+            state: variant(&approval.state),
+            event_request_json: to_json(&request.event_request),
+            sn: request.sn,
+            gov_version: request.gov_version,
+            patch_json: to_json(&request.patch),
+            state_hash: request.state_hash,
+            hash_prev_event: request.hash_prev_event,
+            approved: approval.reponse.map(|response| response.content.approved),
+        }
+    }
+}
+
+impl From<Page<NodeApprovalEntity>> for proto::Approvals {
+    fn from(page: Page<NodeApprovalEntity>) -> Self {
+        Self {
+            approvals: page.items.into_iter().map(Into::into).collect(),
+            next_cursor: page.next_cursor,
+            total: page.total,
+        }
+    }
+}
+
+impl From<proto::GetSubjectsRequest> for NodeSubjects {
+    fn from(message: proto::GetSubjectsRequest) -> Self {
+        Self {
+            from: message.from,
+            quantity: message.quantity,
+            subject_type: message.subject_type,
+            governanceid: message.governance_id,
+            archive_filter: message.archive_filter,
+        }
+    }
+}
+
+impl From<&proto::GetSubjectsRequest> for NodeSubjectKeys {
+    fn from(message: &proto::GetSubjectsRequest) -> Self {
+        Self {
+            owner: message.owner.clone(),
+            namespace: message.namespace.clone(),
+        }
+    }
+}
+
+impl From<NodeSubjectData> for proto::Subject {
+    fn from(subject: NodeSubjectData) -> Self {
+        Self {
+            properties_json: to_json(&subject.properties),
+            subject_id: subject.subject_id,
+            governance_id: subject.governance_id,
+            sn: subject.sn,
+            public_key: subject.public_key,
+            namespace: subject.namespace,
+            name: subject.name,
+            schema_id: subject.schema_id,
+            owner: subject.owner,
+            creator: subject.creator,
+            active: subject.active,
+            archived: subject.archived,
+        }
+    }
+}
+
+impl From<Page<NodeSubjectData>> for proto::Subjects {
+    fn from(page: Page<NodeSubjectData>) -> Self {
+        Self {
+            subjects: page.items.into_iter().map(Into::into).collect(),
+            next_cursor: page.next_cursor,
+            total: page.total,
+        }
+    }
+}
+
+impl TryFrom<proto::RegisterKeysRequest> for NodeKeys {
+    type Error = NodeError;
+
+    fn try_from(message: proto::RegisterKeysRequest) -> Result<Self, Self::Error> {
+        let algorithm = match message.algorithm.as_deref() {
+            None => None,
+            Some("Ed25519") => Some(KeyAlgorithms::Ed25519),
+            Some("Secp256k1") => Some(KeyAlgorithms::Secp256k1),
+            Some(other) => {
+                return Err(NodeError::InvalidParameter(format!(
+                    "unknown key algorithm {}",
+                    other
+                )))
+            }
+        };
+        Ok(Self { algorithm })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_event_request() {
+        let message = proto::EventRequest {
+            request: Some(proto::event_request::Request::Fact(proto::FactRequest {
+                subject_id: "JSubject".to_owned(),
+                payload_json: r#"{"temperature": 21}"#.to_owned(),
+            })),
+            signature_json: None,
+            origin: Some(proto::RequestOrigin {
+                source: Some("erp".to_owned()),
+                device_id: None,
+                geo_hint: None,
+            }),
+        };
+        let request = NodeSignedEventRequest::try_from(message).unwrap();
+        let NodeEventRequest::Fact(fact) = request.request else {
+            panic!("not a fact request");
+        };
+        assert_eq!(fact.subject_id, "JSubject");
+        assert_eq!(fact.payload, json!({ "temperature": 21 }));
+        assert!(request.signature.is_none());
+        assert_eq!(request.origin.unwrap().source, Some("erp".to_owned()));
+    }
+
+    #[test]
+    fn test_invalid_event_request() {
+        let message = |request| proto::EventRequest {
+            request,
+            signature_json: None,
+            origin: None,
+        };
+        let invalid_payload = message(Some(proto::event_request::Request::Fact(
+            proto::FactRequest {
+                subject_id: "JSubject".to_owned(),
+                payload_json: "{".to_owned(),
+            },
+        )));
+        assert!(matches!(
+            NodeSignedEventRequest::try_from(invalid_payload),
+            Err(NodeError::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            NodeSignedEventRequest::try_from(message(None)),
+            Err(NodeError::InvalidParameter(_))
+        ));
+    }
+
+    #[test]
+    fn test_register_keys() {
+        let keys = |algorithm: Option<&str>| {
+            NodeKeys::try_from(proto::RegisterKeysRequest {
+                algorithm: algorithm.map(str::to_owned),
+            })
+        };
+        assert_eq!(
+            keys(Some("Secp256k1")).unwrap().algorithm,
+            Some(KeyAlgorithms::Secp256k1)
+        );
+        assert_eq!(keys(None).unwrap().algorithm, None);
+        assert!(keys(Some("rsa")).is_err());
+    }
+}
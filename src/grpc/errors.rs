@@ -0,0 +1,88 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Status codes of the gRPC API.
+//!
+
+use tonic::{Code, Status};
+
+use crate::error::NodeError;
+
+impl From<NodeError> for Status {
+    fn from(error: NodeError) -> Self {
+        let code = match error {
+            NodeError::InvalidParameter(_) => Code::InvalidArgument,
+            NodeError::InvalidSignature(_) => Code::Unauthenticated,
+            NodeError::NotFound(_) => Code::NotFound,
+            NodeError::Unauthenticated(_) => Code::Unauthenticated,
+            NodeError::Unauthorized(_) => Code::PermissionDenied,
+            NodeError::Conflict(_) => Code::FailedPrecondition,
+            NodeError::QuotaExceeded(_) => Code::ResourceExhausted,
+            NodeError::RateLimited(_) => Code::ResourceExhausted,
+            NodeError::Timeout => Code::DeadlineExceeded,
+            NodeError::Cancelled => Code::Cancelled,
+            NodeError::Database {
+                retryable: true, ..
+            } => Code::Unavailable,
+            _ => Code::Internal,
+        };
+        Status::new(code, error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_status_code() {
+        let code = |error: NodeError| Status::from(error).code();
+        assert_eq!(
+            code(NodeError::InvalidParameter("invalid subject_id".to_owned())),
+            Code::InvalidArgument
+        );
+        assert_eq!(
+            code(NodeError::InvalidSignature("stale signature".to_owned())),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            code(NodeError::Unauthenticated("invalid API key".to_owned())),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            code(NodeError::QuotaExceeded("3 subjects".to_owned())),
+            Code::ResourceExhausted
+        );
+        assert_eq!(
+            code(NodeError::RateLimited(
+                "writes over the rate limit".to_owned()
+            )),
+            Code::ResourceExhausted
+        );
+        assert_eq!(
+            code(NodeError::NotFound("subject JSubject".to_owned())),
+            Code::NotFound
+        );
+        assert_eq!(
+            code(NodeError::Unauthorized("EOL requests".to_owned())),
+            Code::PermissionDenied
+        );
+        assert_eq!(
+            code(NodeError::Conflict("vote not needed".to_owned())),
+            Code::FailedPrecondition
+        );
+        assert_eq!(code(NodeError::Timeout), Code::DeadlineExceeded);
+        assert_eq!(
+            code(NodeError::Database {
+                message: "database is locked".to_owned(),
+                retryable: true,
+            }),
+            Code::Unavailable
+        );
+        assert_eq!(
+            code(NodeError::InternalApi("Failed to get request".to_owned())),
+            Code::Internal
+        );
+    }
+}
@@ -0,0 +1,463 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # gRPC API.
+//!
+//! The `kore.node.v1.Kore` service of `proto/kore.proto`, served over `KoreApi`. Like the REST
+//! API, each call is served through a handle bound to the address of the client and to the trace
+//! id of the `x-request-id` metadata, generated when missing or not valid and returned in the
+//! response metadata, see `GrpcTrace`. The server is configured in the `[kore.grpc]` section, with
+//! TLS when a certificate and its key are set. Calls and the encoded size of their messages are
+//! accounted to the principal of their credentials, see `auth::Principal`, or else to the IP
+//! address of the client, see `KoreApi::usage`. Calls require the API key or JWT of `kore.auth`
+//! when it is set, see the `auth` module. Clients present their token in the `authorization`
+//! metadata, as `Bearer <token>`, and calls of a surface they may not reach fail with
+//! `PERMISSION_DENIED`, see `surface::authorize`. Event requests must be signed by their
+//! clients, see `PublicApi::send_event_request`.
+//!
+//! | Method | Method of `KoreApi` | Surface |
+//! |---|---|---|
+//! | `SendEventRequest` | `send_event_request` | Public |
+//! | `GetEventRequestState` | `get_event_request_state` | Public |
+//! | `GetApprovals` | `get_approvals` | Public |
+//! | `GetApproval` | `get_approval_id` | Public |
+//! | `VoteApproval` | `approval_request` | Admin |
+//! | `GetSubjects` | `get_subjects` | Public |
+//! | `GetSubject` | `get_subject` | Public |
+//! | `RegisterKeys` | `register_keys` | Admin |
+//!
+
+mod convert;
+mod errors;
+
+use std::{fs, net::SocketAddr};
+
+use prost::Message;
+
+use tonic::{
+    body::BoxBody,
+    codegen::{
+        http::{self, HeaderValue},
+        BoxFuture, Context, Poll, Service,
+    },
+    server::NamedService,
+    transport::{server::TcpIncoming, Identity, Server, ServerTlsConfig},
+    Request, Response, Status,
+};
+
+use crate::{
+    access_log::{accept_trace_id, TRACE_ID_HEADER},
+    api::IDEMPOTENCY_KEY_HEADER,
+    auth::{Authenticator, GrpcAuth, Principal},
+    error::NodeError,
+    model::{NodeSubjectKeys, PatchVote},
+    settings::{ApiAuthSettings, GrpcSettings},
+    surface::{authorize, Surface},
+    tasks::NodeTasks,
+    AdminApi, KoreApi, PublicApi,
+};
+
+/// Code generated from `proto/kore.proto`.
+pub mod proto {
+    tonic::include_proto!("kore.node.v1");
+}
+
+use proto::kore_server::{Kore, KoreServer};
+
+/// Result of a call.
+type GrpcResult<T> = Result<Response<T>, Status>;
+
+/// gRPC service over the Kore API.
+#[derive(Clone)]
+pub struct KoreService {
+    api: KoreApi,
+    auth: ApiAuthSettings,
+}
+
+impl KoreService {
+    /// Create the service, with the default credentials: open public surface and admin surface
+    /// reserved to loopback clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `api` - Kore API served.
+    ///
+    pub fn new(api: KoreApi) -> Self {
+        Self {
+            api,
+            auth: ApiAuthSettings::default(),
+        }
+    }
+
+    /// Set the credentials required from the clients of each surface.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - Tokens of the surfaces.
+    ///
+    pub fn with_auth(mut self, auth: ApiAuthSettings) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Check that the client of the call may reach `surface`.
+    fn authorize<T>(&self, request: &Request<T>, surface: Surface) -> Result<(), NodeError> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let address = request.remote_addr().map(|address| address.ip());
+        authorize(&self.auth, surface, token, address)
+    }
+
+    /// Public surface for the call being served.
+    fn public<T: Message>(&self, request: &Request<T>) -> Result<PublicApi, NodeError> {
+        self.authorize(request, Surface::Public)?;
+        Ok(PublicApi::new(self.caller(request)))
+    }
+
+    /// Admin surface for the call being served.
+    fn admin<T: Message>(&self, request: &Request<T>) -> Result<AdminApi, NodeError> {
+        self.authorize(request, Surface::Admin)?;
+        Ok(AdminApi::new(self.caller(request)))
+    }
+
+    /// Handle of the API for the call being served.
+    fn caller<T: Message>(&self, request: &Request<T>) -> KoreApi {
+        let mut api = self.api.clone();
+        if let Some(address) = request.remote_addr() {
+            api = api.with_identity(&address.to_string());
+            let bytes = request.get_ref().encoded_len() as u64;
+            // Accounted to the principal of the credentials, or else to the client.
+            match request.extensions().get::<Principal>() {
+                Some(Principal(principal)) => api.record_usage(principal, bytes),
+                None => api.record_usage(&address.ip().to_string(), bytes),
+            }
+        }
+        if let Some(trace_id) = request
+            .metadata()
+            .get(TRACE_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            api = api.with_trace_id(trace_id);
+        }
+        api
+    }
+}
+
+/// Layer of the gRPC services that keeps the trace id sent by the caller in the `x-request-id`
+/// metadata, or gives the call a new one, see `access_log::accept_trace_id`, and returns it in
+/// the response metadata.
+#[derive(Clone)]
+pub struct GrpcTrace<S> {
+    inner: S,
+}
+
+impl<S> GrpcTrace<S> {
+    /// Trace the calls of `inner`.
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, B> Service<http::Request<B>> for GrpcTrace<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<S::Response, S::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        // The service polled ready serves this call, its clone the next one.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let incoming = request
+            .headers()
+            .get(TRACE_ID_HEADER)
+            .and_then(|value| value.to_str().ok());
+        let trace_id = HeaderValue::from_str(&accept_trace_id(incoming))
+            .unwrap_or_else(|_| HeaderValue::from_static("-"));
+        request
+            .headers_mut()
+            .insert(TRACE_ID_HEADER, trace_id.clone());
+        Box::pin(async move {
+            let mut response = inner.call(request).await?;
+            response.headers_mut().insert(TRACE_ID_HEADER, trace_id);
+            Ok(response)
+        })
+    }
+}
+
+impl<S: NamedService> NamedService for GrpcTrace<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+#[tonic::async_trait]
+impl Kore for KoreService {
+    async fn send_event_request(
+        &self,
+        request: Request<proto::EventRequest>,
+    ) -> GrpcResult<proto::EventRequestId> {
+        let api = self.public(&request)?;
+        let key = request
+            .metadata()
+            .get(IDEMPOTENCY_KEY_HEADER)
+            .map(|key| key.to_str().map(str::to_owned))
+            .transpose()
+            .map_err(|_| Status::invalid_argument("idempotency key is not text"))?;
+        let event_request = request.into_inner().try_into()?;
+        let response = match key {
+            Some(key) => {
+                api.send_idempotent_event_request(event_request, &key)
+                    .await?
+            }
+            None => api.send_event_request(event_request).await?,
+        };
+        Ok(Response::new(proto::EventRequestId {
+            request_id: response.request_id,
+        }))
+    }
+
+    async fn get_event_request_state(
+        &self,
+        request: Request<proto::EventRequestId>,
+    ) -> GrpcResult<proto::EventRequestState> {
+        let api = self.public(&request)?;
+        let state = api
+            .get_event_request_state(&request.into_inner().request_id)
+            .await?;
+        Ok(Response::new(state.into()))
+    }
+
+    async fn get_approvals(
+        &self,
+        request: Request<proto::GetApprovalsRequest>,
+    ) -> GrpcResult<proto::Approvals> {
+        let api = self.public(&request)?;
+        let approvals = api.get_approvals(request.into_inner().into()).await?;
+        Ok(Response::new(approvals.into()))
+    }
+
+    async fn get_approval(
+        &self,
+        request: Request<proto::ApprovalId>,
+    ) -> GrpcResult<proto::Approval> {
+        let api = self.public(&request)?;
+        let approval = api.get_approval_id(&request.into_inner().id).await?;
+        Ok(Response::new(approval.into()))
+    }
+
+    async fn vote_approval(&self, request: Request<proto::Vote>) -> GrpcResult<proto::Approval> {
+        let api = self.admin(&request)?;
+        let vote = request.into_inner();
+        let response = if vote.accept {
+            PatchVote::RespondedAccepted
+        } else {
+            PatchVote::RespondedRejected
+        };
+        let approval = api.approval_request(&vote.id, response).await?;
+        Ok(Response::new(approval.into()))
+    }
+
+    async fn get_subjects(
+        &self,
+        request: Request<proto::GetSubjectsRequest>,
+    ) -> GrpcResult<proto::Subjects> {
+        let api = self.public(&request)?;
+        let request = request.into_inner();
+        let keys = NodeSubjectKeys::from(&request);
+        let subjects = api.get_subjects_by(request.into(), keys).await?;
+        Ok(Response::new(subjects.into()))
+    }
+
+    async fn get_subject(&self, request: Request<proto::SubjectId>) -> GrpcResult<proto::Subject> {
+        let api = self.public(&request)?;
+        let subject = api.get_subject(&request.into_inner().subject_id).await?;
+        Ok(Response::new(subject.into()))
+    }
+
+    async fn register_keys(
+        &self,
+        request: Request<proto::RegisterKeysRequest>,
+    ) -> GrpcResult<proto::PublicKey> {
+        let api = self.admin(&request)?;
+        let public_key = api.register_keys(request.into_inner().try_into()?).await?;
+        Ok(Response::new(proto::PublicKey { public_key }))
+    }
+}
+
+/// Serve the gRPC API until the node is cancelled.
+/// The TLS files are read before returning, so that a missing certificate stops the node start.
+///
+/// # Arguments
+///
+/// * `api` - Kore API served.
+/// * `settings` - Address and TLS files of the server.
+/// * `auth` - Credentials required from the clients of each surface.
+/// * `authenticator` - Checks the API keys and JWTs of the calls, see `auth`.
+/// * `tasks` - Tasks of the node, the server stops when it is cancelled.
+///
+/// # Errors
+///
+/// * `NodeError::InvalidParameter` - The address is not valid.
+/// * `NodeError::InternalApi` - The TLS certificate or key cannot be loaded.
+/// * `NodeError::Bind` - The address cannot be bound.
+///
+pub fn run_grpc(
+    api: KoreApi,
+    settings: &GrpcSettings,
+    auth: &ApiAuthSettings,
+    authenticator: Authenticator,
+    tasks: &NodeTasks,
+) -> Result<(), NodeError> {
+    let address: SocketAddr = settings
+        .listen
+        .parse()
+        .map_err(|_| NodeError::InvalidParameter(format!("gRPC address {}", settings.listen)))?;
+    let mut server = Server::builder();
+    if !settings.tls_cert.is_empty() {
+        let read = |path: &str| {
+            fs::read(path).map_err(|error| {
+                NodeError::InternalApi(format!("Error reading gRPC TLS file {}: {}", path, error))
+            })
+        };
+        let identity = Identity::from_pem(read(&settings.tls_cert)?, read(&settings.tls_key)?);
+        server = server
+            .tls_config(ServerTlsConfig::new().identity(identity))
+            .map_err(|error| NodeError::InternalApi(format!("gRPC TLS error: {}", error)))?;
+    }
+    let router = server.add_service(GrpcTrace::new(GrpcAuth::new(
+        KoreServer::new(KoreService::new(api).with_auth(auth.clone())),
+        authenticator,
+    )));
+
+    let incoming = TcpIncoming::new(address, false, None).map_err(|error| {
+        log::error!("gRPC cannot listen on {}: {}", address, error);
+        NodeError::Bind {
+            address: address.to_string(),
+            cause: error.to_string(),
+        }
+    })?;
+
+    let cancellation = tasks.token();
+    tasks.spawn(async move {
+        if let Err(error) = router
+            .serve_with_incoming_shutdown(incoming, cancellation.cancelled_owned())
+            .await
+        {
+            log::error!("gRPC server error on {}: {}", address, error);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+
+    use super::*;
+    use crate::api::tests::create_event;
+    use crate::node::tests::export_sqlite_api;
+    use tonic::Code;
+
+    #[tokio::test]
+    async fn test_sqlite_grpc_service() {
+        let api = export_sqlite_api(217, vec![]).await;
+        let governance_id = create_event(&api, "", "governance", "grpc").await;
+        let service = KoreService::new(api);
+
+        let subject = service
+            .get_subject(Request::new(proto::SubjectId {
+                subject_id: governance_id.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(subject.subject_id, governance_id);
+        assert_eq!(subject.schema_id, "governance");
+        assert!(serde_json::from_str::<serde_json::Value>(&subject.properties_json).is_ok());
+
+        let subjects = service
+            .get_subjects(Request::new(proto::GetSubjectsRequest {
+                subject_type: Some("governances".to_owned()),
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(subjects.subjects.len(), 1);
+        assert_eq!(subjects.next_cursor, None);
+        assert_eq!(subjects.total, Some(1));
+
+        let status = service
+            .get_subject(Request::new(proto::SubjectId {
+                subject_id: "invalid".to_owned(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let status = service
+            .send_event_request(Request::new(proto::EventRequest::default()))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let status = service
+            .register_keys(Request::new(proto::RegisterKeysRequest::default()))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+
+        let service = service.with_auth(ApiAuthSettings {
+            public_token: String::new(),
+            admin_token: "admin-token".to_owned(),
+        });
+        let mut request = Request::new(proto::RegisterKeysRequest::default());
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer admin-token".parse().unwrap());
+        assert!(service.register_keys(request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_grpc_trace() {
+        use tower::{service_fn, ServiceExt};
+
+        // Answers with the trace id the call reached the service with.
+        let service = GrpcTrace::new(service_fn(|request: http::Request<()>| async move {
+            let mut response = http::Response::new(tonic::body::empty_body());
+            let trace_id = request.headers()[TRACE_ID_HEADER].clone();
+            response.headers_mut().insert("x-seen", trace_id);
+            Ok::<_, std::convert::Infallible>(response)
+        }));
+        let call = |trace_id: Option<&'static str>| {
+            let mut request = http::Request::new(());
+            if let Some(trace_id) = trace_id {
+                request
+                    .headers_mut()
+                    .insert(TRACE_ID_HEADER, HeaderValue::from_static(trace_id));
+            }
+            service.clone().oneshot(request)
+        };
+
+        let response = call(Some("trace-1")).await.unwrap();
+        assert_eq!(response.headers()[TRACE_ID_HEADER], "trace-1");
+        assert_eq!(response.headers()["x-seen"], "trace-1");
+        for trace_id in [None, Some("trace 1")] {
+            let response = call(trace_id).await.unwrap();
+            assert_eq!(response.headers()[TRACE_ID_HEADER].len(), 32);
+            assert_eq!(
+                response.headers()[TRACE_ID_HEADER],
+                response.headers()["x-seen"]
+            );
+        }
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Error responses of the REST API.
+//!
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+use crate::error::NodeError;
+
+/// Error of a call, answered as `{"error": <message>}` with the status of the error.
+#[derive(Debug)]
+pub struct ApiError(pub NodeError);
+
+impl ApiError {
+    /// HTTP status of the error.
+    pub fn status(&self) -> StatusCode {
+        match self.0 {
+            NodeError::InvalidParameter(_) => StatusCode::BAD_REQUEST,
+            NodeError::InvalidSignature(_) => StatusCode::UNAUTHORIZED,
+            NodeError::NotFound(_) => StatusCode::NOT_FOUND,
+            NodeError::Unauthenticated(_) => StatusCode::UNAUTHORIZED,
+            NodeError::Unauthorized(_) => StatusCode::FORBIDDEN,
+            NodeError::Conflict(_) => StatusCode::CONFLICT,
+            NodeError::QuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+            NodeError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            NodeError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            NodeError::Cancelled => StatusCode::SERVICE_UNAVAILABLE,
+            NodeError::Database {
+                retryable: true, ..
+            } => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<NodeError> for ApiError {
+    fn from(error: NodeError) -> Self {
+        Self(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status(), Json(json!({ "error": self.0.to_string() }))).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_error_status() {
+        let status = |error: NodeError| ApiError::from(error).into_response().status();
+        assert_eq!(
+            status(NodeError::InvalidParameter("invalid subject_id".to_owned())),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(NodeError::InvalidSignature("stale signature".to_owned())),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(NodeError::Unauthenticated("invalid API key".to_owned())),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(NodeError::QuotaExceeded("3 subjects".to_owned())),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            status(NodeError::RateLimited(
+                "too many reads in flight".to_owned()
+            )),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            status(NodeError::NotFound("subject JSubject".to_owned())),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status(NodeError::Unauthorized("EOL requests".to_owned())),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(NodeError::Conflict("vote not needed".to_owned())),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(status(NodeError::Timeout), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(
+            status(NodeError::Database {
+                message: "database is locked".to_owned(),
+                retryable: true,
+            }),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            status(NodeError::database("disk image is malformed")),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(
+            status(NodeError::InternalApi("Failed to get request".to_owned())),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Entity tags.
+//!
+//! Successful `GET` responses carry a weak `ETag`, and a request whose `If-None-Match` holds the
+//! current tag is answered with `304 Not Modified` and no body. Subjects are tagged by their
+//! sequence number; other responses by a hash of their body. Streamed responses, whose size is
+//! unknown, are not tagged.
+//!
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use axum::{
+    body::{to_bytes, Body, HttpBody},
+    extract::Request,
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+        HeaderValue, Method, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::api::etag_matches;
+
+/// Largest body that is hashed.
+const MAX_TAGGED_BODY: u64 = 16 * 1024 * 1024;
+
+/// Weak entity tag of a body.
+fn body_etag(body: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    format!("W/\"{:016x}\"", hasher.finish())
+}
+
+/// Tag the successful `GET` responses, and answer the unchanged ones with `304 Not Modified`.
+pub(super) async fn etag(request: Request, next: Next) -> Response {
+    if request.method() != Method::GET {
+        return next.run(request).await;
+    }
+    let if_none_match = request
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, mut body) = response.into_parts();
+    let etag = match parts.headers.get(ETAG) {
+        Some(etag) => etag.to_str().unwrap_or_default().to_owned(),
+        None => {
+            match body.size_hint().upper() {
+                Some(size) if size <= MAX_TAGGED_BODY => {}
+                _ => return Response::from_parts(parts, body),
+            }
+            let Ok(bytes) = to_bytes(body, MAX_TAGGED_BODY as usize).await else {
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            };
+            let etag = body_etag(&bytes);
+            if let Ok(value) = HeaderValue::from_str(&etag) {
+                parts.headers.insert(ETAG, value);
+            }
+            body = Body::from(bytes);
+            etag
+        }
+    };
+
+    match if_none_match {
+        Some(if_none_match) if etag_matches(&if_none_match, &etag) => {
+            parts.status = StatusCode::NOT_MODIFIED;
+            parts.headers.remove(CONTENT_LENGTH);
+            parts.headers.remove(CONTENT_TYPE);
+            Response::from_parts(parts, Body::empty())
+        }
+        _ => Response::from_parts(parts, body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use axum::{middleware::from_fn, routing::get, Json, Router};
+    use tower::ServiceExt;
+
+    fn routes() -> Router {
+        Router::new()
+            .route(
+                "/subjects",
+                get(|| async { Json(vec!["JSubject"]) }).post(|| async { Json("Ok") }),
+            )
+            .route(
+                "/subjects/tagged",
+                get(|| async { ([(ETAG, "W/\"JSubject-3-0\"")], Json("JSubject")) }),
+            )
+            .layer(from_fn(etag))
+    }
+
+    fn request(method: Method, uri: &str, if_none_match: Option<&str>) -> Request {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(if_none_match) = if_none_match {
+            request = request.header(IF_NONE_MATCH, if_none_match);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(etag_matches("W/\"a\"", "W/\"a\""));
+        assert!(etag_matches("\"b\", \"a\"", "W/\"a\""));
+        assert!(etag_matches("*", "W/\"a\""));
+        assert!(!etag_matches("W/\"b\"", "W/\"a\""));
+    }
+
+    #[tokio::test]
+    async fn test_etag() {
+        let response = routes()
+            .oneshot(request(Method::GET, "/subjects", None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[ETAG].to_str().unwrap().to_owned();
+        assert!(etag.starts_with("W/"));
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], br#"["JSubject"]"#);
+
+        let response = routes()
+            .oneshot(request(Method::GET, "/subjects", Some(&etag)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], etag.as_str());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.is_empty());
+
+        let response = routes()
+            .oneshot(request(Method::POST, "/subjects", Some(&etag)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(ETAG));
+
+        // Tags set by the handler are kept.
+        let response = routes()
+            .oneshot(request(
+                Method::GET,
+                "/subjects/tagged",
+                Some("W/\"JSubject-3-0\""),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+}
@@ -0,0 +1,884 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # REST API.
+//!
+//! HTTP routes over the surfaces of `KoreApi`, one per method, with JSON bodies and responses.
+//! Routes of the public surface are served to the clients allowed by `kore.api_auth`, and those
+//! of the admin surface to the clients holding the admin token, see `surface::authorize`; other
+//! clients get `403 Forbidden`. Before that, every route requires the API key or JWT of
+//! `kore.auth` when it is set, see the `auth` module. Each request is served through a handle
+//! bound to the address of the client and to the trace id of the `x-request-id` header, which is
+//! generated when missing or not valid and returned in the response. The server listens on a
+//! TCP address or on a Unix socket, see the `listener` module.
+//! Requests and their body size are accounted to the principal of their credentials, see
+//! `auth::Principal`, or else to the IP address of the client, see `KoreApi::usage`.
+//!
+//! Responses are compressed with zstd when the client sends `Accept-Encoding: zstd`, and the
+//! events of a subject and the checks of each of them by `verify_subject_chain_reports` are
+//! streamed as NDJSON when it sends `Accept: application/x-ndjson`.
+//! New events are pushed over a WebSocket opened on `/subscriptions`, see `KoreApi::subscribe`.
+//! `GET` responses carry a weak `ETag` and honour `If-None-Match`, see `etag`. Event requests sent
+//! with an `Idempotency-Key` header are sent once per key, see
+//! `KoreApi::send_idempotent_event_request`. Event requests sent to the public surface must be
+//! signed by their clients; those the node signs are sent to `/admin/event-requests`.
+//!
+//! | Route | Method of `KoreApi` | Surface |
+//! |---|---|---|
+//! | `POST /event-requests` | `send_event_request` | Public |
+//! | `POST /admin/event-requests` | `send_event_request` | Admin |
+//! | `GET /event-requests` | `list_requests` | Public |
+//! | `GET /event-requests/{id}` | `get_event_request` | Public |
+//! | `GET /event-requests/{id}/state` | `get_event_request_state` | Public |
+//! | `GET /event-requests/{id}/state/wait` | `wait_state_change` | Public |
+//! | `GET /approvals` | `get_approvals` | Public |
+//! | `GET /approvals/{id}` | `get_approval_id` | Public |
+//! | `PATCH /approvals/{id}` | `approval_request` | Admin |
+//! | `GET /allowed-subjects` | `get_all_allowed_subjects_and_providers` | Public |
+//! | `GET /allowed-subjects/count` | `count_allowed_subjects` | Public |
+//! | `PUT /allowed-subjects/{id}` | `add_preauthorize_subject` | Admin |
+//! | `POST /keys` | `register_keys` | Admin |
+//! | `GET /subjects` | `get_subjects` | Public |
+//! | `POST /subjects/search` | `search_subjects` | Public |
+//! | `GET /subjects/{id}` | `get_subject` | Public |
+//! | `PUT /subjects/{id}/archive` | `archive_subject` | Admin |
+//! | `DELETE /subjects/{id}/archive` | `unarchive_subject` | Admin |
+//! | `GET /subjects/{id}/validation-proof` | `get_validation_proof` | Public |
+//! | `GET /subjects/{id}/validation-proofs` | `get_validation_proofs` | Public |
+//! | `GET /subjects/{id}/validation-proofs/{sn}` | `get_validation_proof_at` | Public |
+//! | `GET /subjects/{id}/graph` | `subject_graph` | Public |
+//! | `GET /subjects/{id}/events` | `get_events_of_subject` | Public |
+//! | `GET /subjects/{id}/events/{sn}` | `get_event_of_subject` | Public |
+//! | `GET /subjects/{id}/verification` | `verify_subject_chain` | Public |
+//! | `GET /subjects/{id}/events/{sn}/verification` | `verify_event` | Public |
+//! | `GET /subscriptions` (WebSocket) | `subscribe` | Public |
+//! | `GET /admin/features` | `feature_flags` | Admin |
+//! | `PUT /admin/features/{name}` | `set_feature_flag` | Admin |
+//! | `GET /admin/database` | `db_stats` | Admin |
+//! | `POST /admin/database/compact` | `compact_db` | Admin |
+//! | `POST /admin/database/reindex` | `reindex_subjects` | Admin |
+//! | `POST /admin/database/archive` | `archive_events` | Admin |
+//! | `GET /admin/contracts` | `list_contracts` | Admin |
+//! | `POST /admin/contracts/reload` | `reload_contracts` | Admin |
+//! | `GET /admin/replication/dead-letters` | `replication_dead_letters` | Admin |
+//! | `GET /services` | `service_record` | Public |
+//! | `GET /peer-services` | `peer_services` | Public |
+//! | `GET /info` | `node_info` | Public |
+//!
+
+mod errors;
+mod etag;
+mod stream;
+mod ws;
+
+use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, Path, Query, Request, State},
+    http::{
+        header::{AUTHORIZATION, CONTENT_LENGTH, ETAG},
+        request::Parts,
+        HeaderMap, HeaderName, HeaderValue,
+    },
+    middleware::{from_fn, from_fn_with_state, Next},
+    response::{IntoResponse, Response},
+    routing::{get, patch, post, put},
+    Json, Router,
+};
+use serde::Serialize;
+use tower_http::compression::CompressionLayer;
+
+pub use errors::ApiError;
+
+use crate::{
+    access_log::{accept_trace_id, TRACE_ID_HEADER},
+    api::IDEMPOTENCY_KEY_HEADER,
+    auth::{require_credentials, Authenticator, Principal},
+    error::NodeError,
+    listener::HttpListener,
+    model::{
+        AuthorizeSubject, EventContentResponse, EventRequestResponse, NodeAllowedSubjectsFilter,
+        NodeApprovalEntity, NodeArchivalReport, NodeChainVerification, NodeContract,
+        NodeDbCompaction, NodeDbStats, NodeEventVerification, NodeFeatureFlag, NodeFeatureToggle,
+        NodeGetApprovals, NodeInfo, NodeKeys, NodeKoreRequestState, NodeProof, NodeRequestRecord,
+        NodeRequestStateWait, NodeServiceRecord, NodeSigned, NodeSignedEventRequest,
+        NodeSubjectData, NodeSubjectGraph, NodeSubjectGraphQuery, NodeSubjectKeys,
+        NodeSubjectSearch, NodeSubjects, Page, PaginatorFromNumber, PaginatorFromString, PatchVote,
+        PreauthorizedSubjectsResponse, Timestamped,
+    },
+    settings::{ApiAuthSettings, TimestampFormat},
+    surface::{authorize, Surface},
+    tasks::NodeTasks,
+    AdminApi, KoreApi, PublicApi,
+};
+
+/// Result of a route.
+type ApiResult<T> = Result<Formatted<T>, ApiError>;
+
+/// JSON body with its timestamps in the format of the node.
+struct Formatted<T>(T, TimestampFormat);
+
+impl<T: Serialize> IntoResponse for Formatted<T> {
+    fn into_response(self) -> Response {
+        Json(Timestamped::new(&self.0, self.1)).into_response()
+    }
+}
+
+/// Surface whose handles are bound to the client of each request.
+trait Handle: Clone + Send + Sync + 'static {
+    fn with_identity(&self, identity: &str) -> Self;
+    fn with_trace_id(&self, trace_id: &str) -> Self;
+    fn record_usage(&self, caller: &str, bytes: u64);
+    fn timestamp_format(&self) -> TimestampFormat;
+
+    /// Response with `value` as its body.
+    fn json<T>(&self, value: T) -> Formatted<T> {
+        Formatted(value, self.timestamp_format())
+    }
+}
+
+impl Handle for PublicApi {
+    fn with_identity(&self, identity: &str) -> Self {
+        PublicApi::with_identity(self, identity)
+    }
+
+    fn with_trace_id(&self, trace_id: &str) -> Self {
+        PublicApi::with_trace_id(self, trace_id)
+    }
+
+    fn record_usage(&self, caller: &str, bytes: u64) {
+        PublicApi::record_usage(self, caller, bytes)
+    }
+
+    fn timestamp_format(&self) -> TimestampFormat {
+        PublicApi::timestamp_format(self)
+    }
+}
+
+impl Handle for AdminApi {
+    fn with_identity(&self, identity: &str) -> Self {
+        AdminApi::with_identity(self, identity)
+    }
+
+    fn with_trace_id(&self, trace_id: &str) -> Self {
+        AdminApi::with_trace_id(self, trace_id)
+    }
+
+    fn record_usage(&self, caller: &str, bytes: u64) {
+        AdminApi::record_usage(self, caller, bytes)
+    }
+
+    fn timestamp_format(&self) -> TimestampFormat {
+        AdminApi::timestamp_format(self)
+    }
+}
+
+/// Handle of the API surface for the request being served.
+struct Caller<A = PublicApi>(A);
+
+#[async_trait]
+impl<A: Handle> FromRequestParts<A> for Caller<A> {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, api: &A) -> Result<Self, Infallible> {
+        let mut api = api.clone();
+        if let Some(ConnectInfo(address)) = parts.extensions.get::<ConnectInfo<SocketAddr>>() {
+            api = api.with_identity(&address.to_string());
+            let bytes = parts
+                .headers
+                .get(CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok()?.parse().ok())
+                .unwrap_or_default();
+            // Accounted to the principal of the credentials, or else to the client.
+            match parts.extensions.get::<Principal>() {
+                Some(Principal(principal)) => api.record_usage(principal, bytes),
+                None => api.record_usage(&address.ip().to_string(), bytes),
+            }
+        }
+        if let Some(trace_id) = parts
+            .headers
+            .get(TRACE_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            api = api.with_trace_id(trace_id);
+        }
+        Ok(Self(api))
+    }
+}
+
+/// Keep the trace id sent by the caller, or give the request a new one, see
+/// `access_log::accept_trace_id`, and return it in the response.
+async fn trace_id(mut request: Request, next: Next) -> Response {
+    let incoming = request
+        .headers()
+        .get(TRACE_ID_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let trace_id = HeaderValue::from_str(&accept_trace_id(incoming))
+        .unwrap_or_else(|_| HeaderValue::from_static("-"));
+    request
+        .headers_mut()
+        .insert(TRACE_ID_HEADER, trace_id.clone());
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(TRACE_ID_HEADER, trace_id);
+    response
+}
+
+/// Answer the requests whose client may not reach `surface` with `403 Forbidden`.
+async fn check_surface(
+    State((auth, surface)): State<(Arc<ApiAuthSettings>, Surface)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let address = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.ip());
+    match authorize(&auth, surface, token, address) {
+        Ok(()) => next.run(request).await,
+        Err(error) => ApiError::from(error).into_response(),
+    }
+}
+
+/// Routes of the public surface, reads and event submission.
+///
+/// # Arguments
+///
+/// * `api` - Public surface served by the routes.
+/// * `auth` - Credentials required from the clients.
+///
+pub fn public_routes(api: PublicApi, auth: &ApiAuthSettings) -> Router {
+    Router::new()
+        .route(
+            "/event-requests",
+            post(send_event_request).get(list_requests),
+        )
+        .route("/event-requests/:id", get(get_event_request))
+        .route("/event-requests/:id/state", get(get_event_request_state))
+        .route("/event-requests/:id/state/wait", get(wait_state_change))
+        .route("/approvals", get(get_approvals))
+        .route("/approvals/:id", get(get_approval))
+        .route("/allowed-subjects", get(get_allowed_subjects))
+        .route("/allowed-subjects/count", get(count_allowed_subjects))
+        .route("/subjects", get(get_subjects))
+        .route("/subjects/search", post(search_subjects))
+        .route("/subjects/:id", get(get_subject))
+        .route("/subjects/:id/validation-proof", get(get_validation_proof))
+        .route(
+            "/subjects/:id/validation-proofs",
+            get(get_validation_proofs),
+        )
+        .route(
+            "/subjects/:id/validation-proofs/:sn",
+            get(get_validation_proof_at),
+        )
+        .route("/subjects/:id/graph", get(subject_graph))
+        .route("/subjects/:id/events", get(get_events_of_subject))
+        .route("/subjects/:id/events/:sn", get(get_event_of_subject))
+        .route("/subjects/:id/verification", get(verify_subject_chain))
+        .route("/subjects/:id/events/:sn/verification", get(verify_event))
+        .route("/subscriptions", get(ws::subscribe))
+        .route("/services", get(service_record))
+        .route("/peer-services", get(peer_services))
+        .route("/info", get(node_info))
+        .route_layer(from_fn_with_state(
+            (Arc::new(auth.clone()), Surface::Public),
+            check_surface,
+        ))
+        .with_state(api)
+}
+
+/// Routes of the admin surface: event requests signed by the node, votes, preauthorizations,
+/// keys, archives, feature flags, database maintenance, contracts and replication dead letters.
+///
+/// # Arguments
+///
+/// * `api` - Admin surface served by the routes.
+/// * `auth` - Credentials required from the clients.
+///
+pub fn admin_routes(api: AdminApi, auth: &ApiAuthSettings) -> Router {
+    let router = Router::new()
+        .route("/admin/event-requests", post(sign_event_request))
+        .route("/approvals/:id", patch(approval_request))
+        .route("/allowed-subjects/:id", put(add_preauthorize_subject))
+        .route("/keys", post(register_keys))
+        .route(
+            "/subjects/:id/archive",
+            put(archive_subject).delete(unarchive_subject),
+        )
+        .route("/admin/features", get(feature_flags))
+        .route("/admin/features/:name", put(set_feature_flag))
+        .route("/admin/database", get(db_stats))
+        .route("/admin/database/compact", post(compact_db))
+        .route("/admin/database/reindex", post(reindex_subjects))
+        .route("/admin/database/archive", post(archive_events))
+        .route("/admin/contracts", get(list_contracts))
+        .route("/admin/contracts/reload", post(reload_contracts));
+    #[cfg(feature = "replication")]
+    let router = router.route(
+        "/admin/replication/dead-letters",
+        get(replication_dead_letters),
+    );
+    router
+        .route_layer(from_fn_with_state(
+            (Arc::new(auth.clone()), Surface::Admin),
+            check_surface,
+        ))
+        .with_state(api)
+}
+
+/// Routes of the REST API, both surfaces. Embedders that only serve the public surface use
+/// `public_routes` instead.
+///
+/// # Arguments
+///
+/// * `api` - Kore API served by the routes.
+/// * `auth` - Credentials required from the clients of each surface.
+/// * `authenticator` - API keys and JWTs required from every client.
+///
+/// # Returns
+///
+/// * `Router` - Routes, to be served with `into_make_service_with_connect_info::<SocketAddr>`
+///   so that the calls are logged with the address of the client, which the admin surface
+///   needs when it has no token.
+///
+pub fn routes(api: KoreApi, auth: &ApiAuthSettings, authenticator: Authenticator) -> Router {
+    with_layers(
+        public_routes(PublicApi::new(api.clone()), auth)
+            .merge(admin_routes(AdminApi::new(api), auth))
+            .layer(from_fn_with_state(authenticator, require_credentials)),
+    )
+}
+
+/// Entity tags, compression and trace ids of the responses.
+fn with_layers(routes: Router) -> Router {
+    routes
+        .layer(from_fn(etag::etag))
+        .layer(CompressionLayer::new())
+        .layer(from_fn(trace_id))
+}
+
+/// Serve the REST API until the node is cancelled.
+///
+/// # Arguments
+///
+/// * `api` - Kore API served.
+/// * `listen` - Address to listen on, `<host>:<port>` or `unix://<path>`, see the `listener`
+///   module.
+/// * `auth` - Credentials required from the clients of each surface.
+/// * `authenticator` - API keys and JWTs required from every client.
+/// * `tasks` - Tasks of the node, the server stops when it is cancelled.
+///
+/// # Errors
+///
+/// * `NodeError::Bind` - The address cannot be bound.
+///
+pub fn run_http_api(
+    api: KoreApi,
+    listen: &str,
+    auth: &ApiAuthSettings,
+    authenticator: Authenticator,
+    tasks: &NodeTasks,
+) -> Result<(), NodeError> {
+    let listener = HttpListener::bind(listen).map_err(|error| {
+        log::error!("REST API cannot listen on {}: {}", listen, error);
+        NodeError::Bind {
+            address: listen.to_owned(),
+            cause: error.to_string(),
+        }
+    })?;
+    if let Some(address) = listener.local_addr() {
+        log::info!("REST API served on {}", address);
+    }
+    let routes = routes(api, auth, authenticator);
+
+    let cancellation = tasks.token();
+    tasks.spawn(async move {
+        if let Err(error) = listener.serve(routes, cancellation).await {
+            log::error!("REST API server error: {}", error);
+        }
+    });
+    Ok(())
+}
+
+/// Idempotency key of a request, if sent.
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<&str>, NodeError> {
+    headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|key| {
+            key.to_str()
+                .map_err(|_| NodeError::InvalidParameter("idempotency key is not text".to_owned()))
+        })
+        .transpose()
+}
+
+async fn send_event_request(
+    Caller(api): Caller,
+    headers: HeaderMap,
+    Json(request): Json<NodeSignedEventRequest>,
+) -> ApiResult<EventRequestResponse> {
+    let response = match idempotency_key(&headers)? {
+        Some(key) => api.send_idempotent_event_request(request, key).await?,
+        None => api.send_event_request(request).await?,
+    };
+    Ok(api.json(response))
+}
+
+async fn sign_event_request(
+    Caller(api): Caller<AdminApi>,
+    headers: HeaderMap,
+    Json(request): Json<NodeSignedEventRequest>,
+) -> ApiResult<EventRequestResponse> {
+    let response = match idempotency_key(&headers)? {
+        Some(key) => api.send_idempotent_event_request(request, key).await?,
+        None => api.send_event_request(request).await?,
+    };
+    Ok(api.json(response))
+}
+
+async fn list_requests(
+    Caller(api): Caller,
+    Query(parameters): Query<PaginatorFromString>,
+) -> ApiResult<Vec<NodeRequestRecord>> {
+    Ok(api.json(api.list_requests(parameters)?))
+}
+
+async fn get_event_request(
+    Caller(api): Caller,
+    Path(id): Path<String>,
+) -> ApiResult<NodeSignedEventRequest> {
+    Ok(api.json(api.get_event_request(&id).await?))
+}
+
+async fn get_event_request_state(
+    Caller(api): Caller,
+    Path(id): Path<String>,
+) -> ApiResult<NodeKoreRequestState> {
+    Ok(api.json(api.get_event_request_state(&id).await?))
+}
+
+async fn wait_state_change(
+    Caller(api): Caller,
+    Path(id): Path<String>,
+    Query(query): Query<NodeRequestStateWait>,
+) -> ApiResult<NodeKoreRequestState> {
+    let timeout = Duration::from_secs(query.timeout.unwrap_or(30));
+    Ok(api.json(
+        api.wait_state_change(&id, query.last_state, timeout)
+            .await?,
+    ))
+}
+
+async fn get_approvals(
+    Caller(api): Caller,
+    Query(parameters): Query<NodeGetApprovals>,
+) -> ApiResult<Page<NodeApprovalEntity>> {
+    Ok(api.json(api.get_approvals(parameters).await?))
+}
+
+async fn get_approval(
+    Caller(api): Caller,
+    Path(id): Path<String>,
+) -> ApiResult<NodeApprovalEntity> {
+    Ok(api.json(api.get_approval_id(&id).await?))
+}
+
+async fn approval_request(
+    Caller(api): Caller<AdminApi>,
+    Path(id): Path<String>,
+    Json(vote): Json<PatchVote>,
+) -> ApiResult<NodeApprovalEntity> {
+    Ok(api.json(api.approval_request(&id, vote).await?))
+}
+
+async fn get_allowed_subjects(
+    Caller(api): Caller,
+    Query(parameters): Query<PaginatorFromString>,
+    Query(filter): Query<NodeAllowedSubjectsFilter>,
+) -> ApiResult<Page<PreauthorizedSubjectsResponse>> {
+    Ok(api.json(
+        api.get_all_allowed_subjects_and_providers(parameters, filter)
+            .await?,
+    ))
+}
+
+async fn count_allowed_subjects(
+    Caller(api): Caller,
+    Query(filter): Query<NodeAllowedSubjectsFilter>,
+) -> ApiResult<u64> {
+    Ok(api.json(api.count_allowed_subjects(filter).await?))
+}
+
+async fn add_preauthorize_subject(
+    Caller(api): Caller<AdminApi>,
+    Path(id): Path<String>,
+    Json(data): Json<AuthorizeSubject>,
+) -> ApiResult<String> {
+    Ok(api.json(api.add_preauthorize_subject(&id, data).await?))
+}
+
+async fn register_keys(
+    Caller(api): Caller<AdminApi>,
+    Json(parameters): Json<NodeKeys>,
+) -> ApiResult<String> {
+    Ok(api.json(api.register_keys(parameters).await?))
+}
+
+async fn get_subjects(
+    Caller(api): Caller,
+    Query(parameters): Query<NodeSubjects>,
+    Query(keys): Query<NodeSubjectKeys>,
+) -> ApiResult<Page<NodeSubjectData>> {
+    Ok(api.json(api.get_subjects_by(parameters, keys).await?))
+}
+
+async fn search_subjects(
+    Caller(api): Caller,
+    Json(filter): Json<NodeSubjectSearch>,
+) -> ApiResult<Page<NodeSubjectData>> {
+    Ok(api.json(api.search_subjects(filter)?))
+}
+
+async fn get_subject(
+    Caller(api): Caller,
+    Path(id): Path<String>,
+) -> Result<([(HeaderName, String); 1], Formatted<NodeSubjectData>), ApiError> {
+    let subject = api.get_subject(&id).await?;
+    Ok(([(ETAG, subject.etag())], api.json(subject)))
+}
+
+async fn archive_subject(
+    Caller(api): Caller<AdminApi>,
+    Path(id): Path<String>,
+) -> ApiResult<String> {
+    Ok(api.json(api.archive_subject(&id).await?))
+}
+
+async fn unarchive_subject(
+    Caller(api): Caller<AdminApi>,
+    Path(id): Path<String>,
+) -> ApiResult<String> {
+    Ok(api.json(api.unarchive_subject(&id).await?))
+}
+
+async fn get_validation_proof(Caller(api): Caller, Path(id): Path<String>) -> ApiResult<NodeProof> {
+    Ok(api.json(api.get_validation_proof(&id).await?))
+}
+
+async fn get_validation_proofs(
+    Caller(api): Caller,
+    Path(id): Path<String>,
+    Query(parameters): Query<PaginatorFromNumber>,
+) -> ApiResult<Page<NodeProof>> {
+    Ok(api.json(api.get_validation_proofs(&id, parameters).await?))
+}
+
+async fn get_validation_proof_at(
+    Caller(api): Caller,
+    Path((id, sn)): Path<(String, u64)>,
+) -> ApiResult<NodeProof> {
+    Ok(api.json(api.get_validation_proof_at(&id, sn).await?))
+}
+
+async fn subject_graph(
+    Caller(api): Caller,
+    Path(id): Path<String>,
+    Query(query): Query<NodeSubjectGraphQuery>,
+) -> ApiResult<NodeSubjectGraph> {
+    Ok(api.json(api.subject_graph(&id, query.depth.unwrap_or(1)).await?))
+}
+
+async fn get_events_of_subject(
+    Caller(api): Caller,
+    Path(id): Path<String>,
+    Query(parameters): Query<PaginatorFromNumber>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    if stream::accepts_ndjson(&headers) {
+        return stream::stream_events(api, id, parameters).await;
+    }
+    let events: Page<NodeSigned<EventContentResponse>> =
+        api.get_events_of_subject(&id, parameters).await?;
+    Ok(api.json(events).into_response())
+}
+
+async fn get_event_of_subject(
+    Caller(api): Caller,
+    Path((id, sn)): Path<(String, u64)>,
+) -> ApiResult<NodeSigned<EventContentResponse>> {
+    Ok(api.json(api.get_event_of_subject(&id, sn).await?))
+}
+
+async fn verify_subject_chain(
+    Caller(api): Caller,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    if stream::accepts_ndjson(&headers) {
+        return stream::stream_verification(api, id).await;
+    }
+    let chain: NodeChainVerification = api.verify_subject_chain(&id).await?;
+    Ok(api.json(chain).into_response())
+}
+
+async fn verify_event(
+    Caller(api): Caller,
+    Path((id, sn)): Path<(String, u64)>,
+) -> ApiResult<NodeEventVerification> {
+    Ok(api.json(api.verify_event(&id, sn).await?))
+}
+
+async fn feature_flags(Caller(api): Caller<AdminApi>) -> ApiResult<Vec<NodeFeatureFlag>> {
+    Ok(api.json(api.feature_flags()))
+}
+
+async fn set_feature_flag(
+    Caller(api): Caller<AdminApi>,
+    Path(name): Path<String>,
+    Json(toggle): Json<NodeFeatureToggle>,
+) -> ApiResult<NodeFeatureFlag> {
+    Ok(api.json(api.set_feature_flag(&name, toggle.enabled)?))
+}
+
+async fn db_stats(Caller(api): Caller<AdminApi>) -> ApiResult<NodeDbStats> {
+    Ok(api.json(api.db_stats().await?))
+}
+
+async fn compact_db(Caller(api): Caller<AdminApi>) -> ApiResult<NodeDbCompaction> {
+    Ok(api.json(api.compact_db().await?))
+}
+
+async fn reindex_subjects(Caller(api): Caller<AdminApi>) -> ApiResult<usize> {
+    Ok(api.json(api.reindex_subjects().await?))
+}
+
+async fn archive_events(Caller(api): Caller<AdminApi>) -> ApiResult<NodeArchivalReport> {
+    Ok(api.json(api.archive_events().await?))
+}
+
+async fn list_contracts(Caller(api): Caller<AdminApi>) -> ApiResult<Vec<NodeContract>> {
+    Ok(api.json(api.list_contracts()))
+}
+
+#[cfg(feature = "replication")]
+async fn replication_dead_letters(
+    Caller(api): Caller<AdminApi>,
+) -> ApiResult<Vec<crate::model::NodeReplicationFailure>> {
+    Ok(api.json(api.replication_dead_letters()?))
+}
+
+async fn reload_contracts(Caller(api): Caller<AdminApi>) -> ApiResult<Vec<NodeContract>> {
+    Ok(api.json(api.reload_contracts()?))
+}
+
+async fn service_record(Caller(api): Caller) -> ApiResult<NodeSigned<NodeServiceRecord>> {
+    Ok(api.json(api.service_record()?))
+}
+
+async fn peer_services(Caller(api): Caller) -> ApiResult<Vec<NodeServiceRecord>> {
+    Ok(api.json(api.peer_services()))
+}
+
+async fn node_info(Caller(api): Caller) -> ApiResult<NodeInfo> {
+    Ok(api.json(api.node_info()))
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+
+    use super::*;
+    use crate::{api::tests::create_event, node::tests::export_sqlite_api};
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_sqlite_http_api() {
+        let routes = routes(
+            export_sqlite_api(213, vec![]).await,
+            &ApiAuthSettings::default(),
+            Authenticator::default(),
+        );
+        let request = |method: &str, uri: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = routes
+            .clone()
+            .oneshot(request("GET", "/subjects?subject_type=all"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(TRACE_ID_HEADER));
+
+        let mut invalid = request("GET", "/subjects/invalid");
+        invalid
+            .headers_mut()
+            .insert(TRACE_ID_HEADER, HeaderValue::from_static("trace-1"));
+        let response = routes.clone().oneshot(invalid).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()[TRACE_ID_HEADER], "trace-1");
+        // An id that would break the access logs is replaced.
+        let mut invalid = request("GET", "/subjects/invalid");
+        invalid
+            .headers_mut()
+            .insert(TRACE_ID_HEADER, HeaderValue::from_static("trace 1"));
+        let response = routes.clone().oneshot(invalid).await.unwrap();
+        assert_eq!(response.headers()[TRACE_ID_HEADER].len(), 32);
+
+        let response = routes
+            .clone()
+            .oneshot(request("GET", "/subjects/invalid/events/x"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = routes
+            .clone()
+            .oneshot(request("GET", "/allowed-subjects/count?provider=invalid"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let mut compressed = request("GET", "/subjects/invalid");
+        compressed
+            .headers_mut()
+            .insert(header::ACCEPT_ENCODING, HeaderValue::from_static("zstd"));
+        let response = routes.clone().oneshot(compressed).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "zstd");
+
+        let response = routes
+            .clone()
+            .oneshot(request("GET", "/info"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let info: NodeInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            info.listen_addresses,
+            vec!["/ip4/127.0.0.1/tcp/50213".to_owned()]
+        );
+        assert_eq!(info.build.version, env!("CARGO_PKG_VERSION"));
+
+        // Without the address of the client, nor an admin token, the admin surface is closed.
+        let response = routes
+            .oneshot(request("GET", "/admin/features"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_http_api_surfaces() {
+        let api = export_sqlite_api(228, vec![]).await;
+        let auth = ApiAuthSettings {
+            public_token: "public-token".to_owned(),
+            admin_token: "admin-token".to_owned(),
+        };
+        let routes = routes(api.clone(), &auth, Authenticator::default());
+        let request = |uri: &str, token: Option<&str>| {
+            let mut request = Request::builder().uri(uri);
+            if let Some(token) = token {
+                request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+            }
+            request.body(Body::empty()).unwrap()
+        };
+        let status = |request: Request<Body>| {
+            let routes = routes.clone();
+            async move { routes.oneshot(request).await.unwrap().status() }
+        };
+
+        let subjects = "/subjects?subject_type=all";
+        assert_eq!(status(request(subjects, None)).await, StatusCode::FORBIDDEN);
+        assert_eq!(
+            status(request(subjects, Some("public-token"))).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(request(subjects, Some("admin-token"))).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(request("/admin/features", Some("public-token"))).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(request("/admin/features", Some("admin-token"))).await,
+            StatusCode::OK
+        );
+
+        // The public routes alone do not serve the admin surface.
+        let response = public_routes(PublicApi::new(api), &auth)
+            .oneshot(request("/admin/features", Some("admin-token")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_http_api_stream_events() {
+        let api = export_sqlite_api(214, vec![]).await;
+        let governance_id = create_event(&api, "", "governance", "streamed").await;
+        let routes = routes(api, &ApiAuthSettings::default(), Authenticator::default());
+        let request = |uri: String| {
+            Request::builder()
+                .uri(uri)
+                .header(header::ACCEPT, stream::NDJSON)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = routes
+            .clone()
+            .oneshot(request(format!("/subjects/{}/events", governance_id)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], stream::NDJSON);
+        assert!(!response.headers().contains_key(header::ETAG));
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let events = body
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<NodeSigned<EventContentResponse>>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].content.sn, 0);
+
+        let response = routes
+            .clone()
+            .oneshot(request(format!("/subjects/{}/verification", governance_id)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], stream::NDJSON);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let reports = body
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<NodeEventVerification>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].sn, 0);
+
+        let response = routes
+            .oneshot(request("/subjects/invalid/events".to_owned()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
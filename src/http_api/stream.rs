@@ -0,0 +1,186 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Streamed event histories.
+//!
+//! Clients that accept `application/x-ndjson` get the events of a subject, or the checks of each
+//! of them, as newline-delimited JSON, sent with chunked transfer encoding while they are read
+//! page by page, so the node never holds the whole history of a subject in memory.
+//!
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use futures::{stream, StreamExt};
+
+use super::ApiError;
+use crate::{
+    error::NodeError,
+    model::{PaginatorFromNumber, Timestamped},
+    PublicApi,
+};
+
+/// Media type of the streamed responses.
+pub(super) const NDJSON: &str = "application/x-ndjson";
+
+/// Events read from the node for each chunk.
+const PAGE_SIZE: i64 = 100;
+
+/// Whether the client asked for a streamed response.
+pub(super) fn accepts_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| media_type.split(';').next().unwrap_or_default().trim() == NDJSON)
+}
+
+/// Pages of the events of a subject, each one encoded as NDJSON.
+struct EventPages {
+    api: PublicApi,
+    subject_id: String,
+    /// Sequence number of the next event.
+    from: i64,
+    /// Events left to send, when the client set a quantity.
+    remaining: Option<i64>,
+    finished: bool,
+}
+
+impl EventPages {
+    /// Read and encode the next page, `None` once the history is sent.
+    async fn next_chunk(&mut self) -> Result<Option<Bytes>, NodeError> {
+        if self.finished {
+            return Ok(None);
+        }
+        let quantity = self
+            .remaining
+            .map_or(PAGE_SIZE, |remaining| remaining.min(PAGE_SIZE));
+        let page = self
+            .api
+            .get_events_of_subject(
+                &self.subject_id,
+                PaginatorFromNumber {
+                    from: Some(self.from),
+                    quantity: Some(quantity),
+                },
+            )
+            .await?;
+
+        let events = page.items;
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining -= events.len() as i64;
+        }
+        self.finished =
+            page.next_cursor.is_none() || self.remaining.is_some_and(|remaining| remaining <= 0);
+        if let Some(last) = events.last() {
+            self.from = last.content.sn as i64 + 1;
+        }
+        if events.is_empty() {
+            return Ok(None);
+        }
+
+        let (mut chunk, format) = (vec![], self.api.timestamp_format());
+        for event in events.iter() {
+            serde_json::to_writer(&mut chunk, &Timestamped::new(event, format))
+                .map_err(|error| NodeError::InternalApi(error.to_string()))?;
+            chunk.push(b'\n');
+        }
+        Ok(Some(Bytes::from(chunk)))
+    }
+}
+
+/// Stream the events of a subject as NDJSON.
+/// The first page is read before answering, so that an invalid subject gets an error status
+/// instead of an interrupted stream.
+///
+/// # Arguments
+///
+/// * `api` - Kore API of the request.
+/// * `subject_id` - Subject identifier.
+/// * `parameters` - First event and total number of events, all of them when missing.
+///
+/// # Errors
+///
+/// * `ApiError` - The first page could not be read.
+///
+pub(super) async fn stream_events(
+    api: PublicApi,
+    subject_id: String,
+    parameters: PaginatorFromNumber,
+) -> Result<Response, ApiError> {
+    if parameters.quantity.is_some_and(|quantity| quantity <= 0) {
+        return Err(
+            NodeError::InvalidParameter("quantity must be greater than 0".to_owned()).into(),
+        );
+    }
+    let mut pages = EventPages {
+        api,
+        subject_id,
+        from: parameters.from.unwrap_or_default(),
+        remaining: parameters.quantity,
+        finished: false,
+    };
+    let first = pages.next_chunk().await?;
+
+    let chunks = stream::try_unfold((pages, first), |(mut pages, pending)| async move {
+        let chunk = match pending {
+            Some(chunk) => Some(chunk),
+            None => pages.next_chunk().await?,
+        };
+        Ok::<_, NodeError>(chunk.map(|chunk| (chunk, (pages, None))))
+    });
+    Ok(([(header::CONTENT_TYPE, NDJSON)], Body::from_stream(chunks)).into_response())
+}
+
+/// Stream the checks of each event of a subject as NDJSON, see
+/// `KoreApi::verify_subject_chain_reports`.
+///
+/// # Arguments
+///
+/// * `api` - Kore API of the request.
+/// * `subject_id` - Subject identifier.
+///
+/// # Errors
+///
+/// * `ApiError` - The subject could not be read.
+///
+pub(super) async fn stream_verification(
+    api: PublicApi,
+    subject_id: String,
+) -> Result<Response, ApiError> {
+    let lines = api
+        .verify_subject_chain_reports(&subject_id)
+        .await?
+        .map(|report| {
+            let mut line = serde_json::to_vec(&report?)
+                .map_err(|error| NodeError::InternalApi(error.to_string()))?;
+            line.push(b'\n');
+            Ok::<_, NodeError>(Bytes::from(line))
+        });
+    Ok(([(header::CONTENT_TYPE, NDJSON)], Body::from_stream(lines)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_accepts_ndjson() {
+        let headers = |accept: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, HeaderValue::from_static(accept));
+            headers
+        };
+        assert!(accepts_ndjson(&headers("application/x-ndjson")));
+        assert!(accepts_ndjson(&headers(
+            "application/json;q=0.5, application/x-ndjson;q=1"
+        )));
+        assert!(!accepts_ndjson(&headers("application/json")));
+        assert!(!accepts_ndjson(&HeaderMap::new()));
+    }
+}
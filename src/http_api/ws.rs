@@ -0,0 +1,111 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! WebSocket subscriptions.
+//!
+//! `GET /subscriptions?subject_id=<id>` or `?governance_id=<id>` upgrades the connection and
+//! sends every new event of the target as a JSON text message, until the client closes it.
+//!
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query,
+    },
+    response::Response,
+};
+use serde::Deserialize;
+
+use super::{ApiError, Caller};
+use crate::{
+    error::NodeError,
+    model::Timestamped,
+    settings::TimestampFormat,
+    subscription::{EventSubscription, SubscriptionTarget},
+};
+
+/// Target of a subscription, exactly one of the identifiers.
+#[derive(Debug, Deserialize)]
+pub(super) struct SubscriptionQuery {
+    subject_id: Option<String>,
+    governance_id: Option<String>,
+}
+
+impl TryFrom<SubscriptionQuery> for SubscriptionTarget {
+    type Error = NodeError;
+
+    fn try_from(query: SubscriptionQuery) -> Result<Self, Self::Error> {
+        match (query.subject_id, query.governance_id) {
+            (Some(subject_id), None) => Ok(Self::Subject(subject_id)),
+            (None, Some(governance_id)) => Ok(Self::Governance(governance_id)),
+            _ => Err(NodeError::InvalidParameter(
+                "set either subject_id or governance_id".to_owned(),
+            )),
+        }
+    }
+}
+
+/// Subscribe before upgrading, so that an invalid target is answered with an error status.
+pub(super) async fn subscribe(
+    Caller(api): Caller,
+    Query(query): Query<SubscriptionQuery>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let subscription = api.subscribe(SubscriptionTarget::try_from(query)?).await?;
+    let format = api.timestamp_format();
+    Ok(upgrade.on_upgrade(move |socket| push_events(socket, subscription, format)))
+}
+
+/// Send the events of the subscription, with their timestamps in `format`, until either side
+/// ends.
+async fn push_events(
+    mut socket: WebSocket,
+    mut subscription: EventSubscription,
+    format: TimestampFormat,
+) {
+    loop {
+        tokio::select! {
+            event = subscription.next() => {
+                let Some(event) = event else {
+                    break;
+                };
+                let Ok(text) = serde_json::to_string(&Timestamped::new(&event, format)) else {
+                    continue;
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_subscription_target() {
+        let query = |subject_id: Option<&str>, governance_id: Option<&str>| SubscriptionQuery {
+            subject_id: subject_id.map(str::to_owned),
+            governance_id: governance_id.map(str::to_owned),
+        };
+        assert_eq!(
+            SubscriptionTarget::try_from(query(Some("JSubject"), None)).unwrap(),
+            SubscriptionTarget::Subject("JSubject".to_owned())
+        );
+        assert_eq!(
+            SubscriptionTarget::try_from(query(None, Some("JGovernance"))).unwrap(),
+            SubscriptionTarget::Governance("JGovernance".to_owned())
+        );
+        assert!(SubscriptionTarget::try_from(query(None, None)).is_err());
+        assert!(
+            SubscriptionTarget::try_from(query(Some("JSubject"), Some("JGovernance"))).is_err()
+        );
+    }
+}
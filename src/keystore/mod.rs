@@ -0,0 +1,129 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Node key stores.
+//!
+//! The node key pair is read from the `KeyStore` selected with `keysBackend`:
+//!
+//! * `file` - Key file in the keys directory, encrypted with the node password.
+//! * `pkcs11` - Data object of a PKCS#11 token, such as an HSM (`hsm` feature), encrypted by a
+//!   non-extractable key pair generated on the token. The node password is the user PIN of the
+//!   token.
+//! * `vault` - Secret of the KV engine, or key file encrypted by the Transit engine, of a
+//!   HashiCorp Vault (`vault` feature), configured under `[kore.keys.vault]`.
+//!
+//! Kore Base signs with the key pair in memory, so the token or Vault keeps the key at rest
+//! instead of signing: the key pair is only readable after logging in and is never written in
+//! clear to the disk of the node. Key rotation and previous key versions work on key files only.
+//!
+
+use kore_base::keys::KeyPair;
+
+use crate::{
+    error::NodeError,
+    settings::{KeysBackend, KoreSettings},
+    utils::file_key_pair,
+};
+
+#[cfg(feature = "hsm")]
+mod pkcs11;
+
+#[cfg(feature = "hsm")]
+pub use pkcs11::Pkcs11KeyStore;
+
+#[cfg(feature = "vault")]
+mod vault;
+
+#[cfg(feature = "vault")]
+pub use vault::VaultKeyStore;
+
+/// Store of the node key pair.
+pub trait KeyStore {
+    /// Read the key pair of the node, generating and storing it on first use.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Kore settings
+    /// * `password` - Secret that unlocks the store
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Keys` - The key pair cannot be read or stored
+    ///
+    fn key_pair(&self, settings: &KoreSettings, password: &str) -> Result<KeyPair, NodeError>;
+}
+
+/// Encrypted key file in the keys directory, see `utils::node_key_pair`.
+pub struct FileKeyStore;
+
+impl KeyStore for FileKeyStore {
+    fn key_pair(&self, settings: &KoreSettings, password: &str) -> Result<KeyPair, NodeError> {
+        file_key_pair(settings, password)
+    }
+}
+
+/// Key store of a backend.
+///
+/// # Errors
+///
+/// * `NodeError::Keys` - The backend is not available in this build
+///
+pub fn key_store(backend: KeysBackend) -> Result<Box<dyn KeyStore>, NodeError> {
+    match backend {
+        KeysBackend::File => Ok(Box::new(FileKeyStore)),
+        #[cfg(feature = "hsm")]
+        KeysBackend::Pkcs11 => Ok(Box::new(pkcs11::Pkcs11KeyStore)),
+        #[cfg(not(feature = "hsm"))]
+        KeysBackend::Pkcs11 => Err(NodeError::Keys(
+            "The pkcs11 keys backend requires the hsm feature".to_owned(),
+        )),
+        #[cfg(feature = "vault")]
+        KeysBackend::Vault => Ok(Box::new(vault::VaultKeyStore)),
+        #[cfg(not(feature = "vault"))]
+        KeysBackend::Vault => Err(NodeError::Keys(
+            "The vault keys backend requires the vault feature".to_owned(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use kore_base::keys::KeyMaterial;
+
+    #[test]
+    fn test_file_key_store() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut settings = KoreSettings {
+            keys_path: tempdir.path().to_str().unwrap().to_owned(),
+            ..Default::default()
+        };
+        settings.keys.iterations = crate::utils::MIN_PBKDF2_ITERATIONS;
+        let store = key_store(KeysBackend::File).unwrap();
+        let key_pair = store.key_pair(&settings, "password").unwrap();
+        assert_eq!(
+            store.key_pair(&settings, "password").unwrap().to_bytes(),
+            key_pair.to_bytes()
+        );
+        assert!(store.key_pair(&settings, "other").is_err());
+    }
+
+    #[cfg(not(feature = "hsm"))]
+    #[test]
+    fn test_pkcs11_key_store_unavailable() {
+        assert!(matches!(
+            key_store(KeysBackend::Pkcs11),
+            Err(NodeError::Keys(_))
+        ));
+    }
+
+    #[cfg(not(feature = "vault"))]
+    #[test]
+    fn test_vault_key_store_unavailable() {
+        assert!(matches!(
+            key_store(KeysBackend::Vault),
+            Err(NodeError::Keys(_))
+        ));
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Node key pair protected by a PKCS#11 token.
+//!
+//! Kore Base signs with the node key pair in memory, so the token cannot keep that key pair as a
+//! non-extractable key. It keeps instead an RSA key pair generated on the token with
+//! `C_GenerateKeyPair`, whose private key is sensitive and non-extractable, and the secret key
+//! DER of the node encrypted with it (RSA-OAEP) in a data object. Only the token can decrypt it,
+//! after logging in. A node key kept in clear by a data object of an older version is encrypted
+//! the first time it is read, and the clear object removed.
+//!
+//! The module of the token is loaded and initialized once per process.
+//!
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock, PoisonError},
+};
+
+use cryptoki::{
+    context::{CInitializeArgs, Pkcs11},
+    error::{Error, RvError},
+    mechanism::{
+        rsa::{PkcsMgfType, PkcsOaepParams, PkcsOaepSource},
+        Mechanism, MechanismType,
+    },
+    object::{Attribute, AttributeType, KeyType, ObjectClass, ObjectHandle},
+    session::{Session, UserType},
+    types::{AuthPin, Ulong},
+};
+use kore_base::keys::KeyPair;
+
+use super::KeyStore;
+use crate::{
+    error::NodeError,
+    settings::{KoreSettings, Pkcs11Settings},
+    utils::{key_pair_from_der, new_key_pair},
+};
+
+/// Size of the RSA key that encrypts the node key.
+const RSA_KEY_BITS: u64 = 3072;
+
+/// Application of the data object that holds the encrypted node key.
+const ENCRYPTED_KEY_APPLICATION: &[u8] = b"kore-node rsa-oaep";
+
+/// Modules loaded, by path.
+static CONTEXTS: OnceLock<Mutex<HashMap<String, Pkcs11>>> = OnceLock::new();
+
+/// Data object of a PKCS#11 token, such as an HSM, holding the secret key DER encrypted by a
+/// non-extractable key pair of the token.
+pub struct Pkcs11KeyStore;
+
+impl KeyStore for Pkcs11KeyStore {
+    fn key_pair(&self, settings: &KoreSettings, password: &str) -> Result<KeyPair, NodeError> {
+        let token = &settings.pkcs11;
+        let key_derivator = settings.settings.node.key_derivator;
+        let session = login(token, password)?;
+        if let Some(object) = find_one(&session, token, &encrypted_key_template(token))? {
+            let Some((_, private_key)) = encryption_keys(&session, token)? else {
+                return Err(NodeError::Keys(format!(
+                    "PKCS#11 key '{}' that decrypts the node key not found in token '{}'",
+                    token.label, token.token
+                )));
+            };
+            let der = session
+                .decrypt(&oaep(), private_key, &value(&session, object, token)?)
+                .map_err(pkcs11_error)?;
+            return key_pair_from_der(key_derivator, &der);
+        }
+
+        // First use of the token, or node key kept in clear by an older version.
+        let clear = find_one(
+            &session,
+            token,
+            &[
+                Attribute::Class(ObjectClass::DATA),
+                Attribute::Label(token.label.as_bytes().to_vec()),
+            ],
+        )?;
+        let key_pair = match clear {
+            Some(object) => key_pair_from_der(key_derivator, &value(&session, object, token)?)?,
+            None => new_key_pair(key_derivator),
+        };
+        let (public_key, _) = match encryption_keys(&session, token)? {
+            Some(keys) => keys,
+            None => generate_encryption_keys(&session, token)?,
+        };
+        let der = key_pair
+            .to_secret_der()
+            .map_err(|error| NodeError::Keys(format!("Error getting secret der: {}", error)))?;
+        let encrypted = session
+            .encrypt(&oaep(), public_key, &der)
+            .map_err(pkcs11_error)?;
+        let mut object = encrypted_key_template(token).to_vec();
+        object.extend([
+            Attribute::Token(true),
+            Attribute::Private(true),
+            Attribute::Value(encrypted),
+        ]);
+        session.create_object(&object).map_err(pkcs11_error)?;
+        match clear {
+            Some(object) => {
+                session.destroy_object(object).map_err(pkcs11_error)?;
+                log::info!(
+                    "Node key in PKCS#11 token '{}' encrypted with key '{}'",
+                    token.token,
+                    token.label
+                );
+            }
+            None => log::info!(
+                "Node key pair generated and encrypted with key '{}' of PKCS#11 token '{}'",
+                token.label,
+                token.token
+            ),
+        }
+        Ok(key_pair)
+    }
+}
+
+/// PKCS#11 module, loaded and initialized the first time it is used.
+fn context(module: &str) -> Result<Pkcs11, NodeError> {
+    let mut contexts = CONTEXTS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(context) = contexts.get(module) {
+        return Ok(context.clone());
+    }
+    let context = Pkcs11::new(module).map_err(pkcs11_error)?;
+    match context.initialize(CInitializeArgs::OsThreads) {
+        Ok(()) | Err(Error::Pkcs11(RvError::CryptokiAlreadyInitialized, ..)) => {}
+        Err(error) => return Err(pkcs11_error(error)),
+    }
+    contexts.insert(module.to_owned(), context.clone());
+    Ok(context)
+}
+
+/// Open a read-write session on the token and log in as its user.
+fn login(token: &Pkcs11Settings, pin: &str) -> Result<Session, NodeError> {
+    let context = context(&token.module)?;
+    let slot = context
+        .get_slots_with_token()
+        .map_err(pkcs11_error)?
+        .into_iter()
+        .find(|slot| {
+            context
+                .get_token_info(*slot)
+                .is_ok_and(|info| info.label() == token.token)
+        })
+        .ok_or_else(|| NodeError::Keys(format!("PKCS#11 token '{}' not found", token.token)))?;
+    let session = context.open_rw_session(slot).map_err(pkcs11_error)?;
+    session
+        .login(UserType::User, Some(&AuthPin::new(pin.to_owned())))
+        .map_err(|error| {
+            NodeError::Keys(format!(
+                "Error logging in to PKCS#11 token '{}', check the password: {}",
+                token.token, error
+            ))
+        })?;
+    Ok(session)
+}
+
+/// Template of the data object that holds the encrypted node key.
+fn encrypted_key_template(token: &Pkcs11Settings) -> [Attribute; 3] {
+    [
+        Attribute::Class(ObjectClass::DATA),
+        Attribute::Label(token.label.as_bytes().to_vec()),
+        Attribute::Application(ENCRYPTED_KEY_APPLICATION.to_vec()),
+    ]
+}
+
+/// The object that matches the template, `None` if there is none.
+///
+/// # Errors
+///
+/// * `NodeError::Keys` - More than one object matches.
+///
+fn find_one(
+    session: &Session,
+    token: &Pkcs11Settings,
+    template: &[Attribute],
+) -> Result<Option<ObjectHandle>, NodeError> {
+    let objects = session.find_objects(template).map_err(pkcs11_error)?;
+    match objects.as_slice() {
+        [] => Ok(None),
+        [object] => Ok(Some(*object)),
+        _ => Err(NodeError::Keys(format!(
+            "{} PKCS#11 objects are labelled '{}' in token '{}', expected one",
+            objects.len(),
+            token.label,
+            token.token
+        ))),
+    }
+}
+
+/// Public and private keys of the RSA key pair that encrypts the node key, if generated.
+fn encryption_keys(
+    session: &Session,
+    token: &Pkcs11Settings,
+) -> Result<Option<(ObjectHandle, ObjectHandle)>, NodeError> {
+    let key = |class| {
+        find_one(
+            session,
+            token,
+            &[
+                Attribute::Class(class),
+                Attribute::KeyType(KeyType::RSA),
+                Attribute::Label(token.label.as_bytes().to_vec()),
+            ],
+        )
+    };
+    match (
+        key(ObjectClass::PUBLIC_KEY)?,
+        key(ObjectClass::PRIVATE_KEY)?,
+    ) {
+        (Some(public_key), Some(private_key)) => Ok(Some((public_key, private_key))),
+        (None, None) => Ok(None),
+        _ => Err(NodeError::Keys(format!(
+            "PKCS#11 key pair '{}' of token '{}' is incomplete",
+            token.label, token.token
+        ))),
+    }
+}
+
+/// Generate on the token the RSA key pair that encrypts the node key. Its private key never
+/// leaves the token.
+fn generate_encryption_keys(
+    session: &Session,
+    token: &Pkcs11Settings,
+) -> Result<(ObjectHandle, ObjectHandle), NodeError> {
+    let label = Attribute::Label(token.label.as_bytes().to_vec());
+    let public_key = [
+        Attribute::Token(true),
+        Attribute::Encrypt(true),
+        Attribute::ModulusBits(Ulong::from(RSA_KEY_BITS)),
+        Attribute::PublicExponent(vec![0x01, 0x00, 0x01]),
+        label.clone(),
+    ];
+    let private_key = [
+        Attribute::Token(true),
+        Attribute::Private(true),
+        Attribute::Sensitive(true),
+        Attribute::Extractable(false),
+        Attribute::Decrypt(true),
+        label,
+    ];
+    session
+        .generate_key_pair(&Mechanism::RsaPkcsKeyPairGen, &public_key, &private_key)
+        .map_err(pkcs11_error)
+}
+
+/// RSA-OAEP with SHA-256.
+fn oaep() -> Mechanism<'static> {
+    Mechanism::RsaPkcsOaep(PkcsOaepParams::new(
+        MechanismType::SHA256,
+        PkcsMgfType::MGF1_SHA256,
+        PkcsOaepSource::empty(),
+    ))
+}
+
+/// Value of a data object.
+fn value(
+    session: &Session,
+    object: ObjectHandle,
+    token: &Pkcs11Settings,
+) -> Result<Vec<u8>, NodeError> {
+    let attributes = session
+        .get_attributes(object, &[AttributeType::Value])
+        .map_err(pkcs11_error)?;
+    match attributes.into_iter().next() {
+        Some(Attribute::Value(value)) => Ok(value),
+        _ => Err(NodeError::Keys(format!(
+            "PKCS#11 object '{}' has no value",
+            token.label
+        ))),
+    }
+}
+
+fn pkcs11_error(error: Error) -> NodeError {
+    NodeError::Keys(format!("PKCS#11 error: {}", error))
+}
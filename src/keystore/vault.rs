@@ -0,0 +1,241 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Node key pair in HashiCorp Vault.
+//!
+//! With the KV engine (version 2) the secret key DER is a secret of the engine, written once
+//! with check-and-set so that two nodes never replace each other's key. With the Transit engine
+//! the secret key DER is encrypted by Vault and only the ciphertext is kept, in the keys
+//! directory as `node_private.vault`.
+//!
+
+use std::{fs, io::ErrorKind, thread, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use kore_base::keys::KeyPair;
+use reqwest::{
+    blocking::Client,
+    header::{HeaderValue, CONTENT_TYPE},
+    Method, StatusCode,
+};
+use serde_json::{json, Value};
+
+use super::KeyStore;
+use crate::{
+    error::NodeError,
+    settings::{KoreSettings, VaultEngine, VaultSettings},
+    utils::{key_pair_from_der, new_key_pair},
+};
+
+/// Field of the KV secret that holds the secret key DER, base64 encoded.
+const KV_FIELD: &str = "private_key";
+
+/// Time allowed for each request to Vault.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Key pair in HashiCorp Vault, see `VaultEngine`. The password of the node is not used: the
+/// node authenticates with the credentials of `kore.keys.vault`.
+pub struct VaultKeyStore;
+
+impl KeyStore for VaultKeyStore {
+    fn key_pair(&self, settings: &KoreSettings, _password: &str) -> Result<KeyPair, NodeError> {
+        // The blocking client runs its own runtime, which cannot be started from a task of the
+        // runtime of the node.
+        thread::scope(|scope| {
+            scope
+                .spawn(|| vault_key_pair(settings))
+                .join()
+                .unwrap_or_else(|_| Err(NodeError::Keys("Vault client panicked".to_owned())))
+        })
+    }
+}
+
+/// Read the key pair from Vault, generating it on first use.
+fn vault_key_pair(settings: &KoreSettings) -> Result<KeyPair, NodeError> {
+    let vault = Vault::login(&settings.keys.vault)?;
+    let key_derivator = settings.settings.node.key_derivator;
+    match settings.keys.vault.engine {
+        VaultEngine::Kv => {
+            if let Some(der) = vault.read_kv()? {
+                return key_pair_from_der(key_derivator, &der);
+            }
+            let key_pair = new_key_pair(key_derivator);
+            vault.write_kv(&secret_der(&key_pair)?)?;
+            log::info!("Node key pair generated in Vault");
+            Ok(key_pair)
+        }
+        VaultEngine::Transit => {
+            let path = format!("{}/node_private.vault", settings.keys_path);
+            match fs::read_to_string(&path) {
+                Ok(ciphertext) => {
+                    key_pair_from_der(key_derivator, &vault.decrypt(ciphertext.trim())?)
+                }
+                Err(error) if error.kind() == ErrorKind::NotFound => {
+                    let key_pair = new_key_pair(key_derivator);
+                    let ciphertext = vault.encrypt(&secret_der(&key_pair)?)?;
+                    let partial = format!("{}.partial", path);
+                    fs::create_dir_all(&settings.keys_path)
+                        .and_then(|_| fs::write(&partial, ciphertext))
+                        .and_then(|_| fs::rename(&partial, &path))
+                        .map_err(|error| {
+                            NodeError::Keys(format!("Error writing {}: {}", path, error))
+                        })?;
+                    log::info!("Node key pair generated and encrypted with Vault Transit");
+                    Ok(key_pair)
+                }
+                Err(error) => Err(NodeError::Keys(format!(
+                    "Error reading {}: {}",
+                    path, error
+                ))),
+            }
+        }
+    }
+}
+
+fn secret_der(key_pair: &KeyPair) -> Result<Vec<u8>, NodeError> {
+    key_pair
+        .to_secret_der()
+        .map_err(|error| NodeError::Keys(format!("Error getting secret der: {}", error)))
+}
+
+/// Session on a Vault server.
+struct Vault<'a> {
+    client: Client,
+    settings: &'a VaultSettings,
+    token: String,
+}
+
+impl<'a> Vault<'a> {
+    /// Log in with the token of the settings or, when it is empty, with AppRole.
+    fn login(settings: &'a VaultSettings) -> Result<Self, NodeError> {
+        let client = Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .map_err(vault_error)?;
+        let mut vault = Self {
+            client,
+            settings,
+            token: settings.token.clone(),
+        };
+        if vault.token.is_empty() {
+            let body = json!({ "role_id": settings.role_id, "secret_id": settings.secret_id });
+            let response = vault
+                .request(Method::POST, "auth/approle/login", Some(body))?
+                .ok_or_else(|| NodeError::Keys("Vault AppRole login not found".to_owned()))?;
+            vault.token = response
+                .pointer("/auth/client_token")
+                .and_then(Value::as_str)
+                .ok_or_else(|| NodeError::Keys("Vault AppRole login without token".to_owned()))?
+                .to_owned();
+        }
+        Ok(vault)
+    }
+
+    /// Secret key DER of the KV secret, `None` if the secret does not exist.
+    fn read_kv(&self) -> Result<Option<Vec<u8>>, NodeError> {
+        let Some(response) = self.request(Method::GET, &self.kv_path(), None)? else {
+            return Ok(None);
+        };
+        let encoded = response
+            .pointer(&format!("/data/data/{}", KV_FIELD))
+            .and_then(Value::as_str)
+            .ok_or_else(|| {
+                NodeError::Keys(format!(
+                    "Vault secret {} has no {}",
+                    self.kv_path(),
+                    KV_FIELD
+                ))
+            })?;
+        decode(encoded).map(Some)
+    }
+
+    /// Write the KV secret, unless it already exists.
+    fn write_kv(&self, der: &[u8]) -> Result<(), NodeError> {
+        let body = json!({
+            "options": { "cas": 0 },
+            "data": { KV_FIELD: STANDARD.encode(der) },
+        });
+        self.request(Method::POST, &self.kv_path(), Some(body))
+            .map(|_| ())
+    }
+
+    /// Encrypt with the Transit key, returning the ciphertext.
+    fn encrypt(&self, der: &[u8]) -> Result<String, NodeError> {
+        let path = format!("{}/encrypt/{}", self.settings.mount, self.settings.path);
+        let body = json!({ "plaintext": STANDARD.encode(der) });
+        self.transit_field(&path, body, "ciphertext")
+    }
+
+    /// Decrypt with the Transit key.
+    fn decrypt(&self, ciphertext: &str) -> Result<Vec<u8>, NodeError> {
+        let path = format!("{}/decrypt/{}", self.settings.mount, self.settings.path);
+        let body = json!({ "ciphertext": ciphertext });
+        decode(&self.transit_field(&path, body, "plaintext")?)
+    }
+
+    fn transit_field(&self, path: &str, body: Value, field: &str) -> Result<String, NodeError> {
+        self.request(Method::POST, path, Some(body))?
+            .and_then(|response| {
+                response
+                    .pointer(&format!("/data/{}", field))?
+                    .as_str()
+                    .map(str::to_owned)
+            })
+            .ok_or_else(|| NodeError::Keys(format!("Vault {} returned no {}", path, field)))
+    }
+
+    fn kv_path(&self) -> String {
+        format!("{}/data/{}", self.settings.mount, self.settings.path)
+    }
+
+    /// Send a request to the API of Vault, `None` when the path is not found.
+    fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<Option<Value>, NodeError> {
+        let url = format!(
+            "{}/v1/{}",
+            self.settings.address.trim_end_matches('/'),
+            path
+        );
+        let mut request = self.client.request(method, &url);
+        if !self.token.is_empty() {
+            request = request.header("X-Vault-Token", &self.token);
+        }
+        if let Some(body) = body {
+            request = request
+                .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                .body(body.to_string());
+        }
+        let response = request.send().map_err(vault_error)?;
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let text = response.text().map_err(vault_error)?;
+        if !status.is_success() {
+            return Err(NodeError::Keys(format!(
+                "Vault {} failed with {}: {}",
+                path, status, text
+            )));
+        }
+        if text.is_empty() {
+            return Ok(Some(Value::Null));
+        }
+        serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|error| NodeError::Keys(format!("Vault {} response: {}", path, error)))
+    }
+}
+
+fn decode(encoded: &str) -> Result<Vec<u8>, NodeError> {
+    STANDARD
+        .decode(encoded)
+        .map_err(|error| NodeError::Keys(format!("Vault value is not base64: {}", error)))
+}
+
+fn vault_error(error: reqwest::Error) -> NodeError {
+    NodeError::Keys(format!("Vault: {}", error))
+}
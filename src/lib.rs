@@ -1,20 +1,80 @@
 // Copyright 2024 Kore Ledger
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
+pub mod access_log;
 pub mod api;
+pub mod archival;
+pub mod auth;
+pub mod backup;
+pub mod bootstrap;
+pub mod cli;
+#[cfg(any(
+    feature = "leveldb",
+    feature = "sled",
+    feature = "redb",
+    feature = "sqlite"
+))]
+pub mod cluster;
 pub mod config;
+pub mod contracts;
 mod database;
+pub mod dns;
 pub mod error;
+pub mod expiry;
+#[cfg(feature = "export")]
+pub mod export;
+pub mod features;
+pub mod governance;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "http-api")]
+pub mod http_api;
+pub mod keystore;
+pub mod lifecycle;
+mod limits;
+pub mod listener;
+pub mod logging;
+pub mod metrics;
+pub mod migration;
 pub mod model;
 pub mod node;
+mod peers;
 #[cfg(feature = "prometheus")]
 mod prometheus;
+pub mod reconcile;
+#[cfg(feature = "replication")]
+pub mod replication;
+pub mod scheduler;
+pub mod search;
+#[cfg(feature = "services")]
+pub mod services;
 mod settings;
+#[cfg(feature = "soak")]
+pub mod soak;
+pub mod subscription;
+pub mod support;
+pub mod surface;
+pub mod tasks;
+pub mod usage;
 mod utils;
+mod verification;
+pub mod warm_up;
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
 pub use clap;
 
 pub use api::KoreApi;
+pub use database::{codec, store::NodeStore};
 #[cfg(feature = "leveldb")]
-pub use node::{KoreNode, LevelDBNode};
+pub use node::LevelDBNode;
+#[cfg(feature = "postgres")]
+pub use node::PostgresNode;
+#[cfg(feature = "redb")]
+pub use node::RedbNode;
+#[cfg(feature = "sled")]
+pub use node::SledNode;
 #[cfg(feature = "sqlite")]
-pub use node::{KoreNode, SqliteNode};
+pub use node::SqliteNode;
+pub use node::{DatabaseNode, KoreNode, KoreNodeBuilder, Supervisor};
+pub use surface::{AdminApi, PublicApi};
+pub use utils::rotate_node_key_pair;
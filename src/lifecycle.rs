@@ -0,0 +1,399 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Lifecycle events.
+//!
+//! With `kore.lifecycle_events`, the node writes what happens to it as a process to the standard
+//! output, one JSON object per line, for supervisors such as systemd, Nomad or Kubernetes
+//! operators to follow without parsing the logs, which go to the standard error or a file.
+//! Every line has the `event` name, the fields of the event and a RFC 3339 `timestamp`:
+//!
+//! | Event | Fields | When |
+//! |---|---|---|
+//! | `starting` | `version` | The node is being built |
+//! | `listening` | `addresses` | The addresses Kore Base binds, fallback and free ports applied |
+//! | `metrics_bound` | `address` | The prometheus server is bound, also after a reload |
+//! | `ready` | `controller_id`, `version` | The APIs are served, after the warm-up if any |
+//! | `degraded` | `component`, `reason` | Something does not work as configured, the node runs on |
+//! | `contract_failed` | `contract`, `reason` | A new or changed contract module is not valid |
+//! | `shutdown` | `reason` | The node stops |
+//! | `stopped` | | The tasks of the cancelled node returned and its database is released |
+//!
+//! ```text
+//! {"event":"ready","controller_id":"E...","version":"0.5.0","timestamp":"2024-05-02T10:00:01.250Z"}
+//! ```
+//!
+//! Embedders get the same events, whether they are written or not, from
+//! `LifecycleEvents::subscribe`, and the state they lead to from `KoreNode::status`: `starting`,
+//! then `network_ready` on `listening` and `serving` on `ready`, `degraded` on `degraded`,
+//! `shutting_down` on `shutdown` and `stopped` at last, unless the supervisor restarts the node
+//! with a new `starting`. A degraded node stays `degraded` until it shuts down, as no event
+//! reports that a component recovered.
+//!
+
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex, RwLock},
+    time::SystemTime,
+};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Events kept for the subscribers that fall behind; slower ones miss the oldest.
+const SUBSCRIBER_CAPACITY: usize = 64;
+
+/// Event of the lifecycle of the node.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LifecycleEvent {
+    /// The node is being built.
+    Starting {
+        /// Version of kore-node.
+        version: String,
+    },
+    /// Addresses of the ledger network that the node listens on.
+    Listening {
+        /// Multiaddresses, with the port picked when 0 was configured.
+        addresses: Vec<String>,
+    },
+    /// Address the prometheus server is bound to.
+    MetricsBound {
+        /// Socket address, with the port picked when 0 was configured.
+        address: String,
+    },
+    /// The APIs are served.
+    Ready {
+        /// Controller identifier of the node.
+        controller_id: String,
+        /// Version of kore-node.
+        version: String,
+    },
+    /// A component of the node does not work as configured; the node keeps running.
+    Degraded {
+        /// Component, e.g. `bootstrap` or `api`.
+        component: String,
+        /// What is wrong.
+        reason: String,
+    },
+    /// A new or changed module of the contracts directory is not a valid contract; the node
+    /// runs on, see the `contracts` module.
+    ContractFailed {
+        /// Contract name, the file name without extension.
+        contract: String,
+        /// What is wrong.
+        reason: String,
+    },
+    /// The node is stopping.
+    Shutdown {
+        /// Why, e.g. `signal`.
+        reason: String,
+    },
+    /// The node was cancelled and its servers and tasks returned.
+    Stopped,
+}
+
+impl LifecycleEvent {
+    /// Event of a degraded component.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - Component, e.g. `bootstrap`.
+    /// * `reason` - What is wrong.
+    ///
+    pub fn degraded(component: &str, reason: impl Into<String>) -> Self {
+        LifecycleEvent::Degraded {
+            component: component.to_owned(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// State of the node, as a supervisor sees it.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum NodeState {
+    /// The node is being built.
+    #[default]
+    Starting,
+    /// Kore Base listens on the ledger network.
+    NetworkReady,
+    /// The warm-up, if any, is over and the APIs are served. The ledger may still be behind
+    /// the other nodes.
+    Serving,
+    /// A component does not work as configured, the node runs on.
+    Degraded {
+        /// Component and what is wrong with it, e.g. `api: address in use`.
+        reason: String,
+    },
+    /// The node is stopping.
+    ShuttingDown,
+    /// The node was cancelled and its tasks returned.
+    Stopped,
+}
+
+impl NodeState {
+    /// State after an event. A stopped node stays stopped until the supervisor starts it again,
+    /// and a degraded one stays degraded until it shuts down.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - Event emitted in this state.
+    ///
+    pub fn next(&self, event: &LifecycleEvent) -> NodeState {
+        match (self, event) {
+            (_, LifecycleEvent::Starting { .. }) => NodeState::Starting,
+            (NodeState::Stopped, _)
+            | (_, LifecycleEvent::MetricsBound { .. })
+            | (_, LifecycleEvent::ContractFailed { .. }) => self.clone(),
+            (_, LifecycleEvent::Stopped) => NodeState::Stopped,
+            (_, LifecycleEvent::Shutdown { .. }) => NodeState::ShuttingDown,
+            (NodeState::ShuttingDown, _) => NodeState::ShuttingDown,
+            (_, LifecycleEvent::Degraded { component, reason }) => NodeState::Degraded {
+                reason: format!("{}: {}", component, reason),
+            },
+            (NodeState::Degraded { .. }, _) => self.clone(),
+            (_, LifecycleEvent::Listening { .. }) => NodeState::NetworkReady,
+            (_, LifecycleEvent::Ready { .. }) => NodeState::Serving,
+        }
+    }
+}
+
+/// Output of the lifecycle events, cheap to clone. Its clones share the state and the
+/// subscribers; events are written only when the output is enabled.
+#[derive(Clone)]
+pub struct LifecycleEvents {
+    output: Option<Arc<Mutex<Box<dyn Write + Send>>>>,
+    subscribers: broadcast::Sender<LifecycleEvent>,
+    state: Arc<RwLock<NodeState>>,
+}
+
+impl Default for LifecycleEvents {
+    fn default() -> Self {
+        let (subscribers, _) = broadcast::channel(SUBSCRIBER_CAPACITY);
+        Self {
+            output: None,
+            subscribers,
+            state: Arc::default(),
+        }
+    }
+}
+
+impl LifecycleEvents {
+    /// Events written to the standard output when `enabled`, dropped otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether events are written, `kore.lifecycle_events`.
+    ///
+    pub fn stdout(enabled: bool) -> Self {
+        if enabled {
+            Self::to_writer(io::stdout())
+        } else {
+            Self::default()
+        }
+    }
+
+    /// Events written to any output, such as a pipe of the embedding process.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Output of the lines.
+    ///
+    pub fn to_writer(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            output: Some(Arc::new(Mutex::new(Box::new(writer)))),
+            ..Self::default()
+        }
+    }
+
+    /// Whether events are written.
+    pub fn is_enabled(&self) -> bool {
+        self.output.is_some()
+    }
+
+    /// Receiver of the events emitted from now on, written or not.
+    pub fn subscribe(&self) -> broadcast::Receiver<LifecycleEvent> {
+        self.subscribers.subscribe()
+    }
+
+    /// State the events emitted so far lead to.
+    pub fn state(&self) -> NodeState {
+        self.state
+            .read()
+            .map(|state| state.clone())
+            .unwrap_or_default()
+    }
+
+    /// Move to the state of an event, send it to the subscribers and write it as a line, flushed
+    /// at once so that supervisors see it.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - Event to emit.
+    ///
+    pub fn emit(&self, event: LifecycleEvent) {
+        if let Ok(mut state) = self.state.write() {
+            *state = state.next(&event);
+        }
+        // No subscriber is not an error.
+        let _ = self.subscribers.send(event.clone());
+        let Some(output) = &self.output else {
+            return;
+        };
+        let line = line(&event, SystemTime::now());
+        let Ok(mut output) = output.lock() else {
+            return;
+        };
+        if let Err(error) = writeln!(output, "{}", line).and_then(|_| output.flush()) {
+            log::debug!("Lifecycle event not written: {}", error);
+        }
+    }
+}
+
+/// Line of an event, with its time.
+#[derive(Serialize)]
+struct Line<'a> {
+    #[serde(flatten)]
+    event: &'a LifecycleEvent,
+    timestamp: String,
+}
+
+/// JSON line of an event.
+fn line(event: &LifecycleEvent, time: SystemTime) -> String {
+    let line = Line {
+        event,
+        timestamp: humantime::format_rfc3339_millis(time).to_string(),
+    };
+    serde_json::to_string(&line).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use serde_json::Value;
+
+    use super::*;
+
+    /// Output that keeps the lines written, for the tests of the emitters.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Captured {
+        /// Events written so far.
+        fn events(&self) -> Vec<Value> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_lifecycle_line() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_714_644_001_250);
+        let event = LifecycleEvent::Listening {
+            addresses: vec!["/ip4/0.0.0.0/tcp/50000".to_owned()],
+        };
+        assert_eq!(
+            line(&event, time),
+            r#"{"event":"listening","addresses":["/ip4/0.0.0.0/tcp/50000"],"timestamp":"2024-05-02T10:00:01.250Z"}"#
+        );
+    }
+
+    #[test]
+    fn test_lifecycle_events() {
+        let captured = Captured::default();
+        let events = LifecycleEvents::to_writer(captured.clone());
+        assert!(events.is_enabled());
+        events.emit(LifecycleEvent::degraded("bootstrap", "eu-west unreachable"));
+        events.emit(LifecycleEvent::Shutdown {
+            reason: "signal".to_owned(),
+        });
+        let written = captured.events();
+        assert_eq!(written.len(), 2);
+        assert_eq!(written[0]["event"], "degraded");
+        assert_eq!(written[0]["component"], "bootstrap");
+        assert_eq!(written[1]["event"], "shutdown");
+        assert!(written[1]["timestamp"].is_string());
+
+        let disabled = LifecycleEvents::stdout(false);
+        assert!(!disabled.is_enabled());
+        disabled.emit(LifecycleEvent::Shutdown {
+            reason: "signal".to_owned(),
+        });
+        assert_eq!(disabled.state(), NodeState::ShuttingDown);
+    }
+
+    #[test]
+    fn test_node_state() {
+        let ready = LifecycleEvent::Ready {
+            controller_id: "E1".to_owned(),
+            version: "0.5.0".to_owned(),
+        };
+        let listening = LifecycleEvent::Listening { addresses: vec![] };
+        let state = NodeState::default().next(&listening).next(&ready);
+        assert_eq!(state, NodeState::Serving);
+
+        let degraded = state.next(&LifecycleEvent::degraded(
+            "bootstrap",
+            "eu-west unreachable",
+        ));
+        assert_eq!(
+            degraded,
+            NodeState::Degraded {
+                reason: "bootstrap: eu-west unreachable".to_owned()
+            }
+        );
+        assert_eq!(degraded.next(&ready), degraded);
+
+        let shutdown = LifecycleEvent::Shutdown {
+            reason: "signal".to_owned(),
+        };
+        let failed = LifecycleEvent::ContractFailed {
+            contract: "token".to_owned(),
+            reason: "not a WebAssembly module".to_owned(),
+        };
+        assert_eq!(state.next(&failed), NodeState::Serving);
+        let stopping = degraded.next(&shutdown);
+        assert_eq!(stopping, NodeState::ShuttingDown);
+        assert_eq!(stopping.next(&listening), NodeState::ShuttingDown);
+        let stopped = stopping.next(&LifecycleEvent::Stopped);
+        assert_eq!(stopped, NodeState::Stopped);
+        assert_eq!(stopped.next(&ready), NodeState::Stopped);
+        let restarted = stopped.next(&LifecycleEvent::Starting {
+            version: "0.5.0".to_owned(),
+        });
+        assert_eq!(restarted, NodeState::Starting);
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_subscribers() {
+        let events = LifecycleEvents::default();
+        let mut subscriber = events.subscribe();
+        let clone = events.clone();
+        clone.emit(LifecycleEvent::Starting {
+            version: "0.5.0".to_owned(),
+        });
+        clone.emit(LifecycleEvent::Stopped);
+        assert!(matches!(
+            subscriber.recv().await.unwrap(),
+            LifecycleEvent::Starting { .. }
+        ));
+        assert_eq!(subscriber.recv().await.unwrap(), LifecycleEvent::Stopped);
+        assert_eq!(events.state(), NodeState::Stopped);
+    }
+}
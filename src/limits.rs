@@ -0,0 +1,225 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Limits of the API calls.
+//!
+//! Every call of `KoreApi` to Kore Base is admitted by the limiter of its class, reads or writes,
+//! before it runs. A token bucket refilled at `rate` tokens per second, holding up to `burst`,
+//! bounds the call rate, and a semaphore bounds the calls in flight. A call over either limit
+//! fails at once with `NodeError::RateLimited`, so that an embedder serving the API over the
+//! network sheds the excess load instead of queuing it. A zero rate or `max_in_flight` disables
+//! that limit. The background tasks of the node, such as the indexer, the archival or the
+//! replication, call through handles with limits of their own, see `KoreApi::background`, so
+//! that the clients never shed their calls.
+//!
+
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::Instant,
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{
+    error::NodeError,
+    settings::{CallLimit, LimitsSettings},
+};
+
+/// Class of a call, each one with its own limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CallClass {
+    /// Calls that only read.
+    Read,
+    /// Calls that change the ledger or the keys.
+    Write,
+}
+
+impl CallClass {
+    /// Name of the class in the errors.
+    fn name(&self) -> &'static str {
+        match self {
+            CallClass::Read => "reads",
+            CallClass::Write => "writes",
+        }
+    }
+}
+
+/// Tokens of the calls, refilled over time.
+#[derive(Debug)]
+struct TokenBucket {
+    /// Tokens added per second.
+    rate: f64,
+    /// Tokens the bucket holds at most.
+    capacity: f64,
+    /// Tokens available at `updated`.
+    tokens: f64,
+    /// Last refill.
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Full bucket.
+    fn new(rate: f64, burst: u32, now: Instant) -> Self {
+        let capacity = f64::from(burst.max(1));
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            updated: now,
+        }
+    }
+
+    /// Take a token if there is one.
+    fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// Limits of a class of calls.
+#[derive(Debug, Clone)]
+struct ClassLimiter {
+    /// Rate limit, `None` when disabled.
+    bucket: Option<Arc<Mutex<TokenBucket>>>,
+    /// Calls in flight, `None` when unbounded.
+    in_flight: Option<Arc<Semaphore>>,
+}
+
+impl ClassLimiter {
+    /// Limiter of a class, with a full bucket.
+    fn new(limit: &CallLimit) -> Self {
+        let bucket = (limit.rate > 0.0).then(|| {
+            Arc::new(Mutex::new(TokenBucket::new(
+                limit.rate,
+                limit.burst,
+                Instant::now(),
+            )))
+        });
+        let in_flight =
+            (limit.max_in_flight > 0).then(|| Arc::new(Semaphore::new(limit.max_in_flight)));
+        Self { bucket, in_flight }
+    }
+
+    /// Admit a call, taking a slot in flight and then a token.
+    fn admit(&self, class: CallClass) -> Result<CallPermit, NodeError> {
+        let permit = match &self.in_flight {
+            Some(in_flight) => Some(in_flight.clone().try_acquire_owned().map_err(|_| {
+                NodeError::RateLimited(format!("too many {} in flight", class.name()))
+            })?),
+            None => None,
+        };
+        if let Some(bucket) = &self.bucket {
+            // The bucket is only counters, still consistent after a panic.
+            let taken = bucket
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .try_take(Instant::now());
+            if !taken {
+                return Err(NodeError::RateLimited(format!(
+                    "{} over the rate limit",
+                    class.name()
+                )));
+            }
+        }
+        Ok(CallPermit { _in_flight: permit })
+    }
+}
+
+/// Admission of a call, which counts as in flight until dropped.
+#[derive(Debug)]
+pub(crate) struct CallPermit {
+    _in_flight: Option<OwnedSemaphorePermit>,
+}
+
+/// Limiters of the calls of a node API, shared by its clones.
+#[derive(Debug, Clone)]
+pub(crate) struct CallLimiter {
+    reads: ClassLimiter,
+    writes: ClassLimiter,
+}
+
+impl CallLimiter {
+    /// Limiters with full buckets and no call in flight.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Limits of the reads and the writes.
+    ///
+    pub(crate) fn new(settings: &LimitsSettings) -> Self {
+        Self {
+            reads: ClassLimiter::new(&settings.reads),
+            writes: ClassLimiter::new(&settings.writes),
+        }
+    }
+
+    /// Admit a call of a class.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::RateLimited` - The class is over its rate or has too many calls in flight.
+    ///
+    pub(crate) fn admit(&self, class: CallClass) -> Result<CallPermit, NodeError> {
+        match class {
+            CallClass::Read => self.reads.admit(class),
+            CallClass::Write => self.writes.admit(class),
+        }
+    }
+}
+
+impl Default for CallLimiter {
+    fn default() -> Self {
+        Self::new(&LimitsSettings::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, 3, start);
+        for _ in 0..3 {
+            assert!(bucket.try_take(start));
+        }
+        assert!(!bucket.try_take(start));
+        assert!(bucket.try_take(start + Duration::from_millis(500)));
+        assert!(!bucket.try_take(start + Duration::from_millis(600)));
+        // Idle time never fills the bucket over its burst.
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(bucket.try_take(later));
+        }
+        assert!(!bucket.try_take(later));
+    }
+
+    #[test]
+    fn test_call_limiter() {
+        let limiter = CallLimiter::new(&LimitsSettings {
+            reads: CallLimit::default(),
+            writes: CallLimit {
+                rate: 0.0,
+                burst: 0,
+                max_in_flight: 1,
+            },
+        });
+        let first = limiter.admit(CallClass::Write).unwrap();
+        assert!(matches!(
+            limiter.admit(CallClass::Write),
+            Err(NodeError::RateLimited(_))
+        ));
+        assert!(limiter.admit(CallClass::Read).is_ok());
+        drop(first);
+        assert!(limiter.admit(CallClass::Write).is_ok());
+    }
+}
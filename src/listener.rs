@@ -0,0 +1,321 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Listeners of the HTTP servers.
+//!
+//! The metrics server and the REST API listen on a TCP address, `<host>:<port>`, or, on Unix, on
+//! a domain socket given as `unix://<path>`, e.g. `unix:///var/run/kore.sock`, so that sidecar
+//! processes reach the node without opening a TCP port. The permissions of the socket file decide
+//! who connects: it is created readable and writable by the user and the group of the node only
+//! (`0660`), before the path is bound, whatever the umask. The routes see its clients from the
+//! unspecified address, not the loopback one, so they reach the admin surface only with the admin
+//! token. A socket file left by a previous run is replaced on bind, and the file is removed when
+//! the server stops.
+//!
+
+use std::{
+    fmt,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+/// Prefix of the Unix socket addresses.
+pub const UNIX_SCHEME: &str = "unix://";
+
+/// Path of the Unix socket of an address, none when it is a TCP address.
+///
+/// # Arguments
+///
+/// * `listen` - Address from the settings.
+///
+pub fn unix_socket_path(listen: &str) -> Option<&Path> {
+    listen
+        .strip_prefix(UNIX_SCHEME)
+        .filter(|path| !path.is_empty())
+        .map(Path::new)
+}
+
+/// Address an HTTP server is bound to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoundAddress {
+    /// TCP address, with the port picked by the system when the settings ask for port 0.
+    Tcp(SocketAddr),
+    /// Path of a Unix socket.
+    Unix(PathBuf),
+}
+
+impl fmt::Display for BoundAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoundAddress::Tcp(address) => write!(f, "{}", address),
+            BoundAddress::Unix(path) => write!(f, "{}{}", UNIX_SCHEME, path.display()),
+        }
+    }
+}
+
+#[cfg(any(feature = "prometheus", feature = "http-api"))]
+pub(crate) use self::server::HttpListener;
+
+#[cfg(any(feature = "prometheus", feature = "http-api"))]
+mod server {
+    use std::{io, net::SocketAddr};
+
+    use axum::Router;
+    use tokio::net::TcpListener;
+    use tokio_util::sync::CancellationToken;
+
+    use super::{unix_socket_path, BoundAddress};
+
+    /// Listener of an HTTP server, bound but not serving yet.
+    pub(crate) enum HttpListener {
+        Tcp(TcpListener),
+        #[cfg(unix)]
+        Unix(unix::UnixSocket),
+    }
+
+    impl HttpListener {
+        /// Bind an address without waiting, so that the caller knows it before serving.
+        ///
+        /// # Arguments
+        ///
+        /// * `listen` - `<host>:<port>`, or `unix://<path>`.
+        ///
+        /// # Errors
+        ///
+        /// * `io::Error` - The address cannot be bound, or Unix sockets are not available.
+        ///
+        pub(crate) fn bind(listen: &str) -> io::Result<Self> {
+            match unix_socket_path(listen) {
+                #[cfg(unix)]
+                Some(path) => Ok(HttpListener::Unix(unix::UnixSocket::bind(path)?)),
+                #[cfg(not(unix))]
+                Some(_) => Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Unix sockets are not available on this platform",
+                )),
+                None => {
+                    let listener = std::net::TcpListener::bind(listen)?;
+                    listener.set_nonblocking(true)?;
+                    Ok(HttpListener::Tcp(TcpListener::from_std(listener)?))
+                }
+            }
+        }
+
+        /// Address bound, none when the system does not report it.
+        pub(crate) fn local_addr(&self) -> Option<BoundAddress> {
+            match self {
+                HttpListener::Tcp(listener) => listener.local_addr().ok().map(BoundAddress::Tcp),
+                #[cfg(unix)]
+                HttpListener::Unix(socket) => Some(BoundAddress::Unix(socket.path().to_owned())),
+            }
+        }
+
+        /// Serve the routes until `shutdown` is cancelled, with the address of each client in
+        /// its `ConnectInfo<SocketAddr>`.
+        ///
+        /// # Errors
+        ///
+        /// * `io::Error` - The server failed.
+        ///
+        pub(crate) async fn serve(
+            self,
+            routes: Router,
+            shutdown: CancellationToken,
+        ) -> io::Result<()> {
+            match self {
+                HttpListener::Tcp(listener) => {
+                    let service = routes.into_make_service_with_connect_info::<SocketAddr>();
+                    axum::serve(listener, service)
+                        .with_graceful_shutdown(shutdown.cancelled_owned())
+                        .await
+                }
+                #[cfg(unix)]
+                HttpListener::Unix(socket) => {
+                    socket.serve(routes, shutdown).await;
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    mod unix {
+        use std::{
+            fs, io,
+            net::{IpAddr, Ipv4Addr, SocketAddr},
+            os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt},
+            path::{Path, PathBuf},
+            time::Duration,
+        };
+
+        use axum::{extract::ConnectInfo, Extension, Router};
+        use hyper::server::conn::http1;
+        use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+        use tokio::net::UnixListener;
+        use tokio_util::sync::CancellationToken;
+
+        /// Address the clients of a Unix socket are seen from, not a loopback one so that they
+        /// are not granted the admin surface.
+        const LOCAL_CLIENT: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+
+        /// Permissions of the socket file: the user and the group of the node.
+        const SOCKET_MODE: u32 = 0o660;
+
+        /// Wait after a failed accept, so that a lack of file descriptors does not spin.
+        const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+        /// Bound Unix socket.
+        pub(crate) struct UnixSocket {
+            listener: UnixListener,
+            path: PathBuf,
+            /// Inode of the socket file, to remove it only while it is still ours.
+            inode: u64,
+        }
+
+        impl UnixSocket {
+            /// Bind `path`, replacing a socket file left by a previous run. The socket is bound
+            /// next to it and moved there once its permissions are `SOCKET_MODE`, so that no
+            /// client connects before.
+            pub(crate) fn bind(path: &Path) -> io::Result<Self> {
+                if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket())
+                {
+                    fs::remove_file(path)?;
+                }
+                let mut unbound = path.as_os_str().to_owned();
+                unbound.push(format!(".{}.tmp", std::process::id()));
+                let unbound = PathBuf::from(unbound);
+                let _ = fs::remove_file(&unbound);
+                let listener = std::os::unix::net::UnixListener::bind(&unbound)?;
+                if let Err(error) =
+                    fs::set_permissions(&unbound, fs::Permissions::from_mode(SOCKET_MODE))
+                        .and_then(|()| fs::rename(&unbound, path))
+                {
+                    let _ = fs::remove_file(&unbound);
+                    return Err(error);
+                }
+                listener.set_nonblocking(true)?;
+                let inode = fs::metadata(path)?.ino();
+                Ok(Self {
+                    listener: UnixListener::from_std(listener)?,
+                    path: path.to_owned(),
+                    inode,
+                })
+            }
+
+            /// Path of the socket file.
+            pub(crate) fn path(&self) -> &Path {
+                &self.path
+            }
+
+            /// Accept and serve connections until `shutdown` is cancelled, then remove the socket
+            /// file unless another listener replaced it.
+            pub(crate) async fn serve(self, routes: Router, shutdown: CancellationToken) {
+                let routes = routes.layer(Extension(ConnectInfo(LOCAL_CLIENT)));
+                loop {
+                    let stream = tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        accepted = self.listener.accept() => match accepted {
+                            Ok((stream, _)) => stream,
+                            Err(error) => {
+                                log::warn!("{}: accept error: {}", self.path.display(), error);
+                                tokio::time::sleep(ACCEPT_BACKOFF).await;
+                                continue;
+                            }
+                        },
+                    };
+                    let service = TowerToHyperService::new(routes.clone());
+                    let shutdown = shutdown.clone();
+                    tokio::spawn(async move {
+                        let connection = http1::Builder::new()
+                            .serve_connection(TokioIo::new(stream), service)
+                            .with_upgrades();
+                        tokio::pin!(connection);
+                        let result = tokio::select! {
+                            result = connection.as_mut() => result,
+                            _ = shutdown.cancelled() => {
+                                connection.as_mut().graceful_shutdown();
+                                connection.await
+                            }
+                        };
+                        if let Err(error) = result {
+                            log::debug!("Unix socket connection error: {}", error);
+                        }
+                    });
+                }
+                if fs::metadata(&self.path).is_ok_and(|metadata| metadata.ino() == self.inode) {
+                    let _ = fs::remove_file(&self.path);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_unix_socket_path() {
+        assert_eq!(
+            unix_socket_path("unix:///var/run/kore.sock"),
+            Some(Path::new("/var/run/kore.sock"))
+        );
+        assert_eq!(unix_socket_path("unix://"), None);
+        assert_eq!(unix_socket_path("127.0.0.1:3050"), None);
+        assert_eq!(
+            BoundAddress::Unix(PathBuf::from("/var/run/kore.sock")).to_string(),
+            "unix:///var/run/kore.sock"
+        );
+    }
+
+    #[cfg(all(unix, any(feature = "prometheus", feature = "http-api")))]
+    #[tokio::test]
+    async fn test_serve_unix_socket() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use axum::{extract::ConnectInfo, routing::get, Router};
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::UnixStream,
+        };
+        use tokio_util::sync::CancellationToken;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kore.sock");
+        let listen = format!("{}{}", UNIX_SCHEME, path.display());
+        // A socket file left by a previous run is replaced.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+        let listener = HttpListener::bind(&listen).unwrap();
+        assert_eq!(
+            listener.local_addr(),
+            Some(BoundAddress::Unix(path.clone()))
+        );
+        let routes = Router::new().route(
+            "/client",
+            get(|ConnectInfo(address): ConnectInfo<SocketAddr>| async move {
+                address.ip().is_loopback().to_string()
+            }),
+        );
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn(listener.serve(routes, shutdown.clone()));
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET /client HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        // Not a loopback client, which would reach the admin surface without a token.
+        assert!(response.ends_with("false"));
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+
+        shutdown.cancel();
+        server.await.unwrap().unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,342 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Logging.
+//!
+//! Logger of the `log` facade, configured by `kore.logging`: a default level with overrides for
+//! some targets, pretty or JSON lines, and the standard error or a file rotated by size.
+//!
+//! The node builders install it on start. Only one logger may be installed in a process, so it is
+//! not replaced when the application, or another node, installed one before.
+//!
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+
+use crate::{
+    error::{ConfigError, NodeError},
+    settings::{LogFormat, LoggingSettings},
+};
+
+/// Install the logger described by the settings.
+///
+/// # Arguments
+///
+/// * `settings` - Level, format and output of the logs.
+///
+/// # Errors
+///
+/// * `NodeError::Config` - Invalid level, or the log file cannot be opened.
+///
+pub fn init_logging(settings: &LoggingSettings) -> Result<(), NodeError> {
+    let logger = NodeLogger::new(settings)?;
+    let max_level = logger.filter.max_level();
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max_level);
+    } else {
+        log::debug!("Logger already installed, kore.logging is ignored");
+    }
+    Ok(())
+}
+
+/// Parse a level of the settings.
+pub(crate) fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    LevelFilter::from_str(level).map_err(|_| format!("unknown level '{}'", level))
+}
+
+/// Levels of the targets.
+struct LevelFilters {
+    default: LevelFilter,
+    /// Overrides, the most specific target first.
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl LevelFilters {
+    fn new(settings: &LoggingSettings) -> Result<Self, NodeError> {
+        let config_error =
+            |key: String, message: String| NodeError::Config(vec![ConfigError::new(key, message)]);
+        let default = parse_level(&settings.level)
+            .map_err(|message| config_error("kore.logging.level".to_owned(), message))?;
+        let mut targets = settings
+            .targets
+            .iter()
+            .map(|(target, level)| {
+                parse_level(level)
+                    .map(|level| (target.clone(), level))
+                    .map_err(|message| {
+                        config_error(format!("kore.logging.targets.{}", target), message)
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        targets.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        Ok(Self { default, targets })
+    }
+
+    /// Level of a target: the one of its longest overridden prefix, or the default one.
+    fn level(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .find(|(prefix, _)| {
+                target == prefix
+                    || target
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    /// Most verbose level of any target.
+    fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+/// Destination of the log lines.
+enum Output {
+    Stderr,
+    File(RotatingFile),
+}
+
+/// File rotated when it reaches its maximum size.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: u32,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_size: u64, max_files: u32) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            size,
+            max_size,
+            max_files,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let length = line.len() as u64 + 1;
+        if self.max_size > 0 && self.size > 0 && self.size + length > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += length;
+        Ok(())
+    }
+
+    /// Shift `<file>.<n>` to `<file>.<n + 1>`, dropping the oldest, and start a new file.
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |index: u32| PathBuf::from(format!("{}.{}", self.path.display(), index));
+        if self.max_files > 0 {
+            let _ = fs::remove_file(rotated(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let _ = fs::rename(rotated(index), rotated(index + 1));
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Logger of the node.
+struct NodeLogger {
+    filter: LevelFilters,
+    format: LogFormat,
+    output: Mutex<Output>,
+}
+
+impl NodeLogger {
+    fn new(settings: &LoggingSettings) -> Result<Self, NodeError> {
+        let output = if settings.file.is_empty() {
+            Output::Stderr
+        } else {
+            let file = RotatingFile::open(
+                Path::new(&settings.file),
+                settings.max_file_size,
+                settings.max_files,
+            )
+            .map_err(|error| {
+                NodeError::Config(vec![ConfigError::new(
+                    "kore.logging.file",
+                    format!("'{}' cannot be opened: {}", settings.file, error),
+                )])
+            })?;
+            Output::File(file)
+        };
+        Ok(Self {
+            filter: LevelFilters::new(settings)?,
+            format: settings.format,
+            output: Mutex::new(output),
+        })
+    }
+
+    /// Line of a record, without the line break.
+    fn format(&self, record: &Record) -> String {
+        let timestamp = humantime::format_rfc3339_millis(SystemTime::now());
+        match self.format {
+            LogFormat::Pretty => format!(
+                "{} {:<5} {}: {}",
+                timestamp,
+                record.level(),
+                record.target(),
+                record.args()
+            ),
+            LogFormat::Json => json!({
+                "timestamp": timestamp.to_string(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            })
+            .to_string(),
+        }
+    }
+}
+
+impl Log for NodeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = self.format(record);
+        let Ok(mut output) = self.output.lock() else {
+            return;
+        };
+        match &mut *output {
+            Output::Stderr => eprintln!("{}", line),
+            Output::File(file) => {
+                if let Err(error) = file.write_line(&line) {
+                    eprintln!("{} (log file error: {})", line, error);
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut output) = self.output.lock() {
+            match &mut *output {
+                Output::Stderr => {
+                    let _ = io::stderr().flush();
+                }
+                Output::File(file) => {
+                    let _ = file.file.flush();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use log::Level;
+
+    fn settings(file: &Path, format: LogFormat) -> LoggingSettings {
+        LoggingSettings {
+            level: "info".to_owned(),
+            targets: [
+                ("kore_base".to_owned(), "warn".to_owned()),
+                ("kore_node::access".to_owned(), "off".to_owned()),
+            ]
+            .into(),
+            format,
+            file: file.display().to_string(),
+            max_file_size: 100,
+            max_files: 2,
+        }
+    }
+
+    fn log(logger: &NodeLogger, level: Level, target: &str, message: &str) {
+        logger.log(
+            &Record::builder()
+                .level(level)
+                .target(target)
+                .args(format_args!("{}", message))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn test_level_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger =
+            NodeLogger::new(&settings(&dir.path().join("node.log"), LogFormat::Pretty)).unwrap();
+        let filter = &logger.filter;
+        assert_eq!(filter.level("kore_node::api"), LevelFilter::Info);
+        assert_eq!(filter.level("kore_base"), LevelFilter::Warn);
+        assert_eq!(filter.level("kore_base::network"), LevelFilter::Warn);
+        assert_eq!(filter.level("kore_base_extra"), LevelFilter::Info);
+        assert_eq!(filter.level("kore_node::access"), LevelFilter::Off);
+        assert_eq!(filter.max_level(), LevelFilter::Info);
+
+        let mut invalid = settings(&dir.path().join("node.log"), LogFormat::Pretty);
+        invalid
+            .targets
+            .insert("libp2p".to_owned(), "loud".to_owned());
+        assert!(matches!(
+            NodeLogger::new(&invalid),
+            Err(NodeError::Config(errors)) if errors[0].location == "kore.logging.targets.libp2p"
+        ));
+    }
+
+    #[test]
+    fn test_log_file_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("node.log");
+        let logger = NodeLogger::new(&settings(&path, LogFormat::Json)).unwrap();
+        for index in 0..6 {
+            log(
+                &logger,
+                Level::Info,
+                "kore_node::api",
+                &format!("event {}", index),
+            );
+        }
+        log(&logger, Level::Info, "kore_base::network", "dropped");
+
+        let lines = |path: PathBuf| {
+            fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .map(|line| line["message"].as_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let rotated = |index: u32| PathBuf::from(format!("{}.{}", path.display(), index));
+        assert_eq!(lines(path.clone()), vec!["event 5"]);
+        assert_eq!(lines(rotated(1)), vec!["event 4"]);
+        assert_eq!(lines(rotated(2)), vec!["event 3"]);
+        assert!(!rotated(3).exists());
+    }
+}
@@ -0,0 +1,325 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Node metrics.
+//!
+//! Metrics of the node itself, next to the ones Kore Base registers: event requests sent through
+//! the API, latency of the database operations, approvals waiting for a vote, the load of the
+//! soak test and the restarts by the supervisor. They are
+//! registered in the same `Registry`, so the metrics server exposes both.
+//!
+
+use std::time::Duration;
+
+use prometheus_client::{
+    encoding::EncodeLabelSet,
+    metrics::{
+        counter::Counter,
+        family::Family,
+        gauge::Gauge,
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::Registry,
+};
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::tasks::NodeTasks;
+use crate::KoreApi;
+
+/// Time between two counts of the pending approvals.
+const APPROVALS_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Identity of the calls of the metrics tasks, in the access logs.
+const METRICS_SOURCE: &str = "metrics";
+
+/// Labels of the event request counter.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+pub struct EventRequestLabels {
+    /// Type of the request: Create, Fact, Transfer or EOL.
+    pub request_type: String,
+    /// `ok` or `error`.
+    pub status: String,
+}
+
+/// Labels of the database latency histograms.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+pub struct DbOperationLabels {
+    /// Collection identifier.
+    pub collection: String,
+    /// `get`, `put` or `del`.
+    pub operation: String,
+}
+
+/// Labels of the boot node group gauges.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+pub struct BootGroupLabels {
+    /// Label of the group.
+    pub group: String,
+}
+
+/// Labels of the restart counter.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+pub struct RestartLabels {
+    /// `panic`, `fatal` or `build`, see `node::NodeFailure`.
+    pub cause: String,
+}
+
+/// Histogram family of the database operations.
+type DbOperations = Family<DbOperationLabels, Histogram, fn() -> Histogram>;
+
+/// Latency histogram, from 1ms to about 4s.
+fn latency_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.001, 2.0, 13))
+}
+
+/// Histogram of the time taken by a request to reach its final state, from 10ms to about 80s.
+fn request_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.01, 2.0, 14))
+}
+
+/// Metrics of the node. Clones share the same values.
+#[derive(Debug, Clone)]
+pub struct NodeMetrics {
+    event_requests: Family<EventRequestLabels, Counter>,
+    event_request_duration: Histogram,
+    db_operations: DbOperations,
+    pending_approvals: Gauge,
+    boot_group_health: Family<BootGroupLabels, Gauge>,
+    bootstrap_group: Family<BootGroupLabels, Gauge>,
+    soak_requests: Family<EventRequestLabels, Counter>,
+    soak_request_duration: Histogram,
+    soak_skipped: Counter,
+    restarts: Family<RestartLabels, Counter>,
+}
+
+impl Default for NodeMetrics {
+    fn default() -> Self {
+        Self {
+            event_requests: Family::default(),
+            event_request_duration: latency_histogram(),
+            db_operations: DbOperations::new_with_constructor(latency_histogram),
+            pending_approvals: Gauge::default(),
+            boot_group_health: Family::default(),
+            bootstrap_group: Family::default(),
+            soak_requests: Family::default(),
+            soak_request_duration: request_histogram(),
+            soak_skipped: Counter::default(),
+            restarts: Family::default(),
+        }
+    }
+}
+
+impl NodeMetrics {
+    /// Create the metrics and register them.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - Registry served by the metrics server.
+    ///
+    pub fn register(registry: &mut Registry) -> Self {
+        let metrics = Self::default();
+        registry.register(
+            "event_request",
+            "Event requests sent through the node API",
+            metrics.event_requests.clone(),
+        );
+        registry.register(
+            "event_request_duration_seconds",
+            "Time taken to send an event request",
+            metrics.event_request_duration.clone(),
+        );
+        registry.register(
+            "db_operation_duration_seconds",
+            "Latency of the database get, put and del operations",
+            metrics.db_operations.clone(),
+        );
+        registry.register(
+            "pending_approvals",
+            "Approvals waiting for a vote of the node",
+            metrics.pending_approvals.clone(),
+        );
+        registry.register(
+            "boot_group_healthy",
+            "Whether a boot node of the group is reachable, by group",
+            metrics.boot_group_health.clone(),
+        );
+        registry.register(
+            "bootstrap_group",
+            "Group of boot nodes that served the bootstrap, 1 for that group",
+            metrics.bootstrap_group.clone(),
+        );
+        registry.register(
+            "soak_request",
+            "Event requests of the soak test, by final result",
+            metrics.soak_requests.clone(),
+        );
+        registry.register(
+            "soak_request_duration_seconds",
+            "Time taken by the event requests of the soak test to reach their final state",
+            metrics.soak_request_duration.clone(),
+        );
+        registry.register(
+            "soak_skipped",
+            "Event requests of the soak test not sent, too many were in flight",
+            metrics.soak_skipped.clone(),
+        );
+        registry.register(
+            "node_restarts",
+            "Restarts of the node by the supervisor since the process started, by cause",
+            metrics.restarts.clone(),
+        );
+        metrics
+    }
+
+    /// Count an event request and the time taken to send it.
+    pub(crate) fn event_request(&self, request_type: &str, ok: bool, elapsed: Duration) {
+        self.event_requests
+            .get_or_create(&EventRequestLabels {
+                request_type: request_type.to_owned(),
+                status: if ok { "ok" } else { "error" }.to_owned(),
+            })
+            .inc();
+        self.event_request_duration.observe(elapsed.as_secs_f64());
+    }
+
+    /// Record the latency of a database operation.
+    pub(crate) fn db_operation(&self, collection: &str, operation: &str, elapsed: Duration) {
+        self.db_operations
+            .get_or_create(&DbOperationLabels {
+                collection: collection.to_owned(),
+                operation: operation.to_owned(),
+            })
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Count a request of the soak test and the time taken to reach its final state.
+    #[cfg(feature = "soak")]
+    pub(crate) fn soak_request(&self, request_type: &str, ok: bool, elapsed: Duration) {
+        self.soak_requests
+            .get_or_create(&EventRequestLabels {
+                request_type: request_type.to_owned(),
+                status: if ok { "ok" } else { "error" }.to_owned(),
+            })
+            .inc();
+        self.soak_request_duration.observe(elapsed.as_secs_f64());
+    }
+
+    /// Count a request of the soak test skipped.
+    #[cfg(feature = "soak")]
+    pub(crate) fn soak_skipped(&self) {
+        self.soak_skipped.inc();
+    }
+
+    /// Add restarts of the node, counted by the supervisor across the registries of its nodes.
+    pub(crate) fn count_restarts(&self, cause: &str, count: u64) {
+        self.restarts
+            .get_or_create(&RestartLabels {
+                cause: cause.to_owned(),
+            })
+            .inc_by(count);
+    }
+
+    /// Set the number of pending approvals.
+    pub(crate) fn set_pending_approvals(&self, count: u64) {
+        self.pending_approvals.set(count as i64);
+    }
+
+    /// Set the health of a group of boot nodes.
+    pub(crate) fn set_boot_group_health(&self, group: &str, healthy: bool) {
+        self.boot_group_health
+            .get_or_create(&BootGroupLabels {
+                group: group.to_owned(),
+            })
+            .set(healthy as i64);
+    }
+
+    /// Set the group of boot nodes that served the bootstrap.
+    pub(crate) fn set_bootstrap_group(&self, group: &str) {
+        self.bootstrap_group
+            .get_or_create(&BootGroupLabels {
+                group: group.to_owned(),
+            })
+            .set(1);
+    }
+}
+
+/// Count the pending approvals periodically, until the node is cancelled.
+///
+/// # Arguments
+///
+/// * `api` - Kore API, whose metrics get the count.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_approvals_gauge(api: KoreApi, tasks: &NodeTasks) {
+    let cancellation = tasks.token();
+    let api = api
+        .with_cancellation(cancellation.clone())
+        .background(METRICS_SOURCE);
+    tasks.spawn(async move {
+        let mut interval = interval(APPROVALS_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = cancellation.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            if let Err(error) = api
+                .with_timeout(APPROVALS_INTERVAL)
+                .refresh_pending_approvals()
+                .await
+            {
+                log::debug!("Pending approvals not counted: {}", error);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use prometheus_client::encoding::text::encode;
+
+    #[test]
+    fn test_node_metrics() {
+        let mut registry = <Registry>::default();
+        let metrics = NodeMetrics::register(&mut registry);
+        metrics.event_request("Fact", true, Duration::from_millis(20));
+        metrics.event_request("Fact", false, Duration::from_millis(5));
+        metrics.db_operation("node", "get", Duration::from_micros(300));
+        metrics.set_pending_approvals(2);
+        metrics.set_boot_group_health("eu-west", false);
+        metrics.set_boot_group_health("us-east", true);
+        metrics.set_bootstrap_group("us-east");
+        metrics.count_restarts("panic", 2);
+
+        let mut text = String::new();
+        encode(&mut text, &registry).unwrap();
+        assert!(text.contains(r#"event_request_total{request_type="Fact",status="ok"} 1"#));
+        assert!(text.contains(r#"event_request_total{request_type="Fact",status="error"} 1"#));
+        assert!(text.contains("event_request_duration_seconds_count 2"));
+        assert!(text.contains(
+            r#"db_operation_duration_seconds_count{collection="node",operation="get"} 1"#
+        ));
+        assert!(text.contains("pending_approvals 2"));
+        assert!(text.contains(r#"boot_group_healthy{group="eu-west"} 0"#));
+        assert!(text.contains(r#"boot_group_healthy{group="us-east"} 1"#));
+        assert!(text.contains(r#"bootstrap_group{group="us-east"} 1"#));
+        assert!(text.contains(r#"node_restarts_total{cause="panic"} 2"#));
+    }
+
+    #[cfg(feature = "soak")]
+    #[test]
+    fn test_soak_metrics() {
+        let mut registry = <Registry>::default();
+        let metrics = NodeMetrics::register(&mut registry);
+        metrics.soak_request("Create", true, Duration::from_secs(2));
+        metrics.soak_skipped();
+
+        let mut text = String::new();
+        encode(&mut text, &registry).unwrap();
+        assert!(text.contains(r#"soak_request_total{request_type="Create",status="ok"} 1"#));
+        assert!(text.contains("soak_request_duration_seconds_count 1"));
+        assert!(text.contains("soak_skipped_total 1"));
+    }
+}
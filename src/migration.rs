@@ -0,0 +1,360 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Legacy data migration.
+//!
+//! Earlier releases kept the data of the node in `examples/`, under the working directory:
+//!
+//! | Data | Legacy location |
+//! |------|-----------------|
+//! | Keys | `examples/keys` |
+//! | LevelDB database | `examples/leveldb` |
+//! | SQLite database | `examples/sqlitedb`, or `examples/sqlitedb/database` |
+//!
+//! The data now defaults to the data directory of the platform, see `default_data_path`, and a
+//! node configured elsewhere would start from an empty ledger and a new key pair, leaving that
+//! data behind. On start, the node builders look for it when the configured location is empty:
+//! it is moved there when `kore.migrate_legacy_data` is set, and otherwise a warning tells the
+//! operator how to move it.
+//!
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    backup::is_empty,
+    error::NodeError,
+    model::NodeHistoryKind,
+    settings::{DbSettings, KoreSettings},
+};
+
+/// Legacy keys directory.
+pub const LEGACY_KEYS_PATH: &str = "examples/keys";
+
+/// Legacy LevelDB database directory.
+pub const LEGACY_LEVELDB_PATH: &str = "examples/leveldb";
+
+/// Legacy SQLite database, a file or a directory holding a `database` file.
+pub const LEGACY_SQLITE_PATH: &str = "examples/sqlitedb";
+
+/// Data found in a legacy location, whose configured location is empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegacyData {
+    /// Kind of data, for the operator.
+    pub kind: &'static str,
+    /// Legacy location.
+    pub from: PathBuf,
+    /// Configured location.
+    pub to: PathBuf,
+}
+
+/// Find the legacy data that the configured locations are missing.
+///
+/// # Arguments
+///
+/// * `root` - Directory the legacy locations are relative to, the working directory of the node.
+/// * `settings` - Settings with the configured locations.
+///
+pub fn find_legacy_data(root: &Path, settings: &KoreSettings) -> Vec<LegacyData> {
+    let mut found = vec![];
+    let keys = root.join(LEGACY_KEYS_PATH);
+    let keys_path = Path::new(&settings.keys_path);
+    if keys.join("node_private.der").is_file()
+        && !keys_path.join("node_private.der").exists()
+        && !same_path(&keys, keys_path)
+    {
+        found.push(LegacyData {
+            kind: "Keys",
+            from: keys,
+            to: keys_path.to_owned(),
+        });
+    }
+    if let Some(from) = legacy_database(root, &settings.db) {
+        let to = database_path(&settings.db);
+        if !same_path(&from, &to) && is_empty(&settings.db) {
+            found.push(LegacyData {
+                kind: "Database",
+                from,
+                to,
+            });
+        }
+    }
+    found
+}
+
+/// Move the legacy data to the configured locations, when `kore.migrate_legacy_data` is set.
+/// Otherwise, each piece of data found is reported in a warning.
+///
+/// # Arguments
+///
+/// * `root` - Directory the legacy locations are relative to, the working directory of the node.
+/// * `settings` - Settings with the configured locations.
+///
+/// # Errors
+///
+/// * `NodeError::InternalApi` - Some data could not be moved.
+///
+/// # Returns
+///
+/// * `Vec<(NodeHistoryKind, String)>` - Entries of the node history, one per move.
+///
+pub fn migrate_legacy_data(
+    root: &Path,
+    settings: &KoreSettings,
+) -> Result<Vec<(NodeHistoryKind, String)>, NodeError> {
+    let mut history = vec![];
+    for data in find_legacy_data(root, settings) {
+        if !settings.migrate_legacy_data {
+            log::warn!(
+                "{} found in the legacy location {}, but {} is empty and the node starts without \
+                 them. Set kore.migrate_legacy_data (KORE_MIGRATE_LEGACY_DATA) to move them there",
+                data.kind,
+                data.from.display(),
+                data.to.display()
+            );
+            continue;
+        }
+        move_data(&data.from, &data.to).map_err(|error| {
+            NodeError::InternalApi(format!(
+                "{} cannot be moved from {} to {}: {}",
+                data.kind,
+                data.from.display(),
+                data.to.display(),
+                error
+            ))
+        })?;
+        let detail = format!(
+            "{} moved from {} to {}",
+            data.kind,
+            data.from.display(),
+            data.to.display()
+        );
+        log::info!("{}", detail);
+        history.push((NodeHistoryKind::Migrated, detail));
+    }
+    Ok(history)
+}
+
+/// Legacy database of the backend of `db`, if it holds data.
+#[allow(unused_variables)]
+fn legacy_database(root: &Path, db: &DbSettings) -> Option<PathBuf> {
+    match db {
+        #[cfg(feature = "leveldb")]
+        DbSettings::LevelDB(_) => {
+            let path = root.join(LEGACY_LEVELDB_PATH);
+            path.join("CURRENT").is_file().then_some(path)
+        }
+        #[cfg(feature = "sqlite")]
+        DbSettings::Sqlite(_) => {
+            let path = root.join(LEGACY_SQLITE_PATH);
+            let path = if path.is_dir() {
+                path.join("database")
+            } else {
+                path
+            };
+            fs::metadata(&path)
+                .is_ok_and(|metadata| metadata.is_file() && metadata.len() > 0)
+                .then_some(path)
+        }
+        #[cfg(feature = "postgres")]
+        DbSettings::Postgres { .. } => None,
+        #[cfg(feature = "sled")]
+        DbSettings::Sled(_) => None,
+        #[cfg(feature = "redb")]
+        DbSettings::Redb(_) => None,
+    }
+}
+
+/// Location of a local database, empty for servers.
+fn database_path(db: &DbSettings) -> PathBuf {
+    match db {
+        #[cfg(feature = "leveldb")]
+        DbSettings::LevelDB(path) => PathBuf::from(path),
+        #[cfg(feature = "sqlite")]
+        DbSettings::Sqlite(path) => PathBuf::from(path),
+        #[cfg(feature = "sled")]
+        DbSettings::Sled(path) => PathBuf::from(path),
+        #[cfg(feature = "redb")]
+        DbSettings::Redb(path) => PathBuf::from(path),
+        #[cfg(feature = "postgres")]
+        DbSettings::Postgres { .. } => PathBuf::new(),
+    }
+}
+
+/// Whether two paths, existing or not, are the same location.
+fn same_path(a: &Path, b: &Path) -> bool {
+    let absolute = |path: &Path| {
+        fs::canonicalize(path)
+            .or_else(|_| std::path::absolute(path))
+            .ok()
+    };
+    absolute(a) == absolute(b)
+}
+
+/// Move a file or directory to `to`, an empty or missing location, and the SQLite journals of a
+/// file along with it.
+fn move_data(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    if from.is_file() {
+        move_path(from, to)?;
+        for suffix in ["-wal", "-shm"] {
+            let journal = PathBuf::from(format!("{}{}", from.display(), suffix));
+            if journal.exists() {
+                move_path(
+                    &journal,
+                    &PathBuf::from(format!("{}{}", to.display(), suffix)),
+                )?;
+            }
+        }
+        return Ok(());
+    }
+    // The configured directory may exist, empty or with other files.
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        move_path(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    fs::remove_dir(from)
+}
+
+/// Rename a path, or copy and remove it when the rename fails (e.g. across file systems).
+fn move_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    if to.is_file() && fs::metadata(to)?.len() == 0 {
+        fs::remove_file(to)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_path(from, to)?;
+    if from.is_dir() {
+        fs::remove_dir_all(from)
+    } else {
+        fs::remove_file(from)
+    }
+}
+
+fn copy_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::settings::DATA_DIR_NAME;
+
+    fn legacy_keys(root: &Path) {
+        let keys = root.join(LEGACY_KEYS_PATH);
+        fs::create_dir_all(&keys).unwrap();
+        fs::write(keys.join("node_private.der"), b"key").unwrap();
+        fs::write(keys.join("node_private.der.1"), b"old key").unwrap();
+    }
+
+    #[test]
+    fn test_migrate_legacy_keys() {
+        let root = tempfile::tempdir().unwrap();
+        legacy_keys(root.path());
+        let keys_path = root.path().join("data").join("keys");
+        let mut settings = KoreSettings {
+            keys_path: keys_path.to_str().unwrap().to_owned(),
+            ..Default::default()
+        };
+
+        let found = find_legacy_data(root.path(), &settings);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, "Keys");
+        assert!(migrate_legacy_data(root.path(), &settings)
+            .unwrap()
+            .is_empty());
+        assert!(!keys_path.exists());
+
+        settings.migrate_legacy_data = true;
+        let history = migrate_legacy_data(root.path(), &settings).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].0, NodeHistoryKind::Migrated);
+        assert_eq!(
+            fs::read(keys_path.join("node_private.der")).unwrap(),
+            b"key"
+        );
+        assert_eq!(
+            fs::read(keys_path.join("node_private.der.1")).unwrap(),
+            b"old key"
+        );
+        assert!(!root.path().join(LEGACY_KEYS_PATH).exists());
+        assert!(find_legacy_data(root.path(), &settings).is_empty());
+    }
+
+    #[test]
+    fn test_default_locations() {
+        let root = std::env::current_dir().unwrap();
+        let settings = KoreSettings::default();
+        assert!(!same_path(
+            &root.join(LEGACY_KEYS_PATH),
+            Path::new(&settings.keys_path)
+        ));
+        assert!(Path::new(&settings.keys_path).ends_with(Path::new(DATA_DIR_NAME).join("keys")));
+    }
+
+    #[test]
+    fn test_legacy_keys_in_use() {
+        let root = tempfile::tempdir().unwrap();
+        legacy_keys(root.path());
+        let settings = KoreSettings {
+            keys_path: root
+                .path()
+                .join(LEGACY_KEYS_PATH)
+                .to_str()
+                .unwrap()
+                .to_owned(),
+            ..Default::default()
+        };
+        assert!(find_legacy_data(root.path(), &settings).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_migrate_legacy_sqlite() {
+        let root = tempfile::tempdir().unwrap();
+        let legacy = root.path().join(LEGACY_SQLITE_PATH);
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(legacy.join("database"), b"ledger").unwrap();
+        fs::write(legacy.join("database-wal"), b"journal").unwrap();
+        let path = root.path().join("data").join("database");
+        let settings = KoreSettings {
+            db: DbSettings::Sqlite(path.to_str().unwrap().to_owned()),
+            keys_path: root.path().join("keys").to_str().unwrap().to_owned(),
+            migrate_legacy_data: true,
+            ..Default::default()
+        };
+
+        let found = find_legacy_data(root.path(), &settings);
+        assert_eq!(
+            found,
+            vec![LegacyData {
+                kind: "Database",
+                from: legacy.join("database"),
+                to: path.clone(),
+            }]
+        );
+        migrate_legacy_data(root.path(), &settings).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"ledger");
+        assert_eq!(
+            fs::read(root.path().join("data").join("database-wal")).unwrap(),
+            b"journal"
+        );
+        assert!(!legacy.join("database").exists());
+    }
+}
@@ -0,0 +1,27 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Backup model.
+//!
+
+use serde::{Deserialize, Serialize};
+
+/// Manifest of a backup archive, its `manifest.json` entry.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeBackupManifest {
+    /// Layout of the archive, restores reject newer versions
+    pub schema_version: u32,
+    /// Database backend, `leveldb` or `sqlite`
+    pub backend: String,
+    /// Version of the node that took the backup
+    pub node_version: String,
+    /// Controller identifier of the node
+    pub node_id: String,
+    /// Peer identifier of the node
+    pub peer_id: String,
+    /// Events of the subjects known by the node when the backup started
+    pub height: u64,
+    /// Milliseconds since UNIX epoch at which the backup started
+    #[serde(with = "super::timestamp::millis")]
+    pub created_at: u64,
+}
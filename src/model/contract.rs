@@ -0,0 +1,49 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Smart contract model.
+//!
+
+use serde::{Deserialize, Serialize};
+
+/// WebAssembly module of the contracts directory, as last read by the node.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeContract {
+    /// Contract name, the file name without the `.wasm` extension
+    pub name: String,
+    /// Size of the module in bytes
+    pub size: u64,
+    /// Milliseconds since UNIX epoch at which the module was last modified
+    #[serde(with = "super::timestamp::millis")]
+    pub modified: u64,
+    /// Whether the module is a valid contract
+    pub status: NodeContractStatus,
+    /// Why the module cannot be used, when it failed
+    pub error: Option<String>,
+}
+
+/// State of a contract module.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeContractStatus {
+    /// The module is a valid contract, run by Kore Base once the node restarts if it is new or
+    /// changed
+    Validated,
+    /// The module is not a valid contract
+    Failed,
+}
+
+/// Contract of a schema, as stored in its governance.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeContractSource {
+    /// Governance identifier
+    pub governance_id: String,
+    /// Schema identifier
+    pub schema_id: String,
+    /// Source of the contract
+    pub source: String,
+    /// Digest of the source, with the digest derivator of the node
+    pub hash: String,
+    /// Version of the governance the contract was read from, its sequence number
+    pub version: u64,
+}
@@ -0,0 +1,54 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Database maintenance model.
+//!
+
+use serde::{Deserialize, Serialize};
+
+/// Keys and approximate size of a collection of the database.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NodeCollectionStats {
+    /// Collection name: the table (SQLite, PostgreSQL) or the key prefix (LevelDB)
+    pub name: String,
+    /// Keys of the collection
+    pub keys: u64,
+    /// Bytes of its keys and values (LevelDB, SQLite) or of its table and indexes (PostgreSQL)
+    pub size: u64,
+}
+
+/// Size report of the database, returned by `KoreApi::db_stats`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NodeDbStats {
+    /// Database backend: `leveldb`, `sqlite` or `postgres`
+    pub backend: String,
+    /// Collections, sorted by name
+    pub collections: Vec<NodeCollectionStats>,
+    /// Bytes used on disk: the files of the database, write-ahead log included (LevelDB,
+    /// SQLite), or the database on the server (PostgreSQL)
+    pub disk_size: u64,
+}
+
+/// Result of `KoreApi::compact_db`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NodeDbCompaction {
+    /// Database backend: `leveldb`, `sqlite` or `postgres`
+    pub backend: String,
+    /// Bytes used on disk before the compaction
+    pub disk_size_before: u64,
+    /// Bytes used on disk after the compaction
+    pub disk_size_after: u64,
+    /// Milliseconds taken by the compaction
+    pub elapsed_ms: u64,
+}
+
+/// Result of `KoreApi::archive_events`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NodeArchivalReport {
+    /// Events moved to the archive
+    pub events: u64,
+    /// Bytes of the events moved, as stored in the database
+    pub bytes: u64,
+    /// Bytes of the events left in the database
+    pub local_bytes: u64,
+}
@@ -0,0 +1,25 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Feature flag model.
+//!
+
+use serde::{Deserialize, Serialize};
+
+/// Flag of an experimental behavior of the node.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeFeatureFlag {
+    /// Flag name
+    pub name: String,
+    /// Behavior gated by the flag
+    pub description: String,
+    /// Whether the behavior is on
+    pub enabled: bool,
+}
+
+/// Body of a feature flag toggle.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeFeatureToggle {
+    /// New state of the flag
+    pub enabled: bool,
+}
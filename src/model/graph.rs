@@ -0,0 +1,226 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Subject graph model.
+//!
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+/// Parameters of a subject graph query.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NodeSubjectGraphQuery {
+    /// Governance links followed from the root subject, 1 when not given
+    pub depth: Option<u32>,
+}
+
+/// Kind of a vertex of the subject graph.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeGraphVertexKind {
+    /// Governance subject.
+    Governance,
+    /// Subject ruled by a governance.
+    Subject,
+    /// Key identifier of a creator or owner.
+    Identity,
+}
+
+/// Subject or identity of the graph.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeGraphVertex {
+    /// Subject identifier, or key identifier of an identity
+    pub id: String,
+    /// Kind of vertex
+    pub kind: NodeGraphVertexKind,
+    /// Name of the subject, none for identities
+    pub name: Option<String>,
+    /// Schema of the subject, none for identities
+    pub schema_id: Option<String>,
+}
+
+/// Relationship between two vertices.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeGraphRelation {
+    /// From a governance to a subject it rules.
+    Governs,
+    /// From a subject to the identity that created it.
+    CreatedBy,
+    /// From a subject to its current owner.
+    OwnedBy,
+    /// From a subject to an identity it was transferred to, a new owner.
+    TransferredTo,
+}
+
+/// Directed edge of the graph.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NodeGraphEdge {
+    /// Source vertex
+    pub from: String,
+    /// Target vertex
+    pub to: String,
+    /// Relationship
+    pub relation: NodeGraphRelation,
+    /// Sequence number of the event behind the relationship, for transfers
+    pub sn: Option<u64>,
+}
+
+/// Subjects related to a root subject, with their creators, owners and transfers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeSubjectGraph {
+    /// Subject the graph starts from
+    pub root: String,
+    /// Maximum number of governance links between the root and another subject
+    pub depth: u32,
+    /// Subjects and identities, the root first
+    pub vertices: Vec<NodeGraphVertex>,
+    /// Relationships between the vertices
+    pub edges: Vec<NodeGraphEdge>,
+    /// Whether subjects were left out because the graph reached its size limit
+    pub truncated: bool,
+}
+
+impl NodeSubjectGraph {
+    /// Empty graph of a root subject.
+    pub(crate) fn new(root: &str, depth: u32) -> Self {
+        Self {
+            root: root.to_owned(),
+            depth,
+            vertices: vec![],
+            edges: vec![],
+            truncated: false,
+        }
+    }
+}
+
+/// Graph under construction, ignoring repeated vertices and edges.
+pub(crate) struct GraphBuilder {
+    graph: NodeSubjectGraph,
+    vertices: HashSet<String>,
+    edges: HashSet<NodeGraphEdge>,
+}
+
+impl GraphBuilder {
+    /// Start the graph of a root subject.
+    pub(crate) fn new(root: &str, depth: u32) -> Self {
+        Self {
+            graph: NodeSubjectGraph::new(root, depth),
+            vertices: HashSet::new(),
+            edges: HashSet::new(),
+        }
+    }
+
+    /// Whether the vertex is already in the graph.
+    pub(crate) fn contains(&self, id: &str) -> bool {
+        self.vertices.contains(id)
+    }
+
+    /// Number of vertices of the graph.
+    pub(crate) fn len(&self) -> usize {
+        self.vertices.len()
+    }
+
+    /// Add a vertex, unless it is already in the graph.
+    pub(crate) fn vertex(&mut self, vertex: NodeGraphVertex) {
+        if self.vertices.insert(vertex.id.clone()) {
+            self.graph.vertices.push(vertex);
+        }
+    }
+
+    /// Add an identity and an edge from a subject to it.
+    pub(crate) fn identity(
+        &mut self,
+        subject: &str,
+        identity: &str,
+        relation: NodeGraphRelation,
+        sn: Option<u64>,
+    ) {
+        if identity.is_empty() {
+            return;
+        }
+        self.vertex(NodeGraphVertex {
+            id: identity.to_owned(),
+            kind: NodeGraphVertexKind::Identity,
+            name: None,
+            schema_id: None,
+        });
+        self.edge(subject, identity, relation, sn);
+    }
+
+    /// Add an edge, unless it is already in the graph.
+    pub(crate) fn edge(
+        &mut self,
+        from: &str,
+        to: &str,
+        relation: NodeGraphRelation,
+        sn: Option<u64>,
+    ) {
+        let edge = NodeGraphEdge {
+            from: from.to_owned(),
+            to: to.to_owned(),
+            relation,
+            sn,
+        };
+        if self.edges.insert(edge.clone()) {
+            self.graph.edges.push(edge);
+        }
+    }
+
+    /// Mark the graph as cut by its size limit.
+    pub(crate) fn truncate(&mut self) {
+        self.graph.truncated = true;
+    }
+
+    /// Finished graph.
+    pub(crate) fn build(self) -> NodeSubjectGraph {
+        self.graph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_graph_builder() {
+        let mut builder = GraphBuilder::new("JSubject", 1);
+        let subject = NodeGraphVertex {
+            id: "JSubject".to_owned(),
+            kind: NodeGraphVertexKind::Subject,
+            name: Some("sensor".to_owned()),
+            schema_id: Some("Sensor".to_owned()),
+        };
+        builder.vertex(subject.clone());
+        builder.vertex(subject.clone());
+        builder.identity("JSubject", "ECreator", NodeGraphRelation::CreatedBy, None);
+        builder.identity("JSubject", "ECreator", NodeGraphRelation::OwnedBy, None);
+        builder.identity("JSubject", "", NodeGraphRelation::OwnedBy, None);
+        builder.identity(
+            "JSubject",
+            "EOwner",
+            NodeGraphRelation::TransferredTo,
+            Some(3),
+        );
+        assert!(builder.contains("ECreator"));
+        assert_eq!(builder.len(), 3);
+
+        let graph = builder.build();
+        assert_eq!(graph.vertices[0], subject);
+        assert_eq!(
+            graph
+                .edges
+                .iter()
+                .map(|edge| (edge.to.as_str(), edge.relation))
+                .collect::<Vec<_>>(),
+            vec![
+                ("ECreator", NodeGraphRelation::CreatedBy),
+                ("ECreator", NodeGraphRelation::OwnedBy),
+                ("EOwner", NodeGraphRelation::TransferredTo),
+            ]
+        );
+        assert!(!graph.truncated);
+    }
+}
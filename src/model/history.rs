@@ -0,0 +1,60 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Node history model.
+//!
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+/// Operational action on the node, as opposed to ledger activity.
+#[derive(
+    Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeHistoryKind {
+    /// The node started.
+    Started,
+    /// The node was cancelled.
+    Stopped,
+    /// Settings were reloaded while running.
+    ConfigReloaded,
+    /// The node key pair is not the one of the previous start.
+    KeysRotated,
+    /// A listen address was in use and a fallback port took its place.
+    ListenFailover,
+    /// A support bundle was written.
+    SupportBundle,
+    /// A backup of the database was written.
+    Backup,
+    /// The database was restored from a backup.
+    Restored,
+    /// Data was moved from a legacy location to the configured one.
+    Migrated,
+    /// A feature flag was turned on or off.
+    FeatureToggled,
+    /// The first group of boot nodes was unreachable and a later one served the bootstrap.
+    BootstrapFailover,
+    /// The database was compacted.
+    Compacted,
+    /// The supervisor rebuilt the node after a panic or a fatal error.
+    Restarted,
+    /// A boot node was added to the node database.
+    BootNodeAdded,
+    /// A boot node was removed from the node database.
+    BootNodeRemoved,
+    /// Old events were moved to the archive.
+    EventsArchived,
+}
+
+/// Entry of the node history.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct NodeHistoryEntry {
+    /// Milliseconds since UNIX epoch at which the action happened
+    #[serde(with = "super::timestamp::millis")]
+    pub timestamp: u64,
+    /// Action
+    pub kind: NodeHistoryKind,
+    /// Details for the operator
+    pub detail: String,
+}
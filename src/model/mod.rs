@@ -10,8 +10,32 @@
 //! The data model is composed of the following elements:
 //!
 
+pub mod backup;
+pub mod contract;
+pub mod database;
+pub mod feature;
+pub mod graph;
+pub mod history;
+pub mod peer;
 pub mod request;
+pub mod search;
+pub mod service;
 pub mod signature;
+pub mod timestamp;
+pub mod usage;
+pub mod verification;
 
+pub use backup::*;
+pub use contract::*;
+pub use database::*;
+pub use feature::*;
+pub use graph::*;
+pub use history::*;
+pub use peer::*;
 pub use request::*;
+pub use search::*;
+pub use service::*;
 pub use signature::*;
+pub use timestamp::{rfc3339_millis, rfc3339_nanos, Timestamped};
+pub use usage::*;
+pub use verification::*;
@@ -0,0 +1,36 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Peers of the ledger network model.
+//!
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use kore_base::RoutingNode;
+use serde::{Deserialize, Serialize};
+
+/// Boot node kept in the node database, given to Kore Base on each start.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct NodeBootNode {
+    /// Peer identifier
+    pub peer_id: String,
+    /// Multiaddresses of the peer
+    pub addresses: Vec<String>,
+}
+
+impl From<RoutingNode> for NodeBootNode {
+    fn from(node: RoutingNode) -> Self {
+        Self {
+            peer_id: node.peer_id,
+            addresses: node.address,
+        }
+    }
+}
+
+impl From<NodeBootNode> for RoutingNode {
+    fn from(node: NodeBootNode) -> Self {
+        Self {
+            peer_id: node.peer_id,
+            address: node.addresses,
+        }
+    }
+}
@@ -21,6 +21,7 @@ use kore_base::{
 
 use std::{collections::HashSet, fmt::Debug, str::FromStr};
 
+use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -35,6 +36,21 @@ pub enum NodeEventRequest {
     EOL(NodeEOLRequest),
 }
 
+/// Types of event request, as returned by `NodeEventRequest::request_type`.
+pub const REQUEST_TYPES: [&str; 4] = ["Create", "Fact", "Transfer", "EOL"];
+
+impl NodeEventRequest {
+    /// Type of the request: Create, Fact, Transfer or EOL.
+    pub fn request_type(&self) -> &'static str {
+        match self {
+            Self::Create(_) => "Create",
+            Self::Fact(_) => "Fact",
+            Self::Transfer(_) => "Transfer",
+            Self::EOL(_) => "EOL",
+        }
+    }
+}
+
 impl From<BaseEventRequest> for NodeEventRequest {
     fn from(request: BaseEventRequest) -> Self {
         match request {
@@ -197,6 +213,9 @@ pub struct NodeSignedEventRequest {
     pub request: NodeEventRequest,
     /// Signature
     pub signature: Option<NodeSignature>,
+    /// Metadata about the caller, kept only in this node
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<NodeRequestOrigin>,
 }
 
 impl From<NodeSigned<BaseEventRequest>> for NodeSignedEventRequest {
@@ -204,6 +223,7 @@ impl From<NodeSigned<BaseEventRequest>> for NodeSignedEventRequest {
         Self {
             request: NodeEventRequest::from(signed.content),
             signature: Some(signed.signature),
+            origin: None,
         }
     }
 }
@@ -229,10 +249,91 @@ impl From<BaseKoreRequest> for NodeSignedEventRequest {
         Self {
             request: signed.content,
             signature: Some(signed.signature),
+            origin: None,
         }
     }
 }
 
+/// Origin of an event request, as informed by the caller.
+#[derive(
+    Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq,
+)]
+pub struct NodeRequestOrigin {
+    /// System that issued the request
+    pub source: Option<String>,
+    /// Device from which the request was issued
+    pub device_id: Option<String>,
+    /// Approximate location of the caller
+    pub geo_hint: Option<String>,
+}
+
+/// Event request sent through this node.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct NodeRequestRecord {
+    /// Event request identifier
+    pub request_id: String,
+    /// Type of event request (Create, Fact, Transfer, EOL)
+    pub request_type: String,
+    /// Subject identifier, none for create requests
+    pub subject_id: Option<String>,
+    /// Milliseconds since UNIX epoch at which the request was recorded, once Kore Base took it
+    #[serde(with = "super::timestamp::millis")]
+    pub timestamp: u64,
+    /// Metadata about the caller
+    pub origin: Option<NodeRequestOrigin>,
+}
+
+impl NodeRequestRecord {
+    /// Create a new record of the request.
+    pub fn new(
+        request_id: String,
+        request: &NodeEventRequest,
+        timestamp: u64,
+        origin: Option<NodeRequestOrigin>,
+    ) -> Self {
+        let subject_id = match request {
+            NodeEventRequest::Create(_) => None,
+            NodeEventRequest::Fact(request) => Some(request.subject_id.clone()),
+            NodeEventRequest::Transfer(request) => Some(request.subject_id.clone()),
+            NodeEventRequest::EOL(request) => Some(request.subject_id.clone()),
+        };
+        Self {
+            request_id,
+            request_type: request.request_type().to_owned(),
+            subject_id,
+            timestamp,
+            origin,
+        }
+    }
+}
+
+/// Event request sent with an idempotency key, see `KoreApi::send_idempotent_event_request`.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct NodeIdempotentRequest {
+    /// Event request identifier
+    pub request_id: String,
+    /// JSON of the event request, which the requests sent again under the key must repeat
+    pub request: String,
+}
+
+/// Replicated event that failed, see the `replication` module.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct NodeReplicationFailure {
+    /// Base URL of the remote node
+    pub remote_url: String,
+    /// Subject identifier
+    pub subject_id: String,
+    /// Sequence number of the event
+    pub sn: u64,
+    /// Attempts that failed
+    pub attempts: u32,
+    /// Error of the last attempt
+    pub error: String,
+    /// Milliseconds since UNIX epoch of the last attempt
+    #[serde(with = "super::timestamp::millis")]
+    pub timestamp: u64,
+}
+
 /// Event request response
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EventRequestResponse {
@@ -294,6 +395,84 @@ impl TryFrom<NodeKoreRequest> for BaseKoreRequest {
     }
 }
 
+/// State of an event request.
+///
+/// A request starts `Processing` and ends in one of the final states, which it never leaves:
+///
+/// ```text
+///               +--> Finished
+///               |
+/// Processing ---+--> Rejected
+///               |
+///               +--> Error
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NodeRequestState {
+    /// The request is being processed by the network.
+    Processing,
+    /// The request produced an event that was applied to the subject.
+    Finished,
+    /// The request produced an event, but it was not applied (e.g. the approval was rejected).
+    Rejected,
+    /// The request could not be processed.
+    Error,
+}
+
+impl NodeRequestState {
+    /// State of a request from the state and success flag of Kore Base.
+    pub fn new(state: &RequestState, success: Option<bool>) -> Self {
+        match state {
+            RequestState::Processing => Self::Processing,
+            RequestState::Finished if success == Some(false) => Self::Rejected,
+            RequestState::Finished => Self::Finished,
+            RequestState::Error => Self::Error,
+        }
+    }
+
+    /// Whether the request reached a state it never leaves.
+    pub fn is_final(&self) -> bool {
+        !matches!(self, Self::Processing)
+    }
+
+    /// Whether the request can still be cancelled, only while it is processing.
+    pub fn can_cancel(&self) -> bool {
+        !self.is_final()
+    }
+
+    /// Whether a request in this state may move to `next`. Staying in the same state is allowed.
+    pub fn can_transition_to(&self, next: Self) -> bool {
+        *self == next || *self == Self::Processing
+    }
+
+    /// Move the request to `next`.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Conflict` - The request already reached a different final state.
+    ///
+    pub fn transition(self, next: Self) -> Result<Self, NodeError> {
+        if self.can_transition_to(next) {
+            Ok(next)
+        } else {
+            Err(NodeError::Conflict(format!(
+                "request state {:?} cannot change to {:?}",
+                self, next
+            )))
+        }
+    }
+}
+
+/// Change of state of an event request, as observed by the node.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeRequestTransition {
+    /// Request identifier
+    pub request_id: String,
+    /// Previous state
+    pub from: NodeRequestState,
+    /// New state
+    pub to: NodeRequestState,
+}
+
 /// Kore request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeKoreRequestState {
@@ -304,23 +483,44 @@ pub struct NodeKoreRequestState {
     /// Current sequence number of the subject
     pub sn: Option<u64>,
     /// Current status of the request
-    pub state: RequestState,
+    pub state: NodeRequestState,
     /// Value that says if the request has been successful
     pub success: Option<bool>,
 }
 
+impl NodeKoreRequestState {
+    /// Whether the request is no longer processing.
+    pub fn is_final(&self) -> bool {
+        self.state.is_final()
+    }
+
+    /// Whether the request can still be cancelled.
+    pub fn can_cancel(&self) -> bool {
+        self.state.can_cancel()
+    }
+}
+
 impl From<BaseKoreRequest> for NodeKoreRequestState {
     fn from(value: BaseKoreRequest) -> Self {
         Self {
             id: value.id.to_str(),
             subject_id: value.subject_id.map(|id| id.to_str()),
             sn: value.sn,
-            state: value.state,
+            state: NodeRequestState::new(&value.state, value.success),
             success: value.success,
         }
     }
 }
 
+/// Parameters of a long poll of the state of a request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeRequestStateWait {
+    /// State the client already knows
+    pub last_state: NodeRequestState,
+    /// Seconds to wait for a change, 30 when not given
+    pub timeout: Option<u64>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NodeGetApprovals {
     /// Status of approvals
@@ -347,6 +547,66 @@ pub struct PaginatorFromNumber {
     pub quantity: Option<i64>,
 }
 
+/// Page of a listing.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    /// Entries of the page.
+    pub items: Vec<T>,
+    /// Value of `from` that reads the next page, `None` on the last page.
+    pub next_cursor: Option<String>,
+    /// Number of entries of the whole listing, known when it fits in the first page.
+    pub total: Option<u64>,
+}
+
+impl<T> Page<T> {
+    /// Number of entries to read for a page of `quantity`: one more, which tells whether
+    /// another page follows.
+    pub(crate) fn read_quantity(quantity: Option<i64>) -> Option<i64> {
+        quantity.map(|quantity| quantity.max(0) + 1)
+    }
+
+    /// Cut a page of `quantity` from entries read with `read_quantity`.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - Entries read.
+    /// * `first` - Whether the entries start the listing.
+    /// * `quantity` - Size of the page, `None` for every entry.
+    /// * `cursor` - Value of `from` that reads the entries after the given one.
+    ///
+    pub(crate) fn cut(
+        mut entries: Vec<T>,
+        first: bool,
+        quantity: Option<i64>,
+        cursor: impl Fn(&T) -> String,
+    ) -> Self {
+        let mut next_cursor = None;
+        let mut complete = first;
+        if let Some(quantity) = quantity {
+            let quantity = quantity.max(0) as usize;
+            if entries.len() > quantity {
+                entries.truncate(quantity);
+                next_cursor = entries.last().map(cursor);
+                complete = false;
+            }
+        }
+        let total = complete.then_some(entries.len() as u64);
+        Self {
+            items: entries,
+            next_cursor,
+            total,
+        }
+    }
+
+    /// Keep the entries that match `filter`, updating a known total.
+    pub(crate) fn retain(&mut self, filter: impl FnMut(&T) -> bool) {
+        self.items.retain(filter);
+        if self.total.is_some() {
+            self.total = Some(self.items.len() as u64);
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NodeApprovalRequest {
     // Evaluation Request
@@ -427,6 +687,64 @@ pub enum PatchVote {
     RespondedRejected,
 }
 
+/// Identities, addresses and build of the node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeInfo {
+    /// Controller identifier, used to sign
+    pub controller_id: String, // KeyIdentifier
+    /// Peer identifier in the network
+    pub peer_id: String,
+    /// Address of the prometheus server, none when the metrics are not served
+    pub prometheus: Option<String>,
+    /// Algorithm of the node key, e.g. `Ed25519`
+    pub key_derivator: String,
+    /// Algorithm of the digests, e.g. `Blake3_256`
+    pub digest_derivator: String,
+    /// Multiaddresses the node listens on
+    pub listen_addresses: Vec<String>,
+    /// Multiaddresses announced to the peers
+    pub external_addresses: Vec<String>,
+    /// Versions and build of the node
+    pub build: NodeBuildInfo,
+}
+
+/// Versions and build of the node binary.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NodeBuildInfo {
+    /// Version of kore-node
+    pub version: String,
+    /// Version of Kore Base
+    pub kore_base_version: String,
+    /// Cargo features enabled
+    pub features: Vec<String>,
+    /// Operating system, e.g. `linux`
+    pub os: String,
+    /// CPU architecture, e.g. `x86_64`
+    pub arch: String,
+    /// Whether it is a debug build
+    pub debug: bool,
+}
+
+/// Result of a node key rotation.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NodeKeyRotation {
+    /// Version under which the replaced key is kept
+    pub version: u32,
+    /// Controller identifier of the replaced key, in use until the node restarts
+    pub previous_controller_id: String, // KeyIdentifier
+    /// Controller identifier of the new key
+    pub controller_id: String, // KeyIdentifier
+}
+
+/// Replaced key of the node, kept to verify what it signed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NodeKeyVersion {
+    /// Key version, starting at 1
+    pub version: u32,
+    /// Controller identifier of the key
+    pub controller_id: String, // KeyIdentifier
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PreauthorizedSubjectsResponse {
     /// Subject identifier
@@ -444,6 +762,24 @@ impl From<(DigestIdentifier, HashSet<KeyIdentifier>)> for PreauthorizedSubjectsR
     }
 }
 
+/// Filters of the preauthorized subjects, all of them must match.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NodeAllowedSubjectsFilter {
+    /// Provider allowed for the subject.
+    pub provider: Option<String>,
+    /// Governance of the subject, or the governance itself. Unknown subjects never match.
+    pub governance_id: Option<String>,
+    /// Namespace of the subject. Unknown subjects never match.
+    pub namespace: Option<String>,
+}
+
+impl NodeAllowedSubjectsFilter {
+    /// Whether matching requires reading the subject.
+    pub fn needs_subject(&self) -> bool {
+        self.governance_id.is_some() || self.namespace.is_some()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthorizeSubject {
     /// Providers acting on a specific subject
@@ -483,6 +819,18 @@ pub struct NodeSubjects {
     pub subject_type: Option<String>,
     /// Governance identifier
     pub governanceid: Option<String>,
+    /// Archived subjects to list (unarchived, archived, all). Archived subjects are hidden by default
+    pub archive_filter: Option<String>,
+}
+
+/// Filters of `KoreApi::get_subjects_by` looked up through the secondary indexes, kept out of
+/// `NodeSubjects` so that its fields do not change.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NodeSubjectKeys {
+    /// Owner of the subjects
+    pub owner: Option<String>,
+    /// Namespace of the subjects
+    pub namespace: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -509,6 +857,22 @@ pub struct NodeSubjectData {
     pub properties: Value,
     /// Indicates if the subject is active or not
     pub active: bool,
+    /// Indicates if the subject has been archived in this node
+    #[serde(default)]
+    pub archived: bool,
+}
+
+impl NodeSubjectData {
+    /// Weak entity tag of the subject.
+    /// It changes with every event of the subject, and when it is archived or unarchived.
+    pub fn etag(&self) -> String {
+        format!(
+            "W/\"{}-{}-{}\"",
+            self.subject_id,
+            self.sn,
+            u8::from(self.archived)
+        )
+    }
 }
 
 impl From<SubjectData> for NodeSubjectData {
@@ -525,11 +889,12 @@ impl From<SubjectData> for NodeSubjectData {
             properties: value.properties.0,
             active: value.active,
             name: value.name,
+            archived: false,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, BorshSerialize, BorshDeserialize, PartialEq)]
 pub struct NodeValidationProof {
     /// Subject identifier
     pub subject_id: String,
@@ -573,7 +938,7 @@ impl From<ValidationProof> for NodeValidationProof {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, BorshSerialize, BorshDeserialize, PartialEq)]
 pub struct NodeProof {
     /// Current validation proof
     pub proof: NodeValidationProof,
@@ -640,3 +1005,75 @@ impl From<Event> for EventContentResponse {
         }
     }
 }
+
+impl TryFrom<EventContentResponse> for Event {
+    type Error = NodeError;
+    fn try_from(value: EventContentResponse) -> Result<Self, Self::Error> {
+        // The first event of a subject has no previous event, whose hash is empty.
+        let digest = |digest: &str| match digest {
+            "" => Ok(DigestIdentifier::default()),
+            digest => DigestIdentifier::from_str(digest)
+                .map_err(|_| NodeError::InvalidParameter("digest identifier".to_owned())),
+        };
+        let signatures = |signatures: Vec<NodeSignature>| {
+            signatures
+                .into_iter()
+                .map(Signature::try_from)
+                .collect::<Result<HashSet<Signature>, NodeError>>()
+        };
+        Ok(Self {
+            subject_id: digest(&value.subject_id)?,
+            event_request: BaseSigned {
+                content: BaseEventRequest::try_from(value.event_request.content)?,
+                signature: Signature::try_from(value.event_request.signature)?,
+            },
+            sn: value.sn,
+            gov_version: value.gov_version,
+            patch: ValueWrapper(value.patch),
+            state_hash: digest(&value.state_hash)?,
+            eval_success: value.eval_success,
+            appr_required: value.appr_required,
+            approved: value.approved,
+            hash_prev_event: digest(&value.hash_prev_event)?,
+            evaluators: signatures(value.evaluators)?,
+            approvers: signatures(value.approvers)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_request_state_transitions() {
+        use NodeRequestState::*;
+
+        assert_eq!(
+            NodeRequestState::new(&RequestState::Processing, None),
+            Processing
+        );
+        assert_eq!(
+            NodeRequestState::new(&RequestState::Finished, Some(true)),
+            Finished
+        );
+        assert_eq!(
+            NodeRequestState::new(&RequestState::Finished, Some(false)),
+            Rejected
+        );
+        assert_eq!(NodeRequestState::new(&RequestState::Error, None), Error);
+
+        assert!(Processing.can_cancel() && !Processing.is_final());
+        for state in [Finished, Rejected, Error] {
+            assert!(state.is_final() && !state.can_cancel());
+            assert_eq!(Processing.transition(state).unwrap(), state);
+            assert_eq!(state.transition(state).unwrap(), state);
+            assert!(matches!(
+                state.transition(Processing),
+                Err(NodeError::Conflict(_))
+            ));
+        }
+        assert!(Finished.transition(Error).is_err());
+    }
+}
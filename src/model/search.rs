@@ -0,0 +1,32 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Subject search model.
+//!
+
+use serde::{Deserialize, Serialize};
+
+/// Filter of a subject search. Every filter set must match.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NodeSubjectSearch {
+    /// Namespace of the subjects
+    pub namespace: Option<String>,
+    /// Schema identifier of the subjects
+    pub schema_id: Option<String>,
+    /// Owner of the subjects
+    pub owner: Option<String>,
+    /// Governance identifier of the subjects
+    pub governance_id: Option<String>,
+    /// Whether the subjects are active
+    pub active: Option<bool>,
+    /// JSONPath expressions over the properties, e.g. `$.grape == "malbec"` or `$.year >= 2020`;
+    /// an expression without comparison matches when the path exists
+    #[serde(default)]
+    pub properties: Vec<String>,
+    /// Text found in the name or in a string of the properties, case insensitive
+    pub text: Option<String>,
+    /// Subject from which the search is made (being excluded)
+    pub from: Option<String>,
+    /// Number of entries
+    pub quantity: Option<i64>,
+}
@@ -0,0 +1,22 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Service endpoints model.
+//!
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+/// Service endpoints advertised by a node to its trusted peers, signed with the node key pair.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct NodeServiceRecord {
+    /// Controller identifier of the node
+    pub controller_id: String,
+    /// URL of its REST API
+    pub rest_url: Option<String>,
+    /// URL of its metrics
+    pub metrics_url: Option<String>,
+    /// Milliseconds since UNIX epoch at which the record was signed
+    #[serde(with = "super::timestamp::millis")]
+    pub timestamp: u64,
+}
@@ -6,6 +6,7 @@
 
 use std::str::FromStr;
 
+use super::timestamp::rfc3339_nanos;
 use crate::error::NodeError;
 
 use kore_base::{
@@ -20,11 +21,12 @@ use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
 /// Signature model.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct NodeSignature {
     /// Public key of the issuer
     signer: String, // KeyIdentifier
-    /// Timestamp at which the signature was made
+    /// Timestamp at which the signature was made, in nanoseconds since UNIX epoch
+    #[serde(with = "super::timestamp::nanos")]
     timestamp: u64,
     /// Signature value
     value: String, // SignatureIdentifier,
@@ -32,6 +34,28 @@ pub struct NodeSignature {
     content_hash: String,
 }
 
+impl NodeSignature {
+    /// Public key of the issuer.
+    pub fn signer(&self) -> &str {
+        &self.signer
+    }
+
+    /// Timestamp at which the signature was made.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Hash of the signed content.
+    pub fn content_hash(&self) -> &str {
+        &self.content_hash
+    }
+
+    /// Timestamp at which the signature was made, as an RFC 3339 string.
+    pub fn timestamp_rfc3339(&self) -> String {
+        rfc3339_nanos(self.timestamp)
+    }
+}
+
 impl From<BaseSignature> for NodeSignature {
     fn from(signature: BaseSignature) -> Self {
         Self {
@@ -0,0 +1,544 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Timestamps of the model.
+//!
+//! Timestamps are kept as integers since UNIX epoch: nanoseconds in signatures, as made by Kore
+//! Base, and milliseconds in the records of the node. Fields marked with `#[serde(with =
+//! "nanos")]` or `#[serde(with = "millis")]` are serialized and deserialized as that integer by
+//! any serializer, so the stored records and the codecs that are not self-describing are not
+//! affected by the format of the responses.
+//!
+//! Responses and notifications are serialized through `Timestamped`, which writes the marked
+//! fields in the `TimestampFormat` it is given, each response with its own.
+//!
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::{
+    de::{self, Visitor},
+    ser::{
+        SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+        SerializeTupleStruct, SerializeTupleVariant,
+    },
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::settings::TimestampFormat;
+
+/// Name of the newtype a timestamp in milliseconds is serialized as.
+const MILLIS: &str = "$kore::timestamp::millis";
+
+/// Name of the newtype a timestamp in nanoseconds is serialized as.
+const NANOS: &str = "$kore::timestamp::nanos";
+
+/// RFC 3339 string of a timestamp in milliseconds since UNIX epoch.
+pub fn rfc3339_millis(millis: u64) -> String {
+    Unit::Millis.rfc3339(millis)
+}
+
+/// RFC 3339 string of a timestamp in nanoseconds since UNIX epoch.
+pub fn rfc3339_nanos(nanos: u64) -> String {
+    Unit::Nanos.rfc3339(nanos)
+}
+
+/// Resolution of a timestamp.
+#[derive(Clone, Copy)]
+enum Unit {
+    Millis,
+    Nanos,
+}
+
+impl Unit {
+    /// Unit of the newtype `name`, `None` if it is not a timestamp.
+    fn of(name: &str) -> Option<Self> {
+        match name {
+            MILLIS => Some(Unit::Millis),
+            NANOS => Some(Unit::Nanos),
+            _ => None,
+        }
+    }
+
+    fn rfc3339(self, value: u64) -> String {
+        match self {
+            Unit::Millis => {
+                humantime::format_rfc3339_millis(UNIX_EPOCH + Duration::from_millis(value))
+                    .to_string()
+            }
+            Unit::Nanos => {
+                humantime::format_rfc3339_nanos(UNIX_EPOCH + Duration::from_nanos(value))
+                    .to_string()
+            }
+        }
+    }
+}
+
+fn serialize<S: Serializer>(
+    value: u64,
+    unit: Unit,
+    format: TimestampFormat,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match format {
+        TimestampFormat::Epoch => serializer.serialize_u64(value),
+        TimestampFormat::Rfc3339 => serializer.serialize_str(&unit.rfc3339(value)),
+        TimestampFormat::Both => {
+            let mut both = serializer.serialize_struct("Timestamp", 2)?;
+            both.serialize_field("epoch", &value)?;
+            both.serialize_field("rfc3339", &unit.rfc3339(value))?;
+            both.end()
+        }
+    }
+}
+
+/// Timestamp serialized as an integer since UNIX epoch, or as a newtype of one.
+struct EpochVisitor;
+
+impl<'de> Visitor<'de> for EpochVisitor {
+    type Value = u64;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an integer since UNIX epoch")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<u64, E> {
+        Ok(value)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<u64, E> {
+        u64::try_from(value).map_err(|_| E::custom(format!("negative timestamp {}", value)))
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<u64, D::Error> {
+        u64::deserialize(deserializer)
+    }
+}
+
+/// Serde functions of timestamps in milliseconds since UNIX epoch.
+pub mod millis {
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(MILLIS, value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        deserializer.deserialize_newtype_struct(MILLIS, EpochVisitor)
+    }
+}
+
+/// Serde functions of timestamps in nanoseconds since UNIX epoch.
+pub mod nanos {
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(NANOS, value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        deserializer.deserialize_newtype_struct(NANOS, EpochVisitor)
+    }
+}
+
+/// A value serialized with its timestamps in `format`.
+pub struct Timestamped<'a, T: ?Sized> {
+    value: &'a T,
+    format: TimestampFormat,
+}
+
+impl<'a, T: ?Sized> Timestamped<'a, T> {
+    /// Serialize `value` with its timestamps in `format`.
+    pub fn new(value: &'a T, format: TimestampFormat) -> Self {
+        Self { value, format }
+    }
+}
+
+impl<T: Serialize + ?Sized> Serialize for Timestamped<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(Formatted {
+            inner: serializer,
+            format: self.format,
+        })
+    }
+}
+
+/// Serializer that writes the timestamps in `format` and everything else as `inner` does.
+struct Formatted<S> {
+    inner: S,
+    format: TimestampFormat,
+}
+
+/// Forward the serialization of primitive values to the inner serializer.
+macro_rules! forward {
+    ($($method:ident($type:ty)),* $(,)?) => {
+        $(
+            fn $method(self, value: $type) -> Result<S::Ok, S::Error> {
+                self.inner.$method(value)
+            }
+        )*
+    };
+}
+
+impl<S: Serializer> Serializer for Formatted<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Formatted<S::SerializeSeq>;
+    type SerializeTuple = Formatted<S::SerializeTuple>;
+    type SerializeTupleStruct = Formatted<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Formatted<S::SerializeTupleVariant>;
+    type SerializeMap = Formatted<S::SerializeMap>;
+    type SerializeStruct = Formatted<S::SerializeStruct>;
+    type SerializeStructVariant = Formatted<S::SerializeStructVariant>;
+
+    forward!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_unit_struct(&'static str),
+    );
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        self.inner
+            .serialize_some(&Timestamped::new(value, self.format))
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_variant(name, index, variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        // The timestamps are the newtypes of `millis` and `nanos`, always of an `u64`.
+        let epoch = Unit::of(name).and_then(|unit| {
+            let epoch = serde_json::to_value(value).ok()?.as_u64()?;
+            Some((unit, epoch))
+        });
+        match epoch {
+            Some((unit, epoch)) => serialize(epoch, unit, self.format, self.inner),
+            None => self
+                .inner
+                .serialize_newtype_struct(name, &Timestamped::new(value, self.format)),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_newtype_variant(
+            name,
+            index,
+            variant,
+            &Timestamped::new(value, self.format),
+        )
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        let inner = self.inner.serialize_seq(len)?;
+        Ok(Formatted {
+            inner,
+            format: self.format,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        let inner = self.inner.serialize_tuple(len)?;
+        Ok(Formatted {
+            inner,
+            format: self.format,
+        })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        let inner = self.inner.serialize_tuple_struct(name, len)?;
+        Ok(Formatted {
+            inner,
+            format: self.format,
+        })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        let inner = self
+            .inner
+            .serialize_tuple_variant(name, index, variant, len)?;
+        Ok(Formatted {
+            inner,
+            format: self.format,
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        let inner = self.inner.serialize_map(len)?;
+        Ok(Formatted {
+            inner,
+            format: self.format,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        let inner = self.inner.serialize_struct(name, len)?;
+        Ok(Formatted {
+            inner,
+            format: self.format,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        let inner = self
+            .inner
+            .serialize_struct_variant(name, index, variant, len)?;
+        Ok(Formatted {
+            inner,
+            format: self.format,
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+impl<S: SerializeSeq> SerializeSeq for Formatted<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.inner
+            .serialize_element(&Timestamped::new(value, self.format))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeTuple> SerializeTuple for Formatted<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.inner
+            .serialize_element(&Timestamped::new(value, self.format))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeTupleStruct> SerializeTupleStruct for Formatted<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.inner
+            .serialize_field(&Timestamped::new(value, self.format))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeTupleVariant> SerializeTupleVariant for Formatted<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.inner
+            .serialize_field(&Timestamped::new(value, self.format))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeMap> SerializeMap for Formatted<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), S::Error> {
+        self.inner.serialize_key(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.inner
+            .serialize_value(&Timestamped::new(value, self.format))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeStruct> SerializeStruct for Formatted<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        self.inner
+            .serialize_field(key, &Timestamped::new(value, self.format))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), S::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeStructVariant> SerializeStructVariant for Formatted<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        self.inner
+            .serialize_field(key, &Timestamped::new(value, self.format))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), S::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::{json, Value};
+
+    const MILLIS: u64 = 1_700_000_000_123;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Record {
+        #[serde(with = "millis")]
+        created: u64,
+        #[serde(flatten)]
+        signed: Signed,
+        nested: Vec<Option<Signed>>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Signed {
+        #[serde(with = "nanos")]
+        timestamp: u64,
+    }
+
+    fn record() -> Record {
+        Record {
+            created: MILLIS,
+            signed: Signed { timestamp: 123 },
+            nested: vec![Some(Signed { timestamp: 123 }), None],
+        }
+    }
+
+    fn to_value(format: TimestampFormat) -> Value {
+        serde_json::to_value(Timestamped::new(&record(), format)).unwrap()
+    }
+
+    #[test]
+    fn test_serialize_timestamp() {
+        let epoch =
+            json!({"created": MILLIS, "timestamp": 123, "nested": [{"timestamp": 123}, null]});
+        assert_eq!(serde_json::to_value(record()).unwrap(), epoch);
+        assert_eq!(to_value(TimestampFormat::Epoch), epoch);
+        assert_eq!(
+            to_value(TimestampFormat::Rfc3339),
+            json!({
+                "created": "2023-11-14T22:13:20.123Z",
+                "timestamp": "1970-01-01T00:00:00.000000123Z",
+                "nested": [{"timestamp": "1970-01-01T00:00:00.000000123Z"}, null],
+            })
+        );
+        assert_eq!(
+            to_value(TimestampFormat::Both)["created"],
+            json!({"epoch": MILLIS, "rfc3339": "2023-11-14T22:13:20.123Z"})
+        );
+    }
+
+    #[test]
+    fn test_deserialize_timestamp() {
+        let epoch = serde_json::to_value(record()).unwrap();
+        assert_eq!(serde_json::from_value::<Record>(epoch).unwrap(), record());
+        // Formatted responses are not read back.
+        assert!(serde_json::from_value::<Record>(to_value(TimestampFormat::Rfc3339)).is_err());
+        assert!(millis::deserialize(json!(-1)).is_err());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_bincode_timestamp() {
+        let signed = Signed { timestamp: 123 };
+        let bytes = bincode::serialize(&signed).unwrap();
+        assert_eq!(bincode::deserialize::<Signed>(&bytes).unwrap(), signed);
+    }
+}
@@ -0,0 +1,25 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Usage accounting model.
+//!
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+/// Requests served to a caller in a period, either one accounting window or a summary of several.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct NodeUsage {
+    /// Caller, the principal of the credentials of the client, e.g. `key:1`, or its IP address
+    pub caller: String,
+    /// Milliseconds since UNIX epoch at which the period starts
+    #[serde(with = "super::timestamp::millis")]
+    pub from: u64,
+    /// Milliseconds since UNIX epoch at which the period ends, excluded
+    #[serde(with = "super::timestamp::millis")]
+    pub to: u64,
+    /// Requests served
+    pub requests: u64,
+    /// Payload bytes received with the requests
+    pub bytes: u64,
+}
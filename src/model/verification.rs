@@ -0,0 +1,104 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Ledger verification model.
+//!
+
+use serde::{Deserialize, Serialize};
+
+/// Checks of an event of the ledger, made by the node on its own copy.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeEventVerification {
+    /// Subject identifier
+    pub subject_id: String,
+    /// Sequence number of the event
+    pub sn: u64,
+    /// Whether every check passed
+    pub valid: bool,
+    /// Whether no check failed but some could not be made, such as the evaluator signatures
+    pub partial: bool,
+    /// Checks made, in order
+    pub checks: Vec<NodeVerificationCheck>,
+}
+
+/// Check of a signature or a link of an event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeVerificationCheck {
+    /// What is checked
+    pub kind: NodeVerificationKind,
+    /// Signer of the checked signature, if any
+    pub signer: Option<String>,
+    /// Result of the check
+    pub status: NodeVerificationStatus,
+    /// Why the check failed or was not made
+    pub detail: Option<String>,
+}
+
+/// Part of an event that is checked.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeVerificationKind {
+    /// Signature of the event by its subject
+    EventSignature,
+    /// Signature of the event request by its issuer
+    RequestSignature,
+    /// Signature of an evaluator
+    EvaluatorSignature,
+    /// Signature of an approver
+    ApproverSignature,
+    /// Subject and sequence number of the event
+    Sequence,
+    /// Digest of the previous event
+    PrevEventHash,
+}
+
+/// Result of a check.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeVerificationStatus {
+    /// The check passed
+    Valid,
+    /// The check failed
+    Invalid,
+    /// The node does not hold what the check needs
+    Unchecked,
+}
+
+/// Outcome of the checks of every event of a subject, from its creation. The checks of each
+/// event are streamed by `KoreApi::verify_subject_chain_reports`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeChainVerification {
+    /// Subject identifier
+    pub subject_id: String,
+    /// Events checked
+    pub events: u64,
+    /// Whether every event is valid
+    pub valid: bool,
+    /// Whether no check failed but some could not be made
+    pub partial: bool,
+    /// Sequence number of the first event with a failed check
+    pub first_invalid: Option<u64>,
+}
+
+impl NodeChainVerification {
+    /// Outcome of a chain before any event is checked.
+    pub fn new(subject_id: &str) -> Self {
+        Self {
+            subject_id: subject_id.to_owned(),
+            events: 0,
+            valid: true,
+            partial: false,
+            first_invalid: None,
+        }
+    }
+
+    /// Count the checks of the next event of the chain.
+    pub fn add(&mut self, report: &NodeEventVerification) {
+        self.events += 1;
+        self.valid &= report.valid;
+        if !report.valid && !report.partial {
+            self.first_invalid.get_or_insert(report.sn);
+        }
+        self.partial = !self.valid && self.first_invalid.is_none();
+    }
+}
@@ -2,31 +2,103 @@
 // SPDX-License-Identifier: AGPL-3.0-or-later
 use prometheus_client::registry::Registry;
 
+#[cfg(feature = "grpc")]
+use crate::grpc::run_grpc;
+#[cfg(feature = "http-api")]
+use crate::http_api::run_http_api;
+#[cfg(feature = "metrics-push")]
+use crate::prometheus::push::run_metrics_push;
 #[cfg(feature = "prometheus")]
-use crate::prometheus::server::run_prometheus;
+use crate::prometheus::server::{run_prometheus, PrometheusServer};
+#[cfg(feature = "replication")]
+use crate::replication::run_replication;
+#[cfg(feature = "services")]
+use crate::services::run_peer_services;
+#[cfg(feature = "soak")]
+use crate::soak::run_soak;
+#[cfg(feature = "webhooks")]
+use crate::webhooks::run_webhooks;
 use crate::{
+    access_log::AccessLogger,
+    archival::{open_archive, run_archival, Archival, ARCHIVAL_SCOPE, EVENT_COLLECTION},
+    auth::Authenticator,
+    backup::{restore_newest, run_backups, BackupSource},
+    bootstrap::{run_boot_group_health, select_boot_group, with_boot_nodes},
+    config::{
+        build::ConfigSources,
+        watcher::{diff_settings, ConfigEvent, ConfigWatcher, SettingChange},
+    },
+    contracts::{run_contracts_watcher, Contracts},
+    database::{
+        batch::BatchCollection,
+        index::{SubjectIndex, INDEX_COLLECTION},
+        ledger::{LedgerCommits, LedgerManager, LedgerProofs, PROOFS_SCOPE},
+        maintenance::DbMaintenance,
+        metered::MeteredManager,
+        store::NodeStore,
+    },
+    dns::{resolve_boot_nodes, run_dns_refresh},
     error::NodeError,
-    settings::{DbSettings, KoreSettings},
-    utils::node_key_pair,
+    expiry::run_sweeper,
+    features::run_auto_approval,
+    lifecycle::{LifecycleEvent, LifecycleEvents, NodeState},
+    logging::init_logging,
+    metrics::{run_approvals_gauge, NodeMetrics},
+    migration::migrate_legacy_data,
+    model::NodeHistoryKind,
+    peers::learn_boot_nodes,
+    scheduler::run_schedules,
+    search::run_subject_indexer,
+    settings::{DbSettings, KeysBackend, KoreSettings, SupervisorSettings},
+    support::write_support_bundle,
+    tasks::{panic_message, NodeTasks},
+    usage::run_usage_flush,
+    utils::{check_listen_addresses, node_key_pair, pick_listen_ports},
+    warm_up::run_warm_up,
     KoreApi,
 };
+use std::collections::{BTreeMap, VecDeque};
+#[cfg(any(
+    feature = "leveldb",
+    feature = "sled",
+    feature = "redb",
+    feature = "sqlite"
+))]
 use std::fs;
-#[cfg(feature = "leveldb")]
+use std::panic::AssertUnwindSafe;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "encryption")]
+use crate::database::encrypted::EncryptedManager;
 #[cfg(feature = "leveldb")]
 use crate::database::leveldb::{open_db, LeveldbManager};
+#[cfg(feature = "postgres")]
+use crate::database::postgres::{connection_url, PostgresManager};
+#[cfg(feature = "redb")]
+use crate::database::redb::RedbManager;
+#[cfg(feature = "sled")]
+use crate::database::sled::SledManager;
 #[cfg(feature = "sqlite")]
 use crate::database::sqlite::SqliteManager;
 #[cfg(feature = "sqlite")]
 use crate::utils::split_path;
 
-use kore_base::Node;
+use kore_base::{keys::KeyPair, DatabaseManager, Node};
 
 use async_trait::async_trait;
-use futures::Future;
+use futures::{Future, FutureExt};
+use tokio::sync::{
+    broadcast,
+    mpsc::{unbounded_channel, UnboundedReceiver},
+    watch,
+};
 use tokio_util::sync::CancellationToken;
 
+/// Longest wait for a supervised node to stop, see `DatabaseNode::stop`.
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Kore node trait.
 #[async_trait]
 pub trait KoreNode {
@@ -51,181 +123,865 @@ pub trait KoreNode {
     /// * `shutdown_signal` - Shutdown signal
     ///
     fn bind_with_shutdown(&self, shutdown_signal: impl Future + Send + 'static);
+    /// Get the state of the node, for supervisors, see the `lifecycle` module.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeState` - State the lifecycle events emitted so far lead to
+    ///
+    fn status(&self) -> NodeState;
+    /// Subscribe to the lifecycle events of the node, written to the standard output or not.
+    /// Events emitted while the node is built are seen by subscribing to the `LifecycleEvents`
+    /// given to `KoreNodeBuilder::with_lifecycle_events` instead.
+    ///
+    /// # Returns
+    ///
+    /// * `broadcast::Receiver<LifecycleEvent>` - Events emitted from now on
+    ///
+    fn lifecycle_events(&self) -> broadcast::Receiver<LifecycleEvent>;
 }
 
-/// Kore node with LevelDB database.
-#[cfg(feature = "leveldb")]
-pub struct LevelDBNode {
-    /// Kore API.
-    api: KoreApi,
-    /// Cancellation token.
-    cancellation: CancellationToken,
+/// Builder of Kore nodes.
+/// The database backend is chosen at runtime from `DbSettings`, so callers do not need to know
+/// which database features are enabled. Key loading, listen address checks, metrics registry,
+/// node metrics and prometheus startup are shared by every backend.
+#[derive(Clone)]
+pub struct KoreNodeBuilder {
+    settings: KoreSettings,
+    password: String,
+    lifecycle: Option<LifecycleEvents>,
+    restarts: Option<RestartRecord>,
 }
 
-/// Implementation for `LevelDBNode`.
-#[cfg(feature = "leveldb")]
-impl LevelDBNode {
-    /// Build a new `LevelDBNode`.
+impl KoreNodeBuilder {
+    /// Create a new builder.
     ///
     /// # Arguments
     ///
     /// * `settings` - Kore settings
     /// * `password` - Password to encrypt/decrypt the key pair
     ///
-    /// # Returns
+    pub fn new(settings: KoreSettings, password: &str) -> Self {
+        Self {
+            settings,
+            password: password.to_owned(),
+            lifecycle: None,
+            restarts: None,
+        }
+    }
+
+    /// Write the lifecycle events to another output than the one of `kore.lifecycle_events`,
+    /// e.g. a pipe of the process that embeds the node.
+    ///
+    /// # Arguments
     ///
-    /// * `Result<Self, NodeError>` - `LevelDBNode`
+    /// * `lifecycle` - Output of the events
     ///
-    pub fn build(settings: KoreSettings, password: &str) -> Result<Self, NodeError> {
-        let key_pair = node_key_pair(&settings, password)?;
-        let DbSettings::LevelDB(path) = settings.db;
+    pub fn with_lifecycle_events(mut self, lifecycle: LifecycleEvents) -> Self {
+        self.lifecycle = Some(lifecycle);
+        self
+    }
 
-        if fs::metadata(&path).is_err() {
-            fs::create_dir_all(&path).map_err(|error| {
-                NodeError::InternalApi(format!("Error creating keys directory: {}", error))
-            })?;
+    /// Build the node with the database of the settings.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<DatabaseNode, NodeError>` - Kore node
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Config` - The settings are not valid
+    /// * `NodeError::Keys` - The node key pair could not be loaded
+    /// * `NodeError::Network` - A listen address is not available
+    /// * `NodeError::Bind` - The address of the metrics or of an API cannot be bound
+    /// * `NodeError::Database` - The database could not be opened
+    /// * `NodeError::InternalApi` - Kore Base could not be built
+    ///
+    pub async fn build(mut self) -> Result<DatabaseNode, NodeError> {
+        self.settings.validate()?;
+        init_logging(&self.settings.logging)?;
+        // Before the key pair is loaded, so that the legacy one is used instead of a new one.
+        let mut history = migrate_legacy_data(Path::new(""), &self.settings)?;
+        let key_pair = node_key_pair(&self.settings, &self.password)?;
+        let listen_addresses = self.settings.settings.network.listen_addresses.clone();
+        check_listen_addresses(
+            &mut self.settings.settings.network,
+            &self.settings.listen_fallback_ports,
+        )?;
+        history.extend(
+            listen_addresses
+                .iter()
+                .zip(self.settings.settings.network.listen_addresses.iter())
+                .filter(|(old, new)| old != new)
+                .map(|(old, new)| {
+                    (
+                        NodeHistoryKind::ListenFailover,
+                        format!("{} replaced by {}", old, new),
+                    )
+                }),
+        );
+        pick_listen_ports(&mut self.settings.settings.network)?;
+        if let Some((path, manifest)) = restore_newest(&self.settings.backup, &self.settings.db)? {
+            history.push((
+                NodeHistoryKind::Restored,
+                format!("{} at height {}", path.display(), manifest.height),
+            ));
         }
 
-        let db = open_db(Path::new(&path));
-        let manager = LeveldbManager::new(db);
+        match self.settings.db.clone() {
+            #[cfg(feature = "leveldb")]
+            DbSettings::LevelDB(path) => {
+                create_dir(&path)?;
+                let db = open_db(Path::new(&path));
+                let manager =
+                    LeveldbManager::new(db.clone()).with_batch(self.settings.db_batch.clone());
+                let maintenance = DbMaintenance::LevelDB {
+                    db: db.clone(),
+                    path,
+                };
+                let backup = Some(BackupSource::LevelDB(db));
+                self.start(key_pair, manager, backup, maintenance, history)
+            }
+            #[cfg(feature = "sled")]
+            DbSettings::Sled(path) => {
+                create_dir(&path)?;
+                let db = crate::database::sled::open_db(Path::new(&path))?;
+                let manager =
+                    SledManager::new(db.clone()).with_batch(self.settings.db_batch.clone());
+                let maintenance = DbMaintenance::Sled {
+                    db: db.clone(),
+                    path,
+                };
+                let backup = Some(BackupSource::Sled(db));
+                self.start(key_pair, manager, backup, maintenance, history)
+            }
+            #[cfg(feature = "redb")]
+            DbSettings::Redb(path) => {
+                if let Some(dir) = Path::new(&path)
+                    .parent()
+                    .and_then(Path::to_str)
+                    .filter(|dir| !dir.is_empty())
+                {
+                    create_dir(dir)?;
+                }
+                let db = crate::database::redb::open_db(Path::new(&path))?;
+                let manager =
+                    RedbManager::new(db.clone()).with_batch(self.settings.db_batch.clone());
+                let maintenance = DbMaintenance::Redb {
+                    db: db.clone(),
+                    path: path.clone(),
+                };
+                let backup = Some(BackupSource::Redb { db, path });
+                self.start(key_pair, manager, backup, maintenance, history)
+            }
+            #[cfg(feature = "sqlite")]
+            DbSettings::Sqlite(path) => {
+                let (_, dir) = split_path(&path);
+                create_dir(&dir)?;
+                let manager = SqliteManager::new(&path)
+                    .with_readers(self.settings.db_read_pool_size)
+                    .with_pragmas(&self.settings.db_options)
+                    .with_batch(self.settings.db_batch.clone());
+                let maintenance = DbMaintenance::Sqlite(path.clone());
+                let backup = Some(BackupSource::Sqlite(path));
+                self.start(key_pair, manager, backup, maintenance, history)
+            }
+            #[cfg(feature = "postgres")]
+            DbSettings::Postgres { url, pool_size } => {
+                let url = connection_url(&url, &self.settings.db_options);
+                let manager = PostgresManager::new(&url, pool_size)?
+                    .with_batch(self.settings.db_batch.clone());
+                let maintenance = DbMaintenance::Postgres(manager.clone());
+                self.start(key_pair, manager, None, maintenance, history)
+            }
+        }
+    }
 
+    /// Start Kore Base over the database manager.
+    /// `backup` is the database copied by the backups, none when the node cannot copy it, and
+    /// `maintenance` the one reported and compacted by the API.
+    /// `history` holds what happened before the start, such as listen addresses replaced by a
+    /// fallback port or a restored backup.
+    fn start<M, C>(
+        self,
+        key_pair: KeyPair,
+        manager: M,
+        backup: Option<BackupSource>,
+        maintenance: DbMaintenance,
+        mut history: Vec<(NodeHistoryKind, String)>,
+    ) -> Result<DatabaseNode, NodeError>
+    where
+        M: DatabaseManager<C> + 'static,
+        C: BatchCollection + 'static,
+    {
         let mut registry = <Registry>::default();
+        let metrics = NodeMetrics::register(&mut registry);
+        if let Some(restarts) = &self.restarts {
+            for (cause, count) in &restarts.counts {
+                metrics.count_restarts(cause, *count);
+            }
+            history.push((NodeHistoryKind::Restarted, restarts.last.clone()));
+        }
+        let lifecycle = self
+            .lifecycle
+            .clone()
+            .unwrap_or_else(|| LifecycleEvents::stdout(self.settings.lifecycle_events));
+        lifecycle.emit(LifecycleEvent::Starting {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+        });
+        #[cfg(feature = "encryption")]
+        let manager = {
+            let secret = if self.settings.db_encryption_key.is_empty() {
+                &self.password
+            } else {
+                &self.settings.db_encryption_key
+            };
+            EncryptedManager::new(
+                manager,
+                self.settings.db_encryption.then_some(secret.as_str()),
+                self.settings.keys.iterations,
+            )?
+        };
+        let manager = MeteredManager::new(manager, metrics.clone());
+        let store = NodeStore::new(Arc::new(manager.create_collection("node")), "node");
+        let subject_index =
+            SubjectIndex::new(Arc::new(manager.create_collection(INDEX_COLLECTION)))?;
+        let archival = &self.settings.archival;
+        let archival = if archival.destination.is_empty() {
+            None
+        } else {
+            Some(Archival::new(
+                open_archive(&archival.destination)?,
+                Arc::new(manager.create_collection(EVENT_COLLECTION)),
+                store.scope(ARCHIVAL_SCOPE),
+                archival.clone(),
+            )?)
+        };
         let cancellation = CancellationToken::new();
+        let tasks = NodeTasks::new(cancellation.clone());
+        let commits = LedgerCommits::default();
+        let manager = LedgerManager::new(manager, archival.as_ref().map(Archival::reader))
+            .with_commits(commits.clone())
+            .with_proofs(LedgerProofs::new(store.scope(PROOFS_SCOPE)))
+            .with_lease(tasks.lease());
+
+        // The settings kept for reloads are left as configured.
+        let mut settings = self.settings.settings.clone();
+        // Groups are probed and dialed at the addresses their names resolve to.
+        let mut bootstrap = self.settings.bootstrap.clone();
+        for group in bootstrap.groups.iter_mut() {
+            group.boot_nodes = resolve_boot_nodes(std::mem::take(&mut group.boot_nodes)).await;
+        }
+        let selection = select_boot_group(&mut settings.network, &bootstrap).await;
+        if let Some(label) = &selection.label {
+            metrics.set_bootstrap_group(label);
+            if !selection.skipped.is_empty() {
+                let detail = format!(
+                    "{} unreachable, bootstrap from {}",
+                    selection.skipped.join(", "),
+                    label
+                );
+                lifecycle.emit(LifecycleEvent::degraded("bootstrap", detail.as_str()));
+                history.push((NodeHistoryKind::BootstrapFailover, detail));
+            }
+        } else if !bootstrap.groups.is_empty() {
+            lifecycle.emit(LifecycleEvent::degraded(
+                "bootstrap",
+                "no boot node group reachable",
+            ));
+        }
+
+        if let Err(error) = learn_boot_nodes(&store, &mut settings.network) {
+            log::warn!("Stored boot nodes not applied: {}", error);
+        }
+        let configured_boot_nodes = settings.network.routing.boot_nodes();
+        let boot_nodes = resolve_boot_nodes(configured_boot_nodes.clone()).await;
+        settings.network.routing = with_boot_nodes(&settings.network.routing, boot_nodes);
+        let boot_nodes = settings.network.routing.boot_nodes();
+        let boot_nodes_in_use = boot_nodes.clone();
 
         let api = Node::build(
-            settings.settings.clone(),
+            settings,
             key_pair.clone(),
             &mut registry,
             manager,
             cancellation.clone(),
-            password,
+            &self.password,
         )
         .map_err(|_| NodeError::InternalApi("Node build error".to_owned()))?;
+        lifecycle.emit(LifecycleEvent::Listening {
+            addresses: self.settings.settings.network.listen_addresses.clone(),
+        });
+
+        let access_log = AccessLogger::new(self.settings.access_log.clone());
+        let authenticator = Authenticator::new(self.settings.auth.clone());
+        #[cfg(feature = "prometheus")]
+        let prometheus = run_prometheus(
+            registry,
+            &self.settings.prometheus,
+            access_log.clone(),
+            authenticator.clone(),
+        )
+        .inspect_err(|_| cancellation.cancel())?;
+        #[cfg(feature = "prometheus")]
+        if let Some(address) = prometheus.local_addr() {
+            lifecycle.emit(LifecycleEvent::MetricsBound {
+                address: address.to_string(),
+            });
+        }
+        #[cfg(feature = "metrics-push")]
+        if self.settings.metrics_push.is_enabled() {
+            run_metrics_push(
+                prometheus.clone(),
+                self.settings.metrics_push.clone(),
+                &tasks,
+            );
+        }
 
+        let node = &self.settings.settings.node;
+        let contracts = Contracts::new(&node.smartcontracts_directory, lifecycle.clone());
+        if let Err(error) = contracts.reload() {
+            log::warn!("{}", error);
+        }
+        run_contracts_watcher(contracts.clone(), &tasks);
+        let api = KoreApi::new(
+            api,
+            key_pair,
+            node.digest_derivator,
+            node.key_derivator,
+            store,
+        )
+        .with_subject_quota(self.settings.subject_quota.clone())
+        .with_signing_policies(self.settings.signing_policies.clone())
+        .with_approval_rules(self.settings.approval_rules.clone())
+        .with_signature_check(self.settings.signature_check.clone())
+        .with_api_calls(self.settings.api.clone())
+        .with_limits(self.settings.limits.clone())
+        .with_access_log(access_log.clone())
+        .with_metrics(metrics.clone())
+        .with_feature_flags(self.settings.features.clone())
+        .with_services(self.settings.services.clone())
+        .with_db_ttl(self.settings.db_ttl.clone())
+        .with_network_addresses(
+            self.settings.settings.network.listen_addresses.clone(),
+            self.settings.settings.network.external_addresses.clone(),
+        )
+        .with_peers(boot_nodes)
+        .with_maintenance(maintenance)
+        .with_contracts(contracts)
+        .with_subject_index(subject_index);
+        let api = match backup {
+            Some(source) => api.with_backup(source),
+            None => api,
+        };
+        let api = match archival {
+            Some(archival) => api.with_archival(archival),
+            None => api,
+        };
         #[cfg(feature = "prometheus")]
-        run_prometheus(registry, &settings.prometheus);
-
-        let settings = settings.settings.node;
-        Ok(Self {
-            api: KoreApi::new(
-                api,
-                key_pair,
-                settings.digest_derivator,
-                settings.key_derivator,
-            ),
+        api.set_metrics_address(prometheus.local_addr().map(|address| address.to_string()));
+        // Rotation and key versions work on key files only.
+        let api = match self.settings.keys_backend {
+            KeysBackend::File => {
+                api.with_key_files(&self.settings.keys_path, self.settings.keys.clone())
+            }
+            KeysBackend::Pkcs11 | KeysBackend::Vault => api,
+        };
+        api.set_timestamp_format(self.settings.timestamp_format);
+        api.expire_legacy_entries();
+        api.record_start();
+        for (kind, detail) in history {
+            api.record_history(kind, &detail);
+        }
+        let (served, readiness) = watch::channel(None);
+        if self.settings.warm_up.is_enabled() {
+            let (api, settings, authenticator, node_tasks, lifecycle) = (
+                api.clone(),
+                self.settings.clone(),
+                authenticator.clone(),
+                tasks.clone(),
+                lifecycle.clone(),
+            );
+            tasks.spawn(async move {
+                run_warm_up(&api, &settings.warm_up).await;
+                let result = serve_apis(&api, &settings, &authenticator, &node_tasks);
+                match &result {
+                    Ok(()) => lifecycle.emit(ready(&api)),
+                    Err(error) => {
+                        // The node cannot run without its APIs, as if they failed on build.
+                        log::error!("APIs not served after the warm-up: {}", error);
+                        lifecycle.emit(LifecycleEvent::degraded("api", error.to_string()));
+                        node_tasks.fail(format!("APIs not served after the warm-up: {}", error));
+                    }
+                }
+                served.send_replace(Some(result));
+            });
+        } else {
+            serve_apis(&api, &self.settings, &authenticator, &tasks)?;
+            lifecycle.emit(ready(&api));
+            served.send_replace(Some(Ok(())));
+        }
+        #[cfg(feature = "webhooks")]
+        if !self.settings.webhooks.urls.is_empty() {
+            run_webhooks(api.clone(), self.settings.webhooks.clone(), &tasks);
+        }
+        #[cfg(feature = "services")]
+        if !self.settings.services.peers.is_empty() {
+            run_peer_services(api.clone(), self.settings.services.clone(), &tasks);
+        }
+        #[cfg(feature = "replication")]
+        if self.settings.replication.is_enabled() {
+            run_replication(api.clone(), self.settings.replication.clone(), &tasks);
+        }
+        run_schedules(api.clone(), self.settings.schedules.clone(), &tasks);
+        #[cfg(feature = "soak")]
+        if self.settings.soak.is_enabled() {
+            run_soak(
+                api.clone(),
+                self.settings.soak.clone(),
+                metrics.clone(),
+                &tasks,
+            );
+        }
+        if !bootstrap.groups.is_empty() {
+            run_boot_group_health(metrics, lifecycle.clone(), bootstrap.clone(), &tasks);
+        }
+        if !bootstrap.dns_refresh.is_zero() {
+            run_dns_refresh(
+                api.clone(),
+                configured_boot_nodes,
+                boot_nodes_in_use,
+                bootstrap.dns_refresh,
+                &tasks,
+            );
+        }
+        run_sweeper(api.clone(), self.settings.db_ttl.sweep_interval, &tasks);
+        run_usage_flush(api.clone(), &tasks);
+        run_approvals_gauge(api.clone(), &tasks);
+        run_auto_approval(api.clone(), &tasks);
+        run_subject_indexer(api.clone(), commits, &tasks);
+        if self.settings.backup.is_scheduled() {
+            run_backups(api.clone(), self.settings.backup.clone(), &tasks);
+        }
+        if self.settings.archival.is_scheduled() {
+            run_archival(api.clone(), self.settings.archival.clone(), &tasks);
+        }
+        run_stop_events(lifecycle.clone(), tasks.clone());
+        Ok(DatabaseNode {
+            live: LiveSettings {
+                settings: Arc::new(Mutex::new(self.settings)),
+                api: api.clone(),
+                access_log,
+                authenticator,
+                lifecycle,
+                #[cfg(feature = "prometheus")]
+                prometheus,
+            },
+            api,
             cancellation,
+            tasks,
+            readiness,
         })
     }
 }
 
-/// Implementation for `KoreNode` for `LevelDBNode`.
+/// Start the REST and gRPC servers enabled in the settings.
+///
+/// # Errors
+///
+/// * `NodeError::Bind` - An address cannot be bound.
+/// * Any error of `run_grpc`.
+///
+#[cfg_attr(
+    not(any(feature = "http-api", feature = "grpc")),
+    allow(unused_variables)
+)]
+fn serve_apis(
+    api: &KoreApi,
+    settings: &KoreSettings,
+    authenticator: &Authenticator,
+    tasks: &NodeTasks,
+) -> Result<(), NodeError> {
+    #[cfg(feature = "http-api")]
+    if !settings.http_api.is_empty() {
+        run_http_api(
+            api.clone(),
+            &settings.http_api,
+            &settings.api_auth,
+            authenticator.clone(),
+            tasks,
+        )?;
+    }
+    #[cfg(feature = "grpc")]
+    if !settings.grpc.listen.is_empty() {
+        run_grpc(
+            api.clone(),
+            &settings.grpc,
+            &settings.api_auth,
+            authenticator.clone(),
+            tasks,
+        )?;
+    }
+    Ok(())
+}
+
+/// Event of a node whose APIs are served.
+fn ready(api: &KoreApi) -> LifecycleEvent {
+    LifecycleEvent::Ready {
+        controller_id: api.get_controller_id(),
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+    }
+}
+
+/// Emit `shutdown` once the node is cancelled, unless a shutdown signal did, and `stopped` once
+/// its tasks returned and Kore Base released the database, see `NodeTasks::stopped`. The task
+/// is not one of the node, as it waits for them.
+fn run_stop_events(lifecycle: LifecycleEvents, tasks: NodeTasks) {
+    tokio::spawn(async move {
+        tasks.token().cancelled().await;
+        if lifecycle.state() != NodeState::ShuttingDown {
+            lifecycle.emit(LifecycleEvent::Shutdown {
+                reason: "cancelled".to_owned(),
+            });
+        }
+        tasks.stopped().await;
+        // Unless the supervisor started the node again in the meantime.
+        if lifecycle.state() == NodeState::ShuttingDown {
+            lifecycle.emit(LifecycleEvent::Stopped);
+        }
+    });
+}
+
+/// Create the directory of a local database.
+#[cfg(any(
+    feature = "leveldb",
+    feature = "sled",
+    feature = "redb",
+    feature = "sqlite"
+))]
+fn create_dir(path: &str) -> Result<(), NodeError> {
+    if fs::metadata(path).is_err() {
+        fs::create_dir_all(path).map_err(|error| {
+            NodeError::InternalApi(format!("Error creating database directory: {}", error))
+        })?;
+    }
+    Ok(())
+}
+
+/// Settings of a running node and the services that apply them.
+#[derive(Clone)]
+struct LiveSettings {
+    settings: Arc<Mutex<KoreSettings>>,
+    api: KoreApi,
+    access_log: AccessLogger,
+    authenticator: Authenticator,
+    lifecycle: LifecycleEvents,
+    #[cfg(feature = "prometheus")]
+    prometheus: PrometheusServer,
+}
+
+impl LiveSettings {
+    /// Apply the reloadable settings that changed, reporting every change.
+    fn reload(&self, new: KoreSettings) -> Vec<SettingChange> {
+        let Ok(mut live) = self.settings.lock() else {
+            return vec![];
+        };
+        let changes: Vec<SettingChange> = diff_settings(&live, &new)
+            .into_iter()
+            .map(|key| {
+                let mut error = None;
+                let applied = match key {
+                    #[cfg(feature = "prometheus")]
+                    "prometheus" => match self.prometheus.rebind(&new.prometheus) {
+                        Ok(address) => {
+                            if let Some(address) = &address {
+                                self.lifecycle.emit(LifecycleEvent::MetricsBound {
+                                    address: address.to_string(),
+                                });
+                            }
+                            self.api
+                                .set_metrics_address(address.map(|address| address.to_string()));
+                            live.prometheus = new.prometheus.clone();
+                            true
+                        }
+                        // The previous address keeps serving the metrics.
+                        Err(rebind) => {
+                            error = Some(rebind.to_string());
+                            false
+                        }
+                    },
+                    "auth" => {
+                        self.authenticator.set_settings(new.auth.clone());
+                        live.auth = new.auth.clone();
+                        true
+                    }
+                    "subject_quota" => {
+                        self.api.set_subject_quota(new.subject_quota.clone());
+                        live.subject_quota = new.subject_quota.clone();
+                        true
+                    }
+                    "signing_policies" => {
+                        self.api.set_signing_policies(new.signing_policies.clone());
+                        live.signing_policies = new.signing_policies.clone();
+                        true
+                    }
+                    "approval_rules" => {
+                        self.api.set_approval_rules(new.approval_rules.clone());
+                        live.approval_rules = new.approval_rules.clone();
+                        true
+                    }
+                    "signature_check" => {
+                        self.api.set_signature_check(new.signature_check.clone());
+                        live.signature_check = new.signature_check.clone();
+                        true
+                    }
+                    "api" => {
+                        self.api.set_api_calls(new.api.clone());
+                        live.api = new.api.clone();
+                        true
+                    }
+                    "limits" => {
+                        self.api.set_limits(new.limits.clone());
+                        live.limits = new.limits.clone();
+                        true
+                    }
+                    "access_log" => {
+                        self.access_log.set_settings(new.access_log.clone());
+                        live.access_log = new.access_log.clone();
+                        true
+                    }
+                    "timestamp_format" => {
+                        self.api.set_timestamp_format(new.timestamp_format);
+                        live.timestamp_format = new.timestamp_format;
+                        true
+                    }
+                    "features" => {
+                        self.api.set_feature_flags(new.features.clone());
+                        live.features = new.features.clone();
+                        true
+                    }
+                    _ => false,
+                };
+                SettingChange {
+                    key: key.to_owned(),
+                    applied,
+                    error,
+                }
+            })
+            .collect();
+        if !changes.is_empty() {
+            let detail = changes
+                .iter()
+                .map(|change| match (change.applied, &change.error) {
+                    (true, _) => change.key.clone(),
+                    (false, Some(error)) => format!("{} (failed: {})", change.key, error),
+                    (false, None) => format!("{} (on restart)", change.key),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            self.api
+                .record_history(NodeHistoryKind::ConfigReloaded, &detail);
+        }
+        changes
+    }
+}
+
+/// Kore node over the database selected in its settings.
+pub struct DatabaseNode {
+    /// Kore API.
+    api: KoreApi,
+    /// Cancellation token.
+    cancellation: CancellationToken,
+    /// Tasks of the node.
+    tasks: NodeTasks,
+    /// Live settings.
+    live: LiveSettings,
+    /// Result of serving the APIs, none until they are served or fail.
+    readiness: watch::Receiver<Option<Result<(), NodeError>>>,
+}
+
+/// Kore node with LevelDB database.
 #[cfg(feature = "leveldb")]
-#[async_trait]
-impl KoreNode for LevelDBNode {
-    /// Get the Kore API.
+pub type LevelDBNode = DatabaseNode;
+
+/// Kore node with SQLite database.
+#[cfg(feature = "sqlite")]
+pub type SqliteNode = DatabaseNode;
+
+/// Kore node with PostgreSQL database.
+#[cfg(feature = "postgres")]
+pub type PostgresNode = DatabaseNode;
+
+/// Kore node with sled database.
+#[cfg(feature = "sled")]
+pub type SledNode = DatabaseNode;
+
+/// Kore node with redb database.
+#[cfg(feature = "redb")]
+pub type RedbNode = DatabaseNode;
+
+/// Implementation for `DatabaseNode`.
+impl DatabaseNode {
+    /// Build a new node, same as `KoreNodeBuilder::new(settings, password).build().await`.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Kore settings
+    /// * `password` - Password to encrypt/decrypt the key pair
     ///
     /// # Returns
     ///
-    /// * `&KoreApi` - Kore API
+    /// * `Result<Self, NodeError>` - Kore node
     ///
-    fn api(&self) -> &KoreApi {
-        &self.api
+    pub async fn build(settings: KoreSettings, password: &str) -> Result<Self, NodeError> {
+        KoreNodeBuilder::new(settings, password).build().await
     }
 
-    /// Get the Kore CancellationToken.
+    /// Reload the settings of the running node.
+    /// Reloadable settings (see `RELOADABLE_SETTINGS`) are applied; the rest of the changes are
+    /// reported with `applied` false and take effect on the next start.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - New settings
     ///
     /// # Returns
     ///
-    /// * `&CancellationToken` - Cancellation Token
+    /// * `Vec<SettingChange>` - Settings that changed
     ///
-    fn token(&self) -> &CancellationToken {
-        &self.cancellation
+    pub fn reload(&self, settings: KoreSettings) -> Vec<SettingChange> {
+        self.live.reload(settings)
     }
 
-    /// Bind the node to the provided shutdown signal.
+    /// Watch the configuration file and reload the node on every change, until the node is
+    /// cancelled.
     ///
     /// # Arguments
     ///
-    /// * `shutdown_signal` - Shutdown signal
+    /// * `sources` - Sources of the settings, its file is the one watched
     ///
-    fn bind_with_shutdown(&self, shutdown_signal: impl Future + Send + 'static) {
-        let cancellation_token = self.cancellation.clone();
-        tokio::spawn(async move {
-            shutdown_signal.await;
-            log::info!("Shutdown signal received");
-            cancellation_token.cancel();
+    /// # Returns
+    ///
+    /// * `UnboundedReceiver<ConfigEvent>` - Changes and errors of each reload
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InternalApi` - The file cannot be watched
+    ///
+    pub fn watch_config(
+        &self,
+        sources: ConfigSources,
+    ) -> Result<UnboundedReceiver<ConfigEvent>, NodeError> {
+        let (watcher, mut reloads) = ConfigWatcher::new(sources)?;
+        let (sender, receiver) = unbounded_channel();
+        let live = self.live.clone();
+        let cancellation = self.cancellation.clone();
+        self.tasks.spawn(async move {
+            let _watcher = watcher;
+            loop {
+                let reload = tokio::select! {
+                    _ = cancellation.cancelled() => break,
+                    reload = reloads.recv() => reload,
+                };
+                let events = match reload {
+                    Some(Ok(settings)) => live
+                        .reload(settings)
+                        .into_iter()
+                        .map(|change| {
+                            match (change.applied, &change.error) {
+                                (true, _) => log::info!("Setting {} reloaded", change.key),
+                                (false, Some(error)) => {
+                                    log::error!("Setting {} not applied: {}", change.key, error)
+                                }
+                                (false, None) => {
+                                    log::warn!(
+                                        "Setting {} changed, restart to apply it",
+                                        change.key
+                                    )
+                                }
+                            }
+                            ConfigEvent::Changed(change)
+                        })
+                        .collect(),
+                    Some(Err(error)) => {
+                        log::error!("Config not reloaded: {}", error);
+                        vec![ConfigEvent::Error(error.to_string())]
+                    }
+                    None => break,
+                };
+                for event in events {
+                    let _ = sender.send(event);
+                }
+            }
         });
+        Ok(receiver)
     }
-}
 
-/// Kore node with SQLite database.
-#[cfg(feature = "sqlite")]
-pub struct SqliteNode {
-    /// Kore API.
-    api: KoreApi,
-    /// Cancellation token.
-    cancellation: CancellationToken,
-}
+    /// Wait until the APIs of the node are served, after the warm-up if any.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Cancelled` - The node was cancelled before its APIs were served.
+    /// * Any error of serving the APIs, which also fails the node, see `NodeTasks::fail`.
+    ///
+    pub async fn ready(&self) -> Result<(), NodeError> {
+        let mut readiness = self.readiness.clone();
+        tokio::select! {
+            served = readiness.wait_for(Option::is_some) => match served {
+                Ok(served) => served.clone().unwrap_or(Err(NodeError::Cancelled)),
+                Err(_) => Err(NodeError::Cancelled),
+            },
+            _ = self.cancellation.cancelled() => Err(NodeError::Cancelled),
+        }
+    }
 
-/// Implementation for `SqliteNode`.
-#[cfg(feature = "sqlite")]
-impl SqliteNode {
-    /// Build a new `SqliteNode`.
+    /// Cancel the node and wait until its tasks return and Kore Base releases the database, see
+    /// `NodeTasks::stopped`, for at most `timeout`.
     ///
     /// # Arguments
     ///
-    /// * `settings` - Kore settings
-    /// * `password` - Password to encrypt/decrypt the key pair
+    /// * `timeout` - Longest wait
     ///
     /// # Returns
     ///
-    /// * `Result<Self, NodeError>` - `SqliteNode`
+    /// * `bool` - Whether the node stopped within `timeout`
     ///
-    pub fn build(settings: KoreSettings, password: &str) -> Result<Self, NodeError> {
-        let key_pair = node_key_pair(&settings, password)?;
-        let DbSettings::Sqlite(path) = settings.db;
-        let (_, all_path) = split_path(&path);
-        if fs::metadata(&all_path).is_err() {
-            fs::create_dir_all(&all_path).map_err(|error| {
-                NodeError::InternalApi(format!("Error creating keys directory: {}", error))
-            })?;
-        }
-
-        let manager = SqliteManager::new(&path);
-
-        let mut registry = <Registry>::default();
-
-        let cancellation = CancellationToken::new();
-        let api = Node::build(
-            settings.settings.clone(),
-            key_pair.clone(),
-            &mut registry,
-            manager,
-            cancellation.clone(),
-            password,
-        )
-        .map_err(|_| NodeError::InternalApi("Node build error".to_owned()))?;
+    pub async fn stop(self, timeout: Duration) -> bool {
+        let tasks = self.tasks.clone();
+        self.cancellation.cancel();
+        // The API held by the node keeps the database open too.
+        drop(self);
+        tokio::time::timeout(timeout, tasks.stopped()).await.is_ok()
+    }
 
-        #[cfg(feature = "prometheus")]
-        run_prometheus(registry, &settings.prometheus);
-
-        let settings = settings.settings.node;
-        Ok(Self {
-            api: KoreApi::new(
-                api,
-                key_pair,
-                settings.digest_derivator,
-                settings.key_derivator,
-            ),
-            cancellation,
-        })
+    /// Write a support bundle with the settings in effect, to attach to bug reports, see the
+    /// `support` module.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Archive to write (`tar.gz`)
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InternalApi` - The archive could not be written
+    ///
+    pub async fn support_bundle(&self, path: &Path) -> Result<(), NodeError> {
+        let settings = self
+            .live
+            .settings
+            .lock()
+            .map_err(|_| NodeError::InternalApi("Settings unavailable".to_owned()))?
+            .clone();
+        write_support_bundle(&self.api, &settings, path).await?;
+        self.api
+            .record_history(NodeHistoryKind::SupportBundle, &path.display().to_string());
+        Ok(())
     }
 }
 
-/// Implementation for `KoreNode` for `SqliteNode`.
-#[cfg(feature = "sqlite")]
+/// Implementation for `KoreNode` for `DatabaseNode`.
 #[async_trait]
-impl KoreNode for SqliteNode {
+impl KoreNode for DatabaseNode {
     /// Get the Kore API.
     ///
     /// # Returns
@@ -254,12 +1010,241 @@ impl KoreNode for SqliteNode {
     ///
     fn bind_with_shutdown(&self, shutdown_signal: impl Future + Send + 'static) {
         let cancellation_token = self.cancellation.clone();
+        let api = self.api.clone();
+        let lifecycle = self.live.lifecycle.clone();
         tokio::spawn(async move {
             shutdown_signal.await;
             log::info!("Shutdown signal received");
+            lifecycle.emit(LifecycleEvent::Shutdown {
+                reason: "signal".to_owned(),
+            });
+            api.record_history(NodeHistoryKind::Stopped, "shutdown signal");
             cancellation_token.cancel();
         });
     }
+
+    /// Get the state of the node.
+    ///
+    /// # Returns
+    ///
+    /// * `NodeState` - Current state
+    ///
+    fn status(&self) -> NodeState {
+        self.live.lifecycle.state()
+    }
+
+    /// Subscribe to the lifecycle events of the node.
+    ///
+    /// # Returns
+    ///
+    /// * `broadcast::Receiver<LifecycleEvent>` - Events emitted from now on
+    ///
+    fn lifecycle_events(&self) -> broadcast::Receiver<LifecycleEvent> {
+        self.live.lifecycle.subscribe()
+    }
+}
+
+/// Why the supervisor restarts a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeFailure {
+    /// A task of the node panicked, see `NodeTasks`.
+    Panic,
+    /// The node was cancelled without a shutdown signal, by Kore Base or one of its tasks.
+    Fatal,
+    /// The node could not be built again after a failure, e.g. its database was still locked.
+    Build,
+}
+
+impl NodeFailure {
+    /// Label of the failure in the metrics.
+    pub fn cause(&self) -> &'static str {
+        match self {
+            NodeFailure::Panic => "panic",
+            NodeFailure::Fatal => "fatal",
+            NodeFailure::Build => "build",
+        }
+    }
+}
+
+/// Restarts done so far, handed to each node the supervisor builds.
+#[derive(Debug, Clone, Default)]
+struct RestartRecord {
+    /// Restarts by cause.
+    counts: BTreeMap<&'static str, u64>,
+    /// Failure that led to the last restart.
+    last: String,
+}
+
+/// Restarts allowed by `kore.supervisor` and the wait before each one.
+#[derive(Debug)]
+struct RestartPolicy {
+    settings: SupervisorSettings,
+    /// Restarts within the window, oldest first.
+    recent: VecDeque<Instant>,
+}
+
+impl RestartPolicy {
+    fn new(settings: SupervisorSettings) -> Self {
+        Self {
+            settings,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Wait before a restart at `now`, doubled for each restart within the window; none when
+    /// `max_restarts` were already done within it.
+    fn next_delay(&mut self, now: Instant) -> Option<Duration> {
+        while self
+            .recent
+            .front()
+            .is_some_and(|restart| now.saturating_duration_since(*restart) >= self.settings.window)
+        {
+            self.recent.pop_front();
+        }
+        if self.recent.len() >= self.settings.max_restarts as usize {
+            return None;
+        }
+        let factor = 2u32.saturating_pow(self.recent.len() as u32);
+        self.recent.push_back(now);
+        Some(
+            self.settings
+                .backoff
+                .saturating_mul(factor)
+                .min(self.settings.max_backoff),
+        )
+    }
+}
+
+/// Stop a supervised node, warning when it did not stop within `STOP_TIMEOUT`.
+async fn stop(node: DatabaseNode) {
+    if !node.stop(STOP_TIMEOUT).await {
+        log::warn!(
+            "Node not stopped within {}",
+            humantime::format_duration(STOP_TIMEOUT)
+        );
+    }
+}
+
+/// Runs a node and rebuilds it after a panic of one of its tasks or a fatal error, see
+/// `kore.supervisor`. The failed node is cancelled and stopped, see `DatabaseNode::stop`, and
+/// after a backoff the builder opens the database again and builds a new node with the same
+/// settings. Restarts are counted by cause in the
+/// `node_restarts` metric and recorded in the node history.
+pub struct Supervisor {
+    builder: KoreNodeBuilder,
+    settings: SupervisorSettings,
+}
+
+impl Supervisor {
+    /// Create a supervisor with the `kore.supervisor` settings of the builder.
+    ///
+    /// # Arguments
+    ///
+    /// * `builder` - Builder of every node
+    ///
+    pub fn new(builder: KoreNodeBuilder) -> Self {
+        let settings = builder.settings.supervisor.clone();
+        Self { builder, settings }
+    }
+
+    /// Run nodes until the shutdown signal, restarting them while the policy allows it.
+    ///
+    /// # Arguments
+    ///
+    /// * `shutdown_signal` - Shutdown signal
+    /// * `on_start` - Called with every node built, e.g. to watch the configuration
+    ///
+    /// # Errors
+    ///
+    /// * Any error of `KoreNodeBuilder::build` for the first node, or of `on_start`
+    /// * `NodeError::InternalApi` - The node failed more than `max_restarts` times in `window`
+    ///
+    pub async fn run<F>(
+        self,
+        shutdown_signal: impl Future + Send + 'static,
+        mut on_start: F,
+    ) -> Result<(), NodeError>
+    where
+        F: FnMut(&DatabaseNode) -> Result<(), NodeError>,
+    {
+        let lifecycle = self
+            .builder
+            .lifecycle
+            .clone()
+            .unwrap_or_else(|| LifecycleEvents::stdout(self.builder.settings.lifecycle_events));
+        let mut builder = self.builder.with_lifecycle_events(lifecycle.clone());
+        let shutdown = CancellationToken::new();
+        let signal = shutdown.clone();
+        tokio::spawn(async move {
+            shutdown_signal.await;
+            signal.cancel();
+        });
+        let mut policy = RestartPolicy::new(self.settings);
+        let mut record = RestartRecord::default();
+        loop {
+            let first = builder.restarts.is_none();
+            let built = AssertUnwindSafe(builder.clone().build())
+                .catch_unwind()
+                .await
+                .unwrap_or_else(|payload| Err(NodeError::InternalApi(panic_message(payload))));
+            let (failure, reason) = match built {
+                Ok(node) => {
+                    if let Err(error) = on_start(&node) {
+                        stop(node).await;
+                        return Err(error);
+                    }
+                    let token = node.token().clone();
+                    tokio::select! {
+                        _ = shutdown.cancelled() => {
+                            log::info!("Shutdown signal received");
+                            lifecycle.emit(LifecycleEvent::Shutdown {
+                                reason: "signal".to_owned(),
+                            });
+                            node.api()
+                                .record_history(NodeHistoryKind::Stopped, "shutdown signal");
+                            stop(node).await;
+                            return Ok(());
+                        }
+                        _ = token.cancelled() => {}
+                    }
+                    let failure = match (node.tasks.panic(), node.tasks.failure()) {
+                        (Some(message), _) => (NodeFailure::Panic, message),
+                        (None, Some(error)) => (NodeFailure::Fatal, error),
+                        (None, None) => (NodeFailure::Fatal, "node cancelled".to_owned()),
+                    };
+                    // Built again once the failed node released the database.
+                    stop(node).await;
+                    failure
+                }
+                Err(error) if first => return Err(error),
+                Err(error) => (NodeFailure::Build, error.to_string()),
+            };
+            let Some(delay) = policy.next_delay(Instant::now()) else {
+                return Err(NodeError::InternalApi(format!(
+                    "node failed more than {} times in {}, last: {}",
+                    policy.settings.max_restarts,
+                    humantime::format_duration(policy.settings.window),
+                    reason
+                )));
+            };
+            log::error!(
+                "Node failed ({}), restart in {}",
+                reason,
+                humantime::format_duration(delay)
+            );
+            lifecycle.emit(LifecycleEvent::degraded(
+                "supervisor",
+                format!("{}: {}", failure.cause(), reason),
+            ));
+            *record.counts.entry(failure.cause()).or_default() += 1;
+            record.last = format!("after {}: {}", failure.cause(), reason);
+            builder.restarts = Some(record.clone());
+            tokio::select! {
+                _ = shutdown.cancelled() => return Ok(()),
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -274,20 +1259,22 @@ pub mod tests {
     #[cfg(feature = "leveldb")]
     #[tokio::test]
     async fn test_leveldb_node() {
-        let node = create_leveldb_node(100, vec![]);
+        let node = create_leveldb_node(100, vec![]).await;
         assert!(node.is_ok());
     }
 
     #[cfg(feature = "leveldb")]
-    pub fn create_leveldb_node(
+    pub async fn create_leveldb_node(
         node: u32,
         boot_nodes: Vec<RoutingNode>,
     ) -> Result<LevelDBNode, NodeError> {
         let tempdir = tempfile::tempdir().unwrap();
         let path = tempdir.path().join(format!("keys{}", node));
         let password = format!("password{}", node);
-        let mut settings = KoreSettings::default();
-        settings.prometheus = format!("127.0.0.1:3{}", node);
+        let mut settings = KoreSettings {
+            prometheus: "127.0.0.1:0".to_owned(),
+            ..Default::default()
+        };
         settings.settings.network = NetworkConfig::new(
             NodeType::Bootstrap,
             vec![format!("/ip4/127.0.0.1/tcp/{}", 50000 + node)],
@@ -300,35 +1287,134 @@ pub mod tests {
             path.to_str().unwrap().to_owned()
         ));
         settings.keys_path = path.to_str().unwrap().to_owned();
-        LevelDBNode::build(settings, &password)
+        // Key files are written on every test node, keep their encryption cheap.
+        settings.keys.iterations = crate::utils::MIN_PBKDF2_ITERATIONS;
+        LevelDBNode::build(settings, &password).await
     }
 
     #[cfg(feature = "leveldb")]
-    pub fn export_leveldb_api(node: u32, known_nodes: Vec<RoutingNode>) -> KoreApi {
-        let node = create_leveldb_node(node, known_nodes);
+    pub async fn export_leveldb_api(node: u32, known_nodes: Vec<RoutingNode>) -> KoreApi {
+        let node = create_leveldb_node(node, known_nodes).await;
         assert!(node.is_ok());
         let node = node.unwrap();
         node.bind_with_shutdown(signal::ctrl_c());
         node.api().clone()
     }
 
+    #[test]
+    fn test_restart_policy() {
+        let mut policy = RestartPolicy::new(SupervisorSettings {
+            enabled: true,
+            max_restarts: 3,
+            window: Duration::from_secs(60),
+            backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(30),
+        });
+        let start = Instant::now();
+        let delays = (0..4).map(|_| policy.next_delay(start)).collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            vec![
+                Some(Duration::from_secs(10)),
+                Some(Duration::from_secs(20)),
+                Some(Duration::from_secs(30)),
+                None
+            ]
+        );
+        // Restarts out of the window no longer count.
+        let later = start + Duration::from_secs(60);
+        assert_eq!(policy.next_delay(later), Some(Duration::from_secs(10)));
+        assert_eq!(NodeFailure::Panic.cause(), "panic");
+    }
+
     #[cfg(feature = "sqlite")]
     #[tokio::test]
     async fn test_sqlite_node() {
-        let node = create_sqlite_node(200, vec![]);
-        assert!(node.is_ok());
+        let node = create_sqlite_node(200, vec![]).await.unwrap();
+        node.ready().await.unwrap();
+        assert!(node.stop(Duration::from_secs(30)).await);
+    }
+
+    #[cfg(all(feature = "sqlite", feature = "prometheus"))]
+    #[tokio::test]
+    async fn test_sqlite_node_prometheus_port() {
+        let first = create_sqlite_node(223, vec![]).await.unwrap();
+        let second = create_sqlite_node(224, vec![]).await.unwrap();
+        let address = |node: &SqliteNode| node.api().node_info().prometheus.unwrap();
+        assert!(!address(&first).ends_with(":0"));
+        assert_ne!(address(&first), address(&second));
+
+        // The address of the other node is in use: the metrics stay where they are.
+        let previous = address(&first);
+        let settings = first.live.settings.lock().unwrap().clone();
+        let changes = first.reload(KoreSettings {
+            prometheus: address(&second),
+            ..settings
+        });
+        assert_eq!(changes.len(), 1);
+        assert!(!changes[0].applied);
+        assert!(changes[0].error.is_some());
+        assert_eq!(address(&first), previous);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_support_bundle() {
+        let node = create_sqlite_node(216, vec![]).await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bundle.tar.gz");
+        node.support_bundle(&path).await.unwrap();
+        assert!(fs::metadata(&path).unwrap().len() > 0);
+
+        // The lock is released before the reload takes it.
+        let settings = node.live.settings.lock().unwrap().clone();
+        node.reload(KoreSettings {
+            http_api: "127.0.0.1:3216".to_owned(),
+            ..settings
+        });
+        let kinds = node
+            .api()
+            .node_history()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                NodeHistoryKind::Started,
+                NodeHistoryKind::SupportBundle,
+                NodeHistoryKind::ConfigReloaded
+            ]
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_node_backup() {
+        let node = create_sqlite_node(225, vec![]).await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup.tar.zst");
+        let manifest = node.api().create_backup(&path).await.unwrap();
+        assert_eq!(manifest.backend, "sqlite");
+        assert_eq!(manifest.node_id, node.api().get_controller_id());
+        assert!(fs::metadata(&path).unwrap().len() > 0);
+        let last = node.api().node_history().unwrap().pop().unwrap();
+        assert_eq!(last.kind, NodeHistoryKind::Backup);
     }
 
     #[cfg(feature = "sqlite")]
-    pub fn create_sqlite_node(
+    pub async fn create_sqlite_node(
         node: u32,
         boot_nodes: Vec<RoutingNode>,
     ) -> Result<SqliteNode, NodeError> {
         let tempdir = tempfile::tempdir().unwrap();
         let path = tempdir.path().join(format!("keys{}", node));
         let password = format!("password{}", node);
-        let mut settings = KoreSettings::default();
-        settings.prometheus = format!("127.0.0.1:3{}", node);
+        let mut settings = KoreSettings {
+            prometheus: "127.0.0.1:0".to_owned(),
+            ..Default::default()
+        };
         settings.settings.network = NetworkConfig::new(
             NodeType::Bootstrap,
             vec![format!("/ip4/127.0.0.1/tcp/{}", 50000 + node)],
@@ -342,12 +1428,13 @@ pub mod tests {
             path.to_str().unwrap().to_owned()
         ));
         settings.keys_path = path.to_str().unwrap().to_owned();
-        SqliteNode::build(settings, &password)
+        settings.keys.iterations = crate::utils::MIN_PBKDF2_ITERATIONS;
+        KoreNodeBuilder::new(settings, &password).build().await
     }
 
     #[cfg(feature = "sqlite")]
-    pub fn export_sqlite_api(node: u32, boot_nodes: Vec<RoutingNode>) -> KoreApi {
-        let node = create_sqlite_node(node, boot_nodes);
+    pub async fn export_sqlite_api(node: u32, boot_nodes: Vec<RoutingNode>) -> KoreApi {
+        let node = create_sqlite_node(node, boot_nodes).await;
         assert!(node.is_ok());
         let node = node.unwrap();
         node.bind_with_shutdown(signal::ctrl_c());
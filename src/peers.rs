@@ -0,0 +1,124 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Peers of the ledger network.
+//!
+//! Kore Base keeps its libp2p swarm to itself: it dials its boot nodes and the peers it finds,
+//! and reports neither its connections nor a way to open, drop or block one. The node does not
+//! list, dial or ban peers, which would need such a handle; `KoreApi::boot_nodes` only reports
+//! the boot nodes given to Kore Base.
+//!
+//! The node keeps its boot nodes in the database. On each start it learns the boot nodes of
+//! the settings and gives Kore Base the stored ones after them, so that a node whose settings
+//! lost their boot nodes, or whose boot nodes left the network, still rejoins through the peers
+//! it knew. The peers Kore Base finds on its own are not reported, so they cannot be kept.
+//!
+//! `add_boot_node` and `remove_boot_node` change the stored boot nodes. Kore Base reads its boot
+//! nodes once, when it starts, so the changes are deferred: they apply from the next start of
+//! the node, and `KoreApi::boot_nodes` reports the boot nodes in use until then. A boot node
+//! removed is remembered, so that it is neither learned again from the settings nor given to
+//! Kore Base, until it is added back.
+//!
+
+use kore_base::{NetworkConfig, RoutingNode};
+
+use crate::{
+    bootstrap::with_boot_nodes, database::store::NodeStore, error::NodeError, model::NodeBootNode,
+};
+
+/// Scope of the node store holding the boot nodes, by peer identifier.
+pub(crate) const BOOT_NODES_SCOPE: &str = "boot_nodes";
+
+/// Scope of the node store holding the boot nodes removed, by peer identifier.
+pub(crate) const REMOVED_BOOT_NODES_SCOPE: &str = "removed_boot_nodes";
+
+/// Learn the boot nodes of the network settings and add the stored ones after them. The boot
+/// nodes removed are left out.
+///
+/// # Arguments
+///
+/// * `store` - Node store, the boot nodes are kept in its `boot_nodes` scope and the removed
+///   ones in its `removed_boot_nodes` scope.
+/// * `network` - Network settings given to Kore Base.
+///
+/// # Errors
+///
+/// * `NodeError::Database` - The boot nodes cannot be read or stored. The settings are left
+///   unchanged.
+///
+pub(crate) fn learn_boot_nodes(
+    store: &NodeStore,
+    network: &mut NetworkConfig,
+) -> Result<(), NodeError> {
+    let scope = store.scope(BOOT_NODES_SCOPE);
+    let removed = store.scope(REMOVED_BOOT_NODES_SCOPE);
+    let stored = scope.entries::<NodeBootNode>()?;
+    let mut configured = vec![];
+    for node in network.routing.boot_nodes() {
+        if removed.get::<NodeBootNode>(&node.peer_id)?.is_some() {
+            log::info!(
+                "Boot node {} of the settings skipped, it was removed",
+                node.peer_id
+            );
+        } else {
+            configured.push(node);
+        }
+    }
+    for node in &configured {
+        let node = NodeBootNode::from(node.clone());
+        if !stored.iter().any(|(_, stored)| *stored == node) {
+            scope.put(&node.peer_id, &node)?;
+        }
+    }
+    let boot_nodes = merge_boot_nodes(configured, stored.into_iter().map(|(_, node)| node));
+    network.routing = with_boot_nodes(&network.routing, boot_nodes);
+    Ok(())
+}
+
+/// Boot nodes of the settings, then the stored ones of other peers.
+fn merge_boot_nodes(
+    mut boot_nodes: Vec<RoutingNode>,
+    stored: impl IntoIterator<Item = NodeBootNode>,
+) -> Vec<RoutingNode> {
+    for node in stored {
+        if !boot_nodes.iter().any(|known| known.peer_id == node.peer_id) {
+            boot_nodes.push(node.into());
+        }
+    }
+    boot_nodes
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_merge_boot_nodes() {
+        let node = |peer_id: &str, address: &str| NodeBootNode {
+            peer_id: peer_id.to_owned(),
+            addresses: vec![address.to_owned()],
+        };
+        let configured = vec![RoutingNode::from(node(
+            "12D3KooWFirst",
+            "/ip4/10.0.0.1/tcp/50000",
+        ))];
+        let merged = merge_boot_nodes(
+            configured,
+            [
+                node("12D3KooWFirst", "/ip4/10.0.0.9/tcp/50000"),
+                node("12D3KooWLearned", "/ip4/10.0.0.2/tcp/50000"),
+            ],
+        );
+        assert_eq!(
+            merged
+                .iter()
+                .map(|node| (node.peer_id.as_str(), node.address[0].as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("12D3KooWFirst", "/ip4/10.0.0.1/tcp/50000"),
+                ("12D3KooWLearned", "/ip4/10.0.0.2/tcp/50000"),
+            ]
+        );
+    }
+}
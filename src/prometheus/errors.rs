@@ -1,7 +1,6 @@
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
-    Json,
 };
 
 // Errors
@@ -14,7 +13,7 @@ impl IntoResponse for Errors {
         match self {
             Errors::ErrorGetPrometheusData => (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json("Error: getting prometheus data."),
+                "Error: getting prometheus data.",
             )
                 .into_response(),
         }
@@ -1,3 +1,5 @@
 mod common;
 mod errors;
+#[cfg(feature = "metrics-push")]
+pub mod push;
 pub mod server;
@@ -0,0 +1,410 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Metrics push.
+//!
+//! Sends the metrics registry every `[kore.metrics.push] interval` to a Prometheus Pushgateway or
+//! to a remote-write endpoint, for nodes behind a NAT or a firewall that Prometheus cannot scrape.
+//!
+//! The Pushgateway receives the text exposition format with a `PUT` to
+//! `<url>/metrics/job/<job>/<label>/<value>...`, so each push replaces the previous one. The
+//! remote-write endpoint receives a snappy-compressed protobuf `WriteRequest` with the samples,
+//! read back from the text exposition with `prometheus-parse` and labelled with `job` and the
+//! configured labels.
+//!
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::URL_SAFE, Engine};
+use prometheus_parse::{Scrape, Value};
+use prost::Message;
+use reqwest::{
+    header::{CONTENT_ENCODING, CONTENT_TYPE},
+    Client,
+};
+use tokio::time::{interval, MissedTickBehavior};
+
+use super::server::{PrometheusServer, TEXT_FORMAT};
+use crate::settings::{MetricsPushMode, MetricsPushSettings};
+use crate::tasks::NodeTasks;
+
+/// Time allowed to each push.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Content type of the remote-write requests.
+const REMOTE_WRITE_FORMAT: &str = "application/x-protobuf";
+
+/// Header with the version of the remote-write protocol.
+const REMOTE_WRITE_VERSION_HEADER: &str = "x-prometheus-remote-write-version";
+
+/// Start pushing the metrics, until the node is cancelled.
+///
+/// # Arguments
+///
+/// * `server` - Prometheus server, whose registry is pushed.
+/// * `settings` - Endpoint, mode, interval, labels and credentials.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_metrics_push(
+    server: PrometheusServer,
+    settings: MetricsPushSettings,
+    tasks: &NodeTasks,
+) {
+    let client = match Client::builder().timeout(PUSH_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(error) => {
+            log::error!("Metrics push disabled, the HTTP client failed: {}", error);
+            return;
+        }
+    };
+
+    let cancellation = tasks.token();
+    tasks.spawn(async move {
+        let mut interval = interval(settings.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = cancellation.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            let Some(body) = server.encode() else {
+                log::warn!("Metrics push skipped, the registry could not be encoded");
+                continue;
+            };
+            if let Err(error) = push(&client, &settings, body).await {
+                log::warn!("Metrics push to {} failed: {}", settings.url, error);
+            }
+        }
+    });
+}
+
+/// Send the encoded registry to the endpoint.
+///
+/// # Arguments
+///
+/// * `client` - HTTP client.
+/// * `settings` - Endpoint, mode, labels and credentials.
+/// * `body` - Registry in the text exposition format.
+///
+/// # Errors
+///
+/// The registry could not be converted, the request failed or the endpoint answered with an
+/// error status.
+///
+async fn push(client: &Client, settings: &MetricsPushSettings, body: String) -> Result<(), String> {
+    let request = match settings.mode {
+        MetricsPushMode::Pushgateway => client
+            .put(pushgateway_url(settings))
+            .header(CONTENT_TYPE, TEXT_FORMAT)
+            .body(body),
+        MetricsPushMode::RemoteWrite => client
+            .post(&settings.url)
+            .header(CONTENT_TYPE, REMOTE_WRITE_FORMAT)
+            .header(CONTENT_ENCODING, "snappy")
+            .header(REMOTE_WRITE_VERSION_HEADER, "0.1.0")
+            .body(write_request(&body, settings, timestamp_millis())?),
+    };
+    let request = if settings.username.is_empty() {
+        request
+    } else {
+        request.basic_auth(&settings.username, Some(&settings.password))
+    };
+    let response = request.send().await.map_err(|error| error.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("status {}", response.status()))
+    }
+}
+
+/// Pushgateway URL of the job and the grouping labels.
+fn pushgateway_url(settings: &MetricsPushSettings) -> String {
+    let mut url = format!(
+        "{}/metrics/{}",
+        settings.url.trim_end_matches('/'),
+        path_segment("job", &settings.job)
+    );
+    for (name, value) in &settings.labels {
+        url.push('/');
+        url.push_str(&path_segment(name, value));
+    }
+    url
+}
+
+/// `<name>/<value>` in a Pushgateway path, with the value in base64 when it is not path-safe.
+fn path_segment(name: &str, value: &str) -> String {
+    let safe = value
+        .bytes()
+        .all(|byte| byte.is_ascii_alphanumeric() || b"-._~".contains(&byte));
+    if safe && !value.is_empty() {
+        format!("{}/{}", name, value)
+    } else if value.is_empty() {
+        format!("{}@base64/=", name)
+    } else {
+        format!("{}@base64/{}", name, URL_SAFE.encode(value))
+    }
+}
+
+/// Label of a time series, in the remote-write protocol.
+#[derive(Clone, PartialEq, Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+/// Sample of a time series, in the remote-write protocol.
+#[derive(Clone, PartialEq, Message)]
+struct Sample {
+    #[prost(double, tag = "1")]
+    value: f64,
+    /// Milliseconds since the Unix epoch.
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+/// Time series, in the remote-write protocol.
+#[derive(Clone, PartialEq, Message)]
+struct TimeSeries {
+    /// Labels sorted by name, `__name__` included.
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Sample>,
+}
+
+/// Body of a remote-write request, before its compression.
+#[derive(Clone, PartialEq, Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+/// Snappy-compressed `WriteRequest` of the samples of a text exposition, taken at `timestamp`.
+fn write_request(
+    text: &str,
+    settings: &MetricsPushSettings,
+    timestamp: i64,
+) -> Result<Vec<u8>, String> {
+    let request = WriteRequest {
+        timeseries: time_series(text, settings, timestamp)?,
+    };
+    snap::raw::Encoder::new()
+        .compress_vec(&request.encode_to_vec())
+        .map_err(|error| error.to_string())
+}
+
+/// Time series of a text exposition, one sample each taken at `timestamp`, with the `job` and
+/// the labels of the settings. The labels of a sample take precedence over the configured ones.
+/// The buckets of histograms and the quantiles of summaries are a series each, labelled with
+/// `le` and `quantile`.
+fn time_series(
+    text: &str,
+    settings: &MetricsPushSettings,
+    timestamp: i64,
+) -> Result<Vec<TimeSeries>, String> {
+    let scrape = Scrape::parse(text.lines().map(|line| Ok(line.to_owned())))
+        .map_err(|error| error.to_string())?;
+    let mut series = vec![];
+    for sample in scrape.samples {
+        let mut labels: BTreeMap<String, String> = std::iter::once(("job", settings.job.as_str()))
+            .chain(
+                settings
+                    .labels
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str())),
+            )
+            .map(|(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+        labels.extend(
+            sample
+                .labels
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        let values = match sample.value {
+            Value::Counter(value) | Value::Gauge(value) | Value::Untyped(value) => {
+                vec![(sample.metric, None, value)]
+            }
+            Value::Histogram(counts) => {
+                let name = if sample.metric.ends_with("_bucket") {
+                    sample.metric
+                } else {
+                    format!("{}_bucket", sample.metric)
+                };
+                counts
+                    .into_iter()
+                    .map(|count| (name.clone(), Some(("le", count.less_than)), count.count))
+                    .collect()
+            }
+            Value::Summary(counts) => counts
+                .into_iter()
+                .map(|count| {
+                    let quantile = Some(("quantile", count.quantile));
+                    (sample.metric.clone(), quantile, count.count)
+                })
+                .collect(),
+        };
+        for (name, bound, value) in values {
+            let mut labels = labels.clone();
+            labels.insert("__name__".to_owned(), name);
+            if let Some((label, bound)) = bound {
+                labels.insert(label.to_owned(), bound_value(bound));
+            }
+            series.push(TimeSeries {
+                labels: labels
+                    .into_iter()
+                    .map(|(name, value)| Label { name, value })
+                    .collect(),
+                samples: vec![Sample { value, timestamp }],
+            });
+        }
+    }
+    Ok(series)
+}
+
+/// Value of an `le` or `quantile` label.
+fn bound_value(bound: f64) -> String {
+    if bound == f64::INFINITY {
+        "+Inf".to_owned()
+    } else {
+        bound.to_string()
+    }
+}
+
+/// Milliseconds since the Unix epoch.
+fn timestamp_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    fn settings(url: &str, mode: MetricsPushMode) -> MetricsPushSettings {
+        MetricsPushSettings {
+            url: url.to_owned(),
+            mode,
+            labels: BTreeMap::from([
+                ("instance".to_owned(), "edge/1".to_owned()),
+                ("region".to_owned(), "eu".to_owned()),
+            ]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pushgateway_url() {
+        let settings = settings("http://gateway:9091/", MetricsPushMode::Pushgateway);
+        assert_eq!(
+            pushgateway_url(&settings),
+            "http://gateway:9091/metrics/job/kore-node/instance@base64/ZWRnZS8x/region/eu"
+        );
+        assert_eq!(path_segment("zone", ""), "zone@base64/=");
+    }
+
+    #[test]
+    fn test_write_request() {
+        let text = "# HELP requests Requests.\n\
+                    # TYPE requests counter\n\
+                    requests_total{method=\"get\"} 3\n\
+                    # TYPE latency histogram\n\
+                    latency_bucket{le=\"0.5\",job=\"api\"} 1\n\
+                    latency_bucket{le=\"+Inf\",job=\"api\"} 2\n\
+                    # EOF\n";
+        let settings = settings("http://write", MetricsPushMode::RemoteWrite);
+        let compressed = write_request(text, &settings, 300).unwrap();
+        let decompressed = snap::raw::Decoder::new()
+            .decompress_vec(&compressed)
+            .unwrap();
+        let request = WriteRequest::decode(decompressed.as_slice()).unwrap();
+        let series = |pairs: &[(&str, &str)], value: f64| TimeSeries {
+            labels: pairs
+                .iter()
+                .map(|(name, value)| Label {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+                .collect(),
+            samples: vec![Sample {
+                value,
+                timestamp: 300,
+            }],
+        };
+        let requests = series(
+            &[
+                ("__name__", "requests_total"),
+                ("instance", "edge/1"),
+                ("job", "kore-node"),
+                ("method", "get"),
+                ("region", "eu"),
+            ],
+            3.0,
+        );
+        let bucket = series(
+            &[
+                ("__name__", "latency_bucket"),
+                ("instance", "edge/1"),
+                ("job", "api"),
+                ("le", "+Inf"),
+                ("region", "eu"),
+            ],
+            2.0,
+        );
+        assert!(request.timeseries.contains(&requests));
+        assert!(request.timeseries.contains(&bucket));
+    }
+
+    #[tokio::test]
+    async fn test_push() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, received) = mpsc::channel();
+        std::thread::spawn(move || {
+            for status in ["200 OK", "400 Bad Request"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = vec![0; 4096];
+                let read = stream.read(&mut request).unwrap();
+                sender
+                    .send(String::from_utf8_lossy(&request[..read]).to_lowercase())
+                    .unwrap();
+                let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let settings = MetricsPushSettings {
+            username: "kore".to_owned(),
+            password: "secret".to_owned(),
+            ..settings(&url, MetricsPushMode::Pushgateway)
+        };
+        let client = Client::new();
+        let result = push(&client, &settings, "uptime 1\n".to_owned()).await;
+        assert!(result.is_ok());
+        let request = received.recv().unwrap();
+        assert!(request.starts_with("put /metrics/job/kore-node/instance@base64/zwrnzs8x/"));
+        assert!(request.contains("authorization: basic a29yztpzzwnyzxq="));
+
+        let settings = MetricsPushSettings {
+            mode: MetricsPushMode::RemoteWrite,
+            ..settings
+        };
+        let result = push(&client, &settings, "uptime 1\n".to_owned()).await;
+        assert_eq!(result, Err("status 400 Bad Request".to_owned()));
+        let request = received.recv().unwrap();
+        assert!(request.starts_with("post / "));
+        assert!(request.contains("content-encoding: snappy"));
+    }
+}
@@ -1,35 +1,339 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    time::Instant,
+};
 
 use super::{common::State, errors::Errors};
-use axum::{routing::get, Extension, Json, Router};
+use crate::{
+    access_log::{accept_trace_id, AccessEntry, AccessLogger, TRACE_ID_HEADER},
+    auth::{require_credentials, Authenticator},
+    error::NodeError,
+    listener::{BoundAddress, HttpListener},
+};
+use axum::{
+    extract::{self, ConnectInfo, Request},
+    http::{header::CONTENT_TYPE, HeaderValue},
+    middleware::{from_fn_with_state, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
 use prometheus_client::{encoding::text::encode, registry::Registry};
+use tokio_util::sync::CancellationToken;
 
+/// Content type of the Prometheus text exposition format.
+pub const TEXT_FORMAT: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Metrics of the registry in the text exposition format.
 pub async fn handler_prometheus_data(
     Extension(state): Extension<Arc<RwLock<State>>>,
-) -> Result<Json<String>, Errors> {
-    let state_read = state.read().map_err(|_| Errors::ErrorGetPrometheusData)?;
+) -> Result<Response, Errors> {
+    let body = encode_state(&state).ok_or(Errors::ErrorGetPrometheusData)?;
+
+    Ok(([(CONTENT_TYPE, TEXT_FORMAT)], body).into_response())
+}
+
+/// Metrics of the registry in the text exposition format, none when they cannot be read.
+fn encode_state(state: &RwLock<State>) -> Option<String> {
+    let state = state.read().ok()?;
     let mut body = String::new();
-    encode(&mut body, &state_read.registry).map_err(|_| Errors::ErrorGetPrometheusData)?;
+    encode(&mut body, &state.registry).ok()?;
+    Some(body)
+}
 
-    Ok(Json(body))
+/// Liveness of the metrics server, for load balancers and orchestrators.
+async fn handler_health() -> &'static str {
+    "OK"
 }
 
-pub fn build_routes(registry: Registry) -> Router {
-    let state = Arc::new(RwLock::new(State { registry }));
+/// Log the request, under the trace id of its `x-request-id` header when it is valid, see
+/// `access_log::accept_trace_id`.
+async fn log_access(
+    extract::State(logger): extract::State<AccessLogger>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let method = format!("{} {}", request.method(), request.uri().path());
+    let identity = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.to_string());
+    let trace_id = request
+        .headers()
+        .get(TRACE_ID_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let trace_id = accept_trace_id(trace_id);
+
+    let mut response = next.run(request).await;
 
+    let status = response.status();
+    logger.log(&AccessEntry {
+        method: &method,
+        latency: start.elapsed(),
+        status: status.as_str(),
+        failed: status.is_server_error(),
+        identity: identity.as_deref(),
+        trace_id: &trace_id,
+    });
+    if let Ok(value) = HeaderValue::from_str(&trace_id) {
+        response.headers_mut().insert(TRACE_ID_HEADER, value);
+    }
+    response
+}
+
+/// Routes of the metrics server over the registry shared with `PrometheusServer`. `/metrics`
+/// requires the credentials of `kore.auth`, `/health` is open.
+pub fn build_routes(
+    state: Arc<RwLock<State>>,
+    logger: AccessLogger,
+    authenticator: Authenticator,
+) -> Router {
     let endpoints = Router::new()
         .route("/metrics", get(handler_prometheus_data))
-        .layer(Extension(state));
+        .route_layer(from_fn_with_state(authenticator, require_credentials))
+        .route("/health", get(handler_health))
+        .layer(Extension(state))
+        .layer(from_fn_with_state(logger, log_access));
 
     Router::new().merge(endpoints)
 }
 
-pub fn run_prometheus(registry: Registry, tcp_listener: &str) {
-    let routes = build_routes(registry);
-    let tcp_listener = tcp_listener.to_owned();
+/// Handle of the prometheus server, which can be moved to another address while running.
+#[derive(Clone)]
+pub struct PrometheusServer {
+    state: Arc<RwLock<State>>,
+    routes: Router,
+    shutdown: Arc<Mutex<CancellationToken>>,
+    address: Arc<Mutex<Option<BoundAddress>>>,
+}
+
+impl PrometheusServer {
+    /// Serve the metrics on a new address, stopping the previous listener once the new one is
+    /// bound. An empty address only stops it.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Bind` - The address cannot be bound. The previous listener keeps
+    ///   serving the metrics.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<BoundAddress>` - Address bound, none when the metrics are not served.
+    ///
+    pub fn rebind(&self, listen: &str) -> Result<Option<BoundAddress>, NodeError> {
+        let listener = bind(listen)?;
+        let shutdown = CancellationToken::new();
+        // The token and the address are replaced whole, so that the previous listener is always
+        // stopped and the address reported is the one served.
+        let mut current = self.shutdown.lock().unwrap_or_else(PoisonError::into_inner);
+        current.cancel();
+        *current = shutdown.clone();
+        let address = listener.and_then(|listener| serve(listener, self.routes.clone(), shutdown));
+        self.address
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone_from(&address);
+        Ok(address)
+    }
+
+    /// Address the metrics are served on, with the port picked by the system when the settings
+    /// ask for port 0.
+    pub fn local_addr(&self) -> Option<BoundAddress> {
+        self.address
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Metrics of the registry in the text exposition format, as served on `/metrics`, none
+    /// when they cannot be read.
+    pub fn encode(&self) -> Option<String> {
+        encode_state(&self.state)
+    }
+}
+
+/// Start the prometheus server, unless `listen` is empty.
+/// Port 0 binds any free port, see `PrometheusServer::local_addr`, and `unix://<path>` a Unix
+/// socket, see the `listener` module.
+///
+/// # Errors
+///
+/// * `NodeError::Bind` - The address cannot be bound.
+///
+pub fn run_prometheus(
+    registry: Registry,
+    listen: &str,
+    logger: AccessLogger,
+    authenticator: Authenticator,
+) -> Result<PrometheusServer, NodeError> {
+    let state = Arc::new(RwLock::new(State { registry }));
+    let routes = build_routes(state.clone(), logger, authenticator);
+    let shutdown = CancellationToken::new();
+    let address =
+        bind(listen)?.and_then(|listener| serve(listener, routes.clone(), shutdown.clone()));
+    Ok(PrometheusServer {
+        state,
+        routes,
+        shutdown: Arc::new(Mutex::new(shutdown)),
+        address: Arc::new(Mutex::new(address)),
+    })
+}
+
+/// Bind `listen` without serving yet, none when it is empty.
+fn bind(listen: &str) -> Result<Option<HttpListener>, NodeError> {
+    if listen.is_empty() {
+        log::info!("Prometheus metrics disabled");
+        return Ok(None);
+    }
+    HttpListener::bind(listen).map(Some).map_err(|error| {
+        log::error!("Prometheus cannot listen on {}: {}", listen, error);
+        NodeError::Bind {
+            address: listen.to_owned(),
+            cause: error.to_string(),
+        }
+    })
+}
+
+/// Serve the routes on a bound listener until `shutdown` is cancelled.
+fn serve(
+    listener: HttpListener,
+    routes: Router,
+    shutdown: CancellationToken,
+) -> Option<BoundAddress> {
+    let address = listener.local_addr();
+    if let Some(address) = &address {
+        log::info!("Prometheus metrics served on {}", address);
+    }
 
     tokio::spawn(async move {
-        let listener = tokio::net::TcpListener::bind(tcp_listener).await.unwrap();
-        axum::serve(listener, routes).await.unwrap();
+        if let Err(error) = listener.serve(routes, shutdown).await {
+            log::error!("Prometheus server error: {}", error);
+        }
     });
+    address
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{auth::API_KEY_HEADER, settings::AuthSettings};
+    use axum::{
+        body::{to_bytes, Body},
+        http::StatusCode,
+    };
+    use prometheus_client::metrics::counter::Counter;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_prometheus_routes() {
+        let mut registry = Registry::default();
+        let counter = Counter::<u64>::default();
+        counter.inc();
+        registry.register("requests", "Requests served", counter);
+        let routes = build_routes(
+            Arc::new(RwLock::new(State { registry })),
+            AccessLogger::new(Default::default()),
+            Authenticator::default(),
+        );
+        let request = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        let response = routes.clone().oneshot(request("/metrics")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], TEXT_FORMAT);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.starts_with("# HELP requests Requests served.\n"));
+        assert!(body.contains("\nrequests_total 1\n"));
+
+        let response = routes.oneshot(request("/health")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_prometheus_credentials() {
+        let authenticator = Authenticator::new(AuthSettings {
+            api_keys: vec!["metrics-key".to_owned()],
+            ..Default::default()
+        });
+        let routes = build_routes(
+            Arc::new(RwLock::new(State {
+                registry: Registry::default(),
+            })),
+            AccessLogger::new(Default::default()),
+            authenticator,
+        );
+        let request = |uri: &str, api_key: Option<&str>| {
+            let mut request = Request::builder().uri(uri);
+            if let Some(api_key) = api_key {
+                request = request.header(API_KEY_HEADER, api_key);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        for (api_key, status) in [
+            (None, StatusCode::UNAUTHORIZED),
+            (Some("other-key"), StatusCode::UNAUTHORIZED),
+            (Some("metrics-key"), StatusCode::OK),
+        ] {
+            let response = routes
+                .clone()
+                .oneshot(request("/metrics", api_key))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status);
+        }
+        let response = routes.oneshot(request("/health", None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_prometheus_free_port() {
+        let logger = AccessLogger::new(Default::default());
+        let authenticator = Authenticator::default();
+        let server = run_prometheus(
+            Registry::default(),
+            "127.0.0.1:0",
+            logger.clone(),
+            authenticator.clone(),
+        )
+        .unwrap();
+        let Some(BoundAddress::Tcp(address)) = server.local_addr() else {
+            panic!("metrics not served on TCP");
+        };
+        assert_ne!(address.port(), 0);
+        let other =
+            run_prometheus(Registry::default(), "127.0.0.1:0", logger, authenticator).unwrap();
+        assert_ne!(other.local_addr(), Some(BoundAddress::Tcp(address)));
+
+        assert_eq!(server.rebind("").unwrap(), None);
+        assert_eq!(server.local_addr(), None);
+    }
+
+    #[tokio::test]
+    async fn test_prometheus_bind_error() {
+        let logger = AccessLogger::new(Default::default());
+        let authenticator = Authenticator::default();
+        let occupied = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let busy = occupied.local_addr().unwrap().to_string();
+        assert!(matches!(
+            run_prometheus(
+                Registry::default(),
+                &busy,
+                logger.clone(),
+                authenticator.clone()
+            ),
+            Err(NodeError::Bind { address, .. }) if address == busy
+        ));
+
+        // The metrics are still served on the previous address.
+        let server =
+            run_prometheus(Registry::default(), "127.0.0.1:0", logger, authenticator).unwrap();
+        let address = server.local_addr();
+        let error = server.rebind(&busy).unwrap_err();
+        assert!(matches!(error, NodeError::Bind { cause, .. } if !cause.is_empty()));
+        assert_eq!(server.local_addr(), address);
+    }
 }
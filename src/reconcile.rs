@@ -0,0 +1,308 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Governance reconciliation.
+//!
+//! Moves a live governance toward a manifest that declares its members, roles, schemas and
+//! policies. Each declared section is compared with the properties of the governance, and the
+//! entries missing on either side become the operations of a JSON patch, sent to the governance
+//! as a single Fact event. A dry run computes the patch, and a readable diff, without sending it.
+//!
+//! A declared section holds every entry it should end with, those of the governance itself
+//! included; sections left out of the manifest are not changed. The patch is only a proposal:
+//! the governance contract and its approvers still decide whether it is applied.
+//!
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    error::{ConfigError, NodeError},
+    model::{NodeEventRequest, NodeFactRequest, NodeRequestOrigin, NodeSignedEventRequest},
+    KoreApi,
+};
+
+/// Sections of the governance properties that a manifest may declare.
+pub const SECTIONS: [&str; 4] = ["members", "roles", "schemas", "policies"];
+
+/// Source of the requests sent by the reconciliation.
+const RECONCILE_SOURCE: &str = "reconcile";
+
+/// Desired state of a governance.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct GovernanceManifest {
+    /// Governance identifier.
+    pub governance_id: String,
+    /// Members, e.g. `{ id, name }`.
+    #[serde(default)]
+    pub members: Option<Vec<Value>>,
+    /// Roles, e.g. `{ who, namespace, role, schema }`.
+    #[serde(default)]
+    pub roles: Option<Vec<Value>>,
+    /// Schemas, e.g. `{ id, schema, initial_value, contract }`.
+    #[serde(default)]
+    pub schemas: Option<Vec<Value>>,
+    /// Policies, e.g. `{ id, approve, evaluate, validate }`.
+    #[serde(default)]
+    pub policies: Option<Vec<Value>>,
+}
+
+impl GovernanceManifest {
+    /// Read a manifest file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Manifest file (yaml, json or toml).
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Config` - The file cannot be read or is not a manifest.
+    ///
+    pub fn from_file(path: &str) -> Result<Self, NodeError> {
+        config::Config::builder()
+            .add_source(config::File::with_name(path))
+            .build()
+            .and_then(|config| config.try_deserialize())
+            .map_err(|error| NodeError::Config(vec![ConfigError::new(path, error.to_string())]))
+    }
+
+    /// Entries declared for a section, `None` when it is left out.
+    fn section(&self, name: &str) -> Option<&Vec<Value>> {
+        match name {
+            "members" => self.members.as_ref(),
+            "roles" => self.roles.as_ref(),
+            "schemas" => self.schemas.as_ref(),
+            "policies" => self.policies.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Changes that move a governance to its manifest.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Reconciliation {
+    /// JSON patch operations, empty when the governance matches the manifest.
+    pub patch: Vec<Value>,
+    /// Entries removed (`-`) and added (`+`), by section.
+    pub diff: Vec<String>,
+    /// Fact request sent, `None` for dry runs and when nothing changes.
+    pub request_id: Option<String>,
+}
+
+/// Compute the JSON patch that turns the governance properties into the manifest.
+/// Removals go from the last index to the first, so the earlier indexes stay valid, and
+/// additions are appended.
+///
+/// # Arguments
+///
+/// * `properties` - Current properties of the governance.
+/// * `manifest` - Desired state.
+///
+/// # Returns
+///
+/// * `(Vec<Value>, Vec<String>)` - Patch operations and readable diff.
+///
+pub fn governance_patch(
+    properties: &Value,
+    manifest: &GovernanceManifest,
+) -> (Vec<Value>, Vec<String>) {
+    let mut patch = vec![];
+    let mut diff = vec![];
+    for name in SECTIONS {
+        let Some(desired) = manifest.section(name) else {
+            continue;
+        };
+        let Some(current) = properties.get(name).and_then(Value::as_array) else {
+            patch.push(json!({ "op": "add", "path": format!("/{}", name), "value": desired }));
+            diff.extend(desired.iter().map(|entry| format!("+ {}: {}", name, entry)));
+            continue;
+        };
+        for (index, entry) in current.iter().enumerate().rev() {
+            if !desired.contains(entry) {
+                patch.push(json!({ "op": "remove", "path": format!("/{}/{}", name, index) }));
+                diff.push(format!("- {}: {}", name, entry));
+            }
+        }
+        for entry in desired.iter().filter(|entry| !current.contains(entry)) {
+            patch.push(json!({ "op": "add", "path": format!("/{}/-", name), "value": entry }));
+            diff.push(format!("+ {}: {}", name, entry));
+        }
+    }
+    (patch, diff)
+}
+
+/// Reconcile a governance with its manifest.
+/// The patch is sent as a Fact event signed by the node, unless `dry_run` is set or the
+/// governance already matches.
+///
+/// # Arguments
+///
+/// * `api` - Kore API.
+/// * `manifest` - Desired state.
+/// * `dry_run` - Only compute the changes.
+///
+/// # Errors
+///
+/// * `NodeError::InvalidParameter` - The subject is not a governance.
+/// * `NodeError` - The governance could not be read or the request not sent.
+///
+/// # Returns
+///
+/// * `Reconciliation` - Changes, and the request that applies them.
+///
+pub async fn reconcile_governance(
+    api: &KoreApi,
+    manifest: &GovernanceManifest,
+    dry_run: bool,
+) -> Result<Reconciliation, NodeError> {
+    let governance = api.get_subject(&manifest.governance_id).await?;
+    if governance.schema_id != "governance" {
+        return Err(NodeError::InvalidParameter(format!(
+            "{} is not a governance",
+            manifest.governance_id
+        )));
+    }
+    let (patch, diff) = governance_patch(&governance.properties, manifest);
+    if dry_run || patch.is_empty() {
+        return Ok(Reconciliation {
+            patch,
+            diff,
+            request_id: None,
+        });
+    }
+    let response = api
+        .send_event_request(NodeSignedEventRequest {
+            request: NodeEventRequest::Fact(NodeFactRequest {
+                subject_id: manifest.governance_id.clone(),
+                payload: json!({ "Patch": { "data": patch } }),
+            }),
+            signature: None,
+            origin: Some(NodeRequestOrigin {
+                source: Some(RECONCILE_SOURCE.to_owned()),
+                device_id: None,
+                geo_hint: None,
+            }),
+        })
+        .await?;
+    Ok(Reconciliation {
+        patch,
+        diff,
+        request_id: Some(response.request_id),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Write;
+
+    fn manifest() -> GovernanceManifest {
+        GovernanceManifest {
+            governance_id: "JGovernance".to_owned(),
+            members: Some(vec![
+                json!({ "id": "EOwner", "name": "Owner" }),
+                json!({ "id": "EWitness", "name": "Witness" }),
+            ]),
+            roles: None,
+            schemas: Some(vec![]),
+            policies: None,
+        }
+    }
+
+    #[test]
+    fn test_governance_patch() {
+        let properties = json!({
+            "members": [
+                { "id": "EOwner", "name": "Owner" },
+                { "id": "EOld", "name": "Old" },
+                { "id": "ELeaving", "name": "Leaving" }
+            ],
+            "roles": [{ "namespace": "", "role": "WITNESS" }],
+            "schemas": [],
+            "policies": []
+        });
+        let (patch, diff) = governance_patch(&properties, &manifest());
+        assert_eq!(
+            patch,
+            vec![
+                json!({ "op": "remove", "path": "/members/2" }),
+                json!({ "op": "remove", "path": "/members/1" }),
+                json!({
+                    "op": "add",
+                    "path": "/members/-",
+                    "value": { "id": "EWitness", "name": "Witness" }
+                }),
+            ]
+        );
+        assert_eq!(diff.len(), 3);
+        assert!(diff[2].starts_with("+ members:"));
+
+        // Once applied, nothing is left to change.
+        let properties = json!({
+            "members": manifest().members,
+            "roles": [],
+            "schemas": []
+        });
+        assert!(governance_patch(&properties, &manifest()).0.is_empty());
+    }
+
+    #[test]
+    fn test_manifest_from_file() {
+        let mut file = tempfile::Builder::new().suffix(".yaml").tempfile().unwrap();
+        write!(
+            file,
+            r#"
+governance_id: JGovernance
+members:
+  - id: EOwner
+    name: Owner
+  - id: EWitness
+    name: Witness
+schemas: []
+"#
+        )
+        .unwrap();
+        let manifest_file = GovernanceManifest::from_file(file.path().to_str().unwrap()).unwrap();
+        assert_eq!(manifest_file, manifest());
+
+        assert!(matches!(
+            GovernanceManifest::from_file("missing.yaml"),
+            Err(NodeError::Config(_))
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_reconcile_governance() {
+        use crate::{api::tests::create_event, node::tests::export_sqlite_api};
+
+        let api = export_sqlite_api(220, vec![]).await;
+        let governance_id = create_event(&api, "", "governance", "manifest").await;
+        let manifest = GovernanceManifest {
+            governance_id: governance_id.clone(),
+            members: Some(vec![
+                json!({ "id": api.get_controller_id(), "name": "Owner" }),
+            ]),
+            roles: None,
+            schemas: None,
+            policies: None,
+        };
+
+        let dry_run = reconcile_governance(&api, &manifest, true).await.unwrap();
+        assert!(!dry_run.patch.is_empty());
+        assert_eq!(dry_run.request_id, None);
+
+        let applied = reconcile_governance(&api, &manifest, false).await.unwrap();
+        assert_eq!(applied.patch, dry_run.patch);
+        assert!(applied.request_id.is_some());
+
+        let not_governance = GovernanceManifest {
+            governance_id: "invalid".to_owned(),
+            ..manifest
+        };
+        assert!(reconcile_governance(&api, &not_governance, true)
+            .await
+            .is_err());
+    }
+}
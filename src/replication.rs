@@ -0,0 +1,564 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Replication.
+//!
+//! One-way bridge from this node to another kore-node, for topologies where the two cannot
+//! reach each other over the ledger network, e.g. an edge node in a DMZ that may only call the
+//! REST API of a core node. The events of the subjects of `[kore.replication]` are read in
+//! order, and each of them is handled as set by its mode:
+//!
+//! | Mode | Remote call | Done when |
+//! |---|---|---|
+//! | `resubmit` | `POST /event-requests` with the signed event request | The remote request finished |
+//! | `verify` | `GET /subjects/{id}/events/{sn}` | The remote event is the same signed event |
+//!
+//! The signature of each event request is verified before it is resubmitted, so the remote
+//! node only gets requests signed by their original signer. Events that create a subject are
+//! not resubmitted: the subject must already exist on the remote node.
+//!
+//! The sequence number of the next event of each subject is kept in the node store, per remote
+//! node, and only moves past an event once the remote node confirmed it. An event may thus be
+//! sent again after a timeout or a restart, but it is never skipped silently. Resubmitted requests
+//! carry an idempotency key, the subject and sequence number of the event, so the remote node
+//! sends each of them once however many times it gets it.
+//!
+//! An event whose signature does not verify, whose request fails on the remote node or does not
+//! finish in time, or that differs on the remote node, is tried again in the next polls. After
+//! `maxAttempts` failures it is given up as a dead letter, listed by
+//! `KoreApi::replication_dead_letters`, and the next events of the subject are replicated. An
+//! unreachable remote node fails no event.
+//!
+//! Each replication of a subject runs under its own trace id, forwarded to the remote node in the
+//! `x-request-id` header, so that the access logs of both nodes share it.
+//!
+
+use std::time::{Duration, Instant};
+
+use kore_base::{signature::Signature as BaseSignature, EventRequest as BaseEventRequest};
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    Client, RequestBuilder, StatusCode,
+};
+use serde::de::DeserializeOwned;
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::{
+    access_log::{new_trace_id, TRACE_ID_HEADER},
+    api::{timestamp_millis, IDEMPOTENCY_KEY_HEADER},
+    database::store::StoreBatch,
+    error::NodeError,
+    model::{
+        EventContentResponse, EventRequestResponse, NodeEventRequest, NodeKoreRequestState,
+        NodeReplicationFailure, NodeRequestOrigin, NodeRequestState, NodeRequestStateWait,
+        NodeSigned, NodeSignedEventRequest,
+    },
+    settings::{ReplicationMode, ReplicationSettings},
+    tasks::NodeTasks,
+    KoreApi,
+};
+
+/// Identity of the replication in the access logs, and source of the resubmitted requests.
+const REPLICATION_SOURCE: &str = "replication";
+
+/// Time allowed to each call to the remote node, a long poll included.
+const CALL_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Seconds of each long poll of the state of a resubmitted request.
+const STATE_WAIT_SECS: u64 = 30;
+
+/// Scope of the replication store holding the failed attempts of the next event of each subject.
+const ATTEMPTS_SCOPE: &str = "attempts";
+
+/// Event of a replicated subject.
+type ReplicatedEvent = NodeSigned<EventContentResponse>;
+
+/// Start replicating the subjects to the remote node, until the node is cancelled.
+///
+/// # Arguments
+///
+/// * `api` - Kore API the events are read from.
+/// * `settings` - Remote node, subjects and mode.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_replication(api: KoreApi, settings: ReplicationSettings, tasks: &NodeTasks) {
+    let cancellation = tasks.token();
+    let api = api
+        .with_cancellation(cancellation.clone())
+        .background(REPLICATION_SOURCE);
+    let replicator = match Replicator::new(api, settings) {
+        Ok(replicator) => replicator,
+        Err(error) => {
+            log::error!("Replication disabled: {}", error);
+            return;
+        }
+    };
+
+    tasks.spawn(async move {
+        log::info!(
+            "Replicating {} subjects to {} ({:?})",
+            replicator.settings.subjects.len(),
+            replicator.settings.remote_url,
+            replicator.settings.mode
+        );
+        let mut interval = interval(replicator.settings.poll_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = cancellation.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            for subject_id in replicator.settings.subjects.iter() {
+                match replicator.replicate(subject_id).await {
+                    Ok(0) => {}
+                    Ok(events) => log::debug!("{} events of {} replicated", events, subject_id),
+                    Err(NodeError::Cancelled) => return,
+                    Err(error) => log::warn!("Replication of {} stopped: {}", subject_id, error),
+                }
+            }
+        }
+    });
+}
+
+/// Client of the remote node for the replicated subjects.
+struct Replicator {
+    api: KoreApi,
+    settings: ReplicationSettings,
+    client: Client,
+}
+
+impl Replicator {
+    /// Create the replicator of a remote node.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InternalApi` - The HTTP client could not be built.
+    ///
+    fn new(api: KoreApi, settings: ReplicationSettings) -> Result<Self, NodeError> {
+        let client = Client::builder()
+            .timeout(CALL_TIMEOUT)
+            .build()
+            .map_err(|error| NodeError::InternalApi(format!("HTTP client: {}", error)))?;
+        Ok(Self {
+            api,
+            settings,
+            client,
+        })
+    }
+
+    /// Replicate the events of a subject after its cursor.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_id` - Replicated subject.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Conflict` - An event is out of order, or differs on the remote node, or its
+    ///   resubmitted request failed there.
+    /// * `NodeError::InvalidParameter` - The signature of an event request does not verify.
+    /// * `NodeError::InternalApi` - The remote node could not be called.
+    /// * `NodeError::Timeout` - A resubmitted request did not finish in time.
+    /// * `NodeError::Database` - The cursor could not be read or written.
+    ///
+    /// Events that fail `max_attempts` times are given up instead, see `fail`.
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - Events replicated, those given up included.
+    ///
+    async fn replicate(&self, subject_id: &str) -> Result<u64, NodeError> {
+        let trace_id = new_trace_id();
+        let cursors = self.api.replication_store(&self.settings.remote_url);
+        let mut next = cursors.get::<u64>(subject_id)?.unwrap_or(0);
+        let events = self
+            .api
+            .with_trace_id(&trace_id)
+            .get_all_events_of_subject(subject_id, next)
+            .await?;
+        let mut replicated = 0;
+        for event in events {
+            if event.content.sn != next {
+                return Err(NodeError::Conflict(format!(
+                    "event {} of {} read while expecting {}",
+                    event.content.sn, subject_id, next
+                )));
+            }
+            let result = match self.settings.mode {
+                ReplicationMode::Resubmit => self.resubmit(&event, &trace_id).await.map(|_| true),
+                ReplicationMode::Verify => self.verify(&event, &trace_id).await,
+            };
+            let mut batch = StoreBatch::default();
+            match result {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(error) if event_failed(&error) => {
+                    if !self.fail(&event, &error, &mut batch)? {
+                        return Err(error);
+                    }
+                }
+                Err(error) => return Err(error),
+            }
+            next += 1;
+            cursors.batch_put(&mut batch, subject_id, &next)?;
+            cursors
+                .scope(ATTEMPTS_SCOPE)
+                .batch_del(&mut batch, subject_id);
+            cursors.write(batch)?;
+            replicated += 1;
+        }
+        Ok(replicated)
+    }
+
+    /// Count a failed attempt of an event, and give it up as a dead letter, in `batch`, once it
+    /// failed `max_attempts` times.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Database` - The attempts could not be read or written.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether the event is given up.
+    ///
+    fn fail(
+        &self,
+        event: &ReplicatedEvent,
+        error: &NodeError,
+        batch: &mut StoreBatch,
+    ) -> Result<bool, NodeError> {
+        let subject_id = &event.content.subject_id;
+        let attempts_store = self
+            .api
+            .replication_store(&self.settings.remote_url)
+            .scope(ATTEMPTS_SCOPE);
+        let attempts = attempts_store
+            .get::<NodeReplicationFailure>(subject_id)?
+            .filter(|failure| failure.sn == event.content.sn)
+            .map_or(0, |failure| failure.attempts);
+        let failure = NodeReplicationFailure {
+            remote_url: self.settings.remote_url.clone(),
+            subject_id: subject_id.clone(),
+            sn: event.content.sn,
+            attempts: attempts + 1,
+            error: error.to_string(),
+            timestamp: timestamp_millis(),
+        };
+        if failure.attempts < self.settings.max_attempts {
+            attempts_store.put(subject_id, &failure)?;
+            return Ok(false);
+        }
+        log::error!(
+            "Event {} of {} given up after {} attempts: {}",
+            failure.sn,
+            subject_id,
+            failure.attempts,
+            failure.error
+        );
+        self.api.dead_letter_store().batch_put(
+            batch,
+            &dead_letter_key(&self.settings.remote_url, subject_id, failure.sn),
+            &failure,
+        )?;
+        Ok(true)
+    }
+
+    /// Send the signed request of an event to the remote node, and follow it until it finishes.
+    async fn resubmit(&self, event: &ReplicatedEvent, trace_id: &str) -> Result<(), NodeError> {
+        let request = resubmitted_request(event)?;
+        let Some(request) = request else {
+            return Ok(());
+        };
+        let body = serde_json::to_vec(&request)
+            .map_err(|error| NodeError::InvalidParameter(format!("event request: {}", error)))?;
+        let response: EventRequestResponse = self
+            .call(
+                self.client
+                    .post(self.url("/event-requests"))
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(IDEMPOTENCY_KEY_HEADER, idempotency_key(event))
+                    .body(body),
+                trace_id,
+            )
+            .await?
+            .ok_or_else(|| NodeError::InternalApi("event request not found".to_owned()))?;
+
+        let deadline = Instant::now() + self.settings.request_timeout;
+        let state_url = self.url(&format!(
+            "/event-requests/{}/state/wait",
+            response.request_id
+        ));
+        let mut last_state = NodeRequestState::Processing;
+        loop {
+            if Instant::now() >= deadline {
+                return Err(NodeError::Timeout);
+            }
+            let wait = NodeRequestStateWait {
+                last_state,
+                timeout: Some(STATE_WAIT_SECS),
+            };
+            let state: Option<NodeKoreRequestState> = self
+                .call(self.client.get(&state_url).query(&wait), trace_id)
+                .await?;
+            // The remote node may not know the request yet.
+            let Some(state) = state else {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            };
+            if state.is_final() {
+                return match state.success {
+                    Some(true) => Ok(()),
+                    _ => Err(NodeError::Conflict(format!(
+                        "request {} of event {} ended {:?} on the remote node",
+                        response.request_id, event.content.sn, state.state
+                    ))),
+                };
+            }
+            last_state = state.state;
+        }
+    }
+
+    /// Check that the remote node holds the same signed event.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether the remote node holds the event, `false` when it does not have it yet.
+    ///
+    async fn verify(&self, event: &ReplicatedEvent, trace_id: &str) -> Result<bool, NodeError> {
+        let url = self.url(&format!(
+            "/subjects/{}/events/{}",
+            event.content.subject_id, event.content.sn
+        ));
+        let Some(remote) = self
+            .call::<ReplicatedEvent>(self.client.get(url), trace_id)
+            .await?
+        else {
+            return Ok(false);
+        };
+        if !same_event(event, &remote) {
+            return Err(NodeError::Conflict(format!(
+                "event {} of {} differs on the remote node",
+                event.content.sn, event.content.subject_id
+            )));
+        }
+        Ok(true)
+    }
+
+    /// Send a call to the remote node under a trace and read its JSON response.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<T>` - Response, `None` when the remote node answers `404 Not Found`.
+    ///
+    async fn call<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+        trace_id: &str,
+    ) -> Result<Option<T>, NodeError> {
+        let request = request.header(TRACE_ID_HEADER, trace_id);
+        let request = if self.settings.token.is_empty() {
+            request
+        } else {
+            request.header(AUTHORIZATION, format!("Bearer {}", self.settings.token))
+        };
+        let remote_error =
+            |error: reqwest::Error| NodeError::InternalApi(format!("remote node: {}", error));
+        let response = request.send().await.map_err(remote_error)?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body = response
+            .error_for_status()
+            .map_err(remote_error)?
+            .bytes()
+            .await
+            .map_err(remote_error)?;
+        serde_json::from_slice(&body)
+            .map(Some)
+            .map_err(|error| NodeError::InternalApi(format!("remote node response: {}", error)))
+    }
+
+    /// URL of a route of the remote node.
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.settings.remote_url.trim_end_matches('/'), path)
+    }
+}
+
+/// Request that resubmits an event, once its signature is verified.
+///
+/// # Errors
+///
+/// * `NodeError::InvalidParameter` - The signature of the event request does not verify.
+///
+/// # Returns
+///
+/// * `Option<NodeSignedEventRequest>` - Request, `None` for the events that create a subject.
+///
+fn resubmitted_request(
+    event: &ReplicatedEvent,
+) -> Result<Option<NodeSignedEventRequest>, NodeError> {
+    let signed = &event.content.event_request;
+    if matches!(signed.content, NodeEventRequest::Create(_)) {
+        return Ok(None);
+    }
+    let invalid = || {
+        NodeError::InvalidParameter(format!(
+            "signature of event {} of {}",
+            event.content.sn, event.content.subject_id
+        ))
+    };
+    let content = BaseEventRequest::try_from(signed.content.clone()).map_err(|_| invalid())?;
+    BaseSignature::try_from(signed.signature.clone())?
+        .verify(&content)
+        .map_err(|_| invalid())?;
+    Ok(Some(NodeSignedEventRequest {
+        request: signed.content.clone(),
+        signature: Some(signed.signature.clone()),
+        origin: Some(NodeRequestOrigin {
+            source: Some(REPLICATION_SOURCE.to_owned()),
+            device_id: None,
+            geo_hint: None,
+        }),
+    }))
+}
+
+/// Whether an error is a failure of the replicated event, counted towards `max_attempts`,
+/// rather than of the remote node or of this one.
+fn event_failed(error: &NodeError) -> bool {
+    matches!(
+        error,
+        NodeError::Conflict(_) | NodeError::InvalidParameter(_) | NodeError::Timeout
+    )
+}
+
+/// Idempotency key of the resubmitted request of an event.
+fn idempotency_key(event: &ReplicatedEvent) -> String {
+    format!(
+        "{}:{}:{}",
+        REPLICATION_SOURCE, event.content.subject_id, event.content.sn
+    )
+}
+
+/// Key of a dead letter, ordered by remote node, subject and sequence number.
+fn dead_letter_key(remote_url: &str, subject_id: &str, sn: u64) -> String {
+    format!("{} {} {:020}", remote_url, subject_id, sn)
+}
+
+/// Whether two events are the same signed event.
+fn same_event(local: &ReplicatedEvent, remote: &ReplicatedEvent) -> bool {
+    local.signature == remote.signature
+        && local.content.subject_id == remote.content.subject_id
+        && local.content.sn == remote.content.sn
+        && local.content.state_hash == remote.content.state_hash
+        && local.content.hash_prev_event == remote.content.hash_prev_event
+        && local.content.event_request.signature == remote.content.event_request.signature
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn event(sn: u64, state_hash: &str) -> ReplicatedEvent {
+        let signature = serde_json::json!({
+            "signer": "EnyisBz0lX9sRvvV0H-BXTrVtARjUa0YDHzaxFHWH-N4",
+            "timestamp": 1,
+            "value": "SEYml_XhryHvxRylu023oyR0nIjlwVCyw2ZC_Tgvf9gH5ChnCqG9cSDB3Fo3a6jBIhO7Cxg9DDeIZn1Ej-VNXRCg",
+            "content_hash": "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+        });
+        serde_json::from_value(serde_json::json!({
+            "subject_id": "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+            "event_request": {
+                "Fact": {
+                    "subject_id": "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE",
+                    "payload": {},
+                },
+                "signature": signature,
+            },
+            "gov_version": 0,
+            "sn": sn,
+            "patch": [],
+            "state_hash": state_hash,
+            "eval_success": true,
+            "appr_required": false,
+            "approved": true,
+            "hash_prev_event": "",
+            "evaluators": [],
+            "approvers": [],
+            "signature": signature,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_same_event() {
+        let local = event(1, "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE");
+        assert!(same_event(&local, &local.clone()));
+        assert!(!same_event(
+            &local,
+            &event(2, "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE")
+        ));
+        assert!(!same_event(
+            &local,
+            &event(1, "Jp7_QFbTOlFCv-NhDrFMsFLDd2LVSNwoQjBYpEw0Fp3E")
+        ));
+    }
+
+    #[test]
+    fn test_event_failed() {
+        assert!(event_failed(&NodeError::Conflict("differs".to_owned())));
+        assert!(event_failed(&NodeError::Timeout));
+        // The remote node is down: the event is tried again without counting an attempt.
+        assert!(!event_failed(&NodeError::InternalApi(
+            "remote node: connection refused".to_owned()
+        )));
+        assert!(dead_letter_key("http://core", "Ja", 9) < dead_letter_key("http://core", "Ja", 10));
+    }
+
+    #[cfg(all(feature = "sqlite", feature = "http-api"))]
+    #[tokio::test]
+    async fn test_sqlite_replication_verify() {
+        use crate::{api::tests::create_event, node::tests::export_sqlite_api};
+        use std::net::SocketAddr;
+
+        let api = export_sqlite_api(230, vec![]).await;
+        let governance_id = create_event(&api, "", "governance", "replicated").await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let routes = crate::http_api::routes(api.clone(), &Default::default(), Default::default());
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                routes.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+        });
+
+        // The node is its own remote, so every event is already there.
+        let settings = ReplicationSettings {
+            remote_url: format!("http://{}", address),
+            subjects: vec![governance_id.clone()],
+            mode: ReplicationMode::Verify,
+            ..Default::default()
+        };
+        let replicator = Replicator::new(api.clone(), settings.clone()).unwrap();
+        assert_eq!(replicator.replicate(&governance_id).await.unwrap(), 1);
+        assert_eq!(replicator.replicate(&governance_id).await.unwrap(), 0);
+        let cursor = api
+            .replication_store(&settings.remote_url)
+            .get::<u64>(&governance_id)
+            .unwrap();
+        assert_eq!(cursor, Some(1));
+
+        // An unreachable remote node stops the subject, which is read again in the next poll.
+        let settings = ReplicationSettings {
+            remote_url: "http://127.0.0.1:1".to_owned(),
+            ..settings
+        };
+        let replicator = Replicator::new(api, settings).unwrap();
+        assert!(matches!(
+            replicator.replicate(&governance_id).await,
+            Err(NodeError::InternalApi(_))
+        ));
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Scheduler.
+//!
+//! Runs the node actions configured under `kore.schedules` at a fixed interval, for edge devices
+//! that would otherwise need an external cron calling the node. Each schedule runs in its own
+//! task until the node is cancelled; a run that takes longer than the interval is aborted, so
+//! runs of a schedule never overlap.
+//!
+
+#[cfg(feature = "export")]
+use crate::export::export_governance;
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
+use crate::{
+    error::NodeError,
+    model::{
+        NodeEventRequest, NodeFactRequest, NodeGetApprovals, NodeRequestOrigin,
+        NodeSignedEventRequest,
+    },
+    settings::{Schedule, ScheduledAction},
+    tasks::NodeTasks,
+    KoreApi,
+};
+
+/// Source of the requests sent by the scheduler.
+const SCHEDULER_SOURCE: &str = "scheduler";
+
+/// Start the schedules, which stop when the node is cancelled.
+/// Schedules without interval are skipped.
+///
+/// # Arguments
+///
+/// * `api` - Kore API.
+/// * `schedules` - Schedules to run.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_schedules(api: KoreApi, schedules: Vec<Schedule>, tasks: &NodeTasks) {
+    for schedule in schedules {
+        if schedule.interval.is_zero() {
+            log::error!("Schedule {} skipped: its interval is zero", schedule.name);
+            continue;
+        }
+        let cancellation = tasks.token();
+        let api = api
+            .with_cancellation(cancellation.clone())
+            .background(&format!("{}:{}", SCHEDULER_SOURCE, schedule.name));
+        tasks.spawn(async move {
+            let mut interval = interval_at(Instant::now() + schedule.interval, schedule.interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            loop {
+                tokio::select! {
+                    _ = cancellation.cancelled() => break,
+                    _ = interval.tick() => {}
+                }
+                let api = api.with_timeout(schedule.interval);
+                match run_action(&api, &schedule.action).await {
+                    Ok(result) => log::info!("Schedule {}: {}", schedule.name, result),
+                    Err(error) => log::warn!("Schedule {} failed: {}", schedule.name, error),
+                }
+            }
+        });
+    }
+}
+
+/// Run a scheduled action.
+///
+/// # Arguments
+///
+/// * `api` - Kore API.
+/// * `action` - Action to run.
+///
+/// # Errors
+///
+/// * `NodeError` - The API call failed.
+///
+/// # Returns
+///
+/// * `String` - Summary of the result, for the logs.
+///
+pub async fn run_action(api: &KoreApi, action: &ScheduledAction) -> Result<String, NodeError> {
+    match action {
+        ScheduledAction::Fact {
+            subject_id,
+            payload,
+        } => {
+            let response = api
+                .send_event_request(NodeSignedEventRequest {
+                    request: NodeEventRequest::Fact(NodeFactRequest {
+                        subject_id: subject_id.clone(),
+                        payload: payload.clone(),
+                    }),
+                    signature: None,
+                    origin: Some(NodeRequestOrigin {
+                        source: Some(SCHEDULER_SOURCE.to_owned()),
+                        device_id: None,
+                        geo_hint: None,
+                    }),
+                })
+                .await?;
+            Ok(format!("fact request {} sent", response.request_id))
+        }
+        ScheduledAction::Verify { subject_id } => {
+            let proof = api.get_validation_proof(subject_id).await?;
+            Ok(format!(
+                "subject {} verified at sn {}",
+                subject_id, proof.proof.sn
+            ))
+        }
+        ScheduledAction::Report => {
+            let pending = api
+                .get_approvals(NodeGetApprovals {
+                    status: Some("pending".to_owned()),
+                    from: None,
+                    quantity: None,
+                })
+                .await?;
+            Ok(format!(
+                "controller {}, {} pending approvals",
+                api.get_controller_id(),
+                pending.items.len()
+            ))
+        }
+        ScheduledAction::Compact => {
+            let compaction = api.compact_db().await?;
+            Ok(format!(
+                "database compacted from {} to {} bytes",
+                compaction.disk_size_before, compaction.disk_size_after
+            ))
+        }
+        #[cfg(feature = "export")]
+        ScheduledAction::Export(export) => {
+            let report = export_governance(api, export).await?;
+            Ok(format!(
+                "{} events of {} subjects exported to {}",
+                report.events, report.subjects, report.location
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::build::{build_config, ConfigPrecedence};
+    use serde_json::json;
+    use std::{io::Write, time::Duration};
+
+    #[test]
+    fn test_schedules_from_file() {
+        let mut file = tempfile::Builder::new().suffix(".yaml").tempfile().unwrap();
+        write!(
+            file,
+            r#"
+kore:
+  schedules:
+    - name: heartbeat
+      interval: 5m
+      action:
+        type: fact
+        subject_id: JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE
+        payload:
+          status: up
+    - name: report
+      interval: 3600
+      action:
+        type: report
+    - name: compact
+      interval: 1d
+      action:
+        type: compact
+"#
+        )
+        .unwrap();
+
+        let settings =
+            build_config(false, file.path().to_str().unwrap(), ConfigPrecedence::File).unwrap();
+        assert_eq!(
+            settings.schedules,
+            vec![
+                Schedule {
+                    name: "heartbeat".to_owned(),
+                    interval: Duration::from_secs(300),
+                    action: ScheduledAction::Fact {
+                        subject_id: "JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE".to_owned(),
+                        payload: json!({"status": "up"}),
+                    },
+                },
+                Schedule {
+                    name: "report".to_owned(),
+                    interval: Duration::from_secs(3600),
+                    action: ScheduledAction::Report,
+                },
+                Schedule {
+                    name: "compact".to_owned(),
+                    interval: Duration::from_secs(86400),
+                    action: ScheduledAction::Compact,
+                },
+            ]
+        );
+    }
+}
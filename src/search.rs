@@ -0,0 +1,486 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Subject search.
+//!
+//! Finds the subjects known to the node by namespace, schema, owner, governance, active flag,
+//! JSONPath expressions over their properties and text, see `KoreApi::search_subjects`. A search
+//! reads the subjects of every [secondary index](../database/index/index.html) it filters on,
+//! keeps the ones found in all of them, and only checks the properties and the text of those.
+//!
+//! The indexes follow the subjects with a task that rewrites the ones Kore Base writes an event
+//! or the subject itself to, as recorded by `LedgerCommits`, right after the write. The task
+//! catches up with every subject when it starts, and again after an update fails.
+//!
+//! The JSONPath expressions are a subset: `$`, then any of `.name`, `['name']`, `[index]`, `.*`
+//! and `[*]`, then optionally `==`, `!=`, `<`, `<=`, `>` or `>=` and a JSON value. Numbers and
+//! strings are ordered, other values are only equal or not. An expression matches when any of
+//! the values it selects matches, and one without comparison when the path exists:
+//!
+//! ```text
+//! $.grape == "malbec"
+//! $.harvest.year >= 2020
+//! $.labels[*] == "organic"
+//! $.certificate
+//! ```
+//!
+
+use std::{cmp::Ordering, time::Duration};
+
+use serde_json::Value;
+
+use crate::{
+    database::{
+        index::{IndexKey, SubjectIndex},
+        ledger::LedgerCommits,
+    },
+    error::NodeError,
+    model::{NodeSubjectData, NodeSubjectSearch, Page},
+    tasks::NodeTasks,
+    KoreApi,
+};
+
+/// Time before the index catches up with every subject after an update failed.
+const RESYNC_DELAY: Duration = Duration::from_secs(5);
+
+/// Identity of the index updates in the access logs.
+const INDEX_SOURCE: &str = "search";
+
+impl SubjectIndex {
+    /// Subjects that match a filter, by subject id.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Filter of the search, with the page to read.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - A JSONPath expression is not valid.
+    /// * `NodeError::Database` - The index could not be read.
+    ///
+    /// # Returns
+    ///
+    /// * `Page<NodeSubjectData>` - Page of subjects, not archived, the cursor is a subject id.
+    ///
+    pub fn search(&self, filter: &NodeSubjectSearch) -> Result<Page<NodeSubjectData>, NodeError> {
+        let expressions = filter
+            .properties
+            .iter()
+            .map(|expression| {
+                PropertyFilter::parse(expression).map_err(|error| {
+                    NodeError::InvalidParameter(format!("properties {}: {}", expression, error))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let text = filter.text.as_ref().map(|text| text.to_lowercase());
+
+        let keys = [
+            (IndexKey::Namespace, filter.namespace.clone()),
+            (IndexKey::Schema, filter.schema_id.clone()),
+            (IndexKey::Owner, filter.owner.clone()),
+            (IndexKey::Governance, filter.governance_id.clone()),
+            (
+                IndexKey::Active,
+                filter.active.map(|active| active.to_string()),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(index, value)| value.map(|value| (index, value)))
+        .collect::<Vec<_>>();
+        let subjects: Box<dyn Iterator<Item = Result<NodeSubjectData, NodeError>>> =
+            match self.lookup(&keys)? {
+                Some(candidates) => Box::new(
+                    candidates
+                        .into_iter()
+                        .filter_map(|subject_id| self.get(&subject_id).transpose()),
+                ),
+                None => Box::new(self.all()?.into_iter().map(Ok)),
+            };
+
+        let first = filter.from.is_none();
+        let quantity = Page::<NodeSubjectData>::read_quantity(filter.quantity);
+        let mut found = vec![];
+        for subject in subjects {
+            let subject = subject?;
+            if filter
+                .from
+                .as_ref()
+                .is_some_and(|from| subject.subject_id <= *from)
+            {
+                continue;
+            }
+            let matches = expressions
+                .iter()
+                .all(|expression| expression.matches(&subject.properties))
+                && text.as_ref().is_none_or(|text| {
+                    subject.name.to_lowercase().contains(text)
+                        || contains_text(&subject.properties, text)
+                });
+            if matches {
+                found.push(subject);
+                if quantity.is_some_and(|quantity| found.len() as i64 >= quantity) {
+                    break;
+                }
+            }
+        }
+        Ok(Page::cut(found, first, filter.quantity, |subject| {
+            subject.subject_id.clone()
+        }))
+    }
+}
+
+/// Whether a string of `value` contains `text`, which is lowercase.
+fn contains_text(value: &Value, text: &str) -> bool {
+    match value {
+        Value::String(value) => value.to_lowercase().contains(text),
+        Value::Array(values) => values.iter().any(|value| contains_text(value, text)),
+        Value::Object(values) => values.values().any(|value| contains_text(value, text)),
+        _ => false,
+    }
+}
+
+/// Keep the index of the subjects up to date, until the node is cancelled.
+///
+/// # Arguments
+///
+/// * `api` - Kore API, with the indexes of the subjects.
+/// * `commits` - Subjects written by Kore Base.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_subject_indexer(api: KoreApi, commits: LedgerCommits, tasks: &NodeTasks) {
+    let cancellation = tasks.token();
+    let api = api
+        .with_cancellation(cancellation.clone())
+        .background(INDEX_SOURCE);
+    tasks.spawn(async move {
+        // Subjects may have been written after the last update, e.g. before a crash.
+        let mut resync = true;
+        loop {
+            if resync {
+                match api.index_subjects().await {
+                    Ok(updated) => {
+                        resync = false;
+                        log::debug!("Subject index caught up, {} subjects", updated);
+                    }
+                    Err(NodeError::Cancelled) => break,
+                    Err(error) => log::warn!("Subject index not caught up: {}", error),
+                }
+            }
+            let subject_ids = tokio::select! {
+                _ = cancellation.cancelled() => break,
+                _ = tokio::time::sleep(RESYNC_DELAY), if resync => continue,
+                subject_ids = commits.changed() => subject_ids,
+            };
+            match api.index_changed(subject_ids).await {
+                Ok(updated) => log::trace!("Subject index updated, {} subjects", updated),
+                Err(NodeError::Cancelled) => break,
+                Err(error) => {
+                    log::warn!("Subject index not updated: {}", error);
+                    resync = true;
+                }
+            }
+        }
+    });
+}
+
+/// Step of a JSONPath expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// Member of an object.
+    Field(String),
+    /// Item of an array.
+    Index(usize),
+    /// Every member or item.
+    Wildcard,
+}
+
+/// Comparison of a JSONPath expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Operators of the comparisons, the longest first.
+const COMPARISONS: [(&str, Comparison); 6] = [
+    ("==", Comparison::Eq),
+    ("!=", Comparison::Ne),
+    ("<=", Comparison::Le),
+    (">=", Comparison::Ge),
+    ("<", Comparison::Lt),
+    (">", Comparison::Gt),
+];
+
+/// JSONPath expression over the properties of a subject.
+#[derive(Debug, Clone, PartialEq)]
+struct PropertyFilter {
+    path: Vec<Segment>,
+    comparison: Option<(Comparison, Value)>,
+}
+
+impl PropertyFilter {
+    /// Parse an expression, see the module documentation.
+    fn parse(expression: &str) -> Result<Self, String> {
+        let mut rest = expression
+            .trim()
+            .strip_prefix('$')
+            .ok_or("the path must start with $")?;
+        let mut path = vec![];
+        loop {
+            if let Some(after) = rest.strip_prefix(".*") {
+                path.push(Segment::Wildcard);
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('.') {
+                let end = after
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+                    .unwrap_or(after.len());
+                if end == 0 {
+                    return Err("empty member name".to_owned());
+                }
+                path.push(Segment::Field(after[..end].to_owned()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or("unclosed [")?;
+                let inner = after[..end].trim();
+                let quoted = inner
+                    .strip_prefix('\'')
+                    .and_then(|name| name.strip_suffix('\''))
+                    .or_else(|| {
+                        inner
+                            .strip_prefix('"')
+                            .and_then(|name| name.strip_suffix('"'))
+                    });
+                path.push(match quoted {
+                    Some(name) => Segment::Field(name.to_owned()),
+                    None if inner == "*" => Segment::Wildcard,
+                    None => Segment::Index(
+                        inner
+                            .parse()
+                            .map_err(|_| format!("invalid index {}", inner))?,
+                    ),
+                });
+                rest = &after[end + 1..];
+            } else {
+                break;
+            }
+        }
+        let rest = rest.trim();
+        if rest.is_empty() {
+            return Ok(Self {
+                path,
+                comparison: None,
+            });
+        }
+        let (comparison, value) = COMPARISONS
+            .iter()
+            .find_map(|(operator, comparison)| {
+                rest.strip_prefix(operator)
+                    .map(|value| (*comparison, value.trim()))
+            })
+            .ok_or_else(|| format!("unexpected {}", rest))?;
+        let value =
+            serde_json::from_str(value).map_err(|_| format!("invalid JSON value {}", value))?;
+        Ok(Self {
+            path,
+            comparison: Some((comparison, value)),
+        })
+    }
+
+    /// Whether any value selected by the path matches.
+    fn matches(&self, properties: &Value) -> bool {
+        let mut selected = vec![properties];
+        for segment in &self.path {
+            selected = selected
+                .into_iter()
+                .flat_map(|value| match (segment, value) {
+                    (Segment::Field(name), Value::Object(members)) => {
+                        members.get(name).into_iter().collect()
+                    }
+                    (Segment::Index(index), Value::Array(items)) => {
+                        items.get(*index).into_iter().collect()
+                    }
+                    (Segment::Wildcard, Value::Array(items)) => items.iter().collect(),
+                    (Segment::Wildcard, Value::Object(members)) => members.values().collect(),
+                    _ => vec![],
+                })
+                .collect();
+        }
+        selected.into_iter().any(|value| match &self.comparison {
+            None => !value.is_null(),
+            Some((comparison, expected)) => compare(value, *comparison, expected),
+        })
+    }
+}
+
+/// Whether `value` compares to `expected` as asked.
+fn compare(value: &Value, comparison: Comparison, expected: &Value) -> bool {
+    let ordering = match (value, expected) {
+        (Value::Number(value), Value::Number(expected)) => value
+            .as_f64()
+            .zip(expected.as_f64())
+            .and_then(|(value, expected)| value.partial_cmp(&expected)),
+        (Value::String(value), Value::String(expected)) => Some(value.cmp(expected)),
+        _ => (value == expected).then_some(Ordering::Equal),
+    };
+    match comparison {
+        Comparison::Eq => ordering == Some(Ordering::Equal),
+        Comparison::Ne => ordering != Some(Ordering::Equal),
+        Comparison::Lt => ordering == Some(Ordering::Less),
+        Comparison::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        Comparison::Gt => ordering == Some(Ordering::Greater),
+        Comparison::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_property_filter() {
+        let properties = json!({
+            "grape": "malbec",
+            "harvest": { "year": 2021, "region": "Mendoza" },
+            "labels": ["organic", "reserve"],
+            "bottles": 1200.0,
+            "certificate": null,
+        });
+        let matches = |expression: &str| {
+            PropertyFilter::parse(expression)
+                .unwrap()
+                .matches(&properties)
+        };
+        assert!(matches("$.grape == \"malbec\""));
+        assert!(!matches("$.grape != \"malbec\""));
+        assert!(matches("$.harvest.year >= 2020"));
+        assert!(matches("$['harvest'][\"region\"] < \"Salta\""));
+        assert!(!matches("$.harvest.year > 2021"));
+        assert!(matches("$.labels[*] == \"organic\""));
+        assert!(matches("$.labels[1] == \"reserve\""));
+        assert!(!matches("$.labels[2]"));
+        assert!(matches("$.bottles == 1200"));
+        assert!(matches("$.* == \"malbec\""));
+        assert!(matches("$.harvest"));
+        assert!(!matches("$.certificate"));
+        assert!(matches("$.grape != 3"));
+        assert!(!matches("$.grape < 3"));
+
+        for invalid in [
+            "grape",
+            "$.",
+            "$[1",
+            "$.grape ~ 1",
+            "$.grape == malbec",
+            "$[x]",
+        ] {
+            assert!(PropertyFilter::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_search_index() {
+        use std::sync::Arc;
+
+        use kore_base::DatabaseManager;
+
+        use crate::database::{index::INDEX_COLLECTION, sqlite::SqliteManager};
+
+        let manager = SqliteManager::default();
+        let index =
+            SubjectIndex::new(Arc::new(manager.create_collection(INDEX_COLLECTION))).unwrap();
+        let subject = |id: &str, schema_id: &str, sn: u64, properties: Value| NodeSubjectData {
+            subject_id: id.to_owned(),
+            governance_id: "JGovernance".to_owned(),
+            sn,
+            public_key: "EKey".to_owned(),
+            namespace: "wines".to_owned(),
+            name: format!("Bottle {}", id),
+            schema_id: schema_id.to_owned(),
+            owner: "EOwner".to_owned(),
+            creator: "EOwner".to_owned(),
+            properties,
+            active: true,
+            archived: false,
+        };
+        let subjects = vec![
+            subject("J1", "wine", 0, json!({ "grape": "malbec" })),
+            subject("J2", "wine", 3, json!({ "grape": "syrah" })),
+            subject("J3", "cheese", 1, json!({ "milk": "goat" })),
+        ];
+        assert_eq!(index.update(&subjects).unwrap().len(), 3);
+        assert!(index.update(&subjects).unwrap().is_empty());
+
+        let ids = |filter: NodeSubjectSearch| {
+            index
+                .search(&filter)
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|subject| subject.subject_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(NodeSubjectSearch::default()), ["J1", "J2", "J3"]);
+        let wines = NodeSubjectSearch {
+            schema_id: Some("wine".to_owned()),
+            namespace: Some("wines".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(ids(wines.clone()), ["J1", "J2"]);
+        assert_eq!(
+            ids(NodeSubjectSearch {
+                properties: vec!["$.grape == \"syrah\"".to_owned()],
+                ..wines.clone()
+            }),
+            ["J2"]
+        );
+        assert_eq!(
+            ids(NodeSubjectSearch {
+                text: Some("GOAT".to_owned()),
+                ..Default::default()
+            }),
+            ["J3"]
+        );
+
+        // A subject that changed moves between the secondary indexes.
+        let mut ended = subject("J1", "wine", 1, json!({ "grape": "malbec" }));
+        ended.active = false;
+        assert_eq!(index.update(&[ended]).unwrap(), ["J1"]);
+        let active = |active| NodeSubjectSearch {
+            active: Some(active),
+            ..wines.clone()
+        };
+        assert_eq!(ids(active(true)), ["J2"]);
+        assert_eq!(ids(active(false)), ["J1"]);
+
+        let page = index
+            .search(&NodeSubjectSearch {
+                quantity: Some(1),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(page.next_cursor.as_deref(), Some("J1"));
+        let next = NodeSubjectSearch {
+            from: page.next_cursor,
+            quantity: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(ids(next), ["J2"]);
+
+        let invalid = NodeSubjectSearch {
+            properties: vec!["grape".to_owned()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            index.search(&invalid),
+            Err(NodeError::InvalidParameter(_))
+        ));
+    }
+}
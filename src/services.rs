@@ -0,0 +1,82 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Peer services.
+//!
+//! Nodes of a consortium advertise the endpoints of their services (REST API, metrics) in a
+//! record served at `GET /services`, signed with the node key pair. Kore Base offers no way to
+//! carry it over the ledger network, so this side channel runs over HTTP: every interval, the
+//! endpoints of the peers in `[kore.services.peers]` are fetched, and kept when the record is
+//! signed by the controller id the peer is trusted as. See `KoreApi::peer_services`.
+//!
+
+use std::time::Duration;
+
+use reqwest::Client;
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::{
+    error::NodeError,
+    model::{NodeServiceRecord, NodeSigned},
+    settings::ServicesSettings,
+    tasks::NodeTasks,
+    KoreApi,
+};
+
+/// Time allowed to each fetch.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start fetching the endpoints of the trusted peers, until the node is cancelled.
+///
+/// # Arguments
+///
+/// * `api` - Kore API, which keeps the endpoints.
+/// * `settings` - Trusted peers and fetch interval.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_peer_services(api: KoreApi, settings: ServicesSettings, tasks: &NodeTasks) {
+    let client = match Client::builder().timeout(FETCH_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(error) => {
+            log::error!("Peer services disabled, the HTTP client failed: {}", error);
+            return;
+        }
+    };
+    let cancellation = tasks.token();
+    tasks.spawn(async move {
+        let mut interval = interval(settings.interval.max(Duration::from_secs(1)));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = cancellation.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            for (controller_id, url) in settings.peers.iter() {
+                let result = match fetch(&client, url).await {
+                    Ok(signed) => api.record_peer_service(controller_id, signed),
+                    Err(error) => Err(error),
+                };
+                if let Err(error) = result {
+                    log::warn!("Services of peer {} not updated: {}", controller_id, error);
+                }
+            }
+        }
+    });
+}
+
+/// Fetch the signed record served by a peer.
+async fn fetch(client: &Client, url: &str) -> Result<NodeSigned<NodeServiceRecord>, NodeError> {
+    let url = format!("{}/services", url.trim_end_matches('/'));
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|error| NodeError::InternalApi(format!("{}: {}", url, error)))?;
+    let body = response
+        .bytes()
+        .await
+        .map_err(|error| NodeError::InternalApi(format!("{}: {}", url, error)))?;
+    serde_json::from_slice(&body)
+        .map_err(|error| NodeError::InvalidParameter(format!("service record: {}", error)))
+}
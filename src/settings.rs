@@ -1,9 +1,25 @@
 // Copyright 2024 Kore Ledger
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
-use kore_base::Settings as BaseSettings;
+use kore_base::{RoutingNode, Settings as BaseSettings};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    config::{
+        render::{write_settings, ConfigFormat},
+        units::deserialize_duration_secs,
+        validate::validate,
+    },
+    error::NodeError,
+};
 
 /// Database settings.
 #[derive(Deserialize, Debug, Clone, PartialEq)]
@@ -14,9 +30,922 @@ pub enum DbSettings {
     /// Configuration for a SQLite database.
     #[cfg(feature = "sqlite")]
     Sqlite(String),
+    /// Configuration for a PostgreSQL database.
+    #[cfg(feature = "postgres")]
+    Postgres {
+        /// Connection string.
+        url: String,
+        /// Maximum number of connections of the pool.
+        pool_size: usize,
+    },
     /// Configuration for a Cassandra database.
     #[cfg(feature = "cassandra")]
     Cassandra,
+    /// Configuration for a sled database.
+    #[cfg(feature = "sled")]
+    Sled(String),
+    /// Configuration for a redb database.
+    #[cfg(feature = "redb")]
+    Redb(String),
+}
+
+/// Directory of the data of the node inside the data directory of the platform.
+pub const DATA_DIR_NAME: &str = "kore-node";
+
+/// Default location of `name` among the data of the node, under `DATA_DIR_NAME` in the data
+/// directory of the platform (e.g. `~/.local/share` on Linux), or in the working directory when
+/// the platform has none.
+pub fn default_data_path(name: &str) -> String {
+    dirs::data_dir()
+        .map(|dir| dir.join(DATA_DIR_NAME))
+        .unwrap_or_else(|| PathBuf::from(DATA_DIR_NAME))
+        .join(name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Database in the data directory of the platform, on the first compiled of LevelDB, SQLite,
+/// PostgreSQL, sled and redb.
+impl Default for DbSettings {
+    #[allow(unreachable_code)]
+    fn default() -> Self {
+        #[cfg(feature = "leveldb")]
+        return DbSettings::LevelDB(default_data_path("leveldb"));
+        #[cfg(feature = "sqlite")]
+        return DbSettings::Sqlite(default_data_path("sqlitedb/database"));
+        #[cfg(feature = "postgres")]
+        return DbSettings::Postgres {
+            url: "postgres://postgres@localhost/kore".to_owned(),
+            pool_size: 4,
+        };
+        #[cfg(feature = "sled")]
+        return DbSettings::Sled(default_data_path("sled"));
+        #[cfg(feature = "redb")]
+        return DbSettings::Redb(default_data_path("redb/database"));
+    }
+}
+
+/// Writes of several keys applied at once, and durability of the writes.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DbBatchSettings {
+    /// Largest number of writes applied at once. Bigger batches are split in parts of this size,
+    /// each of them atomic.
+    #[serde(rename = "maxWrites")]
+    pub max_writes: usize,
+    /// Whether writes reach the disk before returning. LevelDB syncs every write and batch;
+    /// SQLite commits with `synchronous=NORMAL`, or `OFF` when unset. PostgreSQL follows the
+    /// settings of the server.
+    pub sync: bool,
+}
+
+impl Default for DbBatchSettings {
+    fn default() -> Self {
+        Self {
+            max_writes: 1000,
+            sync: true,
+        }
+    }
+}
+
+/// Expiry of the entries of the auxiliary collections, see `KoreApi::expiring_store`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DbTtlSettings {
+    /// Time between sweeps of the expired entries. Zero, expired entries are not read but
+    /// remain in the database.
+    #[serde(rename = "sweepInterval")]
+    pub sweep_interval: Duration,
+    /// Time to live of the entries of each collection, by name. Collections not listed keep
+    /// the time to live given by the code that uses them.
+    pub collections: BTreeMap<String, Duration>,
+}
+
+impl DbTtlSettings {
+    /// Time to live of the entries of a collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - Name of the collection.
+    /// * `default` - Time to live when the collection is not listed.
+    ///
+    pub fn ttl(&self, collection: &str, default: Duration) -> Duration {
+        self.collections.get(collection).copied().unwrap_or(default)
+    }
+}
+
+impl Default for DbTtlSettings {
+    fn default() -> Self {
+        Self {
+            sweep_interval: Duration::from_secs(300),
+            collections: BTreeMap::new(),
+        }
+    }
+}
+
+/// Limit of subjects that an identity may create in a governance.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SubjectQuota {
+    /// Maximum number of subjects per window, 0 disables the quota.
+    #[serde(rename = "maxSubjects")]
+    pub max_subjects: u64,
+    /// Time window in which subjects are counted.
+    pub window: Duration,
+}
+
+impl Default for SubjectQuota {
+    fn default() -> Self {
+        Self {
+            max_subjects: 0,
+            window: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// Checks of the signatures that clients send with their event requests.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SignatureCheck {
+    /// Reject the requests whose signature does not match them, or is too old or too far ahead,
+    /// before sending them to the ledger.
+    pub enabled: bool,
+    /// Age after which a signature is stale.
+    #[serde(rename = "maxAge")]
+    pub max_age: Duration,
+    /// Time a signature may be ahead of the clock of the node, for clock skew.
+    #[serde(rename = "maxFuture")]
+    pub max_future: Duration,
+}
+
+impl Default for SignatureCheck {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_age: Duration::from_secs(5 * 60),
+            max_future: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Timeout and retries of the calls of the node API to Kore Base.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiCallSettings {
+    /// Time allowed to each attempt of a call. Zero, calls wait until Kore Base answers.
+    pub timeout: Duration,
+    /// Attempts after the first one of the calls that read, when the previous one timed out or
+    /// failed inside Kore Base. Calls that write are never retried.
+    pub retries: u32,
+    /// Wait before the first retry, doubled before each of the next ones.
+    pub backoff: Duration,
+}
+
+impl Default for ApiCallSettings {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::ZERO,
+            retries: 0,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Limits of a class of calls to Kore Base.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CallLimit {
+    /// Calls per second. Zero, the rate is not limited.
+    pub rate: f64,
+    /// Calls allowed at once above the rate after a quiet period, at least 1.
+    pub burst: u32,
+    /// Calls running at the same time. Zero, without bound.
+    #[serde(rename = "maxInFlight")]
+    pub max_in_flight: usize,
+}
+
+/// Limits of the calls of the node API, see the `limits` module.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct LimitsSettings {
+    /// Calls that only read.
+    pub reads: CallLimit,
+    /// Calls that send requests, votes, keys or authorizations.
+    pub writes: CallLimit,
+}
+
+/// Identities allowed to sign the requests of some types.
+/// Requests of other types are not restricted.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub struct SigningPolicy {
+    /// Request types restricted: Create, Fact, Transfer or EOL.
+    pub request_types: Vec<String>,
+    /// Key identifiers of the keystore allowed to sign them, including the node controller when
+    /// the node must sign them itself.
+    #[serde(default)]
+    pub signers: Vec<String>,
+    /// Allow any request signed by the caller instead of by the node.
+    #[serde(default)]
+    pub external_signer: bool,
+}
+
+/// Approvals accepted by the `auto_approval` feature flag, see the `features` module.
+/// A rule accepts the approvals of the subjects that match every identifier it gives.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub struct ApprovalRule {
+    /// Governance of the subjects, or the governance itself for the approvals of its own
+    /// changes. Any governance when empty.
+    #[serde(default)]
+    pub governance_id: String,
+    /// Schema of the subjects, `governance` for governances. Any schema when empty.
+    #[serde(default)]
+    pub schema_id: String,
+}
+
+/// Access logs of the served requests.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AccessLogSettings {
+    /// Fraction of the requests logged, from 0 (none) to 1 (all).
+    #[serde(rename = "sampleRate")]
+    pub sample_rate: f64,
+    /// Requests slower than this are always logged.
+    #[serde(rename = "slowThreshold")]
+    pub slow_threshold: Duration,
+}
+
+impl Default for AccessLogSettings {
+    fn default() -> Self {
+        Self {
+            sample_rate: 1.0,
+            slow_threshold: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Format of the log lines.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `<timestamp> <LEVEL> <target>: <message>`.
+    Pretty,
+    /// One JSON object per line, with `timestamp`, `level`, `target` and `message`.
+    Json,
+}
+
+/// Logs of the node, written by the logger that the node builders install.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct LoggingSettings {
+    /// Level of the targets without override: off, error, warn, info, debug or trace.
+    pub level: String,
+    /// Levels of some targets and their submodules, e.g. `kore_base = "warn"`.
+    pub targets: BTreeMap<String, String>,
+    /// Format of the lines.
+    pub format: LogFormat,
+    /// File the logs are appended to. Empty, they are written to the standard error.
+    pub file: String,
+    /// Size in bytes at which the file is rotated. 0, it is never rotated.
+    #[serde(rename = "maxFileSize")]
+    pub max_file_size: u64,
+    /// Rotated files kept, as `<file>.1` (the newest) to `<file>.<maxFiles>`.
+    #[serde(rename = "maxFiles")]
+    pub max_files: u32,
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        Self {
+            level: "info".to_owned(),
+            targets: BTreeMap::new(),
+            format: LogFormat::Pretty,
+            file: String::default(),
+            max_file_size: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+/// Tokens of the API surfaces, checked by the REST and gRPC servers, see the `surface` module.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ApiAuthSettings {
+    /// Bearer token of the public surface. Empty, any client reaches it.
+    #[serde(rename = "publicToken")]
+    pub public_token: String,
+    /// Bearer token of the admin surface. Empty, only loopback TCP clients reach it, not the
+    /// clients of a Unix socket.
+    #[serde(rename = "adminToken")]
+    pub admin_token: String,
+}
+
+/// Credentials required by the HTTP endpoints of the node, the metrics and the REST API, on top
+/// of the tokens of the surfaces. See the `auth` module.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct AuthSettings {
+    /// Static API keys, any of them accepted in the `x-api-key` header.
+    #[serde(rename = "apiKeys")]
+    pub api_keys: Vec<String>,
+    /// Required `iss` claim of the JWTs. Empty, any issuer.
+    pub issuer: String,
+    /// Audience the JWTs must be issued for, in their `aud` claim. Empty, any audience.
+    pub audience: String,
+    /// URL of the JWK set with the keys that sign the JWTs (`jwt` feature). Empty, JWTs are not
+    /// accepted.
+    #[serde(rename = "jwksUrl")]
+    pub jwks_url: String,
+}
+
+impl AuthSettings {
+    /// Whether the endpoints require credentials.
+    pub fn is_enabled(&self) -> bool {
+        !self.api_keys.is_empty() || !self.jwks_url.is_empty()
+    }
+}
+
+/// gRPC server (`grpc` feature).
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct GrpcSettings {
+    /// TcpListener of the server. Empty, the server is not started.
+    pub listen: String,
+    /// PEM certificate chain. TLS is enabled when it is set, along with its key.
+    #[serde(rename = "tlsCert")]
+    pub tls_cert: String,
+    /// PEM private key of the certificate.
+    #[serde(rename = "tlsKey")]
+    pub tls_key: String,
+}
+
+/// Webhooks notified when an approval is pending and when a request finishes or fails.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookSettings {
+    /// Endpoints that receive the notifications. Empty, no notification is sent.
+    pub urls: Vec<String>,
+    /// Key of the HMAC-SHA256 signature of each payload. Empty, payloads are not signed.
+    pub secret: String,
+    /// Attempts after a failed delivery.
+    #[serde(rename = "maxRetries")]
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled after each attempt.
+    #[serde(rename = "retryBackoff")]
+    pub retry_backoff: Duration,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self {
+            urls: vec![],
+            secret: String::default(),
+            max_retries: 3,
+            retry_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Metrics pushed periodically (`metrics-push` feature), for nodes behind NAT that cannot be
+/// scraped. They are the ones served on `/metrics`, even when `prometheus` is empty.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct MetricsPushSettings {
+    /// Base URL of the Pushgateway, or URL of the remote-write endpoint. Empty, the metrics are
+    /// not pushed.
+    pub url: String,
+    /// Protocol of the endpoint.
+    pub mode: MetricsPushMode,
+    /// Time between two pushes.
+    pub interval: Duration,
+    /// `job` label of the pushed metrics.
+    pub job: String,
+    /// Other labels of the pushed metrics: the grouping key of the node in a Pushgateway, or
+    /// labels added to every series sent by remote write.
+    pub labels: BTreeMap<String, String>,
+    /// User of the basic authentication. Empty, requests carry no `Authorization` header.
+    pub username: String,
+    /// Password of the basic authentication.
+    pub password: String,
+}
+
+impl MetricsPushSettings {
+    /// Whether the metrics are pushed.
+    pub fn is_enabled(&self) -> bool {
+        !self.url.is_empty()
+    }
+}
+
+impl Default for MetricsPushSettings {
+    fn default() -> Self {
+        Self {
+            url: String::default(),
+            mode: MetricsPushMode::Pushgateway,
+            interval: Duration::from_secs(15),
+            job: "kore-node".to_owned(),
+            labels: BTreeMap::new(),
+            username: String::default(),
+            password: String::default(),
+        }
+    }
+}
+
+/// Protocol of the endpoint the metrics are pushed to.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsPushMode {
+    /// `PUT` of the text exposition format to a Prometheus Pushgateway, which replaces the
+    /// metrics of the grouping key of the node.
+    Pushgateway,
+    /// Prometheus remote-write protocol, accepted by Prometheus, Mimir or VictoriaMetrics.
+    RemoteWrite,
+}
+
+/// Boot nodes sharing a label, such as a region or a provider.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BootGroup {
+    /// Label of the group, e.g. `eu-west`.
+    pub label: String,
+    /// Boot nodes of the group.
+    #[serde(rename = "bootNodes")]
+    pub boot_nodes: Vec<RoutingNode>,
+}
+
+/// Groups of boot nodes tried in order when the node starts, see the `bootstrap` module.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BootstrapSettings {
+    /// Groups in failover order, the nearest first. Empty, only the boot nodes of the routing
+    /// settings are used.
+    pub groups: Vec<BootGroup>,
+    /// Time allowed to reach a boot node of a group, whose boot nodes are probed at once.
+    #[serde(rename = "probeTimeout")]
+    pub probe_timeout: Duration,
+    /// Time between two health checks of the groups.
+    #[serde(rename = "healthInterval")]
+    pub health_interval: Duration,
+    /// Time between two resolutions of the boot node names while the node runs. Zero, they are
+    /// resolved only when the node starts.
+    #[serde(rename = "dnsRefresh")]
+    pub dns_refresh: Duration,
+}
+
+impl Default for BootstrapSettings {
+    fn default() -> Self {
+        Self {
+            groups: vec![],
+            probe_timeout: Duration::from_secs(3),
+            health_interval: Duration::from_secs(30),
+            dns_refresh: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Service endpoints advertised to trusted peers, and the peers whose endpoints are fetched,
+/// see the `services` module.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ServicesSettings {
+    /// URL of the REST API advertised to peers. Empty, it is not advertised.
+    #[serde(rename = "restUrl")]
+    pub rest_url: String,
+    /// URL of the metrics advertised to peers. Empty, it is not advertised.
+    #[serde(rename = "metricsUrl")]
+    pub metrics_url: String,
+    /// Trusted peers: controller id and base URL of the REST API, serving `/services`.
+    pub peers: BTreeMap<String, String>,
+    /// Time between two fetches of the endpoints of the peers.
+    pub interval: Duration,
+}
+
+impl Default for ServicesSettings {
+    fn default() -> Self {
+        Self {
+            rest_url: String::default(),
+            metrics_url: String::default(),
+            peers: BTreeMap::new(),
+            interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Subjects read when the node starts, before its APIs are served, so that the first reads of
+/// dashboards find the database caches warm.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct WarmUpSettings {
+    /// Subjects always warmed up.
+    pub subjects: Vec<String>,
+    /// Number of subjects with the most recent requests warmed up, besides the configured ones.
+    pub recent: usize,
+    /// Longest time the APIs wait for the warm-up.
+    pub timeout: Duration,
+}
+
+impl WarmUpSettings {
+    /// Whether there is any subject to warm up.
+    pub fn is_enabled(&self) -> bool {
+        !self.subjects.is_empty() || self.recent > 0
+    }
+}
+
+impl Default for WarmUpSettings {
+    fn default() -> Self {
+        Self {
+            subjects: vec![],
+            recent: 0,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Restarts of the node after a panic or a fatal error, see `node::Supervisor`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SupervisorSettings {
+    /// Whether `kore-node run` rebuilds the node when it fails instead of exiting.
+    pub enabled: bool,
+    /// Restarts allowed within `window`; one more failure stops the node for good.
+    #[serde(rename = "maxRestarts")]
+    pub max_restarts: u32,
+    /// Time over which the restarts are counted.
+    pub window: Duration,
+    /// Wait before the first restart, doubled for each restart within `window`.
+    pub backoff: Duration,
+    /// Longest wait before a restart.
+    #[serde(rename = "maxBackoff")]
+    pub max_backoff: Duration,
+}
+
+impl Default for SupervisorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_restarts: 5,
+            window: Duration::from_secs(600),
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Load generated against the node itself to validate its sizing, see the `soak` module.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SoakSettings {
+    /// Event requests sent per second. 0, no load is generated.
+    pub rate: u32,
+    /// Longest time the load runs. 0, until the node stops.
+    pub duration: Duration,
+    /// Governance of the created subjects. Empty, governances are created.
+    #[serde(rename = "governanceId")]
+    pub governance_id: String,
+    /// Schema of the created subjects.
+    #[serde(rename = "schemaId")]
+    pub schema_id: String,
+    /// Subjects created before the fact events, which are spread over them.
+    pub subjects: usize,
+    /// JSON payload of the fact events, valid for the contract of the schema.
+    pub payload: String,
+    /// Requests followed at once; the requests due beyond it are skipped.
+    #[serde(rename = "maxInFlight")]
+    pub max_in_flight: usize,
+    /// Time between two reports of the load in the logs.
+    #[serde(rename = "reportInterval")]
+    pub report_interval: Duration,
+}
+
+impl SoakSettings {
+    /// Whether load is generated.
+    pub fn is_enabled(&self) -> bool {
+        self.rate > 0
+    }
+}
+
+impl Default for SoakSettings {
+    fn default() -> Self {
+        Self {
+            rate: 0,
+            duration: Duration::ZERO,
+            governance_id: String::default(),
+            schema_id: "governance".to_owned(),
+            subjects: 10,
+            payload: "{}".to_owned(),
+            max_in_flight: 100,
+            report_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Events of local subjects replicated to another kore-node through its REST API, for
+/// topologies where the nodes cannot reach each other over the ledger network, see the
+/// `replication` module.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ReplicationSettings {
+    /// Base URL of the REST API of the remote node. Empty, nothing is replicated.
+    #[serde(rename = "remoteUrl")]
+    pub remote_url: String,
+    /// Bearer token of the remote API. Empty, requests carry no `Authorization` header.
+    pub token: String,
+    /// Subjects whose events are replicated.
+    pub subjects: Vec<String>,
+    /// What is done with each event.
+    pub mode: ReplicationMode,
+    /// Time between two reads of the events of the subjects.
+    #[serde(rename = "pollInterval")]
+    pub poll_interval: Duration,
+    /// Longest time a resubmitted request is followed on the remote node.
+    #[serde(rename = "requestTimeout")]
+    pub request_timeout: Duration,
+    /// Attempts of an event that fails before it is given up as a dead letter and the next
+    /// events are replicated, see `KoreApi::replication_dead_letters`.
+    #[serde(rename = "maxAttempts")]
+    pub max_attempts: u32,
+}
+
+impl ReplicationSettings {
+    /// Whether events are replicated.
+    pub fn is_enabled(&self) -> bool {
+        !self.remote_url.is_empty()
+    }
+}
+
+impl Default for ReplicationSettings {
+    fn default() -> Self {
+        Self {
+            remote_url: String::default(),
+            token: String::default(),
+            subjects: vec![],
+            mode: ReplicationMode::Resubmit,
+            poll_interval: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(120),
+            max_attempts: 5,
+        }
+    }
+}
+
+/// What a replication bridge does with each event of the replicated subjects.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplicationMode {
+    /// Send the signed event request to the remote node, which processes it as if its signer
+    /// had sent it there. Events that create a subject are not sent.
+    Resubmit,
+    /// Check that the remote node holds the same signed event, without sending anything.
+    Verify,
+}
+
+/// Backups of the local database (LevelDB or SQLite), see the `backup` module.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BackupSettings {
+    /// Directory of the scheduled backups. When the database is empty at startup, the newest
+    /// backup of the directory is restored. Empty, there are no scheduled backups nor restores.
+    pub directory: String,
+    /// Time between scheduled backups. Zero, backups are only taken through the API.
+    pub interval: Duration,
+    /// Scheduled backups kept, the oldest are removed.
+    pub keep: usize,
+}
+
+impl BackupSettings {
+    /// Whether backups are taken periodically.
+    pub fn is_scheduled(&self) -> bool {
+        !self.directory.is_empty() && !self.interval.is_zero()
+    }
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self {
+            directory: String::default(),
+            interval: Duration::ZERO,
+            keep: 7,
+        }
+    }
+}
+
+/// Archival of the old events of the subjects to object storage, see the `archival` module.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ArchivalSettings {
+    /// Where the events are archived: a directory, or the URL of a bucket (`s3://`, `gs://`,
+    /// `az://`) with the `object-store` feature. Empty, events are never archived.
+    pub destination: String,
+    /// Events signed longer ago are archived. Zero, events are not archived for their age.
+    pub max_age: Duration,
+    /// Bytes of the events kept in the database, the oldest over it are archived. Zero, events
+    /// are not archived for their size.
+    pub max_size: u64,
+    /// Time between archival runs. Zero, events are only archived through the API.
+    pub interval: Duration,
+}
+
+impl ArchivalSettings {
+    /// Whether events are archived periodically.
+    pub fn is_scheduled(&self) -> bool {
+        !self.destination.is_empty() && !self.interval.is_zero()
+    }
+}
+
+/// Key derivation function used to encrypt the node key files.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyKdf {
+    /// PBKDF2 with HMAC-SHA256.
+    Pbkdf2,
+    /// scrypt, memory hard.
+    Scrypt,
+}
+
+/// Encryption of the node key files, always AES-256-CBC with a random salt and IV per file.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct KeysSettings {
+    /// Key derivation function.
+    pub kdf: KeyKdf,
+    /// PBKDF2 iterations.
+    pub iterations: u32,
+    /// scrypt cost, as the base 2 logarithm of N.
+    #[serde(rename = "scryptLogN")]
+    pub scrypt_log_n: u8,
+    /// scrypt block size.
+    #[serde(rename = "scryptR")]
+    pub scrypt_r: u32,
+    /// scrypt parallelization.
+    #[serde(rename = "scryptP")]
+    pub scrypt_p: u32,
+    /// HashiCorp Vault of the `vault` keys backend.
+    pub vault: VaultSettings,
+}
+
+impl Default for KeysSettings {
+    fn default() -> Self {
+        Self {
+            kdf: KeyKdf::Pbkdf2,
+            iterations: 600_000,
+            scrypt_log_n: 15,
+            scrypt_r: 8,
+            scrypt_p: 1,
+            vault: VaultSettings::default(),
+        }
+    }
+}
+
+/// Where the node key pair is kept.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeysBackend {
+    /// Key file in the keys directory, encrypted as set in `keys`.
+    File,
+    /// Private data object of a PKCS#11 token, such as an HSM (`hsm` feature).
+    Pkcs11,
+    /// HashiCorp Vault (`vault` feature).
+    Vault,
+}
+
+/// Serialization of the timestamps of the model, in API responses and notifications.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampFormat {
+    /// Integer since UNIX epoch.
+    Epoch,
+    /// RFC 3339 string in UTC.
+    Rfc3339,
+    /// Object with both forms, `{"epoch": <integer>, "rfc3339": <string>}`.
+    Both,
+}
+
+/// Vault secrets engine that keeps the node key pair.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VaultEngine {
+    /// KV version 2: the key pair is a secret of the engine.
+    Kv,
+    /// Transit: the key pair is encrypted by the engine and the ciphertext is kept in the keys
+    /// directory as `node_private.vault`.
+    Transit,
+}
+
+/// HashiCorp Vault of the `vault` keys backend. The node logs in with the token or, when it is
+/// empty, with the AppRole credentials.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct VaultSettings {
+    /// Address of the Vault server, e.g. `https://vault.example:8200`.
+    pub address: String,
+    /// Vault token.
+    pub token: String,
+    /// AppRole role id.
+    #[serde(rename = "roleId")]
+    pub role_id: String,
+    /// AppRole secret id.
+    #[serde(rename = "secretId")]
+    pub secret_id: String,
+    /// Secrets engine.
+    pub engine: VaultEngine,
+    /// Mount path of the secrets engine.
+    pub mount: String,
+    /// Path of the KV secret, or name of the Transit key.
+    pub path: String,
+}
+
+impl Default for VaultSettings {
+    fn default() -> Self {
+        Self {
+            address: String::default(),
+            token: String::default(),
+            role_id: String::default(),
+            secret_id: String::default(),
+            engine: VaultEngine::Kv,
+            mount: "secret".to_owned(),
+            path: "kore-node".to_owned(),
+        }
+    }
+}
+
+/// PKCS#11 token of the `pkcs11` keys backend.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Pkcs11Settings {
+    /// PKCS#11 library of the token vendor, e.g. `/usr/lib/softhsm/libsofthsm2.so`.
+    pub module: String,
+    /// Label of the token.
+    pub token: String,
+    /// Label of the key pair of the token and of the data object it encrypts the node key in.
+    pub label: String,
+}
+
+impl Default for Pkcs11Settings {
+    fn default() -> Self {
+        Self {
+            module: String::default(),
+            token: String::default(),
+            label: "kore-node".to_owned(),
+        }
+    }
+}
+
+/// Format of the exported files.
+#[cfg(feature = "export")]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// Comma separated values, with a header row.
+    Csv,
+    /// Apache Parquet.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+/// Value of the event payload exported as a column.
+#[cfg(feature = "export")]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ExportColumn {
+    /// Column name.
+    pub name: String,
+    /// JSON pointer to the value in the payload, e.g. `/temperature`.
+    pub path: String,
+}
+
+/// Export of the events of the subjects of a governance.
+#[cfg(feature = "export")]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ExportSettings {
+    /// Governance identifier.
+    pub governance_id: String,
+    /// File format.
+    pub format: ExportFormat,
+    /// Payload values exported, besides the event metadata.
+    #[serde(default)]
+    pub columns: Vec<ExportColumn>,
+    /// Local directory, or object storage URL (`s3://`, `gs://`, `az://`) with the
+    /// `object-store` feature.
+    pub destination: String,
+    /// Event ranges read at the same time, and read ahead of the next one written to the file.
+    #[serde(default = "default_export_workers")]
+    pub workers: usize,
+    /// Local directory where the progress of the export is kept, so that an interrupted export
+    /// resumes instead of starting again. Empty, no progress is kept.
+    #[serde(default)]
+    pub checkpoint_dir: String,
+}
+
+#[cfg(feature = "export")]
+fn default_export_workers() -> usize {
+    4
+}
+
+/// Node action run periodically.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduledAction {
+    /// Send a fact event, e.g. a heartbeat to a monitoring subject.
+    Fact {
+        /// Subject identifier.
+        subject_id: String,
+        /// Payload of the event.
+        payload: Value,
+    },
+    /// Check that the validation proof of a subject is available.
+    Verify {
+        /// Subject identifier.
+        subject_id: String,
+    },
+    /// Log a summary of the node state.
+    Report,
+    /// Compact the database, see `KoreApi::compact_db`.
+    Compact,
+    /// Export the events of a governance.
+    #[cfg(feature = "export")]
+    Export(ExportSettings),
+}
+
+/// Periodic call to the node API.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Schedule {
+    /// Name used in the logs.
+    pub name: String,
+    /// Time between runs.
+    #[serde(deserialize_with = "deserialize_duration_secs")]
+    pub interval: Duration,
+    /// Action to run.
+    pub action: ScheduledAction,
 }
 
 /// Specific settings for the node.
@@ -26,33 +955,194 @@ pub struct KoreSettings {
     pub settings: BaseSettings,
     /// Database settings.
     pub db: DbSettings,
+    /// Options of the database backend: PRAGMAs of each SQLite connection, or parameters of
+    /// the PostgreSQL connection string. LevelDB takes none.
+    #[serde(rename = "dbOptions")]
+    pub db_options: BTreeMap<String, String>,
+    /// Size of the connection pool: read-only connections per collection (SQLite) or
+    /// connections to the server (PostgreSQL).
+    #[serde(rename = "dbReadPoolSize")]
+    pub db_read_pool_size: usize,
+    /// Write batches of the collections.
+    #[serde(rename = "dbBatch")]
+    pub db_batch: DbBatchSettings,
+    /// Expiry of the entries of the auxiliary collections.
+    #[serde(rename = "dbTtl")]
+    pub db_ttl: DbTtlSettings,
+    /// Encrypt the values of the database, see the `encrypted` database module. Requires the
+    /// `encryption` feature.
+    #[serde(rename = "dbEncryption")]
+    pub db_encryption: bool,
+    /// Secret the database key is derived from. When empty, the node password is used.
+    #[serde(rename = "dbEncryptionKey")]
+    pub db_encryption_key: String,
+    /// Ports tried, in order, when a listen address is already in use.
+    #[serde(rename = "listenFallbackPorts")]
+    pub listen_fallback_ports: Vec<u16>,
+    /// Groups of boot nodes with failover order and health checks.
+    pub bootstrap: BootstrapSettings,
+    /// Quota of subject creation.
+    #[serde(rename = "subjectQuota")]
+    pub subject_quota: SubjectQuota,
+    /// Access logs of the API and the prometheus server.
+    #[serde(rename = "accessLog")]
+    pub access_log: AccessLogSettings,
+    /// Level, format and output of the logs.
+    pub logging: LoggingSettings,
+    /// Periodic calls to the node API.
+    pub schedules: Vec<Schedule>,
+    /// Signers allowed for each request type.
+    #[serde(rename = "signingPolicies")]
+    pub signing_policies: Vec<SigningPolicy>,
+    /// Approvals accepted by the `auto_approval` feature flag, none when empty.
+    #[serde(rename = "approvalRules")]
+    pub approval_rules: Vec<ApprovalRule>,
+    /// Checks of the signatures sent by clients.
+    #[serde(rename = "signatureCheck")]
+    pub signature_check: SignatureCheck,
+    /// Timeout and retries of the calls to Kore Base.
+    pub api: ApiCallSettings,
+    /// Rate and concurrency of the calls to Kore Base.
+    pub limits: LimitsSettings,
     /// Path for encryptep keys.
     #[serde(rename = "keysPath")]
     pub keys_path: String,
-    /// TcpListener from prometheus axum server.
+    /// Replace a corrupted node private key with a new key pair, instead of failing to start.
+    /// The new key pair gives the node new controller and peer ids.
+    #[serde(rename = "regenerateCorruptedKeys")]
+    pub regenerate_corrupted_keys: bool,
+    /// Move the keys and database found in the legacy `examples/` locations to the configured
+    /// ones when these are empty, see the `migration` module.
+    #[serde(rename = "migrateLegacyData")]
+    pub migrate_legacy_data: bool,
+    /// Write the lifecycle events of the node to the standard output, one JSON object per line,
+    /// see the `lifecycle` module.
+    #[serde(rename = "lifecycleEvents")]
+    pub lifecycle_events: bool,
+    /// Encryption of the key files.
+    pub keys: KeysSettings,
+    /// Where the node key pair is kept.
+    #[serde(rename = "keysBackend")]
+    pub keys_backend: KeysBackend,
+    /// Token of the `pkcs11` keys backend.
+    pub pkcs11: Pkcs11Settings,
+    /// Serialization of the timestamps of the model in the REST responses and notifications.
+    #[serde(rename = "timestampFormat")]
+    pub timestamp_format: TimestampFormat,
+    /// TcpListener of the prometheus server, serving `/metrics` and `/health`, or `unix://<path>`
+    /// for a Unix socket. Empty, metrics are not served. Port 0 picks any free port, announced in
+    /// the logs and `node_info`.
     pub prometheus: String,
+    /// Metrics pushed to a Pushgateway or a remote-write endpoint, for nodes that cannot be
+    /// scraped.
+    #[serde(rename = "metricsPush")]
+    pub metrics_push: MetricsPushSettings,
+    /// TcpListener of the REST API server (`http-api` feature), or `unix://<path>` for a Unix
+    /// socket. Empty, the server is not started.
+    #[serde(rename = "httpApi")]
+    pub http_api: String,
+    /// Credentials of the public and admin surfaces of the REST and gRPC servers.
+    #[serde(rename = "apiAuth")]
+    pub api_auth: ApiAuthSettings,
+    /// API keys and JWTs required by the HTTP endpoints.
+    pub auth: AuthSettings,
+    /// gRPC server.
+    pub grpc: GrpcSettings,
+    /// Notifications of approvals and request results.
+    pub webhooks: WebhookSettings,
+    /// Service endpoints exchanged with trusted peers.
+    pub services: ServicesSettings,
+    /// Subjects read at startup.
+    #[serde(rename = "warmUp")]
+    pub warm_up: WarmUpSettings,
+    /// Restarts after a crash.
+    pub supervisor: SupervisorSettings,
+    /// Scheduled backups and restore of an empty database.
+    pub backup: BackupSettings,
+    /// Archival of old events to object storage.
+    pub archival: ArchivalSettings,
+    /// Soak test run against the node.
+    pub soak: SoakSettings,
+    /// Replication of subjects to another node.
+    pub replication: ReplicationSettings,
+    /// Feature flags of experimental behaviors, see the `features` module.
+    pub features: BTreeMap<String, bool>,
 }
 
-#[cfg(feature = "sqlite")]
-impl Default for KoreSettings {
-    fn default() -> Self {
-        Self {
-            settings: BaseSettings::default(),
-            db: DbSettings::Sqlite("examples/sqlitedb/database".to_owned()),
-            keys_path: "examples/keys".to_owned(),
-            prometheus: "127.0.0.1:3050".to_owned(),
-        }
+impl KoreSettings {
+    /// Check the settings before starting the node, see `config::validate`.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Config` - Every invalid value, with its key in the configuration file
+    ///
+    pub fn validate(&self) -> Result<(), NodeError> {
+        validate(self)
+    }
+
+    /// Write the settings as a configuration file that `build_config` reads back, see
+    /// `config::render`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - File to write, replaced when it exists
+    /// * `format` - Format of the file, e.g. `ConfigFormat::from_path(path)`
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::Config` - The file could not be written, or a value has no form in the
+    ///   format
+    ///
+    pub fn to_file(&self, path: &Path, format: ConfigFormat) -> Result<(), NodeError> {
+        write_settings(self, path, format, false)
     }
 }
 
-#[cfg(feature = "leveldb")]
 impl Default for KoreSettings {
     fn default() -> Self {
         Self {
             settings: BaseSettings::default(),
-            db: DbSettings::LevelDB("examples/leveldb".to_owned()),
-            keys_path: "examples/keys".to_owned(),
+            db: DbSettings::default(),
+            db_options: BTreeMap::new(),
+            db_read_pool_size: 4,
+            db_batch: DbBatchSettings::default(),
+            db_ttl: DbTtlSettings::default(),
+            db_encryption: false,
+            db_encryption_key: String::default(),
+            listen_fallback_ports: vec![],
+            bootstrap: BootstrapSettings::default(),
+            subject_quota: SubjectQuota::default(),
+            access_log: AccessLogSettings::default(),
+            logging: LoggingSettings::default(),
+            schedules: vec![],
+            signing_policies: vec![],
+            approval_rules: vec![],
+            signature_check: SignatureCheck::default(),
+            api: ApiCallSettings::default(),
+            limits: LimitsSettings::default(),
+            keys_path: default_data_path("keys"),
+            regenerate_corrupted_keys: false,
+            migrate_legacy_data: false,
+            lifecycle_events: false,
+            keys: KeysSettings::default(),
+            keys_backend: KeysBackend::File,
+            timestamp_format: TimestampFormat::Epoch,
+            pkcs11: Pkcs11Settings::default(),
             prometheus: "127.0.0.1:3050".to_owned(),
+            metrics_push: MetricsPushSettings::default(),
+            http_api: String::default(),
+            api_auth: ApiAuthSettings::default(),
+            auth: AuthSettings::default(),
+            grpc: GrpcSettings::default(),
+            webhooks: WebhookSettings::default(),
+            services: ServicesSettings::default(),
+            warm_up: WarmUpSettings::default(),
+            supervisor: SupervisorSettings::default(),
+            backup: BackupSettings::default(),
+            archival: ArchivalSettings::default(),
+            soak: SoakSettings::default(),
+            replication: ReplicationSettings::default(),
+            features: BTreeMap::new(),
         }
     }
 }
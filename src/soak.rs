@@ -0,0 +1,368 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Soak test.
+//!
+//! Load generated against the node itself, to validate its sizing before a rollout. At the rate
+//! of `[kore.soak]`, subjects of the configured governance and schema are created first, then
+//! fact events are sent in turn to each of them. Every request is followed until its final
+//! state: the time taken and the result go to the `soak_*` metrics, and a report of the load is
+//! logged periodically. The requests go through the API like those of any client, so they also
+//! count in the quota, the access logs and the event request metrics.
+//!
+//! The load stops with the node, or when `duration` expires; requests still in flight are then
+//! dropped. When too many requests are in flight, the ticks are skipped instead of queued, so a
+//! node that cannot keep up shows up as skipped requests rather than as growing memory.
+//!
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+use tokio::{
+    task::JoinSet,
+    time::{interval, sleep_until, MissedTickBehavior},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    error::NodeError,
+    metrics::NodeMetrics,
+    model::{
+        NodeEventRequest, NodeFactRequest, NodeKoreRequestState, NodeRequestState,
+        NodeSignedEventRequest, NodeStartRequest,
+    },
+    settings::SoakSettings,
+    tasks::NodeTasks,
+    KoreApi,
+};
+
+/// Identity of the soak test in the access logs.
+const SOAK_SOURCE: &str = "soak";
+
+/// Longest time a request is followed before it counts as failed.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Time of each wait for a state change of a request.
+const STATE_WAIT: Duration = Duration::from_secs(30);
+
+/// Counts of the load since it started.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SoakReport {
+    /// Requests sent.
+    pub sent: u64,
+    /// Requests finished successfully.
+    pub succeeded: u64,
+    /// Requests that failed, were rejected or timed out.
+    pub failed: u64,
+    /// Requests not sent, too many were in flight.
+    pub skipped: u64,
+    /// Sum of the time taken by the finished requests, successful or not.
+    pub total_latency: Duration,
+    /// Longest time taken by a request.
+    pub max_latency: Duration,
+}
+
+impl SoakReport {
+    /// Mean time taken by the finished requests.
+    pub fn mean_latency(&self) -> Duration {
+        match u32::try_from(self.succeeded + self.failed) {
+            Ok(0) | Err(_) => Duration::ZERO,
+            Ok(finished) => self.total_latency / finished,
+        }
+    }
+
+    /// Count a finished request.
+    fn record(&mut self, ok: bool, elapsed: Duration) {
+        if ok {
+            self.succeeded += 1;
+        } else {
+            self.failed += 1;
+        }
+        self.total_latency += elapsed;
+        self.max_latency = self.max_latency.max(elapsed);
+    }
+}
+
+/// Report shared by the tasks of the requests.
+type SharedReport = Arc<Mutex<SoakReport>>;
+
+/// Request of the soak test once it is no longer followed.
+struct Followed {
+    /// Whether it was a create request.
+    create: bool,
+    /// Subject created by a successful create request.
+    subject_id: Option<String>,
+}
+
+/// Generate load until `settings.duration` expires or `cancellation` is cancelled.
+///
+/// # Arguments
+///
+/// * `api` - Kore API the requests are sent to.
+/// * `settings` - Rate, subjects and payload of the load.
+/// * `metrics` - Metrics that get the result of each request.
+/// * `cancellation` - Stops the load.
+///
+/// # Errors
+///
+/// * `NodeError::InvalidParameter` - The rate is 0 or the payload is not JSON.
+///
+/// # Returns
+///
+/// * `SoakReport` - Counts of the load.
+///
+pub async fn soak(
+    api: &KoreApi,
+    settings: &SoakSettings,
+    metrics: &NodeMetrics,
+    cancellation: CancellationToken,
+) -> Result<SoakReport, NodeError> {
+    if !settings.is_enabled() {
+        return Err(NodeError::InvalidParameter(
+            "soak rate must be greater than 0".to_owned(),
+        ));
+    }
+    let payload: Value = serde_json::from_str(&settings.payload).map_err(|error| {
+        NodeError::InvalidParameter(format!("soak payload is not JSON: {}", error))
+    })?;
+    let api = api
+        .with_cancellation(cancellation.clone())
+        .with_identity(SOAK_SOURCE);
+    let deadline =
+        (!settings.duration.is_zero()).then(|| tokio::time::Instant::now() + settings.duration);
+    let report = SharedReport::default();
+
+    let mut ticks = interval(Duration::from_secs_f64(1.0 / settings.rate as f64));
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut reports = interval(settings.report_interval);
+    reports.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    reports.tick().await;
+
+    let mut in_flight = JoinSet::new();
+    let mut subjects: Vec<String> = vec![];
+    let mut creating = 0;
+    let mut next_subject = 0;
+    loop {
+        tokio::select! {
+            _ = cancellation.cancelled() => break,
+            _ = sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)),
+                if deadline.is_some() => break,
+            Some(followed) = in_flight.join_next() => {
+                if let Ok(Followed { create, subject_id }) = followed {
+                    if create {
+                        creating -= 1;
+                    }
+                    subjects.extend(subject_id);
+                }
+            }
+            _ = reports.tick() => log_report(&lock(&report), in_flight.len()),
+            _ = ticks.tick() => {
+                let create = subjects.len() + creating < settings.subjects;
+                if !create && subjects.is_empty() {
+                    // The subjects are still being created.
+                    continue;
+                }
+                if in_flight.len() >= settings.max_in_flight {
+                    lock(&report).skipped += 1;
+                    metrics.soak_skipped();
+                    continue;
+                }
+                let request = if create {
+                    creating += 1;
+                    NodeEventRequest::Create(NodeStartRequest {
+                        governance_id: settings.governance_id.clone(),
+                        schema_id: settings.schema_id.clone(),
+                        namespace: String::default(),
+                        name: format!("soak-{}", lock(&report).sent),
+                        public_key: None,
+                    })
+                } else {
+                    next_subject = (next_subject + 1) % subjects.len();
+                    NodeEventRequest::Fact(NodeFactRequest {
+                        subject_id: subjects[next_subject].clone(),
+                        payload: payload.clone(),
+                    })
+                };
+                lock(&report).sent += 1;
+                in_flight.spawn(follow(
+                    api.clone(),
+                    request,
+                    report.clone(),
+                    metrics.clone(),
+                ));
+            }
+        }
+    }
+    if !in_flight.is_empty() {
+        log::info!(
+            "Soak test stopped with {} requests in flight, they are not counted",
+            in_flight.len()
+        );
+    }
+    in_flight.abort_all();
+    let report = lock(&report).clone();
+    Ok(report)
+}
+
+/// Generate load in the background, and log the report once it stops.
+///
+/// # Arguments
+///
+/// * `api` - Kore API the requests are sent to.
+/// * `settings` - Rate, subjects and payload of the load.
+/// * `metrics` - Metrics that get the result of each request.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_soak(api: KoreApi, settings: SoakSettings, metrics: NodeMetrics, tasks: &NodeTasks) {
+    let cancellation = tasks.token();
+    tasks.spawn(async move {
+        log::warn!(
+            "Soak test started: {} requests per second on schema {}",
+            settings.rate,
+            settings.schema_id
+        );
+        match soak(&api, &settings, &metrics, cancellation).await {
+            Ok(report) => {
+                log::info!("Soak test finished");
+                log_report(&report, 0);
+            }
+            Err(error) => log::error!("Soak test not started: {}", error),
+        }
+    });
+}
+
+/// Send a request and follow it until its final state, counting the result.
+async fn follow(
+    api: KoreApi,
+    request: NodeEventRequest,
+    report: SharedReport,
+    metrics: NodeMetrics,
+) -> Followed {
+    let request_type = request.request_type();
+    let create = matches!(request, NodeEventRequest::Create(_));
+    let start = Instant::now();
+    let state = match tokio::time::timeout(REQUEST_TIMEOUT, final_state(&api, request)).await {
+        Ok(Ok(state)) => Some(state),
+        Ok(Err(error)) => {
+            log::debug!("Soak request failed: {}", error);
+            None
+        }
+        Err(_) => {
+            log::debug!("Soak request not finished in {:?}", REQUEST_TIMEOUT);
+            None
+        }
+    };
+    let elapsed = start.elapsed();
+    let state = state.filter(|state| state.success == Some(true));
+    let ok = state.is_some();
+    metrics.soak_request(request_type, ok, elapsed);
+    lock(&report).record(ok, elapsed);
+    Followed {
+        create,
+        subject_id: state.filter(|_| create).and_then(|state| state.subject_id),
+    }
+}
+
+/// Send a request and wait until it is no longer processing.
+async fn final_state(
+    api: &KoreApi,
+    request: NodeEventRequest,
+) -> Result<NodeKoreRequestState, NodeError> {
+    let response = api
+        .send_event_request(NodeSignedEventRequest {
+            request,
+            signature: None,
+            origin: None,
+        })
+        .await?;
+    let mut last_seen_state = NodeRequestState::Processing;
+    loop {
+        let state = api
+            .wait_state_change(&response.request_id, last_seen_state, STATE_WAIT)
+            .await?;
+        if state.is_final() {
+            return Ok(state);
+        }
+        last_seen_state = state.state;
+    }
+}
+
+/// Lock the report, even if a task panicked while holding it.
+fn lock(report: &SharedReport) -> std::sync::MutexGuard<'_, SoakReport> {
+    report
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Log the counts of the load.
+fn log_report(report: &SoakReport, in_flight: usize) {
+    log::info!(
+        "Soak test: {} sent, {} succeeded, {} failed, {} skipped, {} in flight, \
+         latency mean {} ms, max {} ms",
+        report.sent,
+        report.succeeded,
+        report.failed,
+        report.skipped,
+        in_flight,
+        report.mean_latency().as_millis(),
+        report.max_latency.as_millis()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_soak_report() {
+        let mut report = SoakReport::default();
+        assert_eq!(report.mean_latency(), Duration::ZERO);
+        report.record(true, Duration::from_millis(100));
+        report.record(false, Duration::from_millis(300));
+        assert_eq!(report.succeeded, 1);
+        assert_eq!(report.failed, 1);
+        assert_eq!(report.mean_latency(), Duration::from_millis(200));
+        assert_eq!(report.max_latency, Duration::from_millis(300));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_soak() {
+        let api = crate::node::tests::export_sqlite_api(229, vec![]).await;
+        let settings = SoakSettings {
+            rate: 10,
+            duration: Duration::from_secs(5),
+            subjects: 2,
+            ..Default::default()
+        };
+        let report = soak(
+            &api,
+            &settings,
+            &NodeMetrics::default(),
+            CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        // Two governances are created, then the facts with an empty payload fail.
+        assert!(report.sent >= 2);
+        assert!(report.succeeded >= 2);
+        assert_eq!(report.skipped, 0);
+
+        let settings = SoakSettings {
+            payload: "{".to_owned(),
+            ..settings
+        };
+        let result = soak(
+            &api,
+            &settings,
+            &NodeMetrics::default(),
+            CancellationToken::new(),
+        )
+        .await;
+        assert!(matches!(result, Err(NodeError::InvalidParameter(_))));
+    }
+}
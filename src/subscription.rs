@@ -0,0 +1,394 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Event subscriptions.
+//!
+//! Pushes the events committed to a subject, or to the subjects of a governance, to every
+//! subscriber. The node follows each subscribed target with a single task, however many clients
+//! subscribe to it, and the task ends once its last subscription is dropped.
+//!
+//! Changes of state of the event requests read through the node are pushed as transitions, and
+//! the approvals that become pending are pushed while anybody listens to them.
+//!
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::MissedTickBehavior,
+};
+
+use crate::{
+    error::NodeError,
+    model::{
+        EventContentResponse, NodeApprovalEntity, NodeKoreRequestState, NodeRequestState,
+        NodeRequestTransition, NodeSigned,
+    },
+    KoreApi,
+};
+
+/// Time between two reads of the events of the followed subjects.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Events kept for subscribers that fall behind. Slower subscribers miss the older events.
+const CAPACITY: usize = 256;
+
+/// Processing requests whose state is remembered. Requests over the limit are not followed.
+const MAX_TRACKED_REQUESTS: usize = 10_000;
+
+/// Event pushed to the subscribers.
+pub type SubscribedEvent = NodeSigned<EventContentResponse>;
+
+/// Events followed by a subscription.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SubscriptionTarget {
+    /// Events of a subject.
+    Subject(String),
+    /// Events of the subjects of a governance, including the ones created after subscribing.
+    Governance(String),
+}
+
+/// Subscription to the events committed after it was created.
+pub struct EventSubscription {
+    target: SubscriptionTarget,
+    receiver: broadcast::Receiver<SubscribedEvent>,
+}
+
+impl EventSubscription {
+    /// Events followed by the subscription.
+    pub fn target(&self) -> &SubscriptionTarget {
+        &self.target
+    }
+
+    /// Wait for the next event.
+    /// Events dropped because the subscriber fell behind are skipped with a warning.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<SubscribedEvent>` - Next event, `None` once the node stops following the target.
+    ///
+    pub async fn next(&mut self) -> Option<SubscribedEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => return Some(event),
+                Err(RecvError::Lagged(missed)) => {
+                    log::warn!("Subscription to {:?} missed {} events", self.target, missed)
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Followed targets and the channels of their subscribers, shared by the clones of a `KoreApi`.
+#[derive(Clone, Default)]
+pub(crate) struct Subscriptions {
+    followed: Arc<Mutex<HashMap<SubscriptionTarget, broadcast::Sender<SubscribedEvent>>>>,
+}
+
+impl Subscriptions {
+    /// Subscribe to a target, following it if nobody does yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `api` - Handle used to follow the target.
+    /// * `target` - Subject or governance.
+    ///
+    /// # Errors
+    ///
+    /// * `NodeError::InvalidParameter` - Invalid identifier.
+    /// * `NodeError::NotFound` - Unknown subject or governance.
+    /// * `NodeError::Internal` - The subject or governance could not be read.
+    ///
+    pub(crate) async fn subscribe(
+        &self,
+        api: KoreApi,
+        target: SubscriptionTarget,
+    ) -> Result<EventSubscription, NodeError> {
+        if let Some(subscription) = self.join(&target) {
+            return Ok(subscription);
+        }
+        // Only the events committed from now on are pushed.
+        let subjects = match &target {
+            SubscriptionTarget::Subject(subject_id) => vec![api.get_subject(subject_id).await?],
+            SubscriptionTarget::Governance(governance_id) => {
+                api.get_subject(governance_id).await?;
+                api.get_all_subjects_of_governance(governance_id).await?
+            }
+        };
+        let next_sn = subjects
+            .into_iter()
+            .map(|subject| (subject.subject_id, subject.sn + 1))
+            .collect::<HashMap<_, _>>();
+
+        let Ok(mut followed) = self.followed.lock() else {
+            return Err(NodeError::InternalApi(
+                "Subscriptions unavailable".to_owned(),
+            ));
+        };
+        // Somebody else may have subscribed while the subjects were read.
+        if let Some(sender) = followed.get(&target) {
+            return Ok(EventSubscription {
+                target,
+                receiver: sender.subscribe(),
+            });
+        }
+        let (sender, receiver) = broadcast::channel(CAPACITY);
+        followed.insert(target.clone(), sender.clone());
+        tokio::spawn(self.clone().follow(api, target.clone(), next_sn, sender));
+        Ok(EventSubscription { target, receiver })
+    }
+
+    /// Subscribe to a target that is already followed.
+    fn join(&self, target: &SubscriptionTarget) -> Option<EventSubscription> {
+        let followed = self.followed.lock().ok()?;
+        followed.get(target).map(|sender| EventSubscription {
+            target: target.clone(),
+            receiver: sender.subscribe(),
+        })
+    }
+
+    /// Stop following a target without subscribers.
+    fn release(
+        &self,
+        target: &SubscriptionTarget,
+        sender: &broadcast::Sender<SubscribedEvent>,
+    ) -> bool {
+        let Ok(mut followed) = self.followed.lock() else {
+            return true;
+        };
+        if sender.receiver_count() > 0 {
+            return false;
+        }
+        followed.remove(target);
+        true
+    }
+
+    /// Push the new events of the target until nobody is subscribed.
+    async fn follow(
+        self,
+        api: KoreApi,
+        target: SubscriptionTarget,
+        mut next_sn: HashMap<String, u64>,
+        sender: broadcast::Sender<SubscribedEvent>,
+    ) {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if self.release(&target, &sender) {
+                return;
+            }
+            if let SubscriptionTarget::Governance(governance_id) = &target {
+                match api.get_all_subjects_of_governance(governance_id).await {
+                    // Subjects created after subscribing are pushed from their first event.
+                    Ok(subjects) => subjects.into_iter().for_each(|subject| {
+                        next_sn.entry(subject.subject_id).or_insert(0);
+                    }),
+                    Err(error) => log::warn!("Subscription to {:?}: {}", target, error),
+                }
+            }
+            for (subject_id, next) in next_sn.iter_mut() {
+                match api.get_all_events_of_subject(subject_id, *next).await {
+                    Ok(events) => {
+                        for event in events {
+                            *next = event.content.sn + 1;
+                            let _ = sender.send(event);
+                        }
+                    }
+                    Err(error) => log::warn!("Subscription to {:?}: {}", target, error),
+                }
+            }
+        }
+    }
+}
+
+/// Subscribers of the pending approvals, shared by the clones of a `KoreApi`. A single task reads
+/// the approvals while anybody is subscribed.
+#[derive(Clone, Default)]
+pub(crate) struct PendingApprovals {
+    sender: Arc<Mutex<Option<broadcast::Sender<NodeApprovalEntity>>>>,
+}
+
+impl PendingApprovals {
+    /// Subscribe to the approvals that become pending from now on, following them if nobody
+    /// does yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `api` - Handle used to read the approvals.
+    ///
+    pub(crate) fn subscribe(&self, api: KoreApi) -> broadcast::Receiver<NodeApprovalEntity> {
+        let Ok(mut followed) = self.sender.lock() else {
+            // Closed at once: the subscriber sees the end of the stream.
+            return broadcast::channel(1).1;
+        };
+        if let Some(sender) = followed.as_ref() {
+            return sender.subscribe();
+        }
+        let (sender, receiver) = broadcast::channel(CAPACITY);
+        *followed = Some(sender.clone());
+        tokio::spawn(self.clone().follow(api, sender));
+        receiver
+    }
+
+    /// Stop following the approvals without subscribers.
+    fn release(&self, sender: &broadcast::Sender<NodeApprovalEntity>) -> bool {
+        let Ok(mut followed) = self.sender.lock() else {
+            return true;
+        };
+        if sender.receiver_count() > 0 {
+            return false;
+        }
+        *followed = None;
+        true
+    }
+
+    /// Push the approvals missing from the previous read until nobody is subscribed. The first
+    /// read only takes the approvals already pending.
+    async fn follow(self, api: KoreApi, sender: broadcast::Sender<NodeApprovalEntity>) {
+        let mut pending: Option<HashSet<String>> = None;
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if self.release(&sender) {
+                return;
+            }
+            let approvals = match api.get_all_pending_approvals().await {
+                Ok(approvals) => approvals,
+                Err(error) => {
+                    log::warn!("Pending approvals not read: {}", error);
+                    continue;
+                }
+            };
+            let ids = approvals
+                .iter()
+                .map(|approval| approval.id.clone())
+                .collect::<HashSet<_>>();
+            if let Some(pending) = &pending {
+                for approval in approvals {
+                    if !pending.contains(&approval.id) {
+                        let _ = sender.send(approval);
+                    }
+                }
+            }
+            pending = Some(ids);
+        }
+    }
+}
+
+/// Last state of the requests still processing, and the subscribers of their transitions.
+#[derive(Clone)]
+pub(crate) struct RequestTransitions {
+    tracked: Arc<Mutex<HashMap<String, NodeRequestState>>>,
+    sender: broadcast::Sender<NodeRequestTransition>,
+}
+
+impl Default for RequestTransitions {
+    fn default() -> Self {
+        Self {
+            tracked: Arc::default(),
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl RequestTransitions {
+    /// Subscribe to the transitions observed from now on.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<NodeRequestTransition> {
+        self.sender.subscribe()
+    }
+
+    /// Compare a state read from Kore Base with the last one, pushing the transition if it
+    /// changed. Requests are followed from the first time they are seen processing, and
+    /// forgotten once they reach a final state.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<NodeRequestTransition>` - Transition pushed, if any.
+    ///
+    pub(crate) fn observe(&self, request: &NodeKoreRequestState) -> Option<NodeRequestTransition> {
+        let mut tracked = self.tracked.lock().ok()?;
+        let Some(&previous) = tracked.get(&request.id) else {
+            if !request.is_final() && tracked.len() < MAX_TRACKED_REQUESTS {
+                tracked.insert(request.id.clone(), request.state);
+            }
+            return None;
+        };
+        if previous == request.state {
+            return None;
+        }
+        if let Err(error) = previous.transition(request.state) {
+            log::warn!("Request {}: {}", request.id, error);
+            return None;
+        }
+        if request.is_final() {
+            tracked.remove(&request.id);
+        } else {
+            tracked.insert(request.id.clone(), request.state);
+        }
+        let transition = NodeRequestTransition {
+            request_id: request.id.clone(),
+            from: previous,
+            to: request.state,
+        };
+        let _ = self.sender.send(transition.clone());
+        Some(transition)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn request(state: NodeRequestState) -> NodeKoreRequestState {
+        NodeKoreRequestState {
+            id: "JRequest".to_owned(),
+            subject_id: None,
+            sn: None,
+            state,
+            success: None,
+        }
+    }
+
+    #[test]
+    fn test_request_transitions() {
+        let transitions = RequestTransitions::default();
+        let mut receiver = transitions.subscribe();
+
+        assert_eq!(
+            transitions.observe(&request(NodeRequestState::Finished)),
+            None
+        );
+        assert_eq!(
+            transitions.observe(&request(NodeRequestState::Processing)),
+            None
+        );
+        assert_eq!(
+            transitions.observe(&request(NodeRequestState::Processing)),
+            None
+        );
+        let finished = NodeRequestTransition {
+            request_id: "JRequest".to_owned(),
+            from: NodeRequestState::Processing,
+            to: NodeRequestState::Rejected,
+        };
+        assert_eq!(
+            transitions.observe(&request(NodeRequestState::Rejected)),
+            Some(finished.clone())
+        );
+        assert_eq!(receiver.try_recv().unwrap(), finished);
+        // Final requests are forgotten.
+        assert_eq!(
+            transitions.observe(&request(NodeRequestState::Finished)),
+            None
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,761 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # API surfaces.
+//!
+//! `KoreApi` holds every method of the node. What is served to other parties goes through one of
+//! two surfaces, which only hold the methods meant for them:
+//!
+//! * `PublicApi` - Reads of subjects, events, requests and approvals, and the submission of
+//!   event requests signed by their clients. Safe to expose to untrusted networks.
+//! * `AdminApi` - Calls that act with the node key or change how the node works: event requests
+//!   signed by the node, votes, preauthorizations, key generation and rotation, archives,
+//!   transfers and ends of life of the subjects of the node, backups, database maintenance,
+//!   contracts, feature flags, settings, and the history and usage of the node.
+//!
+//! The REST and gRPC servers check the credentials of each client against `kore.api_auth`
+//! before serving a surface, see `authorize`:
+//!
+//! | Surface | Token set | Token empty |
+//! |---|---|---|
+//! | Public | `Authorization: Bearer <public or admin token>` | Any client |
+//! | Admin | `Authorization: Bearer <admin token>` | Loopback clients only |
+//!
+
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    ops::Range,
+    path::Path,
+    time::{Duration, Instant as StdInstant},
+};
+
+use futures::stream::BoxStream;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    error::NodeError,
+    model::{
+        AuthorizeSubject, EventContentResponse, EventRequestResponse, NodeAllowedSubjectsFilter,
+        NodeApprovalEntity, NodeArchivalReport, NodeBackupManifest, NodeChainVerification,
+        NodeContract, NodeDbCompaction, NodeDbStats, NodeEventRequest, NodeEventVerification,
+        NodeFeatureFlag, NodeGetApprovals, NodeHistoryEntry, NodeInfo, NodeKeyRotation,
+        NodeKeyVersion, NodeKeys, NodeKoreRequestState, NodeProof, NodeRequestRecord,
+        NodeRequestState, NodeRequestTransition, NodeServiceRecord, NodeSigned,
+        NodeSignedEventRequest, NodeSubjectData, NodeSubjectGraph, NodeSubjectKeys,
+        NodeSubjectSearch, NodeSubjects, NodeUsage, Page, PaginatorFromNumber, PaginatorFromString,
+        PatchVote, PreauthorizedSubjectsResponse,
+    },
+    settings::{
+        ApiAuthSettings, ApiCallSettings, ApprovalRule, LimitsSettings, SignatureCheck,
+        SigningPolicy, SubjectQuota, TimestampFormat,
+    },
+    subscription::{EventSubscription, SubscriptionTarget},
+    KoreApi,
+};
+
+#[cfg(feature = "replication")]
+use crate::model::NodeReplicationFailure;
+
+/// Surface of the API reached by a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Surface {
+    /// `PublicApi`.
+    Public,
+    /// `AdminApi`.
+    Admin,
+}
+
+/// Check the credentials of a client against the settings of its surface.
+///
+/// # Arguments
+///
+/// * `auth` - Tokens of the surfaces.
+/// * `surface` - Surface reached.
+/// * `token` - Bearer token presented by the client, if any.
+/// * `address` - Address of the client, none when unknown.
+///
+/// # Errors
+///
+/// * `NodeError::Unauthorized` - The client may not reach the surface.
+///
+pub fn authorize(
+    auth: &ApiAuthSettings,
+    surface: Surface,
+    token: Option<&str>,
+    address: Option<IpAddr>,
+) -> Result<(), NodeError> {
+    let presented = |expected: &str| token.is_some_and(|token| same_token(token, expected));
+    let allowed = match surface {
+        Surface::Public => {
+            auth.public_token.is_empty()
+                || presented(&auth.public_token)
+                || (!auth.admin_token.is_empty() && presented(&auth.admin_token))
+        }
+        Surface::Admin if auth.admin_token.is_empty() => {
+            address.is_some_and(|address| address.is_loopback())
+        }
+        Surface::Admin => presented(&auth.admin_token),
+    };
+    if allowed {
+        Ok(())
+    } else {
+        Err(NodeError::Unauthorized(match surface {
+            Surface::Public => "the public API requires a valid token".to_owned(),
+            Surface::Admin => "the admin API requires the admin token".to_owned(),
+        }))
+    }
+}
+
+/// Check that an event request sent through the public surface needs nothing of the node key:
+/// it is signed by its client and, if it creates a subject, carries the key of the subject.
+///
+/// # Errors
+///
+/// * `NodeError::Unauthorized` - The node would have to sign the request or generate the key of
+///   the subject, which only the admin surface does.
+///
+fn check_client_signed(request: &NodeSignedEventRequest) -> Result<(), NodeError> {
+    if request.signature.is_none() {
+        return Err(NodeError::Unauthorized(
+            "only the admin API signs event requests with the node key".to_owned(),
+        ));
+    }
+    if let NodeEventRequest::Create(create_request) = &request.request {
+        if create_request.public_key.is_none() {
+            return Err(NodeError::Unauthorized(
+                "only the admin API generates the key of a new subject".to_owned(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Compare two tokens in a time that does not depend on where they differ.
+pub(crate) fn same_token(token: &str, expected: &str) -> bool {
+    token.len() == expected.len()
+        && token
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Public surface of the node API.
+#[derive(Clone)]
+pub struct PublicApi(KoreApi);
+
+impl PublicApi {
+    /// Create the public surface of `api`.
+    pub fn new(api: KoreApi) -> Self {
+        Self(api)
+    }
+
+    /// See `KoreApi::with_identity`.
+    pub fn with_identity(&self, identity: &str) -> Self {
+        Self(self.0.with_identity(identity))
+    }
+
+    /// See `KoreApi::with_trace_id`.
+    pub fn with_trace_id(&self, trace_id: &str) -> Self {
+        Self(self.0.with_trace_id(trace_id))
+    }
+
+    /// See `KoreApi::with_deadline`.
+    pub fn with_deadline(&self, deadline: StdInstant) -> Self {
+        Self(self.0.with_deadline(deadline))
+    }
+
+    /// See `KoreApi::with_timeout`.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self(self.0.with_timeout(timeout))
+    }
+
+    /// See `KoreApi::with_cancellation`.
+    pub fn with_cancellation(&self, cancellation: CancellationToken) -> Self {
+        Self(self.0.with_cancellation(cancellation))
+    }
+
+    /// See `KoreApi::record_usage`.
+    pub fn record_usage(&self, caller: &str, bytes: u64) {
+        self.0.record_usage(caller, bytes)
+    }
+
+    /// See `KoreApi::timestamp_format`.
+    pub fn timestamp_format(&self) -> TimestampFormat {
+        self.0.timestamp_format()
+    }
+
+    /// See `KoreApi::send_event_request`. The request must be signed by its client, and carry
+    /// the key of the subject it creates, see `AdminApi::send_event_request` otherwise.
+    pub async fn send_event_request(
+        &self,
+        request: NodeSignedEventRequest,
+    ) -> Result<EventRequestResponse, NodeError> {
+        check_client_signed(&request)?;
+        self.0.send_event_request(request).await
+    }
+
+    /// See `KoreApi::send_idempotent_event_request`, and `send_event_request` for the requests
+    /// taken.
+    pub async fn send_idempotent_event_request(
+        &self,
+        request: NodeSignedEventRequest,
+        key: &str,
+    ) -> Result<EventRequestResponse, NodeError> {
+        check_client_signed(&request)?;
+        self.0.send_idempotent_event_request(request, key).await
+    }
+
+    /// See `KoreApi::verify_signed_request`.
+    pub fn verify_signed_request(&self, request: &NodeSignedEventRequest) -> Result<(), NodeError> {
+        self.0.verify_signed_request(request)
+    }
+
+    /// See `KoreApi::get_event_request`.
+    pub async fn get_event_request(
+        &self,
+        request_id: &str,
+    ) -> Result<NodeSignedEventRequest, NodeError> {
+        self.0.get_event_request(request_id).await
+    }
+
+    /// See `KoreApi::get_event_request_state`.
+    pub async fn get_event_request_state(
+        &self,
+        request_id: &str,
+    ) -> Result<NodeKoreRequestState, NodeError> {
+        self.0.get_event_request_state(request_id).await
+    }
+
+    /// See `KoreApi::wait_state_change`.
+    pub async fn wait_state_change(
+        &self,
+        request_id: &str,
+        last_seen_state: NodeRequestState,
+        timeout: Duration,
+    ) -> Result<NodeKoreRequestState, NodeError> {
+        self.0
+            .wait_state_change(request_id, last_seen_state, timeout)
+            .await
+    }
+
+    /// See `KoreApi::subscribe_request_transitions`.
+    pub fn subscribe_request_transitions(&self) -> broadcast::Receiver<NodeRequestTransition> {
+        self.0.subscribe_request_transitions()
+    }
+
+    /// See `KoreApi::approvals_stream`.
+    pub fn approvals_stream(&self) -> broadcast::Receiver<NodeApprovalEntity> {
+        self.0.approvals_stream()
+    }
+
+    /// See `KoreApi::list_requests`.
+    pub fn list_requests(
+        &self,
+        parameters: PaginatorFromString,
+    ) -> Result<Vec<NodeRequestRecord>, NodeError> {
+        self.0.list_requests(parameters)
+    }
+
+    /// See `KoreApi::get_approvals`.
+    pub async fn get_approvals(
+        &self,
+        params: NodeGetApprovals,
+    ) -> Result<Page<NodeApprovalEntity>, NodeError> {
+        self.0.get_approvals(params).await
+    }
+
+    /// See `KoreApi::get_approval_id`.
+    pub async fn get_approval_id(&self, id: &str) -> Result<NodeApprovalEntity, NodeError> {
+        self.0.get_approval_id(id).await
+    }
+
+    /// See `KoreApi::get_all_allowed_subjects_and_providers`.
+    pub async fn get_all_allowed_subjects_and_providers(
+        &self,
+        parameters: PaginatorFromString,
+        filter: NodeAllowedSubjectsFilter,
+    ) -> Result<Page<PreauthorizedSubjectsResponse>, NodeError> {
+        self.0
+            .get_all_allowed_subjects_and_providers(parameters, filter)
+            .await
+    }
+
+    /// See `KoreApi::count_allowed_subjects`.
+    pub async fn count_allowed_subjects(
+        &self,
+        filter: NodeAllowedSubjectsFilter,
+    ) -> Result<u64, NodeError> {
+        self.0.count_allowed_subjects(filter).await
+    }
+
+    /// See `KoreApi::get_subjects`.
+    pub async fn get_subjects(
+        &self,
+        parameters: NodeSubjects,
+    ) -> Result<Page<NodeSubjectData>, NodeError> {
+        self.0.get_subjects(parameters).await
+    }
+
+    /// See `KoreApi::get_subjects_by`.
+    pub async fn get_subjects_by(
+        &self,
+        parameters: NodeSubjects,
+        keys: NodeSubjectKeys,
+    ) -> Result<Page<NodeSubjectData>, NodeError> {
+        self.0.get_subjects_by(parameters, keys).await
+    }
+
+    /// See `KoreApi::search_subjects`.
+    pub fn search_subjects(
+        &self,
+        filter: NodeSubjectSearch,
+    ) -> Result<Page<NodeSubjectData>, NodeError> {
+        self.0.search_subjects(filter)
+    }
+
+    /// See `KoreApi::get_subject`.
+    pub async fn get_subject(&self, subject_id: &str) -> Result<NodeSubjectData, NodeError> {
+        self.0.get_subject(subject_id).await
+    }
+
+    /// See `KoreApi::get_subject_if_changed`.
+    pub async fn get_subject_if_changed(
+        &self,
+        subject_id: &str,
+        etag: &str,
+    ) -> Result<Option<NodeSubjectData>, NodeError> {
+        self.0.get_subject_if_changed(subject_id, etag).await
+    }
+
+    /// See `KoreApi::get_validation_proof`.
+    pub async fn get_validation_proof(&self, subject_id: &str) -> Result<NodeProof, NodeError> {
+        self.0.get_validation_proof(subject_id).await
+    }
+
+    /// See `KoreApi::get_validation_proof_at`.
+    pub async fn get_validation_proof_at(
+        &self,
+        subject_id: &str,
+        sn: u64,
+    ) -> Result<NodeProof, NodeError> {
+        self.0.get_validation_proof_at(subject_id, sn).await
+    }
+
+    /// See `KoreApi::get_validation_proofs`.
+    pub async fn get_validation_proofs(
+        &self,
+        subject_id: &str,
+        parameters: PaginatorFromNumber,
+    ) -> Result<Page<NodeProof>, NodeError> {
+        self.0.get_validation_proofs(subject_id, parameters).await
+    }
+
+    /// See `KoreApi::get_events_of_subject`.
+    pub async fn get_events_of_subject(
+        &self,
+        subject_id: &str,
+        parameters: PaginatorFromNumber,
+    ) -> Result<Page<NodeSigned<EventContentResponse>>, NodeError> {
+        self.0.get_events_of_subject(subject_id, parameters).await
+    }
+
+    /// See `KoreApi::get_event_of_subject`.
+    pub async fn get_event_of_subject(
+        &self,
+        subject_id: &str,
+        sn: u64,
+    ) -> Result<NodeSigned<EventContentResponse>, NodeError> {
+        self.0.get_event_of_subject(subject_id, sn).await
+    }
+
+    /// See `KoreApi::verify_event`.
+    pub async fn verify_event(
+        &self,
+        subject_id: &str,
+        sn: u64,
+    ) -> Result<NodeEventVerification, NodeError> {
+        self.0.verify_event(subject_id, sn).await
+    }
+
+    /// See `KoreApi::verify_subject_chain`.
+    pub async fn verify_subject_chain(
+        &self,
+        subject_id: &str,
+    ) -> Result<NodeChainVerification, NodeError> {
+        self.0.verify_subject_chain(subject_id).await
+    }
+
+    /// See `KoreApi::verify_subject_chain_reports`.
+    pub async fn verify_subject_chain_reports(
+        &self,
+        subject_id: &str,
+    ) -> Result<BoxStream<'static, Result<NodeEventVerification, NodeError>>, NodeError> {
+        self.0.verify_subject_chain_reports(subject_id).await
+    }
+
+    /// See `KoreApi::subject_graph`.
+    pub async fn subject_graph(
+        &self,
+        root: &str,
+        depth: u32,
+    ) -> Result<NodeSubjectGraph, NodeError> {
+        self.0.subject_graph(root, depth).await
+    }
+
+    /// See `KoreApi::subscribe`.
+    pub async fn subscribe(
+        &self,
+        target: SubscriptionTarget,
+    ) -> Result<EventSubscription, NodeError> {
+        self.0.subscribe(target).await
+    }
+
+    /// See `KoreApi::get_controller_id`.
+    pub fn get_controller_id(&self) -> String {
+        self.0.get_controller_id()
+    }
+
+    /// See `KoreApi::get_peer_id`.
+    pub fn get_peer_id(&self) -> String {
+        self.0.get_peer_id()
+    }
+
+    /// See `KoreApi::node_info`.
+    pub fn node_info(&self) -> NodeInfo {
+        self.0.node_info()
+    }
+
+    /// See `KoreApi::service_record`.
+    pub fn service_record(&self) -> Result<NodeSigned<NodeServiceRecord>, NodeError> {
+        self.0.service_record()
+    }
+
+    /// See `KoreApi::peer_services`.
+    pub fn peer_services(&self) -> Vec<NodeServiceRecord> {
+        self.0.peer_services()
+    }
+}
+
+/// Administration surface of the node API.
+#[derive(Clone)]
+pub struct AdminApi(KoreApi);
+
+impl AdminApi {
+    /// Create the administration surface of `api`.
+    pub fn new(api: KoreApi) -> Self {
+        Self(api)
+    }
+
+    /// Public surface of the same node, for the reads that administration tools also need.
+    pub fn public(&self) -> PublicApi {
+        PublicApi(self.0.clone())
+    }
+
+    /// See `KoreApi::with_identity`.
+    pub fn with_identity(&self, identity: &str) -> Self {
+        Self(self.0.with_identity(identity))
+    }
+
+    /// See `KoreApi::with_trace_id`.
+    pub fn with_trace_id(&self, trace_id: &str) -> Self {
+        Self(self.0.with_trace_id(trace_id))
+    }
+
+    /// See `KoreApi::with_deadline`.
+    pub fn with_deadline(&self, deadline: StdInstant) -> Self {
+        Self(self.0.with_deadline(deadline))
+    }
+
+    /// See `KoreApi::with_timeout`.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self(self.0.with_timeout(timeout))
+    }
+
+    /// See `KoreApi::with_cancellation`.
+    pub fn with_cancellation(&self, cancellation: CancellationToken) -> Self {
+        Self(self.0.with_cancellation(cancellation))
+    }
+
+    /// See `KoreApi::record_usage`.
+    pub fn record_usage(&self, caller: &str, bytes: u64) {
+        self.0.record_usage(caller, bytes)
+    }
+
+    /// See `KoreApi::timestamp_format`.
+    pub fn timestamp_format(&self) -> TimestampFormat {
+        self.0.timestamp_format()
+    }
+
+    /// See `KoreApi::send_event_request`. Unsigned requests are signed with the node key.
+    pub async fn send_event_request(
+        &self,
+        request: NodeSignedEventRequest,
+    ) -> Result<EventRequestResponse, NodeError> {
+        self.0.send_event_request(request).await
+    }
+
+    /// See `KoreApi::send_idempotent_event_request`.
+    pub async fn send_idempotent_event_request(
+        &self,
+        request: NodeSignedEventRequest,
+        key: &str,
+    ) -> Result<EventRequestResponse, NodeError> {
+        self.0.send_idempotent_event_request(request, key).await
+    }
+
+    /// See `KoreApi::approval_request`.
+    pub async fn approval_request(
+        &self,
+        id: &str,
+        response: PatchVote,
+    ) -> Result<NodeApprovalEntity, NodeError> {
+        self.0.approval_request(id, response).await
+    }
+
+    /// See `KoreApi::refresh_pending_approvals`.
+    pub async fn refresh_pending_approvals(&self) -> Result<u64, NodeError> {
+        self.0.refresh_pending_approvals().await
+    }
+
+    /// See `KoreApi::add_preauthorize_subject`.
+    pub async fn add_preauthorize_subject(
+        &self,
+        subject_id: &str,
+        data: AuthorizeSubject,
+    ) -> Result<String, NodeError> {
+        self.0.add_preauthorize_subject(subject_id, data).await
+    }
+
+    /// See `KoreApi::register_keys`.
+    pub async fn register_keys(&self, parameters: NodeKeys) -> Result<String, NodeError> {
+        self.0.register_keys(parameters).await
+    }
+
+    /// See `KoreApi::archive_subject`.
+    pub async fn archive_subject(&self, subject_id: &str) -> Result<String, NodeError> {
+        self.0.archive_subject(subject_id).await
+    }
+
+    /// See `KoreApi::unarchive_subject`.
+    pub async fn unarchive_subject(&self, subject_id: &str) -> Result<String, NodeError> {
+        self.0.unarchive_subject(subject_id).await
+    }
+
+    /// See `KoreApi::transfer_subject`.
+    pub async fn transfer_subject(
+        &self,
+        subject_id: &str,
+        new_public_key: &str,
+    ) -> Result<EventRequestResponse, NodeError> {
+        self.0.transfer_subject(subject_id, new_public_key).await
+    }
+
+    /// See `KoreApi::end_subject_life`.
+    pub async fn end_subject_life(
+        &self,
+        subject_id: &str,
+    ) -> Result<EventRequestResponse, NodeError> {
+        self.0.end_subject_life(subject_id).await
+    }
+
+    /// See `KoreApi::feature_flags`.
+    pub fn feature_flags(&self) -> Vec<NodeFeatureFlag> {
+        self.0.feature_flags()
+    }
+
+    /// See `KoreApi::set_feature_flag`.
+    pub fn set_feature_flag(
+        &self,
+        name: &str,
+        enabled: bool,
+    ) -> Result<NodeFeatureFlag, NodeError> {
+        self.0.set_feature_flag(name, enabled)
+    }
+
+    /// See `KoreApi::set_feature_flags`.
+    pub fn set_feature_flags(&self, flags: BTreeMap<String, bool>) {
+        self.0.set_feature_flags(flags)
+    }
+
+    /// See `KoreApi::set_subject_quota`.
+    pub fn set_subject_quota(&self, quota: SubjectQuota) {
+        self.0.set_subject_quota(quota)
+    }
+
+    /// See `KoreApi::set_signing_policies`.
+    pub fn set_signing_policies(&self, policies: Vec<SigningPolicy>) {
+        self.0.set_signing_policies(policies)
+    }
+
+    /// See `KoreApi::set_approval_rules`.
+    pub fn set_approval_rules(&self, rules: Vec<ApprovalRule>) {
+        self.0.set_approval_rules(rules)
+    }
+
+    /// See `KoreApi::set_signature_check`.
+    pub fn set_signature_check(&self, check: SignatureCheck) {
+        self.0.set_signature_check(check)
+    }
+
+    /// See `KoreApi::set_api_calls`.
+    pub fn set_api_calls(&self, settings: ApiCallSettings) {
+        self.0.set_api_calls(settings)
+    }
+
+    /// See `KoreApi::set_limits`.
+    pub fn set_limits(&self, limits: LimitsSettings) {
+        self.0.set_limits(limits)
+    }
+
+    /// See `KoreApi::node_history`.
+    pub fn node_history(&self) -> Result<Vec<NodeHistoryEntry>, NodeError> {
+        self.0.node_history()
+    }
+
+    /// See `KoreApi::usage`.
+    pub fn usage(
+        &self,
+        caller: Option<&str>,
+        range: Range<u64>,
+    ) -> Result<Vec<NodeUsage>, NodeError> {
+        self.0.usage(caller, range)
+    }
+
+    /// See `KoreApi::usage_summary`.
+    pub fn usage_summary(&self, range: Range<u64>) -> Result<Vec<NodeUsage>, NodeError> {
+        self.0.usage_summary(range)
+    }
+
+    /// See `KoreApi::create_backup`.
+    pub async fn create_backup(&self, path: &Path) -> Result<NodeBackupManifest, NodeError> {
+        self.0.create_backup(path).await
+    }
+
+    /// See `KoreApi::db_stats`.
+    pub async fn db_stats(&self) -> Result<NodeDbStats, NodeError> {
+        self.0.db_stats().await
+    }
+
+    /// See `KoreApi::compact_db`.
+    pub async fn compact_db(&self) -> Result<NodeDbCompaction, NodeError> {
+        self.0.compact_db().await
+    }
+
+    /// See `KoreApi::archive_events`.
+    pub async fn archive_events(&self) -> Result<NodeArchivalReport, NodeError> {
+        self.0.archive_events().await
+    }
+
+    /// See `KoreApi::reindex_subjects`.
+    pub async fn reindex_subjects(&self) -> Result<usize, NodeError> {
+        self.0.reindex_subjects().await
+    }
+
+    /// See `KoreApi::list_contracts`.
+    pub fn list_contracts(&self) -> Vec<NodeContract> {
+        self.0.list_contracts()
+    }
+
+    /// See `KoreApi::replication_dead_letters`.
+    #[cfg(feature = "replication")]
+    pub fn replication_dead_letters(&self) -> Result<Vec<NodeReplicationFailure>, NodeError> {
+        self.0.replication_dead_letters()
+    }
+
+    /// See `KoreApi::reload_contracts`.
+    pub fn reload_contracts(&self) -> Result<Vec<NodeContract>, NodeError> {
+        self.0.reload_contracts()
+    }
+
+    /// See `KoreApi::rotate_node_key`.
+    pub fn rotate_node_key(&self, password: &str) -> Result<NodeKeyRotation, NodeError> {
+        self.0.rotate_node_key(password)
+    }
+
+    /// See `KoreApi::node_key_versions`.
+    pub fn node_key_versions(&self, password: &str) -> Result<Vec<NodeKeyVersion>, NodeError> {
+        self.0.node_key_versions(password)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+    use crate::model::{NodeFactRequest, NodeSignature, NodeStartRequest};
+
+    #[test]
+    fn test_authorize() {
+        let loopback = Some(IpAddr::V6(Ipv6Addr::LOCALHOST));
+        let remote = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3)));
+
+        let open = ApiAuthSettings::default();
+        assert!(authorize(&open, Surface::Public, None, remote).is_ok());
+        assert!(authorize(&open, Surface::Admin, None, loopback).is_ok());
+        assert!(authorize(&open, Surface::Admin, Some("admin"), remote).is_err());
+        assert!(authorize(&open, Surface::Admin, None, None).is_err());
+
+        let tokens = ApiAuthSettings {
+            public_token: "public".to_owned(),
+            admin_token: "admin".to_owned(),
+        };
+        assert!(authorize(&tokens, Surface::Public, None, loopback).is_err());
+        assert!(authorize(&tokens, Surface::Public, Some("public"), remote).is_ok());
+        assert!(authorize(&tokens, Surface::Public, Some("admin"), remote).is_ok());
+        assert!(authorize(&tokens, Surface::Public, Some("publi"), remote).is_err());
+        assert!(authorize(&tokens, Surface::Admin, Some("admin"), remote).is_ok());
+        assert!(matches!(
+            authorize(&tokens, Surface::Admin, Some("public"), loopback),
+            Err(NodeError::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_client_signed() {
+        let signature: NodeSignature = serde_json::from_value(serde_json::json!({
+            "signer": "EnyisBz0lX9sRvvV0H-BXTrVtARjUa0YDHzaxFHWH-N4",
+            "timestamp": 0,
+            "value": "SEYml_XhryHvxRylu023oyR0nIjlwVCyw2ZC_Tgvf9Fg",
+            "content_hash": "JzEh9ukZtpnGsAUxjZ7FXe6oe8aHHYHtvTZt9Xe1WMZ4",
+        }))
+        .unwrap();
+        let request = |request, signature| NodeSignedEventRequest {
+            request,
+            signature,
+            origin: None,
+        };
+        let fact = NodeEventRequest::Fact(NodeFactRequest {
+            subject_id: "JzEh9ukZtpnGsAUxjZ7FXe6oe8aHHYHtvTZt9Xe1WMZ4".to_owned(),
+            payload: serde_json::json!({}),
+        });
+        let create = |public_key: Option<&str>| {
+            NodeEventRequest::Create(NodeStartRequest {
+                governance_id: String::new(),
+                schema_id: "governance".to_owned(),
+                namespace: String::new(),
+                name: String::new(),
+                public_key: public_key.map(str::to_owned),
+            })
+        };
+        let key = Some("EnyisBz0lX9sRvvV0H-BXTrVtARjUa0YDHzaxFHWH-N4");
+
+        let signed = Some(signature);
+        assert!(check_client_signed(&request(fact.clone(), signed.clone())).is_ok());
+        assert!(check_client_signed(&request(create(key), signed.clone())).is_ok());
+        for unsigned in [
+            request(fact, None),
+            request(create(key), None),
+            request(create(None), signed),
+        ] {
+            assert!(matches!(
+                check_client_signed(&unsigned),
+                Err(NodeError::Unauthorized(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Tasks of the node.
+//!
+//! The background tasks of a node, its servers included, are spawned through its `NodeTasks`,
+//! which checks the result of each one: a task that panics cancels the node, as the node may not
+//! work without it, and its message is kept for the supervisor, see `Supervisor`. Panics of other
+//! threads of the process are left to them. A task that cannot go on without failing the node
+//! reports it with `NodeTasks::fail`, which also cancels the node.
+//!
+//! Once the node is cancelled, `NodeTasks::stopped` waits for its tasks to return and for Kore
+//! Base to drop the collections of the database, which hold the database open, so that a node
+//! built again over the same database finds it released.
+//!
+
+use std::{
+    any::Any,
+    future::Future,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+/// Time between two checks of the collections held by Kore Base while the node stops.
+const RELEASE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Held by the collections of the database handed to Kore Base, see `NodeTasks::lease`.
+#[derive(Clone)]
+pub(crate) struct Lease {
+    _count: Arc<()>,
+}
+
+/// State shared by the clones of `NodeTasks`.
+struct TasksState {
+    tracker: TaskTracker,
+    cancellation: CancellationToken,
+    /// Message of the first task that panicked.
+    panic: Mutex<Option<String>>,
+    /// Error of the first task that failed, see `NodeTasks::fail`.
+    failure: Mutex<Option<String>>,
+    /// Counts the leases still held.
+    lease: Arc<()>,
+}
+
+/// Tasks of a node, see the module documentation. Its clones share the tasks.
+#[derive(Clone)]
+pub struct NodeTasks(Arc<TasksState>);
+
+impl NodeTasks {
+    /// Tasks of the node cancelled by `cancellation`.
+    pub fn new(cancellation: CancellationToken) -> Self {
+        Self(Arc::new(TasksState {
+            tracker: TaskTracker::new(),
+            cancellation,
+            panic: Mutex::new(None),
+            failure: Mutex::new(None),
+            lease: Arc::new(()),
+        }))
+    }
+
+    /// Cancellation token of the node.
+    pub fn token(&self) -> CancellationToken {
+        self.0.cancellation.clone()
+    }
+
+    /// Spawn a task of the node. The node is cancelled if it panics.
+    ///
+    /// # Arguments
+    ///
+    /// * `task` - Task, which should return once the node is cancelled.
+    ///
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let handle = tokio::spawn(task);
+        let state = self.0.clone();
+        self.0.tracker.spawn(async move {
+            let Err(error) = handle.await else {
+                return;
+            };
+            if error.is_panic() {
+                let message = panic_message(error.into_panic());
+                log::error!("Node task panicked: {}", message);
+                state
+                    .panic
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .get_or_insert(message);
+                state.cancellation.cancel();
+            }
+        });
+    }
+
+    /// Lease held by the collections of the database handed to Kore Base, released when they
+    /// are all dropped.
+    pub(crate) fn lease(&self) -> Lease {
+        Lease {
+            _count: self.0.lease.clone(),
+        }
+    }
+
+    /// Message of the first task that panicked, if any.
+    pub fn panic(&self) -> Option<String> {
+        self.0
+            .panic
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Fail the node: keep the error for the supervisor and cancel the node.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - Why the node cannot run on.
+    ///
+    pub fn fail(&self, error: impl Into<String>) {
+        self.0
+            .failure
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert_with(|| error.into());
+        self.0.cancellation.cancel();
+    }
+
+    /// Error of the first task that failed the node, if any.
+    pub fn failure(&self) -> Option<String> {
+        self.0
+            .failure
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Wait for the tasks of the cancelled node to return and for its leases to be released.
+    /// No task can be spawned afterwards.
+    pub async fn stopped(&self) {
+        self.0.tracker.close();
+        self.0.tracker.wait().await;
+        while Arc::strong_count(&self.0.lease) > 1 {
+            tokio::time::sleep(RELEASE_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Message of the payload of a panic.
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map(|message| (*message).to_owned())
+            .unwrap_or_else(|| "task panicked".to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_node_tasks() {
+        let tasks = NodeTasks::new(CancellationToken::new());
+        let token = tasks.token();
+        tasks.spawn(async move { token.cancelled().await });
+        tasks.spawn(async { panic!("task failed") });
+        let lease = tasks.lease();
+
+        tasks.token().cancelled().await;
+        assert_eq!(tasks.panic().as_deref(), Some("task failed"));
+        assert_eq!(tasks.failure(), None);
+        tasks.fail("first");
+        tasks.fail("second");
+        assert_eq!(tasks.failure().as_deref(), Some("first"));
+
+        let stopped = tasks.clone();
+        let stopped = tokio::spawn(async move { stopped.stopped().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        // The lease is still held.
+        assert!(!stopped.is_finished());
+        drop(lease);
+        tokio::time::timeout(Duration::from_secs(5), stopped)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Usage accounting.
+//!
+//! The REST and gRPC servers account every request they serve to its caller: the principal of
+//! its credentials, see `auth`, or the IP address of the client when the endpoints require
+//! none. Requests are counted by `USAGE_WINDOW` in memory, so that serving one only updates a
+//! counter, and the counters are added to the node store every `USAGE_FLUSH_INTERVAL` and when
+//! the node stops, see `KoreApi::flush_usage`.
+//!
+//! Windows are kept for the time to live of the `usage` collection in `kore.db_ttl.collections`,
+//! `USAGE_RETENTION` by default; older ones are deleted by the flushes.
+//!
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
+use crate::{api::USAGE_WINDOW, model::NodeUsage, tasks::NodeTasks, KoreApi};
+
+/// Time between two flushes of the counted usage to the node store.
+pub const USAGE_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Time the usage windows are kept when `kore.db_ttl.collections` does not set one.
+pub const USAGE_RETENTION: Duration = Duration::from_secs(90 * 24 * 3600);
+
+/// Start of the window of a timestamp, in milliseconds since UNIX epoch.
+pub(crate) fn window_start(timestamp: u64) -> u64 {
+    timestamp / USAGE_WINDOW * USAGE_WINDOW
+}
+
+/// Key of the usage of a window, sorted by window so that the usage is read in time order.
+pub(crate) fn usage_key(from: u64, caller: &str) -> String {
+    format!("{:020}.{}", from, caller)
+}
+
+/// Longest prefix shared by the keys of every window starting inside `from..to`.
+pub(crate) fn windows_prefix(from: u64, to: u64) -> String {
+    let (first, last) = (
+        format!("{:020}", from),
+        format!("{:020}", to.saturating_sub(1)),
+    );
+    first
+        .chars()
+        .zip(last.chars())
+        .take_while(|(first, last)| first == last)
+        .map(|(digit, _)| digit)
+        .collect()
+}
+
+/// Usage counted since the last flush, shared by the clones of the API.
+#[derive(Clone, Default)]
+pub(crate) struct UsageMeter {
+    pending: Arc<Mutex<HashMap<(u64, String), NodeUsage>>>,
+    /// Held by a flush, so that two flushes never add to the same stored window.
+    flushing: Arc<Mutex<()>>,
+}
+
+impl UsageMeter {
+    /// Count a request of `caller` served at `timestamp`.
+    pub fn record(&self, caller: &str, bytes: u64, timestamp: u64) {
+        let from = window_start(timestamp);
+        // Counters are never left half updated.
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        let usage = pending
+            .entry((from, caller.to_owned()))
+            .or_insert_with(|| NodeUsage {
+                caller: caller.to_owned(),
+                from,
+                to: from + USAGE_WINDOW,
+                requests: 0,
+                bytes: 0,
+            });
+        usage.requests += 1;
+        usage.bytes += bytes;
+    }
+
+    /// Lock the flushes, released when the guard is dropped.
+    pub fn flushing(&self) -> MutexGuard<'_, ()> {
+        self.flushing.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Take the usage counted since the last call.
+    pub fn take(&self) -> Vec<NodeUsage> {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        std::mem::take(&mut *pending).into_values().collect()
+    }
+
+    /// Count again usage taken but not stored.
+    pub fn restore(&self, usage: Vec<NodeUsage>) {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        for usage in usage {
+            let counted = pending
+                .entry((usage.from, usage.caller.clone()))
+                .or_insert_with(|| NodeUsage {
+                    requests: 0,
+                    bytes: 0,
+                    ..usage.clone()
+                });
+            counted.requests += usage.requests;
+            counted.bytes += usage.bytes;
+        }
+    }
+
+    /// Usage counted since the last flush in the windows starting inside `from..to`.
+    pub fn pending(&self, from: u64, to: u64) -> Vec<NodeUsage> {
+        let pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        pending
+            .values()
+            .filter(|usage| (from..to).contains(&usage.from))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Flush the counted usage every `USAGE_FLUSH_INTERVAL`, and a last time when the node stops.
+///
+/// # Arguments
+///
+/// * `api` - Kore API of the node.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_usage_flush(api: KoreApi, tasks: &NodeTasks) {
+    let cancellation = tasks.token();
+    tasks.spawn(async move {
+        let mut interval = interval_at(Instant::now() + USAGE_FLUSH_INTERVAL, USAGE_FLUSH_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            let stopping = tokio::select! {
+                _ = cancellation.cancelled() => true,
+                _ = interval.tick() => false,
+            };
+            let flush = api.clone();
+            let result = tokio::task::spawn_blocking(move || flush.flush_usage()).await;
+            match result {
+                Ok(Ok(_)) => {}
+                Ok(Err(error)) => log::warn!("Usage not flushed: {}", error),
+                Err(error) => log::warn!("Usage not flushed: {}", error),
+            }
+            if stopping {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_windows_prefix() {
+        assert_eq!(windows_prefix(0, 1), "00000000000000000000");
+        assert_eq!(
+            windows_prefix(1_700_000_000_000, 1_700_003_600_000),
+            "00000001700"
+        );
+        assert_eq!(windows_prefix(0, u64::MAX), "");
+    }
+
+    #[test]
+    fn test_usage_meter() {
+        let meter = UsageMeter::default();
+        meter.record("key:1", 100, USAGE_WINDOW + 1);
+        meter.record("key:1", 20, USAGE_WINDOW + 2);
+        meter.record("key:1", 0, 2 * USAGE_WINDOW);
+        assert_eq!(meter.pending(USAGE_WINDOW, 2 * USAGE_WINDOW).len(), 1);
+
+        let mut taken = meter.take();
+        taken.sort_by_key(|usage| usage.from);
+        assert_eq!(
+            taken
+                .iter()
+                .map(|usage| (usage.from, usage.requests, usage.bytes))
+                .collect::<Vec<_>>(),
+            vec![(USAGE_WINDOW, 2, 120), (2 * USAGE_WINDOW, 1, 0)]
+        );
+        assert!(meter.take().is_empty());
+
+        meter.record("key:1", 1, USAGE_WINDOW);
+        meter.restore(taken);
+        let usage = meter.pending(USAGE_WINDOW, 2 * USAGE_WINDOW);
+        assert_eq!((usage[0].requests, usage[0].bytes), (3, 121));
+    }
+}
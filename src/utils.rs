@@ -1,21 +1,75 @@
 // Copyright 2024 Kore Ledger
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
-use crate::{error::NodeError, settings::KoreSettings};
+use crate::{
+    error::NodeError,
+    keystore::key_store,
+    settings::{KeyKdf, KeysSettings, KoreSettings},
+};
 use kore_base::{
     keys::{Ed25519KeyPair, KeyGenerator, KeyMaterial, KeyPair, KeyPairType, Secp256k1KeyPair},
-    KeyDerivator,
+    KeyDerivator, NetworkConfig,
 };
 
 use hex_literal::hex;
-use pkcs8::{pkcs5, Document, EncryptedPrivateKeyInfo, PrivateKeyInfo};
+use libp2p_identity::{ed25519, secp256k1, PeerId, PublicKey};
+use pkcs8::{
+    der,
+    pkcs5::{
+        self,
+        pbes2::{self, Kdf},
+        scrypt,
+    },
+    Document, EncryptedPrivateKeyInfo, PrivateKeyInfo,
+};
+
+use std::{
+    fs,
+    net::{IpAddr, TcpListener},
+    sync::{PoisonError, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Lowest PBKDF2 iteration count accepted in the settings.
+pub(crate) const MIN_PBKDF2_ITERATIONS: u32 = 10_000;
+
+/// Salt of the key files written before the encryption was configurable.
+const LEGACY_SALT: [u8; 8] = hex!("79d982e70df91a88");
 
-use std::fs;
+/// Key size of AES-256.
+const AES_256_KEY_SIZE: usize = 32;
 
-/// Get node key pair.
+/// Get node key pair from the key store of `kore.keys_backend`, see `keystore`.
+/// The key pair is generated on first use and kept in the store.
+///
+/// # Arguments
+///
+/// * `settings` - Kore settings
+/// * `password` - Password of the key file, or user PIN of the PKCS#11 token
+///
+/// # Returns
+///
+/// * `Result<KeyPair, NodeError>` - Key pair
+///
+/// # Errors
+///
+/// * `NodeError::InternalApi` - Internal API error
+/// * `NodeError::Keys` - The key pair cannot be read or stored
+///
+pub fn node_key_pair(settings: &KoreSettings, password: &str) -> Result<KeyPair, NodeError> {
+    key_store(settings.keys_backend)?.key_pair(settings, password)
+}
+
+/// Get node key pair from the key file.
 /// If the key pair does not exist, it is generated and encrypted with the provided password.
 /// If the key pair exists, it is decrypted with the provided password.
-/// The key pair is stored in the keys directory.
+/// The key pair is stored in the keys directory, encrypted as set in `kore.keys`. A key file
+/// encrypted with other parameters, such as the fixed salt of older versions, is encrypted again
+/// with the settings once it is unlocked.
+///
+/// A key file that is not a valid encrypted key, e.g. one truncated by a crash while it was
+/// written, is moved aside as `node_private.der.<timestamp>.corrupted`. A new key pair replaces
+/// it when `regenerate_corrupted_keys` is set; otherwise the error explains how to recover.
 ///
 /// # Arguments
 ///
@@ -29,9 +83,9 @@ use std::fs;
 /// # Errors
 ///
 /// * `NodeError::InternalApi` - Internal API error
-/// * `NodeError::Keys` - Keys error
+/// * `NodeError::Keys` - Keys error, or corrupted key file that may not be regenerated
 ///
-pub fn node_key_pair(settings: &KoreSettings, password: &str) -> Result<KeyPair, NodeError> {
+pub(crate) fn file_key_pair(settings: &KoreSettings, password: &str) -> Result<KeyPair, NodeError> {
     if fs::metadata(&settings.keys_path).is_err() {
         fs::create_dir_all(&settings.keys_path).map_err(|error| {
             NodeError::InternalApi(format!("Error creating keys directory: {}", error))
@@ -40,59 +94,489 @@ pub fn node_key_pair(settings: &KoreSettings, password: &str) -> Result<KeyPair,
     let path = format!("{}/node_private.der", &settings.keys_path);
     match fs::metadata(&path) {
         Ok(_) => {
-            let document = Document::read_der_file(path).map_err(|error| {
-                NodeError::Keys(format!("Error reading node private key: {}", error))
-            })?;
-            let enc_pk =
-                EncryptedPrivateKeyInfo::try_from(document.as_bytes()).map_err(|error| {
-                    NodeError::Keys(format!("Error reading node private key: {}", error))
-                })?;
-            let dec_pk = enc_pk.decrypt(password).map_err(|error| {
-                NodeError::Keys(format!("Error decrypting node private key: {}", error))
-            })?;
-            let key_type = match &settings.settings.node.key_derivator {
-                KeyDerivator::Ed25519 => KeyPairType::Ed25519,
-                KeyDerivator::Secp256k1 => KeyPairType::Secp256k1,
-            };
-            let key_pair =
-                KeyPair::from_secret_der(key_type, dec_pk.as_bytes()).map_err(|error| {
-                    NodeError::Keys(format!(
-                        "Error creating key pair from secret der: {}",
+            let document = match read_key_file(&path) {
+                Ok(document) => document,
+                Err(pkcs8::Error::Asn1(error)) if matches!(error.kind(), der::ErrorKind::Io(_)) => {
+                    return Err(NodeError::Keys(format!(
+                        "Error reading node private key: {}",
                         error
-                    ))
-                })?;
-            Ok(key_pair)
-        }
-        Err(_) => {
-            let key_pair = match &settings.settings.node.key_derivator {
-                KeyDerivator::Ed25519 => KeyPair::Ed25519(Ed25519KeyPair::new()),
-                KeyDerivator::Secp256k1 => KeyPair::Secp256k1(Secp256k1KeyPair::new()),
+                    )))
+                }
+                Err(error) => return recover_corrupted_key(settings, &path, password, error),
             };
-            let der = key_pair
-                .to_secret_der()
-                .map_err(|error| NodeError::Keys(format!("Error getting secret der: {}", error)))?;
-            let pk = PrivateKeyInfo::try_from(der.as_slice()).map_err(|error| {
-                NodeError::Keys(format!("Error creating private key info: {}", error))
-            })?;
-            let params = pkcs5::pbes2::Parameters::pbkdf2_sha256_aes256cbc(
-                2048,
-                &hex!("79d982e70df91a88"),
-                &hex!("b2d02d78b2efd9dff694cf8e0af40925"),
+            unlock_key_pair(
+                &document,
+                &path,
+                settings.settings.node.key_derivator,
+                &settings.keys,
+                password,
+            )
+        }
+        Err(_) => generate_node_key_pair(settings, &path, password),
+    }
+}
+
+/// Rotate node key pair, with the node stopped.
+/// The key file is read with `password`, so that a wrong one leaves it in place, and rotated
+/// like the running node does it, see `rotate_key_file`: it is kept as the next key version,
+/// `node_private.v<N>.der`, and a new key pair takes its place, both encrypted with `password`.
+/// When there is no key file yet, a key pair is generated instead. The new key pair is used from
+/// the next node start.
+///
+/// Kore Base derives both the controller identifier and the network (peer) identifier from the
+/// node key pair, so they cannot be rotated independently: both identities change. Boot nodes and
+/// control lists that reference the old peer identifier must be updated by the operator.
+///
+/// # Arguments
+///
+/// * `settings` - Kore settings
+/// * `password` - Password of the node key
+///
+/// # Returns
+///
+/// * `Result<KeyPair, NodeError>` - New key pair
+///
+/// # Errors
+///
+/// * `NodeError::InternalApi` - Internal API error
+/// * `NodeError::Keys` - Keys error, or `password` is not the one of the node key
+///
+pub fn rotate_node_key_pair(settings: &KoreSettings, password: &str) -> Result<KeyPair, NodeError> {
+    let path = format!("{}/node_private.der", &settings.keys_path);
+    if fs::metadata(&path).is_err() {
+        return file_key_pair(settings, password);
+    }
+    let current = file_key_pair(settings, password)?;
+    let (_, key_pair) = rotate_key_file(
+        &settings.keys_path,
+        settings.settings.node.key_derivator,
+        &settings.keys,
+        &current,
+        password,
+    )?;
+    Ok(key_pair)
+}
+
+/// Replace the key pair in use with a new one, for both the running node, see
+/// `KoreApi::rotate_node_key`, and the stopped one, see `rotate_node_key_pair`.
+/// `current` is written again, encrypted with `password`, as the next key version, so every
+/// version shares the password of the node key; then a new key pair is written to
+/// `node_private.der`.
+///
+/// # Arguments
+///
+/// * `keys_path` - Keys directory
+/// * `key_derivator` - Algorithm of the new key pair
+/// * `encryption` - Encryption of the key files
+/// * `current` - Key pair in use
+/// * `password` - Password of the node key, used to encrypt both key pairs
+///
+/// # Returns
+///
+/// * `Result<(u32, KeyPair), NodeError>` - Version given to `current`, and new key pair
+///
+/// # Errors
+///
+/// * `NodeError::Keys` - Keys error, or `password` is not the one of the node key
+/// * `NodeError::Conflict` - The key file no longer holds `current`: a rotation waits for the
+///   restart of the node
+///
+pub(crate) fn rotate_key_file(
+    keys_path: &str,
+    key_derivator: KeyDerivator,
+    encryption: &KeysSettings,
+    current: &KeyPair,
+    password: &str,
+) -> Result<(u32, KeyPair), NodeError> {
+    let path = format!("{}/node_private.der", keys_path);
+    let stored = read_key_pair(&path, key_derivator, encryption, password)?;
+    if stored.to_bytes() != current.to_bytes() {
+        return Err(NodeError::Conflict(
+            "the node key was already rotated, restart the node to use it".to_owned(),
+        ));
+    }
+    let version = next_key_version(keys_path)?;
+    write_key_pair(
+        current,
+        &key_version_path(keys_path, version),
+        encryption,
+        password,
+    )?;
+    let key_pair = new_key_pair(key_derivator);
+    write_key_pair(&key_pair, &path, encryption, password)?;
+    Ok((version, key_pair))
+}
+
+/// Read the previous key versions of the node, oldest first.
+/// Versions encrypted with another password, e.g. those kept before a password change, are
+/// skipped with a warning.
+///
+/// # Arguments
+///
+/// * `keys_path` - Keys directory
+/// * `key_derivator` - Algorithm of the key pairs
+/// * `encryption` - Encryption of the key files
+/// * `password` - Password of the key versions
+///
+/// # Errors
+///
+/// * `NodeError::Keys` - The keys directory cannot be read
+///
+pub(crate) fn previous_key_pairs(
+    keys_path: &str,
+    key_derivator: KeyDerivator,
+    encryption: &KeysSettings,
+    password: &str,
+) -> Result<Vec<(u32, KeyPair)>, NodeError> {
+    let mut key_pairs = vec![];
+    for version in key_versions(keys_path)? {
+        let path = key_version_path(keys_path, version);
+        match read_key_pair(&path, key_derivator, encryption, password) {
+            Ok(key_pair) => key_pairs.push((version, key_pair)),
+            Err(error) => log::warn!("Node key version {} skipped: {}", version, error),
+        }
+    }
+    Ok(key_pairs)
+}
+
+/// Read and unlock a key file.
+fn read_key_pair(
+    path: &str,
+    key_derivator: KeyDerivator,
+    encryption: &KeysSettings,
+    password: &str,
+) -> Result<KeyPair, NodeError> {
+    let document = read_key_file(path)
+        .map_err(|error| NodeError::Keys(format!("Error reading {}: {}", path, error)))?;
+    unlock_key_pair(&document, path, key_derivator, encryption, password)
+}
+
+/// File of a key version.
+fn key_version_path(keys_path: &str, version: u32) -> String {
+    format!("{}/node_private.v{}.der", keys_path, version)
+}
+
+/// Versions of the key files in the keys directory, sorted.
+fn key_versions(keys_path: &str) -> Result<Vec<u32>, NodeError> {
+    let entries = match fs::read_dir(keys_path) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => {
+            return Err(NodeError::Keys(format!(
+                "Error reading keys directory: {}",
+                error
+            )))
+        }
+    };
+    let mut versions = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|name| {
+            name.strip_prefix("node_private.v")?
+                .strip_suffix(".der")?
+                .parse::<u32>()
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    versions.sort_unstable();
+    Ok(versions)
+}
+
+/// First free key version.
+fn next_key_version(keys_path: &str) -> Result<u32, NodeError> {
+    Ok(key_versions(keys_path)?.last().map_or(1, |last| last + 1))
+}
+
+/// Read a key file, checking that it holds an encrypted private key.
+fn read_key_file(path: &str) -> Result<Document, pkcs8::Error> {
+    let document = Document::read_der_file(path)?;
+    EncryptedPrivateKeyInfo::try_from(document.as_bytes())?;
+    Ok(document)
+}
+
+/// Move a corrupted key file aside and, if the settings allow it, generate a new key pair.
+fn recover_corrupted_key(
+    settings: &KoreSettings,
+    path: &str,
+    password: &str,
+    error: pkcs8::Error,
+) -> Result<KeyPair, NodeError> {
+    let corrupted = format!("{}.{}.corrupted", path, unix_secs());
+    fs::rename(path, &corrupted).map_err(|rename_error| {
+        NodeError::Keys(format!(
+            "Node private key {} is corrupted ({}) and cannot be moved aside: {}",
+            path, error, rename_error
+        ))
+    })?;
+    log::warn!(
+        "Node private key {} is corrupted ({}), moved to {}",
+        path,
+        error,
+        corrupted
+    );
+    if !settings.regenerate_corrupted_keys {
+        return Err(NodeError::Keys(format!(
+            "Node private key {} is corrupted ({}) and was moved to {}. Restore the key file from \
+             a backup, or set kore.regenerate_corrupted_keys (KORE_REGENERATE_CORRUPTED_KEYS) to \
+             generate a new key pair, which changes the controller and peer ids of the node",
+            path, error, corrupted
+        )));
+    }
+    log::warn!("Generating a new node key pair, the controller and peer ids of the node change");
+    generate_node_key_pair(settings, path, password)
+}
+
+/// Seconds since UNIX epoch, used to name the replaced key files.
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Decrypt a key file read from `path`, and encrypt it again when its encryption is not the
+/// one of the settings. A failure to rewrite the file is only logged: the key pair is valid.
+fn unlock_key_pair(
+    document: &Document,
+    path: &str,
+    key_derivator: KeyDerivator,
+    encryption: &KeysSettings,
+    password: &str,
+) -> Result<KeyPair, NodeError> {
+    let enc_pk = EncryptedPrivateKeyInfo::try_from(document.as_bytes())
+        .map_err(|error| NodeError::Keys(format!("Error reading node private key: {}", error)))?;
+    let dec_pk = enc_pk.decrypt(password).map_err(|error| {
+        NodeError::Keys(format!(
+            "Error decrypting node private key {}, check the password: {}",
+            path, error
+        ))
+    })?;
+    let key_pair = key_pair_from_der(key_derivator, dec_pk.as_bytes())?;
+    if is_outdated(&enc_pk.encryption_algorithm, encryption) {
+        match write_key_pair(&key_pair, path, encryption, password) {
+            Ok(()) => log::info!("Key file {} encrypted again with kore.keys", path),
+            Err(error) => log::warn!("Key file {} not encrypted again: {}", path, error),
+        }
+    }
+    Ok(key_pair)
+}
+
+/// Whether a key file is encrypted with other parameters than the settings, or with the fixed
+/// salt of older versions.
+fn is_outdated(scheme: &pkcs5::EncryptionScheme, encryption: &KeysSettings) -> bool {
+    let Some(params) = scheme.pbes2() else {
+        return true;
+    };
+    if !matches!(params.encryption, pbes2::EncryptionScheme::Aes256Cbc { .. }) {
+        return true;
+    }
+    match (&params.kdf, encryption.kdf) {
+        (Kdf::Pbkdf2(kdf), KeyKdf::Pbkdf2) => {
+            kdf.salt == LEGACY_SALT || kdf.iteration_count != encryption.iterations
+        }
+        (Kdf::Scrypt(kdf), KeyKdf::Scrypt) => {
+            kdf.salt == LEGACY_SALT
+                || kdf.cost_parameter != 1 << encryption.scrypt_log_n
+                || u32::from(kdf.block_size) != encryption.scrypt_r
+                || u32::from(kdf.parallelization) != encryption.scrypt_p
+        }
+        _ => true,
+    }
+}
+
+/// scrypt parameters of the settings.
+///
+/// # Errors
+///
+/// * `InvalidParams` - `log_n` is 64 or more, or `r` or `p` out of range
+///
+pub(crate) fn scrypt_params(
+    log_n: u8,
+    r: u32,
+    p: u32,
+) -> Result<scrypt::Params, scrypt::errors::InvalidParams> {
+    scrypt::Params::new(log_n, r, p, AES_256_KEY_SIZE)
+}
+
+/// Key pair of an unencrypted secret key DER.
+pub(crate) fn key_pair_from_der(
+    key_derivator: KeyDerivator,
+    der: &[u8],
+) -> Result<KeyPair, NodeError> {
+    let key_type = match key_derivator {
+        KeyDerivator::Ed25519 => KeyPairType::Ed25519,
+        KeyDerivator::Secp256k1 => KeyPairType::Secp256k1,
+    };
+    KeyPair::from_secret_der(key_type, der).map_err(|error| {
+        NodeError::Keys(format!(
+            "Error creating key pair from secret der: {}",
+            error
+        ))
+    })
+}
+
+/// Generate a new key pair.
+pub(crate) fn new_key_pair(key_derivator: KeyDerivator) -> KeyPair {
+    match key_derivator {
+        KeyDerivator::Ed25519 => KeyPair::Ed25519(Ed25519KeyPair::new()),
+        KeyDerivator::Secp256k1 => KeyPair::Secp256k1(Secp256k1KeyPair::new()),
+    }
+}
+
+/// Peer identifier of a node key pair, the one Kore Base announces in the network.
+pub(crate) fn peer_id(key_pair: &KeyPair) -> Result<String, NodeError> {
+    let public_key = key_pair.public_key_bytes();
+    let public_key: PublicKey = match key_pair {
+        KeyPair::Ed25519(_) => ed25519::PublicKey::try_from_bytes(&public_key)
+            .map_err(|error| NodeError::Keys(error.to_string()))?
+            .into(),
+        KeyPair::Secp256k1(_) => secp256k1::PublicKey::try_from_bytes(&public_key)
+            .map_err(|error| NodeError::Keys(error.to_string()))?
+            .into(),
+    };
+    Ok(PeerId::from_public_key(&public_key).to_string())
+}
+
+/// Generate a new node key pair and store it encrypted in `path`.
+fn generate_node_key_pair(
+    settings: &KoreSettings,
+    path: &str,
+    password: &str,
+) -> Result<KeyPair, NodeError> {
+    let key_pair = new_key_pair(settings.settings.node.key_derivator);
+    write_key_pair(&key_pair, path, &settings.keys, password)?;
+    Ok(key_pair)
+}
+
+/// Store a key pair encrypted in `path`, with a new random salt and IV.
+/// The key is written to a temporary file that then replaces `path`, so a crash never leaves a
+/// partially written key in place.
+fn write_key_pair(
+    key_pair: &KeyPair,
+    path: &str,
+    encryption: &KeysSettings,
+    password: &str,
+) -> Result<(), NodeError> {
+    let der = key_pair
+        .to_secret_der()
+        .map_err(|error| NodeError::Keys(format!("Error getting secret der: {}", error)))?;
+    let pk = PrivateKeyInfo::try_from(der.as_slice())
+        .map_err(|error| NodeError::Keys(format!("Error creating private key info: {}", error)))?;
+    let salt = rand::random::<[u8; 16]>();
+    let iv = rand::random::<[u8; 16]>();
+    let params = match encryption.kdf {
+        KeyKdf::Pbkdf2 => {
+            pbes2::Parameters::pbkdf2_sha256_aes256cbc(encryption.iterations, &salt, &iv)
+        }
+        KeyKdf::Scrypt => {
+            let scrypt = scrypt_params(
+                encryption.scrypt_log_n,
+                encryption.scrypt_r,
+                encryption.scrypt_p,
             )
-            .map_err(|error| {
-                NodeError::Keys(format!("Error creating pkcs5 parameters: {}", error))
-            })?;
-            let enc_pk = pk
-                .encrypt_with_params(params, password)
-                .map_err(|_| NodeError::Keys("Error encrypting private key".to_owned()))?;
-            enc_pk.write_der_file(path).map_err(|error| {
-                NodeError::Keys(format!("Error writing node private key: {}", error))
-            })?;
-            Ok(key_pair)
+            .map_err(|error| NodeError::Keys(format!("Invalid scrypt parameters: {}", error)))?;
+            pbes2::Parameters::scrypt_aes256cbc(scrypt, &salt, &iv)
+        }
+    }
+    .map_err(|error| NodeError::Keys(format!("Error creating pkcs5 parameters: {}", error)))?;
+    let enc_pk = pk
+        .encrypt_with_params(params, password)
+        .map_err(|_| NodeError::Keys("Error encrypting private key".to_owned()))?;
+    let partial = format!("{}.partial", path);
+    enc_pk
+        .write_der_file(&partial)
+        .and_then(|_| fs::rename(&partial, path).map_err(der::Error::from))
+        .map_err(|error| NodeError::Keys(format!("Error writing node private key: {}", error)))
+}
+
+/// Check the listen addresses of the node.
+/// Each TCP listen address is bound and released again. When an address is in use, the
+/// fallback ports are tried in order on the same IP and the address is rewritten with the first
+/// free one, so that the node starts instead of failing later inside the network layer. Addresses
+/// that are not TCP or use port 0 are left as they are, and nothing is checked with port reuse.
+///
+/// # Arguments
+///
+/// * `network` - Network settings, whose listen addresses may be rewritten
+/// * `fallback_ports` - Alternate ports, each one used at most once
+///
+/// # Errors
+///
+/// * `NodeError::Network` - Address in use and no free fallback port
+///
+pub fn check_listen_addresses(
+    network: &mut NetworkConfig,
+    fallback_ports: &[u16],
+) -> Result<(), NodeError> {
+    if network.port_reuse {
+        return Ok(());
+    }
+    let mut fallback_ports = fallback_ports.iter();
+    for address in network.listen_addresses.iter_mut() {
+        let Some((ip, port)) = tcp_socket(address) else {
+            continue;
+        };
+        if port == 0 || TcpListener::bind((ip, port)).is_ok() {
+            continue;
         }
+        let Some(free) = fallback_ports.find(|port| TcpListener::bind((ip, **port)).is_ok()) else {
+            return Err(NodeError::Network(address.clone()));
+        };
+        log::warn!("Listen address {} in use, using port {}", address, free);
+        *address = with_port(address, *free);
+    }
+    Ok(())
+}
+
+/// Replace port 0 in the TCP listen addresses of the node with a free port picked by the
+/// system, so that the addresses the node reports are the ones it binds.
+///
+/// # Arguments
+///
+/// * `network` - Network settings, whose listen addresses may be rewritten
+///
+/// # Errors
+///
+/// * `NodeError::Network` - No free port
+///
+pub fn pick_listen_ports(network: &mut NetworkConfig) -> Result<(), NodeError> {
+    for address in network.listen_addresses.iter_mut() {
+        let Some((ip, 0)) = tcp_socket(address) else {
+            continue;
+        };
+        let port = TcpListener::bind((ip, 0))
+            .and_then(|listener| listener.local_addr())
+            .map_err(|_| NodeError::Network(address.clone()))?
+            .port();
+        *address = with_port(address, port);
+    }
+    Ok(())
+}
+
+/// TCP multiaddress with another port.
+fn with_port(address: &str, port: u16) -> String {
+    let port = port.to_string();
+    let mut parts = address.split('/').collect::<Vec<&str>>();
+    parts[4] = &port;
+    parts.join("/")
+}
+
+/// IP and port of a `/ip4/<ip>/tcp/<port>` or `/ip6/<ip>/tcp/<port>` multiaddress.
+fn tcp_socket(address: &str) -> Option<(IpAddr, u16)> {
+    let parts = address.split('/').collect::<Vec<&str>>();
+    match parts.as_slice() {
+        ["", "ip4" | "ip6", ip, "tcp", port, ..] => Some((ip.parse().ok()?, port.parse().ok()?)),
+        _ => None,
     }
 }
 
+/// Replace a setting shared by a component and its clones, e.g. on a reload. The value is
+/// replaced whole, so a lock poisoned by a panic while it was held is recovered and cleared
+/// instead of dropping the update.
+pub(crate) fn replace_setting<T>(setting: &RwLock<T>, value: T) {
+    *setting.write().unwrap_or_else(PoisonError::into_inner) = value;
+    setting.clear_poison();
+}
+
 #[cfg(feature = "sqlite")]
 pub fn split_path(path: &str) -> (String, String) {
     let mut parts: Vec<&str> = path.rsplitn(2, '/').collect();
@@ -113,6 +597,7 @@ mod tests {
     #[test]
     fn test_node_key_pair() {
         let mut settings = KoreSettings::default();
+        settings.keys.iterations = MIN_PBKDF2_ITERATIONS;
         let tempdir = tempfile::tempdir().unwrap();
         let path = tempdir.path().join("keys");
         settings.keys_path = path.to_str().unwrap().to_owned();
@@ -121,4 +606,212 @@ mod tests {
         assert_eq!(key_pair.to_bytes(), key_pair2.to_bytes());
         fs::remove_dir_all(&settings.keys_path).unwrap();
     }
+
+    #[test]
+    fn test_rotate_node_key_pair() {
+        let mut settings = KoreSettings::default();
+        settings.keys.iterations = MIN_PBKDF2_ITERATIONS;
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("keys");
+        settings.keys_path = path.to_str().unwrap().to_owned();
+        let key_pair = node_key_pair(&settings, "password").unwrap();
+        let rotated = rotate_node_key_pair(&settings, "password").unwrap();
+        assert_ne!(key_pair.to_bytes(), rotated.to_bytes());
+        let key_pair2 = node_key_pair(&settings, "password").unwrap();
+        assert_eq!(rotated.to_bytes(), key_pair2.to_bytes());
+        assert_eq!(fs::read_dir(&path).unwrap().count(), 2);
+        assert!(path.join("node_private.v1.der").exists());
+        // A wrong password leaves the key in place.
+        assert!(matches!(
+            rotate_node_key_pair(&settings, "wrong"),
+            Err(NodeError::Keys(_))
+        ));
+        assert_eq!(fs::read_dir(&path).unwrap().count(), 2);
+
+        // Versions share the password of the node key.
+        let key_derivator = settings.settings.node.key_derivator;
+        let current = node_key_pair(&settings, "password").unwrap();
+        let (version, new) = rotate_key_file(
+            &settings.keys_path,
+            key_derivator,
+            &settings.keys,
+            &current,
+            "password",
+        )
+        .unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(
+            node_key_pair(&settings, "password").unwrap().to_bytes(),
+            new.to_bytes()
+        );
+        let previous = previous_key_pairs(
+            &settings.keys_path,
+            key_derivator,
+            &settings.keys,
+            "password",
+        )
+        .unwrap();
+        assert_eq!(previous.len(), 2);
+        assert_eq!(previous[0].1.to_bytes(), key_pair.to_bytes());
+        assert_eq!(previous[1].0, 2);
+        assert_eq!(previous[1].1.to_bytes(), current.to_bytes());
+
+        let rotate = |key_pair: &KeyPair, password: &str| {
+            rotate_key_file(
+                &settings.keys_path,
+                key_derivator,
+                &settings.keys,
+                key_pair,
+                password,
+            )
+        };
+        assert!(matches!(
+            rotate(&current, "password"),
+            Err(NodeError::Conflict(_))
+        ));
+        assert!(matches!(rotate(&new, "wrong"), Err(NodeError::Keys(_))));
+    }
+
+    #[test]
+    fn test_key_file_encryption() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut settings = KoreSettings {
+            keys_path: tempdir.path().to_str().unwrap().to_owned(),
+            ..Default::default()
+        };
+        settings.keys.iterations = MIN_PBKDF2_ITERATIONS;
+        let path = format!("{}/node_private.der", settings.keys_path);
+        let scheme = |path: &str| {
+            let document = Document::read_der_file(path).unwrap();
+            let enc_pk = EncryptedPrivateKeyInfo::try_from(document.as_bytes()).unwrap();
+            let kdf = &enc_pk.encryption_algorithm.pbes2().unwrap().kdf;
+            let salt = kdf
+                .pbkdf2()
+                .map(|kdf| kdf.salt)
+                .or(kdf.scrypt().map(|kdf| kdf.salt))
+                .unwrap();
+            (kdf.is_scrypt(), salt.to_vec())
+        };
+
+        // Key file written with the fixed salt of older versions.
+        let key_pair = new_key_pair(settings.settings.node.key_derivator);
+        let der = key_pair.to_secret_der().unwrap();
+        PrivateKeyInfo::try_from(der.as_slice())
+            .unwrap()
+            .encrypt_with_params(
+                pbes2::Parameters::pbkdf2_sha256_aes256cbc(
+                    2048,
+                    &LEGACY_SALT,
+                    &hex!("b2d02d78b2efd9dff694cf8e0af40925"),
+                )
+                .unwrap(),
+                "password",
+            )
+            .unwrap()
+            .write_der_file(&path)
+            .unwrap();
+        let legacy = fs::read(&path).unwrap();
+
+        let unlocked = node_key_pair(&settings, "password").unwrap();
+        assert_eq!(unlocked.to_bytes(), key_pair.to_bytes());
+        let (scrypt, salt) = scheme(&path);
+        assert!(!scrypt);
+        assert_ne!(salt, LEGACY_SALT);
+        // Up to date files are not written again.
+        let migrated = fs::read(&path).unwrap();
+        assert_ne!(migrated, legacy);
+        node_key_pair(&settings, "password").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), migrated);
+
+        settings.keys.kdf = KeyKdf::Scrypt;
+        settings.keys.scrypt_log_n = 10;
+        let unlocked = node_key_pair(&settings, "password").unwrap();
+        assert_eq!(unlocked.to_bytes(), key_pair.to_bytes());
+        assert!(scheme(&path).0);
+
+        // Every file gets its own salt.
+        let other = format!("{}/other.der", settings.keys_path);
+        write_key_pair(&key_pair, &other, &settings.keys, "password").unwrap();
+        assert_ne!(scheme(&other).1, scheme(&path).1);
+    }
+
+    #[test]
+    fn test_corrupted_node_key_pair() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("keys");
+        fs::create_dir_all(&path).unwrap();
+        let key_file = path.join("node_private.der");
+        // Key file truncated in the middle of its first write.
+        fs::write(&key_file, [0x30, 0x82, 0x01, 0x2c, 0x30]).unwrap();
+
+        let mut settings = KoreSettings {
+            keys_path: path.to_str().unwrap().to_owned(),
+            ..Default::default()
+        };
+        settings.keys.iterations = MIN_PBKDF2_ITERATIONS;
+        let Err(NodeError::Keys(message)) = node_key_pair(&settings, "password") else {
+            panic!("corrupted key file accepted");
+        };
+        assert!(message.contains("corrupted"));
+        assert!(message.contains("KORE_REGENERATE_CORRUPTED_KEYS"));
+        assert!(fs::metadata(&key_file).is_err());
+        let moved = fs::read_dir(&path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(moved.len(), 1);
+        assert!(moved[0].starts_with("node_private.der.") && moved[0].ends_with(".corrupted"));
+
+        fs::write(&key_file, b"").unwrap();
+        settings.regenerate_corrupted_keys = true;
+        let key_pair = node_key_pair(&settings, "password").unwrap();
+        let key_pair2 = node_key_pair(&settings, "password").unwrap();
+        assert_eq!(key_pair.to_bytes(), key_pair2.to_bytes());
+    }
+
+    #[test]
+    fn test_check_listen_addresses() {
+        let busy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let busy_port = busy.local_addr().unwrap().port();
+        let free_port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut network = NetworkConfig::new(
+            kore_base::NodeType::Bootstrap,
+            vec![
+                format!("/ip4/127.0.0.1/tcp/{}", busy_port),
+                "/memory/1".to_owned(),
+            ],
+            vec![],
+            vec![],
+            false,
+        );
+
+        let error = check_listen_addresses(&mut network.clone(), &[]).unwrap_err();
+        assert!(
+            matches!(error, NodeError::Network(address) if address.ends_with(&busy_port.to_string()))
+        );
+
+        check_listen_addresses(&mut network, &[busy_port, free_port]).unwrap();
+        assert_eq!(
+            network.listen_addresses,
+            vec![
+                format!("/ip4/127.0.0.1/tcp/{}", free_port),
+                "/memory/1".to_owned(),
+            ]
+        );
+
+        let mut network = NetworkConfig::new(
+            kore_base::NodeType::Bootstrap,
+            vec!["/ip4/127.0.0.1/tcp/0".to_owned(), "/memory/1".to_owned()],
+            vec![],
+            vec![],
+            false,
+        );
+        pick_listen_ports(&mut network).unwrap();
+        assert!(!network.listen_addresses[0].ends_with("/tcp/0"));
+        assert_eq!(network.listen_addresses[1], "/memory/1");
+    }
 }
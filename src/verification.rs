@@ -0,0 +1,217 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Ledger verification.
+//!
+//! Checks the events of a subject on the copy of the ledger held by the node, without asking the
+//! validators, see `KoreApi::verify_event` and `KoreApi::verify_subject_chain`. For each event:
+//!
+//! | Check | Passes when |
+//! |-------|-------------|
+//! | `sequence` | The event belongs to the subject and has the expected sequence number |
+//! | `prev_event_hash` | It holds the digest of the previous event, or none for the first one |
+//! | `event_signature` | The signature of the event matches its content |
+//! | `request_signature` | The signature of the event request matches the request |
+//! | `approver_signature` | Each approver signed the approval response rebuilt from the event |
+//! | `evaluator_signature` | Never checked: the evaluation request is not kept in the ledger |
+//!
+//! An event is valid when every check passed, and partially verified when none failed but some
+//! could not be made, as for the evaluator signatures. Digests are computed with the derivator
+//! of the ledger, the one of the content hash of the event signature, whatever the derivator of
+//! the node.
+//!
+
+use borsh::BorshSerialize;
+use kore_base::{
+    signature::{Signature, Signed},
+    ApprovalRequest, ApprovalResponse, Derivable, DigestDerivator, DigestIdentifier, Event,
+};
+
+use crate::model::{
+    NodeEventVerification, NodeVerificationCheck, NodeVerificationKind, NodeVerificationStatus,
+};
+
+/// Check an event of a subject.
+///
+/// # Arguments
+///
+/// * `event` - Event to check, as kept by Kore Base.
+/// * `previous` - Event before it, `None` for the first event.
+/// * `subject_id` - Subject the event must belong to.
+/// * `governance_id` - Governance of the subject, the subject itself for a governance.
+///
+/// # Returns
+///
+/// * `NodeEventVerification` - Checks of the event, valid when all of them passed.
+///
+pub(crate) fn verify_event(
+    event: &Signed<Event>,
+    previous: Option<&Signed<Event>>,
+    subject_id: &DigestIdentifier,
+    governance_id: &DigestIdentifier,
+) -> NodeEventVerification {
+    let content = &event.content;
+    let derivator = event.signature.content_hash.derivator;
+    let expected_sn = previous.map_or(0, |previous| previous.content.sn + 1);
+    let mut checks = vec![
+        check(
+            NodeVerificationKind::Sequence,
+            None,
+            if content.subject_id != *subject_id {
+                Err(format!("event of subject {}", content.subject_id.to_str()))
+            } else if content.sn != expected_sn {
+                Err(format!(
+                    "sequence number {} after {}",
+                    content.sn, expected_sn
+                ))
+            } else {
+                Ok(())
+            },
+        ),
+        check(
+            NodeVerificationKind::PrevEventHash,
+            None,
+            prev_event_hash(content, previous, derivator),
+        ),
+        signature_check(
+            NodeVerificationKind::EventSignature,
+            &event.signature,
+            content,
+            derivator,
+        ),
+        signature_check(
+            NodeVerificationKind::RequestSignature,
+            &content.event_request.signature,
+            &content.event_request.content,
+            derivator,
+        ),
+    ];
+
+    let mut evaluators = content.evaluators.iter().collect::<Vec<_>>();
+    evaluators.sort_by_key(|signature| signature.signer.to_str());
+    checks.extend(
+        evaluators
+            .into_iter()
+            .map(|signature| NodeVerificationCheck {
+                kind: NodeVerificationKind::EvaluatorSignature,
+                signer: Some(signature.signer.to_str()),
+                status: NodeVerificationStatus::Unchecked,
+                detail: Some("the evaluation request is not kept in the ledger".to_owned()),
+            }),
+    );
+
+    let mut approvers = content.approvers.iter().collect::<Vec<_>>();
+    approvers.sort_by_key(|signature| signature.signer.to_str());
+    if !approvers.is_empty() {
+        let request = ApprovalRequest {
+            event_request: content.event_request.clone(),
+            sn: content.sn,
+            gov_version: content.gov_version,
+            patch: content.patch.clone(),
+            state_hash: content.state_hash.clone(),
+            hash_prev_event: content.hash_prev_event.clone(),
+            gov_id: governance_id.clone(),
+        };
+        match DigestIdentifier::from_serializable_borsh(&request, derivator) {
+            Ok(appr_req_hash) => {
+                let response = ApprovalResponse {
+                    appr_req_hash,
+                    approved: content.approved,
+                };
+                checks.extend(approvers.into_iter().map(|signature| {
+                    signature_check(
+                        NodeVerificationKind::ApproverSignature,
+                        signature,
+                        &response,
+                        derivator,
+                    )
+                }));
+            }
+            Err(error) => checks.extend(approvers.into_iter().map(|signature| {
+                check(
+                    NodeVerificationKind::ApproverSignature,
+                    Some(signature),
+                    Err(format!("approval request cannot be hashed: {}", error)),
+                )
+            })),
+        }
+    }
+
+    let failed = checks
+        .iter()
+        .any(|check| check.status == NodeVerificationStatus::Invalid);
+    let unchecked = checks
+        .iter()
+        .any(|check| check.status == NodeVerificationStatus::Unchecked);
+    NodeEventVerification {
+        subject_id: subject_id.to_str(),
+        sn: content.sn,
+        valid: !failed && !unchecked,
+        partial: !failed && unchecked,
+        checks,
+    }
+}
+
+/// Whether an event links to the previous one.
+fn prev_event_hash(
+    event: &Event,
+    previous: Option<&Signed<Event>>,
+    derivator: DigestDerivator,
+) -> Result<(), String> {
+    let Some(previous) = previous else {
+        return if event.hash_prev_event.to_str().is_empty() {
+            Ok(())
+        } else {
+            Err("first event with a previous event".to_owned())
+        };
+    };
+    let digest = DigestIdentifier::from_serializable_borsh(&previous.content, derivator)
+        .map_err(|error| format!("previous event cannot be hashed: {}", error))?;
+    if digest == event.hash_prev_event {
+        Ok(())
+    } else {
+        Err(format!(
+            "digest {} of event {} expected, found {}",
+            digest.to_str(),
+            previous.content.sn,
+            event.hash_prev_event.to_str()
+        ))
+    }
+}
+
+/// Check a signature of `content`: its content hash, then the signature itself.
+fn signature_check<T: BorshSerialize>(
+    kind: NodeVerificationKind,
+    signature: &Signature,
+    content: &T,
+    derivator: DigestDerivator,
+) -> NodeVerificationCheck {
+    let result = match DigestIdentifier::from_serializable_borsh(content, derivator) {
+        Ok(digest) if digest != signature.content_hash => {
+            Err("content hash does not match the content".to_owned())
+        }
+        Ok(_) => signature
+            .verify(content)
+            .map_err(|_| "signature does not match the content".to_owned()),
+        Err(error) => Err(format!("content cannot be hashed: {}", error)),
+    };
+    check(kind, Some(signature), result)
+}
+
+/// Check with its result.
+fn check(
+    kind: NodeVerificationKind,
+    signature: Option<&Signature>,
+    result: Result<(), String>,
+) -> NodeVerificationCheck {
+    let (status, detail) = match result {
+        Ok(()) => (NodeVerificationStatus::Valid, None),
+        Err(detail) => (NodeVerificationStatus::Invalid, Some(detail)),
+    };
+    NodeVerificationCheck {
+        kind,
+        signer: signature.map(|signature| signature.signer.to_str()),
+        status,
+        detail,
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Warm-up.
+//!
+//! Reads the hot subjects of `kore.warm_up` when the node starts, before the REST and gRPC APIs
+//! are served: the configured subjects, then those with the most recent requests sent through
+//! the node. The head event of each subject is read and checked against the subject, so the
+//! first reads of dashboards find the database caches warm and a broken head is logged at boot
+//! instead of on a client request.
+//!
+
+use std::{collections::HashSet, time::Instant};
+
+use crate::{error::NodeError, model::PaginatorFromString, settings::WarmUpSettings, KoreApi};
+
+/// Result of the warm-up.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WarmUpReport {
+    /// Subjects whose head was read and verified.
+    pub verified: Vec<String>,
+    /// Subjects that could not be read or whose head does not match, with the reason.
+    pub failed: Vec<(String, String)>,
+}
+
+/// Warm up the subjects of the settings.
+/// Every call shares the deadline of `settings.timeout`; once it expires, the remaining subjects
+/// fail with `NodeError::Timeout`.
+///
+/// # Arguments
+///
+/// * `api` - Kore API.
+/// * `settings` - Subjects to warm up.
+///
+/// # Returns
+///
+/// * `WarmUpReport` - Verified and failed subjects.
+///
+pub async fn warm_up(api: &KoreApi, settings: &WarmUpSettings) -> WarmUpReport {
+    let api = api.with_timeout(settings.timeout);
+    let mut report = WarmUpReport::default();
+    let subjects = match hot_subjects(&api, settings).await {
+        Ok(subjects) => subjects,
+        Err(error) => {
+            log::warn!("Recent subjects of the warm-up not available: {}", error);
+            settings.subjects.clone()
+        }
+    };
+    for subject_id in subjects {
+        match verify_head(&api, &subject_id).await {
+            Ok(()) => report.verified.push(subject_id),
+            Err(error) => report.failed.push((subject_id, error.to_string())),
+        }
+    }
+    report
+}
+
+/// Warm up the subjects and log the result.
+///
+/// # Arguments
+///
+/// * `api` - Kore API.
+/// * `settings` - Subjects to warm up.
+///
+pub async fn run_warm_up(api: &KoreApi, settings: &WarmUpSettings) {
+    let start = Instant::now();
+    let report = warm_up(api, settings).await;
+    for (subject_id, reason) in report.failed.iter() {
+        log::warn!("Warm-up of subject {} failed: {}", subject_id, reason);
+    }
+    log::info!(
+        "Warm-up finished in {} ms: {} subjects verified, {} failed",
+        start.elapsed().as_millis(),
+        report.verified.len(),
+        report.failed.len()
+    );
+}
+
+/// Configured subjects, followed by the subjects of the most recent requests.
+async fn hot_subjects(api: &KoreApi, settings: &WarmUpSettings) -> Result<Vec<String>, NodeError> {
+    let mut subjects = settings.subjects.clone();
+    let mut seen = subjects.iter().cloned().collect::<HashSet<_>>();
+    if settings.recent == 0 {
+        return Ok(subjects);
+    }
+    let records = api.list_requests(PaginatorFromString {
+        from: None,
+        quantity: None,
+    })?;
+    let mut recent = 0;
+    for record in records.into_iter().rev() {
+        if recent == settings.recent {
+            break;
+        }
+        // Create requests are recorded before their subject exists.
+        let subject_id = match record.subject_id {
+            Some(subject_id) => subject_id,
+            None => match api.get_event_request_state(&record.request_id).await {
+                Ok(state) => match state.subject_id {
+                    Some(subject_id) => subject_id,
+                    None => continue,
+                },
+                Err(NodeError::Timeout) => return Err(NodeError::Timeout),
+                Err(_) => continue,
+            },
+        };
+        if seen.insert(subject_id.clone()) {
+            subjects.push(subject_id);
+            recent += 1;
+        }
+    }
+    Ok(subjects)
+}
+
+/// Read the subject and its head event, and check that they agree.
+async fn verify_head(api: &KoreApi, subject_id: &str) -> Result<(), NodeError> {
+    let subject = api.get_subject(subject_id).await?;
+    let head = api.get_event_of_subject(subject_id, subject.sn).await?;
+    if head.content.subject_id != subject.subject_id || head.content.sn != subject.sn {
+        return Err(NodeError::Conflict(format!(
+            "head event {} of {} does not match the subject at sn {}",
+            head.content.sn, head.content.subject_id, subject.sn
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+
+    use super::*;
+    use crate::{api::tests::create_event, node::tests::export_sqlite_api};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_sqlite_warm_up() {
+        let api = export_sqlite_api(219, vec![]).await;
+        let governance_id = create_event(&api, "", "governance", "warm").await;
+        let settings = WarmUpSettings {
+            subjects: vec!["JoRd5mMTEpAGJjHP8W3SsN93rNVzbFXyrVjxsRGcP6kE".to_owned()],
+            recent: 5,
+            timeout: Duration::from_secs(10),
+        };
+
+        let report = warm_up(&api, &settings).await;
+        assert_eq!(report.verified, vec![governance_id]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, settings.subjects[0]);
+    }
+}
@@ -0,0 +1,408 @@
+// Copyright 2024 Kore Ledger
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! # Webhooks.
+//!
+//! POSTs a JSON notification to every URL of `[kore.webhooks]` when an approval becomes pending
+//! and when an event request sent through this node finishes or fails, so that external systems
+//! do not have to poll `get_approvals`. Kore Base does not notify these changes, so a single task
+//! polls the pending approvals and the requests in flight. A request whose state cannot be read
+//! for `MAX_STATE_FAILURES` polls in a row is no longer followed.
+//!
+//! Each notification is delivered to the URLs concurrently, and a failed delivery is retried with
+//! exponential backoff until the node stops. When a secret is set, the `X-Kore-Signature` header carries
+//! `sha256=<hex HMAC-SHA256 of the body>`.
+//!
+
+use std::{collections::HashSet, time::Duration};
+
+use futures::future::join_all;
+use hmac::{Hmac, Mac};
+use reqwest::{header::CONTENT_TYPE, Client};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::{
+    error::NodeError,
+    model::{NodeApprovalEntity, NodeKoreRequestState, NodeRequestState, Timestamped},
+    settings::{TimestampFormat, WebhookSettings},
+    tasks::NodeTasks,
+    KoreApi,
+};
+
+/// Header with the name of the notified event.
+pub const EVENT_HEADER: &str = "x-kore-event";
+
+/// Header with the HMAC-SHA256 signature of the body.
+pub const SIGNATURE_HEADER: &str = "x-kore-signature";
+
+/// Time between two reads of the approvals and requests.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time allowed to each delivery attempt.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Polls in a row in which the state of a request may not be read before it is no longer
+/// followed.
+const MAX_STATE_FAILURES: u32 = 60;
+
+/// Identity of the webhooks in the access logs.
+const WEBHOOKS_SOURCE: &str = "webhooks";
+
+/// Notification sent to the webhooks.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// An approval is waiting for the vote of the node.
+    ApprovalPending { approval: Box<NodeApprovalEntity> },
+    /// An event request was processed.
+    RequestFinished { request: NodeKoreRequestState },
+    /// An event request failed or was rejected.
+    RequestFailed { request: NodeKoreRequestState },
+}
+
+impl WebhookEvent {
+    /// Name of the event, as in the `event` field of the payload.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ApprovalPending { .. } => "approval_pending",
+            Self::RequestFinished { .. } => "request_finished",
+            Self::RequestFailed { .. } => "request_failed",
+        }
+    }
+
+    /// Event of a request that is no longer processing.
+    fn from_request(request: NodeKoreRequestState) -> Option<Self> {
+        match request.state {
+            NodeRequestState::Processing => None,
+            NodeRequestState::Finished => Some(Self::RequestFinished { request }),
+            NodeRequestState::Rejected | NodeRequestState::Error => {
+                Some(Self::RequestFailed { request })
+            }
+        }
+    }
+}
+
+/// Start notifying the webhooks, until the node is cancelled.
+/// Only the approvals and requests that appear after the start are notified.
+///
+/// # Arguments
+///
+/// * `api` - Kore API.
+/// * `settings` - URLs, secret and retry policy.
+/// * `tasks` - Tasks of the node, stopped when it is cancelled.
+///
+pub fn run_webhooks(api: KoreApi, settings: WebhookSettings, tasks: &NodeTasks) {
+    let client = match Client::builder().timeout(DELIVERY_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(error) => {
+            log::error!("Webhooks disabled, the HTTP client failed: {}", error);
+            return;
+        }
+    };
+    let cancellation = tasks.token();
+    let api = api
+        .with_cancellation(cancellation.clone())
+        .background(WEBHOOKS_SOURCE);
+    let notifications = tasks.clone();
+    tasks.spawn(async move {
+        let mut followed = Followed::default();
+        if let Err(error) = followed.start(&api).await {
+            log::warn!("Webhooks could not read the node state: {}", error);
+        }
+        let mut interval = interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = cancellation.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            match followed.poll(&api).await {
+                Ok(events) => {
+                    let format = api.timestamp_format();
+                    for event in events {
+                        // Deliveries still retrying stop with the node.
+                        let delivery = notify(client.clone(), settings.clone(), event, format);
+                        let cancellation = cancellation.clone();
+                        notifications.spawn(async move {
+                            tokio::select! {
+                                _ = cancellation.cancelled() => {}
+                                _ = delivery => {}
+                            }
+                        });
+                    }
+                }
+                Err(error) => log::warn!("Webhooks could not read the node state: {}", error),
+            }
+        }
+    });
+}
+
+/// Approvals and requests already seen by the webhooks.
+#[derive(Default)]
+struct Followed {
+    /// Approvals pending in the last poll.
+    pending: HashSet<String>,
+    /// Requests still processing, with the polls in a row in which their state was not read.
+    in_flight: Vec<(String, u32)>,
+    /// Cursor of the last request read, see `KoreApi::requests_after`.
+    last_request: Option<String>,
+}
+
+impl Followed {
+    /// Take the current approvals and requests as already notified.
+    async fn start(&mut self, api: &KoreApi) -> Result<(), NodeError> {
+        self.pending = api
+            .get_all_pending_approvals()
+            .await?
+            .into_iter()
+            .map(|approval| approval.id)
+            .collect();
+        self.last_request = api.last_request_cursor()?;
+        Ok(())
+    }
+
+    /// Events since the previous poll.
+    async fn poll(&mut self, api: &KoreApi) -> Result<Vec<WebhookEvent>, NodeError> {
+        let mut events = vec![];
+        let approvals = api.get_all_pending_approvals().await?;
+        let pending = approvals
+            .iter()
+            .map(|approval| approval.id.clone())
+            .collect::<HashSet<_>>();
+        for approval in approvals {
+            if !self.pending.contains(&approval.id) {
+                events.push(WebhookEvent::ApprovalPending {
+                    approval: Box::new(approval),
+                });
+            }
+        }
+        self.pending = pending;
+
+        let new_requests = api.requests_after(self.last_request.as_deref(), usize::MAX)?;
+        if let Some((cursor, _)) = new_requests.last() {
+            self.last_request = Some(cursor.clone());
+        }
+        self.in_flight.extend(
+            new_requests
+                .into_iter()
+                .map(|(_, record)| (record.request_id, 0)),
+        );
+        let mut in_flight = vec![];
+        for (request_id, failures) in std::mem::take(&mut self.in_flight) {
+            // Requests not known yet by Kore Base are read again in the next poll, up to
+            // `MAX_STATE_FAILURES` times in a row.
+            match api.get_event_request_state(&request_id).await {
+                Ok(state) => match WebhookEvent::from_request(state) {
+                    Some(event) => events.push(event),
+                    None => in_flight.push((request_id, 0)),
+                },
+                Err(NodeError::Cancelled) => return Err(NodeError::Cancelled),
+                Err(error) if failures + 1 >= MAX_STATE_FAILURES => {
+                    log::warn!(
+                        "Webhooks no longer follow request {}, its state was not read: {}",
+                        request_id,
+                        error
+                    );
+                }
+                Err(_) => in_flight.push((request_id, failures + 1)),
+            }
+        }
+        self.in_flight = in_flight;
+        Ok(events)
+    }
+}
+
+/// Deliver an event to every webhook, with its timestamps in `format`.
+async fn notify(
+    client: Client,
+    settings: WebhookSettings,
+    event: WebhookEvent,
+    format: TimestampFormat,
+) {
+    let body = match serde_json::to_vec(&Timestamped::new(&event, format)) {
+        Ok(body) => body,
+        Err(error) => {
+            log::error!("Webhook payload of {} failed: {}", event.name(), error);
+            return;
+        }
+    };
+    let signature = (!settings.secret.is_empty()).then(|| sign(&settings.secret, &body));
+    let (client, settings, body, name) = (&client, &settings, &body, event.name());
+    let signature = signature.as_deref();
+    let deliveries = settings.urls.iter().map(|url| async move {
+        let result = deliver(client, settings, url, name, body, signature).await;
+        if let Err(error) = result {
+            log::warn!("Webhook {} not notified of {}: {}", url, name, error);
+        }
+    });
+    join_all(deliveries).await;
+}
+
+/// POST a payload to a webhook, retrying until it answers with a success status.
+///
+/// # Arguments
+///
+/// * `client` - HTTP client.
+/// * `settings` - Retry policy.
+/// * `url` - Webhook.
+/// * `event` - Name of the event.
+/// * `body` - JSON payload.
+/// * `signature` - Signature of the payload, if any.
+///
+/// # Errors
+///
+/// * `String` - Error of the last attempt.
+///
+async fn deliver(
+    client: &Client,
+    settings: &WebhookSettings,
+    url: &str,
+    event: &str,
+    body: &[u8],
+    signature: Option<&str>,
+) -> Result<(), String> {
+    let mut backoff = settings.retry_backoff;
+    let mut retries = 0;
+    loop {
+        let mut request = client
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event)
+            .body(body.to_vec());
+        if let Some(signature) = signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) => format!("status {}", response.status()),
+            Err(error) => error.to_string(),
+        };
+        if retries >= settings.max_retries {
+            return Err(error);
+        }
+        retries += 1;
+        tokio::time::sleep(backoff).await;
+        backoff = backoff.saturating_mul(2);
+    }
+}
+
+/// Signature of a payload, `sha256=` followed by the hex HMAC-SHA256 of the body.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    let hex = digest
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!("sha256={}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use kore_base::request::RequestState;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    fn request_state(state: RequestState, success: Option<bool>) -> NodeKoreRequestState {
+        NodeKoreRequestState {
+            id: "JRequest".to_owned(),
+            subject_id: None,
+            sn: None,
+            state: NodeRequestState::new(&state, success),
+            success,
+        }
+    }
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_request_event() {
+        let event = |state, success| {
+            WebhookEvent::from_request(request_state(state, success)).map(|event| event.name())
+        };
+        assert_eq!(event(RequestState::Processing, None), None);
+        assert_eq!(
+            event(RequestState::Finished, Some(true)),
+            Some("request_finished")
+        );
+        assert_eq!(
+            event(RequestState::Finished, Some(false)),
+            Some("request_failed")
+        );
+        assert_eq!(event(RequestState::Error, None), Some("request_failed"));
+
+        let payload = serde_json::to_value(WebhookEvent::RequestFinished {
+            request: request_state(RequestState::Finished, Some(true)),
+        })
+        .unwrap();
+        assert_eq!(payload["event"], "request_finished");
+        assert_eq!(payload["request"]["id"], "JRequest");
+    }
+
+    #[tokio::test]
+    async fn test_deliver_retries() {
+        // Answers the first request with an error and the second one with success.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, received) = mpsc::channel();
+        std::thread::spawn(move || {
+            for status in ["500 Internal Server Error", "204 No Content"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = vec![0; 4096];
+                let read = stream.read(&mut request).unwrap();
+                sender
+                    .send(String::from_utf8_lossy(&request[..read]).to_lowercase())
+                    .unwrap();
+                let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let settings = WebhookSettings {
+            urls: vec![url.clone()],
+            secret: "secret".to_owned(),
+            max_retries: 1,
+            retry_backoff: Duration::from_millis(10),
+        };
+        let signature = sign(&settings.secret, b"{}");
+        let client = Client::new();
+        let result = deliver(
+            &client,
+            &settings,
+            &url,
+            "request_finished",
+            b"{}",
+            Some(&signature),
+        )
+        .await;
+        assert!(result.is_ok());
+
+        let first = received.recv().unwrap();
+        assert!(first.contains("x-kore-event: request_finished"));
+        assert!(first.contains(&format!("x-kore-signature: {}", signature)));
+        assert!(received.recv().is_ok());
+
+        let settings = WebhookSettings {
+            max_retries: 0,
+            ..settings
+        };
+        let result = deliver(&client, &settings, &url, "request_finished", b"{}", None).await;
+        assert!(result.is_err());
+    }
+}